@@ -79,6 +79,53 @@ fn bench_session_metadata(c: &mut Criterion) {
     });
 }
 
+fn bench_solve_request_metadata(c: &mut Criterion) {
+    use buildkit_client::session::Session;
+    use tonic::metadata::{Ascii, MetadataKey, MetadataMap, MetadataValue};
+
+    let session = Session::new();
+
+    // What every solve request used to do: parse each header name and value
+    c.bench_function("solve_metadata_parse_per_request", |b| {
+        b.iter(|| {
+            let mut metadata = MetadataMap::new();
+            for (key, values) in black_box(&session).metadata() {
+                if let Ok(k) = key.parse::<MetadataKey<Ascii>>() {
+                    for value in values {
+                        if let Ok(v) = value.parse::<MetadataValue<Ascii>>() {
+                            metadata.append(k.clone(), v);
+                        }
+                    }
+                }
+            }
+            metadata
+        })
+    });
+
+    c.bench_function("solve_metadata_cached", |b| {
+        b.iter(|| black_box(&session).grpc_metadata())
+    });
+}
+
+fn bench_frontend_attrs(c: &mut Criterion) {
+    use buildkit_client::FrontendAttrs;
+
+    let config = BuildConfig::local("./app")
+        .dockerfile("Dockerfile")
+        .build_arg("VERSION", "1.0.0")
+        .build_arg("ENV", "production")
+        .target("production")
+        .platform(Platform::linux_arm64());
+
+    c.bench_function("frontend_attrs_from_config", |b| {
+        b.iter(|| {
+            FrontendAttrs::from_config(black_box(&config))
+                .unwrap()
+                .into_map()
+        })
+    });
+}
+
 fn bench_dockerfile_source_match(c: &mut Criterion) {
     let local_source = DockerfileSource::Local {
         context_path: PathBuf::from("./test"),
@@ -90,6 +137,7 @@ fn bench_dockerfile_source_match(c: &mut Criterion) {
         git_ref: Some("main".to_string()),
         dockerfile_path: None,
         token: None,
+        proxy: None,
     };
 
     c.bench_function("dockerfile_source_match_local", |b| {
@@ -130,6 +178,8 @@ criterion_group!(
     bench_build_config_creation,
     bench_build_config_builder_pattern,
     bench_session_metadata,
+    bench_solve_request_metadata,
+    bench_frontend_attrs,
     bench_dockerfile_source_match,
     bench_hashmap_operations,
 );
@@ -63,6 +63,11 @@ const VENDOR_MAPPINGS: &[(&str, &str)] = &[
         "vendor/github.com/containerd/containerd/api/types/mount.proto",
         "github.com/containerd/containerd/api/types/mount.proto",
     ),
+    // containerd content service, served over the session for custom stores
+    (
+        "vendor/github.com/containerd/containerd/api/services/content/v1/content.proto",
+        "github.com/containerd/containerd/api/services/content/v1/content.proto",
+    ),
 ];
 
 // Google RPC proto files
@@ -628,6 +633,11 @@ fn compile_protos() -> Result<(), Box<dyn std::error::Error>> {
                 proto_dir.join("github.com/moby/buildkit/session/filesync/filesync.proto"),
                 proto_dir.join("github.com/moby/buildkit/session/auth/auth.proto"),
                 proto_dir.join("github.com/moby/buildkit/session/secrets/secrets.proto"),
+                proto_dir.join("github.com/moby/buildkit/session/sshforward/ssh.proto"),
+                proto_dir.join("github.com/moby/buildkit/session/upload/upload.proto"),
+                proto_dir.join("github.com/moby/buildkit/frontend/gateway/pb/gateway.proto"),
+                proto_dir.join("github.com/moby/buildkit/solver/errdefs/errdefs.proto"),
+                proto_dir.join("github.com/containerd/containerd/api/services/content/v1/content.proto"),
             ],
             &[&proto_dir], // Include path
         )?;
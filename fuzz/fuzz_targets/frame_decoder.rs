@@ -0,0 +1,56 @@
+//! Feed arbitrary bytes to the frame decoder in arbitrary chunk sizes
+//!
+//! The first byte picks the chunk size so the fuzzer also explores headers
+//! and payloads split across HTTP/2 DATA frames. Small limits make the
+//! size checks reachable with short inputs.
+
+#![no_main]
+
+use buildkit_client::session::frame::{encode_frame, FrameDecoder, FrameLimits, FRAME_HEADER_LEN};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&chunk_size, input)) = data.split_first() else {
+        return;
+    };
+    let chunk_size = usize::from(chunk_size).max(1);
+
+    let limits = FrameLimits {
+        max_frame_size: 4096,
+        max_buffered: 8192,
+    };
+    let mut decoder = FrameDecoder::with_limits(limits);
+    let mut pushed = 0;
+    let mut consumed = 0;
+    'chunks: for chunk in input.chunks(chunk_size) {
+        if decoder.push(chunk).is_err() {
+            assert!(decoder.buffered() + chunk.len() > limits.max_buffered);
+            break;
+        }
+        pushed += chunk.len();
+        assert!(decoder.buffered() <= limits.max_buffered);
+
+        loop {
+            match decoder.decode() {
+                Ok(Some(frame)) => {
+                    assert!(frame.payload.len() <= limits.max_frame_size);
+                    let start = consumed;
+                    consumed += FRAME_HEADER_LEN + frame.payload.len();
+                    // Re-encoding an uncompressed frame must reproduce the input
+                    if !frame.compressed {
+                        assert_eq!(&encode_frame(&frame.payload)[..], &input[start..consumed]);
+                    }
+                }
+                Ok(None) => break,
+                Err(_) => break 'chunks,
+            }
+        }
+
+        // Nothing is lost or duplicated between decoded frames and the buffer
+        assert_eq!(consumed + decoder.buffered(), pushed);
+    }
+
+    if decoder.finish().is_ok() {
+        assert_eq!(decoder.buffered(), 0);
+    }
+});
@@ -0,0 +1,30 @@
+//! Decode every complete frame as the messages the session tunnel accepts
+//!
+//! Mirrors the receive loops in `grpc_tunnel.rs`: fsutil packets for
+//! FileSync/FileSend and BytesMessage chunks for tarball exports.
+
+#![no_main]
+
+use buildkit_client::proto::fsutil::types::Packet;
+use buildkit_client::proto::moby::filesync::v1::BytesMessage;
+use buildkit_client::session::frame::FrameDecoder;
+use libfuzzer_sys::fuzz_target;
+use prost::Message;
+
+fuzz_target!(|data: &[u8]| {
+    let mut decoder = FrameDecoder::new();
+    if decoder.push(data).is_err() {
+        return;
+    }
+
+    while let Ok(Some(frame)) = decoder.decode() {
+        if frame.compressed {
+            continue;
+        }
+        if let Ok(packet) = Packet::decode(frame.payload.clone()) {
+            let _ = packet.encode_to_vec();
+        }
+        let _ = BytesMessage::decode(frame.payload);
+    }
+    let _ = decoder.finish();
+});
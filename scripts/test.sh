@@ -114,6 +114,51 @@ run_unit_tests() {
     print_msg "$YELLOW" "Running builder tests..."
     cargo test --test builder_test --verbose
 
+    print_msg "$YELLOW" "Running platform property tests..."
+    cargo test --test platform_test --verbose
+
+    print_msg "$YELLOW" "Running frontend attribute golden tests..."
+    cargo test --test frontend_attrs_test --verbose
+
+    print_msg "$YELLOW" "Running cache backend tests..."
+    cargo test --test cache_test --verbose
+
+    print_msg "$YELLOW" "Running image copy tests..."
+    cargo test --test copy_test --verbose
+
+    print_msg "$YELLOW" "Running per-platform build tests..."
+    cargo test --test parallel_test --verbose
+
+    print_msg "$YELLOW" "Running in-memory context tests..."
+    cargo test --test context_source_test --verbose
+
+    print_msg "$YELLOW" "Running HTTP context tests..."
+    cargo test --test http_context_test --verbose
+
+    print_msg "$YELLOW" "Running raw LLB tests..."
+    cargo test --test llb_test --verbose
+
+    print_msg "$YELLOW" "Running frontend subrequest tests..."
+    cargo test --test subrequest_test --verbose
+
+    print_msg "$YELLOW" "Running debug container tests..."
+    cargo test --test debug_test --verbose
+
+    print_msg "$YELLOW" "Running config validation tests..."
+    cargo test --test validate_test --verbose
+
+    print_msg "$YELLOW" "Running registry access tests..."
+    cargo test --test registry_test --verbose
+
+    print_msg "$YELLOW" "Running .dockerignore tests..."
+    cargo test --test dockerignore_test --verbose
+
+    print_msg "$YELLOW" "Running build journal tests..."
+    cargo test --features journal --test journal_test --verbose
+
+    print_msg "$YELLOW" "Running git checkout tests..."
+    cargo test --test git_test --verbose
+
     print_msg "$YELLOW" "Running session tests..."
     cargo test --test session_test --verbose
 
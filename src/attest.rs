@@ -0,0 +1,248 @@
+//! Build attestations (SLSA provenance)
+//!
+//! Attestations are requested through `attest:<type>` frontend attributes and
+//! attached by BuildKit to the exported image index. Each attested image gets
+//! an attestation manifest in the index whose layers are in-toto statements;
+//! [`attestations_in_index`] finds them and [`AttestationManifestRef::fetch`]
+//! loads and decodes them from any [`ContentStore`].
+
+use crate::error::{Error, Result};
+use crate::oci::{Descriptor, ImageIndex, ImageManifest};
+use crate::session::content::read_blob;
+use crate::session::ContentStore;
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// Frontend attribute requesting SLSA provenance
+pub const PROVENANCE_ATTR: &str = "attest:provenance";
+
+/// How much build detail the provenance records
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ProvenanceMode {
+    /// Build timestamps, frontend and materials (BuildKit's default)
+    #[default]
+    Min,
+    /// Everything in `min` plus the full build definition and build args
+    Max,
+}
+
+impl ProvenanceMode {
+    /// Mode name as used in the attestation attribute
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProvenanceMode::Min => "min",
+            ProvenanceMode::Max => "max",
+        }
+    }
+}
+
+impl FromStr for ProvenanceMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "min" => Ok(ProvenanceMode::Min),
+            "max" => Ok(ProvenanceMode::Max),
+            _ => Err(Error::InvalidConfig(format!(
+                "unknown provenance mode {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ProvenanceMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// SLSA provenance attestation settings
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+    /// Level of detail
+    pub mode: ProvenanceMode,
+    /// Only attach provenance inline to image exporters (image, oci), instead
+    /// of also writing it out for filesystem exporters (local, tar)
+    pub inline_only: bool,
+    /// Builder identity recorded in the provenance (e.g., a CI run URL)
+    pub builder_id: Option<String>,
+}
+
+impl Provenance {
+    /// Create provenance settings with the given mode
+    pub fn new(mode: ProvenanceMode) -> Self {
+        Self {
+            mode,
+            ..Default::default()
+        }
+    }
+
+    /// Set whether provenance is only attached inline to images
+    pub fn inline_only(mut self, inline_only: bool) -> Self {
+        self.inline_only = inline_only;
+        self
+    }
+
+    /// Set the builder identity
+    pub fn builder_id(mut self, id: impl Into<String>) -> Self {
+        self.builder_id = Some(id.into());
+        self
+    }
+
+    /// Value of the `attest:provenance` frontend attribute
+    pub fn attr_value(&self) -> String {
+        let mut value = format!("mode={}", self.mode);
+        if self.inline_only {
+            value.push_str(",inline-only=true");
+        }
+        if let Some(id) = &self.builder_id {
+            // BuildKit splits the value as CSV; quote fields that need it
+            let field = format!("builder-id={}", id);
+            value.push(',');
+            if field.contains([',', '"']) {
+                value.push_str(&format!("\"{}\"", field.replace('"', "\"\"")));
+            } else {
+                value.push_str(&field);
+            }
+        }
+        value
+    }
+}
+
+impl From<ProvenanceMode> for Provenance {
+    fn from(mode: ProvenanceMode) -> Self {
+        Self::new(mode)
+    }
+}
+
+/// Annotation marking what kind of reference an index entry is
+pub const REFERENCE_TYPE_ANNOTATION: &str = "vnd.docker.reference.type";
+
+/// Annotation naming the manifest an attestation manifest refers to
+pub const REFERENCE_DIGEST_ANNOTATION: &str = "vnd.docker.reference.digest";
+
+/// Reference type of attestation manifests
+pub const ATTESTATION_MANIFEST: &str = "attestation-manifest";
+
+/// Annotation giving the predicate type of an attestation layer
+pub const PREDICATE_TYPE_ANNOTATION: &str = "in-toto.io/predicate-type";
+
+/// Predicate type of SLSA provenance (v0.2, as produced by BuildKit by default)
+pub const SLSA_PROVENANCE_V02: &str = "https://slsa.dev/provenance/v0.2";
+
+/// Predicate type of SPDX SBOMs
+pub const SPDX_SBOM: &str = "https://spdx.dev/Document";
+
+/// An attestation manifest listed in an image index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttestationManifestRef {
+    /// Descriptor of the attestation manifest
+    pub descriptor: Descriptor,
+    /// Digest of the image manifest the attestations describe
+    pub subject: String,
+}
+
+/// Find the attestation manifests in an image index
+pub fn attestations_in_index(index: &ImageIndex) -> Vec<AttestationManifestRef> {
+    index
+        .manifests
+        .iter()
+        .filter(|desc| {
+            desc.annotations
+                .get(REFERENCE_TYPE_ANNOTATION)
+                .map(String::as_str)
+                == Some(ATTESTATION_MANIFEST)
+        })
+        .map(|desc| AttestationManifestRef {
+            descriptor: desc.clone(),
+            subject: desc
+                .annotations
+                .get(REFERENCE_DIGEST_ANNOTATION)
+                .cloned()
+                .unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// Load the attestation manifests of an exported image index
+pub async fn fetch_attestations(
+    store: &dyn ContentStore,
+    index: &Descriptor,
+) -> Result<Vec<AttestationManifestRef>> {
+    if !index.is_index() {
+        // Attestations are only attached to indexes
+        return Ok(Vec::new());
+    }
+    let index = ImageIndex::from_json(&read_blob(store, &index.digest).await?)?;
+    Ok(attestations_in_index(&index))
+}
+
+impl AttestationManifestRef {
+    /// Load the attestation manifest and decode every statement in it
+    pub async fn fetch(&self, store: &dyn ContentStore) -> Result<Vec<Attestation>> {
+        let manifest = ImageManifest::from_json(&read_blob(store, &self.descriptor.digest).await?)?;
+        let mut attestations = Vec::with_capacity(manifest.layers.len());
+        for layer in manifest.layers {
+            let statement = Statement::from_json(&read_blob(store, &layer.digest).await?)?;
+            attestations.push(Attestation {
+                predicate_type: layer
+                    .annotations
+                    .get(PREDICATE_TYPE_ANNOTATION)
+                    .cloned()
+                    .unwrap_or_else(|| statement.predicate_type.clone()),
+                descriptor: layer,
+                statement,
+            });
+        }
+        Ok(attestations)
+    }
+}
+
+/// A single attestation document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attestation {
+    /// Descriptor of the attestation layer
+    pub descriptor: Descriptor,
+    /// Predicate type (e.g., [`SLSA_PROVENANCE_V02`])
+    pub predicate_type: String,
+    /// Decoded in-toto statement
+    pub statement: Statement,
+}
+
+/// An in-toto statement
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Statement {
+    /// Statement type URI
+    #[serde(rename = "_type")]
+    pub statement_type: String,
+    /// Predicate type URI
+    #[serde(rename = "predicateType")]
+    pub predicate_type: String,
+    /// Artifacts the statement is about
+    #[serde(default)]
+    pub subject: Vec<Subject>,
+    /// Predicate document (provenance, SBOM, ...)
+    #[serde(default)]
+    pub predicate: serde_json::Value,
+}
+
+impl Statement {
+    /// Decode a statement from JSON
+    pub fn from_json(json: &[u8]) -> Result<Self> {
+        serde_json::from_slice(json)
+            .map_err(|e| Error::oci(format!("invalid in-toto statement: {}", e)))
+    }
+}
+
+/// Subject of an in-toto statement
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Subject {
+    /// Artifact name
+    #[serde(default)]
+    pub name: String,
+    /// Digests by algorithm (e.g., `sha256`)
+    #[serde(default)]
+    pub digest: std::collections::HashMap<String, String>,
+}
@@ -1,11 +1,31 @@
 //! Build operations and configuration
 
+use crate::attest::Provenance;
+use crate::cache::{CacheExport, CacheImport};
 use crate::error::{Error, Result};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use crate::frontend::Frontend;
+use crate::git::{GitAuth, GitProxy};
+use crate::output::{Annotation, AnnotationScope, ImageOutput, Output};
+use crate::progress::ProgressBufferConfig;
+use crate::proto::moby::buildkit::v1::SolveRequest;
+use crate::registry::{split_image_name, DOCKER_HUB_REGISTRY};
+use crate::session::frame::FrameLimits;
+use crate::session::{
+    list_context, AccessLogPolicy, ContentStore, ContentStoreServer, ContextProvider, ContextUser,
+    DuplicateFiles, FileEntry, FileSyncOptions, SharedKey, SshKey, TarContext, Upload,
+};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use uuid::Uuid;
 
 /// Source location for Dockerfile
-#[derive(Debug, Clone)]
+///
+/// Tokens and headers are redacted from the `Debug` output.
+#[derive(Clone)]
 pub enum DockerfileSource {
     /// Local filesystem path
     Local {
@@ -24,54 +44,411 @@ pub enum DockerfileSource {
         dockerfile_path: Option<String>,
         /// GitHub token for private repositories
         token: Option<String>,
+        /// Fetch the repository on the client through this proxy and upload
+        /// it as a local context, for remotes the daemon cannot reach
+        proxy: Option<GitProxy>,
+    },
+    /// Any git repository (GitLab, Bitbucket, self-hosted, ...), cloned
+    /// by BuildKit
+    Git {
+        /// Repository URL (e.g., "<https://gitlab.com/group/app.git>")
+        url: String,
+        /// Git reference (branch, tag, or commit SHA)
+        git_ref: Option<String>,
+        /// Path to the Dockerfile within the repository
+        dockerfile_path: Option<String>,
+        /// Credentials, passed to BuildKit as a session secret
+        auth: Option<GitAuth>,
+    },
+    /// Tarball downloaded and unpacked by BuildKit
+    Http {
+        /// URL of the tarball (e.g., "https://example.com/app-1.2.tar.gz")
+        url: String,
+        /// Expected `sha256:` digest of the download
+        checksum: Option<String>,
+        /// `Authorization` header for the download, passed as a session
+        /// secret
+        auth_header: Option<String>,
+        /// Path to the Dockerfile within the unpacked tarball
+        dockerfile_path: Option<String>,
+    },
+    /// Context served from the client process rather than a directory
+    Context {
+        /// Where the context's files come from
+        context: ContextSource,
+        /// Path to the Dockerfile within the context
+        dockerfile_path: Option<String>,
+    },
+    /// Tarball streamed to BuildKit through the session's Upload service
+    /// and unpacked by the frontend
+    Upload {
+        /// Stream of the tarball
+        upload: Arc<Upload>,
+        /// Path to the Dockerfile within the unpacked tarball
+        dockerfile_path: Option<String>,
     },
 }
 
-/// Platform specification for multi-platform builds
+impl std::fmt::Debug for DockerfileSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let redacted = |value: &Option<String>| value.as_ref().map(|_| "<redacted>");
+        match self {
+            DockerfileSource::Local {
+                context_path,
+                dockerfile_path,
+            } => f
+                .debug_struct("Local")
+                .field("context_path", context_path)
+                .field("dockerfile_path", dockerfile_path)
+                .finish(),
+            DockerfileSource::GitHub {
+                repo_url,
+                git_ref,
+                dockerfile_path,
+                token,
+                proxy,
+            } => f
+                .debug_struct("GitHub")
+                .field("repo_url", repo_url)
+                .field("git_ref", git_ref)
+                .field("dockerfile_path", dockerfile_path)
+                .field("token", &redacted(token))
+                .field("proxy", proxy)
+                .finish(),
+            DockerfileSource::Git {
+                url,
+                git_ref,
+                dockerfile_path,
+                auth,
+            } => f
+                .debug_struct("Git")
+                .field("url", url)
+                .field("git_ref", git_ref)
+                .field("dockerfile_path", dockerfile_path)
+                .field("auth", auth)
+                .finish(),
+            DockerfileSource::Http {
+                url,
+                checksum,
+                auth_header,
+                dockerfile_path,
+            } => f
+                .debug_struct("Http")
+                .field("url", url)
+                .field("checksum", checksum)
+                .field("auth_header", &redacted(auth_header))
+                .field("dockerfile_path", dockerfile_path)
+                .finish(),
+            DockerfileSource::Context {
+                context,
+                dockerfile_path,
+            } => f
+                .debug_struct("Context")
+                .field("context", context)
+                .field("dockerfile_path", dockerfile_path)
+                .finish(),
+            DockerfileSource::Upload {
+                upload,
+                dockerfile_path,
+            } => f
+                .debug_struct("Upload")
+                .field("upload", upload)
+                .field("dockerfile_path", dockerfile_path)
+                .finish(),
+        }
+    }
+}
+
+/// Build context that is not a local directory
+///
+/// Served to BuildKit through the session like a local context, so nothing
+/// has to be written to disk first.
 #[derive(Debug, Clone)]
+pub enum ContextSource {
+    /// Files held in memory, keyed by their path relative to the context root
+    Memory(HashMap<PathBuf, FileEntry>),
+    /// Tar archive, optionally gzip-compressed
+    Tar(Arc<TarContext>),
+    /// Entries listed by a custom provider
+    Provider(Arc<dyn ContextProvider>),
+}
+
+impl ContextSource {
+    /// Provider serving the context's files over the session
+    pub fn provider(&self) -> Arc<dyn ContextProvider> {
+        match self {
+            ContextSource::Memory(files) => Arc::new(files.clone()),
+            ContextSource::Tar(archive) => Arc::clone(archive) as Arc<dyn ContextProvider>,
+            ContextSource::Provider(provider) => Arc::clone(provider),
+        }
+    }
+
+    /// Read a regular file of the context, `None` if there is no such file
+    pub async fn read_file(&self, path: &str) -> Result<Option<Bytes>> {
+        if let ContextSource::Memory(files) = self {
+            return Ok(files.get(Path::new(path)).map(|file| file.content.clone()));
+        }
+        let path = path.trim_start_matches("./");
+        Ok(list_context(self.provider().as_ref())
+            .await?
+            .into_iter()
+            .find(|entry| entry.is_file() && entry.path == path)
+            .map(|entry| entry.content))
+    }
+}
+
+/// Source of a named build context
+///
+/// Named contexts are what `docker buildx build --build-context` provides:
+/// the Dockerfile refers to them by name in `FROM <name>`,
+/// `COPY --from=<name>` and `RUN --mount=from=<name>`. An image context
+/// named like a base image (e.g. `alpine`) replaces that image for the build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NamedContext {
+    /// Local directory, synced through the session like the main context
+    Local(PathBuf),
+    /// Image pulled by the daemon (e.g., "alpine:3.20@sha256:...")
+    Image(String),
+    /// Git repository, optionally with `#<ref>[:<subdir>]` (e.g.,
+    /// "https://github.com/org/lib.git#v1.2")
+    Git(String),
+    /// Image in a local OCI layout directory, served through the session
+    OciLayout {
+        /// Layout directory
+        path: PathBuf,
+        /// Tag of the image in the layout's `index.json`
+        tag: String,
+        /// Manifest digest; resolved from `tag` when the build starts if unset
+        digest: Option<String>,
+    },
+}
+
+impl NamedContext {
+    /// Image context
+    pub fn image(reference: impl Into<String>) -> Self {
+        NamedContext::Image(reference.into())
+    }
+
+    /// Git repository context
+    pub fn git(url: impl Into<String>) -> Self {
+        NamedContext::Git(url.into())
+    }
+
+    /// Context from the image tagged `tag` in an OCI layout directory
+    pub fn oci_layout(path: impl Into<PathBuf>, tag: impl Into<String>) -> Self {
+        NamedContext::OciLayout {
+            path: path.into(),
+            tag: tag.into(),
+            digest: None,
+        }
+    }
+}
+
+impl std::str::FromStr for NamedContext {
+    type Err = Error;
+
+    /// Parse the value of a `--build-context name=<value>` flag
+    ///
+    /// Accepts `docker-image://<ref>`, `oci-layout://<dir>[:<tag>][@<digest>]`,
+    /// git URLs (`git://`, `ssh://`, `git@host:...` or `http(s)://...` ending
+    /// in `.git`) and local paths.
+    fn from_str(s: &str) -> Result<Self> {
+        if s.is_empty() {
+            return Err(Error::InvalidConfig("empty named context".to_string()));
+        }
+        if let Some(reference) = s.strip_prefix(crate::copy::IMAGE_SOURCE_SCHEME) {
+            return Ok(NamedContext::image(reference));
+        }
+        if let Some(layout) = s.strip_prefix(crate::layout::OCI_LAYOUT_SCHEME) {
+            let (layout, digest) = match layout.rsplit_once('@') {
+                Some((layout, digest)) => (layout, Some(digest.to_string())),
+                None => (layout, None),
+            };
+            let name_start = layout.rfind('/').map_or(0, |i| i + 1);
+            let (path, tag) = match layout[name_start..].rfind(':') {
+                Some(i) => (&layout[..name_start + i], &layout[name_start + i + 1..]),
+                None => (layout, "latest"),
+            };
+            return Ok(NamedContext::OciLayout {
+                path: PathBuf::from(path),
+                tag: tag.to_string(),
+                digest,
+            });
+        }
+        if is_git_url(s) {
+            return Ok(NamedContext::git(s));
+        }
+        if s.contains("://") {
+            return Err(Error::InvalidConfig(format!(
+                "unsupported named context {:?}",
+                s
+            )));
+        }
+        Ok(NamedContext::Local(PathBuf::from(s)))
+    }
+}
+
+/// Check whether a context value refers to a git repository
+fn is_git_url(s: &str) -> bool {
+    let url = s.split('#').next().unwrap_or(s);
+    url.starts_with("git://")
+        || url.starts_with("ssh://")
+        || url.starts_with("git@")
+        || ((url.starts_with("https://") || url.starts_with("http://")) && url.ends_with(".git"))
+}
+
+/// Platform specification for multi-platform builds
+///
+/// Components are kept lowercase, so platforms compare, hash and order
+/// consistently and can be used as map keys. With serde, platforms use the
+/// OCI image spec layout (`os`, `architecture`, `variant`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(from = "OciPlatform")]
 pub struct Platform {
     pub os: String,
+    #[serde(rename = "architecture")]
     pub arch: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub variant: Option<String>,
 }
 
+/// Platform as found in OCI manifests, before normalization
+#[derive(Deserialize)]
+struct OciPlatform {
+    os: String,
+    architecture: String,
+    #[serde(default)]
+    variant: Option<String>,
+}
+
+impl From<OciPlatform> for Platform {
+    fn from(raw: OciPlatform) -> Self {
+        let platform = Platform::new(raw.os, raw.architecture);
+        match raw.variant.filter(|v| !v.is_empty()) {
+            Some(variant) => platform.with_variant(variant),
+            None => platform,
+        }
+    }
+}
+
 impl Platform {
-    /// Create a Linux AMD64 platform
-    pub fn linux_amd64() -> Self {
+    /// Create a platform from an OS and architecture
+    ///
+    /// Components are lowercased.
+    pub fn new(os: impl Into<String>, arch: impl Into<String>) -> Self {
         Self {
-            os: "linux".to_string(),
-            arch: "amd64".to_string(),
+            os: os.into().to_ascii_lowercase(),
+            arch: arch.into().to_ascii_lowercase(),
             variant: None,
         }
     }
 
+    /// Set the architecture variant (e.g., "v7" for linux/arm/v7)
+    pub fn with_variant(mut self, variant: impl Into<String>) -> Self {
+        self.variant = Some(variant.into().to_ascii_lowercase());
+        self
+    }
+
+    /// Create a Linux AMD64 platform
+    pub fn linux_amd64() -> Self {
+        Self::new("linux", "amd64")
+    }
+
     /// Create a Linux ARM64 platform
     pub fn linux_arm64() -> Self {
-        Self {
-            os: "linux".to_string(),
-            arch: "arm64".to_string(),
-            variant: None,
+        Self::new("linux", "arm64")
+    }
+
+    /// Create a Linux ARMv7 platform
+    pub fn linux_arm_v7() -> Self {
+        Self::new("linux", "arm").with_variant("v7")
+    }
+
+    /// Create a Linux 32-bit x86 platform
+    pub fn linux_386() -> Self {
+        Self::new("linux", "386")
+    }
+
+    /// Create a Linux RISC-V 64 platform
+    pub fn linux_riscv64() -> Self {
+        Self::new("linux", "riscv64")
+    }
+
+    /// Create a Linux IBM Z platform
+    pub fn linux_s390x() -> Self {
+        Self::new("linux", "s390x")
+    }
+
+    /// Create a Linux little-endian POWER platform
+    pub fn linux_ppc64le() -> Self {
+        Self::new("linux", "ppc64le")
+    }
+
+    /// Create a Windows AMD64 platform
+    pub fn windows_amd64() -> Self {
+        Self::new("windows", "amd64")
+    }
+
+    /// Platform of the host the client runs on, in OCI naming
+    ///
+    /// Rust target names are mapped to their OCI equivalents (`x86_64` is
+    /// `amd64`, `macos` is `darwin`, ...). A 32-bit ARM host is reported as
+    /// `arm/v7`.
+    pub fn current() -> Self {
+        let os = match std::env::consts::OS {
+            "macos" => "darwin",
+            os => os,
+        };
+        let arch = match std::env::consts::ARCH {
+            "x86_64" => "amd64",
+            "x86" => "386",
+            "aarch64" => "arm64",
+            "powerpc64" if cfg!(target_endian = "little") => "ppc64le",
+            "powerpc64" => "ppc64",
+            "loongarch64" => "loong64",
+            "mips64" if cfg!(target_endian = "little") => "mips64le",
+            arch => arch,
+        };
+        let platform = Self::new(os, arch);
+        if arch == "arm" {
+            platform.with_variant("v7")
+        } else {
+            platform
         }
     }
 
     /// Parse platform from string (e.g., "linux/amd64", "linux/arm64/v8")
+    ///
+    /// Input is lowercased ("Linux/AMD64" parses as linux/amd64). Every
+    /// component must be non-empty and consist of ASCII letters, digits,
+    /// `.`, `_` or `-`.
     pub fn parse(s: &str) -> Result<Self> {
-        let parts: Vec<&str> = s.split('/').collect();
+        let normalized = s.to_ascii_lowercase();
+        let parts: Vec<&str> = normalized.split('/').collect();
+        if !parts.iter().all(|part| is_platform_component(part)) {
+            return Err(Error::InvalidPlatform(s.to_string()));
+        }
         match parts.as_slice() {
-            [os, arch] => Ok(Self {
-                os: os.to_string(),
-                arch: arch.to_string(),
-                variant: None,
-            }),
-            [os, arch, variant] => Ok(Self {
-                os: os.to_string(),
-                arch: arch.to_string(),
-                variant: Some(variant.to_string()),
-            }),
+            [os, arch] => Ok(Self::new(*os, *arch)),
+            [os, arch, variant] => Ok(Self::new(*os, *arch).with_variant(*variant)),
             _ => Err(Error::InvalidPlatform(s.to_string())),
         }
     }
+}
 
+pub(crate) fn is_platform_component(part: &str) -> bool {
+    !part.is_empty()
+        && part
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-'))
+}
+
+impl std::str::FromStr for Platform {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
 }
 
 impl std::fmt::Display for Platform {
@@ -84,8 +461,124 @@ impl std::fmt::Display for Platform {
     }
 }
 
+/// Privilege a build may use beyond the default sandbox
+///
+/// The daemon must also allow the entitlement (`buildkitd --allow-insecure-entitlement`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Entitlement {
+    /// Run steps in the host's network namespace (`RUN --network=host`)
+    NetworkHost,
+    /// Run steps without sandboxing (`RUN --security=insecure`)
+    SecurityInsecure,
+}
+
+impl Entitlement {
+    /// Name used in solve requests (e.g., "network.host")
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Entitlement::NetworkHost => "network.host",
+            Entitlement::SecurityInsecure => "security.insecure",
+        }
+    }
+}
+
+impl std::str::FromStr for Entitlement {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "network.host" => Ok(Entitlement::NetworkHost),
+            "security.insecure" => Ok(Entitlement::SecurityInsecure),
+            _ => Err(Error::InvalidConfig(format!("unknown entitlement {:?}", s))),
+        }
+    }
+}
+
+impl std::fmt::Display for Entitlement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Network access of `RUN` steps
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum NetworkMode {
+    /// Whatever each step asks for (`RUN --network=...`), sandboxed by default
+    #[default]
+    Default,
+    /// No network access, for hermetic builds
+    None,
+    /// The host's network; requires [`Entitlement::NetworkHost`], which the
+    /// build requests automatically
+    Host,
+}
+
+impl NetworkMode {
+    /// Value of the `force-network-mode` frontend attribute, if one is sent
+    pub fn attr_value(&self) -> Option<&'static str> {
+        match self {
+            NetworkMode::Default => None,
+            NetworkMode::None => Some("none"),
+            NetworkMode::Host => Some("host"),
+        }
+    }
+}
+
+impl std::str::FromStr for NetworkMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "default" => Ok(NetworkMode::Default),
+            "none" => Ok(NetworkMode::None),
+            "host" => Ok(NetworkMode::Host),
+            _ => Err(Error::InvalidConfig(format!("unknown network mode {:?}", s))),
+        }
+    }
+}
+
+/// How base images are resolved (`image-resolve-mode`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ImageResolveMode {
+    /// Use the worker's copy while it is fresh, otherwise pull
+    #[default]
+    Default,
+    /// Always check the registry for a newer image
+    ForcePull,
+    /// Prefer the worker's image store; images that only exist there
+    /// (e.g., built or loaded locally) resolve without a registry
+    PreferLocal,
+}
+
+impl ImageResolveMode {
+    /// Value of the `image-resolve-mode` frontend attribute, if one is sent
+    pub fn attr_value(&self) -> Option<&'static str> {
+        match self {
+            ImageResolveMode::Default => None,
+            ImageResolveMode::ForcePull => Some("pull"),
+            ImageResolveMode::PreferLocal => Some("local"),
+        }
+    }
+}
+
+impl std::str::FromStr for ImageResolveMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "default" => Ok(ImageResolveMode::Default),
+            "pull" => Ok(ImageResolveMode::ForcePull),
+            "local" => Ok(ImageResolveMode::PreferLocal),
+            _ => Err(Error::InvalidConfig(format!(
+                "unknown image resolve mode {:?}",
+                s
+            ))),
+        }
+    }
+}
+
 /// Registry authentication credentials
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct RegistryAuth {
     /// Registry host (e.g., "docker.io", "localhost:5000")
     pub host: String,
@@ -93,6 +586,125 @@ pub struct RegistryAuth {
     pub username: String,
     /// Password or token
     pub password: String,
+    /// OAuth2 refresh token (e.g., a Docker Hub 2FA access token or an ACR
+    /// refresh token), used instead of the username and password
+    pub identity_token: Option<String>,
+}
+
+impl RegistryAuth {
+    /// Authenticate with a username and password
+    pub fn basic(
+        host: impl Into<String>,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        Self {
+            host: host.into(),
+            username: username.into(),
+            password: password.into(),
+            identity_token: None,
+        }
+    }
+
+    /// Authenticate with an identity token
+    ///
+    /// The registry's token service exchanges it for access tokens, as with
+    /// `docker login` for accounts that cannot use passwords.
+    pub fn identity_token(host: impl Into<String>, token: impl Into<String>) -> Self {
+        Self {
+            host: host.into(),
+            username: String::new(),
+            password: String::new(),
+            identity_token: Some(token.into()),
+        }
+    }
+}
+
+impl std::fmt::Debug for RegistryAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegistryAuth")
+            .field("host", &self.host)
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .field(
+                "identity_token",
+                &self.identity_token.as_ref().map(|_| "<redacted>"),
+            )
+            .finish()
+    }
+}
+
+/// How a registry is reached, set per build with
+/// [`BuildConfig::registry_config`]
+///
+/// Registries are assumed to serve HTTPS with certificates the daemon trusts.
+/// The insecure flags reach BuildKit through `registry.insecure` on the image
+/// exporter and registry cache refs. Mirrors are tried before the registry
+/// itself for cache imports; pushes always go to the registry. CA
+/// certificates are trusted by the client's own registry requests (e.g.,
+/// assembling multi-platform indexes) and must also be installed on the
+/// daemon for BuildKit's pulls and pushes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegistryConfig {
+    /// Talk to the registry over plain HTTP
+    pub plain_http: bool,
+    /// Accept TLS certificates that cannot be verified
+    pub skip_tls_verify: bool,
+    /// PEM files of additional CAs to trust
+    pub ca_certs: Vec<PathBuf>,
+    /// Mirror hosts (e.g., "mirror.example.com:5000"), in order of preference
+    pub mirrors: Vec<String>,
+}
+
+impl RegistryConfig {
+    /// Plain HTTP, falling back to unverified TLS
+    pub fn insecure() -> Self {
+        Self {
+            plain_http: true,
+            skip_tls_verify: true,
+            ..Default::default()
+        }
+    }
+
+    /// Trust an additional CA certificate (PEM file)
+    pub fn with_ca_cert(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ca_certs.push(path.into());
+        self
+    }
+
+    /// Add a mirror host, tried after the mirrors added before it
+    pub fn with_mirror(mut self, host: impl Into<String>) -> Self {
+        self.mirrors.push(host.into());
+        self
+    }
+
+    /// Whether BuildKit must be told to relax transport security
+    pub fn is_insecure(&self) -> bool {
+        self.plain_http || self.skip_tls_verify
+    }
+}
+
+/// Caller-supplied change to the solve request, see
+/// [`BuildConfig::with_solve_mutation`]
+#[derive(Clone)]
+pub struct SolveMutation(Arc<dyn Fn(&mut SolveRequest) + Send + Sync>);
+
+impl SolveMutation {
+    /// Wrap a closure
+    pub fn new(mutation: impl Fn(&mut SolveRequest) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(mutation))
+    }
+
+    /// Apply the mutation to a request
+    pub fn apply(&self, request: &mut SolveRequest) {
+        (self.0)(request)
+    }
+}
+
+impl std::fmt::Debug for SolveMutation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SolveMutation(..)")
+    }
 }
 
 /// Build configuration
@@ -101,38 +713,171 @@ pub struct BuildConfig {
     /// Dockerfile source
     pub source: DockerfileSource,
 
+    /// Dockerfile content served from memory instead of a file on disk
+    pub inline_dockerfile: Option<String>,
+
+    /// Dockerfile frontend that runs the build
+    pub frontend: Frontend,
+
     /// Build arguments (ARG values)
     pub build_args: HashMap<String, String>,
 
     /// Target stage in multi-stage build
     pub target: Option<String>,
 
+    /// Additional named contexts, by name
+    pub named_contexts: BTreeMap<String, NamedContext>,
+
     /// Target platforms
     pub platforms: Vec<Platform>,
 
-    /// Image tags to push
+    /// Image tags
     pub tags: Vec<String>,
 
+    /// Push `tags` to their registry (otherwise the image stays in the worker's image store)
+    pub push: bool,
+
+    /// Read pushed manifests back from the registry and compare digests
+    pub verify_push: bool,
+
+    /// Additional outputs (exporters); `tags` is shorthand for a pushed image
+    pub outputs: Vec<Output>,
+
+    /// OCI annotations added by image exporters (image, oci, docker)
+    pub annotations: Vec<Annotation>,
+
+    /// SLSA provenance attestation
+    pub provenance: Option<Provenance>,
+
     /// Registry authentication
     pub registry_auth: Option<RegistryAuth>,
 
-    /// Cache imports (registry or local paths)
+    /// Credentials for cache registries other than the push target
+    pub cache_registry_auths: Vec<RegistryAuth>,
+
+    /// Transport settings by registry host (e.g., "localhost:5000")
+    pub registries: BTreeMap<String, RegistryConfig>,
+
+    /// Cache imports as `buildctl --import-cache` strings
+    ///
+    /// Deprecated in favour of `cache_imports`; parsed with
+    /// [`CacheImport::parse`] when the build starts.
     pub cache_from: Vec<String>,
 
-    /// Cache exports
+    /// Cache exports as `buildctl --export-cache` strings
+    ///
+    /// Deprecated in favour of `cache_exports`; parsed with
+    /// [`CacheExport::parse`] when the build starts.
     pub cache_to: Vec<String>,
 
+    /// Typed cache imports, sent after those in `cache_from`
+    pub cache_imports: Vec<CacheImport>,
+
+    /// Typed cache exports, sent after those in `cache_to`
+    pub cache_exports: Vec<CacheExport>,
+
     /// Secrets to mount during build
     pub secrets: HashMap<String, String>,
 
-    /// SSH agent sockets to forward
+    /// Secrets read from files when the build mounts them
+    pub secret_files: HashMap<String, PathBuf>,
+
+    /// SSH agents to forward, as `docker build --ssh` specs: `default`,
+    /// `id=socket` or `id=key[,key...]`
     pub ssh_agents: Vec<String>,
 
+    /// Private keys served from memory by an embedded agent, by agent ID
+    pub ssh_keys: HashMap<String, Vec<SshKey>>,
+
     /// No cache flag
     pub no_cache: bool,
 
-    /// Pull always flag
-    pub pull: bool,
+    /// Stages built without cache, when `no_cache` is not set
+    pub no_cache_filter: Vec<String>,
+
+    /// How base images are resolved
+    pub image_resolve_mode: ImageResolveMode,
+
+    /// Forward the client's proxy variables as build arguments
+    pub forward_proxy_env: bool,
+
+    /// Privileges granted to the build
+    pub entitlements: BTreeSet<Entitlement>,
+
+    /// Network access of `RUN` steps
+    pub network: NetworkMode,
+
+    /// Extra `/etc/hosts` entries for `RUN` steps, as (hostname, IP) pairs
+    pub extra_hosts: Vec<(String, String)>,
+
+    /// Size of `/dev/shm` in `RUN` steps, in bytes
+    pub shm_size: Option<u64>,
+
+    /// Resource limits for `RUN` steps (e.g., "nofile=65536:65536")
+    pub ulimits: Vec<String>,
+
+    /// Parent cgroup of the `RUN` step containers
+    pub cgroup_parent: Option<String>,
+
+    /// Hostname of the `RUN` step containers
+    pub hostname: Option<String>,
+
+    /// Raw frontend attributes, applied after (and over) the typed options
+    pub frontend_attrs: BTreeMap<String, String>,
+
+    /// Buffering applied between the status stream and the progress handler
+    pub progress_buffer: ProgressBufferConfig,
+
+    /// Check remote `ADD` sources (reachability and `--checksum`) before building
+    pub verify_remote_sources: bool,
+
+    /// Resolve the git ref to a commit SHA through the GitHub API before building
+    pub resolve_git_ref: bool,
+
+    /// Keep the `.git` directory of git contexts
+    pub keep_git_dir: bool,
+
+    /// Exact build ref to use instead of a generated one
+    pub ref_id: Option<String>,
+
+    /// Prefix for generated build refs (defaults to "build")
+    pub ref_prefix: Option<String>,
+
+    /// Key the build ref is derived from, so identical requests share a ref
+    pub ref_key: Option<String>,
+
+    /// Join a running build with the same ref instead of failing
+    pub deduplicate: bool,
+
+    /// User whose read permissions the local context must satisfy
+    pub context_user: Option<ContextUser>,
+
+    /// Memory limits for messages BuildKit sends through the session
+    pub frame_limits: FrameLimits,
+
+    /// How registry credential and secret requests are logged
+    pub access_log: AccessLogPolicy,
+
+    /// How the session shared key (BuildKit's local source cache key) is chosen
+    pub shared_key: SharedKey,
+
+    /// How local context files with duplicate content are sent
+    pub duplicate_files: DuplicateFiles,
+
+    /// Options for sending local directories
+    pub file_sync: FileSyncOptions,
+
+    /// Patterns local context files have to match to be sent, if any
+    pub context_include: Vec<String>,
+
+    /// Patterns of local context files left out on top of `.dockerignore`
+    pub context_exclude: Vec<String>,
+
+    /// Custom blob stores served to BuildKit over the session
+    pub content_stores: ContentStoreServer,
+
+    /// Changes applied to the solve request right before it is sent
+    pub solve_mutations: Vec<SolveMutation>,
 }
 
 impl Default for BuildConfig {
@@ -142,17 +887,59 @@ impl Default for BuildConfig {
                 context_path: PathBuf::from("."),
                 dockerfile_path: None,
             },
+            inline_dockerfile: None,
+            frontend: Frontend::default(),
             build_args: HashMap::new(),
             target: None,
+            named_contexts: BTreeMap::new(),
             platforms: vec![Platform::linux_amd64()],
             tags: Vec::new(),
+            push: true,
+            verify_push: false,
+            outputs: Vec::new(),
+            annotations: Vec::new(),
+            provenance: None,
             registry_auth: None,
+            cache_registry_auths: Vec::new(),
+            registries: BTreeMap::new(),
             cache_from: Vec::new(),
             cache_to: Vec::new(),
+            cache_imports: Vec::new(),
+            cache_exports: Vec::new(),
             secrets: HashMap::new(),
+            secret_files: HashMap::new(),
             ssh_agents: Vec::new(),
+            ssh_keys: HashMap::new(),
             no_cache: false,
-            pull: false,
+            no_cache_filter: Vec::new(),
+            image_resolve_mode: ImageResolveMode::default(),
+            forward_proxy_env: false,
+            entitlements: BTreeSet::new(),
+            network: NetworkMode::default(),
+            extra_hosts: Vec::new(),
+            shm_size: None,
+            ulimits: Vec::new(),
+            cgroup_parent: None,
+            hostname: None,
+            frontend_attrs: BTreeMap::new(),
+            progress_buffer: ProgressBufferConfig::default(),
+            verify_remote_sources: false,
+            resolve_git_ref: false,
+            keep_git_dir: false,
+            ref_id: None,
+            ref_prefix: None,
+            ref_key: None,
+            deduplicate: false,
+            context_user: None,
+            frame_limits: FrameLimits::default(),
+            access_log: AccessLogPolicy::default(),
+            shared_key: SharedKey::default(),
+            duplicate_files: DuplicateFiles::default(),
+            file_sync: FileSyncOptions::default(),
+            context_include: Vec::new(),
+            context_exclude: Vec::new(),
+            content_stores: ContentStoreServer::default(),
+            solve_mutations: Vec::new(),
         }
     }
 }
@@ -169,6 +956,149 @@ impl BuildConfig {
         }
     }
 
+    /// Create a new build configuration from a context held in memory
+    ///
+    /// `files` maps paths relative to the context root to their content;
+    /// the Dockerfile is one of them (`Dockerfile` unless
+    /// [`dockerfile`](Self::dockerfile) says otherwise). Parent directories
+    /// are created as needed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use buildkit_client::{BuildConfig, FileEntry};
+    /// use std::collections::HashMap;
+    /// use std::path::PathBuf;
+    ///
+    /// let mut files = HashMap::new();
+    /// files.insert(PathBuf::from("Dockerfile"), FileEntry::from("FROM alpine\nCOPY app.conf /etc/\n"));
+    /// files.insert(PathBuf::from("app.conf"), FileEntry::from("port = 8080\n"));
+    /// let config = BuildConfig::memory(files);
+    /// ```
+    pub fn memory(files: HashMap<PathBuf, FileEntry>) -> Self {
+        Self::context(ContextSource::Memory(files))
+    }
+
+    /// Create a new build configuration from a git repository on any host
+    ///
+    /// BuildKit clones the repository itself; use
+    /// [`git_auth`](Self::git_auth) for private repositories. SSH remotes
+    /// (`git@host:org/repo.git`) authenticate through a forwarded agent.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use buildkit_client::{BuildConfig, GitAuth};
+    ///
+    /// let config = BuildConfig::git("https://gitlab.example.com/team/app.git")
+    ///     .git_ref("v1.4.0")
+    ///     .git_auth(GitAuth::basic("ci-bot", "app-password"));
+    ///
+    /// let config = BuildConfig::git("git@github.com:acme/platform.git")
+    ///     .git_auth(GitAuth::ssh_key("/run/secrets/deploy_key"));
+    /// ```
+    pub fn git(url: impl Into<String>) -> Self {
+        Self {
+            source: DockerfileSource::Git {
+                url: url.into(),
+                git_ref: None,
+                dockerfile_path: None,
+                auth: None,
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Set credentials for a git source
+    pub fn git_auth(mut self, git_auth: GitAuth) -> Self {
+        if let DockerfileSource::Git { auth, .. } = &mut self.source {
+            *auth = Some(git_auth);
+        }
+        self
+    }
+
+    /// Create a new build configuration from a remote tarball
+    ///
+    /// BuildKit downloads and unpacks the tarball itself (plain or
+    /// compressed), so it never passes through the client. Pin the content
+    /// with [`http_checksum`](Self::http_checksum) and authenticate with
+    /// [`http_auth_header`](Self::http_auth_header).
+    pub fn http_context(url: impl Into<String>) -> Self {
+        Self {
+            source: DockerfileSource::Http {
+                url: url.into(),
+                checksum: None,
+                auth_header: None,
+                dockerfile_path: None,
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Expected `sha256:<hex>` digest of an HTTP context's tarball
+    pub fn http_checksum(mut self, digest: impl Into<String>) -> Self {
+        if let DockerfileSource::Http { checksum, .. } = &mut self.source {
+            *checksum = Some(digest.into());
+        }
+        self
+    }
+
+    /// `Authorization` header (e.g., "Bearer <token>") for downloading an
+    /// HTTP context
+    ///
+    /// The header reaches BuildKit as a session secret, never as part of
+    /// the build graph. Needs a BuildKit version with authenticated HTTP
+    /// sources.
+    pub fn http_auth_header(mut self, header: impl Into<String>) -> Self {
+        if let DockerfileSource::Http { auth_header, .. } = &mut self.source {
+            *auth_header = Some(header.into());
+        }
+        self
+    }
+
+    /// Create a new build configuration from a tar archive
+    ///
+    /// The archive (plain or gzip-compressed) is served to BuildKit as the
+    /// context without being unpacked to disk; the Dockerfile is read from
+    /// it like from a directory.
+    pub fn tar(path: impl Into<PathBuf>) -> Self {
+        Self::context(ContextSource::Tar(Arc::new(TarContext::open(path))))
+    }
+
+    /// Create a new build configuration from a tar archive read from a
+    /// stream (e.g., stdin)
+    pub fn tar_reader(reader: impl tokio::io::AsyncRead + Send + Unpin + 'static) -> Self {
+        Self::context(ContextSource::Tar(Arc::new(TarContext::from_reader(reader))))
+    }
+
+    /// Create a new build configuration from a tarball streamed to
+    /// BuildKit (e.g., generated on the fly or read from stdin)
+    ///
+    /// Unlike [`tar_reader`](Self::tar_reader), the client never holds the
+    /// whole archive: it is streamed once through the session's Upload
+    /// service and unpacked by the frontend, like a remote tarball.
+    pub fn upload(reader: impl tokio::io::AsyncRead + Send + Unpin + 'static) -> Self {
+        Self {
+            source: DockerfileSource::Upload {
+                upload: Arc::new(Upload::new(reader)),
+                dockerfile_path: None,
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Create a new build configuration from a context that is not a
+    /// local directory
+    pub fn context(context: ContextSource) -> Self {
+        Self {
+            source: DockerfileSource::Context {
+                context,
+                dockerfile_path: None,
+            },
+            ..Default::default()
+        }
+    }
+
     /// Create a new build configuration with GitHub repository
     pub fn github(repo_url: impl Into<String>) -> Self {
         Self {
@@ -177,6 +1107,7 @@ impl BuildConfig {
                 git_ref: None,
                 dockerfile_path: None,
                 token: None,
+                proxy: None,
             },
             ..Default::default()
         }
@@ -188,19 +1119,62 @@ impl BuildConfig {
             DockerfileSource::Local { dockerfile_path, .. } => {
                 *dockerfile_path = Some(PathBuf::from(path.into()));
             }
-            DockerfileSource::GitHub { dockerfile_path, .. } => {
+            DockerfileSource::GitHub { dockerfile_path, .. }
+            | DockerfileSource::Git { dockerfile_path, .. }
+            | DockerfileSource::Http { dockerfile_path, .. }
+            | DockerfileSource::Context { dockerfile_path, .. }
+            | DockerfileSource::Upload { dockerfile_path, .. } => {
                 *dockerfile_path = Some(path.into());
             }
         }
         self
     }
 
+    /// Use `content` as the Dockerfile instead of reading it from disk
+    ///
+    /// The Dockerfile is served to BuildKit from memory, so generated
+    /// Dockerfiles need no temporary file; the context directory is still
+    /// synced as usual and any [`dockerfile`](Self::dockerfile) path is
+    /// ignored. Git sources do not support this.
+    pub fn inline_dockerfile(mut self, content: impl Into<String>) -> Self {
+        self.inline_dockerfile = Some(content.into());
+        self
+    }
+
     /// Add a build argument
     pub fn build_arg(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
         self.build_args.insert(key.into(), value.into());
         self
     }
 
+    /// Expose a local directory to the Dockerfile as the named context `name`
+    ///
+    /// The directory is synced alongside the main context, so steps can bind
+    /// it without copying it into the image, e.g.
+    /// `RUN --mount=type=bind,from=<name>,target=/src ...`, or use it in
+    /// `COPY --from=<name>` and `FROM <name>`.
+    pub fn bind_context(self, name: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        self.named_context(name, NamedContext::Local(path.into()))
+    }
+
+    /// Add the named context `name`, replacing an earlier one of that name
+    ///
+    /// The names `context` and `dockerfile` are reserved for the main
+    /// context and the Dockerfile.
+    pub fn named_context(mut self, name: impl Into<String>, context: NamedContext) -> Self {
+        self.named_contexts.insert(name.into(), context);
+        self
+    }
+
+    /// Select the Dockerfile frontend, e.g. [`Frontend::dockerfile_labs`]
+    ///
+    /// Local Dockerfiles using labs syntax are rejected before the build
+    /// unless a labs frontend is selected here or with `# syntax=`.
+    pub fn frontend(mut self, frontend: Frontend) -> Self {
+        self.frontend = frontend;
+        self
+    }
+
     /// Set target stage
     pub fn target(mut self, target: impl Into<String>) -> Self {
         self.target = Some(target.into());
@@ -219,12 +1193,88 @@ impl BuildConfig {
         self
     }
 
+    /// Add an output (exporter)
+    ///
+    /// An [`Output::Image`] without names inherits the configured tags.
+    pub fn output(mut self, output: Output) -> Self {
+        self.outputs.push(output);
+        self
+    }
+
+    /// Attach SLSA provenance to the exported images
+    ///
+    /// Accepts a [`ProvenanceMode`](crate::ProvenanceMode) or full
+    /// [`Provenance`] settings.
+    ///
+    /// ```
+    /// use buildkit_client::{BuildConfig, ProvenanceMode};
+    ///
+    /// let config = BuildConfig::local(".")
+    ///     .tag("registry.example.com/app:v1")
+    ///     .provenance(ProvenanceMode::Max);
+    /// ```
+    pub fn provenance(mut self, provenance: impl Into<Provenance>) -> Self {
+        self.provenance = Some(provenance.into());
+        self
+    }
+
+    /// Add an annotation to the image manifests
+    pub fn annotation(self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.scoped_annotation(AnnotationScope::Manifest, key, value)
+    }
+
+    /// Add an annotation at a specific scope
+    ///
+    /// Use [`AnnotationScope::Index`] to annotate the index of a
+    /// multi-platform image.
+    pub fn scoped_annotation(
+        mut self,
+        scope: AnnotationScope,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.annotations.push(Annotation::new(scope, key, value));
+        self
+    }
+
     /// Set registry authentication
     pub fn registry_auth(mut self, auth: RegistryAuth) -> Self {
         self.registry_auth = Some(auth);
         self
     }
 
+    /// Add credentials for a registry holding cache imports or exports
+    ///
+    /// Needed when cache refs point to a different registry than the image.
+    /// Cache refs on registries without credentials are accessed
+    /// anonymously, with a warning.
+    pub fn cache_registry_auth(mut self, auth: RegistryAuth) -> Self {
+        self.cache_registry_auths.push(auth);
+        self
+    }
+
+    /// Reach a registry over plain HTTP or unverified TLS
+    ///
+    /// `host` is the registry part of image names, e.g. "localhost:5000".
+    pub fn insecure_registry(self, host: impl Into<String>) -> Self {
+        self.registry_config(host, RegistryConfig::insecure())
+    }
+
+    /// Set how BuildKit reaches the registry at `host`
+    pub fn registry_config(mut self, host: impl Into<String>, config: RegistryConfig) -> Self {
+        self.registries.insert(host.into(), config);
+        self
+    }
+
+    /// Transport settings for the registry of an image reference
+    pub fn registry_config_for(&self, reference: &str) -> Option<&RegistryConfig> {
+        let (host, _) = split_image_name(reference);
+        self.registries
+            .iter()
+            .find(|(configured, _)| normalize_registry_host(configured) == host)
+            .map(|(_, config)| config)
+    }
+
     /// Set GitHub token for private repositories
     pub fn github_token(mut self, token: impl Into<String>) -> Self {
         if let DockerfileSource::GitHub { token: ref mut t, .. } = &mut self.source {
@@ -233,29 +1283,95 @@ impl BuildConfig {
         self
     }
 
+    /// Fetch the git context through a SOCKS proxy or SSH jump host
+    ///
+    /// The repository is checked out on the client and uploaded to BuildKit
+    /// instead of being cloned by the daemon.
+    pub fn git_proxy(mut self, git_proxy: GitProxy) -> Self {
+        if let DockerfileSource::GitHub { proxy, .. } = &mut self.source {
+            *proxy = Some(git_proxy);
+        }
+        self
+    }
+
     /// Set git reference (branch, tag, or commit)
     pub fn git_ref(mut self, git_ref: impl Into<String>) -> Self {
-        if let DockerfileSource::GitHub { git_ref: ref mut r, .. } = &mut self.source {
-            *r = Some(git_ref.into());
+        match &mut self.source {
+            DockerfileSource::GitHub { git_ref: r, .. } | DockerfileSource::Git { git_ref: r, .. } => {
+                *r = Some(git_ref.into());
+            }
+            _ => {}
         }
         self
     }
 
-    /// Add cache import source
+    /// Add a cache import as a `buildctl --import-cache` string
+    #[deprecated(note = "use `cache_import` with a typed `CacheImport`")]
     pub fn cache_from(mut self, source: impl Into<String>) -> Self {
         self.cache_from.push(source.into());
         self
     }
 
-    /// Add cache export destination
+    /// Add a cache export as a `buildctl --export-cache` string
+    #[deprecated(note = "use `cache_export` with a typed `CacheExport`")]
     pub fn cache_to(mut self, dest: impl Into<String>) -> Self {
         self.cache_to.push(dest.into());
         self
     }
 
+    /// Add a typed cache import (e.g., [`CacheImport::Local`])
+    pub fn cache_import(mut self, import: CacheImport) -> Self {
+        self.cache_imports.push(import);
+        self
+    }
+
+    /// Add a typed cache export (e.g., [`CacheExport::Inline`])
+    pub fn cache_export(mut self, export: CacheExport) -> Self {
+        self.cache_exports.push(export);
+        self
+    }
+
     /// Add a secret
     pub fn secret(mut self, id: impl Into<String>, value: impl Into<String>) -> Self {
-        self.secrets.insert(id.into(), value.into());
+        let id = id.into();
+        self.secret_files.remove(&id);
+        self.secrets.insert(id, value.into());
+        self
+    }
+
+    /// Add a secret whose value is the contents of a file
+    ///
+    /// The file is read when BuildKit mounts the secret, not when the build
+    /// is configured, and must not exceed BuildKit's 500KB secret limit.
+    /// Replaces a value given to [`secret`](Self::secret) under the same ID.
+    pub fn secret_from_file(mut self, id: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        let id = id.into();
+        self.secrets.remove(&id);
+        self.secret_files.insert(id, path.into());
+        self
+    }
+
+    /// Forward an SSH agent for `RUN --mount=type=ssh` steps
+    ///
+    /// `ssh("default")` forwards the local ssh-agent (`SSH_AUTH_SOCK`);
+    /// `ssh("deploy=/path/to/id_ed25519")` a private agent holding the given
+    /// keys, and `ssh("id=/path/to/agent.sock")` another agent's socket.
+    pub fn ssh(mut self, spec: impl Into<String>) -> Self {
+        self.ssh_agents.push(spec.into());
+        self
+    }
+
+    /// Serve a private key to `RUN --mount=type=ssh,id=<id>` steps from memory
+    ///
+    /// For CI jobs that have a key in a secret but no ssh-agent: an agent
+    /// embedded in the client holds every key added under `id` and never
+    /// writes them to disk. Keys must be unencrypted Ed25519 keys in the
+    /// OpenSSH format (see [`SshKey`]).
+    pub fn ssh_key(mut self, id: impl Into<String>, pem: impl Into<Vec<u8>>) -> Self {
+        self.ssh_keys
+            .entry(id.into())
+            .or_default()
+            .push(SshKey::new(pem));
         self
     }
 
@@ -265,9 +1381,428 @@ impl BuildConfig {
         self
     }
 
-    /// Set pull flag
+    /// Build the given stages without cache, keeping it for the others
+    ///
+    /// Ignored when [`no_cache`](Self::no_cache) disables the cache for
+    /// every stage.
+    pub fn no_cache_filter<I, S>(mut self, stages: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.no_cache_filter
+            .extend(stages.into_iter().map(Into::into));
+        self
+    }
+
+    /// Set whether `tags` are pushed
+    ///
+    /// With `false` the image is only named in the worker's image store
+    /// (e.g. containerd), which is what local development loops need.
+    pub fn push(mut self, push: bool) -> Self {
+        self.push = push;
+        self
+    }
+
+    /// Verify pushes against the registry
+    ///
+    /// After the build, every pushed name is resolved in its registry with
+    /// the configured credentials and transport settings. The build fails
+    /// with [`Error::PushVerification`] unless each resolves to the built
+    /// manifest, catching silent push failures and registries (or mirrors)
+    /// serving different content.
+    pub fn verify_push(mut self, verify: bool) -> Self {
+        self.verify_push = verify;
+        self
+    }
+
+    /// Always pull base images (shorthand for [`ImageResolveMode::ForcePull`])
     pub fn pull(mut self, pull: bool) -> Self {
-        self.pull = pull;
+        self.image_resolve_mode = if pull {
+            ImageResolveMode::ForcePull
+        } else {
+            ImageResolveMode::Default
+        };
+        self
+    }
+
+    /// Set how base images are resolved
+    pub fn image_resolve_mode(mut self, mode: ImageResolveMode) -> Self {
+        self.image_resolve_mode = mode;
+        self
+    }
+
+    /// Forward `HTTP_PROXY`, `HTTPS_PROXY`, `FTP_PROXY`, `NO_PROXY` and
+    /// `ALL_PROXY` (either case) from the environment as build arguments
+    ///
+    /// Like `docker build`, explicit build arguments of the same name win.
+    /// These are predefined arguments: the Dockerfile does not need to
+    /// declare them and they do not invalidate the cache.
+    pub fn forward_proxy_env(mut self, forward: bool) -> Self {
+        self.forward_proxy_env = forward;
+        self
+    }
+
+    /// Set the network access of all `RUN` steps
+    ///
+    /// [`NetworkMode::None`] disables networking even for steps that ask for
+    /// it; [`NetworkMode::Host`] also grants [`Entitlement::NetworkHost`].
+    pub fn network(mut self, mode: NetworkMode) -> Self {
+        self.network = mode;
+        self
+    }
+
+    /// Add an `/etc/hosts` entry for `RUN` steps
+    ///
+    /// Lets builds resolve internal names without DNS changes on the builder.
+    /// `ip` may also be `host-gateway`, which BuildKit replaces with the
+    /// builder's gateway address.
+    pub fn add_host(mut self, host: impl Into<String>, ip: impl Into<String>) -> Self {
+        self.extra_hosts.push((host.into(), ip.into()));
+        self
+    }
+
+    /// Set the size of `/dev/shm` in `RUN` steps, in bytes
+    ///
+    /// BuildKit's default of 64MB is too small for some workloads, such as
+    /// browser tests.
+    pub fn shm_size(mut self, bytes: u64) -> Self {
+        self.shm_size = Some(bytes);
+        self
+    }
+
+    /// Set a resource limit for `RUN` steps, like `docker build --ulimit`
+    ///
+    /// The format is `name=soft[:hard]`, e.g. "nofile=65536:65536"; `-1`
+    /// means unlimited. A later limit of the same name replaces an earlier one.
+    pub fn ulimit(mut self, ulimit: impl Into<String>) -> Self {
+        self.ulimits.push(ulimit.into());
+        self
+    }
+
+    /// Place the `RUN` step containers under a cgroup
+    ///
+    /// Lets shared builders account resource usage per tenant, e.g.
+    /// "/buildkit/team-a".
+    pub fn cgroup_parent(mut self, path: impl Into<String>) -> Self {
+        self.cgroup_parent = Some(path.into());
+        self
+    }
+
+    /// Set the hostname seen by `RUN` steps
+    pub fn build_hostname(mut self, name: impl Into<String>) -> Self {
+        self.hostname = Some(name.into());
+        self
+    }
+
+    /// Set a frontend attribute this crate has no typed option for
+    ///
+    /// Attributes are sent as given (e.g., `("attest:sbom", "")` or
+    /// `("build-arg:BUILDKIT_MULTI_PLATFORM", "1")`) and replace any value
+    /// the typed options produce for the same key, including `context`.
+    pub fn frontend_attr(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.frontend_attrs.insert(key.into(), value.into());
+        self
+    }
+
+    /// Grant the build an entitlement
+    ///
+    /// Required by Dockerfiles using `RUN --network=host` or
+    /// `RUN --security=insecure`.
+    pub fn allow(mut self, entitlement: Entitlement) -> Self {
+        self.entitlements.insert(entitlement);
+        self
+    }
+
+    /// Set status buffering for slow progress handlers
+    pub fn progress_buffer(mut self, buffer: ProgressBufferConfig) -> Self {
+        self.progress_buffer = buffer;
+        self
+    }
+
+    /// Verify remote `ADD` sources before starting the build
+    ///
+    /// Only applies to local Dockerfiles; see [`crate::remote_sources`].
+    pub fn verify_remote_sources(mut self, verify: bool) -> Self {
+        self.verify_remote_sources = verify;
+        self
+    }
+
+    /// Pin the git ref to a commit SHA before starting the build
+    ///
+    /// Only applies to GitHub sources; the ref is resolved with the GitHub
+    /// API (using the source's token), so nonexistent branches or tags fail
+    /// before any work is sent to BuildKit. See [`crate::github`].
+    pub fn resolve_git_ref(mut self, resolve: bool) -> Self {
+        self.resolve_git_ref = resolve;
+        self
+    }
+
+    /// Keep the `.git` directory when BuildKit clones a git context
+    ///
+    /// BuildKit drops it by default; keep it for builds that read the
+    /// repository, e.g. to embed the commit SHA with `git rev-parse HEAD`.
+    /// Applies to the main context and to git named contexts; not supported
+    /// for GitHub sources fetched through a [`GitProxy`].
+    pub fn keep_git_dir(mut self, keep: bool) -> Self {
+        self.keep_git_dir = keep;
+        self
+    }
+
+    /// Use an exact build ref (e.g., an external job ID)
+    ///
+    /// The ref shows up in BuildKit's build history, so external systems can
+    /// correlate records with their own jobs. It must be unique among builds
+    /// running through the same client.
+    pub fn ref_id(mut self, id: impl Into<String>) -> Self {
+        self.ref_id = Some(id.into());
+        self
+    }
+
+    /// Set the prefix for generated build refs (`<prefix>-<uuid>`)
+    pub fn ref_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.ref_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Derive the build ref from `key` (e.g., a webhook delivery ID)
+    ///
+    /// The ref is `<prefix>-<hash of key>`, so requests with the same key
+    /// get the same ref; an explicit [`ref_id`](Self::ref_id) still wins.
+    pub fn ref_key(mut self, key: impl Into<String>) -> Self {
+        self.ref_key = Some(key.into());
+        self
+    }
+
+    /// Join a build with the same ref already running through this client
+    /// (or a clone) instead of failing with [`Error::DuplicateBuildRef`]
+    ///
+    /// The joining request follows the running build's progress and gets a
+    /// copy of its result; nothing is solved twice. Use with
+    /// [`ref_id`](Self::ref_id) or [`ref_key`](Self::ref_key).
+    pub fn deduplicate(mut self, deduplicate: bool) -> Self {
+        self.deduplicate = deduplicate;
+        self
+    }
+
+    /// Only send the local context if `user` could read all of it
+    ///
+    /// Useful when the client runs as root on behalf of other users: every
+    /// unreadable path is reported before the build starts.
+    pub fn read_as(mut self, user: ContextUser) -> Self {
+        self.context_user = Some(user);
+        self
+    }
+
+    /// Set the largest message and buffer size accepted from the daemon
+    ///
+    /// Streams that exceed either limit fail with a protocol error instead of
+    /// growing without bound.
+    pub fn frame_limits(mut self, limits: FrameLimits) -> Self {
+        self.frame_limits = limits;
+        self
+    }
+
+    /// Set how registry hosts and secret IDs requested by the build are logged
+    ///
+    /// Defaults to [`AccessLogPolicy::Ids`] (DEBUG level).
+    pub fn access_log(mut self, policy: AccessLogPolicy) -> Self {
+        self.access_log = policy;
+        self
+    }
+
+    /// Set how the session shared key is chosen
+    ///
+    /// BuildKit reuses what it synced from a local context in earlier
+    /// sessions with the same shared key, so only changed files are sent.
+    /// Defaults to [`SharedKey::ContextPath`], a key derived from the
+    /// absolute context path.
+    pub fn shared_key(mut self, key: SharedKey) -> Self {
+        self.shared_key = key;
+        self
+    }
+
+    /// Set how local context files whose content duplicates another file are sent
+    ///
+    /// [`DuplicateFiles::Report`] hashes files of equal size and counts the
+    /// copies in [`BuildResult::sync_stats`](crate::BuildResult::sync_stats);
+    /// [`DuplicateFiles::Link`] also sends each copy as a hardlink so its
+    /// data crosses the wire once. Defaults to [`DuplicateFiles::Ignore`].
+    pub fn duplicate_files(mut self, duplicates: DuplicateFiles) -> Self {
+        self.duplicate_files = duplicates;
+        self
+    }
+
+    /// Set the options for sending the local context and bound directories
+    ///
+    /// See [`FileSyncOptions::snapshot`] for failing builds whose context is
+    /// edited while it is sent.
+    pub fn file_sync_options(mut self, options: FileSyncOptions) -> Self {
+        self.file_sync = options;
+        self
+    }
+
+    /// Only send local context files matching these patterns
+    ///
+    /// Patterns use `.dockerignore` syntax. A matching directory is sent
+    /// with everything below it, and the directories leading to a match are
+    /// sent as needed; nothing else is walked or uploaded. Files the
+    /// `.dockerignore` or [`context_exclude`](Self::context_exclude) ignore
+    /// stay out.
+    pub fn context_include<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.context_include.extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Leave local context files matching these patterns out of the upload
+    ///
+    /// Patterns use `.dockerignore` syntax and apply after the context's own
+    /// `.dockerignore`, so `!pattern` re-includes files it ignores. Useful
+    /// when the repository's `.dockerignore` cannot be changed.
+    pub fn context_exclude<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.context_exclude.extend(patterns.into_iter().map(Into::into));
         self
     }
+
+    /// Serve a custom blob store to BuildKit under `id`
+    ///
+    /// BuildKit reads OCI layout sources (`oci-layout://<id>/...`) from the
+    /// store and writes unpacked `oci`/`docker` exports to the store
+    /// registered as [`EXPORT_STORE_ID`](crate::session::content::EXPORT_STORE_ID).
+    pub fn content_store(mut self, id: impl Into<String>, store: Arc<dyn ContentStore>) -> Self {
+        self.content_stores.add_store(id, store);
+        self
+    }
+
+    /// Modify the solve request right before it is sent
+    ///
+    /// An escape hatch for daemon features this crate does not model yet:
+    /// the closure can change any field while the session, outputs and
+    /// progress are still handled here. Mutations run in the order they were
+    /// added; the session ID and ref should be left alone.
+    ///
+    /// ```
+    /// use buildkit_client::BuildConfig;
+    ///
+    /// let config = BuildConfig::local(".").with_solve_mutation(|request| {
+    ///     request
+    ///         .frontend_attrs
+    ///         .insert("build-arg:BUILDKIT_SYNTAX".to_string(), "docker/dockerfile:1".to_string());
+    /// });
+    /// assert_eq!(config.solve_mutations.len(), 1);
+    /// ```
+    pub fn with_solve_mutation(
+        mut self,
+        mutation: impl Fn(&mut SolveRequest) + Send + Sync + 'static,
+    ) -> Self {
+        self.solve_mutations.push(SolveMutation::new(mutation));
+        self
+    }
+
+    /// Resolve the configured outputs in exporter order
+    ///
+    /// `tags` is shorthand for an image output, pushed unless
+    /// [`push(false)`](Self::push) was set. When the caller already configured
+    /// an [`Output::Image`] without names, the tags are assigned to it instead
+    /// of adding a second image exporter.
+    pub fn resolved_outputs(&self) -> Vec<Output> {
+        let mut outputs = self.outputs.clone();
+
+        if !self.tags.is_empty() {
+            let unnamed = outputs.iter_mut().find_map(|output| match output {
+                Output::Image(image) if image.names.is_empty() => Some(image),
+                _ => None,
+            });
+            match unnamed {
+                Some(image) => image.names = self.tags.clone(),
+                None => {
+                    let image = ImageOutput {
+                        names: self.tags.clone(),
+                        push: self.push,
+                        ..Default::default()
+                    };
+                    outputs.insert(0, Output::Image(image));
+                }
+            }
+        }
+
+        outputs
+    }
+
+    /// Resolve the build ref for this configuration
+    ///
+    /// Returns the explicit [`ref_id`](Self::ref_id) if set, a ref derived
+    /// from the [`ref_key`](Self::ref_key), or otherwise a fresh
+    /// `<prefix>-<uuid>` ref.
+    pub fn resolve_ref(&self) -> Result<String> {
+        let prefix = self.ref_prefix.as_deref().unwrap_or("build");
+        let build_ref = match (&self.ref_id, &self.ref_key) {
+            (Some(id), _) => id.clone(),
+            (None, Some(key)) => {
+                let digest = format!("{:x}", Sha256::digest(key.as_bytes()));
+                format!("{}-{}", prefix, &digest[..32])
+            }
+            (None, None) => format!("{}-{}", prefix, Uuid::new_v4()),
+        };
+        validate_ref(&build_ref)?;
+        Ok(build_ref)
+    }
+}
+
+/// Check that a build ref only uses characters safe for BuildKit history and headers
+fn validate_ref(build_ref: &str) -> Result<()> {
+    let valid = !build_ref.is_empty()
+        && build_ref.len() <= 128
+        && build_ref
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidConfig(format!(
+            "invalid build ref {:?}: use 1-128 characters from [A-Za-z0-9._-]",
+            build_ref
+        )))
+    }
+}
+
+/// Proxy variables forwarded as build arguments, as `docker build` does
+pub const PROXY_BUILD_ARGS: &[&str] = &[
+    "HTTP_PROXY",
+    "http_proxy",
+    "HTTPS_PROXY",
+    "https_proxy",
+    "FTP_PROXY",
+    "ftp_proxy",
+    "NO_PROXY",
+    "no_proxy",
+    "ALL_PROXY",
+    "all_proxy",
+];
+
+/// Pick the proxy variables out of an environment (e.g., `std::env::vars()`)
+///
+/// Variables set to an empty value are left out.
+pub fn proxy_build_args(
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> BTreeMap<String, String> {
+    vars.into_iter()
+        .filter(|(name, value)| !value.is_empty() && PROXY_BUILD_ARGS.contains(&name.as_str()))
+        .collect()
+}
+
+/// Registry host as [`split_image_name`] reports it
+fn normalize_registry_host(host: &str) -> &str {
+    match host {
+        "docker.io" | "index.docker.io" => DOCKER_HUB_REGISTRY,
+        host => host,
+    }
 }
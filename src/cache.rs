@@ -0,0 +1,862 @@
+//! Build cache import and export backends
+//!
+//! Each variant maps to one entry of the solve request's cache options
+//! (`type=...` plus attributes), mirroring `buildctl --export-cache` and
+//! `--import-cache`. Backends without a dedicated variant, or options this
+//! crate does not model, can still be passed through the `Raw` variants.
+//!
+//! The `local` backend keeps the cache in an OCI image layout on the client's
+//! file system. BuildKit reaches the directory through a session content
+//! store registered under [`local_store_id`], and the client tags the
+//! exported cache manifest as [`LOCAL_CACHE_TAG`] in its `index.json`.
+//! [`CacheImport::OciLayout`] reads any tagged manifest of an existing layout
+//! the same way, such as a base image downloaded for offline builds.
+
+use crate::error::{Error, Result};
+use crate::proto::moby::buildkit::v1::CacheOptionsEntry;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Tag of the cache manifest in a local cache directory
+pub const LOCAL_CACHE_TAG: &str = "latest";
+
+/// Exporter response key holding the descriptor of the exported cache manifest
+pub const CACHE_MANIFEST_RESPONSE_KEY: &str = "cache.manifest";
+
+/// Session content store ID BuildKit uses for a local cache directory
+pub fn local_store_id(path: &Path) -> String {
+    format!("local:{}", path.display())
+}
+
+/// Where BuildKit imports build cache from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheImport {
+    /// Pull cache from an image (`type=registry,ref=...`)
+    Registry {
+        /// Cache image reference
+        reference: String,
+    },
+    /// Read cache from a local directory written by [`CacheExport::Local`]
+    /// (`type=local,src=...`)
+    ///
+    /// The import is skipped if the directory holds no cache yet, so the
+    /// first CI job on a fresh volume still builds.
+    Local {
+        /// Cache directory
+        src: PathBuf,
+    },
+    /// Read cache from a tagged manifest of any OCI image layout
+    /// (`type=local,src=...,tag=...`)
+    ///
+    /// Layouts written by `skopeo`, `crane` or an OCI exporter work too:
+    /// BuildKit takes the cache from the inline cache metadata of the image.
+    /// Unlike [`CacheImport::Local`], a missing tag fails the build.
+    OciLayout {
+        /// Layout directory
+        path: PathBuf,
+        /// Tag of the manifest in the layout's `index.json`
+        tag: String,
+    },
+    /// Read cache from an S3 bucket (`type=s3,...`)
+    S3(S3Cache),
+    /// Read cache from Azure Blob Storage (`type=azblob,...`)
+    AzBlob(AzBlobCache),
+    /// Read cache from the GitHub Actions cache (`type=gha,...`)
+    Gha(GhaCache),
+    /// Any importer, with attributes passed through as they are
+    Raw {
+        /// Importer type
+        cache_type: String,
+        /// Importer attributes
+        attrs: HashMap<String, String>,
+    },
+}
+
+impl CacheImport {
+    /// Import cache from a registry reference
+    pub fn registry(reference: impl Into<String>) -> Self {
+        CacheImport::Registry {
+            reference: reference.into(),
+        }
+    }
+
+    /// Import cache from a local directory
+    pub fn local(src: impl Into<PathBuf>) -> Self {
+        CacheImport::Local { src: src.into() }
+    }
+
+    /// Import cache from the manifest tagged `tag` in an OCI layout directory
+    pub fn oci_layout(path: impl Into<PathBuf>, tag: impl Into<String>) -> Self {
+        CacheImport::OciLayout {
+            path: path.into(),
+            tag: tag.into(),
+        }
+    }
+
+    /// Parse a `buildctl --import-cache` style string
+    ///
+    /// `type=<type>,<key>=<value>,...`; a string without `type=` is taken as
+    /// a registry reference. Specs that the typed variants cannot represent
+    /// exactly become [`CacheImport::Raw`].
+    pub fn parse(s: &str) -> Result<Self> {
+        if !s.contains('=') {
+            return Ok(CacheImport::registry(s));
+        }
+        let (cache_type, attrs) = parse_spec(s)?;
+        let typed = match cache_type.as_str() {
+            "registry" => attrs.get("ref").map(|r| CacheImport::registry(r.as_str())),
+            "local" => attrs.get("src").map(|src| match attrs.get("tag") {
+                Some(tag) => CacheImport::oci_layout(src.as_str(), tag.as_str()),
+                None => CacheImport::local(src.as_str()),
+            }),
+            "s3" => S3Cache::from_attrs(&attrs).map(CacheImport::S3),
+            "azblob" => AzBlobCache::from_attrs(&attrs).map(CacheImport::AzBlob),
+            "gha" => Some(CacheImport::Gha(GhaCache::from_attrs(&attrs))),
+            _ => None,
+        };
+        Ok(match typed {
+            Some(typed) if typed.to_entry().attrs == attrs => typed,
+            _ => CacheImport::Raw { cache_type, attrs },
+        })
+    }
+
+    /// BuildKit cache importer type
+    pub fn cache_type(&self) -> &str {
+        match self {
+            CacheImport::Registry { .. } => "registry",
+            CacheImport::Local { .. } | CacheImport::OciLayout { .. } => "local",
+            CacheImport::S3(_) => "s3",
+            CacheImport::AzBlob(_) => "azblob",
+            CacheImport::Gha(_) => "gha",
+            CacheImport::Raw { cache_type, .. } => cache_type,
+        }
+    }
+
+    /// Image reference of a registry cache
+    pub fn registry_ref(&self) -> Option<&str> {
+        match self {
+            CacheImport::Registry { reference } => Some(reference),
+            CacheImport::Raw { cache_type, attrs } if cache_type == "registry" => {
+                attrs.get("ref").map(String::as_str)
+            }
+            _ => None,
+        }
+    }
+
+    /// Convert into a solve request cache entry
+    ///
+    /// Local and OCI layout imports also need the `digest` of the cache
+    /// manifest, which the build resolves from the directory's `index.json`.
+    pub fn to_entry(&self) -> CacheOptionsEntry {
+        let mut attrs = HashMap::new();
+        match self {
+            CacheImport::Registry { reference } => {
+                attrs.insert("ref".to_string(), reference.clone());
+            }
+            CacheImport::Local { src } => {
+                attrs.insert("src".to_string(), src.display().to_string());
+            }
+            CacheImport::OciLayout { path, tag } => {
+                attrs.insert("src".to_string(), path.display().to_string());
+                attrs.insert("tag".to_string(), tag.clone());
+            }
+            CacheImport::S3(s3) => attrs.extend(s3.attrs()),
+            CacheImport::AzBlob(azblob) => attrs.extend(azblob.attrs()),
+            CacheImport::Gha(gha) => attrs.extend(gha.attrs()),
+            CacheImport::Raw { attrs: raw, .. } => attrs.extend(raw.clone()),
+        }
+        CacheOptionsEntry {
+            r#type: self.cache_type().to_string(),
+            attrs,
+        }
+    }
+}
+
+/// Where BuildKit exports the build cache
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheExport {
+    /// Push the cache as a separate image (`type=registry,ref=...`)
+    Registry {
+        /// Cache image reference (e.g., "localhost:5000/app:buildcache")
+        reference: String,
+    },
+    /// Embed cache metadata in the exported image itself (`type=inline`)
+    ///
+    /// Only the cache of the final stage is kept; import it later with a
+    /// registry cache import of the pushed image.
+    Inline,
+    /// Write the cache to a local directory (`type=local,dest=...`)
+    ///
+    /// The directory is an OCI image layout and can be shared between CI
+    /// jobs, e.g. on a mounted volume, and imported with
+    /// [`CacheImport::Local`].
+    Local {
+        /// Cache directory, created if missing
+        dest: PathBuf,
+    },
+    /// Write the cache to an S3 bucket (`type=s3,...`)
+    S3(S3Cache),
+    /// Write the cache to Azure Blob Storage (`type=azblob,...`)
+    AzBlob(AzBlobCache),
+    /// Write the cache to the GitHub Actions cache (`type=gha,...`)
+    Gha(GhaCache),
+    /// Any exporter, with attributes passed through as they are
+    Raw {
+        /// Exporter type
+        cache_type: String,
+        /// Exporter attributes
+        attrs: HashMap<String, String>,
+    },
+    /// Another export with non-default [`CacheExportOptions`]
+    ///
+    /// Created by [`mode`](CacheExport::mode),
+    /// [`ignore_error`](CacheExport::ignore_error) and
+    /// [`oci_mediatypes`](CacheExport::oci_mediatypes).
+    WithOptions {
+        /// The export the options apply to
+        export: Box<CacheExport>,
+        /// Options overriding the export's defaults
+        options: CacheExportOptions,
+    },
+}
+
+/// Which layers a cache export includes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CacheMode {
+    /// Only the layers of the exported image
+    Min,
+    /// The layers of all intermediate steps too
+    Max,
+}
+
+impl CacheMode {
+    /// Value of the `mode` attribute
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CacheMode::Min => "min",
+            CacheMode::Max => "max",
+        }
+    }
+}
+
+impl std::str::FromStr for CacheMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "min" => Ok(CacheMode::Min),
+            "max" => Ok(CacheMode::Max),
+            _ => Err(Error::InvalidConfig(format!("unknown cache mode {:?}", s))),
+        }
+    }
+}
+
+impl fmt::Display for CacheMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Options shared by all cache exporters
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheExportOptions {
+    /// Layers to export (exports default to [`CacheMode::Max`])
+    pub mode: Option<CacheMode>,
+    /// Let the build succeed even if the cache cannot be exported
+    pub ignore_error: bool,
+    /// Use OCI instead of Docker media types for the cache manifest
+    pub oci_mediatypes: Option<bool>,
+}
+
+impl CacheExportOptions {
+    fn apply(&self, attrs: &mut HashMap<String, String>) {
+        if let Some(mode) = self.mode {
+            attrs.insert("mode".to_string(), mode.to_string());
+        }
+        if self.ignore_error {
+            attrs.insert("ignore-error".to_string(), "true".to_string());
+        }
+        if let Some(enabled) = self.oci_mediatypes {
+            attrs.insert("oci-mediatypes".to_string(), enabled.to_string());
+        }
+    }
+
+    /// Move the option attributes out of `attrs`
+    ///
+    /// Returns `None` if one of them has an invalid value.
+    fn take(attrs: &mut HashMap<String, String>) -> Option<Self> {
+        let mut options = Self::default();
+        if let Some(mode) = attrs.remove("mode") {
+            options.mode = Some(mode.parse().ok()?);
+        }
+        if let Some(ignore) = attrs.remove("ignore-error") {
+            options.ignore_error = ignore.parse().ok()?;
+        }
+        if let Some(enabled) = attrs.remove("oci-mediatypes") {
+            options.oci_mediatypes = Some(enabled.parse().ok()?);
+        }
+        Some(options)
+    }
+}
+
+impl CacheExport {
+    /// Export the cache to a registry reference
+    pub fn registry(reference: impl Into<String>) -> Self {
+        CacheExport::Registry {
+            reference: reference.into(),
+        }
+    }
+
+    /// Export the cache to a local directory
+    pub fn local(dest: impl Into<PathBuf>) -> Self {
+        CacheExport::Local { dest: dest.into() }
+    }
+
+    /// Parse a `buildctl --export-cache` style string
+    ///
+    /// `type=<type>,<key>=<value>,...`; a string without `type=` is taken as
+    /// a registry reference. Specs that the typed variants cannot represent
+    /// exactly become [`CacheExport::Raw`].
+    pub fn parse(s: &str) -> Result<Self> {
+        if !s.contains('=') {
+            return Ok(CacheExport::registry(s));
+        }
+        let (cache_type, attrs) = parse_spec(s)?;
+
+        let mut backend_attrs = attrs.clone();
+        let options = CacheExportOptions::take(&mut backend_attrs);
+        let typed = match cache_type.as_str() {
+            "registry" => backend_attrs
+                .get("ref")
+                .map(|r| CacheExport::registry(r.as_str())),
+            "inline" => Some(CacheExport::Inline),
+            "local" => backend_attrs
+                .get("dest")
+                .map(|dest| CacheExport::local(dest.as_str())),
+            "s3" => S3Cache::from_attrs(&backend_attrs).map(CacheExport::S3),
+            "azblob" => AzBlobCache::from_attrs(&backend_attrs).map(CacheExport::AzBlob),
+            "gha" => Some(CacheExport::Gha(GhaCache::from_attrs(&backend_attrs))),
+            _ => None,
+        };
+
+        // Prefer the plain export when its default options already match
+        let candidates = typed.into_iter().flat_map(|typed| {
+            let configured = options
+                .clone()
+                .map(|options| typed.clone().with_options(options));
+            std::iter::once(typed).chain(configured)
+        });
+        for candidate in candidates {
+            if candidate.to_entry().attrs == attrs {
+                return Ok(candidate);
+            }
+        }
+        Ok(CacheExport::Raw { cache_type, attrs })
+    }
+
+    /// Set which layers are exported
+    pub fn mode(self, mode: CacheMode) -> Self {
+        self.map_options(|options| options.mode = Some(mode))
+    }
+
+    /// Let the build succeed even if the cache cannot be exported
+    pub fn ignore_error(self, ignore_error: bool) -> Self {
+        self.map_options(|options| options.ignore_error = ignore_error)
+    }
+
+    /// Use OCI (`true`) or Docker (`false`) media types for the cache manifest
+    pub fn oci_mediatypes(self, enabled: bool) -> Self {
+        self.map_options(|options| options.oci_mediatypes = Some(enabled))
+    }
+
+    /// Replace the export options; default options leave the export plain
+    pub fn with_options(self, options: CacheExportOptions) -> Self {
+        let export = match self {
+            CacheExport::WithOptions { export, .. } => export,
+            other => Box::new(other),
+        };
+        if options == CacheExportOptions::default() {
+            *export
+        } else {
+            CacheExport::WithOptions { export, options }
+        }
+    }
+
+    fn map_options(self, f: impl FnOnce(&mut CacheExportOptions)) -> Self {
+        let mut options = self.options();
+        f(&mut options);
+        self.with_options(options)
+    }
+
+    /// The export without its options
+    pub fn backend(&self) -> &CacheExport {
+        match self {
+            CacheExport::WithOptions { export, .. } => export.backend(),
+            other => other,
+        }
+    }
+
+    /// Options of the export
+    pub fn options(&self) -> CacheExportOptions {
+        match self {
+            CacheExport::WithOptions { options, .. } => options.clone(),
+            _ => CacheExportOptions::default(),
+        }
+    }
+
+    /// BuildKit cache exporter type
+    pub fn cache_type(&self) -> &str {
+        match self {
+            CacheExport::Registry { .. } => "registry",
+            CacheExport::Inline => "inline",
+            CacheExport::Local { .. } => "local",
+            CacheExport::S3(_) => "s3",
+            CacheExport::AzBlob(_) => "azblob",
+            CacheExport::Gha(_) => "gha",
+            CacheExport::Raw { cache_type, .. } => cache_type,
+            CacheExport::WithOptions { export, .. } => export.cache_type(),
+        }
+    }
+
+    /// Image reference of a registry cache
+    pub fn registry_ref(&self) -> Option<&str> {
+        match self.backend() {
+            CacheExport::Registry { reference } => Some(reference),
+            CacheExport::Raw { cache_type, attrs } if cache_type == "registry" => {
+                attrs.get("ref").map(String::as_str)
+            }
+            _ => None,
+        }
+    }
+
+    /// Convert into a solve request cache entry
+    pub fn to_entry(&self) -> CacheOptionsEntry {
+        let mut attrs = HashMap::new();
+        match self {
+            CacheExport::Registry { reference } => {
+                attrs.insert("ref".to_string(), reference.clone());
+                attrs.insert("mode".to_string(), "max".to_string());
+            }
+            CacheExport::Inline => {}
+            CacheExport::Local { dest } => {
+                attrs.insert("dest".to_string(), dest.display().to_string());
+                attrs.insert("mode".to_string(), "max".to_string());
+            }
+            CacheExport::S3(s3) => {
+                attrs.extend(s3.attrs());
+                attrs.insert("mode".to_string(), "max".to_string());
+            }
+            CacheExport::AzBlob(azblob) => {
+                attrs.extend(azblob.attrs());
+                attrs.insert("mode".to_string(), "max".to_string());
+            }
+            CacheExport::Gha(gha) => {
+                attrs.extend(gha.attrs());
+                attrs.insert("mode".to_string(), "max".to_string());
+            }
+            CacheExport::Raw { attrs: raw, .. } => attrs.extend(raw.clone()),
+            CacheExport::WithOptions { export, options } => {
+                attrs = export.to_entry().attrs;
+                options.apply(&mut attrs);
+            }
+        }
+        CacheOptionsEntry {
+            r#type: self.cache_type().to_string(),
+            attrs,
+        }
+    }
+}
+
+/// Location and credentials of an S3 cache
+///
+/// Without explicit credentials BuildKit falls back to the daemon's own AWS
+/// configuration (environment, instance profile, ...). Use
+/// [`credentials_from_env`](Self::credentials_from_env) to forward the
+/// client's instead, e.g. when the builder runs elsewhere.
+///
+/// # Example
+///
+/// ```
+/// use buildkit_client::cache::S3Cache;
+/// use buildkit_client::CacheExport;
+///
+/// let cache = S3Cache::new("ci-cache", "eu-west-1")
+///     .prefix("app/")
+///     .name("main");
+/// let export = CacheExport::S3(cache).to_entry();
+/// assert_eq!(export.attrs["bucket"], "ci-cache");
+/// assert_eq!(export.attrs["name"], "main");
+/// ```
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct S3Cache {
+    /// Bucket name
+    pub bucket: String,
+    /// Bucket region
+    pub region: String,
+    /// Prefix of all cache objects
+    pub prefix: Option<String>,
+    /// Name of the cache manifest, to keep several caches in one bucket
+    pub name: Option<String>,
+    /// Custom endpoint (e.g., MinIO)
+    pub endpoint_url: Option<String>,
+    /// Use path-style instead of virtual-hosted-style bucket URLs
+    pub use_path_style: bool,
+    /// Access key ID
+    pub access_key_id: Option<String>,
+    /// Secret access key
+    pub secret_access_key: Option<String>,
+    /// Session token of temporary credentials
+    pub session_token: Option<String>,
+}
+
+impl S3Cache {
+    /// Cache in `bucket` located in `region`
+    pub fn new(bucket: impl Into<String>, region: impl Into<String>) -> Self {
+        Self {
+            bucket: bucket.into(),
+            region: region.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Set the prefix of all cache objects
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Set the cache manifest name
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Read the attributes produced by [`attrs`](Self::attrs)
+    ///
+    /// Returns `None` without a bucket or region; other attributes are
+    /// ignored.
+    pub fn from_attrs(attrs: &HashMap<String, String>) -> Option<Self> {
+        let get = |key: &str| attrs.get(key).cloned();
+        Some(Self {
+            bucket: get("bucket")?,
+            region: get("region")?,
+            prefix: get("prefix"),
+            name: get("name"),
+            endpoint_url: get("endpoint_url"),
+            use_path_style: attrs.get("use_path_style").map(String::as_str) == Some("true"),
+            access_key_id: get("access_key_id"),
+            secret_access_key: get("secret_access_key"),
+            session_token: get("session_token"),
+        })
+    }
+
+    /// Use a custom S3-compatible endpoint
+    pub fn endpoint_url(mut self, url: impl Into<String>) -> Self {
+        self.endpoint_url = Some(url.into());
+        self
+    }
+
+    /// Use path-style bucket URLs (required by most S3-compatible stores)
+    pub fn use_path_style(mut self, use_path_style: bool) -> Self {
+        self.use_path_style = use_path_style;
+        self
+    }
+
+    /// Use static credentials
+    pub fn credentials(
+        mut self,
+        access_key_id: impl Into<String>,
+        secret_access_key: impl Into<String>,
+    ) -> Self {
+        self.access_key_id = Some(access_key_id.into());
+        self.secret_access_key = Some(secret_access_key.into());
+        self
+    }
+
+    /// Use the session token of temporary credentials
+    pub fn session_token(mut self, token: impl Into<String>) -> Self {
+        self.session_token = Some(token.into());
+        self
+    }
+
+    /// Forward the client's `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+    /// `AWS_SESSION_TOKEN`
+    ///
+    /// Credentials that are already set, or missing from the environment,
+    /// are left alone.
+    pub fn credentials_from_env(mut self) -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        if self.access_key_id.is_none() && self.secret_access_key.is_none() {
+            if let (Some(id), Some(secret)) =
+                (var("AWS_ACCESS_KEY_ID"), var("AWS_SECRET_ACCESS_KEY"))
+            {
+                self.access_key_id = Some(id);
+                self.secret_access_key = Some(secret);
+                self.session_token = self.session_token.or_else(|| var("AWS_SESSION_TOKEN"));
+            }
+        }
+        self
+    }
+
+    /// Cache attributes shared by import and export
+    pub fn attrs(&self) -> HashMap<String, String> {
+        let mut attrs = HashMap::new();
+        attrs.insert("bucket".to_string(), self.bucket.clone());
+        attrs.insert("region".to_string(), self.region.clone());
+        let optional = [
+            ("prefix", &self.prefix),
+            ("name", &self.name),
+            ("endpoint_url", &self.endpoint_url),
+            ("access_key_id", &self.access_key_id),
+            ("secret_access_key", &self.secret_access_key),
+            ("session_token", &self.session_token),
+        ];
+        for (key, value) in optional {
+            if let Some(value) = value {
+                attrs.insert(key.to_string(), value.clone());
+            }
+        }
+        if self.use_path_style {
+            attrs.insert("use_path_style".to_string(), "true".to_string());
+        }
+        attrs
+    }
+}
+
+impl fmt::Debug for S3Cache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let redacted = |value: &Option<String>| value.as_ref().map(|_| "<redacted>");
+        f.debug_struct("S3Cache")
+            .field("bucket", &self.bucket)
+            .field("region", &self.region)
+            .field("prefix", &self.prefix)
+            .field("name", &self.name)
+            .field("endpoint_url", &self.endpoint_url)
+            .field("use_path_style", &self.use_path_style)
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &redacted(&self.secret_access_key))
+            .field("session_token", &redacted(&self.session_token))
+            .finish()
+    }
+}
+
+/// Location and credentials of an Azure Blob Storage cache
+///
+/// Without an account key BuildKit authenticates with the daemon's Azure
+/// default credentials (environment, managed identity, ...).
+///
+/// # Example
+///
+/// ```
+/// use buildkit_client::cache::AzBlobCache;
+/// use buildkit_client::CacheExport;
+///
+/// let cache = AzBlobCache::new("https://ciaccount.blob.core.windows.net")
+///     .container("build-cache")
+///     .prefix("app/");
+/// let export = CacheExport::AzBlob(cache).to_entry();
+/// assert_eq!(export.attrs["container"], "build-cache");
+/// ```
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct AzBlobCache {
+    /// Storage account URL (e.g., "https://myaccount.blob.core.windows.net")
+    pub account_url: String,
+    /// Blob container (BuildKit defaults to "buildkit-cache")
+    pub container: Option<String>,
+    /// Prefix of all cache blobs
+    pub prefix: Option<String>,
+    /// Name of the cache manifest, to keep several caches in one container
+    pub name: Option<String>,
+    /// Storage account key
+    pub secret_access_key: Option<String>,
+}
+
+impl AzBlobCache {
+    /// Cache in the storage account at `account_url`
+    pub fn new(account_url: impl Into<String>) -> Self {
+        Self {
+            account_url: account_url.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Read the attributes produced by [`attrs`](Self::attrs)
+    ///
+    /// Returns `None` without an account URL; other attributes are ignored.
+    pub fn from_attrs(attrs: &HashMap<String, String>) -> Option<Self> {
+        let get = |key: &str| attrs.get(key).cloned();
+        Some(Self {
+            account_url: get("account_url")?,
+            container: get("container"),
+            prefix: get("prefix"),
+            name: get("name"),
+            secret_access_key: get("secret_access_key"),
+        })
+    }
+
+    /// Set the blob container
+    pub fn container(mut self, container: impl Into<String>) -> Self {
+        self.container = Some(container.into());
+        self
+    }
+
+    /// Set the prefix of all cache blobs
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Set the cache manifest name
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Authenticate with a storage account key
+    pub fn secret_access_key(mut self, key: impl Into<String>) -> Self {
+        self.secret_access_key = Some(key.into());
+        self
+    }
+
+    /// Forward the client's `AZURE_STORAGE_ACCOUNT_KEY`, unless a key is set
+    pub fn credentials_from_env(mut self) -> Self {
+        if self.secret_access_key.is_none() {
+            self.secret_access_key = std::env::var("AZURE_STORAGE_ACCOUNT_KEY")
+                .ok()
+                .filter(|v| !v.is_empty());
+        }
+        self
+    }
+
+    /// Cache attributes shared by import and export
+    pub fn attrs(&self) -> HashMap<String, String> {
+        let mut attrs = HashMap::new();
+        attrs.insert("account_url".to_string(), self.account_url.clone());
+        let optional = [
+            ("container", &self.container),
+            ("prefix", &self.prefix),
+            ("name", &self.name),
+            ("secret_access_key", &self.secret_access_key),
+        ];
+        for (key, value) in optional {
+            if let Some(value) = value {
+                attrs.insert(key.to_string(), value.clone());
+            }
+        }
+        attrs
+    }
+}
+
+impl fmt::Debug for AzBlobCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AzBlobCache")
+            .field("account_url", &self.account_url)
+            .field("container", &self.container)
+            .field("prefix", &self.prefix)
+            .field("name", &self.name)
+            .field(
+                "secret_access_key",
+                &self.secret_access_key.as_ref().map(|_| "<redacted>"),
+            )
+            .finish()
+    }
+}
+
+/// Scope and credentials of a GitHub Actions cache
+///
+/// Inside a workflow the runtime URL and token are only available to
+/// actions, so they have to be exported to the step's environment (e.g.,
+/// with `crazy-max/ghaction-github-runtime`) and picked up with
+/// [`from_env`](Self::from_env).
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct GhaCache {
+    /// Cache scope, to keep caches of several images apart
+    pub scope: Option<String>,
+    /// Cache service URL (`ACTIONS_CACHE_URL`)
+    pub url: Option<String>,
+    /// Results service URL of the v2 cache API (`ACTIONS_RESULTS_URL`)
+    pub url_v2: Option<String>,
+    /// Runtime token (`ACTIONS_RUNTIME_TOKEN`)
+    pub token: Option<String>,
+}
+
+impl GhaCache {
+    /// Cache with BuildKit's default scope and no credentials
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cache using the runtime URLs and token of the current workflow job
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        Self {
+            scope: None,
+            url: var("ACTIONS_CACHE_URL"),
+            url_v2: var("ACTIONS_RESULTS_URL"),
+            token: var("ACTIONS_RUNTIME_TOKEN"),
+        }
+    }
+
+    /// Read the attributes produced by [`attrs`](Self::attrs)
+    pub fn from_attrs(attrs: &HashMap<String, String>) -> Self {
+        let get = |key: &str| attrs.get(key).cloned();
+        Self {
+            scope: get("scope"),
+            url: get("url"),
+            url_v2: get("url_v2"),
+            token: get("token"),
+        }
+    }
+
+    /// Set the cache scope
+    pub fn scope(mut self, scope: impl Into<String>) -> Self {
+        self.scope = Some(scope.into());
+        self
+    }
+
+    /// Cache attributes shared by import and export
+    pub fn attrs(&self) -> HashMap<String, String> {
+        let mut attrs = HashMap::new();
+        let optional = [
+            ("scope", &self.scope),
+            ("url", &self.url),
+            ("url_v2", &self.url_v2),
+            ("token", &self.token),
+        ];
+        for (key, value) in optional {
+            if let Some(value) = value {
+                attrs.insert(key.to_string(), value.clone());
+            }
+        }
+        attrs
+    }
+}
+
+impl fmt::Debug for GhaCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GhaCache")
+            .field("scope", &self.scope)
+            .field("url", &self.url)
+            .field("url_v2", &self.url_v2)
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+/// Split a `type=...,key=value` spec into its type and attributes
+fn parse_spec(s: &str) -> Result<(String, HashMap<String, String>)> {
+    let invalid = || Error::InvalidConfig(format!("invalid cache spec {:?}", s));
+    let mut cache_type = None;
+    let mut attrs = HashMap::new();
+    for field in s.split(',').filter(|f| !f.is_empty()) {
+        let (key, value) = field.split_once('=').ok_or_else(invalid)?;
+        if key == "type" {
+            cache_type = Some(value.to_string());
+        } else {
+            attrs.insert(key.to_string(), value.to_string());
+        }
+    }
+    let cache_type = cache_type.filter(|t| !t.is_empty()).ok_or_else(invalid)?;
+    Ok((cache_type, attrs))
+}
@@ -2,12 +2,48 @@
 
 use crate::error::{Error, Result};
 use crate::proto::moby::buildkit::v1::control_client::ControlClient;
+use crate::session::{Session, SessionHealth, SessionMonitor};
+use crate::solve::BuildResult;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{watch, Notify};
+use tokio::task::AbortHandle;
 use tonic::transport::{Channel, Endpoint};
 
 /// BuildKit client for interacting with buildkitd
+///
+/// Clones share the underlying channel, the set of in-flight build refs and
+/// the shutdown state.
 #[derive(Clone)]
 pub struct BuildKitClient {
+    channel: Channel,
     control: ControlClient<Channel>,
+    in_flight: Arc<Mutex<HashMap<String, InFlightBuild>>>,
+    lifecycle: Arc<Lifecycle>,
+    #[cfg(feature = "journal")]
+    journal: Option<Arc<crate::journal::BuildJournal>>,
+}
+
+/// Result of a build as shared with requests that joined it
+pub(crate) type SharedOutcome = std::result::Result<BuildResult, String>;
+
+/// A build running through a client
+struct InFlightBuild {
+    /// Session tasks to abort if the build outlives a shutdown grace period
+    sessions: Vec<AbortHandle>,
+    /// Health of the most recently started session
+    monitor: Option<SessionMonitor>,
+    /// Set once the build finished, for requests that joined it
+    outcome: watch::Sender<Option<SharedOutcome>>,
+}
+
+/// Shutdown coordination shared by all clones of a client
+struct Lifecycle {
+    /// Set once [`BuildKitClient::shutdown`] was called
+    shutdown: watch::Sender<bool>,
+    /// Notified whenever an in-flight build releases its ref
+    released: Notify,
 }
 
 impl BuildKitClient {
@@ -42,11 +78,36 @@ impl BuildKitClient {
                 source: e,
             })?;
 
-        let control = ControlClient::new(channel);
+        let control = ControlClient::new(channel.clone());
 
         tracing::info!("Successfully connected to buildkitd");
 
-        Ok(Self { control })
+        Ok(Self {
+            channel,
+            control,
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            lifecycle: Arc::new(Lifecycle {
+                shutdown: watch::Sender::new(false),
+                released: Notify::new(),
+            }),
+            #[cfg(feature = "journal")]
+            journal: None,
+        })
+    }
+
+    /// Record every build made through this client (and its clones) in `journal`
+    ///
+    /// Journal write failures are logged and never fail the build.
+    #[cfg(feature = "journal")]
+    pub fn with_journal(mut self, journal: Arc<crate::journal::BuildJournal>) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// The journal builds are recorded in, if any
+    #[cfg(feature = "journal")]
+    pub fn journal(&self) -> Option<Arc<crate::journal::BuildJournal>> {
+        self.journal.clone()
     }
 
     /// Get a reference to the control client
@@ -54,6 +115,11 @@ impl BuildKitClient {
         &mut self.control
     }
 
+    /// Channel to the daemon, for services other than `Control`
+    pub(crate) fn channel(&self) -> Channel {
+        self.channel.clone()
+    }
+
     /// Check if the buildkitd service is available
     pub async fn health_check(&mut self) -> Result<()> {
         use crate::proto::moby::buildkit::v1::InfoRequest;
@@ -66,4 +132,152 @@ impl BuildKitClient {
         tracing::debug!("BuildKit health check passed");
         Ok(())
     }
+
+    /// Get the refs of builds currently running through this client (and its clones)
+    pub fn in_flight_refs(&self) -> Vec<String> {
+        let in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        let mut refs: Vec<String> = in_flight.keys().cloned().collect();
+        refs.sort();
+        refs
+    }
+
+    /// Health of the session of the running build `build_ref`
+    ///
+    /// `None` if no such build is running through this client (or a clone)
+    /// or its session has not been started yet.
+    pub fn session_health(&self, build_ref: &str) -> Option<SessionHealth> {
+        let in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        in_flight
+            .get(build_ref)
+            .and_then(|build| build.monitor.as_ref())
+            .map(SessionMonitor::health)
+    }
+
+    /// Check whether [`shutdown`](Self::shutdown) was called on this client or a clone
+    pub fn is_shutting_down(&self) -> bool {
+        *self.lifecycle.shutdown.borrow()
+    }
+
+    /// Stop all builds running through this client and its clones
+    ///
+    /// New builds are refused from now on. Running builds are cancelled,
+    /// which makes the daemon abandon their solves, and get up to `grace` to
+    /// flush their sessions. Sessions still open after that are aborted.
+    ///
+    /// Returns the refs of the builds that had to be aborted.
+    pub async fn shutdown(&self, grace: Duration) -> Vec<String> {
+        self.lifecycle.shutdown.send_replace(true);
+        tracing::info!("Shutting down BuildKit client, cancelling {} build(s)", self.in_flight_refs().len());
+
+        let drained = tokio::time::timeout(grace, async {
+            loop {
+                // Register before checking so a release in between is not missed
+                let released = self.lifecycle.released.notified();
+                if self.in_flight.lock().unwrap_or_else(|e| e.into_inner()).is_empty() {
+                    return;
+                }
+                released.await;
+            }
+        })
+        .await;
+        if drained.is_ok() {
+            return Vec::new();
+        }
+
+        let in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        let mut aborted = Vec::with_capacity(in_flight.len());
+        for (build_ref, build) in in_flight.iter() {
+            tracing::warn!("Build {} did not stop within {:?}, aborting its session", build_ref, grace);
+            for handle in &build.sessions {
+                handle.abort();
+            }
+            aborted.push(build_ref.clone());
+        }
+        aborted.sort();
+        aborted
+    }
+
+    /// Wait until [`shutdown`](Self::shutdown) is called
+    pub(crate) async fn shutdown_requested(&self) {
+        let mut shutdown = self.lifecycle.shutdown.subscribe();
+        // Only fails if the sender is gone, which cannot happen while `self` holds it
+        let _ = shutdown.wait_for(|shutdown| *shutdown).await;
+    }
+
+    /// Reserve a build ref for the duration of a build
+    ///
+    /// Fails if a build with the same ref is already running or the client
+    /// is shutting down.
+    pub(crate) fn reserve_ref(&self, build_ref: &str) -> Result<InFlightRef> {
+        if self.is_shutting_down() {
+            return Err(Error::ClientShutdown);
+        }
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        if in_flight.contains_key(build_ref) {
+            return Err(Error::DuplicateBuildRef(build_ref.to_string()));
+        }
+        in_flight.insert(
+            build_ref.to_string(),
+            InFlightBuild {
+                sessions: Vec::new(),
+                monitor: None,
+                outcome: watch::Sender::new(None),
+            },
+        );
+        Ok(InFlightRef {
+            build_ref: build_ref.to_string(),
+            in_flight: self.in_flight.clone(),
+            lifecycle: self.lifecycle.clone(),
+        })
+    }
+
+    /// Follow the outcome of the running build `build_ref`, if there is one
+    pub(crate) fn join_ref(
+        &self,
+        build_ref: &str,
+    ) -> Option<watch::Receiver<Option<SharedOutcome>>> {
+        let in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        in_flight
+            .get(build_ref)
+            .map(|build| build.outcome.subscribe())
+    }
+}
+
+/// Releases a reserved build ref when dropped
+pub(crate) struct InFlightRef {
+    build_ref: String,
+    in_flight: Arc<Mutex<HashMap<String, InFlightBuild>>>,
+    lifecycle: Arc<Lifecycle>,
+}
+
+impl InFlightRef {
+    /// Register a started session, to report its health and to abort its
+    /// tasks if the build outlives a shutdown grace period
+    pub(crate) fn attach(&self, session: &Session) {
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(build) = in_flight.get_mut(&self.build_ref) {
+            build.sessions.extend(session.abort_handles());
+            build.monitor = session.monitor();
+        }
+    }
+
+    /// Hand the outcome of the build to the requests that joined it
+    pub(crate) fn publish(&self, result: &Result<BuildResult>) {
+        let outcome = match result {
+            Ok(result) => Ok(result.clone()),
+            Err(e) => Err(e.to_string()),
+        };
+        let in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(build) = in_flight.get(&self.build_ref) {
+            build.outcome.send_replace(Some(outcome));
+        }
+    }
+}
+
+impl Drop for InFlightRef {
+    fn drop(&mut self) {
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        in_flight.remove(&self.build_ref);
+        self.lifecycle.released.notify_waiters();
+    }
 }
@@ -0,0 +1,106 @@
+//! Copying images between registries on the builder
+//!
+//! Promoting an image from a staging registry to a release registry does not
+//! need a Dockerfile. [`BuildKitClient::copy_image`] solves a raw LLB graph
+//! made of a single image source and exports it with the image exporter, so
+//! the layers move from registry to registry on the BuildKit node and never
+//! pass through the client.
+
+use crate::builder::RegistryAuth;
+use crate::client::BuildKitClient;
+use crate::error::{Error, Result};
+use crate::llb::LlbSolveOptions;
+use crate::output::{ImageOutput, Output};
+use crate::proto::pb;
+use crate::solve::BuildResult;
+use prost::Message;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Scheme of LLB image source identifiers
+pub const IMAGE_SOURCE_SCHEME: &str = "docker-image://";
+
+/// LLB capability used by image sources
+const IMAGE_SOURCE_CAP: &str = "source.image";
+
+/// LLB definition that resolves to the root filesystem of `image`
+///
+/// The graph holds the image source and the terminal op pointing at it;
+/// ops are keyed by the `sha256` digest of their encoding, as BuildKit
+/// expects.
+pub fn image_definition(image: &str) -> Result<pb::Definition> {
+    check_image_ref(image)?;
+
+    let source = pb::Op {
+        op: Some(pb::op::Op::Source(pb::SourceOp {
+            identifier: format!("{}{}", IMAGE_SOURCE_SCHEME, image),
+            attrs: HashMap::new(),
+        })),
+        ..Default::default()
+    }
+    .encode_to_vec();
+    let source_digest = op_digest(&source);
+
+    let terminal = pb::Op {
+        inputs: vec![pb::Input {
+            digest: source_digest.clone(),
+            index: 0,
+        }],
+        ..Default::default()
+    }
+    .encode_to_vec();
+
+    let mut metadata = HashMap::with_capacity(1);
+    metadata.insert(
+        source_digest,
+        pb::OpMetadata {
+            caps: HashMap::from([(IMAGE_SOURCE_CAP.to_string(), true)]),
+            ..Default::default()
+        },
+    );
+
+    Ok(pb::Definition {
+        def: vec![source, terminal],
+        metadata,
+        source: None,
+    })
+}
+
+/// Check that `image` looks like an image reference BuildKit can resolve
+pub(crate) fn check_image_ref(image: &str) -> Result<()> {
+    if image.is_empty() || image.contains("://") || image.chars().any(char::is_whitespace) {
+        return Err(Error::InvalidConfig(format!(
+            "invalid image reference {:?}",
+            image
+        )));
+    }
+    Ok(())
+}
+
+/// Digest identifying an encoded op in a definition
+pub(crate) fn op_digest(op: &[u8]) -> String {
+    format!("sha256:{:x}", Sha256::digest(op))
+}
+
+impl BuildKitClient {
+    /// Copy an image to another name, usually in another registry
+    ///
+    /// The daemon pulls `src_ref` and pushes it as `dst_ref` without a
+    /// frontend; `auths` holds credentials for either registry. Only the
+    /// platform of the BuildKit worker is copied from multi-platform images.
+    pub async fn copy_image(
+        &mut self,
+        src_ref: &str,
+        dst_ref: &str,
+        auths: Vec<RegistryAuth>,
+    ) -> Result<BuildResult> {
+        let definition = image_definition(src_ref)?;
+        let output = Output::Image(ImageOutput::new().name(dst_ref).push(true));
+        tracing::info!("Copying {} to {}", src_ref, dst_ref);
+
+        let options = auths
+            .into_iter()
+            .fold(LlbSolveOptions::new(), LlbSolveOptions::registry_auth);
+        self.solve_llb(definition, vec![output], options).await
+    }
+}
@@ -0,0 +1,473 @@
+//! Debugging failed builds in a container
+//!
+//! When a `RUN` step fails, BuildKit can keep the filesystems of the step
+//! around: the state it started from and the state it failed in. A build run
+//! with [`BuildKitClient::build_with_debugger`] goes through the gateway API
+//! so that, on failure, a [`DebugHandler`] can start containers on either
+//! state and run processes in them (like `buildx debug`) before the build is
+//! reported as failed.
+
+use crate::builder::BuildConfig;
+use crate::client::BuildKitClient;
+use crate::error::{Error, Result};
+use crate::gateway::{take_frontend_request, Bridge, Forward};
+use crate::progress::ProgressHandler;
+use crate::proto::errdefs;
+use crate::proto::google::rpc::Status;
+use crate::proto::moby::buildkit::v1::control_client::ControlClient;
+use crate::proto::moby::buildkit::v1::frontend::exec_message::Input;
+use crate::proto::moby::buildkit::v1::frontend::{
+    ExecMessage, FdMessage, InitMessage, NewContainerRequest, ResizeMessage, SignalMessage,
+};
+use crate::proto::moby::buildkit::v1::SolveRequest;
+use crate::proto::pb;
+use crate::solve::BuildResult;
+use bytes::Bytes;
+use prost::Message;
+use std::collections::HashMap;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::Channel;
+
+/// Type of the error detail describing a failed step
+pub const SOLVE_ERROR_TYPE: &str = "errdefs.Solve";
+
+/// Filesystem state a debug container is started on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DebugState {
+    /// As the failed step left it
+    #[default]
+    Failed,
+    /// As the failed step started from
+    Initial,
+}
+
+/// Step that failed a build, as reported by BuildKit
+#[derive(Debug, Clone, Default)]
+pub struct FailedStep {
+    /// Error message of the step
+    pub message: String,
+    /// The failed `RUN` step, if the failing op is one
+    pub exec: Option<pb::ExecOp>,
+    /// Results the step started from, by op input
+    pub input_ids: Vec<String>,
+    /// Results the step left in its mounts, by mount
+    pub mount_ids: Vec<String>,
+}
+
+impl FailedStep {
+    /// Read the failed step from the details of a solve error
+    pub fn from_status(status: &tonic::Status) -> Option<Self> {
+        let details = Status::decode(status.details()).ok()?;
+        let solve = details
+            .details
+            .iter()
+            .filter(|detail| detail.type_url.ends_with(SOLVE_ERROR_TYPE))
+            .find_map(|detail| errdefs::Solve::decode(detail.value.as_slice()).ok())?;
+        let exec = match solve.op.and_then(|op| op.op) {
+            Some(pb::op::Op::Exec(exec)) => Some(exec),
+            _ => None,
+        };
+        Some(Self {
+            message: status.message().to_string(),
+            exec,
+            input_ids: solve.input_i_ds,
+            mount_ids: solve.mount_i_ds,
+        })
+    }
+
+    /// Command line of the failed step
+    pub fn args(&self) -> &[String] {
+        self.exec
+            .as_ref()
+            .and_then(|exec| exec.meta.as_ref())
+            .map(|meta| meta.args.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Mounts of a container on `state`, in the order of the step's mounts
+    pub fn mounts(&self, state: DebugState) -> Result<Vec<pb::Mount>> {
+        let exec = self
+            .exec
+            .as_ref()
+            .ok_or_else(|| Error::frontend("only failed RUN steps can be debugged"))?;
+        let mounts = exec
+            .mounts
+            .iter()
+            .enumerate()
+            .map(|(index, mount)| {
+                let result_id = match state {
+                    DebugState::Failed => self.mount_ids.get(index),
+                    DebugState::Initial => usize::try_from(mount.input)
+                        .ok()
+                        .and_then(|input| self.input_ids.get(input)),
+                };
+                pb::Mount {
+                    result_id: result_id.cloned().unwrap_or_default(),
+                    ..mount.clone()
+                }
+            })
+            .collect();
+        Ok(mounts)
+    }
+}
+
+/// Inspects the step that failed a build
+///
+/// The build is kept open while [`on_failure`](Self::on_failure) runs and
+/// fails with the step's error once it returns; containers still running
+/// then are removed.
+#[tonic::async_trait]
+pub trait DebugHandler: Send {
+    /// Called with the failed step of the build
+    async fn on_failure(&mut self, session: &mut DebugSession) -> Result<()>;
+}
+
+/// Access to the results of a failed build
+pub struct DebugSession {
+    bridge: Bridge,
+    step: FailedStep,
+}
+
+impl DebugSession {
+    /// The step that failed the build
+    pub fn step(&self) -> &FailedStep {
+        &self.step
+    }
+
+    /// Start a container on `state` of the failed step
+    ///
+    /// The container gets the mounts and network of the step; processes
+    /// run with its environment, working directory and user.
+    pub async fn start_container(&mut self, state: DebugState) -> Result<DebugContainer> {
+        let mounts = self.step.mounts(state)?;
+        let exec = self.step.exec.clone().unwrap_or_default();
+        let meta = exec.meta.unwrap_or_default();
+        let id = uuid::Uuid::new_v4().to_string();
+        self.bridge
+            .new_container(NewContainerRequest {
+                container_id: id.clone(),
+                mounts,
+                network: exec.network,
+                extra_hosts: meta.extra_hosts.clone(),
+                hostname: meta.hostname.clone(),
+                ..Default::default()
+            })
+            .await?;
+        tracing::info!("Started debug container {}", id);
+        Ok(DebugContainer {
+            id,
+            bridge: self.bridge.clone(),
+            meta,
+            security: exec.security,
+        })
+    }
+}
+
+/// A container on the state of a failed step
+pub struct DebugContainer {
+    id: String,
+    bridge: Bridge,
+    meta: pb::Meta,
+    security: i32,
+}
+
+impl DebugContainer {
+    /// Container ID
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Run `args` with the environment, working directory and user of the failed step
+    ///
+    /// With `tty`, the process gets a terminal and its stderr is merged
+    /// into stdout.
+    pub async fn exec(&mut self, args: Vec<String>, tty: bool) -> Result<DebugProcess> {
+        if args.is_empty() {
+            return Err(Error::InvalidConfig(
+                "debug process needs a command".to_string(),
+            ));
+        }
+        let process_id = uuid::Uuid::new_v4().to_string();
+        let (input, stream) = mpsc::channel(16);
+        let init = ExecMessage {
+            process_id: process_id.clone(),
+            input: Some(Input::Init(InitMessage {
+                container_id: self.id.clone(),
+                meta: Some(pb::Meta {
+                    args,
+                    ..self.meta.clone()
+                }),
+                fds: vec![0, 1, 2],
+                tty,
+                security: self.security,
+                ..Default::default()
+            })),
+        };
+        input
+            .send(init)
+            .await
+            .map_err(|_| Error::frontend("process stream closed"))?;
+        let output = self
+            .bridge
+            .exec_process(ReceiverStream::new(stream))
+            .await?;
+        Ok(DebugProcess {
+            process_id,
+            input,
+            output,
+            exited: false,
+        })
+    }
+
+    /// Run an interactive `/bin/sh`
+    pub async fn shell(&mut self) -> Result<DebugProcess> {
+        self.exec(vec!["/bin/sh".to_string()], true).await
+    }
+
+    /// Stop the container and its processes
+    pub async fn release(mut self) -> Result<()> {
+        self.bridge.release_container(self.id.clone()).await?;
+        Ok(())
+    }
+}
+
+/// Output of a debug process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcessOutput {
+    /// Data written to stdout (or the terminal)
+    Stdout(Bytes),
+    /// Data written to stderr
+    Stderr(Bytes),
+    /// The process exited; `error` is set if it could not run
+    Exit { code: u32, error: Option<String> },
+}
+
+/// A process running in a debug container
+pub struct DebugProcess {
+    process_id: String,
+    input: mpsc::Sender<ExecMessage>,
+    output: tonic::Streaming<ExecMessage>,
+    exited: bool,
+}
+
+impl DebugProcess {
+    async fn send(&self, input: Input) -> Result<()> {
+        let message = ExecMessage {
+            process_id: self.process_id.clone(),
+            input: Some(input),
+        };
+        self.input
+            .send(message)
+            .await
+            .map_err(|_| Error::frontend("process stream closed"))
+    }
+
+    /// Write to the process's stdin
+    pub async fn write_stdin(&self, data: impl Into<Vec<u8>>) -> Result<()> {
+        self.send(Input::File(FdMessage {
+            fd: 0,
+            eof: false,
+            data: data.into(),
+        }))
+        .await
+    }
+
+    /// Close the process's stdin
+    pub async fn close_stdin(&self) -> Result<()> {
+        self.send(Input::File(FdMessage {
+            fd: 0,
+            eof: true,
+            data: Vec::new(),
+        }))
+        .await
+    }
+
+    /// Resize the terminal of a process started with a TTY
+    pub async fn resize(&self, rows: u32, cols: u32) -> Result<()> {
+        self.send(Input::Resize(ResizeMessage { rows, cols })).await
+    }
+
+    /// Send a signal (e.g., `SIGINT`) to the process
+    pub async fn signal(&self, name: &str) -> Result<()> {
+        self.send(Input::Signal(SignalMessage {
+            name: name.to_string(),
+        }))
+        .await
+    }
+
+    /// Wait for the next output; `None` once the process exited
+    pub async fn next_output(&mut self) -> Result<Option<ProcessOutput>> {
+        if self.exited {
+            return Ok(None);
+        }
+        while let Some(message) = self.output.message().await? {
+            match message.input {
+                Some(Input::File(file)) if !file.data.is_empty() => {
+                    let data = Bytes::from(file.data);
+                    return Ok(Some(match file.fd {
+                        2 => ProcessOutput::Stderr(data),
+                        _ => ProcessOutput::Stdout(data),
+                    }));
+                }
+                Some(Input::Exit(exit)) => {
+                    self.exited = true;
+                    let error = exit
+                        .error
+                        .filter(|status| status.code != 0)
+                        .map(|status| status.message);
+                    return Ok(Some(ProcessOutput::Exit {
+                        code: exit.code,
+                        error,
+                    }));
+                }
+                Some(Input::Done(_)) => break,
+                _ => {}
+            }
+        }
+        self.exited = true;
+        Ok(None)
+    }
+
+    /// Discard the remaining output and return the exit code
+    pub async fn wait(mut self) -> Result<u32> {
+        while let Some(output) = self.next_output().await? {
+            if let ProcessOutput::Exit { code, error } = output {
+                return match error {
+                    Some(error) => Err(Error::frontend(error)),
+                    None => Ok(code),
+                };
+            }
+        }
+        Err(Error::frontend("debug process ended without exit status"))
+    }
+}
+
+/// Opens a shell on a failed step, wired to the local stdin and stdout
+///
+/// Input is line-buffered by the local terminal; there is no TTY in the
+/// container.
+#[derive(Debug, Clone)]
+pub struct ShellDebugger {
+    /// Shell command line
+    pub shell: Vec<String>,
+    /// State to open the shell on
+    pub state: DebugState,
+}
+
+impl Default for ShellDebugger {
+    fn default() -> Self {
+        Self {
+            shell: vec!["/bin/sh".to_string()],
+            state: DebugState::Failed,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl DebugHandler for ShellDebugger {
+    async fn on_failure(&mut self, session: &mut DebugSession) -> Result<()> {
+        let mut stdout = tokio::io::stdout();
+        let mut stderr = tokio::io::stderr();
+        let banner = format!(
+            "Step {:?} failed: {}\nOpening {} on its {:?} state; exit the shell to continue\n",
+            session.step().args().join(" "),
+            session.step().message,
+            self.shell.join(" "),
+            self.state,
+        );
+        stderr.write_all(banner.as_bytes()).await?;
+
+        let mut container = session.start_container(self.state).await?;
+        let mut process = container.exec(self.shell.clone(), false).await?;
+        let mut stdin = tokio::io::stdin();
+        let mut buf = vec![0u8; 4096];
+        let mut stdin_open = true;
+        loop {
+            tokio::select! {
+                read = stdin.read(&mut buf), if stdin_open => match read {
+                    Ok(0) | Err(_) => {
+                        stdin_open = false;
+                        process.close_stdin().await?;
+                    }
+                    Ok(n) => process.write_stdin(&buf[..n]).await?,
+                },
+                output = process.next_output() => match output? {
+                    Some(ProcessOutput::Stdout(data)) => {
+                        stdout.write_all(&data).await?;
+                        stdout.flush().await?;
+                    }
+                    Some(ProcessOutput::Stderr(data)) => {
+                        stderr.write_all(&data).await?;
+                    }
+                    Some(ProcessOutput::Exit { error: Some(error), .. }) => {
+                        stderr.write_all(format!("{}\n", error).as_bytes()).await?;
+                        break;
+                    }
+                    Some(ProcessOutput::Exit { .. }) | None => break,
+                },
+            }
+        }
+        container.release().await
+    }
+}
+
+/// Build through the gateway, handing a failed step to `handler`
+pub(crate) async fn solve_with_debugger(
+    control: &mut ControlClient<Channel>,
+    channel: Channel,
+    mut request: tonic::Request<SolveRequest>,
+    handler: &mut dyn DebugHandler,
+) -> Result<HashMap<String, String>> {
+    let solve = request.get_mut();
+    let build_ref = solve.r#ref.clone();
+    let mut frontend_request = take_frontend_request(solve);
+    // Fail on the step itself, keeping its results for containers
+    frontend_request.allow_result_return = true;
+    frontend_request.evaluate = true;
+
+    let debug = async {
+        let mut bridge = Bridge::new(channel, &build_ref)?;
+        match bridge.solve(frontend_request).await {
+            // Exported by the solve as usual
+            Ok(result) => bridge.finish(result, None).await,
+            Err(status) => {
+                match FailedStep::from_status(&status) {
+                    Some(step) => {
+                        let mut session = DebugSession {
+                            bridge: bridge.clone(),
+                            step,
+                        };
+                        if let Err(e) = handler.on_failure(&mut session).await {
+                            tracing::warn!("Debugging build {} failed: {}", build_ref, e);
+                        }
+                    }
+                    None => tracing::warn!("Build {} failed outside of a step", build_ref),
+                }
+                bridge.finish(Default::default(), Some(&status)).await?;
+                Err(Error::from(status))
+            }
+        }
+    };
+    let (solved, debugged) = tokio::join!(control.solve(request), debug);
+    // A failed step also fails the solve; its own error says more
+    debugged?;
+    Ok(solved?.into_inner().exporter_response)
+}
+
+impl BuildKitClient {
+    /// Build `config`, handing a failed `RUN` step to `debugger`
+    ///
+    /// The build runs through the gateway API; on success it is exported
+    /// like with [`build`](Self::build).
+    pub async fn build_with_debugger(
+        &mut self,
+        config: BuildConfig,
+        progress_handler: Option<Box<dyn ProgressHandler>>,
+        debugger: &mut dyn DebugHandler,
+    ) -> Result<BuildResult> {
+        self.run_build(config, progress_handler, Some(Forward::Debug(debugger)))
+            .await
+    }
+}
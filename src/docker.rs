@@ -0,0 +1,287 @@
+//! Helpers for loading built images into a local Docker daemon
+//!
+//! A `type=docker` export is streamed through the session straight into the
+//! daemon's `POST /images/load` endpoint, so the image shows up in
+//! `docker images` without a registry round-trip or a temporary file.
+//! [`BuildKitClient::build_and_load`] does it for the configured tags.
+
+use crate::builder::BuildConfig;
+use crate::client::BuildKitClient;
+use crate::error::{Error, Result};
+use crate::output::{ArchiveOutput, ExportDest, ExportWriter, Output};
+use crate::progress::ProgressHandler;
+use crate::solve::BuildResult;
+use crate::task::spawn_named;
+use std::path::PathBuf;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::task::JoinHandle;
+
+/// Default Docker daemon socket
+pub const DEFAULT_DOCKER_SOCKET: &str = "/var/run/docker.sock";
+
+/// Size of the in-memory pipe between the exporter and the daemon upload
+const PIPE_CAPACITY: usize = 1024 * 1024;
+
+/// Loads `type=docker` exports into a local Docker daemon
+#[derive(Debug, Clone)]
+pub struct DockerLoader {
+    socket: PathBuf,
+}
+
+impl DockerLoader {
+    /// Create a loader using `DOCKER_HOST` (unix:// only) or the default socket
+    pub fn new() -> Self {
+        let socket = std::env::var("DOCKER_HOST")
+            .ok()
+            .and_then(|host| host.strip_prefix("unix://").map(PathBuf::from))
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_DOCKER_SOCKET));
+        Self { socket }
+    }
+
+    /// Create a loader for a specific daemon socket
+    pub fn with_socket(socket: impl Into<PathBuf>) -> Self {
+        Self {
+            socket: socket.into(),
+        }
+    }
+
+    /// Get the daemon socket path
+    pub fn socket(&self) -> &PathBuf {
+        &self.socket
+    }
+
+    /// Create a `type=docker` output piped into the daemon
+    ///
+    /// Add the returned output to the build configuration and await the
+    /// returned [`DockerLoad`] once the build has finished. Must be called
+    /// from within a Tokio runtime.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use buildkit_client::{BuildConfig, BuildKitClient};
+    /// use buildkit_client::docker::DockerLoader;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let mut client = BuildKitClient::connect("http://localhost:1234").await?;
+    ///
+    ///     let (output, load) = DockerLoader::new().output("my-app:dev");
+    ///     let config = BuildConfig::local(".").output(output);
+    ///
+    ///     client.build(config, None).await?;
+    ///     println!("{}", load.wait().await?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn output(&self, name: impl Into<String>) -> (Output, DockerLoad) {
+        self.tagged_output([name])
+    }
+
+    /// Create a `type=docker` output piped into the daemon, tagging the
+    /// image with every name in `names`
+    ///
+    /// See [`output`](Self::output).
+    pub fn tagged_output<I, S>(&self, names: I) -> (Output, DockerLoad)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let (writer, reader) = tokio::io::duplex(PIPE_CAPACITY);
+        let archive = names.into_iter().fold(
+            ArchiveOutput::new(ExportDest::Writer(ExportWriter::new(writer))),
+            |archive, name| archive.name(name),
+        );
+
+        let socket = self.socket.clone();
+        let task = spawn_named(
+            "buildkit.docker_load",
+            tracing::info_span!("docker_load", socket = %socket.display()),
+            load_image(socket, reader),
+        );
+
+        (Output::Docker(archive), DockerLoad { task })
+    }
+
+    /// Build `config` and load the image into the daemon under its tags
+    ///
+    /// The configured tags name the loaded image instead of being pushed;
+    /// other outputs are exported as usual. Returns the build result and
+    /// the daemon's response.
+    pub async fn build(
+        &self,
+        client: &mut BuildKitClient,
+        mut config: BuildConfig,
+        progress_handler: Option<Box<dyn ProgressHandler>>,
+    ) -> Result<(BuildResult, String)> {
+        let tags = std::mem::take(&mut config.tags);
+        let (output, load) = self.tagged_output(tags);
+        config.outputs.push(output);
+
+        match client.build(config, progress_handler).await {
+            Ok(result) => Ok((result, load.wait().await?)),
+            Err(e) => {
+                // Never hand the daemon a truncated image
+                load.task.abort();
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Default for DockerLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pending upload of an exported image into the Docker daemon
+#[derive(Debug)]
+pub struct DockerLoad {
+    task: JoinHandle<Result<String>>,
+}
+
+impl DockerLoad {
+    /// Wait for the daemon to finish loading the image
+    ///
+    /// Returns the daemon's response body (e.g., "Loaded image: my-app:dev").
+    pub async fn wait(self) -> Result<String> {
+        self.task
+            .await
+            .map_err(|e| Error::DockerLoad(format!("load task failed: {}", e)))?
+    }
+}
+
+impl BuildKitClient {
+    /// Build an image and load it into the local Docker daemon
+    ///
+    /// The image appears in `docker images` under the configured tags
+    /// without going through a registry; the daemon is found through
+    /// `DOCKER_HOST` (unix sockets only) or at the default socket. Use
+    /// [`DockerLoader::build`] for another daemon. Multi-platform builds
+    /// cannot be loaded.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use buildkit_client::{BuildConfig, BuildKitClient};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let mut client = BuildKitClient::connect("http://localhost:1234").await?;
+    ///
+    ///     let config = BuildConfig::local(".").tag("my-app:dev");
+    ///     let (_, loaded) = client.build_and_load(config, None).await?;
+    ///     println!("{}", loaded);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn build_and_load(
+        &mut self,
+        config: BuildConfig,
+        progress_handler: Option<Box<dyn ProgressHandler>>,
+    ) -> Result<(BuildResult, String)> {
+        DockerLoader::new()
+            .build(self, config, progress_handler)
+            .await
+    }
+}
+
+/// Stream a tarball into `POST /images/load` on the daemon socket
+#[cfg(unix)]
+async fn load_image(socket: PathBuf, mut tarball: impl AsyncRead + Unpin) -> Result<String> {
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::UnixStream;
+
+    let mut stream = UnixStream::connect(&socket).await.map_err(|e| {
+        Error::DockerLoad(format!("failed to connect to {}: {}", socket.display(), e))
+    })?;
+
+    stream
+        .write_all(
+            b"POST /images/load?quiet=1 HTTP/1.1\r\n\
+              Host: docker\r\n\
+              Content-Type: application/x-tar\r\n\
+              Transfer-Encoding: chunked\r\n\
+              Connection: close\r\n\r\n",
+        )
+        .await?;
+
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut total = 0u64;
+    loop {
+        let n = tarball.read(&mut buffer).await?;
+        if n == 0 {
+            break;
+        }
+        stream.write_all(format!("{:x}\r\n", n).as_bytes()).await?;
+        stream.write_all(&buffer[..n]).await?;
+        stream.write_all(b"\r\n").await?;
+        total += n as u64;
+    }
+    stream.write_all(b"0\r\n\r\n").await?;
+    tracing::debug!("Uploaded {} bytes to docker daemon", total);
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    parse_load_response(&response)
+}
+
+#[cfg(not(unix))]
+async fn load_image(socket: PathBuf, _tarball: impl AsyncRead + Unpin) -> Result<String> {
+    Err(Error::DockerLoad(format!(
+        "cannot reach {}: only unix sockets are supported",
+        socket.display()
+    )))
+}
+
+/// Parse the daemon's HTTP response, returning the body on success
+fn parse_load_response(response: &[u8]) -> Result<String> {
+    let split = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| Error::DockerLoad("malformed response from daemon".to_string()))?;
+    let head = String::from_utf8_lossy(&response[..split]);
+    let body = &response[split + 4..];
+
+    let status = head
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| Error::DockerLoad("malformed status line from daemon".to_string()))?;
+
+    let chunked = head
+        .lines()
+        .any(|line| line.eq_ignore_ascii_case("transfer-encoding: chunked"));
+    let body = if chunked {
+        decode_chunked(body)
+    } else {
+        body.to_vec()
+    };
+    let body = String::from_utf8_lossy(&body).trim().to_string();
+
+    if status == 200 {
+        Ok(body)
+    } else {
+        Err(Error::DockerLoad(format!("daemon returned {}: {}", status, body)))
+    }
+}
+
+/// Decode an HTTP/1.1 chunked body
+fn decode_chunked(mut body: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::new();
+    while let Some(line_end) = body.windows(2).position(|w| w == b"\r\n") {
+        let size = std::str::from_utf8(&body[..line_end])
+            .ok()
+            .and_then(|s| usize::from_str_radix(s.trim(), 16).ok())
+            .unwrap_or(0);
+        let rest = &body[line_end + 2..];
+        if size == 0 || rest.len() < size {
+            break;
+        }
+        decoded.extend_from_slice(&rest[..size]);
+        body = rest[size..].strip_prefix(b"\r\n").unwrap_or(&rest[size..]);
+    }
+    decoded
+}
@@ -1,5 +1,6 @@
 //! Error types for BuildKit client operations
 
+use std::fmt;
 use std::path::PathBuf;
 use thiserror::Error;
 
@@ -45,6 +46,14 @@ pub enum Error {
     #[error("Path is not a directory: {0}")]
     NotADirectory(PathBuf),
 
+    /// Build context contains paths the configured user cannot read
+    #[error(
+        "{} context path(s) not readable by uid {uid}: {}",
+        .paths.len(),
+        format_paths(.paths)
+    )]
+    ContextPermissionDenied { uid: u32, paths: Vec<PathBuf> },
+
     /// Path is outside root directory
     #[error("Path {path} is outside root directory")]
     PathOutsideRoot { path: String },
@@ -57,6 +66,26 @@ pub enum Error {
         source: std::io::Error,
     },
 
+    /// A context file could not be opened or read
+    #[error("Failed to read context file {path}: {source}")]
+    ContextFileRead {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// A context file changed while it was being sent
+    #[error("Context file {path} changed during sync: {reason}")]
+    ContextFileChanged { path: PathBuf, reason: String },
+
+    /// A context file changed after it was snapshotted
+    #[error("Context file {path} changed during the build: {reason}")]
+    ContextChangedDuringBuild { path: PathBuf, reason: String },
+
+    /// A tar archive used as the build context could not be read
+    #[error("Invalid context archive: {0}")]
+    ContextArchive(String),
+
     /// Build execution errors
     #[error("Build execution failed: {0}")]
     Build(String),
@@ -65,10 +94,38 @@ pub enum Error {
     #[error("Invalid build configuration: {0}")]
     InvalidConfig(String),
 
+    /// The Dockerfile uses syntax only the labs frontend understands
+    #[error("Dockerfile line {line} uses {feature}, which needs the labs Dockerfile frontend; select Frontend::dockerfile_labs() or add `# syntax=docker/dockerfile:1-labs`")]
+    LabsFeatureRequired { feature: String, line: usize },
+
+    /// The client is shutting down and accepts no new builds
+    #[error("BuildKit client is shutting down")]
+    ClientShutdown,
+
+    /// The build was cancelled before it finished
+    #[error("Build {0} was cancelled")]
+    BuildCancelled(String),
+
+    /// Pre-flight validation found problems with a build configuration
+    #[error("Invalid build configuration: {}", join_problems(.0))]
+    InvalidBuildConfig(Vec<ConfigProblem>),
+
+    /// A build with the same ref is already running
+    #[error("Build ref {0} is already in use by a running build")]
+    DuplicateBuildRef(String),
+
+    /// The running build a deduplicated request joined failed
+    #[error("Joined build {build_ref} failed: {message}")]
+    JoinedBuildFailed { build_ref: String, message: String },
+
     /// Invalid platform format
     #[error("Invalid platform format: {0}")]
     InvalidPlatform(String),
 
+    /// Invalid output specification
+    #[error("Invalid output specification: {0}")]
+    InvalidOutput(String),
+
     /// Progress monitoring errors
     #[error("Progress monitoring failed: {0}")]
     Progress(String),
@@ -84,6 +141,10 @@ pub enum Error {
         source: h2::Error,
     },
 
+    /// The HTTP/2 connection inside the session could not be established
+    #[error("Session tunnel handshake failed: {0}; check that the BuildKit daemon supports client sessions (v0.11 or newer) and that nothing between client and daemon rewrites the session stream")]
+    SessionHandshake(String),
+
     /// HTTP/2 stream error
     #[error("HTTP/2 stream error: {source}")]
     Http2Stream {
@@ -126,6 +187,58 @@ pub enum Error {
     #[error("Secrets service is not configured")]
     SecretsNotConfigured,
 
+    /// Blob missing from a session content store
+    #[error("Content {digest} not found in store {store}")]
+    ContentNotFound { store: String, digest: String },
+
+    /// Session content store errors
+    #[error("Content store error: {0}")]
+    ContentStore(String),
+
+    /// Malformed OCI descriptor, index or manifest
+    #[error("Invalid OCI content: {0}")]
+    Oci(String),
+
+    /// Registry request failed
+    #[error("Registry request to {url} failed: {reason}")]
+    Registry { url: String, reason: String },
+
+    /// A pushed name does not resolve to the built manifest
+    #[error("Push verification of {name} failed: {reason}")]
+    PushVerification { name: String, reason: String },
+
+    /// Remote `ADD` source failed pre-flight verification
+    #[error("Remote source {url} (Dockerfile line {line}) failed verification: {reason}")]
+    RemoteSource {
+        url: String,
+        line: usize,
+        reason: String,
+    },
+
+    /// The git ref of a context does not exist in the repository
+    #[error("Git ref {reference} not found in {repo}")]
+    GitRefNotFound { repo: String, reference: String },
+
+    /// Fetching a git context on the client failed
+    #[error("Git error: {0}")]
+    Git(String),
+
+    /// Starting or reaching an SSH agent to forward failed
+    #[error("SSH agent error: {0}")]
+    SshAgent(String),
+
+    /// A frontend request through the gateway API failed
+    #[error("Frontend request failed: {0}")]
+    Frontend(String),
+
+    /// Loading an image into the local Docker daemon failed
+    #[error("Docker load failed: {0}")]
+    DockerLoad(String),
+
+    /// Reading or writing the build journal failed
+    #[error("Build journal error: {0}")]
+    Journal(String),
+
     /// Generic error for compatibility during migration
     #[error("{0}")]
     Other(String),
@@ -157,6 +270,36 @@ impl Error {
         Error::Secrets(msg.into())
     }
 
+    /// Create a content store error
+    pub fn content_store(msg: impl Into<String>) -> Self {
+        Error::ContentStore(msg.into())
+    }
+
+    /// Create an OCI content error
+    pub fn oci(msg: impl Into<String>) -> Self {
+        Error::Oci(msg.into())
+    }
+
+    /// Create a git error
+    pub fn git(msg: impl Into<String>) -> Self {
+        Error::Git(msg.into())
+    }
+
+    /// Create an SSH agent error
+    pub fn ssh_agent(msg: impl Into<String>) -> Self {
+        Error::SshAgent(msg.into())
+    }
+
+    /// Create a frontend request error
+    pub fn frontend(msg: impl Into<String>) -> Self {
+        Error::Frontend(msg.into())
+    }
+
+    /// Create a context archive error
+    pub fn context_archive(msg: impl Into<String>) -> Self {
+        Error::ContextArchive(msg.into())
+    }
+
     /// Create a send failed error
     pub fn send_failed(message_type: impl Into<String>, reason: impl Into<String>) -> Self {
         Error::SendFailed {
@@ -185,6 +328,71 @@ impl Error {
     pub fn other(msg: impl Into<String>) -> Self {
         Error::Other(msg.into())
     }
+
+    /// Whether this error only reflects the daemon closing the session
+    ///
+    /// BuildKit tears the session down as soon as the solve finishes, often
+    /// while the tunnel is still flushing; the resulting resets and broken
+    /// pipes are expected.
+    pub fn is_disconnect(&self) -> bool {
+        match self {
+            Error::Http2Handshake { source } | Error::Http2Stream { source } => {
+                is_h2_disconnect(source)
+            }
+            Error::Io(e) => is_io_disconnect(e),
+            _ => false,
+        }
+    }
+
+    /// Whether this error is a genuine fault in the session protocol
+    ///
+    /// Covers malformed or oversized messages and HTTP/2 errors other than a
+    /// [disconnect](Self::is_disconnect).
+    pub fn is_protocol_fault(&self) -> bool {
+        match self {
+            Error::Protocol(_) | Error::Decode { .. } | Error::SessionHandshake(_) => true,
+            Error::Http2Handshake { .. } | Error::Http2Stream { .. } => !self.is_disconnect(),
+            _ => false,
+        }
+    }
+}
+
+fn is_io_disconnect(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::BrokenPipe
+            | std::io::ErrorKind::UnexpectedEof
+            | std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::ConnectionAborted
+    )
+}
+
+fn is_h2_disconnect(e: &h2::Error) -> bool {
+    if let Some(io) = e.get_io() {
+        return is_io_disconnect(io);
+    }
+    matches!(e.reason(), Some(h2::Reason::NO_ERROR) | Some(h2::Reason::CANCEL))
+}
+
+/// Format a list of paths for error messages, truncating long lists
+fn format_paths(paths: &[PathBuf]) -> String {
+    const MAX_LISTED: usize = 10;
+
+    let mut listed: Vec<String> = paths
+        .iter()
+        .take(MAX_LISTED)
+        .map(|p| {
+            if p.as_os_str().is_empty() {
+                ".".to_string()
+            } else {
+                p.display().to_string()
+            }
+        })
+        .collect();
+    if paths.len() > MAX_LISTED {
+        listed.push(format!("and {} more", paths.len() - MAX_LISTED));
+    }
+    listed.join(", ")
 }
 
 // Implement From for common error types
@@ -211,3 +419,36 @@ impl From<tonic::Status> for Error {
         Error::Grpc(Box::new(status))
     }
 }
+
+/// A problem found by [`BuildConfig::validate`](crate::BuildConfig::validate)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigProblem {
+    /// Option the problem is about (e.g., `tags`, `platforms`)
+    pub field: &'static str,
+    /// What is wrong with it
+    pub message: String,
+}
+
+impl ConfigProblem {
+    /// Create a problem report for `field`
+    pub fn new(field: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            field,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+fn join_problems(problems: &[ConfigProblem]) -> String {
+    problems
+        .iter()
+        .map(ConfigProblem::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
@@ -0,0 +1,690 @@
+//! Frontend attribute construction
+//!
+//! The Dockerfile frontend is configured through a flat string map in the
+//! solve request. [`FrontendAttrs`] is the single place that map is built:
+//! keys are validated as they are added and kept in canonical (sorted) order,
+//! so the attributes for a given [`BuildConfig`] are always rendered the same
+//! way and can be compared against what `buildctl build --opt ...` sends.
+
+use crate::attest::PROVENANCE_ATTR;
+use crate::builder::{BuildConfig, DockerfileSource, NamedContext, Platform};
+use crate::copy::IMAGE_SOURCE_SCHEME;
+use crate::error::{Error, Result};
+use crate::layout::{context_store_id, OCI_LAYOUT_SCHEME};
+use crate::remote_sources::logical_lines;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::net::IpAddr;
+
+/// Frontend used for Dockerfile builds
+pub const DOCKERFILE_FRONTEND: &str = "dockerfile.v0";
+
+/// Frontend that runs a frontend image, named by the `source` attribute
+pub const GATEWAY_FRONTEND: &str = "gateway.v0";
+
+/// Image of the stable Dockerfile frontend channel
+pub const DOCKERFILE_STABLE_IMAGE: &str = "docker/dockerfile:1";
+
+/// Image of the labs Dockerfile frontend channel
+pub const DOCKERFILE_LABS_IMAGE: &str = "docker/dockerfile:1-labs";
+
+/// Prefix of build argument attributes
+pub const BUILD_ARG_PREFIX: &str = "build-arg:";
+
+/// Build argument telling the frontend to keep `.git` in git contexts
+pub const KEEP_GIT_DIR_ARG: &str = "BUILDKIT_CONTEXT_KEEP_GIT_DIR";
+
+/// Prefix of named context attributes
+pub const NAMED_CONTEXT_PREFIX: &str = "context:";
+
+/// Extra host IP that BuildKit replaces with the builder's gateway
+const HOST_GATEWAY: &str = "host-gateway";
+
+/// Resource names accepted by `--ulimit`
+const ULIMIT_NAMES: [&str; 15] = [
+    "core",
+    "cpu",
+    "data",
+    "fsize",
+    "locks",
+    "memlock",
+    "msgqueue",
+    "nice",
+    "nofile",
+    "nproc",
+    "rss",
+    "rtprio",
+    "rttime",
+    "sigpending",
+    "stack",
+];
+
+/// Local directory names used by the main context and Dockerfile
+const RESERVED_CONTEXT_NAMES: [&str; 2] = ["context", "dockerfile"];
+
+/// Frontend that runs the build
+///
+/// The builtin frontend is whatever version ships with the daemon, unless the
+/// Dockerfile picks one with a `# syntax=` directive. Pinning a release
+/// channel makes the Dockerfile syntax independent of the daemon version.
+/// [`Gateway`](Frontend::Gateway) runs any other frontend image, e.g. for
+/// Earthfiles or Mockerfiles.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum Frontend {
+    /// The daemon's builtin `dockerfile.v0` frontend
+    #[default]
+    Builtin,
+    /// A Dockerfile frontend image, run through `gateway.v0`
+    Image {
+        /// Frontend image reference (e.g., "docker/dockerfile:1")
+        image: String,
+        /// Whether the image is from the labs channel
+        labs: bool,
+    },
+    /// Any frontend image, run through `gateway.v0`
+    ///
+    /// The build definition is not assumed to be a Dockerfile, so no
+    /// Dockerfile syntax checks are made. It is still served as the
+    /// `dockerfile` local with its name in the `filename` attribute, which
+    /// is where frontends built on BuildKit's `dockerui` look for it.
+    Gateway(String),
+}
+
+impl Frontend {
+    /// The latest stable Dockerfile frontend (`docker/dockerfile:1`)
+    pub fn dockerfile_stable() -> Self {
+        Frontend::Image {
+            image: DOCKERFILE_STABLE_IMAGE.to_string(),
+            labs: false,
+        }
+    }
+
+    /// The latest labs Dockerfile frontend (`docker/dockerfile:1-labs`)
+    ///
+    /// Labs releases accept experimental syntax such as
+    /// `RUN --security=insecure` on top of the stable channel.
+    pub fn dockerfile_labs() -> Self {
+        Frontend::Image {
+            image: DOCKERFILE_LABS_IMAGE.to_string(),
+            labs: true,
+        }
+    }
+
+    /// A specific frontend image; tags ending in `-labs` are labs releases
+    pub fn image(image: impl Into<String>) -> Self {
+        let image = image.into();
+        let labs = is_labs_image(&image);
+        Frontend::Image { image, labs }
+    }
+
+    /// Any frontend image (e.g., "earthly/buildkitd-frontend:latest")
+    pub fn gateway(image: impl Into<String>) -> Self {
+        Frontend::Gateway(image.into())
+    }
+
+    /// Frontend name for the solve request
+    pub fn name(&self) -> &'static str {
+        match self {
+            Frontend::Builtin => DOCKERFILE_FRONTEND,
+            Frontend::Image { .. } | Frontend::Gateway(_) => GATEWAY_FRONTEND,
+        }
+    }
+
+    /// Frontend image, sent as the `source` attribute
+    pub fn source(&self) -> Option<&str> {
+        match self {
+            Frontend::Builtin => None,
+            Frontend::Image { image, .. } | Frontend::Gateway(image) => Some(image),
+        }
+    }
+
+    /// Whether the frontend builds Dockerfiles
+    pub fn is_dockerfile(&self) -> bool {
+        !matches!(self, Frontend::Gateway(_))
+    }
+
+    /// Whether labs syntax is enabled
+    pub fn is_labs(&self) -> bool {
+        matches!(self, Frontend::Image { labs: true, .. })
+    }
+
+    /// Check that a Dockerfile only uses syntax this frontend accepts
+    ///
+    /// Labs syntax is allowed when this is a labs frontend or when the
+    /// Dockerfile itself selects a labs image with `# syntax=`. Fails with
+    /// [`Error::LabsFeatureRequired`] for the first labs-only instruction.
+    /// Definitions for [`Gateway`](Frontend::Gateway) frontends are not
+    /// checked.
+    pub fn check_dockerfile(&self, dockerfile: &str) -> Result<()> {
+        if !self.is_dockerfile()
+            || self.is_labs()
+            || syntax_directive(dockerfile).is_some_and(is_labs_image)
+        {
+            return Ok(());
+        }
+        match LabsFeature::find(dockerfile).into_iter().next() {
+            Some((line, feature)) => Err(Error::LabsFeatureRequired {
+                feature: feature.to_string(),
+                line,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Parse a frontend choice: `builtin`, `stable`, `labs`, a Dockerfile
+/// frontend image reference or `gateway=<image>` for any other frontend
+impl std::str::FromStr for Frontend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "builtin" => Ok(Frontend::Builtin),
+            "stable" => Ok(Frontend::dockerfile_stable()),
+            "labs" => Ok(Frontend::dockerfile_labs()),
+            _ => {
+                let (image, gateway) = match s.strip_prefix("gateway=") {
+                    Some(image) => (image, true),
+                    None => (s, false),
+                };
+                if !is_valid_image(image) {
+                    return Err(Error::InvalidConfig(format!("invalid frontend {:?}", s)));
+                }
+                Ok(if gateway {
+                    Frontend::gateway(image)
+                } else {
+                    Frontend::image(image)
+                })
+            }
+        }
+    }
+}
+
+/// Dockerfile syntax that only the labs frontend channel accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LabsFeature {
+    /// `RUN --security=...`
+    RunSecurity,
+    /// `RUN --mount=type=secret,env=...`
+    SecretEnv,
+}
+
+impl LabsFeature {
+    /// Instructions using labs syntax, as (1-based line, feature)
+    pub fn find(dockerfile: &str) -> Vec<(usize, LabsFeature)> {
+        let mut found = Vec::new();
+        for (line, instruction) in logical_lines(dockerfile) {
+            let mut words = instruction.split_whitespace();
+            if !words
+                .next()
+                .is_some_and(|keyword| keyword.eq_ignore_ascii_case("RUN"))
+            {
+                continue;
+            }
+            for word in words.take_while(|word| word.starts_with("--")) {
+                if word.starts_with("--security=") {
+                    found.push((line, LabsFeature::RunSecurity));
+                } else if let Some(mount) = word.strip_prefix("--mount=") {
+                    let options: Vec<&str> = mount.split(',').collect();
+                    if options.contains(&"type=secret")
+                        && options.iter().any(|option| option.starts_with("env="))
+                    {
+                        found.push((line, LabsFeature::SecretEnv));
+                    }
+                }
+            }
+        }
+        found
+    }
+}
+
+impl fmt::Display for LabsFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LabsFeature::RunSecurity => f.write_str("RUN --security"),
+            LabsFeature::SecretEnv => f.write_str("RUN --mount=type=secret,env="),
+        }
+    }
+}
+
+/// Value of the Dockerfile's `# syntax=` parser directive, if any
+///
+/// Directives are only recognized at the very top of the file, before any
+/// blank line, other comment or instruction.
+pub fn syntax_directive(dockerfile: &str) -> Option<&str> {
+    for line in dockerfile.lines() {
+        let (key, value) = line.trim().strip_prefix('#')?.split_once('=')?;
+        if key.trim().eq_ignore_ascii_case("syntax") {
+            return Some(value.trim());
+        }
+    }
+    None
+}
+
+/// Whether `name` is a valid hostname: dot-separated labels of letters,
+/// digits and inner hyphens, at most 63 characters each
+fn is_valid_hostname(name: &str) -> bool {
+    name.len() <= 253
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Frontend reference (`context:<name>` value) for a named context
+fn named_context_value(name: &str, context: &NamedContext) -> Result<String> {
+    let invalid = |reason: &str| {
+        Err(Error::InvalidConfig(format!(
+            "named context {:?}: {}",
+            name, reason
+        )))
+    };
+    match context {
+        NamedContext::Local(_) => Ok(format!("local:{}", name)),
+        NamedContext::Image(reference) => {
+            if reference.is_empty()
+                || reference.contains("://")
+                || reference.chars().any(char::is_whitespace)
+            {
+                return invalid("invalid image reference");
+            }
+            Ok(format!("{}{}", IMAGE_SOURCE_SCHEME, reference))
+        }
+        NamedContext::Git(url) => {
+            if url.is_empty() || url.chars().any(char::is_whitespace) {
+                return invalid("invalid git URL");
+            }
+            Ok(url.clone())
+        }
+        NamedContext::OciLayout { path, tag, digest } => {
+            if tag.is_empty() {
+                return invalid("empty OCI layout tag");
+            }
+            let Some(digest) = digest else {
+                return invalid("OCI layout digest not resolved");
+            };
+            Ok(format!(
+                "{}{}:{}@{}",
+                OCI_LAYOUT_SCHEME,
+                context_store_id(path),
+                tag,
+                digest
+            ))
+        }
+    }
+}
+
+/// Whether `image` can be a frontend image reference
+fn is_valid_image(image: &str) -> bool {
+    !image.is_empty() && !image.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+/// Whether a frontend image tag names a labs release
+fn is_labs_image(image: &str) -> bool {
+    image.split('@').next().unwrap_or(image).ends_with("-labs")
+}
+
+/// Validated frontend attributes in canonical order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrontendAttrs {
+    attrs: BTreeMap<String, String>,
+}
+
+impl FrontendAttrs {
+    /// Create an empty attribute set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build the attributes for a configuration, except `context`
+    ///
+    /// The context attribute depends on the session and is added with
+    /// [`context`](Self::context) once it is known, unless it was set
+    /// explicitly with [`BuildConfig::frontend_attr`].
+    pub fn from_config(config: &BuildConfig) -> Result<Self> {
+        let mut attrs = Self::new();
+
+        match &config.source {
+            // An inline Dockerfile is served under the default name
+            DockerfileSource::Local { .. } | DockerfileSource::Context { .. }
+                if config.inline_dockerfile.is_some() => {}
+            DockerfileSource::GitHub { .. }
+            | DockerfileSource::Git { .. }
+            | DockerfileSource::Http { .. }
+            | DockerfileSource::Upload { .. }
+                if config.inline_dockerfile.is_some() =>
+            {
+                return Err(Error::InvalidConfig(
+                    "inline Dockerfiles need a local context".to_string(),
+                ));
+            }
+            DockerfileSource::Local {
+                dockerfile_path: Some(path),
+                ..
+            } => attrs.set("filename", path.to_string_lossy())?,
+            DockerfileSource::GitHub {
+                dockerfile_path: Some(path),
+                ..
+            }
+            | DockerfileSource::Git {
+                dockerfile_path: Some(path),
+                ..
+            }
+            | DockerfileSource::Http {
+                dockerfile_path: Some(path),
+                ..
+            }
+            | DockerfileSource::Context {
+                dockerfile_path: Some(path),
+                ..
+            }
+            | DockerfileSource::Upload {
+                dockerfile_path: Some(path),
+                ..
+            } => attrs.set("filename", path.as_str())?,
+            _ => {}
+        }
+
+        for (key, value) in &config.build_args {
+            attrs.build_arg(key, value.as_str())?;
+        }
+        if let Some(target) = &config.target {
+            attrs.set("target", target.as_str())?;
+        }
+        if config.keep_git_dir {
+            if let DockerfileSource::GitHub { proxy: Some(_), .. } = &config.source {
+                return Err(Error::InvalidConfig(
+                    "keep_git_dir is not supported for GitHub sources fetched through a proxy"
+                        .to_string(),
+                ));
+            }
+            attrs.build_arg(KEEP_GIT_DIR_ARG, "1")?;
+        }
+        for (name, context) in &config.named_contexts {
+            attrs.named_context(name, named_context_value(name, context)?)?;
+        }
+        attrs.platforms(&config.platforms)?;
+        if config.no_cache {
+            // An empty value disables the cache for every stage
+            attrs.set("no-cache", "")?;
+        } else if !config.no_cache_filter.is_empty() {
+            attrs.no_cache_filter(&config.no_cache_filter)?;
+        }
+        if let Some(mode) = config.image_resolve_mode.attr_value() {
+            attrs.set("image-resolve-mode", mode)?;
+        }
+        if let Some(mode) = config.network.attr_value() {
+            attrs.set("force-network-mode", mode)?;
+        }
+        attrs.add_hosts(&config.extra_hosts)?;
+        if let Some(bytes) = config.shm_size {
+            attrs.set("shm-size", bytes.to_string())?;
+        }
+        attrs.ulimits(&config.ulimits)?;
+        if let Some(parent) = &config.cgroup_parent {
+            if parent.is_empty() {
+                return Err(Error::InvalidConfig("empty cgroup parent".to_string()));
+            }
+            attrs.set("cgroup-parent", parent.as_str())?;
+        }
+        if let Some(hostname) = &config.hostname {
+            if !is_valid_hostname(hostname) {
+                return Err(Error::InvalidConfig(format!(
+                    "invalid hostname {:?}",
+                    hostname
+                )));
+            }
+            attrs.set("hostname", hostname.as_str())?;
+        }
+        if let Some(source) = config.frontend.source() {
+            if !is_valid_image(source) {
+                return Err(Error::InvalidConfig(format!(
+                    "invalid frontend image {:?}",
+                    source
+                )));
+            }
+            attrs.set("source", source)?;
+        }
+        if let Some(provenance) = &config.provenance {
+            attrs.set(PROVENANCE_ATTR, provenance.attr_value())?;
+        }
+        // Explicit attributes win over the typed options
+        for (key, value) in &config.frontend_attrs {
+            attrs.set(key.as_str(), value.as_str())?;
+        }
+
+        Ok(attrs)
+    }
+
+    /// Set an attribute, replacing any previous value
+    ///
+    /// Keys must be non-empty and may not contain whitespace, `=` or control
+    /// characters; values may not contain control characters.
+    pub fn set(&mut self, key: impl Into<String>, value: impl Into<String>) -> Result<()> {
+        let key = key.into();
+        let value = value.into();
+        if key.is_empty()
+            || key
+                .chars()
+                .any(|c| c.is_whitespace() || c.is_control() || c == '=')
+        {
+            return Err(Error::InvalidConfig(format!(
+                "invalid frontend attribute key {:?}",
+                key
+            )));
+        }
+        if value.chars().any(char::is_control) {
+            return Err(Error::InvalidConfig(format!(
+                "frontend attribute {} contains control characters",
+                key
+            )));
+        }
+        self.attrs.insert(key, value);
+        Ok(())
+    }
+
+    /// Set a build argument (`build-arg:<name>`)
+    pub fn build_arg(&mut self, name: &str, value: impl Into<String>) -> Result<()> {
+        if name.is_empty() {
+            return Err(Error::InvalidConfig(
+                "empty build argument name".to_string(),
+            ));
+        }
+        self.set(format!("{}{}", BUILD_ARG_PREFIX, name), value)
+    }
+
+    /// Set the extra `/etc/hosts` entries (`add-hosts`)
+    ///
+    /// Entries are sent as `host=ip` in the order given. IPs must be IPv4 or
+    /// IPv6 addresses, or `host-gateway`.
+    pub fn add_hosts(&mut self, hosts: &[(String, String)]) -> Result<()> {
+        if hosts.is_empty() {
+            return Ok(());
+        }
+        let mut entries = Vec::with_capacity(hosts.len());
+        for (host, ip) in hosts {
+            if host.is_empty()
+                || host
+                    .chars()
+                    .any(|c| c.is_whitespace() || c.is_control() || c == '=' || c == ',')
+            {
+                return Err(Error::InvalidConfig(format!(
+                    "invalid extra host name {:?}",
+                    host
+                )));
+            }
+            if ip != HOST_GATEWAY && ip.parse::<IpAddr>().is_err() {
+                return Err(Error::InvalidConfig(format!(
+                    "invalid IP address {:?} for extra host {}",
+                    ip, host
+                )));
+            }
+            entries.push(format!("{}={}", host, ip));
+        }
+        self.set("add-hosts", entries.join(","))
+    }
+
+    /// Set the resource limits of `RUN` steps (`ulimit`)
+    ///
+    /// Each limit is `name=soft[:hard]`, with the hard limit defaulting to
+    /// the soft one. Limits are sent sorted by name, the last of each name
+    /// winning.
+    pub fn ulimits(&mut self, ulimits: &[String]) -> Result<()> {
+        if ulimits.is_empty() {
+            return Ok(());
+        }
+        let mut limits = BTreeMap::new();
+        for ulimit in ulimits {
+            let (name, soft, hard) = parse_ulimit(ulimit)?;
+            limits.insert(name, format!("{}={}:{}", name, soft, hard));
+        }
+        self.set("ulimit", limits.into_values().collect::<Vec<_>>().join(","))
+    }
+
+    /// Set the target platforms, keeping the first occurrence of each
+    pub fn platforms(&mut self, platforms: &[Platform]) -> Result<()> {
+        if platforms.is_empty() {
+            return Ok(());
+        }
+        let mut unique: Vec<&Platform> = Vec::with_capacity(platforms.len());
+        for platform in platforms {
+            if !unique.contains(&platform) {
+                unique.push(platform);
+            }
+        }
+        let value = unique
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        self.set("platform", value)
+    }
+
+    /// Set a named context (`context:<name>`) the Dockerfile can refer to
+    ///
+    /// `value` is a frontend context reference such as `local:<dir>` or
+    /// `docker-image://alpine:3.20`. The names `context` and `dockerfile`
+    /// are reserved for the main context and Dockerfile.
+    pub fn named_context(&mut self, name: &str, value: impl Into<String>) -> Result<()> {
+        if name.is_empty() || RESERVED_CONTEXT_NAMES.contains(&name) {
+            return Err(Error::InvalidConfig(format!(
+                "invalid named context {:?}",
+                name
+            )));
+        }
+        self.set(format!("{}{}", NAMED_CONTEXT_PREFIX, name), value)
+    }
+
+    /// Set the build context reference
+    pub fn context(&mut self, context: impl Into<String>) -> Result<()> {
+        self.set("context", context)
+    }
+
+    /// Disable the cache for the given stages only
+    pub fn no_cache_filter(&mut self, stages: &[String]) -> Result<()> {
+        let mut names: Vec<&str> = Vec::with_capacity(stages.len());
+        for stage in stages {
+            if stage.is_empty() || stage.contains(',') {
+                return Err(Error::InvalidConfig(format!(
+                    "invalid no-cache stage name {:?}",
+                    stage
+                )));
+            }
+            if !names.contains(&stage.as_str()) {
+                names.push(stage);
+            }
+        }
+        self.set("no-cache", names.join(","))
+    }
+
+    /// Look up an attribute
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.attrs.get(key).map(String::as_str)
+    }
+
+    /// Iterate over attributes in canonical order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.attrs.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Number of attributes
+    pub fn len(&self) -> usize {
+        self.attrs.len()
+    }
+
+    /// Check whether no attributes are set
+    pub fn is_empty(&self) -> bool {
+        self.attrs.is_empty()
+    }
+
+    /// Convert into the map carried by the solve request
+    pub fn into_map(self) -> HashMap<String, String> {
+        self.attrs.into_iter().collect()
+    }
+
+    /// Render like [`Display`](fmt::Display), but with the values of build
+    /// arguments redacted, as they often carry tokens
+    pub fn redacted(&self) -> impl fmt::Display + '_ {
+        Redacted(self)
+    }
+}
+
+/// [`FrontendAttrs`] rendered for logs
+struct Redacted<'a>(&'a FrontendAttrs);
+
+impl fmt::Display for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, value) in self.0.iter() {
+            if key.starts_with(BUILD_ARG_PREFIX) {
+                writeln!(f, "{}=<redacted>", key)?;
+            } else {
+                writeln!(f, "{}={}", key, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Split a `name=soft[:hard]` limit, checking the name and the values
+fn parse_ulimit(ulimit: &str) -> Result<(&str, i64, i64)> {
+    let invalid =
+        |reason: &str| Error::InvalidConfig(format!("invalid ulimit {:?}: {}", ulimit, reason));
+    let (name, values) = ulimit
+        .split_once('=')
+        .ok_or_else(|| invalid("expected name=soft[:hard]"))?;
+    if !ULIMIT_NAMES.contains(&name) {
+        return Err(invalid("unknown resource"));
+    }
+    let parse = |value: &str| {
+        value
+            .parse::<i64>()
+            .ok()
+            .filter(|v| *v >= -1)
+            .ok_or_else(|| invalid("limits must be numbers, or -1 for unlimited"))
+    };
+    let (soft, hard) = match values.split_once(':') {
+        Some((soft, hard)) => (parse(soft)?, parse(hard)?),
+        None => {
+            let soft = parse(values)?;
+            (soft, soft)
+        }
+    };
+    // -1 (unlimited) is the largest value
+    if hard != -1 && (soft == -1 || soft > hard) {
+        return Err(invalid("soft limit exceeds hard limit"));
+    }
+    Ok((name, soft, hard))
+}
+
+/// One `key=value` line per attribute, in canonical order
+impl fmt::Display for FrontendAttrs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, value) in self.iter() {
+            writeln!(f, "{}={}", key, value)?;
+        }
+        Ok(())
+    }
+}
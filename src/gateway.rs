@@ -0,0 +1,263 @@
+//! Frontend requests through BuildKit's gateway API
+//!
+//! A solve through the `Control` service only reports exporter output, not
+//! the metadata a frontend attaches to its result. Subrequests such as
+//! `frontend.lint` answer with that metadata alone, so they are forwarded
+//! the way `buildctl` does it: a solve without frontend or definition makes
+//! the daemon wait for a gateway client, and the client drives the frontend
+//! through the `LLBBridge` service of the same connection, under the build
+//! ref of that solve.
+
+use crate::debug::DebugHandler;
+use crate::error::{Error, Result};
+use crate::proto::google::rpc::Status;
+use crate::proto::moby::buildkit::v1::control_client::ControlClient;
+use crate::proto::moby::buildkit::v1::frontend::llb_bridge_client::LlbBridgeClient;
+use crate::proto::moby::buildkit::v1::frontend::{self as gateway, ReturnRequest};
+use crate::proto::moby::buildkit::v1::SolveRequest;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::transport::Channel;
+
+/// Metadata header tying gateway calls to the build ref of their solve
+pub const CONTROL_REF_HEADER: &str = "buildkit-control-ref";
+
+/// Frontend option selecting a subrequest instead of a build
+pub const REQUEST_ID_ATTR: &str = "requestid";
+
+/// Result metadata key holding a subrequest's JSON answer
+pub const RESULT_JSON_KEY: &str = "result.json";
+
+/// Result metadata key holding a subrequest's answer formatted for humans
+pub const RESULT_TEXT_KEY: &str = "result.txt";
+
+/// How a build is driven through the gateway API
+pub(crate) enum Forward<'a> {
+    /// Answer a frontend subrequest instead of building
+    Subrequest(&'a str),
+    /// Build, handing a failed step to a debugger
+    Debug(&'a mut dyn DebugHandler),
+}
+
+/// Ranges in one of the sources of a subrequest answer
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct SourceLocation {
+    /// Index into the sources of the answer
+    #[serde(default, rename = "sourceIndex")]
+    pub source_index: i32,
+    /// Ranges covered, usually one
+    #[serde(default, deserialize_with = "nullable")]
+    pub ranges: Vec<SourceRange>,
+}
+
+impl SourceLocation {
+    /// First line of the location (1-based), if known
+    pub fn line(&self) -> Option<usize> {
+        self.ranges
+            .first()
+            .and_then(|range| usize::try_from(range.start.line).ok())
+            .filter(|line| *line > 0)
+    }
+}
+
+/// A range of a source file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub struct SourceRange {
+    /// First position of the range
+    #[serde(default)]
+    pub start: SourcePosition,
+    /// Last position of the range
+    #[serde(default)]
+    pub end: SourcePosition,
+}
+
+/// A position in a source file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub struct SourcePosition {
+    /// Line number, starting at 1
+    #[serde(default)]
+    pub line: i32,
+    /// Column, starting at 0
+    #[serde(default)]
+    pub character: i32,
+}
+
+/// Decode a list the frontend may encode as `null` when empty
+pub(crate) fn nullable<'de, D, T>(deserializer: D) -> std::result::Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Option::unwrap_or_default)
+}
+
+/// Run the frontend of `request` through the gateway API
+///
+/// `request` must carry the frontend, its attributes and inputs as for a
+/// regular solve; they are moved into the gateway request. Returns the
+/// metadata of the frontend's result.
+pub(crate) async fn solve_with_gateway(
+    control: &mut ControlClient<Channel>,
+    channel: Channel,
+    mut request: tonic::Request<SolveRequest>,
+) -> Result<HashMap<String, Vec<u8>>> {
+    let solve = request.get_mut();
+    let build_ref = solve.r#ref.clone();
+    let frontend_request = take_frontend_request(solve);
+    // Subrequests have no result to export or cache
+    solve.exporters.clear();
+    solve.cache = None;
+
+    let (solved, forwarded) = tokio::join!(
+        control.solve(request),
+        forward(channel, &build_ref, frontend_request)
+    );
+    // A failed frontend also fails the solve; its own error says more
+    let metadata = forwarded?;
+    solved?;
+    Ok(metadata)
+}
+
+/// Move the frontend of a solve into a request for its gateway
+///
+/// Cache imports are copied; the solve keeps exporting the result.
+pub(crate) fn take_frontend_request(solve: &mut SolveRequest) -> gateway::SolveRequest {
+    gateway::SolveRequest {
+        frontend: std::mem::take(&mut solve.frontend),
+        frontend_opt: std::mem::take(&mut solve.frontend_attrs),
+        frontend_inputs: std::mem::take(&mut solve.frontend_inputs),
+        cache_imports: solve
+            .cache
+            .as_ref()
+            .map(|cache| cache.imports.clone())
+            .unwrap_or_default(),
+        ..Default::default()
+    }
+}
+
+/// Solve `request` on the gateway of build `build_ref` and return the result
+async fn forward(
+    channel: Channel,
+    build_ref: &str,
+    request: gateway::SolveRequest,
+) -> Result<HashMap<String, Vec<u8>>> {
+    let mut bridge = Bridge::new(channel, build_ref)?;
+    let solved = bridge.solve(request).await;
+    let result = match &solved {
+        Ok(result) => gateway::Result {
+            metadata: result.metadata.clone(),
+            ..Default::default()
+        },
+        Err(_) => gateway::Result::default(),
+    };
+    bridge.finish(result, solved.as_ref().err()).await?;
+    solved
+        .map(|result| result.metadata)
+        .map_err(|status| Error::frontend(status.message()))
+}
+
+/// Gateway client of a build waiting in gateway mode
+///
+/// Every call is tied to the build by its ref; the build only completes
+/// once [`finish`](Self::finish) was called.
+#[derive(Clone)]
+pub(crate) struct Bridge {
+    client: LlbBridgeClient<Channel>,
+    control_ref: MetadataValue<Ascii>,
+}
+
+impl Bridge {
+    /// Connect to the gateway of build `build_ref`
+    pub(crate) fn new(channel: Channel, build_ref: &str) -> Result<Self> {
+        let control_ref = MetadataValue::try_from(build_ref)
+            .map_err(|_| Error::frontend(format!("invalid build ref {:?}", build_ref)))?;
+        Ok(Self {
+            client: LlbBridgeClient::new(channel),
+            control_ref,
+        })
+    }
+
+    /// Wrap a gateway message for this build
+    fn request<T>(&self, message: T) -> tonic::Request<T> {
+        let mut request = tonic::Request::new(message);
+        request
+            .metadata_mut()
+            .insert(CONTROL_REF_HEADER, self.control_ref.clone());
+        request
+    }
+
+    /// Run a frontend (or solve a definition) within the build
+    pub(crate) async fn solve(
+        &mut self,
+        request: gateway::SolveRequest,
+    ) -> std::result::Result<gateway::Result, tonic::Status> {
+        let request = self.request(request);
+        let response = self.client.solve(request).await?;
+        Ok(response.into_inner().result.unwrap_or_default())
+    }
+
+    /// Resolve an image reference to its digest and config blob
+    pub(crate) async fn resolve_image_config(
+        &mut self,
+        request: gateway::ResolveImageConfigRequest,
+    ) -> std::result::Result<gateway::ResolveImageConfigResponse, tonic::Status> {
+        let request = self.request(request);
+        Ok(self
+            .client
+            .resolve_image_config(request)
+            .await?
+            .into_inner())
+    }
+
+    /// Create a container from results of the build
+    pub(crate) async fn new_container(
+        &mut self,
+        request: gateway::NewContainerRequest,
+    ) -> std::result::Result<(), tonic::Status> {
+        let request = self.request(request);
+        self.client.new_container(request).await?;
+        Ok(())
+    }
+
+    /// Stop and remove a container
+    pub(crate) async fn release_container(
+        &mut self,
+        container_id: String,
+    ) -> std::result::Result<(), tonic::Status> {
+        let request = self.request(gateway::ReleaseContainerRequest { container_id });
+        self.client.release_container(request).await?;
+        Ok(())
+    }
+
+    /// Open a process stream in a container
+    ///
+    /// The first message of `input` must initialize the process.
+    pub(crate) async fn exec_process(
+        &mut self,
+        input: ReceiverStream<gateway::ExecMessage>,
+    ) -> std::result::Result<tonic::Streaming<gateway::ExecMessage>, tonic::Status> {
+        let request = self.request(input);
+        Ok(self.client.exec_process(request).await?.into_inner())
+    }
+
+    /// Complete the build with `result`, or fail it with `error`
+    pub(crate) async fn finish(
+        &mut self,
+        result: gateway::Result,
+        error: Option<&tonic::Status>,
+    ) -> Result<()> {
+        let returned = ReturnRequest {
+            result: Some(result),
+            error: error.map(|status| Status {
+                code: status.code() as i32,
+                message: status.message().to_string(),
+                details: Vec::new(),
+            }),
+        };
+        let request = self.request(returned);
+        self.client.r#return(request).await?;
+        Ok(())
+    }
+}
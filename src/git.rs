@@ -0,0 +1,362 @@
+//! Git credentials and client-side git checkouts
+//!
+//! BuildKit normally clones git contexts itself, from the daemon's network,
+//! reading credentials for private repositories from the session
+//! ([`GitAuth`]): HTTP(S) credentials as secrets, SSH keys through a
+//! forwarded agent. Repositories that are only reachable through a SOCKS proxy
+//! or an SSH bastion are instead fetched here with the local `git` binary
+//! and uploaded to BuildKit like a local context.
+
+use crate::error::{Error, Result};
+use base64::Engine;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+
+/// How long to wait for an SSH tunnel to start accepting connections
+const TUNNEL_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Route for reaching a git remote the daemon cannot reach directly
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitProxy {
+    /// SOCKS5 proxy ("host:port"); host names are resolved by the proxy
+    Socks(String),
+    /// SSH jump host ("[user@]host[:port]"), as for `ssh -J`
+    ///
+    /// SSH remotes connect through the bastion directly; HTTP(S) remotes go
+    /// through a dynamic port forward opened for the duration of the fetch.
+    SshJump(String),
+}
+
+impl GitProxy {
+    /// Use a SOCKS5 proxy
+    pub fn socks(addr: impl Into<String>) -> Self {
+        GitProxy::Socks(addr.into())
+    }
+
+    /// Use an SSH jump host
+    pub fn ssh_jump(host: impl Into<String>) -> Self {
+        GitProxy::SshJump(host.into())
+    }
+}
+
+/// Secret BuildKit's git source reads an `Authorization` header from
+///
+/// The source looks up `GIT_AUTH_HEADER.<host>` first, then the bare name.
+pub const GIT_AUTH_HEADER_SECRET: &str = "GIT_AUTH_HEADER";
+
+/// Secret BuildKit's git source reads an access token from
+///
+/// Looked up like [`GIT_AUTH_HEADER_SECRET`]; the token is sent as the
+/// password of `x-access-token`.
+pub const GIT_AUTH_TOKEN_SECRET: &str = "GIT_AUTH_TOKEN";
+
+/// Credentials for cloning a private git repository
+///
+/// BuildKit's git source reads them from the session when it clones, so
+/// they never appear in the context URL, vertex names or cache keys.
+/// `Token` and `Basic` apply to HTTP(S) remotes; `SshAgent` and `SshKey`
+/// to SSH remotes (`git@host:org/repo.git` or `ssh://...`), whose key never
+/// leaves the client: BuildKit signs through a forwarded agent.
+#[derive(Clone, PartialEq, Eq)]
+pub enum GitAuth {
+    /// Access token, sent as the password of `x-access-token` (GitHub
+    /// tokens; GitLab accepts any user name with a personal access token)
+    Token(String),
+    /// User name and password, e.g. a Bitbucket app password, or
+    /// `x-token-auth` with a Bitbucket access token
+    Basic {
+        /// User name
+        username: String,
+        /// Password or token
+        password: String,
+    },
+    /// Keys of the local ssh-agent (`SSH_AUTH_SOCK`)
+    SshAgent,
+    /// Private key file, loaded into an ssh-agent started for the build
+    SshKey(PathBuf),
+}
+
+impl GitAuth {
+    /// Authenticate with an access token
+    pub fn token(token: impl Into<String>) -> Self {
+        GitAuth::Token(token.into())
+    }
+
+    /// Authenticate with a user name and password
+    pub fn basic(username: impl Into<String>, password: impl Into<String>) -> Self {
+        GitAuth::Basic {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+
+    /// Authenticate through the local ssh-agent
+    pub fn ssh_agent() -> Self {
+        GitAuth::SshAgent
+    }
+
+    /// Authenticate with the private key at `path`
+    ///
+    /// The key must not be protected by a passphrase; add such keys to the
+    /// local agent and use [`ssh_agent`](Self::ssh_agent) instead.
+    pub fn ssh_key(path: impl Into<PathBuf>) -> Self {
+        GitAuth::SshKey(path.into())
+    }
+
+    /// Check whether these are credentials for SSH remotes
+    pub fn is_ssh(&self) -> bool {
+        matches!(self, GitAuth::SshAgent | GitAuth::SshKey(_))
+    }
+
+    /// Value of the `Authorization` header sent to HTTP(S) remotes
+    ///
+    /// `None` for SSH credentials.
+    pub fn authorization_header(&self) -> Option<String> {
+        let credentials = match self {
+            GitAuth::Token(token) => format!("x-access-token:{}", token),
+            GitAuth::Basic { username, password } => format!("{}:{}", username, password),
+            GitAuth::SshAgent | GitAuth::SshKey(_) => return None,
+        };
+        Some(format!(
+            "basic {}",
+            base64::engine::general_purpose::STANDARD.encode(credentials)
+        ))
+    }
+
+    /// Check that these credentials can be used for the remote `url`
+    pub fn validate(&self, url: &str) -> Result<()> {
+        if self.is_ssh() == is_ssh_url(url) {
+            return Ok(());
+        }
+        let expected = if self.is_ssh() {
+            "an SSH"
+        } else {
+            "an HTTP(S)"
+        };
+        Err(Error::InvalidConfig(format!(
+            "{:?} credentials need {} remote, got {}",
+            self, expected, url
+        )))
+    }
+
+    /// Name of the session secret holding the header for the remote `url`
+    pub fn secret_name(url: &str) -> String {
+        host_secret_name(GIT_AUTH_HEADER_SECRET, url)
+    }
+
+    /// Name of the session secret holding a bare token for the remote `url`
+    pub fn token_secret_name(url: &str) -> String {
+        host_secret_name(GIT_AUTH_TOKEN_SECRET, url)
+    }
+}
+
+/// `<name>.<host>` for HTTP(S) remotes, the bare `name` otherwise
+fn host_secret_name(name: &str, url: &str) -> String {
+    match git_host(url) {
+        Some(host) => format!("{}.{}", name, host),
+        None => name.to_string(),
+    }
+}
+
+impl std::fmt::Debug for GitAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GitAuth::Token(_) => f.write_str("Token(<redacted>)"),
+            GitAuth::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .field("password", &"<redacted>")
+                .finish(),
+            GitAuth::SshAgent => f.write_str("SshAgent"),
+            GitAuth::SshKey(path) => f.debug_tuple("SshKey").field(path).finish(),
+        }
+    }
+}
+
+/// Host (with port) of an HTTP(S) git remote, as BuildKit suffixes secrets
+pub fn git_host(url: &str) -> Option<&str> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let authority = rest.split(['/', '#', '?']).next().unwrap_or(rest);
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    (!host.is_empty()).then_some(host)
+}
+
+/// A repository checked out into a temporary directory
+///
+/// The directory is removed when the checkout is dropped.
+#[derive(Debug)]
+pub struct GitCheckout {
+    dir: PathBuf,
+}
+
+impl GitCheckout {
+    /// Fetch `git_ref` (default branch if `None`) of `url` through `proxy`
+    ///
+    /// Only the requested commit is fetched, and the `.git` directory is
+    /// removed afterwards, matching what BuildKit's git source provides.
+    /// The token, if any, is sent as an HTTP header rather than embedded in
+    /// the URL.
+    pub async fn fetch(
+        url: &str,
+        git_ref: Option<&str>,
+        token: Option<&str>,
+        proxy: &GitProxy,
+    ) -> Result<Self> {
+        let dir = std::env::temp_dir().join(format!("buildkit-git-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir)?;
+        let checkout = Self { dir };
+
+        let mut config = Vec::new();
+        if let Some(token) = token {
+            let credentials = base64::engine::general_purpose::STANDARD
+                .encode(format!("x-access-token:{}", token));
+            config.push((
+                "http.extraHeader".to_string(),
+                format!("Authorization: Basic {}", credentials),
+            ));
+        }
+
+        // The tunnel is kept alive (and killed on drop) until the fetch is done
+        let (proxy_config, _tunnel) = match proxy {
+            GitProxy::Socks(addr) if is_ssh_url(url) => (
+                (
+                    "core.sshCommand",
+                    format!("ssh -o ProxyCommand='nc -X 5 -x {} %h %p'", addr),
+                ),
+                None,
+            ),
+            GitProxy::Socks(addr) => (("http.proxy", format!("socks5h://{}", addr)), None),
+            GitProxy::SshJump(jump) if is_ssh_url(url) => {
+                (("core.sshCommand", format!("ssh -J {}", jump)), None)
+            }
+            GitProxy::SshJump(jump) => {
+                let (tunnel, port) = open_tunnel(jump).await?;
+                (
+                    ("http.proxy", format!("socks5h://127.0.0.1:{}", port)),
+                    Some(tunnel),
+                )
+            }
+        };
+        config.push((proxy_config.0.to_string(), proxy_config.1));
+
+        tracing::info!("Fetching {} through {:?}", url, proxy);
+        let dir = checkout.dir.as_path();
+        git(&config, dir, &["init", "--quiet"]).await?;
+        let git_ref = git_ref.unwrap_or("HEAD");
+        git(
+            &config,
+            dir,
+            &["fetch", "--quiet", "--depth", "1", url, git_ref],
+        )
+        .await?;
+        git(&config, dir, &["checkout", "--quiet", "FETCH_HEAD"]).await?;
+        std::fs::remove_dir_all(checkout.dir.join(".git"))?;
+
+        Ok(checkout)
+    }
+
+    /// Directory holding the checked out files
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Drop for GitCheckout {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+            tracing::warn!("Failed to remove checkout {}: {}", self.dir.display(), e);
+        }
+    }
+}
+
+/// Check whether a remote is reached over SSH rather than HTTP(S)
+fn is_ssh_url(url: &str) -> bool {
+    url.starts_with("ssh://") || (!url.contains("://") && url.contains('@'))
+}
+
+/// Run git with `config` passed through the environment, keeping credentials
+/// out of the process list
+async fn git(config: &[(String, String)], dir: &Path, args: &[&str]) -> Result<()> {
+    let mut command = Command::new("git");
+    command
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .env("GIT_CONFIG_COUNT", config.len().to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    for (i, (key, value)) in config.iter().enumerate() {
+        command
+            .env(format!("GIT_CONFIG_KEY_{}", i), key)
+            .env(format!("GIT_CONFIG_VALUE_{}", i), value);
+    }
+
+    let output = command
+        .output()
+        .await
+        .map_err(|e| Error::git(format!("failed to run git: {}", e)))?;
+    if !output.status.success() {
+        return Err(Error::git(format!(
+            "git {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Open a SOCKS tunnel through an SSH jump host on a free local port
+async fn open_tunnel(jump: &str) -> Result<(Child, u16)> {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")?
+        .local_addr()?
+        .port();
+    let mut tunnel = Command::new("ssh")
+        .args([
+            "-N",
+            "-o",
+            "BatchMode=yes",
+            "-o",
+            "ExitOnForwardFailure=yes",
+            "-D",
+            &format!("127.0.0.1:{}", port),
+            jump,
+        ])
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| Error::git(format!("failed to run ssh: {}", e)))?;
+
+    let started: std::result::Result<Result<()>, _> = tokio::time::timeout(TUNNEL_TIMEOUT, async {
+        loop {
+            if let Some(status) = tunnel.try_wait()? {
+                return Err(Error::git(format!(
+                    "ssh tunnel through {} exited with {}",
+                    jump, status
+                )));
+            }
+            if tokio::net::TcpStream::connect(("127.0.0.1", port))
+                .await
+                .is_ok()
+            {
+                return Ok(());
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await;
+    match started {
+        Ok(result) => result.map(|_| (tunnel, port)),
+        Err(_) => Err(Error::git(format!(
+            "ssh tunnel through {} not ready after {:?}",
+            jump, TUNNEL_TIMEOUT
+        ))),
+    }
+}
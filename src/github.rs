@@ -0,0 +1,165 @@
+//! GitHub API helpers
+//!
+//! [`resolve_ref`] turns a branch, tag or short SHA into the full commit SHA
+//! before a build starts, so the context URL is pinned to one commit and a
+//! mistyped ref fails immediately instead of deep inside the frontend.
+//!
+//! Requests honour GitHub's rate limit headers: once the limit is exhausted,
+//! later calls in the process wait for the reset (up to
+//! [`MAX_RATE_LIMIT_WAIT`]) instead of hammering the API.
+
+use crate::error::{Error, Result};
+use reqwest::header::{ACCEPT, AUTHORIZATION, RETRY_AFTER};
+use reqwest::{Response, StatusCode};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// GitHub REST API base URL
+pub const GITHUB_API_URL: &str = "https://api.github.com";
+
+/// Longest wait for a rate limit reset before giving up
+pub const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+/// When the exhausted rate limit resets, shared by all requests
+static RATE_LIMITED_UNTIL: Mutex<Option<SystemTime>> = Mutex::new(None);
+
+/// Split a GitHub repository URL into owner and repository name
+///
+/// # Example
+///
+/// ```
+/// use buildkit_client::github::parse_repo_url;
+///
+/// assert_eq!(
+///     parse_repo_url("https://github.com/moby/buildkit.git"),
+///     Some(("moby".to_string(), "buildkit".to_string()))
+/// );
+/// assert_eq!(parse_repo_url("https://gitlab.com/group/project"), None);
+/// ```
+pub fn parse_repo_url(url: &str) -> Option<(String, String)> {
+    let path = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    // Drop credentials embedded in the URL
+    let path = path.rsplit_once('@').map_or(path, |(_, rest)| rest);
+    let path = path
+        .strip_prefix("github.com/")
+        .or_else(|| path.strip_prefix("www.github.com/"))?;
+
+    let mut parts = path.trim_end_matches('/').splitn(3, '/');
+    let owner = parts.next().filter(|s| !s.is_empty())?;
+    let repo = parts.next()?.trim_end_matches(".git");
+    if repo.is_empty() || parts.next().is_some() {
+        return None;
+    }
+    Some((owner.to_string(), repo.to_string()))
+}
+
+/// Resolve `git_ref` (default branch if `None`) of a GitHub repository to a
+/// commit SHA
+pub async fn resolve_ref(
+    repo_url: &str,
+    git_ref: Option<&str>,
+    token: Option<&str>,
+) -> Result<String> {
+    let (owner, repo) = parse_repo_url(repo_url)
+        .ok_or_else(|| Error::git(format!("{} is not a GitHub repository URL", repo_url)))?;
+    let reference = git_ref.unwrap_or("HEAD");
+    let url = format!(
+        "{}/repos/{}/{}/commits/{}",
+        GITHUB_API_URL, owner, repo, reference
+    );
+
+    let client = reqwest::Client::builder()
+        .user_agent("buildkit-client")
+        .timeout(Duration::from_secs(30))
+        .build()
+        .map_err(|e| Error::other(format!("Failed to create HTTP client: {}", e)))?;
+
+    // One retry after waiting out a rate limit hit mid-flight
+    for attempt in 0..2 {
+        wait_for_rate_limit().await?;
+
+        let mut request = client
+            .get(&url)
+            .header(ACCEPT, "application/vnd.github.sha");
+        if let Some(token) = token {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| Error::git(format!("GitHub API request failed: {}", e)))?;
+        let limited = record_rate_limit(&response);
+
+        match response.status() {
+            status if status.is_success() => {
+                let sha = response
+                    .text()
+                    .await
+                    .map_err(|e| Error::git(format!("GitHub API request failed: {}", e)))?;
+                let sha = sha.trim().to_string();
+                tracing::info!("Resolved {}/{}@{} to {}", owner, repo, reference, sha);
+                return Ok(sha);
+            }
+            StatusCode::NOT_FOUND | StatusCode::UNPROCESSABLE_ENTITY => {
+                return Err(Error::GitRefNotFound {
+                    repo: repo_url.to_string(),
+                    reference: reference.to_string(),
+                });
+            }
+            StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS if limited && attempt == 0 => {
+                tracing::warn!(
+                    "GitHub API rate limit reached while resolving {}",
+                    reference
+                );
+            }
+            status => {
+                return Err(Error::git(format!(
+                    "GitHub API returned {} for {}/{}@{}",
+                    status, owner, repo, reference
+                )));
+            }
+        }
+    }
+    Err(Error::git("GitHub API rate limit exceeded"))
+}
+
+/// Sleep until the recorded rate limit resets, if it does so soon enough
+async fn wait_for_rate_limit() -> Result<()> {
+    let until = *RATE_LIMITED_UNTIL.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(wait) = until.and_then(|until| until.duration_since(SystemTime::now()).ok()) else {
+        return Ok(());
+    };
+    if wait > MAX_RATE_LIMIT_WAIT {
+        return Err(Error::git(format!(
+            "GitHub API rate limit exceeded, resets in {}s",
+            wait.as_secs()
+        )));
+    }
+    tracing::info!("Waiting {:?} for the GitHub API rate limit to reset", wait);
+    tokio::time::sleep(wait).await;
+    Ok(())
+}
+
+/// Remember when an exhausted rate limit resets; returns whether it is exhausted
+fn record_rate_limit(response: &Response) -> bool {
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+    };
+
+    let reset = if let Some(seconds) = header(RETRY_AFTER.as_str()) {
+        Some(SystemTime::now() + Duration::from_secs(seconds))
+    } else if header("x-ratelimit-remaining") == Some(0) {
+        header("x-ratelimit-reset").map(|epoch| UNIX_EPOCH + Duration::from_secs(epoch))
+    } else {
+        None
+    };
+
+    *RATE_LIMITED_UNTIL.lock().unwrap_or_else(|e| e.into_inner()) = reset;
+    reset.is_some()
+}
@@ -0,0 +1,337 @@
+//! Handles for builds running in the background
+//!
+//! [`BuildKitClient::start_build`] runs a build on a spawned task and returns
+//! a [`BuildHandle`]. Besides waiting for the result, the handle can follow
+//! the log output of a single build step while the build is running.
+
+use crate::builder::BuildConfig;
+use crate::client::BuildKitClient;
+use crate::error::{Error, Result};
+use crate::progress::{LogChunk, ProgressHandler, StatusSummary};
+use crate::session::SessionHealth;
+use crate::solve::{read_dockerfile, BuildResult};
+use crate::task::spawn_named;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+
+/// Number of status updates kept for followers that fall behind
+const STATUS_CHANNEL_CAPACITY: usize = 1024;
+
+/// Number of log chunks buffered per follower
+const STEP_LOG_CAPACITY: usize = 1024;
+
+/// Selects the build step(s) to follow
+#[derive(Debug, Clone)]
+pub enum StepMatcher {
+    /// Steps whose name matches a regular expression (e.g., `"RUN cargo test"`)
+    Name(Regex),
+    /// Steps whose name ends with the given Dockerfile instruction
+    Instruction(String),
+    /// The step created by the instruction on a Dockerfile line (1-based)
+    ///
+    /// Resolved against the build's Dockerfile by [`BuildHandle::follow_step`].
+    Line(usize),
+}
+
+impl StepMatcher {
+    /// Match step names against a regular expression
+    pub fn name(pattern: &str) -> Result<Self> {
+        Regex::new(pattern)
+            .map(StepMatcher::Name)
+            .map_err(|e| Error::InvalidConfig(format!("invalid step pattern {:?}: {}", pattern, e)))
+    }
+
+    /// Match the instruction on a Dockerfile line
+    pub fn line(line: usize) -> Self {
+        StepMatcher::Line(line)
+    }
+
+    /// Resolve a [`StepMatcher::Line`] into the instruction covering that line
+    ///
+    /// Other matchers are returned unchanged.
+    pub fn resolve(self, dockerfile: &str) -> Result<Self> {
+        let StepMatcher::Line(line) = self else {
+            return Ok(self);
+        };
+
+        crate::remote_sources::logical_lines(dockerfile)
+            .into_iter()
+            .take_while(|(start, _)| *start <= line)
+            .last()
+            .filter(|(_, instruction)| !instruction.is_empty())
+            .map(|(_, instruction)| StepMatcher::Instruction(normalize(&instruction)))
+            .ok_or_else(|| {
+                Error::InvalidConfig(format!("no Dockerfile instruction at line {}", line))
+            })
+    }
+
+    /// Check whether a step name is selected
+    ///
+    /// An unresolved [`StepMatcher::Line`] never matches.
+    pub fn matches(&self, vertex_name: &str) -> bool {
+        match self {
+            StepMatcher::Name(regex) => regex.is_match(vertex_name),
+            StepMatcher::Instruction(instruction) => {
+                normalize(vertex_name).ends_with(&normalize(instruction))
+            }
+            StepMatcher::Line(_) => false,
+        }
+    }
+}
+
+/// Collapse runs of whitespace so multi-line instructions compare equal
+fn normalize(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Picks the log chunks of matching steps out of status updates
+#[derive(Debug)]
+pub struct StepFilter {
+    matcher: StepMatcher,
+    digests: HashSet<String>,
+}
+
+impl StepFilter {
+    /// Create a filter for the given matcher
+    pub fn new(matcher: StepMatcher) -> Self {
+        Self {
+            matcher,
+            digests: HashSet::new(),
+        }
+    }
+
+    /// Record a step's name, selecting it if it matches
+    pub fn observe_vertex(&mut self, digest: &str, name: &str) {
+        if self.matcher.matches(name) {
+            self.digests.insert(digest.to_string());
+        }
+    }
+
+    /// Return the log chunks of matching steps in `status`
+    pub fn filter(&mut self, status: &StatusSummary) -> Vec<LogChunk> {
+        for vertex in &status.vertexes {
+            self.observe_vertex(&vertex.digest, &vertex.name);
+        }
+        status
+            .logs
+            .iter()
+            .filter(|log| self.digests.contains(&log.vertex))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Log output of the followed step(s)
+#[derive(Debug)]
+pub struct StepLogs {
+    rx: mpsc::Receiver<LogChunk>,
+}
+
+impl StepLogs {
+    /// Wait for the next log chunk; `None` once the build has finished
+    pub async fn next(&mut self) -> Option<LogChunk> {
+        self.rx.recv().await
+    }
+
+    /// Convert into a [`Stream`](tokio_stream::Stream) of log chunks
+    pub fn into_stream(self) -> tokio_stream::wrappers::ReceiverStream<LogChunk> {
+        tokio_stream::wrappers::ReceiverStream::new(self.rx)
+    }
+}
+
+/// A build running in the background
+pub struct BuildHandle {
+    build_ref: String,
+    client: BuildKitClient,
+    dockerfile: Option<String>,
+    /// Never read; only used to subscribe followers (holding a sender would
+    /// keep the channel open after the build finished)
+    events: broadcast::Receiver<StatusSummary>,
+    vertexes: Arc<Mutex<HashMap<String, String>>>,
+    task: JoinHandle<Result<BuildResult>>,
+}
+
+impl BuildHandle {
+    /// Build reference of the running build
+    pub fn build_ref(&self) -> &str {
+        &self.build_ref
+    }
+
+    /// Health of the build's session
+    ///
+    /// `None` before the session is started and once the build finished.
+    pub fn session_health(&self) -> Option<SessionHealth> {
+        self.client.session_health(&self.build_ref)
+    }
+
+    /// Follow the log output of the step(s) selected by `matcher`
+    ///
+    /// Steps that already started are matched too, but only log output
+    /// produced after this call is delivered. [`StepMatcher::Line`] requires a
+    /// local, inline or in-memory Dockerfile.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use buildkit_client::{BuildConfig, BuildKitClient, StepMatcher};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let client = BuildKitClient::connect("http://localhost:1234").await?;
+    ///     let handle = client.start_build(BuildConfig::local("."), None).await?;
+    ///
+    ///     let mut logs = handle.follow_step(StepMatcher::name(r"RUN cargo test")?)?;
+    ///     while let Some(chunk) = logs.next().await {
+    ///         print!("{}", String::from_utf8_lossy(&chunk.data));
+    ///     }
+    ///
+    ///     handle.wait().await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn follow_step(&self, matcher: StepMatcher) -> Result<StepLogs> {
+        let matcher = if let StepMatcher::Line(_) = matcher {
+            let dockerfile = self.dockerfile.as_deref().ok_or_else(|| {
+                Error::InvalidConfig(
+                    "following a step by line requires a Dockerfile on the client".to_string(),
+                )
+            })?;
+            matcher.resolve(dockerfile)?
+        } else {
+            matcher
+        };
+
+        let mut filter = StepFilter::new(matcher);
+        let mut events = self.events.resubscribe();
+        {
+            let vertexes = self.vertexes.lock().unwrap_or_else(|e| e.into_inner());
+            for (digest, name) in vertexes.iter() {
+                filter.observe_vertex(digest, name);
+            }
+        }
+
+        let (tx, rx) = mpsc::channel(STEP_LOG_CAPACITY);
+        let span = tracing::info_span!("follow_step", build_ref = %self.build_ref);
+        spawn_named("buildkit.follow_step", span, async move {
+            loop {
+                match events.recv().await {
+                    Ok(status) => {
+                        for chunk in filter.filter(&status) {
+                            if tx.send(chunk).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!(
+                            "Step follower fell behind, skipped {} status updates",
+                            skipped
+                        );
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        });
+
+        Ok(StepLogs { rx })
+    }
+
+    /// Wait for the build to finish
+    pub async fn wait(self) -> Result<BuildResult> {
+        self.task
+            .await
+            .map_err(|e| Error::build(format!("build task failed: {}", e)))?
+    }
+}
+
+/// Forwards status updates to followers and an optional wrapped handler
+struct FanoutHandler {
+    inner: Option<Box<dyn ProgressHandler>>,
+    events: broadcast::Sender<StatusSummary>,
+    vertexes: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl ProgressHandler for FanoutHandler {
+    fn on_start(&mut self) -> Result<()> {
+        match &mut self.inner {
+            Some(inner) => inner.on_start(),
+            None => Ok(()),
+        }
+    }
+
+    fn on_status(&mut self, status: StatusSummary) -> Result<()> {
+        {
+            let mut vertexes = self.vertexes.lock().unwrap_or_else(|e| e.into_inner());
+            for vertex in &status.vertexes {
+                vertexes.insert(vertex.digest.clone(), vertex.name.clone());
+            }
+        }
+        // Sending only fails when nobody is following, which is fine
+        let _ = self.events.send(status.clone());
+
+        match &mut self.inner {
+            Some(inner) => inner.on_status(status),
+            None => Ok(()),
+        }
+    }
+
+    fn on_complete(&mut self) -> Result<()> {
+        match &mut self.inner {
+            Some(inner) => inner.on_complete(),
+            None => Ok(()),
+        }
+    }
+
+    fn on_error(&mut self, error: &str) -> Result<()> {
+        match &mut self.inner {
+            Some(inner) => inner.on_error(error),
+            None => Ok(()),
+        }
+    }
+}
+
+impl BuildKitClient {
+    /// Start a build in the background
+    ///
+    /// The returned handle resolves to the same result as [`build`](Self::build).
+    /// `progress_handler`, if given, still receives every status update.
+    pub async fn start_build(
+        &self,
+        mut config: BuildConfig,
+        progress_handler: Option<Box<dyn ProgressHandler>>,
+    ) -> Result<BuildHandle> {
+        // Fix the ref up front so it is known before the build starts
+        let build_ref = config.resolve_ref()?;
+        config.ref_id = Some(build_ref.clone());
+
+        // Keep the Dockerfile around to resolve line matchers; the build
+        // reports a missing one itself
+        let dockerfile = read_dockerfile(&config).await.and_then(Result::ok);
+
+        let (sender, events) = broadcast::channel(STATUS_CHANNEL_CAPACITY);
+        let vertexes = Arc::new(Mutex::new(HashMap::new()));
+        let handler = FanoutHandler {
+            inner: progress_handler,
+            events: sender,
+            vertexes: vertexes.clone(),
+        };
+
+        let mut client = self.clone();
+        let span = tracing::info_span!("build", build_ref = %build_ref);
+        let task = spawn_named("buildkit.build", span, async move {
+            client.build(config, Some(Box::new(handler))).await
+        });
+
+        Ok(BuildHandle {
+            build_ref,
+            client: self.clone(),
+            dockerfile,
+            events,
+            vertexes,
+            task,
+        })
+    }
+}
@@ -0,0 +1,146 @@
+//! Remote HTTP(S) tarball contexts
+//!
+//! BuildKit downloads and unpacks `https://` contexts on the daemon, so
+//! release tarballs never pass through the client. The Dockerfile frontend
+//! only takes a bare URL, though. When the download has to be pinned to a
+//! checksum or authenticated, the client describes it as a small LLB graph
+//! (an HTTP source unpacked into an empty filesystem) and hands that to the
+//! frontend as its `context` input instead.
+
+use crate::copy::op_digest;
+use crate::error::{Error, Result};
+use crate::proto::pb;
+use prost::Message;
+use std::collections::HashMap;
+
+/// Session secret holding the `Authorization` header of an HTTP context
+pub const HTTP_AUTH_HEADER_SECRET: &str = "BUILDKIT_HTTP_CONTEXT_AUTH_HEADER";
+
+/// Name of the downloaded file on the builder
+const DOWNLOAD_NAME: &str = "context";
+
+/// Check that `url` can be used as an HTTP context
+pub fn validate_url(url: &str) -> Result<()> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"));
+    match rest {
+        Some(rest) if !rest.is_empty() && !rest.starts_with('/') => Ok(()),
+        _ => Err(Error::InvalidConfig(format!(
+            "HTTP context {:?} must be an http:// or https:// URL",
+            url
+        ))),
+    }
+}
+
+/// Check that `checksum` is a `sha256:<hex>` digest
+pub fn validate_checksum(checksum: &str) -> Result<()> {
+    let valid = checksum.strip_prefix("sha256:").is_some_and(|hex| {
+        hex.len() == 64
+            && hex
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+    });
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidConfig(format!(
+            "HTTP context checksum {:?} must be sha256:<64 lowercase hex digits>",
+            checksum
+        )))
+    }
+}
+
+/// LLB definition downloading `url` and unpacking it as the build context
+///
+/// BuildKit fails the download if its content does not match `checksum`.
+/// With `auth_header_secret`, the `Authorization` header is read from that
+/// session secret on the builder, so it never appears in the graph, the
+/// build's vertex names or its cache keys.
+pub fn http_context_definition(
+    url: &str,
+    checksum: Option<&str>,
+    auth_header_secret: Option<&str>,
+) -> Result<pb::Definition> {
+    validate_url(url)?;
+    let mut attrs = HashMap::from([("http.filename".to_string(), DOWNLOAD_NAME.to_string())]);
+    let mut caps = HashMap::from([("source.http".to_string(), true)]);
+    if let Some(checksum) = checksum {
+        validate_checksum(checksum)?;
+        attrs.insert("http.checksum".to_string(), checksum.to_string());
+        caps.insert("source.http.checksum".to_string(), true);
+    }
+    if let Some(secret) = auth_header_secret {
+        attrs.insert("http.authheadersecret".to_string(), secret.to_string());
+    }
+
+    let source = pb::Op {
+        op: Some(pb::op::Op::Source(pb::SourceOp {
+            identifier: url.to_string(),
+            attrs,
+        })),
+        ..Default::default()
+    }
+    .encode_to_vec();
+    let source_digest = op_digest(&source);
+
+    // Copy the download into scratch, unpacking it like `ADD` does
+    let unpack = pb::Op {
+        inputs: vec![pb::Input {
+            digest: source_digest.clone(),
+            index: 0,
+        }],
+        op: Some(pb::op::Op::File(pb::FileOp {
+            actions: vec![pb::FileAction {
+                input: -1,
+                secondary_input: 0,
+                output: 0,
+                action: Some(pb::file_action::Action::Copy(pb::FileActionCopy {
+                    src: format!("/{}", DOWNLOAD_NAME),
+                    dest: "/".to_string(),
+                    mode: -1,
+                    follow_symlink: true,
+                    dir_copy_contents: true,
+                    attempt_unpack_docker_compatibility: true,
+                    create_dest_path: true,
+                    timestamp: -1,
+                    ..Default::default()
+                })),
+            }],
+        })),
+        ..Default::default()
+    }
+    .encode_to_vec();
+    let unpack_digest = op_digest(&unpack);
+
+    let terminal = pb::Op {
+        inputs: vec![pb::Input {
+            digest: unpack_digest.clone(),
+            index: 0,
+        }],
+        ..Default::default()
+    }
+    .encode_to_vec();
+
+    let mut metadata = HashMap::with_capacity(2);
+    metadata.insert(
+        source_digest,
+        pb::OpMetadata {
+            caps,
+            ..Default::default()
+        },
+    );
+    metadata.insert(
+        unpack_digest,
+        pb::OpMetadata {
+            caps: HashMap::from([("file.base".to_string(), true)]),
+            ..Default::default()
+        },
+    );
+
+    Ok(pb::Definition {
+        def: vec![source, unpack, terminal],
+        metadata,
+        source: None,
+    })
+}
@@ -0,0 +1,132 @@
+//! Resolving image references on the builder
+//!
+//! BuildKit resolves the base images of a build itself, with the registry
+//! credentials served by the session. [`BuildKitClient::resolve_image_config`]
+//! asks it to do the same for a single reference through the gateway API,
+//! which is handy for checking base images against a policy (allowed
+//! registries, required labels, no root user, ...) before building.
+
+use crate::builder::{Platform, RegistryAuth};
+use crate::client::BuildKitClient;
+use crate::copy::check_image_ref;
+use crate::error::{Error, Result};
+use crate::gateway::Bridge;
+use crate::oci::ImageConfig;
+use crate::proto::moby::buildkit::v1::frontend::ResolveImageConfigRequest;
+use crate::proto::moby::buildkit::v1::SolveRequest;
+use crate::proto::pb;
+use crate::session::{AuthServer, RegistryAuthConfig, Session};
+
+/// An image reference resolved by BuildKit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedImage {
+    /// Normalized reference (e.g., `docker.io/library/alpine:3.20`)
+    pub reference: String,
+    /// Digest of the manifest (or index) the reference points at
+    pub digest: String,
+    /// Decoded image config
+    pub config: ImageConfig,
+    /// Image config as stored in the registry
+    pub raw_config: Vec<u8>,
+}
+
+impl ResolvedImage {
+    /// Decode the answer to a resolve request
+    pub fn new(reference: String, digest: String, raw_config: Vec<u8>) -> Result<Self> {
+        Ok(Self {
+            config: ImageConfig::from_json(&raw_config)?,
+            reference,
+            digest,
+            raw_config,
+        })
+    }
+
+    /// Reference pinned to the resolved digest
+    pub fn pinned_reference(&self) -> String {
+        let name = self.reference.split('@').next().unwrap_or_default();
+        format!("{}@{}", name, self.digest)
+    }
+}
+
+impl BuildKitClient {
+    /// Resolve `image` to its digest and config on the builder
+    ///
+    /// `platform` selects the manifest of multi-platform images (the
+    /// worker's platform by default); `auths` holds credentials for private
+    /// registries, as for builds.
+    pub async fn resolve_image_config(
+        &mut self,
+        image: &str,
+        platform: Option<&Platform>,
+        auths: Vec<RegistryAuth>,
+    ) -> Result<ResolvedImage> {
+        check_image_ref(image)?;
+
+        let build_ref = uuid::Uuid::new_v4().to_string();
+        let _in_flight = self.reserve_ref(&build_ref)?;
+        tracing::info!("Resolving image config of {} ({})", image, build_ref);
+
+        // Public images still need anonymous tokens from the auth service
+        let mut session = Session::new();
+        let mut auth = AuthServer::new();
+        for registry_auth in &auths {
+            auth.add_registry(RegistryAuthConfig::from(registry_auth));
+        }
+        session.add_auth(auth).await;
+        session.start(self.control().clone()).await?;
+        _in_flight.attach(&session);
+
+        // Without frontend or definition the daemon waits for a gateway client
+        let request = SolveRequest {
+            r#ref: build_ref.clone(),
+            session: session.get_id(),
+            ..Default::default()
+        };
+        let mut grpc_request = tonic::Request::new(request);
+        *grpc_request.metadata_mut() = session.grpc_metadata();
+
+        let resolve_request = ResolveImageConfigRequest {
+            r#ref: image.to_string(),
+            platform: platform.map(|platform| pb::Platform {
+                architecture: platform.arch.clone(),
+                os: platform.os.clone(),
+                variant: platform.variant.clone().unwrap_or_default(),
+                ..Default::default()
+            }),
+            log_name: format!("resolve image config for {}", image),
+            session_id: session.get_id(),
+            ..Default::default()
+        };
+        let channel = self.channel();
+        let resolve = async {
+            let mut bridge = Bridge::new(channel, &build_ref)?;
+            let resolved = bridge.resolve_image_config(resolve_request).await;
+            bridge
+                .finish(Default::default(), resolved.as_ref().err())
+                .await?;
+            resolved.map_err(Error::from)
+        };
+
+        let client = self.clone();
+        let resolved = tokio::select! {
+            (solved, resolved) = async { tokio::join!(self.control().solve(grpc_request), resolve) } => {
+                // A failed resolve also fails the solve; its own error says more
+                let resolved = resolved?;
+                solved?;
+                resolved
+            }
+            _ = client.shutdown_requested() => {
+                tracing::warn!("Cancelling image resolution {} for client shutdown", build_ref);
+                let _ = session.finish().await;
+                return Err(Error::BuildCancelled(build_ref.clone()));
+            }
+            error = session.failed() => {
+                let _ = session.finish().await;
+                return Err(error);
+            }
+        };
+        session.finish().await?;
+
+        ResolvedImage::new(resolved.r#ref, resolved.digest, resolved.config)
+    }
+}
@@ -0,0 +1,222 @@
+//! Cache invalidation analysis
+//!
+//! BuildKit only requests the data of context files that are new or changed
+//! since its last sync under the session's shared key. Matching those files
+//! against the sources of the `COPY` and `ADD` steps that were rebuilt shows
+//! which files invalidated the cache, and which steps were only rebuilt
+//! because a step before them was.
+
+use crate::progress::VertexSummary;
+use crate::proto::moby::buildkit::v1::Vertex;
+use std::collections::{HashMap, HashSet};
+
+/// A Dockerfile step that was not served from cache
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidatedStep {
+    /// Step name (e.g., "[build 3/5] COPY src/ /app/src")
+    pub name: String,
+    /// Vertex digest of the step
+    pub digest: String,
+    /// Changed context files the step copies
+    pub files: Vec<String>,
+    /// Whether a step this one depends on was rebuilt too
+    pub inputs_rebuilt: bool,
+}
+
+impl InvalidatedStep {
+    /// Whether changed context files invalidated this step directly
+    pub fn invalidated_by_files(&self) -> bool {
+        !self.files.is_empty()
+    }
+}
+
+/// Which context changes caused steps to be rebuilt
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheInvalidationReport {
+    /// Rebuilt Dockerfile steps, in the order BuildKit reported them
+    pub steps: Vec<InvalidatedStep>,
+    /// Context files BuildKit requested because they were new or changed
+    pub changed_files: Vec<String>,
+}
+
+impl CacheInvalidationReport {
+    /// Correlate the build's steps with the context files that changed
+    pub fn analyze(vertexes: &[VertexSummary], changed_files: &[String]) -> Self {
+        // Loading the context is never cached and does not count
+        let rebuilt: Vec<&VertexSummary> = vertexes
+            .iter()
+            .filter(|vertex| !vertex.cached && is_dockerfile_step(&vertex.name))
+            .collect();
+        let rebuilt_digests: HashSet<&str> = rebuilt
+            .iter()
+            .map(|vertex| vertex.digest.as_str())
+            .collect();
+
+        let steps = rebuilt
+            .iter()
+            .map(|vertex| {
+                let files = match copy_sources(&vertex.name) {
+                    Some(sources) => changed_files
+                        .iter()
+                        .filter(|file| sources.iter().any(|source| source_matches(source, file)))
+                        .cloned()
+                        .collect(),
+                    None => Vec::new(),
+                };
+                InvalidatedStep {
+                    name: vertex.name.clone(),
+                    digest: vertex.digest.clone(),
+                    files,
+                    inputs_rebuilt: vertex
+                        .inputs
+                        .iter()
+                        .any(|input| rebuilt_digests.contains(input.as_str())),
+                }
+            })
+            .collect();
+
+        Self {
+            steps,
+            changed_files: changed_files.to_vec(),
+        }
+    }
+
+    /// Files that invalidated at least one step
+    pub fn invalidating_files(&self) -> Vec<&str> {
+        let mut files: Vec<&str> = self
+            .steps
+            .iter()
+            .flat_map(|step| step.files.iter().map(String::as_str))
+            .collect();
+        files.sort_unstable();
+        files.dedup();
+        files
+    }
+}
+
+/// Latest state of every vertex seen on the status stream
+#[derive(Debug, Default)]
+pub(crate) struct VertexRecorder {
+    index: HashMap<String, usize>,
+    vertexes: Vec<VertexSummary>,
+}
+
+impl VertexRecorder {
+    pub(crate) fn record(&mut self, vertexes: &[Vertex]) {
+        for vertex in vertexes {
+            let summary = VertexSummary::from(vertex.clone());
+            match self.index.get(&vertex.digest) {
+                Some(&i) => self.vertexes[i] = summary,
+                None => {
+                    self.index
+                        .insert(vertex.digest.clone(), self.vertexes.len());
+                    self.vertexes.push(summary);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn into_vertexes(self) -> Vec<VertexSummary> {
+        self.vertexes
+    }
+}
+
+/// Steps of the Dockerfile are named "[stage n/m] INSTRUCTION ..."
+pub(crate) fn is_dockerfile_step(name: &str) -> bool {
+    name.starts_with('[') && !name.starts_with("[internal]")
+}
+
+/// Context sources of a `COPY` or `ADD` step, or `None` for other steps
+///
+/// Steps copying from another stage or image (`--from`) read nothing from
+/// the context; remote `ADD` sources are left out.
+fn copy_sources(name: &str) -> Option<Vec<String>> {
+    let (_, instruction) = name.split_once("] ")?;
+    let mut words = instruction.split_whitespace();
+    let keyword = words.next()?;
+    if !keyword.eq_ignore_ascii_case("COPY") && !keyword.eq_ignore_ascii_case("ADD") {
+        return None;
+    }
+
+    let mut args = Vec::new();
+    for word in words {
+        if word.starts_with("--from=") {
+            return Some(Vec::new());
+        }
+        if !word.starts_with("--") {
+            args.push(word);
+        }
+    }
+    // The last argument is the destination
+    args.pop();
+    Some(
+        args.into_iter()
+            .filter(|source| !source.contains("://"))
+            .map(|source| {
+                let source = source.trim_start_matches("./").trim_start_matches('/');
+                source.trim_end_matches('/').to_string()
+            })
+            .collect(),
+    )
+}
+
+/// Whether `file` (relative to the context) is copied by `source`
+fn source_matches(source: &str, file: &str) -> bool {
+    if source.is_empty() || source == "." {
+        return true;
+    }
+    if source.contains(['*', '?', '[']) {
+        // A matching directory copies everything below it
+        let depth = source.split('/').count();
+        let prefix: Vec<&str> = file.split('/').take(depth).collect();
+        return prefix.len() == depth && glob_match(source, &prefix.join("/"));
+    }
+    file == source
+        || file
+            .strip_prefix(source)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Match `*`, `?` and `[...]` wildcards, none of which cross `/`
+fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    glob_match_from(&pattern, &path)
+}
+
+fn glob_match_from(pattern: &[char], path: &[char]) -> bool {
+    match pattern.first() {
+        None => path.is_empty(),
+        Some('*') => (0..=path.len())
+            .take_while(|&i| i == 0 || path[i - 1] != '/')
+            .any(|i| glob_match_from(&pattern[1..], &path[i..])),
+        Some('?') => {
+            !path.is_empty() && path[0] != '/' && glob_match_from(&pattern[1..], &path[1..])
+        }
+        Some('[') => {
+            let Some(end) = pattern.iter().position(|&c| c == ']') else {
+                return path.first() == Some(&'[') && glob_match_from(&pattern[1..], &path[1..]);
+            };
+            let Some(&c) = path.first() else {
+                return false;
+            };
+            let (negated, class) = match &pattern[1..end] {
+                ['!' | '^', rest @ ..] => (true, rest),
+                class => (false, class),
+            };
+            let mut matched = false;
+            let mut i = 0;
+            while i < class.len() {
+                if i + 2 < class.len() && class[i + 1] == '-' {
+                    matched |= class[i] <= c && c <= class[i + 2];
+                    i += 3;
+                } else {
+                    matched |= class[i] == c;
+                    i += 1;
+                }
+            }
+            c != '/' && matched != negated && glob_match_from(&pattern[end + 1..], &path[1..])
+        }
+        Some(&p) => path.first() == Some(&p) && glob_match_from(&pattern[1..], &path[1..]),
+    }
+}
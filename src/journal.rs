@@ -0,0 +1,541 @@
+//! Persistent client-side build journal
+//!
+//! BuildKit keeps its own build history, but it lives on the daemon and is
+//! pruned with the cache. A [`BuildJournal`] records every build made through
+//! a client in a local SQLite database instead: what was built (a hash of the
+//! configuration), when, for how long, how much came from cache and, for
+//! failed builds, what kind of failure it was. Journals from many clients can
+//! be queried for fleet-wide analytics.
+//!
+//! Requires the `journal` feature.
+
+use crate::builder::{BuildConfig, ContextSource, DockerfileSource};
+use crate::error::{Error, Result};
+use crate::frontend::FrontendAttrs;
+use crate::invalidation::is_dockerfile_step;
+use crate::solve::BuildResult;
+use rusqlite::{params, Connection};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Schema of the journal database
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS builds (
+    id INTEGER PRIMARY KEY,
+    build_ref TEXT NOT NULL,
+    config_hash TEXT NOT NULL,
+    started_at_ms INTEGER NOT NULL,
+    duration_ms INTEGER NOT NULL,
+    digest TEXT,
+    failure TEXT,
+    error TEXT,
+    steps INTEGER NOT NULL,
+    cached_steps INTEGER NOT NULL,
+    context_files INTEGER NOT NULL,
+    context_bytes INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS builds_config_hash ON builds (config_hash);
+CREATE INDEX IF NOT EXISTS builds_started_at ON builds (started_at_ms);
+";
+
+/// Columns read back into a [`JournalEntry`], in order
+const COLUMNS: &str = "build_ref, config_hash, started_at_ms, duration_ms, digest, failure, \
+                       error, steps, cached_steps, context_files, context_bytes";
+
+/// Broad cause of a failed build
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FailureKind {
+    /// The configuration was rejected before or by the daemon
+    Config,
+    /// The daemon could not be reached
+    Connection,
+    /// The session between client and daemon broke down
+    Session,
+    /// The local context could not be read or changed while it was sent
+    Context,
+    /// A build step failed on the daemon
+    Solve,
+    /// The build was cancelled, e.g. by a client shutdown
+    Cancelled,
+    /// Anything else
+    Other,
+}
+
+impl FailureKind {
+    /// Classify a build error
+    pub fn classify(error: &Error) -> Self {
+        match error {
+            Error::BuildCancelled(_) | Error::ClientShutdown => FailureKind::Cancelled,
+            Error::Connection { .. } | Error::InvalidEndpoint(_) => FailureKind::Connection,
+            Error::Grpc(status) if status.code() == tonic::Code::Unavailable => {
+                FailureKind::Connection
+            }
+            Error::Grpc(_)
+            | Error::Build(_)
+            | Error::Frontend(_)
+            | Error::JoinedBuildFailed { .. } => FailureKind::Solve,
+            Error::InvalidConfig(_)
+            | Error::InvalidPlatform(_)
+            | Error::InvalidOutput(_)
+            | Error::LabsFeatureRequired { .. }
+            | Error::InvalidBuildConfig(_)
+            | Error::DuplicateBuildRef(_) => FailureKind::Config,
+            Error::ContextFileRead { .. }
+            | Error::ContextFileChanged { .. }
+            | Error::ContextChangedDuringBuild { .. }
+            | Error::ContextArchive(_)
+            | Error::PathNotFound(_)
+            | Error::NotADirectory(_)
+            | Error::PathResolution { .. } => FailureKind::Context,
+            Error::Session(_) | Error::SessionNotStarted => FailureKind::Session,
+            e if e.is_protocol_fault() || e.is_disconnect() => FailureKind::Session,
+            _ => FailureKind::Other,
+        }
+    }
+
+    /// Name stored in the journal
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureKind::Config => "config",
+            FailureKind::Connection => "connection",
+            FailureKind::Session => "session",
+            FailureKind::Context => "context",
+            FailureKind::Solve => "solve",
+            FailureKind::Cancelled => "cancelled",
+            FailureKind::Other => "other",
+        }
+    }
+}
+
+impl std::str::FromStr for FailureKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "config" => Ok(FailureKind::Config),
+            "connection" => Ok(FailureKind::Connection),
+            "session" => Ok(FailureKind::Session),
+            "context" => Ok(FailureKind::Context),
+            "solve" => Ok(FailureKind::Solve),
+            "cancelled" => Ok(FailureKind::Cancelled),
+            "other" => Ok(FailureKind::Other),
+            _ => Err(Error::InvalidConfig(format!(
+                "unknown failure kind {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// One recorded build
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    /// Build reference
+    pub build_ref: String,
+    /// Hash of the configuration, see [`config_hash`]
+    pub config_hash: String,
+    /// When the build started
+    pub started_at: SystemTime,
+    /// How long the build ran
+    pub duration: Duration,
+    /// Digest of the exported image, if any
+    pub digest: Option<String>,
+    /// Cause of the failure, or `None` for a successful build
+    pub failure: Option<FailureKind>,
+    /// Error message of a failed build
+    pub error: Option<String>,
+    /// Dockerfile steps seen on the progress stream
+    pub steps: u64,
+    /// Steps served from cache
+    pub cached_steps: u64,
+    /// Regular files listed from local contexts
+    pub context_files: u64,
+    /// Combined size of those files
+    pub context_bytes: u64,
+}
+
+impl JournalEntry {
+    /// Describe a finished build
+    pub fn new(
+        build_ref: impl Into<String>,
+        config_hash: impl Into<String>,
+        started_at: SystemTime,
+        result: &Result<BuildResult>,
+    ) -> Self {
+        let mut entry = Self {
+            build_ref: build_ref.into(),
+            config_hash: config_hash.into(),
+            // Milliseconds are what the journal keeps
+            started_at: UNIX_EPOCH + Duration::from_millis(unix_millis(started_at) as u64),
+            duration: Duration::from_millis(
+                started_at.elapsed().unwrap_or_default().as_millis() as u64
+            ),
+            digest: None,
+            failure: None,
+            error: None,
+            steps: 0,
+            cached_steps: 0,
+            context_files: 0,
+            context_bytes: 0,
+        };
+        match result {
+            Ok(result) => {
+                let steps = result
+                    .vertexes
+                    .iter()
+                    .filter(|vertex| is_dockerfile_step(&vertex.name));
+                for vertex in steps {
+                    entry.steps += 1;
+                    entry.cached_steps += u64::from(vertex.cached);
+                }
+                entry.digest = result.digest.clone();
+                entry.context_files = result.sync_stats.files;
+                entry.context_bytes = result.sync_stats.bytes;
+            }
+            Err(error) => {
+                entry.failure = Some(FailureKind::classify(error));
+                entry.error = Some(error.to_string());
+            }
+        }
+        entry
+    }
+
+    /// Whether the build succeeded
+    pub fn succeeded(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+/// Filter for [`BuildJournal::query`]; matches everything by default
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JournalQuery {
+    /// Only builds with this ref
+    pub build_ref: Option<String>,
+    /// Only builds of this configuration
+    pub config_hash: Option<String>,
+    /// Only successful (`true`) or failed (`false`) builds
+    pub succeeded: Option<bool>,
+    /// Only builds that failed this way
+    pub failure: Option<FailureKind>,
+    /// Only builds started at or after this time
+    pub since: Option<SystemTime>,
+    /// At most this many builds, newest first
+    pub limit: Option<usize>,
+}
+
+impl JournalQuery {
+    /// Match every build
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only builds with a ref
+    pub fn build_ref(mut self, build_ref: impl Into<String>) -> Self {
+        self.build_ref = Some(build_ref.into());
+        self
+    }
+
+    /// Only builds of a configuration
+    pub fn config_hash(mut self, hash: impl Into<String>) -> Self {
+        self.config_hash = Some(hash.into());
+        self
+    }
+
+    /// Only successful or failed builds
+    pub fn succeeded(mut self, succeeded: bool) -> Self {
+        self.succeeded = Some(succeeded);
+        self
+    }
+
+    /// Only builds that failed this way
+    pub fn failure(mut self, kind: FailureKind) -> Self {
+        self.failure = Some(kind);
+        self
+    }
+
+    /// Only builds started at or after `time`
+    pub fn since(mut self, time: SystemTime) -> Self {
+        self.since = Some(time);
+        self
+    }
+
+    /// Return at most `limit` builds
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+/// Aggregates over a set of journal entries
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct JournalStats {
+    /// Builds
+    pub builds: u64,
+    /// Failed builds
+    pub failures: u64,
+    /// Mean duration of the builds
+    pub mean_duration: Duration,
+    /// Share of steps served from cache (0 when no steps were seen)
+    pub cache_hit_ratio: f64,
+}
+
+impl JournalStats {
+    /// Aggregate entries, e.g. the result of a query
+    pub fn from_entries(entries: &[JournalEntry]) -> Self {
+        if entries.is_empty() {
+            return Self::default();
+        }
+        let total: Duration = entries.iter().map(|entry| entry.duration).sum();
+        let steps: u64 = entries.iter().map(|entry| entry.steps).sum();
+        let cached: u64 = entries.iter().map(|entry| entry.cached_steps).sum();
+        Self {
+            builds: entries.len() as u64,
+            failures: entries.iter().filter(|entry| !entry.succeeded()).count() as u64,
+            mean_duration: total / entries.len() as u32,
+            cache_hit_ratio: if steps == 0 {
+                0.0
+            } else {
+                cached as f64 / steps as f64
+            },
+        }
+    }
+}
+
+/// SQLite-backed record of builds
+///
+/// Attach it to a client with
+/// [`BuildKitClient::with_journal`](crate::BuildKitClient::with_journal).
+/// The database is accessed synchronously; builds are recorded from a
+/// blocking thread so the async runtime is never stalled on SQLite.
+#[derive(Debug)]
+pub struct BuildJournal {
+    connection: Mutex<Connection>,
+}
+
+impl BuildJournal {
+    /// Open (or create) a journal database file
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::init(Connection::open(path).map_err(journal_error)?)
+    }
+
+    /// Open a journal that only lives as long as this value
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory().map_err(journal_error)?)
+    }
+
+    fn init(connection: Connection) -> Result<Self> {
+        connection.execute_batch(SCHEMA).map_err(journal_error)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Record a build
+    pub fn record(&self, entry: &JournalEntry) -> Result<()> {
+        let connection = self.connection.lock().unwrap_or_else(|e| e.into_inner());
+        connection
+            .execute(
+                &format!(
+                    "INSERT INTO builds ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                    COLUMNS
+                ),
+                params![
+                    entry.build_ref,
+                    entry.config_hash,
+                    unix_millis(entry.started_at),
+                    entry.duration.as_millis() as i64,
+                    entry.digest,
+                    entry.failure.map(|kind| kind.as_str()),
+                    entry.error,
+                    entry.steps as i64,
+                    entry.cached_steps as i64,
+                    entry.context_files as i64,
+                    entry.context_bytes as i64,
+                ],
+            )
+            .map_err(journal_error)?;
+        Ok(())
+    }
+
+    /// Builds matching `query`, newest first
+    pub fn query(&self, query: &JournalQuery) -> Result<Vec<JournalEntry>> {
+        let mut sql = format!("SELECT {} FROM builds WHERE 1 = 1", COLUMNS);
+        let mut values: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+        if let Some(build_ref) = &query.build_ref {
+            values.push(Box::new(build_ref.clone()));
+            sql.push_str(&format!(" AND build_ref = ?{}", values.len()));
+        }
+        if let Some(hash) = &query.config_hash {
+            values.push(Box::new(hash.clone()));
+            sql.push_str(&format!(" AND config_hash = ?{}", values.len()));
+        }
+        match query.succeeded {
+            Some(true) => sql.push_str(" AND failure IS NULL"),
+            Some(false) => sql.push_str(" AND failure IS NOT NULL"),
+            None => {}
+        }
+        if let Some(kind) = query.failure {
+            values.push(Box::new(kind.as_str()));
+            sql.push_str(&format!(" AND failure = ?{}", values.len()));
+        }
+        if let Some(since) = query.since {
+            values.push(Box::new(unix_millis(since)));
+            sql.push_str(&format!(" AND started_at_ms >= ?{}", values.len()));
+        }
+        sql.push_str(" ORDER BY started_at_ms DESC, id DESC");
+        if let Some(limit) = query.limit {
+            sql.push_str(&format!(" LIMIT {}", limit));
+        }
+
+        let connection = self.connection.lock().unwrap_or_else(|e| e.into_inner());
+        let mut statement = connection.prepare(&sql).map_err(journal_error)?;
+        let rows = statement
+            .query_map(rusqlite::params_from_iter(values.iter()), |row| {
+                Ok((
+                    JournalEntry {
+                        build_ref: row.get(0)?,
+                        config_hash: row.get(1)?,
+                        started_at: UNIX_EPOCH
+                            + Duration::from_millis(row.get::<_, i64>(2)? as u64),
+                        duration: Duration::from_millis(row.get::<_, i64>(3)? as u64),
+                        digest: row.get(4)?,
+                        failure: None,
+                        error: row.get(6)?,
+                        steps: row.get::<_, i64>(7)? as u64,
+                        cached_steps: row.get::<_, i64>(8)? as u64,
+                        context_files: row.get::<_, i64>(9)? as u64,
+                        context_bytes: row.get::<_, i64>(10)? as u64,
+                    },
+                    row.get::<_, Option<String>>(5)?,
+                ))
+            })
+            .map_err(journal_error)?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            let (mut entry, failure) = row.map_err(journal_error)?;
+            entry.failure = failure.as_deref().map(str::parse).transpose()?;
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// The most recent build with this ref, if any
+    pub fn find(&self, build_ref: &str) -> Result<Option<JournalEntry>> {
+        Ok(self
+            .query(&JournalQuery::new().build_ref(build_ref).limit(1))?
+            .pop())
+    }
+}
+
+/// Hash identifying what a configuration builds
+///
+/// Covers the source, frontend, frontend attributes, tags and outputs, but
+/// not credentials, so rotating a token keeps the hash.
+pub fn config_hash(config: &BuildConfig) -> String {
+    let mut hasher = Sha256::new();
+    match &config.source {
+        DockerfileSource::Local {
+            context_path,
+            dockerfile_path,
+        } => {
+            hasher.update(format!("local {:?} {:?}\n", context_path, dockerfile_path));
+        }
+        DockerfileSource::GitHub {
+            repo_url,
+            git_ref,
+            dockerfile_path,
+            ..
+        } => {
+            hasher.update(format!(
+                "git {} {:?} {:?}\n",
+                repo_url, git_ref, dockerfile_path
+            ));
+        }
+        DockerfileSource::Git {
+            url,
+            git_ref,
+            dockerfile_path,
+            ..
+        } => {
+            hasher.update(format!("git {} {:?} {:?}\n", url, git_ref, dockerfile_path));
+        }
+        DockerfileSource::Http {
+            url,
+            checksum,
+            dockerfile_path,
+            ..
+        } => {
+            hasher.update(format!(
+                "http {} {:?} {:?}\n",
+                url, checksum, dockerfile_path
+            ));
+        }
+        DockerfileSource::Context {
+            context: ContextSource::Memory(files),
+            dockerfile_path,
+        } => {
+            hasher.update(format!("memory {:?}\n", dockerfile_path));
+            let mut files: Vec<_> = files.iter().collect();
+            files.sort_by(|a, b| a.0.cmp(b.0));
+            for (path, file) in files {
+                hasher.update(format!(
+                    "file {:?} {:o} {:x}\n",
+                    path,
+                    file.mode,
+                    Sha256::digest(&file.content)
+                ));
+            }
+        }
+        DockerfileSource::Context {
+            context: ContextSource::Tar(archive),
+            dockerfile_path,
+        } => {
+            hasher.update(format!("tar {:?} {:?}\n", archive.path(), dockerfile_path));
+        }
+        DockerfileSource::Context {
+            context: ContextSource::Provider(provider),
+            dockerfile_path,
+        } => {
+            hasher.update(format!("provider {:?} {:?}\n", provider, dockerfile_path));
+        }
+        DockerfileSource::Upload {
+            dockerfile_path, ..
+        } => {
+            hasher.update(format!("upload {:?}\n", dockerfile_path));
+        }
+    }
+    if let Some(dockerfile) = &config.inline_dockerfile {
+        hasher.update(format!("dockerfile {:x}\n", Sha256::digest(dockerfile)));
+    }
+    hasher.update(format!("frontend {}\n", config.frontend.name()));
+    match FrontendAttrs::from_config(config) {
+        Ok(attrs) => hasher.update(attrs.to_string()),
+        Err(e) => hasher.update(format!("invalid {}\n", e)),
+    }
+    for tag in &config.tags {
+        hasher.update(format!("tag {}\n", tag));
+    }
+    for output in &config.outputs {
+        hasher.update(format!("output {:?}\n", output));
+    }
+    format!("sha256:{:x}", hasher.finalize())
+}
+
+fn unix_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+fn journal_error(e: rusqlite::Error) -> Error {
+    Error::Journal(e.to_string())
+}
@@ -0,0 +1,240 @@
+//! OCI image layout directories
+//!
+//! [`OciLayout`] serves an [OCI image layout] on the local file system
+//! through the [`ContentStore`] trait. It backs the `local` cache backend:
+//! BuildKit reads and writes the cache blobs over the session, and the
+//! client keeps `index.json` pointing at the latest cache manifest. Named
+//! contexts read base images from a layout the same way.
+//!
+//! [OCI image layout]: https://github.com/opencontainers/image-spec/blob/main/image-layout.md
+
+use crate::error::{Error, Result};
+use crate::oci::{Descriptor, ImageIndex, MEDIA_TYPE_IMAGE_INDEX};
+use crate::session::{BlobInfo, BlobWriter, ContentStore};
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Annotation naming the tag of a manifest listed in `index.json`
+pub const REF_NAME_ANNOTATION: &str = "org.opencontainers.image.ref.name";
+
+/// Scheme of OCI layout context references
+pub const OCI_LAYOUT_SCHEME: &str = "oci-layout://";
+
+/// Session store ID under which the layout at `path` backs a named context
+///
+/// BuildKit parses the ID as an image name, so it is derived from a hash of
+/// the path rather than the path itself.
+pub fn context_store_id(path: &Path) -> String {
+    let hash = format!("{:x}", Sha256::digest(path.to_string_lossy().as_bytes()));
+    format!("oci-context-{}", &hash[..16])
+}
+
+const LAYOUT_FILE: &str = "oci-layout";
+const LAYOUT_CONTENT: &str = r#"{"imageLayoutVersion":"1.0.0"}"#;
+const INDEX_FILE: &str = "index.json";
+
+/// Content of an OCI image layout directory
+#[derive(Debug, Clone)]
+pub struct OciLayout {
+    root: PathBuf,
+}
+
+impl OciLayout {
+    /// Open a layout directory, creating it if it does not exist
+    pub fn open(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        std::fs::create_dir_all(root.join("blobs"))?;
+        let layout_file = root.join(LAYOUT_FILE);
+        if !layout_file.exists() {
+            std::fs::write(&layout_file, LAYOUT_CONTENT)?;
+        }
+        Ok(Self { root })
+    }
+
+    /// Root directory of the layout
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Path of a blob (`blobs/<algorithm>/<hex>`)
+    pub fn blob_path(&self, digest: &str) -> Result<PathBuf> {
+        let (algorithm, hex) = digest
+            .split_once(':')
+            .filter(|(algorithm, hex)| {
+                !algorithm.is_empty()
+                    && !hex.is_empty()
+                    && algorithm.chars().all(|c| c.is_ascii_alphanumeric())
+                    && hex.chars().all(|c| c.is_ascii_hexdigit())
+            })
+            .ok_or_else(|| Error::content_store(format!("invalid digest {:?}", digest)))?;
+        Ok(self.root.join("blobs").join(algorithm).join(hex))
+    }
+
+    /// Read `index.json`, which is empty if the layout has none yet
+    pub fn index(&self) -> Result<ImageIndex> {
+        match std::fs::read(self.root.join(INDEX_FILE)) {
+            Ok(json) => ImageIndex::from_json(&json),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(ImageIndex::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Descriptor tagged `tag` in `index.json`
+    pub fn resolve_tag(&self, tag: &str) -> Result<Option<Descriptor>> {
+        Ok(self.index()?.manifests.into_iter().find(|manifest| {
+            manifest
+                .annotations
+                .get(REF_NAME_ANNOTATION)
+                .map(String::as_str)
+                == Some(tag)
+        }))
+    }
+
+    /// Point `tag` in `index.json` at a descriptor, given as JSON
+    ///
+    /// Other entries of the index, and fields this crate does not model,
+    /// are kept as they are.
+    pub fn set_tag(&self, descriptor: &[u8], tag: &str) -> Result<()> {
+        let mut descriptor: serde_json::Value = serde_json::from_slice(descriptor)
+            .map_err(|e| Error::oci(format!("invalid descriptor: {}", e)))?;
+        let entry = descriptor
+            .as_object_mut()
+            .ok_or_else(|| Error::oci("descriptor is not a JSON object"))?;
+        let annotations = entry
+            .entry("annotations")
+            .or_insert_with(|| serde_json::json!({}));
+        annotations
+            .as_object_mut()
+            .ok_or_else(|| Error::oci("descriptor annotations are not a JSON object"))?
+            .insert(REF_NAME_ANNOTATION.to_string(), tag.into());
+
+        let index_path = self.root.join(INDEX_FILE);
+        let mut index = match std::fs::read(&index_path) {
+            Ok(json) => serde_json::from_slice(&json)
+                .map_err(|e| Error::oci(format!("invalid image index: {}", e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => serde_json::json!({
+                "schemaVersion": 2,
+                "mediaType": MEDIA_TYPE_IMAGE_INDEX,
+                "manifests": [],
+            }),
+            Err(e) => return Err(e.into()),
+        };
+        let manifests = index
+            .get_mut("manifests")
+            .and_then(serde_json::Value::as_array_mut)
+            .ok_or_else(|| Error::oci("image index without manifests"))?;
+        manifests
+            .retain(|manifest| manifest["annotations"][REF_NAME_ANNOTATION].as_str() != Some(tag));
+        manifests.push(descriptor);
+
+        let json = serde_json::to_vec(&index)
+            .map_err(|e| Error::oci(format!("failed to encode image index: {}", e)))?;
+        write_atomic(&index_path, &json)
+    }
+}
+
+#[tonic::async_trait]
+impl ContentStore for OciLayout {
+    async fn info(&self, digest: &str) -> Result<Option<BlobInfo>> {
+        let path = self.blob_path(digest)?;
+        match tokio::fs::metadata(&path).await {
+            Ok(metadata) => Ok(Some(BlobInfo {
+                digest: digest.to_string(),
+                size: metadata.len(),
+                labels: HashMap::new(),
+            })),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn read_at(&self, digest: &str, offset: u64, len: usize) -> Result<Bytes> {
+        let path = self.blob_path(digest)?;
+        let store = self.root.display().to_string();
+        let digest = digest.to_string();
+        tokio::task::spawn_blocking(move || {
+            let mut file = std::fs::File::open(&path).map_err(|e| {
+                if e.kind() == std::io::ErrorKind::NotFound {
+                    Error::ContentNotFound { store, digest }
+                } else {
+                    e.into()
+                }
+            })?;
+            file.seek(SeekFrom::Start(offset))?;
+            let mut data = Vec::with_capacity(len);
+            file.take(len as u64).read_to_end(&mut data)?;
+            Ok(Bytes::from(data))
+        })
+        .await
+        .map_err(|e| Error::content_store(format!("blob read failed: {}", e)))?
+    }
+
+    async fn writer(&self, _reference: &str) -> Result<Box<dyn BlobWriter>> {
+        let blobs = self.root.join("blobs");
+        tokio::fs::create_dir_all(&blobs).await?;
+        let tmp = blobs.join(format!(".tmp-{}", uuid::Uuid::new_v4()));
+        let file = tokio::fs::File::create(&tmp).await?;
+        Ok(Box::new(LayoutWriter {
+            layout: self.clone(),
+            file,
+            tmp: Some(tmp),
+        }))
+    }
+}
+
+/// Upload staged in a temporary file under `blobs/` until it is committed
+struct LayoutWriter {
+    layout: OciLayout,
+    file: tokio::fs::File,
+    tmp: Option<PathBuf>,
+}
+
+#[tonic::async_trait]
+impl BlobWriter for LayoutWriter {
+    async fn write(&mut self, data: &[u8]) -> Result<()> {
+        use tokio::io::AsyncWriteExt;
+        self.file.write_all(data).await.map_err(Into::into)
+    }
+
+    async fn commit(mut self: Box<Self>, info: BlobInfo) -> Result<()> {
+        let path = self.layout.blob_path(&info.digest)?;
+        self.file.sync_all().await?;
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let tmp = self.tmp.take().expect("writer commits once");
+        if let Err(e) = tokio::fs::rename(&tmp, &path).await {
+            self.tmp = Some(tmp);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for LayoutWriter {
+    fn drop(&mut self) {
+        if let Some(tmp) = self.tmp.take() {
+            let _ = std::fs::remove_file(tmp);
+        }
+    }
+}
+
+/// Replace a file so readers never see it half-written
+fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".tmp-{}", uuid::Uuid::new_v4()));
+    let tmp = PathBuf::from(tmp);
+
+    let mut file = std::fs::File::create(&tmp)?;
+    let written = file
+        .write_all(data)
+        .and_then(|_| file.sync_all())
+        .and_then(|_| std::fs::rename(&tmp, path));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    written.map_err(Into::into)
+}
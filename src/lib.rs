@@ -7,10 +7,21 @@
 //! - Build from local Dockerfile or GitHub repository
 //! - Support for private GitHub repositories with authentication
 //! - Push images to registries with authentication
-//! - Multi-platform builds
+//! - Multiple outputs (image, local directory, OCI/Docker/filesystem tarball) in a single build
+//! - Loading built images straight into a local Docker daemon
+//! - Pre-flight verification of remote `ADD` sources and base image configs
+//! - Pre-flight validation of build configurations, reporting every problem at once
+//! - Multi-platform builds, optionally as parallel per-platform solves
+//! - SLSA provenance attestations
 //! - Build arguments, target stages, and advanced options
-//! - Real-time progress monitoring
-//! - Cache import/export
+//! - Real-time progress monitoring, including following a single step's logs
+//! - Cache import/export, with reports of which context files invalidated the cache
+//! - A one-call `quick_build` for the common build, push and cache case
+//! - Copying images between registries on the builder, without a local pull
+//! - Solving raw LLB definitions without a frontend
+//! - Linting Dockerfiles and listing the inputs of a target without building it
+//! - Opening debug containers on the state of a failed `RUN` step
+//! - An optional local SQLite journal of builds (`journal` feature)
 //!
 //! # Examples
 //!
@@ -72,11 +83,7 @@
 //!         .platform(Platform::linux_amd64())
 //!         .platform(Platform::linux_arm64())
 //!         .tag("docker.io/myuser/myapp:latest")
-//!         .registry_auth(RegistryAuth {
-//!             host: "docker.io".to_string(),
-//!             username: "myuser".to_string(),
-//!             password: "mytoken".to_string(),
-//!         });
+//!         .registry_auth(RegistryAuth::basic("docker.io", "myuser", "mytoken"));
 //!
 //!     let progress = Box::new(ConsoleProgressHandler::new(true));
 //!     let result = client.build(config, Some(progress)).await?;
@@ -87,15 +94,71 @@
 //! ```
 
 pub mod proto;
+pub mod attest;
 pub mod error;
 pub mod builder;
+pub mod cache;
+pub mod copy;
+pub mod debug;
+pub mod frontend;
+pub mod gateway;
+pub mod git;
+pub mod github;
+pub mod http_context;
+pub mod image_config;
+pub mod llb;
+pub mod layout;
+pub mod lint;
+pub mod outline;
+pub mod output;
+pub mod oci;
+pub mod parallel;
+pub mod registry;
+pub mod docker;
+pub mod remote_sources;
 pub mod client;
 pub mod progress;
 pub mod solve;
+pub mod validate;
+pub mod handle;
+pub mod invalidation;
+#[cfg(feature = "journal")]
+pub mod journal;
+pub mod quick;
+mod task;
 pub mod session;
 
 // Re-export main types
-pub use builder::{BuildConfig, DockerfileSource, Platform, RegistryAuth};
+pub use attest::{Attestation, AttestationManifestRef, Provenance, ProvenanceMode};
+pub use builder::{
+    BuildConfig, ContextSource, DockerfileSource, Entitlement, ImageResolveMode, NamedContext,
+    NetworkMode, Platform, RegistryAuth, RegistryConfig, SolveMutation,
+};
+pub use cache::{CacheExport, CacheImport, CacheMode};
 pub use client::BuildKitClient;
+pub use debug::{DebugHandler, DebugSession, DebugState, FailedStep, ShellDebugger};
+pub use frontend::{Frontend, FrontendAttrs, LabsFeature};
+pub use git::{GitAuth, GitProxy};
+pub use image_config::ResolvedImage;
+pub use lint::{LintReport, LintWarning};
+pub use llb::LlbSolveOptions;
+pub use outline::Outline;
+pub use progress::{LogChunk, StatusSummary, VertexProgress, VertexSummary, WarningSummary};
+pub use output::{
+    Annotation, AnnotationScope, ArchiveOutput, Compression, ExportDest, ExportWriter, ImageOutput,
+    LayerCompression, Output,
+};
+pub use session::frame::FrameLimits;
+pub use session::{
+    AccessLogPolicy, BlobInfo, BlobWriter, ContentStore, ContextEntry, ContextProvider,
+    ContextUser, DuplicateFiles, FileEntry, FileSyncOptions, IgnorePatterns, SessionHealth,
+    SharedKey, SshKey, SyncStats, TarContext, Upload,
+};
 pub use solve::BuildResult;
-pub use error::{Error, Result};
+pub use parallel::{ParallelBuildResult, PlatformBuild};
+pub use handle::{BuildHandle, StepLogs, StepMatcher};
+pub use invalidation::{CacheInvalidationReport, InvalidatedStep};
+#[cfg(feature = "journal")]
+pub use journal::{BuildJournal, FailureKind, JournalEntry, JournalQuery, JournalStats};
+pub use quick::QuickBuild;
+pub use error::{ConfigProblem, Error, Result};
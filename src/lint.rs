@@ -0,0 +1,107 @@
+//! Dockerfile linting through the `frontend.lint` subrequest
+//!
+//! The Dockerfile frontend checks a Dockerfile against its build rules
+//! (`StageNameCasing`, `FromAsCasing`, `UndefinedVar`, ...) without running
+//! any step, the way `docker build --check` does. Findings come back as JSON
+//! with the location of each one in the Dockerfile.
+
+use crate::builder::BuildConfig;
+use crate::client::BuildKitClient;
+use crate::error::{Error, Result};
+use crate::gateway::{nullable, SourceLocation, RESULT_JSON_KEY};
+use serde::Deserialize;
+
+/// Subrequest ID of Dockerfile linting
+pub const LINT_REQUEST_ID: &str = "frontend.lint";
+
+/// Findings of a lint run
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct LintReport {
+    /// Rule violations, in Dockerfile order
+    #[serde(default, deserialize_with = "nullable")]
+    pub warnings: Vec<LintWarning>,
+    /// Files the locations refer to, by index
+    #[serde(default, deserialize_with = "nullable")]
+    pub sources: Vec<LintSource>,
+    /// Error that would fail the build (e.g., a syntax error)
+    #[serde(default, rename = "buildError")]
+    pub build_error: Option<LintError>,
+}
+
+impl LintReport {
+    /// Decode the `result.json` answer of a lint subrequest
+    pub fn from_json(json: &[u8]) -> Result<Self> {
+        serde_json::from_slice(json)
+            .map_err(|e| Error::frontend(format!("invalid lint result: {}", e)))
+    }
+
+    /// Check whether the Dockerfile has neither warnings nor errors
+    pub fn is_clean(&self) -> bool {
+        self.warnings.is_empty() && self.build_error.is_none()
+    }
+
+    /// File a location refers to
+    pub fn source(&self, location: &SourceLocation) -> Option<&LintSource> {
+        usize::try_from(location.source_index)
+            .ok()
+            .and_then(|index| self.sources.get(index))
+    }
+}
+
+/// A lint rule violation
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct LintWarning {
+    /// Rule name (e.g., `StageNameCasing`)
+    #[serde(rename = "ruleName")]
+    pub rule_name: String,
+    /// What the rule checks
+    #[serde(default)]
+    pub description: String,
+    /// Documentation of the rule
+    #[serde(default)]
+    pub url: String,
+    /// What is wrong at this location
+    #[serde(default)]
+    pub detail: String,
+    /// Where the violation is
+    #[serde(default)]
+    pub location: SourceLocation,
+}
+
+/// Error reported instead of (or next to) the warnings
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct LintError {
+    /// Error message
+    #[serde(default)]
+    pub message: String,
+    /// Where the error is, if known
+    #[serde(default)]
+    pub location: Option<SourceLocation>,
+}
+
+/// A file checked by the linter
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct LintSource {
+    /// File name (e.g., `Dockerfile`)
+    #[serde(default)]
+    pub filename: String,
+    /// Language of the file (e.g., `Dockerfile`)
+    #[serde(default)]
+    pub language: String,
+}
+
+impl BuildKitClient {
+    /// Lint the Dockerfile of `config` without building it
+    ///
+    /// The frontend reads the Dockerfile (and build arguments, target, ...)
+    /// exactly as for a build; no step runs and nothing is exported. A
+    /// Dockerfile that would fail to parse is reported in
+    /// [`LintReport::build_error`] rather than as an error.
+    pub async fn lint(&mut self, config: BuildConfig) -> Result<LintReport> {
+        let metadata = self.frontend_subrequest(config, LINT_REQUEST_ID).await?;
+        let json = metadata.get(RESULT_JSON_KEY).ok_or_else(|| {
+            Error::frontend("the frontend did not answer the lint request; it may predate linting")
+        })?;
+        LintReport::from_json(json.as_bytes())
+    }
+}
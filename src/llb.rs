@@ -0,0 +1,259 @@
+//! Solving raw LLB definitions
+//!
+//! [`BuildKitClient::build`] always goes through a frontend. Tools that
+//! generate their own build graph (other build languages, CI systems,
+//! ad-hoc pipelines) can hand BuildKit a marshalled [`pb::Definition`]
+//! instead with [`BuildKitClient::solve_llb`]; the session still serves
+//! local directories, secrets, registry credentials and export streams.
+
+use crate::builder::{Entitlement, RegistryAuth};
+use crate::client::BuildKitClient;
+use crate::copy::op_digest;
+use crate::error::{Error, Result};
+use crate::oci::Descriptor;
+use crate::output::Output;
+use crate::progress::{ProgressBufferConfig, ProgressHandler};
+use crate::proto::moby::buildkit::v1::{Exporter, SolveRequest};
+use crate::proto::pb;
+use crate::session::{AuthServer, RegistryAuthConfig, SecretsServer, Session};
+use crate::solve::{file_send_for, BuildResult};
+use prost::Message;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+
+/// Session services and solve settings for [`BuildKitClient::solve_llb`]
+#[derive(Default)]
+pub struct LlbSolveOptions {
+    /// Build reference; a random one is used by default
+    pub ref_id: Option<String>,
+    /// Directories served to `local://<name>` sources, by name
+    pub local_dirs: HashMap<String, PathBuf>,
+    /// Secrets served to secret mounts, by ID
+    pub secrets: HashMap<String, String>,
+    /// Credentials for the registries the graph pulls from or pushes to
+    pub registry_auths: Vec<RegistryAuth>,
+    /// Privileges the graph needs (host network, insecure execution)
+    pub entitlements: BTreeSet<Entitlement>,
+    /// Progress handler for real-time updates
+    pub progress_handler: Option<Box<dyn ProgressHandler>>,
+}
+
+impl LlbSolveOptions {
+    /// Create options without session services
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the build reference
+    pub fn ref_id(mut self, ref_id: impl Into<String>) -> Self {
+        self.ref_id = Some(ref_id.into());
+        self
+    }
+
+    /// Serve `path` to `local://<name>` sources
+    pub fn local_dir(mut self, name: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        self.local_dirs.insert(name.into(), path.into());
+        self
+    }
+
+    /// Serve a secret to mounts with the given ID
+    pub fn secret(mut self, id: impl Into<String>, value: impl Into<String>) -> Self {
+        self.secrets.insert(id.into(), value.into());
+        self
+    }
+
+    /// Add credentials for a registry
+    pub fn registry_auth(mut self, auth: RegistryAuth) -> Self {
+        self.registry_auths.push(auth);
+        self
+    }
+
+    /// Grant the solve a privilege
+    pub fn entitlement(mut self, entitlement: Entitlement) -> Self {
+        self.entitlements.insert(entitlement);
+        self
+    }
+
+    /// Report progress to `handler`
+    pub fn progress(mut self, handler: Box<dyn ProgressHandler>) -> Self {
+        self.progress_handler = Some(handler);
+        self
+    }
+}
+
+impl fmt::Debug for LlbSolveOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secrets: Vec<&String> = self.secrets.keys().collect();
+        let registries: Vec<&String> = self.registry_auths.iter().map(|auth| &auth.host).collect();
+        f.debug_struct("LlbSolveOptions")
+            .field("ref_id", &self.ref_id)
+            .field("local_dirs", &self.local_dirs)
+            .field("secrets", &secrets)
+            .field("registry_auths", &registries)
+            .field("entitlements", &self.entitlements)
+            .field("progress_handler", &self.progress_handler.is_some())
+            .finish()
+    }
+}
+
+/// Check that `definition` is a complete graph BuildKit can load
+///
+/// Every op input must point at another op of the definition, by the digest
+/// of its encoding.
+pub fn validate_definition(definition: &pb::Definition) -> Result<()> {
+    if definition.def.is_empty() {
+        return Err(Error::InvalidConfig(
+            "LLB definition has no ops".to_string(),
+        ));
+    }
+    let mut digests = HashSet::with_capacity(definition.def.len());
+    let mut ops = Vec::with_capacity(definition.def.len());
+    for (index, encoded) in definition.def.iter().enumerate() {
+        let op = pb::Op::decode(encoded.as_slice()).map_err(|e| {
+            Error::InvalidConfig(format!("LLB definition: op {} is malformed: {}", index, e))
+        })?;
+        digests.insert(op_digest(encoded));
+        ops.push(op);
+    }
+    for op in &ops {
+        if let Some(input) = op
+            .inputs
+            .iter()
+            .find(|input| !digests.contains(&input.digest))
+        {
+            return Err(Error::InvalidConfig(format!(
+                "LLB definition: input {} is not part of the graph",
+                input.digest
+            )));
+        }
+    }
+    Ok(())
+}
+
+impl BuildKitClient {
+    /// Solve a raw LLB definition, bypassing the Dockerfile frontend
+    ///
+    /// `outputs` are exported like those of a [`BuildConfig`](crate::BuildConfig);
+    /// without any, the result only ends up in BuildKit's cache.
+    pub async fn solve_llb(
+        &mut self,
+        definition: pb::Definition,
+        outputs: Vec<Output>,
+        mut options: LlbSolveOptions,
+    ) -> Result<BuildResult> {
+        validate_definition(&definition)?;
+
+        let build_ref = options
+            .ref_id
+            .take()
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let _in_flight = self.reserve_ref(&build_ref)?;
+        tracing::info!("Solving LLB definition with ref: {}", build_ref);
+
+        let mut session = Session::new();
+        for (name, path) in &options.local_dirs {
+            let abs_path = std::fs::canonicalize(path).map_err(|e| Error::PathResolution {
+                path: path.clone(),
+                source: e,
+            })?;
+            if !abs_path.is_dir() {
+                return Err(Error::NotADirectory(path.clone()));
+            }
+            session.add_local_dir(name.as_str(), abs_path).await;
+        }
+
+        let file_send = file_send_for(&outputs);
+        if !file_send.is_empty() {
+            session.add_file_send(file_send).await;
+        }
+
+        // Public images still need anonymous tokens from the auth service
+        let mut auth = AuthServer::new();
+        for registry_auth in &options.registry_auths {
+            auth.add_registry(RegistryAuthConfig::from(registry_auth));
+        }
+        session.add_auth(auth).await;
+
+        if !options.secrets.is_empty() {
+            let secrets = SecretsServer::from_map(std::mem::take(&mut options.secrets))
+                .map_err(|e| Error::secrets(format!("Failed to create secrets server: {}", e)))?;
+            session.add_secrets(secrets).await;
+        }
+
+        session.start(self.control().clone()).await?;
+        _in_flight.attach(&session);
+
+        let request = SolveRequest {
+            r#ref: build_ref.clone(),
+            definition: Some(definition),
+            session: session.get_id(),
+            entitlements: options
+                .entitlements
+                .iter()
+                .map(|entitlement| entitlement.to_string())
+                .collect(),
+            exporters: outputs
+                .iter()
+                .map(|output| Exporter {
+                    r#type: output.exporter_type().to_string(),
+                    attrs: output.attrs(),
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let mut grpc_request = tonic::Request::new(request);
+        *grpc_request.metadata_mut() = session.grpc_metadata();
+
+        let client = self.clone();
+        let monitor = self.clone();
+        let mut progress_handler = options.progress_handler.take();
+        let solved = tokio::select! {
+            solved = async {
+                let solve = async {
+                    Ok::<_, Error>(self.control().solve(grpc_request).await?.into_inner())
+                };
+                monitor
+                    .follow_solve(
+                        &build_ref,
+                        progress_handler.as_mut(),
+                        ProgressBufferConfig::default(),
+                        solve,
+                    )
+                    .await
+            } => solved,
+            _ = client.shutdown_requested() => {
+                tracing::warn!("Cancelling solve {} for client shutdown", build_ref);
+                let _ = session.finish().await;
+                return Err(Error::BuildCancelled(build_ref.clone()));
+            }
+            error = session.failed() => {
+                let _ = session.finish().await;
+                return Err(error);
+            }
+        };
+        let (response, (progress_metrics, vertexes)) = solved?;
+        session.finish().await?;
+
+        let digest = response
+            .exporter_response
+            .get("containerimage.digest")
+            .cloned();
+        let descriptor = Descriptor::from_exporter_response(&response.exporter_response)
+            .unwrap_or_else(|e| {
+                tracing::warn!("Ignoring exported image descriptor: {}", e);
+                None
+            });
+
+        Ok(BuildResult {
+            build_ref,
+            digest,
+            metadata: response.exporter_response,
+            descriptor,
+            progress_metrics,
+            sync_stats: session.sync_stats().await,
+            vertexes,
+            changed_files: Vec::new(),
+        })
+    }
+}
@@ -1,5 +1,8 @@
 use anyhow::Result;
-use buildkit_client::{BuildConfig, BuildKitClient, Platform, RegistryAuth};
+use buildkit_client::{
+    AccessLogPolicy, Annotation, BuildConfig, BuildKitClient, ContextUser, Entitlement, Frontend,
+    ImageResolveMode, NamedContext, NetworkMode, Output, Platform, RegistryAuth,
+};
 use buildkit_client::progress::{ConsoleProgressHandler, JsonProgressHandler};
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
@@ -16,6 +19,10 @@ struct Cli {
     #[arg(short, long)]
     verbose: bool,
 
+    /// How registry hosts and secret IDs requested by builds are logged (silent, ids, full)
+    #[arg(long, default_value = "ids")]
+    access_log: AccessLogPolicy,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -24,7 +31,8 @@ struct Cli {
 enum Commands {
     /// Build from a local Dockerfile
     Local {
-        /// Context directory
+        /// Context directory, a tar archive (optionally gzip-compressed), or
+        /// "-" to stream a tarball from stdin
         #[arg(short, long, default_value = ".")]
         context: PathBuf,
 
@@ -36,10 +44,34 @@ enum Commands {
         #[arg(short, long)]
         tag: Vec<String>,
 
+        /// Keep tagged images in the worker's image store instead of pushing
+        #[arg(long)]
+        no_push: bool,
+
+        /// Output destination (e.g., type=local,dest=./out)
+        #[arg(short, long)]
+        output: Vec<String>,
+
+        /// Image annotation ([scope:]key=value, scope: manifest, index, manifest-descriptor)
+        #[arg(long)]
+        annotation: Vec<String>,
+
         /// Build arguments
         #[arg(long)]
         build_arg: Vec<String>,
 
+        /// Secret read from a file (id=ID,src=PATH, repeatable)
+        #[arg(long, value_name = "SPEC")]
+        secret: Vec<String>,
+
+        /// SSH agent to forward (default|ID[=SOCKET|KEY[,KEY...]], repeatable)
+        #[arg(long, value_name = "SPEC")]
+        ssh: Vec<String>,
+
+        /// Ed25519 key served from memory without an ssh-agent (repeatable)
+        #[arg(long, value_name = "ID=PATH")]
+        ssh_key: Vec<String>,
+
         /// Target stage
         #[arg(long)]
         target: Option<String>,
@@ -60,14 +92,88 @@ enum Commands {
         #[arg(long)]
         registry_password: Option<String>,
 
+        /// Registry identity token, instead of a username and password
+        #[arg(long, conflicts_with_all = ["registry_user", "registry_password"])]
+        registry_token: Option<String>,
+
+        /// Registry reached over plain HTTP or unverified TLS (repeatable)
+        #[arg(long, value_name = "HOST")]
+        insecure_registry: Vec<String>,
+
         /// No cache
         #[arg(long)]
         no_cache: bool,
 
+        /// Build a stage without cache (repeatable)
+        #[arg(long)]
+        no_cache_filter: Vec<String>,
+
         /// Always pull base images
         #[arg(long)]
         pull: bool,
 
+        /// How base images are resolved (default, pull, local)
+        #[arg(long, conflicts_with = "pull")]
+        image_resolve_mode: Option<ImageResolveMode>,
+
+        /// Forward HTTP_PROXY, HTTPS_PROXY, NO_PROXY and ALL_PROXY as build args
+        #[arg(long)]
+        forward_proxy_env: bool,
+
+        /// Check that pushed tags resolve to the built manifest
+        #[arg(long)]
+        verify_push: bool,
+
+        /// Load the image into the local Docker daemon under its tags instead of pushing it
+        #[arg(long)]
+        load: bool,
+
+        /// Grant an entitlement (network.host, security.insecure)
+        #[arg(long)]
+        allow: Vec<Entitlement>,
+
+        /// Frontend (builtin, stable, labs, a Dockerfile frontend image or
+        /// gateway=<image> for any other frontend)
+        #[arg(long, default_value = "builtin")]
+        frontend: Frontend,
+
+        /// Network mode for RUN steps (default, none, host)
+        #[arg(long, default_value = "default")]
+        network: NetworkMode,
+
+        /// Add a host-to-IP mapping for RUN steps (host:ip)
+        #[arg(long)]
+        add_host: Vec<String>,
+
+        /// Additional named build context (name=path, name=docker-image://ref,
+        /// name=<git url> or name=oci-layout://dir[:tag])
+        #[arg(long)]
+        build_context: Vec<String>,
+
+        /// Size of /dev/shm for RUN steps, in bytes
+        #[arg(long)]
+        shm_size: Option<u64>,
+
+        /// Resource limit for RUN steps (name=soft[:hard])
+        #[arg(long)]
+        ulimit: Vec<String>,
+
+        /// Parent cgroup for RUN step containers
+        #[arg(long)]
+        cgroup_parent: Option<String>,
+
+        /// Hostname of RUN step containers
+        #[arg(long)]
+        build_hostname: Option<String>,
+
+        /// Check remote ADD sources before starting the build
+        #[arg(long)]
+        verify_sources: bool,
+
+        /// Require the context to be readable by this user (uid[:gid])
+        #[arg(long)]
+        read_as: Option<String>,
+
         /// JSON output
         #[arg(long)]
         json: bool,
@@ -83,9 +189,13 @@ enum Commands {
         git_ref: Option<String>,
 
         /// GitHub token for private repositories
-        #[arg(long, env = "GITHUB_TOKEN")]
+        #[arg(long, env = "GITHUB_TOKEN", hide_env_values = true)]
         token: Option<String>,
 
+        /// Keep the .git directory in the build context
+        #[arg(long)]
+        keep_git_dir: bool,
+
         /// Dockerfile path within the repository
         #[arg(short = 'f', long)]
         dockerfile: Option<String>,
@@ -94,10 +204,34 @@ enum Commands {
         #[arg(short, long)]
         tag: Vec<String>,
 
+        /// Keep tagged images in the worker's image store instead of pushing
+        #[arg(long)]
+        no_push: bool,
+
+        /// Output destination (e.g., type=local,dest=./out)
+        #[arg(short, long)]
+        output: Vec<String>,
+
+        /// Image annotation ([scope:]key=value, scope: manifest, index, manifest-descriptor)
+        #[arg(long)]
+        annotation: Vec<String>,
+
         /// Build arguments
         #[arg(long)]
         build_arg: Vec<String>,
 
+        /// Secret read from a file (id=ID,src=PATH, repeatable)
+        #[arg(long, value_name = "SPEC")]
+        secret: Vec<String>,
+
+        /// SSH agent to forward (default|ID[=SOCKET|KEY[,KEY...]], repeatable)
+        #[arg(long, value_name = "SPEC")]
+        ssh: Vec<String>,
+
+        /// Ed25519 key served from memory without an ssh-agent (repeatable)
+        #[arg(long, value_name = "ID=PATH")]
+        ssh_key: Vec<String>,
+
         /// Target stage
         #[arg(long)]
         target: Option<String>,
@@ -118,14 +252,80 @@ enum Commands {
         #[arg(long)]
         registry_password: Option<String>,
 
+        /// Registry identity token, instead of a username and password
+        #[arg(long, conflicts_with_all = ["registry_user", "registry_password"])]
+        registry_token: Option<String>,
+
+        /// Registry reached over plain HTTP or unverified TLS (repeatable)
+        #[arg(long, value_name = "HOST")]
+        insecure_registry: Vec<String>,
+
         /// No cache
         #[arg(long)]
         no_cache: bool,
 
+        /// Build a stage without cache (repeatable)
+        #[arg(long)]
+        no_cache_filter: Vec<String>,
+
         /// Always pull base images
         #[arg(long)]
         pull: bool,
 
+        /// How base images are resolved (default, pull, local)
+        #[arg(long, conflicts_with = "pull")]
+        image_resolve_mode: Option<ImageResolveMode>,
+
+        /// Forward HTTP_PROXY, HTTPS_PROXY, NO_PROXY and ALL_PROXY as build args
+        #[arg(long)]
+        forward_proxy_env: bool,
+
+        /// Check that pushed tags resolve to the built manifest
+        #[arg(long)]
+        verify_push: bool,
+
+        /// Load the image into the local Docker daemon under its tags instead of pushing it
+        #[arg(long)]
+        load: bool,
+
+        /// Grant an entitlement (network.host, security.insecure)
+        #[arg(long)]
+        allow: Vec<Entitlement>,
+
+        /// Frontend (builtin, stable, labs, a Dockerfile frontend image or
+        /// gateway=<image> for any other frontend)
+        #[arg(long, default_value = "builtin")]
+        frontend: Frontend,
+
+        /// Network mode for RUN steps (default, none, host)
+        #[arg(long, default_value = "default")]
+        network: NetworkMode,
+
+        /// Add a host-to-IP mapping for RUN steps (host:ip)
+        #[arg(long)]
+        add_host: Vec<String>,
+
+        /// Additional named build context (name=path, name=docker-image://ref,
+        /// name=<git url> or name=oci-layout://dir[:tag])
+        #[arg(long)]
+        build_context: Vec<String>,
+
+        /// Size of /dev/shm for RUN steps, in bytes
+        #[arg(long)]
+        shm_size: Option<u64>,
+
+        /// Resource limit for RUN steps (name=soft[:hard])
+        #[arg(long)]
+        ulimit: Vec<String>,
+
+        /// Parent cgroup for RUN step containers
+        #[arg(long)]
+        cgroup_parent: Option<String>,
+
+        /// Hostname of RUN step containers
+        #[arg(long)]
+        build_hostname: Option<String>,
+
         /// JSON output
         #[arg(long)]
         json: bool,
@@ -156,17 +356,47 @@ async fn main() -> Result<()> {
             context,
             dockerfile,
             tag,
+            no_push,
+            output,
+            annotation,
             build_arg,
+            secret,
+            ssh,
+            ssh_key,
             target,
             platform,
             registry_host,
             registry_user,
             registry_password,
+            registry_token,
+            insecure_registry,
             no_cache,
+            no_cache_filter,
             pull,
+            image_resolve_mode,
+            forward_proxy_env,
+            verify_push,
+            load,
+            allow,
+            frontend,
+            network,
+            add_host,
+            build_context,
+            shm_size,
+            ulimit,
+            cgroup_parent,
+            build_hostname,
+            verify_sources,
+            read_as,
             json,
         } => {
-            let mut config = BuildConfig::local(context);
+            let mut config = if context.as_os_str() == "-" {
+                BuildConfig::upload(tokio::io::stdin())
+            } else if context.is_file() {
+                BuildConfig::tar(context)
+            } else {
+                BuildConfig::local(context)
+            };
 
             if let Some(df) = dockerfile {
                 config = config.dockerfile(df.to_string_lossy().to_string());
@@ -175,6 +405,15 @@ async fn main() -> Result<()> {
             for t in tag {
                 config = config.tag(t);
             }
+            config = config.push(!no_push);
+
+            for o in output {
+                config = config.output(Output::parse(&o)?);
+            }
+
+            for a in annotation {
+                config.annotations.push(Annotation::parse(&a)?);
+            }
 
             for arg in build_arg {
                 if let Some((key, value)) = arg.split_once('=') {
@@ -182,6 +421,18 @@ async fn main() -> Result<()> {
                 }
             }
 
+            for spec in &secret {
+                let (id, path) = parse_secret(spec)?;
+                config = config.secret_from_file(id, path);
+            }
+            for spec in ssh {
+                config = config.ssh(spec);
+            }
+            for spec in &ssh_key {
+                let (id, path) = parse_ssh_key(spec)?;
+                config = config.ssh_key(id, std::fs::read(path)?);
+            }
+
             if let Some(t) = target {
                 config = config.target(t);
             }
@@ -193,17 +444,66 @@ async fn main() -> Result<()> {
                 }
             }
 
-            if let (Some(host), Some(user), Some(pass)) =
-                (registry_host, registry_user, registry_password)
-            {
-                config = config.registry_auth(RegistryAuth {
-                    host,
-                    username: user,
-                    password: pass,
-                });
+            match (registry_host, registry_user, registry_password, registry_token) {
+                (Some(host), _, _, Some(token)) => {
+                    config = config.registry_auth(RegistryAuth::identity_token(host, token));
+                }
+                (Some(host), Some(user), Some(pass), None) => {
+                    config = config.registry_auth(RegistryAuth::basic(host, user, pass));
+                }
+                _ => {}
+            }
+            for host in insecure_registry {
+                config = config.insecure_registry(host);
+            }
+
+            config = config
+                .no_cache(no_cache)
+                .no_cache_filter(no_cache_filter)
+                .pull(pull)
+                .forward_proxy_env(forward_proxy_env)
+                .verify_push(verify_push)
+                .frontend(frontend)
+                .network(network)
+                .verify_remote_sources(verify_sources)
+                .access_log(cli.access_log);
+
+            if let Some(mode) = image_resolve_mode {
+                config = config.image_resolve_mode(mode);
+            }
+            for entitlement in allow {
+                config = config.allow(entitlement);
+            }
+
+            for entry in add_host {
+                let (host, ip) = parse_add_host(&entry)?;
+                config = config.add_host(host, ip);
+            }
+
+            for entry in build_context {
+                let (name, context) = parse_build_context(&entry)?;
+                config = config.named_context(name, context);
+            }
+
+            if let Some(bytes) = shm_size {
+                config = config.shm_size(bytes);
             }
 
-            config = config.no_cache(no_cache).pull(pull);
+            for limit in ulimit {
+                config = config.ulimit(limit);
+            }
+
+            if let Some(parent) = cgroup_parent {
+                config = config.cgroup_parent(parent);
+            }
+
+            if let Some(hostname) = build_hostname {
+                config = config.build_hostname(hostname);
+            }
+
+            if let Some(user) = read_as {
+                config = config.read_as(parse_context_user(&user)?);
+            }
 
             let progress: Box<dyn buildkit_client::progress::ProgressHandler> = if json {
                 Box::new(JsonProgressHandler::new())
@@ -211,7 +511,13 @@ async fn main() -> Result<()> {
                 Box::new(ConsoleProgressHandler::new(cli.verbose))
             };
 
-            let result = client.build(config, Some(progress)).await?;
+            let result = if load {
+                let (result, loaded) = client.build_and_load(config, Some(progress)).await?;
+                println!("\n🐳 {}", loaded);
+                result
+            } else {
+                client.build(config, Some(progress)).await?
+            };
 
             if let Some(digest) = result.digest {
                 println!("\n📦 Image digest: {}", digest);
@@ -222,16 +528,39 @@ async fn main() -> Result<()> {
             repo,
             git_ref,
             token,
+            keep_git_dir,
             dockerfile,
             tag,
+            no_push,
+            output,
+            annotation,
             build_arg,
+            secret,
+            ssh,
+            ssh_key,
             target,
             platform,
             registry_host,
             registry_user,
             registry_password,
+            registry_token,
+            insecure_registry,
             no_cache,
+            no_cache_filter,
             pull,
+            image_resolve_mode,
+            forward_proxy_env,
+            verify_push,
+            load,
+            allow,
+            frontend,
+            network,
+            add_host,
+            build_context,
+            shm_size,
+            ulimit,
+            cgroup_parent,
+            build_hostname,
             json,
         } => {
             let mut config = BuildConfig::github(repo);
@@ -243,6 +572,7 @@ async fn main() -> Result<()> {
             if let Some(token) = token {
                 config = config.github_token(token);
             }
+            config = config.keep_git_dir(keep_git_dir);
 
             if let Some(df) = dockerfile {
                 config = config.dockerfile(df);
@@ -251,6 +581,15 @@ async fn main() -> Result<()> {
             for t in tag {
                 config = config.tag(t);
             }
+            config = config.push(!no_push);
+
+            for o in output {
+                config = config.output(Output::parse(&o)?);
+            }
+
+            for a in annotation {
+                config.annotations.push(Annotation::parse(&a)?);
+            }
 
             for arg in build_arg {
                 if let Some((key, value)) = arg.split_once('=') {
@@ -258,6 +597,18 @@ async fn main() -> Result<()> {
                 }
             }
 
+            for spec in &secret {
+                let (id, path) = parse_secret(spec)?;
+                config = config.secret_from_file(id, path);
+            }
+            for spec in ssh {
+                config = config.ssh(spec);
+            }
+            for spec in &ssh_key {
+                let (id, path) = parse_ssh_key(spec)?;
+                config = config.ssh_key(id, std::fs::read(path)?);
+            }
+
             if let Some(t) = target {
                 config = config.target(t);
             }
@@ -269,17 +620,61 @@ async fn main() -> Result<()> {
                 }
             }
 
-            if let (Some(host), Some(user), Some(pass)) =
-                (registry_host, registry_user, registry_password)
-            {
-                config = config.registry_auth(RegistryAuth {
-                    host,
-                    username: user,
-                    password: pass,
-                });
+            match (registry_host, registry_user, registry_password, registry_token) {
+                (Some(host), _, _, Some(token)) => {
+                    config = config.registry_auth(RegistryAuth::identity_token(host, token));
+                }
+                (Some(host), Some(user), Some(pass), None) => {
+                    config = config.registry_auth(RegistryAuth::basic(host, user, pass));
+                }
+                _ => {}
+            }
+            for host in insecure_registry {
+                config = config.insecure_registry(host);
+            }
+
+            config = config
+                .no_cache(no_cache)
+                .no_cache_filter(no_cache_filter)
+                .pull(pull)
+                .forward_proxy_env(forward_proxy_env)
+                .verify_push(verify_push)
+                .frontend(frontend)
+                .network(network)
+                .access_log(cli.access_log);
+
+            if let Some(mode) = image_resolve_mode {
+                config = config.image_resolve_mode(mode);
+            }
+            for entitlement in allow {
+                config = config.allow(entitlement);
             }
 
-            config = config.no_cache(no_cache).pull(pull);
+            for entry in add_host {
+                let (host, ip) = parse_add_host(&entry)?;
+                config = config.add_host(host, ip);
+            }
+
+            for entry in build_context {
+                let (name, context) = parse_build_context(&entry)?;
+                config = config.named_context(name, context);
+            }
+
+            if let Some(bytes) = shm_size {
+                config = config.shm_size(bytes);
+            }
+
+            for limit in ulimit {
+                config = config.ulimit(limit);
+            }
+
+            if let Some(parent) = cgroup_parent {
+                config = config.cgroup_parent(parent);
+            }
+
+            if let Some(hostname) = build_hostname {
+                config = config.build_hostname(hostname);
+            }
 
             let progress: Box<dyn buildkit_client::progress::ProgressHandler> = if json {
                 Box::new(JsonProgressHandler::new())
@@ -287,7 +682,13 @@ async fn main() -> Result<()> {
                 Box::new(ConsoleProgressHandler::new(cli.verbose))
             };
 
-            let result = client.build(config, Some(progress)).await?;
+            let result = if load {
+                let (result, loaded) = client.build_and_load(config, Some(progress)).await?;
+                println!("\n🐳 {}", loaded);
+                result
+            } else {
+                client.build(config, Some(progress)).await?
+            };
 
             if let Some(digest) = result.digest {
                 println!("\n📦 Image digest: {}", digest);
@@ -302,3 +703,54 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// Parse a `uid[:gid]` user specification (gid defaults to uid)
+fn parse_context_user(spec: &str) -> Result<ContextUser> {
+    let (uid, gid) = match spec.split_once(':') {
+        Some((uid, gid)) => (uid.parse()?, gid.parse()?),
+        None => {
+            let uid = spec.parse()?;
+            (uid, uid)
+        }
+    };
+    Ok(ContextUser::new(uid, gid))
+}
+
+/// Parse a `--secret id=ID,src=PATH` flag (`source` is accepted for `src`)
+fn parse_secret(spec: &str) -> Result<(&str, &str)> {
+    let (mut id, mut src) = (None, None);
+    for field in spec.split(',') {
+        match field.split_once('=') {
+            Some(("id", value)) => id = Some(value),
+            Some(("src" | "source", value)) => src = Some(value),
+            _ => anyhow::bail!("invalid --secret field {:?} in {:?}", field, spec),
+        }
+    }
+    match (id, src) {
+        (Some(id), Some(src)) if !id.is_empty() && !src.is_empty() => Ok((id, src)),
+        _ => anyhow::bail!("invalid --secret {:?}, expected id=ID,src=PATH", spec),
+    }
+}
+
+/// Parse a `--ssh-key ID=PATH` flag
+fn parse_ssh_key(spec: &str) -> Result<(&str, &str)> {
+    spec.split_once('=')
+        .filter(|(id, path)| !id.is_empty() && !path.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("invalid --ssh-key {:?}, expected ID=PATH", spec))
+}
+
+/// Parse a `host:ip` mapping; the IP may be IPv6 and contain colons
+fn parse_add_host(spec: &str) -> Result<(&str, &str)> {
+    spec.split_once(':')
+        .filter(|(host, ip)| !host.is_empty() && !ip.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("invalid --add-host {:?}, expected host:ip", spec))
+}
+
+/// Parse a `--build-context name=value` flag
+fn parse_build_context(spec: &str) -> Result<(&str, NamedContext)> {
+    let (name, value) = spec
+        .split_once('=')
+        .filter(|(name, _)| !name.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("invalid --build-context {:?}, expected name=value", spec))?;
+    Ok((name, value.parse()?))
+}
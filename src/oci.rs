@@ -0,0 +1,218 @@
+//! OCI image descriptors, indexes, manifests and configs
+//!
+//! Only the fields the client inspects are modelled; unknown fields are
+//! ignored when decoding.
+
+use crate::builder::Platform;
+use crate::error::{Error, Result};
+use crate::gateway::nullable;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// OCI image index media type
+pub const MEDIA_TYPE_IMAGE_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+
+/// OCI image manifest media type
+pub const MEDIA_TYPE_IMAGE_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+
+/// Docker manifest list media type
+pub const MEDIA_TYPE_DOCKER_MANIFEST_LIST: &str =
+    "application/vnd.docker.distribution.manifest.list.v2+json";
+
+/// Docker image manifest media type
+pub const MEDIA_TYPE_DOCKER_MANIFEST: &str = "application/vnd.docker.distribution.manifest.v2+json";
+
+/// Exporter response key holding the base64-encoded JSON descriptor of the
+/// exported image (or index)
+pub const DESCRIPTOR_RESPONSE_KEY: &str = "containerimage.descriptor";
+
+/// Content descriptor
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Descriptor {
+    /// Media type of the referenced content
+    pub media_type: String,
+    /// Content digest
+    pub digest: String,
+    /// Content size in bytes
+    pub size: u64,
+    /// Descriptor annotations
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub annotations: HashMap<String, String>,
+    /// Platform of an image manifest listed in an index
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
+}
+
+impl Descriptor {
+    /// Check whether the descriptor points at an index (or manifest list)
+    pub fn is_index(&self) -> bool {
+        matches!(
+            self.media_type.as_str(),
+            MEDIA_TYPE_IMAGE_INDEX | MEDIA_TYPE_DOCKER_MANIFEST_LIST
+        )
+    }
+
+    /// Read the exported image descriptor from a solve's exporter response
+    pub fn from_exporter_response(response: &HashMap<String, String>) -> Result<Option<Self>> {
+        let Some(encoded) = response.get(DESCRIPTOR_RESPONSE_KEY) else {
+            return Ok(None);
+        };
+        let json = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|e| Error::oci(format!("invalid descriptor encoding: {}", e)))?;
+        decode_json(&json, "descriptor").map(Some)
+    }
+}
+
+/// Image index (or Docker manifest list)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageIndex {
+    /// Media type, when the index declares one
+    #[serde(default)]
+    pub media_type: Option<String>,
+    /// Manifests listed in the index
+    #[serde(default)]
+    pub manifests: Vec<Descriptor>,
+    /// Index annotations
+    #[serde(default)]
+    pub annotations: HashMap<String, String>,
+}
+
+impl ImageIndex {
+    /// Create an OCI image index listing `manifests`
+    pub fn new(manifests: Vec<Descriptor>) -> Self {
+        Self {
+            media_type: Some(MEDIA_TYPE_IMAGE_INDEX.to_string()),
+            manifests,
+            annotations: HashMap::new(),
+        }
+    }
+
+    /// Decode an index from JSON
+    pub fn from_json(json: &[u8]) -> Result<Self> {
+        decode_json(json, "image index")
+    }
+
+    /// Encode the index as JSON, ready to be pushed to a registry
+    pub fn to_json(&self) -> Result<Vec<u8>> {
+        let mut index = serde_json::json!({
+            "schemaVersion": 2,
+            "manifests": self.manifests,
+        });
+        if let Some(media_type) = &self.media_type {
+            index["mediaType"] = media_type.clone().into();
+        }
+        if !self.annotations.is_empty() {
+            index["annotations"] = serde_json::json!(self.annotations);
+        }
+        serde_json::to_vec(&index)
+            .map_err(|e| Error::oci(format!("failed to encode image index: {}", e)))
+    }
+}
+
+/// Image manifest
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageManifest {
+    /// Media type, when the manifest declares one
+    #[serde(default)]
+    pub media_type: Option<String>,
+    /// Image configuration
+    pub config: Descriptor,
+    /// Layers (or attestation documents)
+    #[serde(default)]
+    pub layers: Vec<Descriptor>,
+    /// Manifest annotations
+    #[serde(default)]
+    pub annotations: HashMap<String, String>,
+}
+
+impl ImageManifest {
+    /// Decode a manifest from JSON
+    pub fn from_json(json: &[u8]) -> Result<Self> {
+        decode_json(json, "image manifest")
+    }
+}
+
+/// Image configuration (the `config` blob of a manifest)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct ImageConfig {
+    /// CPU architecture
+    #[serde(default)]
+    pub architecture: String,
+    /// Operating system
+    #[serde(default)]
+    pub os: String,
+    /// CPU variant
+    #[serde(default)]
+    pub variant: Option<String>,
+    /// Creation time (RFC 3339)
+    #[serde(default)]
+    pub created: Option<String>,
+    /// Defaults for containers run from the image
+    #[serde(default)]
+    pub config: ContainerConfig,
+}
+
+impl ImageConfig {
+    /// Decode an image config from JSON
+    pub fn from_json(json: &[u8]) -> Result<Self> {
+        decode_json(json, "image config")
+    }
+}
+
+/// Execution defaults of an image
+///
+/// `None` and an empty list differ for the entrypoint and command: an
+/// empty list resets the value inherited from the base image.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ContainerConfig {
+    /// User (and group) processes run as
+    #[serde(default)]
+    pub user: String,
+    /// Environment as `KEY=value` entries
+    #[serde(default, deserialize_with = "nullable")]
+    pub env: Vec<String>,
+    /// Entrypoint
+    #[serde(default)]
+    pub entrypoint: Option<Vec<String>>,
+    /// Default arguments (or command without entrypoint)
+    #[serde(default)]
+    pub cmd: Option<Vec<String>>,
+    /// Working directory
+    #[serde(default)]
+    pub working_dir: String,
+    /// Image labels
+    #[serde(default, deserialize_with = "nullable")]
+    pub labels: HashMap<String, String>,
+    /// Exposed ports (e.g., `80/tcp`)
+    #[serde(default, deserialize_with = "nullable")]
+    pub exposed_ports: HashMap<String, serde_json::Value>,
+    /// Volumes
+    #[serde(default, deserialize_with = "nullable")]
+    pub volumes: HashMap<String, serde_json::Value>,
+    /// Signal stopping the container
+    #[serde(default)]
+    pub stop_signal: String,
+}
+
+impl ContainerConfig {
+    /// Value of the environment variable `name`
+    pub fn env_var(&self, name: &str) -> Option<&str> {
+        self.env.iter().find_map(|entry| {
+            entry
+                .split_once('=')
+                .filter(|(key, _)| *key == name)
+                .map(|(_, value)| value)
+        })
+    }
+}
+
+fn decode_json<'a, T: Deserialize<'a>>(json: &'a [u8], what: &str) -> Result<T> {
+    serde_json::from_slice(json).map_err(|e| Error::oci(format!("invalid {}: {}", what, e)))
+}
+
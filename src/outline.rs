@@ -0,0 +1,136 @@
+//! Dockerfile outlines through the `frontend.outline` subrequest
+//!
+//! The outline of a build target lists what a caller can or must provide
+//! to build it: the build arguments it reads (with their defaults and the
+//! comments documenting them), the secrets and SSH agents its `RUN` steps
+//! mount, and its cache mounts. Nothing is built to produce it.
+
+use crate::builder::BuildConfig;
+use crate::client::BuildKitClient;
+use crate::error::{Error, Result};
+use crate::gateway::{nullable, SourceLocation, RESULT_JSON_KEY};
+use serde::Deserialize;
+
+/// Subrequest ID of Dockerfile outlines
+pub const OUTLINE_REQUEST_ID: &str = "frontend.outline";
+
+/// Inputs of a build target
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct Outline {
+    /// Target stage (empty for the last stage)
+    #[serde(default)]
+    pub name: String,
+    /// Comment documenting the target stage
+    #[serde(default)]
+    pub description: String,
+    /// Build arguments the target reads, in Dockerfile order
+    #[serde(default, deserialize_with = "nullable")]
+    pub args: Vec<OutlineArg>,
+    /// Secrets mounted by the target's steps
+    #[serde(default, deserialize_with = "nullable")]
+    pub secrets: Vec<OutlineSecret>,
+    /// SSH agents mounted by the target's steps
+    #[serde(default, deserialize_with = "nullable")]
+    pub ssh: Vec<OutlineSsh>,
+    /// Cache mounts of the target's steps
+    #[serde(default, deserialize_with = "nullable")]
+    pub cache: Vec<OutlineCacheMount>,
+}
+
+impl Outline {
+    /// Decode the `result.json` answer of an outline subrequest
+    pub fn from_json(json: &[u8]) -> Result<Self> {
+        serde_json::from_slice(json)
+            .map_err(|e| Error::frontend(format!("invalid outline result: {}", e)))
+    }
+
+    /// Look up a build argument by name
+    pub fn arg(&self, name: &str) -> Option<&OutlineArg> {
+        self.args.iter().find(|arg| arg.name == name)
+    }
+
+    /// IDs of the secrets the build fails without
+    pub fn required_secrets(&self) -> impl Iterator<Item = &str> {
+        self.secrets
+            .iter()
+            .filter(|secret| secret.required)
+            .map(|secret| secret.name.as_str())
+    }
+
+    /// IDs of the SSH agents the build fails without
+    pub fn required_ssh(&self) -> impl Iterator<Item = &str> {
+        self.ssh
+            .iter()
+            .filter(|ssh| ssh.required)
+            .map(|ssh| ssh.name.as_str())
+    }
+}
+
+/// A build argument declared with `ARG`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct OutlineArg {
+    /// Argument name
+    pub name: String,
+    /// Comment documenting the argument
+    #[serde(default)]
+    pub description: String,
+    /// Default value, or the value set by the build's configuration
+    #[serde(default)]
+    pub value: String,
+    /// Where the argument is declared
+    #[serde(default)]
+    pub location: Option<SourceLocation>,
+}
+
+/// A secret mounted with `RUN --mount=type=secret`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct OutlineSecret {
+    /// Secret ID
+    pub name: String,
+    /// Whether the mount sets `required=true`
+    #[serde(default)]
+    pub required: bool,
+    /// Where the secret is first mounted
+    #[serde(default)]
+    pub location: Option<SourceLocation>,
+}
+
+/// An SSH agent mounted with `RUN --mount=type=ssh`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct OutlineSsh {
+    /// Agent ID (`default` unless set with `id=`)
+    pub name: String,
+    /// Whether the mount sets `required=true`
+    #[serde(default)]
+    pub required: bool,
+    /// Where the agent is first mounted
+    #[serde(default)]
+    pub location: Option<SourceLocation>,
+}
+
+/// A cache mount of a `RUN` step
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct OutlineCacheMount {
+    /// Cache ID (the target path unless set with `id=`)
+    #[serde(rename = "ID")]
+    pub id: String,
+    /// Where the cache is first mounted
+    #[serde(default)]
+    pub location: Option<SourceLocation>,
+}
+
+impl BuildKitClient {
+    /// Describe the inputs of the target of `config` without building it
+    ///
+    /// The target, build arguments and Dockerfile are taken from `config`
+    /// as for a build, so values it sets show up in [`OutlineArg::value`].
+    pub async fn outline(&mut self, config: BuildConfig) -> Result<Outline> {
+        let metadata = self.frontend_subrequest(config, OUTLINE_REQUEST_ID).await?;
+        let json = metadata.get(RESULT_JSON_KEY).ok_or_else(|| {
+            Error::frontend(
+                "the frontend did not answer the outline request; it may predate outlines",
+            )
+        })?;
+        Outline::from_json(json.as_bytes())
+    }
+}
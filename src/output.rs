@@ -0,0 +1,679 @@
+//! Build output (exporter) configuration
+//!
+//! Mirrors the semantics of `buildctl --output`: a single build may carry any
+//! number of outputs, each of which becomes one BuildKit exporter.
+
+use crate::error::{Error, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::{AsyncWrite, DuplexStream};
+use tokio::sync::Mutex;
+
+/// Boxed async writer receiving exporter output
+pub type BoxedWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// Caller-supplied writer receiving an exported tarball
+///
+/// The writer is consumed by the first export stream that uses it.
+#[derive(Clone)]
+pub struct ExportWriter {
+    inner: Arc<Mutex<Option<BoxedWriter>>>,
+}
+
+impl ExportWriter {
+    /// Wrap an async writer
+    pub fn new(writer: impl AsyncWrite + Send + Unpin + 'static) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Some(Box::new(writer)))),
+        }
+    }
+
+    async fn take(&self) -> Option<BoxedWriter> {
+        self.inner.lock().await.take()
+    }
+}
+
+impl fmt::Debug for ExportWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ExportWriter(..)")
+    }
+}
+
+/// Where an exported tarball is written on the client
+#[derive(Debug, Clone)]
+pub enum ExportDest {
+    /// Write to a file, creating parent directories as needed
+    File(PathBuf),
+    /// Write into a caller-supplied writer
+    Writer(ExportWriter),
+}
+
+impl ExportDest {
+    /// Open the destination for writing
+    ///
+    /// A [`ExportDest::Writer`] can only be opened once.
+    pub async fn open(&self) -> Result<BoxedWriter> {
+        match self {
+            ExportDest::File(path) => {
+                if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                let file = tokio::fs::File::create(path).await?;
+                Ok(Box::new(file))
+            }
+            ExportDest::Writer(writer) => writer
+                .take()
+                .await
+                .ok_or_else(|| Error::session("export writer has already been used")),
+        }
+    }
+}
+
+/// Layer compression algorithm
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// No compression
+    Uncompressed,
+    /// gzip (BuildKit's default)
+    Gzip,
+    /// eStargz, lazily pullable gzip (implies OCI media types)
+    Estargz,
+    /// zstd, faster to decompress (implies OCI media types)
+    Zstd,
+}
+
+impl Compression {
+    /// Exporter attribute value
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Compression::Uncompressed => "uncompressed",
+            Compression::Gzip => "gzip",
+            Compression::Estargz => "estargz",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    /// Highest accepted compression level
+    pub fn max_level(&self) -> u32 {
+        match self {
+            Compression::Uncompressed => 0,
+            Compression::Gzip | Compression::Estargz => 9,
+            Compression::Zstd => 22,
+        }
+    }
+}
+
+impl std::str::FromStr for Compression {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "uncompressed" => Ok(Compression::Uncompressed),
+            "gzip" => Ok(Compression::Gzip),
+            "estargz" => Ok(Compression::Estargz),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(Error::InvalidOutput(format!("unknown compression {:?}", s))),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Layer compression settings of an image exporter
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LayerCompression {
+    /// Compression algorithm (BuildKit defaults to gzip)
+    pub compression: Option<Compression>,
+    /// Compression level (gzip/estargz: 0-9, zstd: 0-22)
+    pub level: Option<u32>,
+    /// Recompress layers that already exist with a different compression
+    pub force: bool,
+}
+
+impl LayerCompression {
+    /// Check that the level is valid for the algorithm
+    pub fn validate(&self) -> Result<()> {
+        if let Some(level) = self.level {
+            let compression = self.compression.unwrap_or(Compression::Gzip);
+            if level > compression.max_level() {
+                return Err(Error::InvalidOutput(format!(
+                    "compression level {} out of range for {} (max {})",
+                    level,
+                    compression,
+                    compression.max_level()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(&self, attrs: &mut HashMap<String, String>) {
+        if let Some(compression) = self.compression {
+            attrs.insert("compression".to_string(), compression.to_string());
+        }
+        if let Some(level) = self.level {
+            attrs.insert("compression-level".to_string(), level.to_string());
+        }
+        if self.force {
+            attrs.insert("force-compression".to_string(), "true".to_string());
+        }
+    }
+}
+
+/// Where an image annotation is attached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AnnotationScope {
+    /// The image manifest of every platform (default)
+    Manifest,
+    /// The image index of a multi-platform image
+    Index,
+    /// The manifest descriptors inside the index
+    ManifestDescriptor,
+    /// The index descriptor (OCI layout exports only)
+    IndexDescriptor,
+}
+
+impl AnnotationScope {
+    /// Exporter attribute prefix for this scope
+    pub fn attr_prefix(&self) -> &'static str {
+        match self {
+            AnnotationScope::Manifest => "annotation",
+            AnnotationScope::Index => "annotation-index",
+            AnnotationScope::ManifestDescriptor => "annotation-manifest-descriptor",
+            AnnotationScope::IndexDescriptor => "annotation-index-descriptor",
+        }
+    }
+}
+
+impl std::str::FromStr for AnnotationScope {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "manifest" => Ok(AnnotationScope::Manifest),
+            "index" => Ok(AnnotationScope::Index),
+            "manifest-descriptor" => Ok(AnnotationScope::ManifestDescriptor),
+            "index-descriptor" => Ok(AnnotationScope::IndexDescriptor),
+            _ => Err(Error::InvalidConfig(format!("unknown annotation scope {:?}", s))),
+        }
+    }
+}
+
+/// An OCI annotation added to exported images
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    /// Where the annotation is attached
+    pub scope: AnnotationScope,
+    /// Annotation key (e.g., "org.opencontainers.image.source")
+    pub key: String,
+    /// Annotation value
+    pub value: String,
+}
+
+impl Annotation {
+    /// Create an annotation with the given scope
+    pub fn new(scope: AnnotationScope, key: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            scope,
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+
+    /// Parse a `docker buildx --annotation` style string
+    ///
+    /// Accepts `<key>=<value>` (manifest scope) or `<scope>:<key>=<value>`,
+    /// e.g. `index:org.opencontainers.image.version=1.2.0`.
+    pub fn parse(s: &str) -> Result<Self> {
+        let (target, value) = s
+            .split_once('=')
+            .ok_or_else(|| Error::InvalidConfig(format!("invalid annotation {:?}", s)))?;
+        // Keys such as "org.opencontainers.image.url" never contain a colon
+        let (scope, key) = match target.split_once(':') {
+            Some((scope, key)) => (scope.parse()?, key),
+            None => (AnnotationScope::Manifest, target),
+        };
+        if key.is_empty() {
+            return Err(Error::InvalidConfig(format!("invalid annotation {:?}", s)));
+        }
+        Ok(Self::new(scope, key, value))
+    }
+
+    /// Exporter attribute key for this annotation
+    pub fn attr_key(&self) -> String {
+        format!("{}.{}", self.scope.attr_prefix(), self.key)
+    }
+}
+
+/// Options for exporters that produce a tarball (`type=oci`, `type=docker`, `type=tar`)
+#[derive(Debug, Clone)]
+pub struct ArchiveOutput {
+    /// Where the tarball is written
+    pub dest: ExportDest,
+    /// Image names recorded in the archive (ignored by `type=tar`)
+    pub names: Vec<String>,
+    /// Layer compression (ignored by `type=tar`)
+    pub compression: LayerCompression,
+    /// Additional raw exporter attributes
+    pub attrs: HashMap<String, String>,
+}
+
+impl ArchiveOutput {
+    /// Create an archive output for the given destination
+    pub fn new(dest: ExportDest) -> Self {
+        Self {
+            dest,
+            names: Vec::new(),
+            compression: LayerCompression::default(),
+            attrs: HashMap::new(),
+        }
+    }
+
+    /// Set the layer compression algorithm
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression.compression = Some(compression);
+        self
+    }
+
+    /// Set the layer compression level
+    pub fn compression_level(mut self, level: u32) -> Self {
+        self.compression.level = Some(level);
+        self
+    }
+
+    /// Recompress existing layers that use a different compression
+    pub fn force_compression(mut self, force: bool) -> Self {
+        self.compression.force = force;
+        self
+    }
+
+    /// Add an image name recorded in the archive
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.names.push(name.into());
+        self
+    }
+
+    /// Set a raw exporter attribute
+    pub fn attr(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.attrs.insert(key.into(), value.into());
+        self
+    }
+}
+
+/// Image exporter (`type=image`) options
+#[derive(Debug, Clone, Default)]
+pub struct ImageOutput {
+    /// Image names to assign (e.g., "localhost:5000/app:latest")
+    pub names: Vec<String>,
+    /// Push the image to its registry after the build
+    pub push: bool,
+    /// Layer compression
+    pub compression: LayerCompression,
+    /// Use OCI media types instead of Docker schema2 for manifests and configs
+    ///
+    /// `None` leaves the choice to BuildKit, which picks OCI media types
+    /// automatically for zstd and estargz layers.
+    pub oci_mediatypes: Option<bool>,
+    /// Unpack the image into the worker's snapshotter (containerd worker)
+    ///
+    /// Needed before the image can be run with ctr or nerdctl on the
+    /// BuildKit node.
+    pub unpack: bool,
+    /// Store the image in the worker's image store (containerd worker)
+    ///
+    /// `None` leaves the choice to BuildKit, which stores images by default.
+    pub store: Option<bool>,
+    /// Additional raw exporter attributes
+    pub attrs: HashMap<String, String>,
+}
+
+impl ImageOutput {
+    /// Create an image output with no names that is not pushed
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an image name
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.names.push(name.into());
+        self
+    }
+
+    /// Set whether the image is pushed
+    pub fn push(mut self, push: bool) -> Self {
+        self.push = push;
+        self
+    }
+
+    /// Set the layer compression algorithm
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression.compression = Some(compression);
+        self
+    }
+
+    /// Set the layer compression level
+    pub fn compression_level(mut self, level: u32) -> Self {
+        self.compression.level = Some(level);
+        self
+    }
+
+    /// Recompress existing layers that use a different compression
+    pub fn force_compression(mut self, force: bool) -> Self {
+        self.compression.force = force;
+        self
+    }
+
+    /// Set whether manifests use OCI media types
+    pub fn oci_mediatypes(mut self, enabled: bool) -> Self {
+        self.oci_mediatypes = Some(enabled);
+        self
+    }
+
+    /// Set whether the image is unpacked after export
+    pub fn unpack(mut self, unpack: bool) -> Self {
+        self.unpack = unpack;
+        self
+    }
+
+    /// Set whether the image is kept in the worker's image store
+    pub fn store(mut self, store: bool) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Set a raw exporter attribute
+    pub fn attr(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.attrs.insert(key.into(), value.into());
+        self
+    }
+}
+
+/// A single build output
+#[derive(Debug, Clone)]
+pub enum Output {
+    /// Export an image (`type=image`)
+    Image(ImageOutput),
+    /// Write the final filesystem to a local directory (`type=local`)
+    Local {
+        /// Destination directory on the client
+        dest: PathBuf,
+    },
+    /// Stream an OCI image layout tarball back to the client (`type=oci`)
+    Oci(ArchiveOutput),
+    /// Stream a `docker load` compatible tarball back to the client (`type=docker`)
+    Docker(ArchiveOutput),
+    /// Stream the final filesystem as a plain tarball back to the client (`type=tar`)
+    Tar(ArchiveOutput),
+}
+
+impl Output {
+    /// Create an image output that only names the image in the worker's store
+    pub fn image(name: impl Into<String>) -> Self {
+        Output::Image(ImageOutput::new().name(name))
+    }
+
+    /// Create an image output that stores the image in the worker's
+    /// containerd image store under `names`, without pushing it
+    ///
+    /// The image is unpacked into the snapshotter, so on nodes running both
+    /// buildkitd and containerd (such as a single-node Kubernetes builder)
+    /// it can be run right away. It lands in the namespace the containerd
+    /// worker is configured with (`buildkit` unless `--containerd-worker-namespace`
+    /// says otherwise; `k8s.io` for images the kubelet should see). Without
+    /// names, the output inherits the configured tags.
+    pub fn containerd<I, S>(names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Output::Image(ImageOutput {
+            names: names.into_iter().map(Into::into).collect(),
+            unpack: true,
+            store: Some(true),
+            ..Default::default()
+        })
+    }
+
+    /// Create an image output that pushes the given name
+    pub fn registry(name: impl Into<String>) -> Self {
+        Output::Image(ImageOutput::new().name(name).push(true))
+    }
+
+    /// Create a local directory output
+    pub fn local(dest: impl Into<PathBuf>) -> Self {
+        Output::Local { dest: dest.into() }
+    }
+
+    /// Create an OCI tarball output written to a file
+    pub fn oci(dest: impl Into<PathBuf>) -> Self {
+        Output::Oci(ArchiveOutput::new(ExportDest::File(dest.into())))
+    }
+
+    /// Create an OCI tarball output written into an async writer
+    pub fn oci_writer(writer: impl AsyncWrite + Send + Unpin + 'static) -> Self {
+        Output::Oci(ArchiveOutput::new(ExportDest::Writer(ExportWriter::new(writer))))
+    }
+
+    /// Create a Docker tarball output written to a file
+    pub fn docker(dest: impl Into<PathBuf>) -> Self {
+        Output::Docker(ArchiveOutput::new(ExportDest::File(dest.into())))
+    }
+
+    /// Create a Docker tarball output written into an async writer
+    pub fn docker_writer(writer: impl AsyncWrite + Send + Unpin + 'static) -> Self {
+        Output::Docker(ArchiveOutput::new(ExportDest::Writer(ExportWriter::new(writer))))
+    }
+
+    /// Create a filesystem tarball output written to a file
+    pub fn tar(dest: impl Into<PathBuf>) -> Self {
+        Output::Tar(ArchiveOutput::new(ExportDest::File(dest.into())))
+    }
+
+    /// Create a filesystem tarball output written into an async writer
+    pub fn tar_writer(writer: impl AsyncWrite + Send + Unpin + 'static) -> Self {
+        Output::Tar(ArchiveOutput::new(ExportDest::Writer(ExportWriter::new(writer))))
+    }
+
+    /// Create a filesystem tarball output read back through an async reader
+    ///
+    /// The reader must be drained concurrently with the build (e.g. from a
+    /// spawned upload task); once `buffer_size` bytes are pending, the export
+    /// waits for the reader.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use buildkit_client::{BuildConfig, BuildKitClient, Output};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let mut client = BuildKitClient::connect("http://localhost:1234").await?;
+    ///
+    ///     let (output, mut reader) = Output::tar_reader(1024 * 1024);
+    ///     let upload = tokio::spawn(async move {
+    ///         let mut sink = tokio::io::sink();
+    ///         tokio::io::copy(&mut reader, &mut sink).await
+    ///     });
+    ///
+    ///     client.build(BuildConfig::local(".").output(output), None).await?;
+    ///     upload.await??;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn tar_reader(buffer_size: usize) -> (Self, DuplexStream) {
+        let (writer, reader) = tokio::io::duplex(buffer_size);
+        (Self::tar_writer(writer), reader)
+    }
+
+    /// Client-side tarball destination, if this output streams back through the session
+    pub fn archive_dest(&self) -> Option<&ExportDest> {
+        match self {
+            Output::Oci(archive) | Output::Docker(archive) | Output::Tar(archive) => {
+                Some(&archive.dest)
+            }
+            _ => None,
+        }
+    }
+
+    /// BuildKit exporter type for this output
+    pub fn exporter_type(&self) -> &'static str {
+        match self {
+            Output::Image(_) => "image",
+            Output::Local { .. } => "local",
+            Output::Oci(_) => "oci",
+            Output::Docker(_) => "docker",
+            Output::Tar(_) => "tar",
+        }
+    }
+
+    /// Exporter attributes sent to BuildKit for this output
+    pub fn attrs(&self) -> HashMap<String, String> {
+        match self {
+            Output::Image(image) => {
+                let mut attrs = image.attrs.clone();
+                if !image.names.is_empty() {
+                    attrs.insert("name".to_string(), image.names.join(","));
+                }
+                if image.push {
+                    attrs.insert("push".to_string(), "true".to_string());
+                }
+                image.compression.apply(&mut attrs);
+                if let Some(enabled) = image.oci_mediatypes {
+                    attrs.insert("oci-mediatypes".to_string(), enabled.to_string());
+                }
+                if image.unpack {
+                    attrs.insert("unpack".to_string(), "true".to_string());
+                }
+                if let Some(store) = image.store {
+                    attrs.insert("store".to_string(), store.to_string());
+                }
+                attrs
+            }
+            // The destination is resolved client-side by the session
+            Output::Local { .. } => HashMap::new(),
+            Output::Oci(archive) | Output::Docker(archive) => {
+                let mut attrs = archive.attrs.clone();
+                if !archive.names.is_empty() {
+                    attrs.insert("name".to_string(), archive.names.join(","));
+                }
+                archive.compression.apply(&mut attrs);
+                attrs
+            }
+            Output::Tar(archive) => archive.attrs.clone(),
+        }
+    }
+
+    /// Parse a `buildctl --output` style string
+    ///
+    /// Supported forms:
+    /// - `type=image,name=<ref>[,name=<ref>][,push=true][,<key>=<value>]`
+    /// - `type=registry,name=<ref>` (image output with `push=true`)
+    /// - `type=local,dest=<path>`
+    /// - `type=oci,dest=<file>[,name=<ref>]`
+    /// - `type=docker,dest=<file>[,name=<ref>]`
+    /// - `type=tar,dest=<file>`
+    ///
+    /// Image and image tarball outputs also accept `compression=<algorithm>`,
+    /// `compression-level=<n>` and `force-compression=true`. Image outputs
+    /// accept `oci-mediatypes=true|false`, `unpack=true` and
+    /// `store=true|false`.
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut output_type = None;
+        let mut names = Vec::new();
+        let mut dest = None;
+        let mut push = None;
+        let mut compression = LayerCompression::default();
+        let mut oci_mediatypes = None;
+        let mut unpack = false;
+        let mut store = None;
+        let mut attrs = HashMap::new();
+
+        for field in s.split(',').filter(|f| !f.is_empty()) {
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| Error::InvalidOutput(s.to_string()))?;
+            match key {
+                "type" => output_type = Some(value.to_string()),
+                "name" => names.push(value.to_string()),
+                "dest" => dest = Some(PathBuf::from(value)),
+                "push" => push = Some(parse_bool(value).ok_or_else(|| Error::InvalidOutput(s.to_string()))?),
+                "compression" => compression.compression = Some(value.parse()?),
+                "compression-level" => {
+                    compression.level =
+                        Some(value.parse().map_err(|_| Error::InvalidOutput(s.to_string()))?)
+                }
+                "force-compression" => {
+                    compression.force =
+                        parse_bool(value).ok_or_else(|| Error::InvalidOutput(s.to_string()))?
+                }
+                "unpack" => {
+                    unpack = parse_bool(value).ok_or_else(|| Error::InvalidOutput(s.to_string()))?
+                }
+                "store" => {
+                    store =
+                        Some(parse_bool(value).ok_or_else(|| Error::InvalidOutput(s.to_string()))?)
+                }
+                "oci-mediatypes" => {
+                    oci_mediatypes =
+                        Some(parse_bool(value).ok_or_else(|| Error::InvalidOutput(s.to_string()))?)
+                }
+                _ => {
+                    attrs.insert(key.to_string(), value.to_string());
+                }
+            }
+        }
+
+        match output_type.as_deref() {
+            Some(kind @ ("image" | "registry")) => {
+                compression.validate()?;
+                Ok(Output::Image(ImageOutput {
+                    names,
+                    push: push.unwrap_or(kind == "registry"),
+                    compression,
+                    oci_mediatypes,
+                    unpack,
+                    store,
+                    attrs,
+                }))
+            }
+            Some("local") => {
+                let dest = dest.ok_or_else(|| Error::InvalidOutput(s.to_string()))?;
+                Ok(Output::Local { dest })
+            }
+            Some(kind @ ("oci" | "docker" | "tar")) => {
+                let dest = dest.ok_or_else(|| Error::InvalidOutput(s.to_string()))?;
+                compression.validate()?;
+                // The docker exporter also honours the toggle; pass it through untyped
+                if let Some(enabled) = oci_mediatypes {
+                    attrs.insert("oci-mediatypes".to_string(), enabled.to_string());
+                }
+                let archive = ArchiveOutput {
+                    dest: ExportDest::File(dest),
+                    names,
+                    compression,
+                    attrs,
+                };
+                Ok(match kind {
+                    "oci" => Output::Oci(archive),
+                    "docker" => Output::Docker(archive),
+                    _ => Output::Tar(archive),
+                })
+            }
+            _ => Err(Error::InvalidOutput(s.to_string())),
+        }
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" | "1" => Some(true),
+        "false" | "0" => Some(false),
+        _ => None,
+    }
+}
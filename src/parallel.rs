@@ -0,0 +1,223 @@
+//! Parallel per-platform builds
+//!
+//! A multi-platform solve builds every platform on a single BuildKit worker,
+//! emulating the foreign ones. On a fleet of native builders it is much
+//! faster to solve each platform on its own worker, concurrently, push every
+//! platform image by digest and assemble the image index on the client.
+//! [`BuildKitClient::build_per_platform`] does exactly that.
+
+use crate::attest::REFERENCE_TYPE_ANNOTATION;
+use crate::builder::{BuildConfig, Platform};
+use crate::client::BuildKitClient;
+use crate::error::{Error, Result};
+use crate::oci::{Descriptor, ImageIndex, MEDIA_TYPE_IMAGE_INDEX};
+use crate::output::{ImageOutput, Output};
+use crate::registry::{split_tag, verify_manifest, RegistryContent};
+use crate::session::content::read_blob;
+use crate::solve::BuildResult;
+use crate::task::spawn_named_in;
+use bytes::Bytes;
+use std::collections::HashMap;
+use tokio::task::JoinSet;
+
+/// Result of one platform of a per-platform build
+#[derive(Debug)]
+pub struct PlatformBuild {
+    /// Platform that was built
+    pub platform: Platform,
+    /// Result of the platform's solve; the image was pushed by digest
+    pub result: BuildResult,
+}
+
+/// Result of a per-platform build
+#[derive(Debug)]
+pub struct ParallelBuildResult {
+    /// Digest of the image index pushed to every tag
+    pub digest: String,
+    /// The assembled image index
+    pub index: ImageIndex,
+    /// Per-platform results, in the configured platform order
+    pub platforms: Vec<PlatformBuild>,
+}
+
+impl BuildKitClient {
+    /// Build every configured platform in its own solve, concurrently
+    ///
+    /// Each platform is solved on the client in `workers` registered for it,
+    /// or on this client otherwise. Platform images are pushed by digest to
+    /// the repositories of the configured tags; once all platforms are done,
+    /// the image index listing them is assembled here and pushed to every
+    /// tag using the configured registry credentials.
+    ///
+    /// If a platform fails, the solves still running are aborted.
+    pub async fn build_per_platform(
+        &self,
+        config: BuildConfig,
+        workers: HashMap<Platform, BuildKitClient>,
+    ) -> Result<ParallelBuildResult> {
+        if config.platforms.is_empty() {
+            return Err(Error::InvalidConfig(
+                "per-platform builds need at least one platform".to_string(),
+            ));
+        }
+        if config.tags.is_empty() || !config.push {
+            return Err(Error::InvalidConfig(
+                "per-platform builds push their image index; add a tag and keep push enabled"
+                    .to_string(),
+            ));
+        }
+        let repositories = repositories(&config.tags);
+
+        let mut tasks = JoinSet::new();
+        for (index, platform) in config.platforms.iter().enumerate() {
+            let mut client = workers
+                .get(platform)
+                .cloned()
+                .unwrap_or_else(|| self.clone());
+            let platform_config = platform_config(&config, platform, &repositories);
+            let platform = platform.clone();
+            let span = tracing::info_span!("platform_build", platform = %platform);
+            spawn_named_in(&mut tasks, "platform-build", span, async move {
+                let result = client.build(platform_config, None).await;
+                (index, platform, result)
+            });
+        }
+
+        // Platforms are collected as they finish, so the first failure
+        // aborts the others right away
+        let mut platforms = Vec::with_capacity(tasks.len());
+        while let Some(joined) = tasks.join_next().await {
+            let (index, platform, result) = match joined {
+                Ok(finished) => finished,
+                Err(e) => {
+                    tasks.abort_all();
+                    return Err(Error::other(format!("Platform build task failed: {}", e)));
+                }
+            };
+            match result {
+                Ok(result) => platforms.push((index, PlatformBuild { platform, result })),
+                Err(e) => {
+                    tracing::warn!(
+                        "Build for {} failed, aborting the other platforms",
+                        platform
+                    );
+                    tasks.abort_all();
+                    return Err(e);
+                }
+            }
+        }
+        platforms.sort_by_key(|(index, _)| *index);
+        let platforms: Vec<PlatformBuild> = platforms.into_iter().map(|(_, build)| build).collect();
+
+        // Nested indexes (e.g. with attestations) are read back from the
+        // first repository, where every platform was pushed
+        let store = RegistryContent::for_build(&repositories[0], &config)?;
+        let mut manifests = Vec::new();
+        for build in &platforms {
+            let descriptor = build.result.descriptor.clone().ok_or_else(|| {
+                Error::Build(format!(
+                    "the {} build returned no image descriptor",
+                    build.platform
+                ))
+            })?;
+            let nested = if descriptor.is_index() {
+                Some(ImageIndex::from_json(
+                    &read_blob(&store, &descriptor.digest).await?,
+                )?)
+            } else {
+                None
+            };
+            manifests.extend(platform_manifests(&build.platform, descriptor, nested));
+        }
+
+        let index = ImageIndex::new(manifests);
+        let json = Bytes::from(index.to_json()?);
+        let mut digest = String::new();
+        for tag in &config.tags {
+            let (_, reference) = split_tag(tag);
+            digest = RegistryContent::for_build(tag, &config)?
+                .put_manifest(reference, MEDIA_TYPE_IMAGE_INDEX, json.clone())
+                .await?;
+            tracing::info!("Pushed image index {} to {}", digest, tag);
+            if config.verify_push {
+                verify_manifest(&config, tag, reference, &digest).await?;
+            }
+        }
+
+        Ok(ParallelBuildResult {
+            digest,
+            index,
+            platforms,
+        })
+    }
+}
+
+/// Entries a platform's exported image contributes to the combined index
+///
+/// A plain manifest is listed under `platform`. When the platform exported
+/// an index (e.g. with attestations attached), its entries are carried over
+/// as they are, with the platform filled in where missing on image
+/// manifests.
+pub fn platform_manifests(
+    platform: &Platform,
+    descriptor: Descriptor,
+    nested: Option<ImageIndex>,
+) -> Vec<Descriptor> {
+    match nested {
+        Some(index) => index
+            .manifests
+            .into_iter()
+            .map(|mut manifest| {
+                let attestation = manifest.annotations.contains_key(REFERENCE_TYPE_ANNOTATION);
+                if manifest.platform.is_none() && !attestation {
+                    manifest.platform = Some(platform.clone());
+                }
+                manifest
+            })
+            .collect(),
+        None => vec![Descriptor {
+            platform: Some(platform.clone()),
+            annotations: HashMap::new(),
+            ..descriptor
+        }],
+    }
+}
+
+/// Configuration of one platform's solve
+fn platform_config(
+    config: &BuildConfig,
+    platform: &Platform,
+    repositories: &[String],
+) -> BuildConfig {
+    let mut platform_config = config.clone();
+    platform_config.platforms = vec![platform.clone()];
+    platform_config.tags = Vec::new();
+    platform_config.ref_id = config
+        .ref_id
+        .as_ref()
+        .map(|id| format!("{}-{}", id, platform.to_string().replace('/', "-")));
+    platform_config.outputs.insert(
+        0,
+        Output::Image(
+            ImageOutput {
+                names: repositories.to_vec(),
+                push: true,
+                ..Default::default()
+            }
+            .attr("push-by-digest", "true"),
+        ),
+    );
+    platform_config
+}
+
+/// Repositories of the tags, without duplicates and in tag order
+fn repositories(tags: &[String]) -> Vec<String> {
+    let mut repositories: Vec<String> = Vec::new();
+    for tag in tags {
+        let (repository, _) = split_tag(tag);
+        if !repositories.iter().any(|r| r == repository) {
+            repositories.push(repository.to_string());
+        }
+    }
+    repositories
+}
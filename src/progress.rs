@@ -1,7 +1,175 @@
 //! Build progress monitoring and reporting
 
 use crate::error::Result;
-use crate::proto::moby::buildkit::v1::StatusResponse;
+use crate::proto::moby::buildkit::v1::{StatusResponse, Vertex, VertexLog, VertexStatus, VertexWarning};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::Notify;
+
+/// Owned snapshot of a single BuildKit status update
+///
+/// Decoupled from the generated protobuf types so handlers do not depend on
+/// prost and keep compiling when BuildKit adds fields.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[non_exhaustive]
+pub struct StatusSummary {
+    /// Build steps whose state changed
+    pub vertexes: Vec<VertexSummary>,
+    /// Progress counters (e.g., bytes transferred)
+    pub statuses: Vec<VertexProgress>,
+    /// Log output chunks
+    pub logs: Vec<LogChunk>,
+    /// Build warnings
+    pub warnings: Vec<WarningSummary>,
+}
+
+/// State of a single build step
+#[derive(Debug, Clone, Default, PartialEq)]
+#[non_exhaustive]
+pub struct VertexSummary {
+    /// Vertex digest identifying the step
+    pub digest: String,
+    /// Digests of the steps this one depends on
+    pub inputs: Vec<String>,
+    /// Human readable step name (e.g., "[2/3] RUN make")
+    pub name: String,
+    /// Whether the result was served from cache
+    pub cached: bool,
+    /// When the step started
+    pub started: Option<SystemTime>,
+    /// When the step completed
+    pub completed: Option<SystemTime>,
+    /// Error message if the step failed
+    pub error: Option<String>,
+}
+
+/// Progress counter reported for a build step
+#[derive(Debug, Clone, Default, PartialEq)]
+#[non_exhaustive]
+pub struct VertexProgress {
+    /// Counter identifier
+    pub id: String,
+    /// Digest of the owning step
+    pub vertex: String,
+    /// Counter name
+    pub name: String,
+    /// Current value
+    pub current: i64,
+    /// Total value (0 if unknown)
+    pub total: i64,
+    /// When the counter was sampled
+    pub timestamp: Option<SystemTime>,
+    /// When the counter started
+    pub started: Option<SystemTime>,
+    /// When the counter completed
+    pub completed: Option<SystemTime>,
+}
+
+/// Chunk of log output produced by a build step
+#[derive(Debug, Clone, Default, PartialEq)]
+#[non_exhaustive]
+pub struct LogChunk {
+    /// Digest of the owning step
+    pub vertex: String,
+    /// When the output was produced
+    pub timestamp: Option<SystemTime>,
+    /// Output stream (1 = stdout, 2 = stderr)
+    pub stream: i64,
+    /// Raw output bytes
+    pub data: Vec<u8>,
+}
+
+/// Warning emitted by the frontend
+#[derive(Debug, Clone, Default, PartialEq)]
+#[non_exhaustive]
+pub struct WarningSummary {
+    /// Digest of the owning step
+    pub vertex: String,
+    /// Warning level
+    pub level: i64,
+    /// Short description
+    pub short: String,
+    /// Detailed description lines
+    pub detail: Vec<String>,
+    /// Link to further documentation
+    pub url: String,
+}
+
+fn to_system_time(ts: Option<prost_types::Timestamp>) -> Option<SystemTime> {
+    ts.and_then(|t| SystemTime::try_from(t).ok())
+}
+
+fn unix_seconds(time: &SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
+
+impl From<Vertex> for VertexSummary {
+    fn from(v: Vertex) -> Self {
+        Self {
+            digest: v.digest,
+            inputs: v.inputs,
+            name: v.name,
+            cached: v.cached,
+            started: to_system_time(v.started),
+            completed: to_system_time(v.completed),
+            error: if v.error.is_empty() { None } else { Some(v.error) },
+        }
+    }
+}
+
+impl From<VertexStatus> for VertexProgress {
+    fn from(s: VertexStatus) -> Self {
+        Self {
+            id: s.id,
+            vertex: s.vertex,
+            name: s.name,
+            current: s.current,
+            total: s.total,
+            timestamp: to_system_time(s.timestamp),
+            started: to_system_time(s.started),
+            completed: to_system_time(s.completed),
+        }
+    }
+}
+
+impl From<VertexLog> for LogChunk {
+    fn from(l: VertexLog) -> Self {
+        Self {
+            vertex: l.vertex,
+            timestamp: to_system_time(l.timestamp),
+            stream: l.stream,
+            data: l.msg,
+        }
+    }
+}
+
+impl From<VertexWarning> for WarningSummary {
+    fn from(w: VertexWarning) -> Self {
+        Self {
+            vertex: w.vertex,
+            level: w.level,
+            short: String::from_utf8_lossy(&w.short).into_owned(),
+            detail: w
+                .detail
+                .iter()
+                .map(|d| String::from_utf8_lossy(d).into_owned())
+                .collect(),
+            url: w.url,
+        }
+    }
+}
+
+impl From<StatusResponse> for StatusSummary {
+    fn from(status: StatusResponse) -> Self {
+        Self {
+            vertexes: status.vertexes.into_iter().map(Into::into).collect(),
+            statuses: status.statuses.into_iter().map(Into::into).collect(),
+            logs: status.logs.into_iter().map(Into::into).collect(),
+            warnings: status.warnings.into_iter().map(Into::into).collect(),
+        }
+    }
+}
 
 /// Trait for handling build progress updates
 pub trait ProgressHandler: Send {
@@ -9,7 +177,7 @@ pub trait ProgressHandler: Send {
     fn on_start(&mut self) -> Result<()>;
 
     /// Called for each status update
-    fn on_status(&mut self, status: StatusResponse) -> Result<()>;
+    fn on_status(&mut self, status: StatusSummary) -> Result<()>;
 
     /// Called when the build completes successfully
     fn on_complete(&mut self) -> Result<()>;
@@ -36,7 +204,7 @@ impl ProgressHandler for ConsoleProgressHandler {
         Ok(())
     }
 
-    fn on_status(&mut self, status: StatusResponse) -> Result<()> {
+    fn on_status(&mut self, status: StatusSummary) -> Result<()> {
         for vertex in status.vertexes {
             if vertex.completed.is_some() {
                 println!("✅ {}", vertex.name);
@@ -53,7 +221,7 @@ impl ProgressHandler for ConsoleProgressHandler {
         // Show logs
         for log in status.logs {
             if self.verbose {
-                if let Ok(msg) = String::from_utf8(log.msg) {
+                if let Ok(msg) = String::from_utf8(log.data) {
                     print!("{}", msg);
                 }
             }
@@ -89,15 +257,15 @@ impl ProgressHandler for JsonProgressHandler {
         Ok(())
     }
 
-    fn on_status(&mut self, status: StatusResponse) -> Result<()> {
+    fn on_status(&mut self, status: StatusSummary) -> Result<()> {
         let json = serde_json::json!({
             "vertexes": status.vertexes.iter().map(|v| {
                 serde_json::json!({
                     "digest": v.digest,
                     "name": v.name,
                     "cached": v.cached,
-                    "started": v.started.as_ref().map(|t| t.seconds),
-                    "completed": v.completed.as_ref().map(|t| t.seconds),
+                    "started": v.started.as_ref().and_then(unix_seconds),
+                    "completed": v.completed.as_ref().and_then(unix_seconds),
                     "error": v.error,
                 })
             }).collect::<Vec<_>>(),
@@ -106,7 +274,7 @@ impl ProgressHandler for JsonProgressHandler {
                     "vertex": s.vertex,
                     "current": s.current,
                     "total": s.total,
-                    "timestamp": s.timestamp.as_ref().map(|t| t.seconds),
+                    "timestamp": s.timestamp.as_ref().and_then(unix_seconds),
                 })
             }).collect::<Vec<_>>(),
         });
@@ -151,7 +319,7 @@ impl ProgressHandler for SilentProgressHandler {
         Ok(())
     }
 
-    fn on_status(&mut self, _status: StatusResponse) -> Result<()> {
+    fn on_status(&mut self, _status: StatusSummary) -> Result<()> {
         Ok(())
     }
 
@@ -163,3 +331,206 @@ impl ProgressHandler for SilentProgressHandler {
         Ok(())
     }
 }
+
+/// Policy applied when the status stream outpaces the progress handler
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Stop reading from BuildKit until the handler catches up
+    #[default]
+    Block,
+    /// Discard incoming status messages while the buffer is full
+    DropNewest,
+    /// Merge incoming status messages into the newest buffered one,
+    /// concatenating log output per vertex
+    Coalesce,
+}
+
+/// Bounded buffering configuration for status consumption
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgressBufferConfig {
+    /// Maximum number of status messages held for the handler
+    pub capacity: usize,
+    /// What to do with status messages once the buffer is full
+    pub policy: OverflowPolicy,
+}
+
+impl ProgressBufferConfig {
+    /// Create a buffer configuration with the given capacity and policy
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self { capacity, policy }
+    }
+}
+
+impl Default for ProgressBufferConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 256,
+            policy: OverflowPolicy::Block,
+        }
+    }
+}
+
+/// Counters describing how status messages flowed through the buffer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProgressMetrics {
+    /// Status messages received from BuildKit
+    pub received: u64,
+    /// Status messages handed to the progress handler
+    pub delivered: u64,
+    /// Status messages discarded because the buffer was full
+    pub dropped: u64,
+    /// Status messages merged into an already buffered message
+    pub coalesced: u64,
+}
+
+/// Bounded queue sitting between the BuildKit status stream and a handler
+///
+/// The producer side is fed from the gRPC stream in a background task while
+/// the consumer side drives the (possibly slow) `ProgressHandler`.
+pub struct StatusBuffer {
+    config: ProgressBufferConfig,
+    state: Mutex<BufferState>,
+    readable: Notify,
+    writable: Notify,
+}
+
+struct BufferState {
+    queue: VecDeque<StatusResponse>,
+    closed: bool,
+    error: Option<String>,
+    metrics: ProgressMetrics,
+}
+
+impl StatusBuffer {
+    /// Create a new status buffer
+    pub fn new(config: ProgressBufferConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(BufferState {
+                queue: VecDeque::new(),
+                closed: false,
+                error: None,
+                metrics: ProgressMetrics::default(),
+            }),
+            readable: Notify::new(),
+            writable: Notify::new(),
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.config.capacity.max(1)
+    }
+
+    /// Push a status message, applying the overflow policy when full
+    pub async fn push(&self, status: StatusResponse) {
+        let mut status = Some(status);
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.queue.len() < self.capacity() {
+                    state.queue.extend(status.take());
+                    state.metrics.received += 1;
+                    drop(state);
+                    self.readable.notify_one();
+                    return;
+                }
+
+                match self.config.policy {
+                    OverflowPolicy::Block => {}
+                    OverflowPolicy::DropNewest => {
+                        state.metrics.received += 1;
+                        state.metrics.dropped += 1;
+                        return;
+                    }
+                    OverflowPolicy::Coalesce => {
+                        if let (Some(tail), Some(incoming)) = (state.queue.back_mut(), status.take()) {
+                            coalesce_status(tail, incoming);
+                        }
+                        state.metrics.received += 1;
+                        state.metrics.coalesced += 1;
+                        return;
+                    }
+                }
+            }
+            self.writable.notified().await;
+        }
+    }
+
+    /// Pop the next status message
+    ///
+    /// Returns `None` once the buffer is closed and drained, or `Some(Err)`
+    /// if the producer recorded a stream error.
+    pub async fn pop(&self) -> Option<std::result::Result<StatusResponse, String>> {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if let Some(status) = state.queue.pop_front() {
+                    state.metrics.delivered += 1;
+                    drop(state);
+                    self.writable.notify_one();
+                    return Some(Ok(status));
+                }
+                if state.closed {
+                    return state.error.take().map(Err);
+                }
+            }
+            self.readable.notified().await;
+        }
+    }
+
+    /// Mark the producer side as finished
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.readable.notify_one();
+    }
+
+    /// Mark the producer side as failed with the given error
+    pub fn fail(&self, error: impl Into<String>) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.error = Some(error.into());
+            state.closed = true;
+        }
+        self.readable.notify_one();
+    }
+
+    /// Snapshot of the buffer counters
+    pub fn metrics(&self) -> ProgressMetrics {
+        self.state.lock().unwrap().metrics
+    }
+}
+
+/// Merge `incoming` into `tail`, keeping the latest vertex/status state and
+/// concatenating log chunks that belong to the same vertex and stream
+fn coalesce_status(tail: &mut StatusResponse, incoming: StatusResponse) {
+    for vertex in incoming.vertexes {
+        match tail.vertexes.iter_mut().find(|v| v.digest == vertex.digest) {
+            Some(existing) => *existing = vertex,
+            None => tail.vertexes.push(vertex),
+        }
+    }
+
+    for status in incoming.statuses {
+        match tail
+            .statuses
+            .iter_mut()
+            .find(|s| s.id == status.id && s.vertex == status.vertex)
+        {
+            Some(existing) => *existing = status,
+            None => tail.statuses.push(status),
+        }
+    }
+
+    for log in incoming.logs {
+        match tail
+            .logs
+            .iter_mut()
+            .find(|l| l.vertex == log.vertex && l.stream == log.stream)
+        {
+            Some(existing) => existing.msg.extend_from_slice(&log.msg),
+            None => tail.logs.push(log),
+        }
+    }
+
+    tail.warnings.extend(incoming.warnings);
+}
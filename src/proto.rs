@@ -13,6 +13,14 @@ pub mod moby {
             pub mod sourcepolicy {
                 tonic::include_proto!("moby.buildkit.v1.sourcepolicy");
             }
+
+            pub mod apicaps {
+                tonic::include_proto!("moby.buildkit.v1.apicaps");
+            }
+
+            pub mod frontend {
+                tonic::include_proto!("moby.buildkit.v1.frontend");
+            }
         }
     }
 
@@ -27,12 +35,38 @@ pub mod moby {
             tonic::include_proto!("moby.buildkit.secrets.v1");
         }
     }
+
+    pub mod sshforward {
+        pub mod v1 {
+            tonic::include_proto!("moby.sshforward.v1");
+        }
+    }
+
+    pub mod upload {
+        pub mod v1 {
+            tonic::include_proto!("moby.upload.v1");
+        }
+    }
+}
+
+pub mod containerd {
+    pub mod services {
+        pub mod content {
+            pub mod v1 {
+                tonic::include_proto!("containerd.services.content.v1");
+            }
+        }
+    }
 }
 
 pub mod pb {
     tonic::include_proto!("pb");
 }
 
+pub mod errdefs {
+    tonic::include_proto!("errdefs");
+}
+
 pub mod fsutil {
     pub mod types {
         tonic::include_proto!("fsutil.types");
@@ -0,0 +1,183 @@
+//! Opinionated one-call build API
+//!
+//! [`BuildKitClient::quick_build`] covers the common "build this directory
+//! for a few platforms, push it with provenance, and reuse the registry
+//! cache" case without having to learn every [`BuildConfig`] option.
+
+use crate::attest::{Provenance, ProvenanceMode};
+use crate::builder::{BuildConfig, Platform, RegistryAuth};
+use crate::cache::{CacheExport, CacheImport};
+use crate::client::BuildKitClient;
+use crate::error::{Error, Result};
+use crate::progress::ConsoleProgressHandler;
+use crate::solve::BuildResult;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Tag used for the registry build cache next to the image
+pub const CACHE_TAG: &str = "buildcache";
+
+/// Delay before the first retry; doubled for every further attempt
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+
+/// Longest delay between retries, however many are configured
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Happy-path build: local context, pushed tag, registry cache
+#[derive(Debug, Clone)]
+pub struct QuickBuild {
+    /// Build context directory containing a `Dockerfile`
+    pub path: PathBuf,
+    /// Image reference to push (e.g., "registry.example.com/team/app:v1")
+    pub tag: String,
+    /// Target platforms (defaults to linux/amd64 when empty)
+    pub platforms: Vec<Platform>,
+    /// Registry credentials for the push and the cache
+    pub registry_auth: Option<RegistryAuth>,
+    /// Extra attempts after a transient failure (connection loss, daemon unavailable)
+    pub retries: u32,
+    /// Provenance attached to the pushed image (inline `mode=min` by default)
+    pub provenance: Option<Provenance>,
+}
+
+impl QuickBuild {
+    /// Create a quick build of `path` pushed as `tag`, retrying twice
+    pub fn new(path: impl Into<PathBuf>, tag: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            tag: tag.into(),
+            platforms: Vec::new(),
+            registry_auth: None,
+            retries: 2,
+            provenance: Some(Provenance::new(ProvenanceMode::Min).inline_only(true)),
+        }
+    }
+
+    /// Add a target platform
+    pub fn platform(mut self, platform: Platform) -> Self {
+        self.platforms.push(platform);
+        self
+    }
+
+    /// Set registry credentials
+    pub fn registry_auth(mut self, auth: RegistryAuth) -> Self {
+        self.registry_auth = Some(auth);
+        self
+    }
+
+    /// Set the provenance attestation, or `None` to push without one
+    pub fn provenance(mut self, provenance: Option<Provenance>) -> Self {
+        self.provenance = provenance;
+        self
+    }
+
+    /// Set the number of retries after transient failures
+    ///
+    /// The delay between retries doubles from 2s up to at most a minute.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Expand into the equivalent build configuration
+    ///
+    /// The cache is imported from and exported to `<repository>:buildcache`.
+    pub fn to_config(&self) -> BuildConfig {
+        let cache_ref = cache_ref_for(&self.tag);
+        let mut config = BuildConfig::local(&self.path)
+            .tag(&self.tag)
+            .cache_import(CacheImport::registry(&cache_ref))
+            .cache_export(CacheExport::registry(&cache_ref));
+
+        if !self.platforms.is_empty() {
+            config.platforms = self.platforms.clone();
+        }
+        if let Some(auth) = &self.registry_auth {
+            config = config.registry_auth(auth.clone());
+        }
+        config.provenance = self.provenance.clone();
+        config
+    }
+}
+
+/// Derive the registry cache reference for an image reference
+///
+/// Drops any tag or digest and appends [`CACHE_TAG`], so
+/// `registry:5000/app:v1` caches to `registry:5000/app:buildcache`.
+pub fn cache_ref_for(image: &str) -> String {
+    let repository = image.split('@').next().unwrap_or(image);
+    // A colon after the last slash separates the tag (a colon before it is a registry port)
+    let name_start = repository.rfind('/').map(|i| i + 1).unwrap_or(0);
+    let repository = match repository[name_start..].rfind(':') {
+        Some(colon) => &repository[..name_start + colon],
+        None => repository,
+    };
+    format!("{}:{}", repository, CACHE_TAG)
+}
+
+/// Check whether a failed build is worth retrying
+fn is_transient(error: &Error) -> bool {
+    match error {
+        Error::Connection { .. } | Error::Http2Handshake { .. } => true,
+        Error::Grpc(status) => matches!(
+            status.code(),
+            tonic::Code::Unavailable | tonic::Code::Aborted | tonic::Code::ResourceExhausted
+        ),
+        _ => false,
+    }
+}
+
+impl BuildKitClient {
+    /// Build, push and cache an image with sensible defaults
+    ///
+    /// Progress is printed to the console. Transient failures are retried
+    /// with exponential backoff.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use buildkit_client::{BuildKitClient, Platform, QuickBuild};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let mut client = BuildKitClient::connect("http://localhost:1234").await?;
+    ///
+    ///     let result = client
+    ///         .quick_build(
+    ///             QuickBuild::new("./app", "localhost:5000/app:latest")
+    ///                 .platform(Platform::linux_amd64())
+    ///                 .platform(Platform::linux_arm64()),
+    ///         )
+    ///         .await?;
+    ///
+    ///     println!("Pushed {:?}", result.digest);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn quick_build(&mut self, quick: QuickBuild) -> Result<BuildResult> {
+        let mut attempt = 0;
+        loop {
+            let progress = Box::new(ConsoleProgressHandler::new(false));
+            match self.build(quick.to_config(), Some(progress)).await {
+                Err(e) if attempt < quick.retries && is_transient(&e) => {
+                    let delay = 2u32
+                        .checked_pow(attempt)
+                        .map_or(RETRY_MAX_DELAY, |factor| {
+                            RETRY_BASE_DELAY.saturating_mul(factor)
+                        })
+                        .min(RETRY_MAX_DELAY);
+                    attempt += 1;
+                    tracing::warn!(
+                        "Build failed ({}), retrying in {:?} (attempt {}/{})",
+                        e,
+                        delay,
+                        attempt,
+                        quick.retries
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
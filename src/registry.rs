@@ -0,0 +1,499 @@
+//! Access to image content in a registry
+//!
+//! [`RegistryContent`] serves the manifests and blobs of one repository
+//! through the [`ContentStore`] trait, so content BuildKit pushed (e.g.,
+//! attestations) can be read back with the same helpers used for session
+//! stores. Manifests can also be uploaded, e.g. an image index assembled on
+//! the client. Anonymous, basic and bearer-token authentication are supported.
+
+use crate::builder::{BuildConfig, RegistryAuth, RegistryConfig};
+use crate::error::{Error, Result};
+use crate::oci::{
+    MEDIA_TYPE_DOCKER_MANIFEST, MEDIA_TYPE_DOCKER_MANIFEST_LIST, MEDIA_TYPE_IMAGE_INDEX,
+    MEDIA_TYPE_IMAGE_MANIFEST,
+};
+use crate::session::auth::{request_token, TokenCredentials};
+use crate::session::{BlobInfo, ContentStore};
+use bytes::Bytes;
+use reqwest::header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, RANGE, WWW_AUTHENTICATE};
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Registry host used for Docker Hub images
+pub const DOCKER_HUB_REGISTRY: &str = "registry-1.docker.io";
+
+/// Media types accepted when fetching manifests by digest
+fn manifest_accept() -> String {
+    [
+        MEDIA_TYPE_IMAGE_INDEX,
+        MEDIA_TYPE_IMAGE_MANIFEST,
+        MEDIA_TYPE_DOCKER_MANIFEST_LIST,
+        MEDIA_TYPE_DOCKER_MANIFEST,
+    ]
+    .join(", ")
+}
+
+/// Split an image name into registry host and repository
+///
+/// Names without a registry resolve to Docker Hub (`library/` is added for
+/// official images); tags and digests are ignored.
+///
+/// # Example
+///
+/// ```
+/// use buildkit_client::registry::split_image_name;
+///
+/// assert_eq!(
+///     split_image_name("localhost:5000/app:v1"),
+///     ("localhost:5000".to_string(), "app".to_string())
+/// );
+/// assert_eq!(
+///     split_image_name("alpine:3.20"),
+///     ("registry-1.docker.io".to_string(), "library/alpine".to_string())
+/// );
+/// ```
+pub fn split_image_name(name: &str) -> (String, String) {
+    let name = name.split('@').next().unwrap_or(name);
+    let (host, path) = match name.split_once('/') {
+        Some((first, rest))
+            if first.contains('.') || first.contains(':') || first == "localhost" =>
+        {
+            (first, rest)
+        }
+        _ => ("docker.io", name),
+    };
+
+    // Drop the tag, which follows the last path component
+    let path = match path.rsplit_once('/') {
+        Some((dir, last)) => format!("{}/{}", dir, last.split(':').next().unwrap_or(last)),
+        None => path.split(':').next().unwrap_or(path).to_string(),
+    };
+
+    if host == "docker.io" || host == "index.docker.io" {
+        let path = if path.contains('/') {
+            path
+        } else {
+            format!("library/{}", path)
+        };
+        (DOCKER_HUB_REGISTRY.to_string(), path)
+    } else {
+        (host.to_string(), path)
+    }
+}
+
+/// Split an image name into repository and tag ("latest" when untagged)
+pub(crate) fn split_tag(name: &str) -> (&str, &str) {
+    let name = name.split('@').next().unwrap_or(name);
+    let last = name.rfind('/').map_or(0, |i| i + 1);
+    match name[last..].rfind(':') {
+        Some(i) => (&name[..last + i], &name[last + i + 1..]),
+        None => (name, "latest"),
+    }
+}
+
+/// Point an image reference at a mirror host, keeping its repository, tag
+/// and digest
+///
+/// # Example
+///
+/// ```
+/// use buildkit_client::registry::mirror_reference;
+///
+/// assert_eq!(
+///     mirror_reference("alpine:3.20", "mirror.local:5000"),
+///     "mirror.local:5000/library/alpine:3.20"
+/// );
+/// assert_eq!(
+///     mirror_reference("ghcr.io/org/app:buildcache", "mirror.local"),
+///     "mirror.local/org/app:buildcache"
+/// );
+/// ```
+pub fn mirror_reference(reference: &str, mirror: &str) -> String {
+    let (_, repository) = split_image_name(reference);
+    // Tag and digest follow the last path component
+    let last = reference.rsplit('/').next().unwrap_or(reference);
+    let suffix = &last[last.find([':', '@']).unwrap_or(last.len())..];
+    format!("{}/{}{}", mirror, repository, suffix)
+}
+
+/// Manifests and blobs of one registry repository
+pub struct RegistryContent {
+    client: reqwest::Client,
+    base_url: String,
+    repository: String,
+    auth: Option<RegistryAuth>,
+    token: Mutex<Option<String>>,
+}
+
+impl RegistryContent {
+    /// Access the repository of an image name (e.g., "localhost:5000/app:v1")
+    ///
+    /// The registry is reached over HTTPS unless `config` says otherwise,
+    /// `localhost` included. Mirrors are not used; reads and writes go to the
+    /// registry itself.
+    pub fn configured(name: &str, config: &RegistryConfig) -> Result<Self> {
+        let (host, repository) = split_image_name(name);
+        let scheme = if config.plain_http { "http" } else { "https" };
+        let mut builder = reqwest::Client::builder()
+            .user_agent("buildkit-client")
+            .timeout(Duration::from_secs(300))
+            .danger_accept_invalid_certs(config.skip_tls_verify);
+        for path in &config.ca_certs {
+            let pem = std::fs::read(path).map_err(|e| {
+                Error::InvalidConfig(format!(
+                    "failed to read CA certificate {}: {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+            let certificate = reqwest::Certificate::from_pem(&pem).map_err(|e| {
+                Error::InvalidConfig(format!("invalid CA certificate {}: {}", path.display(), e))
+            })?;
+            builder = builder.add_root_certificate(certificate);
+        }
+        let client = builder
+            .build()
+            .map_err(|e| Error::other(format!("Failed to create HTTP client: {}", e)))?;
+        Ok(Self {
+            client,
+            base_url: format!("{}://{}", scheme, host),
+            repository,
+            auth: None,
+            token: Mutex::new(None),
+        })
+    }
+
+    /// Access the repository of an image name with a build's registry
+    /// settings, authenticated if the build's credentials are for its host
+    pub(crate) fn for_build(name: &str, config: &BuildConfig) -> Result<Self> {
+        let default = RegistryConfig::default();
+        let registry = config.registry_config_for(name).unwrap_or(&default);
+        let store = Self::configured(name, registry)?;
+        let (host, _) = split_image_name(name);
+        match config.registry_auth.as_ref() {
+            Some(auth) if auth_matches(&auth.host, &host) => Ok(store.with_auth(auth.clone())),
+            _ => Ok(store),
+        }
+    }
+
+    /// Authenticate with the given credentials
+    pub fn with_auth(mut self, auth: RegistryAuth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Repository path within the registry
+    pub fn repository(&self) -> &str {
+        &self.repository
+    }
+
+    /// Upload a manifest (or index) under a tag or digest
+    ///
+    /// Returns the digest of the uploaded content.
+    pub async fn put_manifest(
+        &self,
+        reference: &str,
+        media_type: &str,
+        body: Bytes,
+    ) -> Result<String> {
+        let url = self.url("manifests", reference);
+        let response = self
+            .send(Method::PUT, &url, |r| {
+                r.header(CONTENT_TYPE, media_type).body(body.clone())
+            })
+            .await?;
+        if !response.status().is_success() {
+            return Err(Error::Registry {
+                url,
+                reason: format!("PUT returned {}", response.status()),
+            });
+        }
+        Ok(format!("sha256:{:x}", Sha256::digest(&body)))
+    }
+
+    /// Digest of the manifest a tag or digest resolves to, if it exists
+    ///
+    /// The digest is computed from the served content rather than taken from
+    /// the registry's `Docker-Content-Digest` header.
+    pub async fn manifest_digest(&self, reference: &str) -> Result<Option<String>> {
+        let url = self.url("manifests", reference);
+        let response = self
+            .send(Method::GET, &url, |r| r.header(ACCEPT, manifest_accept()))
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(Error::Registry {
+                url,
+                reason: format!("GET returned {}", response.status()),
+            });
+        }
+        let body = response.bytes().await.map_err(|e| Error::Registry {
+            url,
+            reason: e.to_string(),
+        })?;
+        Ok(Some(format!("sha256:{:x}", Sha256::digest(&body))))
+    }
+
+    fn url(&self, kind: &str, digest: &str) -> String {
+        format!(
+            "{}/v2/{}/{}/{}",
+            self.base_url, self.repository, kind, digest
+        )
+    }
+
+    /// Send a request, authenticating and retrying once if challenged
+    async fn send(
+        &self,
+        method: Method,
+        url: &str,
+        build: impl Fn(RequestBuilder) -> RequestBuilder,
+    ) -> Result<Response> {
+        let fail = |reason: String| Error::Registry {
+            url: url.to_string(),
+            reason,
+        };
+
+        let request = |token: Option<String>| {
+            let request = build(self.client.request(method.clone(), url));
+            match (token, &self.auth) {
+                (Some(token), _) => request.bearer_auth(token),
+                // Identity tokens are only exchanged after a bearer challenge
+                (None, Some(auth)) if auth.identity_token.is_none() => {
+                    request.basic_auth(&auth.username, Some(&auth.password))
+                }
+                (None, _) => request,
+            }
+        };
+
+        let token = self.token.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let response = request(token)
+            .send()
+            .await
+            .map_err(|e| fail(e.to_string()))?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+
+        let challenge = response
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(parse_challenge)
+            .ok_or_else(|| fail("unauthorized".to_string()))?;
+        let token = self.fetch_token(&challenge).await.map_err(fail)?;
+        *self.token.lock().unwrap_or_else(|e| e.into_inner()) = Some(token.clone());
+
+        request(Some(token))
+            .send()
+            .await
+            .map_err(|e| fail(e.to_string()))
+    }
+
+    /// Exchange a bearer challenge for a token
+    async fn fetch_token(
+        &self,
+        challenge: &HashMap<String, String>,
+    ) -> std::result::Result<String, String> {
+        let realm = challenge
+            .get("realm")
+            .ok_or_else(|| "bearer challenge without realm".to_string())?;
+        let scope = challenge
+            .get("scope")
+            .cloned()
+            .unwrap_or_else(|| format!("repository:{}:pull", self.repository));
+        let service = challenge.get("service").map_or("", String::as_str);
+        let credentials = match &self.auth {
+            Some(RegistryAuth {
+                identity_token: Some(token),
+                ..
+            }) => TokenCredentials::Refresh(token),
+            Some(auth) => TokenCredentials::Basic {
+                username: &auth.username,
+                password: &auth.password,
+            },
+            None => TokenCredentials::Anonymous,
+        };
+        let issued = request_token(
+            &self.client,
+            realm,
+            service,
+            &[scope],
+            "buildkit-client",
+            credentials,
+        )
+        .await?;
+        Ok(issued.token)
+    }
+
+    /// Fetch a manifest or a byte range of a blob
+    async fn get(&self, digest: &str, range: Option<(u64, usize)>) -> Result<Option<Bytes>> {
+        let url = self.url("manifests", digest);
+        let response = self
+            .send(Method::GET, &url, |r| r.header(ACCEPT, manifest_accept()))
+            .await?;
+        let (url, response) = if response.status() == StatusCode::NOT_FOUND {
+            let url = self.url("blobs", digest);
+            let response = self
+                .send(Method::GET, &url, |r| match range {
+                    Some((offset, len)) if len > 0 => r.header(
+                        RANGE,
+                        format!("bytes={}-{}", offset, offset + len as u64 - 1),
+                    ),
+                    _ => r,
+                })
+                .await?;
+            if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+                return Ok(Some(Bytes::new()));
+            }
+            if response.status() == StatusCode::PARTIAL_CONTENT {
+                let data = response.bytes().await.map_err(|e| Error::Registry {
+                    url: url.clone(),
+                    reason: e.to_string(),
+                })?;
+                return Ok(Some(data));
+            }
+            (url, response)
+        } else {
+            (url, response)
+        };
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(Error::Registry {
+                url,
+                reason: format!("GET returned {}", response.status()),
+            });
+        }
+        let data = response.bytes().await.map_err(|e| Error::Registry {
+            url,
+            reason: e.to_string(),
+        })?;
+
+        // Manifests (and servers ignoring Range) return the whole content
+        Ok(Some(match range {
+            Some((offset, len)) => {
+                let start = (offset as usize).min(data.len());
+                let end = start.saturating_add(len).min(data.len());
+                data.slice(start..end)
+            }
+            None => data,
+        }))
+    }
+}
+
+fn auth_matches(auth_host: &str, host: &str) -> bool {
+    auth_host == host
+        || (host == DOCKER_HUB_REGISTRY
+            && matches!(
+                auth_host,
+                "docker.io" | "index.docker.io" | "registry-1.docker.io"
+            ))
+}
+
+/// Check that `reference` (a tag or digest) of `name`'s repository serves
+/// the manifest with digest `expected`
+pub(crate) async fn verify_manifest(
+    config: &BuildConfig,
+    name: &str,
+    reference: &str,
+    expected: &str,
+) -> Result<()> {
+    let fail = |reason: String| Error::PushVerification {
+        name: name.to_string(),
+        reason,
+    };
+    let actual = RegistryContent::for_build(name, config)?
+        .manifest_digest(reference)
+        .await?;
+    match actual {
+        Some(actual) if actual == expected => {
+            tracing::info!("Verified {} at {}", name, expected);
+            Ok(())
+        }
+        Some(actual) => Err(fail(format!(
+            "the registry serves {} instead of {}",
+            actual, expected
+        ))),
+        None => Err(fail(format!("{} not found in the registry", reference))),
+    }
+}
+
+impl std::fmt::Debug for RegistryContent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegistryContent")
+            .field("base_url", &self.base_url)
+            .field("repository", &self.repository)
+            .finish()
+    }
+}
+
+#[tonic::async_trait]
+impl ContentStore for RegistryContent {
+    async fn info(&self, digest: &str) -> Result<Option<BlobInfo>> {
+        for kind in ["manifests", "blobs"] {
+            let url = self.url(kind, digest);
+            let response = self
+                .send(Method::HEAD, &url, |r| r.header(ACCEPT, manifest_accept()))
+                .await?;
+            if response.status() == StatusCode::NOT_FOUND {
+                continue;
+            }
+            if !response.status().is_success() {
+                return Err(Error::Registry {
+                    url,
+                    reason: format!("HEAD returned {}", response.status()),
+                });
+            }
+            return Ok(Some(BlobInfo {
+                digest: digest.to_string(),
+                size: response
+                    .headers()
+                    .get(CONTENT_LENGTH)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0),
+                labels: HashMap::new(),
+            }));
+        }
+        Ok(None)
+    }
+
+    async fn read_at(&self, digest: &str, offset: u64, len: usize) -> Result<Bytes> {
+        self.get(digest, Some((offset, len)))
+            .await?
+            .ok_or_else(|| Error::ContentNotFound {
+                store: self.base_url.clone(),
+                digest: digest.to_string(),
+            })
+    }
+}
+
+/// Parse the parameters of a `WWW-Authenticate: Bearer ...` challenge
+fn parse_challenge(params: &str) -> HashMap<String, String> {
+    let mut result = HashMap::new();
+    let mut rest = params.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_string();
+        let (value, remaining) = match after.strip_prefix('"') {
+            Some(quoted) => match quoted.split_once('"') {
+                Some((value, remaining)) => (value, remaining),
+                None => (quoted, ""),
+            },
+            None => match after.split_once(',') {
+                Some((value, remaining)) => (value, remaining),
+                None => (after, ""),
+            },
+        };
+        result.insert(key, value.trim().to_string());
+        rest = remaining.trim_start_matches(',').trim();
+    }
+    result
+}
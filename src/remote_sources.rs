@@ -0,0 +1,229 @@
+//! Pre-flight verification of remote `ADD` sources
+//!
+//! `ADD https://...` sources are only fetched once BuildKit reaches that step,
+//! which can be many minutes into a build. These helpers scan a Dockerfile for
+//! remote sources and check them up front: a source fails the build if it is
+//! missing (404, 410) or its server is failing (5xx), and sources pinned with
+//! `--checksum=sha256:<hex>` are downloaded and hashed. Any other answer, such
+//! as a 401, 403 or 429 from a server that only refuses this client, is left
+//! for BuildKit to fetch with its own credentials.
+
+use crate::error::{Error, Result};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+/// A remote source referenced by an `ADD` instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteSource {
+    /// Source URL
+    pub url: String,
+    /// Expected digest from `--checksum` (e.g., "sha256:abc...")
+    pub checksum: Option<String>,
+    /// 1-based Dockerfile line where the instruction starts
+    pub line: usize,
+}
+
+/// Find all `http(s)://` sources of `ADD` instructions in a Dockerfile
+///
+/// Sources containing unexpanded variables (`$VAR`) are skipped since their
+/// final value is only known to the frontend.
+///
+/// # Example
+///
+/// ```
+/// use buildkit_client::remote_sources::find_remote_sources;
+///
+/// let sources = find_remote_sources(
+///     "FROM alpine\nADD --checksum=sha256:abc https://example.com/a.tar.gz /a.tar.gz\n",
+/// );
+/// assert_eq!(sources[0].url, "https://example.com/a.tar.gz");
+/// assert_eq!(sources[0].checksum.as_deref(), Some("sha256:abc"));
+/// ```
+pub fn find_remote_sources(dockerfile: &str) -> Vec<RemoteSource> {
+    let mut sources = Vec::new();
+
+    for (line, instruction) in logical_lines(dockerfile) {
+        let mut words = instruction.split_whitespace();
+        if !words
+            .next()
+            .is_some_and(|keyword| keyword.eq_ignore_ascii_case("ADD"))
+        {
+            continue;
+        }
+
+        let mut checksum = None;
+        let mut args = Vec::new();
+        for word in words {
+            if let Some(value) = word.strip_prefix("--checksum=") {
+                checksum = Some(value.to_string());
+            } else if !word.starts_with("--") {
+                args.push(word);
+            }
+        }
+
+        // JSON (exec) form: ADD ["src", "dest"]
+        let rest = args.join(" ");
+        let args: Vec<String> = if rest.starts_with('[') {
+            serde_json::from_str::<Vec<String>>(&rest).unwrap_or_default()
+        } else {
+            args.iter().map(|a| a.to_string()).collect()
+        };
+
+        // The last argument is the destination
+        let Some((_, srcs)) = args.split_last() else {
+            continue;
+        };
+
+        for src in srcs {
+            let is_remote = src.starts_with("http://") || src.starts_with("https://");
+            if is_remote && !src.contains('$') {
+                sources.push(RemoteSource {
+                    url: src.clone(),
+                    checksum: checksum.clone(),
+                    line,
+                });
+            }
+        }
+    }
+
+    sources
+}
+
+/// Join continuation lines and drop comments, yielding (start line, instruction)
+pub(crate) fn logical_lines(dockerfile: &str) -> Vec<(usize, String)> {
+    let mut result = Vec::new();
+    let mut current = String::new();
+    let mut start = 0;
+
+    for (index, raw) in dockerfile.lines().enumerate() {
+        let trimmed = raw.trim();
+        if trimmed.starts_with('#') {
+            continue;
+        }
+        if current.is_empty() {
+            if trimmed.is_empty() {
+                continue;
+            }
+            start = index + 1;
+        }
+
+        match trimmed.strip_suffix('\\') {
+            Some(body) => {
+                current.push_str(body);
+                current.push(' ');
+            }
+            None => {
+                current.push_str(trimmed);
+                result.push((start, std::mem::take(&mut current)));
+            }
+        }
+    }
+
+    if !current.is_empty() {
+        result.push((start, current));
+    }
+
+    result
+}
+
+/// Verify that every remote source is reachable and matches its checksum
+///
+/// Fails on the first broken source with [`Error::RemoteSource`]. Only
+/// missing sources (404, 410) and server errors count as broken; sources
+/// that refuse this client, e.g. with 401, 403 or 429, are left to BuildKit.
+pub async fn verify_remote_sources(sources: &[RemoteSource]) -> Result<()> {
+    let client = reqwest::Client::builder()
+        .user_agent("buildkit-client")
+        .timeout(Duration::from_secs(300))
+        .build()
+        .map_err(|e| Error::other(format!("Failed to create HTTP client: {}", e)))?;
+
+    for source in sources {
+        verify_source(&client, source).await?;
+        tracing::debug!(
+            "Verified remote source {} (line {})",
+            source.url,
+            source.line
+        );
+    }
+
+    Ok(())
+}
+
+/// Whether `status` shows a source is missing (404, 410) or its server is
+/// failing (5xx), rather than just refusing this client
+fn is_unreachable(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::NOT_FOUND
+        || status == reqwest::StatusCode::GONE
+        || status.is_server_error()
+}
+
+async fn verify_source(client: &reqwest::Client, source: &RemoteSource) -> Result<()> {
+    let fail = |reason: String| Error::RemoteSource {
+        url: source.url.clone(),
+        line: source.line,
+        reason,
+    };
+
+    let expected = match source.checksum.as_deref() {
+        Some(checksum) => match checksum.strip_prefix("sha256:") {
+            Some(hex) => Some(hex.to_ascii_lowercase()),
+            None => {
+                tracing::warn!(
+                    "Cannot verify {} locally (only sha256 is supported), checking reachability only",
+                    checksum
+                );
+                None
+            }
+        },
+        None => None,
+    };
+
+    let Some(expected) = expected else {
+        let response = client
+            .head(&source.url)
+            .send()
+            .await
+            .map_err(|e| fail(e.to_string()))?;
+        // Some servers reject HEAD, and BuildKit may hold credentials this
+        // client lacks; only missing sources and failing servers are broken
+        let status = response.status();
+        if is_unreachable(status) {
+            return Err(fail(format!("HEAD returned {}", status)));
+        }
+        return Ok(());
+    };
+
+    let mut response = client
+        .get(&source.url)
+        .send()
+        .await
+        .map_err(|e| fail(e.to_string()))?;
+    let status = response.status();
+    if is_unreachable(status) {
+        return Err(fail(format!("GET returned {}", status)));
+    }
+    if !status.is_success() {
+        tracing::warn!(
+            "Cannot verify {} locally (GET returned {}), leaving it to BuildKit",
+            source.url,
+            status
+        );
+        return Ok(());
+    }
+
+    let mut hasher = Sha256::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| fail(e.to_string()))? {
+        hasher.update(&chunk);
+    }
+    let actual = format!("{:x}", hasher.finalize());
+
+    if actual != expected {
+        return Err(fail(format!(
+            "checksum mismatch: expected sha256:{}, got sha256:{}",
+            expected, actual
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,113 @@
+//! Read-permission checks for build contexts owned by other users
+//!
+//! A service running as root can read every file in a context, even ones the
+//! context's owner could not. Running the check below before the session
+//! starts reports every unreadable path up front, instead of the transfer
+//! failing halfway through on the first one.
+
+use crate::error::{Error, Result};
+use std::path::{Path, PathBuf};
+
+/// Unix identity whose permissions are enforced when reading a build context
+///
+/// Only the classic owner/group/other mode bits are evaluated; ACLs and
+/// capabilities are not taken into account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextUser {
+    /// User ID
+    pub uid: u32,
+    /// Primary group ID
+    pub gid: u32,
+    /// Supplementary group IDs
+    pub groups: Vec<u32>,
+}
+
+impl ContextUser {
+    /// Create a user with a primary group and no supplementary groups
+    pub fn new(uid: u32, gid: u32) -> Self {
+        Self {
+            uid,
+            gid,
+            groups: Vec::new(),
+        }
+    }
+
+    /// Add a supplementary group
+    pub fn group(mut self, gid: u32) -> Self {
+        self.groups.push(gid);
+        self
+    }
+
+    /// Check whether this user may read a file, or list and enter a directory,
+    /// given the entry's owner, group and permission bits
+    pub fn can_read(&self, owner: u32, group: u32, mode: u32, is_dir: bool) -> bool {
+        if self.uid == 0 {
+            return true;
+        }
+
+        let bits = if owner == self.uid {
+            (mode >> 6) & 0o7
+        } else if group == self.gid || self.groups.contains(&group) {
+            (mode >> 3) & 0o7
+        } else {
+            mode & 0o7
+        };
+
+        let wanted = if is_dir { 0o5 } else { 0o4 };
+        bits & wanted == wanted
+    }
+
+    /// Walk `root` and fail with every path this user cannot read
+    ///
+    /// Unreadable directories are reported once and not descended into.
+    /// Symlinks are not followed.
+    pub fn check_tree(&self, root: &Path) -> Result<()> {
+        let mut denied = Vec::new();
+        self.check_dir(root, Path::new(""), &mut denied)?;
+
+        if denied.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::ContextPermissionDenied {
+                uid: self.uid,
+                paths: denied,
+            })
+        }
+    }
+
+    #[cfg(unix)]
+    fn check_dir(&self, dir: &Path, rel: &Path, denied: &mut Vec<PathBuf>) -> Result<()> {
+        use std::os::unix::fs::MetadataExt;
+
+        let metadata = std::fs::symlink_metadata(dir)?;
+        if !self.can_read(metadata.uid(), metadata.gid(), metadata.mode(), true) {
+            denied.push(rel.to_path_buf());
+            return Ok(());
+        }
+
+        let mut entries = std::fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let path = entry.path();
+            let rel_path = rel.join(entry.file_name());
+            let metadata = entry.metadata()?;
+
+            if metadata.is_dir() {
+                self.check_dir(&path, &rel_path, denied)?;
+            } else if metadata.is_file()
+                && !self.can_read(metadata.uid(), metadata.gid(), metadata.mode(), false)
+            {
+                denied.push(rel_path);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn check_dir(&self, _dir: &Path, _rel: &Path, _denied: &mut Vec<PathBuf>) -> Result<()> {
+        tracing::warn!("Context permission checks are only supported on unix");
+        Ok(())
+    }
+}
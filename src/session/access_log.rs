@@ -0,0 +1,79 @@
+//! Logging policy for credential and secret access
+//!
+//! Registry hosts, usernames and secret IDs can be sensitive in their own
+//! right. [`AccessLogPolicy`] decides how much of them the auth and secrets
+//! services (and the tunnel dispatching to them) write to the log.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+
+/// Placeholder logged instead of an identifier under [`AccessLogPolicy::Silent`]
+pub const REDACTED: &str = "<redacted>";
+
+/// How credential and secret requests are logged
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum AccessLogPolicy {
+    /// Never log which hosts or secrets were requested; failures are logged
+    /// with identifiers redacted
+    Silent,
+    /// Log registry hosts and secret IDs at DEBUG
+    #[default]
+    Ids,
+    /// Log hosts, secret IDs, usernames and sizes at INFO
+    Full,
+}
+
+impl AccessLogPolicy {
+    /// Log an access event
+    ///
+    /// `ids` names the host or secret; `details` is only built under
+    /// [`Full`](Self::Full) and may add usernames, sizes and annotations.
+    pub fn record(&self, ids: impl FnOnce() -> String, details: impl FnOnce() -> String) {
+        match self {
+            AccessLogPolicy::Silent => {}
+            AccessLogPolicy::Ids => tracing::debug!("{}", ids()),
+            AccessLogPolicy::Full => tracing::info!("{}", details()),
+        }
+    }
+
+    /// The identifier to use in warnings and errors
+    pub fn redact<'a>(&self, id: &'a str) -> &'a str {
+        match self {
+            AccessLogPolicy::Silent => REDACTED,
+            _ => id,
+        }
+    }
+
+    /// Policy name as accepted by [`FromStr`]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccessLogPolicy::Silent => "silent",
+            AccessLogPolicy::Ids => "ids",
+            AccessLogPolicy::Full => "full",
+        }
+    }
+}
+
+impl FromStr for AccessLogPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "silent" => Ok(AccessLogPolicy::Silent),
+            "ids" => Ok(AccessLogPolicy::Ids),
+            "full" => Ok(AccessLogPolicy::Full),
+            _ => Err(Error::InvalidConfig(format!(
+                "unknown access log policy {:?} (expected silent, ids or full)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for AccessLogPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
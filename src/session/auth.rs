@@ -1,6 +1,13 @@
 //! Authentication protocol implementation for BuildKit sessions
 
 use tonic::{Request, Response, Status};
+use super::access_log::AccessLogPolicy;
+use crate::builder::RegistryAuth;
+use ed25519_dalek::{Signer, SigningKey};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use crate::proto::moby::filesync::v1::{
     auth_server::Auth,
     CredentialsRequest, CredentialsResponse,
@@ -12,7 +19,7 @@ use crate::proto::moby::filesync::v1::{
 /// Registry authentication configuration
 ///
 /// Stores credentials for authenticating with container registries.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct RegistryAuthConfig {
     /// Registry hostname (e.g., "docker.io", "ghcr.io", "localhost:5000")
     pub host: String,
@@ -20,14 +27,245 @@ pub struct RegistryAuthConfig {
     pub username: String,
     /// Password or access token for registry authentication
     pub password: String,
+    /// Identity (OAuth2 refresh) token, returned instead of the username and
+    /// password when set
+    pub identity_token: Option<String>,
+}
+
+impl std::fmt::Debug for RegistryAuthConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegistryAuthConfig")
+            .field("host", &self.host)
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .field(
+                "identity_token",
+                &self.identity_token.as_ref().map(|_| "<redacted>"),
+            )
+            .finish()
+    }
+}
+
+impl From<&RegistryAuth> for RegistryAuthConfig {
+    fn from(auth: &RegistryAuth) -> Self {
+        Self {
+            host: auth.host.clone(),
+            username: auth.username.clone(),
+            password: auth.password.clone(),
+            identity_token: auth.identity_token.clone(),
+        }
+    }
+}
+
+/// Credentials presented to a registry token server
+#[derive(Clone, Copy)]
+pub(crate) enum TokenCredentials<'a> {
+    /// No credentials, for public repositories
+    Anonymous,
+    /// Username and password, sent as basic auth
+    Basic {
+        username: &'a str,
+        password: &'a str,
+    },
+    /// OAuth2 refresh token, exchanged with a `refresh_token` grant
+    Refresh(&'a str),
+}
+
+impl<'a> TokenCredentials<'a> {
+    /// Credentials of a configured registry
+    fn for_registry(config: Option<&'a RegistryAuthConfig>) -> Self {
+        match config {
+            Some(RegistryAuthConfig {
+                identity_token: Some(token),
+                ..
+            }) => TokenCredentials::Refresh(token),
+            Some(config) if !config.username.is_empty() || !config.password.is_empty() => {
+                TokenCredentials::Basic {
+                    username: &config.username,
+                    password: &config.password,
+                }
+            }
+            _ => TokenCredentials::Anonymous,
+        }
+    }
+}
+
+/// Token issued by a registry token server
+pub(crate) struct IssuedToken {
+    /// Bearer token
+    pub token: String,
+    /// Lifetime in seconds
+    pub expires_in: i64,
+}
+
+/// Lifetime assumed for tokens that do not state one, per the distribution
+/// token specification
+const DEFAULT_TOKEN_LIFETIME: i64 = 60;
+
+/// Request a bearer token for `scopes` from a token server
+///
+/// Refresh tokens use the OAuth2 `refresh_token` grant (a form POST); other
+/// credentials use the classic GET flow with optional basic auth.
+pub(crate) async fn request_token(
+    client: &reqwest::Client,
+    realm: &str,
+    service: &str,
+    scopes: &[String],
+    client_id: &str,
+    credentials: TokenCredentials<'_>,
+) -> std::result::Result<IssuedToken, String> {
+    #[derive(serde::Deserialize)]
+    struct TokenResponse {
+        #[serde(default)]
+        token: Option<String>,
+        #[serde(default)]
+        access_token: Option<String>,
+        #[serde(default)]
+        expires_in: Option<i64>,
+    }
+
+    let request = match credentials {
+        TokenCredentials::Refresh(refresh_token) => {
+            let scope = scopes.join(" ");
+            let mut form = vec![
+                ("grant_type", "refresh_token"),
+                ("refresh_token", refresh_token),
+                ("client_id", client_id),
+            ];
+            if !service.is_empty() {
+                form.push(("service", service));
+            }
+            if !scope.is_empty() {
+                form.push(("scope", scope.as_str()));
+            }
+            client.post(realm).form(&form)
+        }
+        TokenCredentials::Basic { .. } | TokenCredentials::Anonymous => {
+            let mut query: Vec<(&str, &str)> = scopes
+                .iter()
+                .map(|scope| ("scope", scope.as_str()))
+                .collect();
+            if !service.is_empty() {
+                query.push(("service", service));
+            }
+            let request = client.get(realm).query(&query);
+            match credentials {
+                TokenCredentials::Basic { username, password } => {
+                    request.basic_auth(username, Some(password))
+                }
+                _ => request,
+            }
+        }
+    };
+
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("token request returned {}", response.status()));
+    }
+    let body: TokenResponse = response.json().await.map_err(|e| e.to_string())?;
+    let token = body
+        .token
+        .filter(|token| !token.is_empty())
+        .or(body.access_token)
+        .ok_or_else(|| "token response without token".to_string())?;
+    Ok(IssuedToken {
+        token,
+        expires_in: body
+            .expires_in
+            .filter(|&seconds| seconds > 0)
+            .unwrap_or(DEFAULT_TOKEN_LIFETIME),
+    })
+}
+
+/// Token handed to BuildKit, reused until shortly before it expires
+#[derive(Clone)]
+struct CachedToken {
+    token: String,
+    expires_in: i64,
+    issued_at: i64,
+    refresh_at: Instant,
+}
+
+impl CachedToken {
+    fn new(issued: IssuedToken) -> Self {
+        let issued_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs() as i64);
+        // Refresh once 90% of the lifetime has passed
+        let lifetime = Duration::from_secs(issued.expires_in as u64);
+        Self {
+            token: issued.token,
+            expires_in: issued.expires_in,
+            issued_at,
+            refresh_at: Instant::now() + lifetime.mul_f64(0.9),
+        }
+    }
+}
+
+impl std::fmt::Debug for CachedToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CachedToken")
+            .field("token", &"<redacted>")
+            .field("expires_in", &self.expires_in)
+            .field("issued_at", &self.issued_at)
+            .finish()
+    }
+}
+
+/// Environment variable that turns the token authority off when true, as
+/// with the Docker CLI and buildx
+pub const NO_CLIENT_TOKEN_ENV: &str = "BUILDKIT_NO_CLIENT_TOKEN";
+
+/// Ed25519 keys vouching for tokens this session fetches for BuildKit
+///
+/// Each host gets a random seed when BuildKit first asks for it, and keys
+/// are derived from the seed and BuildKit's salt. Seeds never leave the
+/// client, so only this session can sign for its keys.
+#[derive(Clone, Default)]
+struct TokenAuthority {
+    seeds: Arc<Mutex<HashMap<String, [u8; 32]>>>,
+}
+
+impl TokenAuthority {
+    fn key(&self, host: &str, salt: &[u8]) -> SigningKey {
+        let seed = *self
+            .seeds
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(host.to_string())
+            .or_insert_with(rand::random);
+        let derived: [u8; 32] = Sha256::new()
+            .chain_update(seed)
+            .chain_update(salt)
+            .finalize()
+            .into();
+        SigningKey::from_bytes(&derived)
+    }
+}
+
+impl std::fmt::Debug for TokenAuthority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokenAuthority").finish_non_exhaustive()
+    }
 }
 
 /// Auth server implementation for BuildKit session
 ///
 /// Handles registry authentication requests during image push operations.
+/// Tokens fetched on BuildKit's behalf are cached per host, realm, service
+/// and scope set, and shared by clones of the server.
+///
+/// For hosts with credentials the server acts as a token authority: it
+/// hands BuildKit an Ed25519 public key and signs BuildKit's challenges, so
+/// the daemon fetches tokens through [`Auth::fetch_token`] instead of asking
+/// for the raw username and password.
 #[derive(Debug, Clone, Default)]
 pub struct AuthServer {
     registries: Vec<RegistryAuthConfig>,
+    access_log: AccessLogPolicy,
+    client: reqwest::Client,
+    tokens: Arc<Mutex<HashMap<String, CachedToken>>>,
+    authority: TokenAuthority,
 }
 
 impl AuthServer {
@@ -41,9 +279,12 @@ impl AuthServer {
     /// let auth = AuthServer::new();
     /// ```
     pub fn new() -> Self {
-        Self {
-            registries: Vec::new(),
-        }
+        Self::default()
+    }
+
+    /// Set how credential requests are logged
+    pub fn set_access_log(&mut self, policy: AccessLogPolicy) {
+        self.access_log = policy;
     }
 
     /// Add registry credentials
@@ -62,12 +303,39 @@ impl AuthServer {
     ///     host: "docker.io".to_string(),
     ///     username: "myuser".to_string(),
     ///     password: "mytoken".to_string(),
+    ///     identity_token: None,
     /// });
     /// ```
     pub fn add_registry(&mut self, config: RegistryAuthConfig) {
         self.registries.push(config);
     }
 
+    /// Check whether no registry has credentials
+    pub fn is_empty(&self) -> bool {
+        self.registries.is_empty()
+    }
+
+    /// Check whether BuildKit would get credentials when asking for `host`
+    pub fn has_credentials(&self, host: &str) -> bool {
+        self.find_credentials(host).is_some()
+    }
+
+    /// Signing key for `host`, if this session can vouch for its tokens
+    fn authority_key(&self, host: &str, salt: &[u8]) -> Result<SigningKey, Status> {
+        let disabled = std::env::var(NO_CLIENT_TOKEN_ENV)
+            .is_ok_and(|value| matches!(value.as_str(), "1" | "true" | "TRUE" | "True"));
+        if disabled {
+            return Err(Status::unavailable("client side tokens disabled"));
+        }
+        match self.find_credentials(host) {
+            Some(config) if config.identity_token.is_some() || !config.password.is_empty() => {
+                Ok(self.authority.key(host, salt))
+            }
+            // BuildKit falls back to the Credentials method
+            _ => Err(Status::unavailable(format!("no credentials for {}", host))),
+        }
+    }
+
     fn find_credentials(&self, host: &str) -> Option<&RegistryAuthConfig> {
         self.registries.iter().find(|r| {
             r.host == host ||
@@ -85,16 +353,31 @@ impl Auth for AuthServer {
         request: Request<CredentialsRequest>,
     ) -> Result<Response<CredentialsResponse>, Status> {
         let req = request.into_inner();
-        tracing::debug!("Credentials requested for host: {}", req.host);
-
         if let Some(config) = self.find_credentials(&req.host) {
-            tracing::debug!("Found credentials for host: {}", req.host);
+            // An empty username tells BuildKit the secret is an identity token
+            if let Some(token) = &config.identity_token {
+                self.access_log.record(
+                    || format!("Credentials requested for host: {}", req.host),
+                    || format!("Returning identity token for host: {}", req.host),
+                );
+                return Ok(Response::new(CredentialsResponse {
+                    username: String::new(),
+                    secret: token.clone(),
+                }));
+            }
+            self.access_log.record(
+                || format!("Credentials requested for host: {}", req.host),
+                || format!("Returning credentials for host: {} (username: {})", req.host, config.username),
+            );
             Ok(Response::new(CredentialsResponse {
                 username: config.username.clone(),
                 secret: config.password.clone(),
             }))
         } else {
-            tracing::debug!("No credentials found for host: {}", req.host);
+            self.access_log.record(
+                || format!("Credentials requested for host: {}", req.host),
+                || format!("No credentials found for host: {}, returning empty", req.host),
+            );
             // Return empty credentials (anonymous access)
             Ok(Response::new(CredentialsResponse {
                 username: String::new(),
@@ -108,37 +391,116 @@ impl Auth for AuthServer {
         request: Request<FetchTokenRequest>,
     ) -> Result<Response<FetchTokenResponse>, Status> {
         let req = request.into_inner();
-        tracing::debug!(
-            "FetchToken requested - Host: {}, Realm: {}, Service: {}, Scopes: {:?}",
-            req.host, req.realm, req.service, req.scopes
+        self.access_log.record(
+            || format!("FetchToken requested for host: {}", req.host),
+            || format!(
+                "FetchToken requested - Host: {}, Realm: {}, Service: {}, Scopes: {:?}",
+                req.host, req.realm, req.service, req.scopes
+            ),
         );
 
-        // For most cases, BuildKit will handle token exchange
-        // We just need to provide basic auth credentials via the Credentials RPC
+        if req.realm.is_empty() {
+            return Err(Status::invalid_argument("token request without realm"));
+        }
+
+        let mut scopes = req.scopes.clone();
+        scopes.sort();
+        scopes.dedup();
+        let key = [
+            req.host.as_str(),
+            req.realm.as_str(),
+            req.service.as_str(),
+            &scopes.join(" "),
+        ]
+        .join("\n");
+
+        let cached = self
+            .tokens
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&key)
+            .filter(|cached| Instant::now() < cached.refresh_at)
+            .cloned();
+        let cached = match cached {
+            Some(cached) => cached,
+            None => {
+                let client_id = if req.client_id.is_empty() {
+                    "buildkit-client"
+                } else {
+                    req.client_id.as_str()
+                };
+                let credentials = TokenCredentials::for_registry(self.find_credentials(&req.host));
+                let issued = request_token(
+                    &self.client,
+                    &req.realm,
+                    &req.service,
+                    &scopes,
+                    client_id,
+                    credentials,
+                )
+                .await
+                .map_err(|e| {
+                    Status::unavailable(format!(
+                        "token request to {} for {} failed: {}",
+                        req.realm, req.host, e
+                    ))
+                })?;
+                let cached = CachedToken::new(issued);
+                self.tokens
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .insert(key, cached.clone());
+                cached
+            }
+        };
+
         Ok(Response::new(FetchTokenResponse {
-            token: String::new(),
-            expires_in: 0,
-            issued_at: 0,
+            token: cached.token,
+            expires_in: cached.expires_in,
+            issued_at: cached.issued_at,
         }))
     }
 
     async fn get_token_authority(
         &self,
-        _request: Request<GetTokenAuthorityRequest>,
+        request: Request<GetTokenAuthorityRequest>,
     ) -> Result<Response<GetTokenAuthorityResponse>, Status> {
-        // Not implementing token authority for now
+        let req = request.into_inner();
+        let key = self.authority_key(&req.host, &req.salt);
+        self.access_log.record(
+            || format!("Token authority requested for host: {}", req.host),
+            || match &key {
+                Ok(_) => format!("Returning token authority key for host: {}", req.host),
+                Err(status) => format!(
+                    "No token authority for host: {} ({})",
+                    req.host,
+                    status.message()
+                ),
+            },
+        );
         Ok(Response::new(GetTokenAuthorityResponse {
-            public_key: vec![],
+            public_key: key?.verifying_key().to_bytes().to_vec(),
         }))
     }
 
     async fn verify_token_authority(
         &self,
-        _request: Request<VerifyTokenAuthorityRequest>,
+        request: Request<VerifyTokenAuthorityRequest>,
     ) -> Result<Response<VerifyTokenAuthorityResponse>, Status> {
-        // Not implementing token authority for now
+        let req = request.into_inner();
+        let key = self.authority_key(&req.host, &req.salt)?;
+        self.access_log.record(
+            || format!("Token authority challenge for host: {}", req.host),
+            || {
+                format!(
+                    "Signing {} byte token authority challenge for host: {}",
+                    req.payload.len(),
+                    req.host
+                )
+            },
+        );
         Ok(Response::new(VerifyTokenAuthorityResponse {
-            signed: vec![],
+            signed: key.sign(&req.payload).to_bytes().to_vec(),
         }))
     }
 }
@@ -0,0 +1,381 @@
+//! Content store service for custom artifact stores
+//!
+//! BuildKit can read and write blobs (layers, configs, manifests) through a
+//! containerd `Content` service attached to the session instead of a
+//! registry. Each request carries a store ID in the
+//! [`STORE_ID_HEADER`] metadata; [`ContentStoreServer`] routes it to the
+//! [`ContentStore`] the embedder registered under that ID.
+//!
+//! Stores are used for OCI layout sources (`oci-layout://<store-id>/...`)
+//! and by the `oci`/`docker` exporters writing unpacked layouts, which use
+//! [`EXPORT_STORE_ID`].
+
+use crate::error::{Error, Result};
+use crate::proto::containerd::services::content::v1::{
+    Info, InfoRequest, InfoResponse, ReadContentRequest, ReadContentResponse, UpdateRequest,
+    UpdateResponse, WriteAction, WriteContentRequest, WriteContentResponse,
+};
+use bytes::{Bytes, BytesMut};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Metadata header naming the store a content request is for
+pub const STORE_ID_HEADER: &str = "buildkit-attachable-store-id";
+
+/// Store ID the `oci` and `docker` exporters write unpacked layouts to
+pub const EXPORT_STORE_ID: &str = "export";
+
+/// Largest chunk returned per `Read` response
+pub const READ_CHUNK_SIZE: usize = 1 << 20;
+
+/// Metadata for a stored blob
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlobInfo {
+    /// Content digest (e.g., "sha256:abc...")
+    pub digest: String,
+    /// Size in bytes
+    pub size: u64,
+    /// Labels attached by BuildKit (e.g., garbage collection references)
+    pub labels: HashMap<String, String>,
+}
+
+impl BlobInfo {
+    fn into_proto(self) -> Info {
+        Info {
+            digest: self.digest,
+            size: self.size as i64,
+            labels: self.labels,
+            ..Default::default()
+        }
+    }
+}
+
+/// A blob store BuildKit can import from or export to over the session
+///
+/// Implement this to serve blobs from (or capture them into) an internal
+/// artifact system. Stores that only serve imports can keep the default
+/// [`writer`](Self::writer), which rejects uploads.
+#[tonic::async_trait]
+pub trait ContentStore: Send + Sync {
+    /// Look up a blob, returning `None` if the store does not have it
+    async fn info(&self, digest: &str) -> Result<Option<BlobInfo>>;
+
+    /// Read up to `len` bytes of a blob starting at `offset`
+    ///
+    /// An empty result means `offset` is at or past the end of the blob.
+    async fn read_at(&self, digest: &str, offset: u64, len: usize) -> Result<Bytes>;
+
+    /// Start receiving the blob uploaded under `reference`
+    ///
+    /// The digest is only known, and verified, once the upload commits.
+    async fn writer(&self, reference: &str) -> Result<Box<dyn BlobWriter>> {
+        Err(Error::content_store(format!(
+            "store is read-only, cannot write {}",
+            reference
+        )))
+    }
+
+    /// Replace the labels of a stored blob
+    async fn set_labels(&self, digest: &str, labels: HashMap<String, String>) -> Result<()> {
+        let _ = (digest, labels);
+        Ok(())
+    }
+}
+
+/// Destination of one blob upload
+///
+/// Chunks are passed on in order as BuildKit sends them. The blob must not
+/// become visible under its digest before [`commit`](Self::commit); a
+/// writer dropped without committing discards what it received.
+#[tonic::async_trait]
+pub trait BlobWriter: Send {
+    /// Append the next chunk of the blob
+    async fn write(&mut self, data: &[u8]) -> Result<()>;
+
+    /// Publish the blob once its size and digest have been verified
+    async fn commit(self: Box<Self>, info: BlobInfo) -> Result<()>;
+}
+
+/// Read a whole blob from a store and check it against its digest
+///
+/// Only `sha256` digests can be verified; others are rejected.
+pub async fn read_blob(store: &dyn ContentStore, digest: &str) -> Result<Bytes> {
+    let expected = digest
+        .strip_prefix("sha256:")
+        .ok_or_else(|| Error::content_store(format!("unsupported digest {}", digest)))?;
+    if store.info(digest).await?.is_none() {
+        return Err(Error::content_store(format!("blob {} not found", digest)));
+    }
+
+    let mut data = BytesMut::new();
+    loop {
+        let chunk = store
+            .read_at(digest, data.len() as u64, READ_CHUNK_SIZE)
+            .await?;
+        if chunk.is_empty() {
+            break;
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    let actual = format!("{:x}", Sha256::digest(&data));
+    if actual != expected {
+        return Err(Error::content_store(format!(
+            "digest mismatch: expected {}, got sha256:{}",
+            digest, actual
+        )));
+    }
+    Ok(data.freeze())
+}
+
+/// Session service routing content requests to registered stores
+#[derive(Clone, Default)]
+pub struct ContentStoreServer {
+    stores: HashMap<String, Arc<dyn ContentStore>>,
+}
+
+impl ContentStoreServer {
+    /// Create a server without stores
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a store under `id`, replacing any store with the same ID
+    pub fn add_store(&mut self, id: impl Into<String>, store: Arc<dyn ContentStore>) {
+        self.stores.insert(id.into(), store);
+    }
+
+    /// Store registered under `id`
+    pub fn store(&self, id: &str) -> Result<&Arc<dyn ContentStore>> {
+        self.stores
+            .get(id)
+            .ok_or_else(|| Error::content_store(format!("unknown content store {:?}", id)))
+    }
+
+    /// IDs of the registered stores
+    pub fn store_ids(&self) -> impl Iterator<Item = &str> {
+        self.stores.keys().map(String::as_str)
+    }
+
+    /// Check whether no stores are registered
+    pub fn is_empty(&self) -> bool {
+        self.stores.is_empty()
+    }
+
+    /// Handle `Content.Info`
+    pub async fn info(&self, store_id: &str, request: InfoRequest) -> Result<InfoResponse> {
+        let info = self
+            .store(store_id)?
+            .info(&request.digest)
+            .await?
+            .ok_or_else(|| Error::ContentNotFound {
+                store: store_id.to_string(),
+                digest: request.digest.clone(),
+            })?;
+        Ok(InfoResponse {
+            info: Some(info.into_proto()),
+        })
+    }
+
+    /// Handle `Content.Update`, which BuildKit uses to set labels
+    pub async fn update(&self, store_id: &str, request: UpdateRequest) -> Result<UpdateResponse> {
+        let update = request
+            .info
+            .ok_or_else(|| Error::protocol("Content.Update request without info"))?;
+        let store = self.store(store_id)?;
+        let mut info = store
+            .info(&update.digest)
+            .await?
+            .ok_or_else(|| Error::ContentNotFound {
+                store: store_id.to_string(),
+                digest: update.digest.clone(),
+            })?;
+        store
+            .set_labels(&update.digest, update.labels.clone())
+            .await?;
+        info.labels = update.labels;
+        Ok(UpdateResponse {
+            info: Some(info.into_proto()),
+        })
+    }
+
+    /// Read the next chunk of a `Content.Read` stream
+    ///
+    /// `position` starts at the request offset and is advanced past the
+    /// returned data; `None` ends the stream.
+    pub async fn read_chunk(
+        &self,
+        store_id: &str,
+        request: &ReadContentRequest,
+        position: &mut u64,
+    ) -> Result<Option<ReadContentResponse>> {
+        let mut len = READ_CHUNK_SIZE;
+        if request.size > 0 {
+            let end = request.offset.max(0) as u64 + request.size as u64;
+            len = len.min(end.saturating_sub(*position) as usize);
+            if len == 0 {
+                return Ok(None);
+            }
+        }
+
+        let data = self
+            .store(store_id)?
+            .read_at(&request.digest, *position, len)
+            .await?;
+        if data.is_empty() {
+            return Ok(None);
+        }
+
+        let offset = *position;
+        *position += data.len() as u64;
+        Ok(Some(ReadContentResponse {
+            offset: offset as i64,
+            data: data.to_vec(),
+        }))
+    }
+}
+
+impl fmt::Debug for ContentStoreServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut ids: Vec<&str> = self.store_ids().collect();
+        ids.sort_unstable();
+        f.debug_struct("ContentStoreServer")
+            .field("stores", &ids)
+            .finish()
+    }
+}
+
+/// State of one `Content.Write` stream
+///
+/// Chunks are streamed to the store's [`BlobWriter`] as they arrive and
+/// hashed along the way, so the digest is checked on commit without holding
+/// the blob in memory.
+#[derive(Default)]
+pub struct BlobUpload {
+    reference: String,
+    expected: Option<String>,
+    total: Option<u64>,
+    offset: u64,
+    hasher: Sha256,
+    writer: Option<Box<dyn BlobWriter>>,
+}
+
+impl BlobUpload {
+    /// Start an empty upload
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of bytes received so far
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Apply one write request and build its response
+    pub async fn apply(
+        &mut self,
+        store: &dyn ContentStore,
+        request: WriteContentRequest,
+    ) -> Result<WriteContentResponse> {
+        if !request.r#ref.is_empty() {
+            self.reference = request.r#ref.clone();
+        }
+        if !request.expected.is_empty() {
+            self.expected = Some(request.expected.clone());
+        }
+        if request.total > 0 {
+            self.total = Some(request.total as u64);
+        }
+
+        let action = WriteAction::try_from(request.action)
+            .map_err(|_| Error::protocol(format!("unknown write action {}", request.action)))?;
+        let mut digest = String::new();
+        match action {
+            WriteAction::Stat => {}
+            WriteAction::Write | WriteAction::Commit => {
+                if !request.data.is_empty() {
+                    self.append(store, request.offset, &request.data).await?;
+                }
+                if action == WriteAction::Commit {
+                    digest = self.commit(store, request.labels).await?;
+                }
+            }
+        }
+
+        Ok(WriteContentResponse {
+            action: request.action,
+            offset: self.offset() as i64,
+            total: self.total.unwrap_or(0) as i64,
+            digest,
+            ..Default::default()
+        })
+    }
+
+    async fn append(&mut self, store: &dyn ContentStore, offset: i64, data: &[u8]) -> Result<()> {
+        if offset as u64 != self.offset() {
+            return Err(Error::protocol(format!(
+                "write to {} at offset {}, expected {}",
+                self.reference,
+                offset,
+                self.offset()
+            )));
+        }
+        self.writer(store).await?.write(data).await?;
+        self.hasher.update(data);
+        self.offset += data.len() as u64;
+        Ok(())
+    }
+
+    /// Writer for this upload, opened on first use
+    async fn writer(&mut self, store: &dyn ContentStore) -> Result<&mut Box<dyn BlobWriter>> {
+        if self.writer.is_none() {
+            self.writer = Some(store.writer(&self.reference).await?);
+        }
+        Ok(self.writer.as_mut().expect("writer was just opened"))
+    }
+
+    async fn commit(
+        &mut self,
+        store: &dyn ContentStore,
+        labels: HashMap<String, String>,
+    ) -> Result<String> {
+        let size = self.offset();
+        if let Some(total) = self.total.filter(|total| *total != size) {
+            return Err(Error::content_store(format!(
+                "commit of {} with {} bytes, expected {}",
+                self.reference, size, total
+            )));
+        }
+
+        let digest = format!("sha256:{:x}", self.hasher.clone().finalize());
+        if let Some(expected) = self.expected.as_ref().filter(|e| **e != digest) {
+            return Err(Error::content_store(format!(
+                "digest mismatch for {}: expected {}, got {}",
+                self.reference, expected, digest
+            )));
+        }
+
+        let info = BlobInfo {
+            digest: digest.clone(),
+            size,
+            labels,
+        };
+        self.writer(store).await?;
+        let writer = self.writer.take().expect("writer was just opened");
+        writer.commit(info).await?;
+        Ok(digest)
+    }
+}
+
+impl fmt::Debug for BlobUpload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlobUpload")
+            .field("reference", &self.reference)
+            .field("expected", &self.expected)
+            .field("total", &self.total)
+            .field("offset", &self.offset)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,199 @@
+//! Duplicate file detection for context uploads
+//!
+//! Vendored dependencies often contain many copies of the same file. The
+//! fsutil protocol has no content addressing, but it does carry hardlinks:
+//! a file sent as a link to an earlier path is recreated by BuildKit without
+//! requesting its data. [`DuplicateIndex`] finds such copies while the
+//! context is walked, hashing only files whose size and mode match a file
+//! seen before.
+//!
+//! Files that already are hardlinks on disk (the same device and inode) are
+//! always sent as links to the first path listed, as fsutil's own sender
+//! does, so the context BuildKit recreates matches the one buildx sends.
+
+use super::filesync::hash_file;
+use crate::error::Result;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// What to do with files whose content duplicates an earlier file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum DuplicateFiles {
+    /// Send every file as it is, without hashing
+    #[default]
+    Ignore,
+    /// Send every file, but count duplicates in [`SyncStats`]
+    Report,
+    /// Send duplicates as hardlinks to the first copy, so their data is
+    /// transferred once
+    ///
+    /// The copies share one inode in the build's context, which only makes a
+    /// difference to builds that inspect link counts.
+    Link,
+}
+
+/// Totals of the files sent from local contexts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SyncStats {
+    /// Regular files listed
+    pub files: u64,
+    /// Combined size of the listed files
+    pub bytes: u64,
+    /// Files whose content matches an earlier file
+    pub duplicate_files: u64,
+    /// Combined size of the duplicate files
+    pub duplicate_bytes: u64,
+}
+
+impl SyncStats {
+    /// Count a listed file
+    pub fn record(&mut self, size: u64, duplicate: bool) {
+        self.files += 1;
+        self.bytes += size;
+        if duplicate {
+            self.duplicate_files += 1;
+            self.duplicate_bytes += size;
+        }
+    }
+
+    /// Add the totals of another transfer
+    pub fn add(&mut self, other: &SyncStats) {
+        self.files += other.files;
+        self.bytes += other.bytes;
+        self.duplicate_files += other.duplicate_files;
+        self.duplicate_bytes += other.duplicate_bytes;
+    }
+}
+
+/// A file that later files are compared against
+#[derive(Debug)]
+struct Original {
+    path: PathBuf,
+    rel_path: String,
+    digest: Option<[u8; 32]>,
+}
+
+/// Files seen so far in one transfer, by size and mode
+#[derive(Debug, Default)]
+pub struct DuplicateIndex {
+    originals: HashMap<(u64, u32), Vec<Original>>,
+}
+
+impl DuplicateIndex {
+    /// Create an empty index
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Relative path of an earlier file with the same content and mode
+    ///
+    /// Files without a match are remembered for later lookups. Empty files
+    /// are never reported as duplicates.
+    pub async fn find(
+        &mut self,
+        path: &Path,
+        rel_path: &str,
+        size: u64,
+        mode: u32,
+    ) -> Result<Option<String>> {
+        if size == 0 {
+            return Ok(None);
+        }
+
+        let originals = self.originals.entry((size, mode)).or_default();
+        let mut digest = None;
+        if !originals.is_empty() {
+            let own = hash_file(path).await?;
+            for original in originals.iter_mut() {
+                let theirs = match original.digest {
+                    Some(theirs) => theirs,
+                    None => *original.digest.insert(hash_file(&original.path).await?),
+                };
+                if theirs == own {
+                    return Ok(Some(original.rel_path.clone()));
+                }
+            }
+            digest = Some(own);
+        }
+
+        originals.push(Original {
+            path: path.to_path_buf(),
+            rel_path: rel_path.to_string(),
+            digest,
+        });
+        Ok(None)
+    }
+}
+
+/// Duplicate handling for one transfer
+#[derive(Debug)]
+pub(crate) struct TransferDedup {
+    policy: DuplicateFiles,
+    index: DuplicateIndex,
+    /// First path listed for each inode with more than one link
+    inodes: HashMap<(u64, u64), String>,
+    stats: SyncStats,
+}
+
+impl TransferDedup {
+    pub(crate) fn new(policy: DuplicateFiles) -> Self {
+        Self {
+            policy,
+            index: DuplicateIndex::new(),
+            inodes: HashMap::new(),
+            stats: SyncStats::default(),
+        }
+    }
+
+    /// Count a regular file; returns the path to link it to instead of
+    /// sending its data
+    ///
+    /// Hardlinks to a file listed before are linked whatever the policy.
+    pub(crate) async fn check(
+        &mut self,
+        path: &Path,
+        rel_path: &str,
+        metadata: &std::fs::Metadata,
+        mode: u32,
+    ) -> Result<Option<String>> {
+        let size = metadata.len();
+        if let Some(original) = self.hardlink(rel_path, metadata) {
+            self.stats.record(size, true);
+            return Ok(Some(original));
+        }
+        let original = match self.policy {
+            DuplicateFiles::Ignore => None,
+            DuplicateFiles::Report | DuplicateFiles::Link => {
+                self.index.find(path, rel_path, size, mode).await?
+            }
+        };
+        self.stats.record(size, original.is_some());
+        Ok(original.filter(|_| self.policy == DuplicateFiles::Link))
+    }
+
+    /// Path listed earlier for the same inode as `metadata`
+    #[cfg(unix)]
+    fn hardlink(&mut self, rel_path: &str, metadata: &std::fs::Metadata) -> Option<String> {
+        use std::os::unix::fs::MetadataExt;
+        if metadata.nlink() < 2 {
+            return None;
+        }
+        match self.inodes.entry((metadata.dev(), metadata.ino())) {
+            Entry::Occupied(entry) => Some(entry.get().clone()),
+            Entry::Vacant(entry) => {
+                entry.insert(rel_path.to_string());
+                None
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn hardlink(&mut self, _rel_path: &str, _metadata: &std::fs::Metadata) -> Option<String> {
+        None
+    }
+
+    pub(crate) fn stats(&self) -> &SyncStats {
+        &self.stats
+    }
+}
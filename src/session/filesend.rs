@@ -0,0 +1,323 @@
+//! FileSend protocol implementation for BuildKit sessions
+//!
+//! Exporters that deliver their result to the client (e.g. `type=oci`) call
+//! back into the session through `/moby.filesync.v1.FileSend/DiffCopy`. The
+//! daemon identifies which exporter is writing through the
+//! `buildkit-attachable-exporter-id` header, which is the exporter's index in
+//! the solve request.
+//!
+//! Tarball exporters stream raw `BytesMessage` chunks. `type=local` runs the
+//! fsutil protocol in reverse: the daemon sends STAT packets for the exported
+//! tree, and we request the contents of regular files and write them out.
+
+use crate::error::{Error, Result};
+use crate::output::ExportDest;
+use crate::proto::fsutil::types::{packet::PacketType, Packet, Stat};
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// Header carrying the index of the exporter that opened the stream
+pub const EXPORTER_ID_HEADER: &str = "buildkit-attachable-exporter-id";
+
+/// Prefix of headers carrying exporter metadata
+pub const EXPORTER_METADATA_PREFIX: &str = "exporter-md-";
+
+/// Client-side destination for a single exporter
+#[derive(Debug, Clone)]
+pub enum SendTarget {
+    /// A single tarball stream written to a file or writer
+    Archive(ExportDest),
+    /// A directory tree written below a local directory
+    Directory(PathBuf),
+}
+
+/// File send server implementation for BuildKit session
+///
+/// Receives exporter output streamed back from BuildKit.
+#[derive(Debug, Clone, Default)]
+pub struct FileSendServer {
+    targets: HashMap<u32, SendTarget>,
+}
+
+impl FileSendServer {
+    /// Create a new file send server with no targets
+    pub fn new() -> Self {
+        Self {
+            targets: HashMap::new(),
+        }
+    }
+
+    /// Register the destination for the exporter at `exporter_id`
+    pub fn add_target(&mut self, exporter_id: u32, target: SendTarget) {
+        self.targets.insert(exporter_id, target);
+    }
+
+    /// Check whether any target is registered
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    /// Look up the target for an exporter
+    pub fn target(&self, exporter_id: u32) -> Option<&SendTarget> {
+        self.targets.get(&exporter_id)
+    }
+
+    /// Open the archive writer for an exporter
+    pub(crate) async fn open_archive(
+        &self,
+        exporter_id: u32,
+    ) -> Result<Box<dyn AsyncWrite + Send + Unpin>> {
+        match self.target(exporter_id) {
+            Some(SendTarget::Archive(dest)) => dest.open().await,
+            Some(SendTarget::Directory(_)) => Err(Error::session(format!(
+                "exporter {} expects a directory, not an archive",
+                exporter_id
+            ))),
+            None => Err(Error::session(format!(
+                "exporter {} not found",
+                exporter_id
+            ))),
+        }
+    }
+}
+
+// Go os.FileMode type bits used by fsutil
+const GO_MODE_DIR: u32 = 1 << 31;
+const GO_MODE_SYMLINK: u32 = 1 << 27;
+const GO_MODE_TYPE: u32 = GO_MODE_DIR
+    | GO_MODE_SYMLINK
+    | 1 << 26 // device
+    | 1 << 25 // named pipe
+    | 1 << 24 // socket
+    | 1 << 21 // char device
+    | 1 << 19; // irregular
+
+/// Receiving side of an fsutil transfer into a local directory
+///
+/// Mirrors fsutil's `Receive` with merge semantics: entries already present
+/// in the destination are overwritten but never deleted. Feed every packet
+/// from the sender to [`handle`](Self::handle) and send back the packets it
+/// returns; the transfer is complete once [`is_done`](Self::is_done) is true.
+#[derive(Debug)]
+pub struct DirectoryReceiver {
+    dest: PathBuf,
+    /// ID assigned to the next STAT packet (its index in the stream)
+    next_id: u32,
+    /// Requested files by ID: relative path and permission bits
+    requested: HashMap<u32, (PathBuf, u32)>,
+    /// Files currently receiving data
+    open: HashMap<u32, tokio::fs::File>,
+    /// Symlinks created so far; nothing may be written through them
+    symlinks: HashSet<PathBuf>,
+    /// Directories whose permissions are applied once all content is written
+    dirs: Vec<(PathBuf, u32)>,
+    stats_done: bool,
+    fin_sent: bool,
+}
+
+impl DirectoryReceiver {
+    /// Create a receiver writing below `dest`
+    pub fn new(dest: impl Into<PathBuf>) -> Self {
+        Self {
+            dest: dest.into(),
+            next_id: 0,
+            requested: HashMap::new(),
+            open: HashMap::new(),
+            symlinks: HashSet::new(),
+            dirs: Vec::new(),
+            stats_done: false,
+            fin_sent: false,
+        }
+    }
+
+    /// Check whether all files have been received and FIN was sent
+    pub fn is_done(&self) -> bool {
+        self.fin_sent
+    }
+
+    /// Process one packet from the sender, returning packets to send back
+    pub async fn handle(&mut self, packet: Packet) -> Result<Vec<Packet>> {
+        let packet_type = PacketType::try_from(packet.r#type)
+            .map_err(|_| Error::protocol(format!("unknown packet type {}", packet.r#type)))?;
+
+        let mut replies = Vec::new();
+        match packet_type {
+            PacketType::PacketStat => match packet.stat {
+                Some(stat) => {
+                    let id = self.next_id;
+                    self.next_id += 1;
+                    if let Some(req) = self.handle_stat(id, stat).await? {
+                        replies.push(req);
+                    }
+                }
+                None => self.stats_done = true,
+            },
+            PacketType::PacketData => self.handle_data(packet.id, &packet.data).await?,
+            PacketType::PacketErr => {
+                return Err(Error::session(format!(
+                    "error from sender: {}",
+                    String::from_utf8_lossy(&packet.data)
+                )));
+            }
+            PacketType::PacketFin => {
+                // The sender acknowledges our FIN; nothing left to do
+                self.stats_done = true;
+            }
+            PacketType::PacketReq => {
+                return Err(Error::protocol("unexpected REQ packet from sender"));
+            }
+        }
+
+        if self.stats_done && self.requested.is_empty() && !self.fin_sent {
+            self.finish().await?;
+            self.fin_sent = true;
+            replies.push(Packet {
+                r#type: PacketType::PacketFin as i32,
+                stat: None,
+                id: 0,
+                data: vec![],
+            });
+        }
+
+        Ok(replies)
+    }
+
+    async fn handle_stat(&mut self, id: u32, stat: Stat) -> Result<Option<Packet>> {
+        let rel = self.checked_path(&stat.path)?;
+        let target = self.dest.join(&rel);
+        let mode = stat.mode;
+        let perm = mode & 0o777;
+
+        if mode & GO_MODE_DIR != 0 {
+            tokio::fs::create_dir_all(&target).await?;
+            self.dirs.push((target, perm));
+            return Ok(None);
+        }
+
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        if mode & GO_MODE_SYMLINK != 0 {
+            remove_existing(&target).await?;
+            create_symlink(&stat.linkname, &target).await?;
+            self.symlinks.insert(rel);
+            return Ok(None);
+        }
+
+        if mode & GO_MODE_TYPE != 0 {
+            tracing::warn!("Skipping special file {} in local export", stat.path);
+            return Ok(None);
+        }
+
+        // Regular file: request its contents
+        self.requested.insert(id, (rel, perm));
+        Ok(Some(Packet {
+            r#type: PacketType::PacketReq as i32,
+            stat: None,
+            id,
+            data: vec![],
+        }))
+    }
+
+    async fn handle_data(&mut self, id: u32, data: &[u8]) -> Result<()> {
+        let Some((rel, perm)) = self.requested.get(&id).cloned() else {
+            return Err(Error::protocol(format!(
+                "DATA for unrequested file id {}",
+                id
+            )));
+        };
+        let target = self.dest.join(&rel);
+
+        if !self.open.contains_key(&id) {
+            remove_existing(&target).await?;
+            let file = tokio::fs::File::create(&target).await?;
+            self.open.insert(id, file);
+        }
+
+        if data.is_empty() {
+            // Empty DATA marks the end of this file
+            if let Some(mut file) = self.open.remove(&id) {
+                file.flush().await?;
+            }
+            self.requested.remove(&id);
+            set_permissions(&target, perm).await?;
+        } else if let Some(file) = self.open.get_mut(&id) {
+            file.write_all(data).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Apply directory permissions, deepest first so parents stay writable
+    async fn finish(&mut self) -> Result<()> {
+        for (dir, perm) in self.dirs.drain(..).rev() {
+            set_permissions(&dir, perm).await?;
+        }
+        Ok(())
+    }
+
+    /// Validate a sender-supplied path, rejecting anything that escapes `dest`
+    fn checked_path(&self, path: &str) -> Result<PathBuf> {
+        let rel = Path::new(path);
+        let mut checked = PathBuf::new();
+        for component in rel.components() {
+            match component {
+                Component::Normal(part) => checked.push(part),
+                Component::CurDir => {}
+                _ => {
+                    return Err(Error::PathOutsideRoot {
+                        path: path.to_string(),
+                    })
+                }
+            }
+        }
+
+        let through_symlink = checked
+            .ancestors()
+            .skip(1)
+            .any(|ancestor| self.symlinks.contains(ancestor));
+        if checked.as_os_str().is_empty() || through_symlink {
+            return Err(Error::PathOutsideRoot {
+                path: path.to_string(),
+            });
+        }
+        Ok(checked)
+    }
+}
+
+/// Remove a file or symlink about to be replaced (directories are kept)
+async fn remove_existing(path: &Path) -> Result<()> {
+    match tokio::fs::symlink_metadata(path).await {
+        Ok(meta) if !meta.is_dir() => Ok(tokio::fs::remove_file(path).await?),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+async fn create_symlink(linkname: &str, path: &Path) -> Result<()> {
+    Ok(tokio::fs::symlink(linkname, path).await?)
+}
+
+#[cfg(not(unix))]
+async fn create_symlink(linkname: &str, path: &Path) -> Result<()> {
+    tracing::warn!(
+        "Skipping symlink {} -> {}: not supported on this platform",
+        path.display(),
+        linkname
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn set_permissions(path: &Path, perm: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let perm = std::fs::Permissions::from_mode(perm);
+    Ok(tokio::fs::set_permissions(path, perm).await?)
+}
+
+#[cfg(not(unix))]
+async fn set_permissions(_path: &Path, _perm: u32) -> Result<()> {
+    Ok(())
+}
@@ -1,7 +1,16 @@
 //! File synchronization protocol implementation for BuildKit sessions
 
+use super::dedup::{DuplicateFiles, SyncStats};
+use super::ignore::{clean, IgnorePatterns};
+use super::source::ContextProvider;
 use crate::error::{Error, Result};
+use crate::task::spawn_named;
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use tokio::fs;
 use tokio::io::AsyncReadExt;
 use tokio_stream::wrappers::ReceiverStream;
@@ -12,13 +21,410 @@ use crate::proto::moby::filesync::v1::{
     file_sync_server::FileSync,
 };
 
+/// Options for sending local directories
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileSyncOptions {
+    /// Hash every file when it is listed and fail the transfer if its
+    /// content differs when BuildKit requests it
+    pub snapshot: bool,
+    /// Send every modification time as zero instead of the file's own
+    pub zero_mod_times: bool,
+    /// Send each entry's owner instead of root
+    pub preserve_ownership: bool,
+}
+
+impl FileSyncOptions {
+    /// Create the default options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pin each file's content when it is listed
+    ///
+    /// Files edited while the context is sent (e.g., by a hot-reload
+    /// workflow) then fail the build with
+    /// [`Error::ContextChangedDuringBuild`] instead of ending up half-written
+    /// in the image. Every file is read twice, once to hash it and once to
+    /// send it.
+    pub fn snapshot(mut self, snapshot: bool) -> Self {
+        self.snapshot = snapshot;
+        self
+    }
+
+    /// Leave modification times out of the STAT packets
+    ///
+    /// By default each entry carries its modification time, as with buildx,
+    /// so COPY layers and remote cache match contexts uploaded by it. Zeroed
+    /// times keep layers reproducible across checkouts of the same files.
+    pub fn zero_mod_times(mut self, zero: bool) -> Self {
+        self.zero_mod_times = zero;
+        self
+    }
+
+    /// Send the uid and gid of each entry instead of `0:0`
+    ///
+    /// Like `docker build`, context files are owned by root by default.
+    /// Keeping their owners is useful for rootful builds that copy system
+    /// trees; ids are sent as numbers and not mapped to names.
+    pub fn preserve_ownership(mut self, preserve: bool) -> Self {
+        self.preserve_ownership = preserve;
+        self
+    }
+
+    /// Modification time of an entry for its STAT packet, in nanoseconds
+    /// since the Unix epoch
+    pub(crate) fn mod_time(&self, metadata: &std::fs::Metadata) -> i64 {
+        if self.zero_mod_times {
+            return 0;
+        }
+        match metadata.modified() {
+            Ok(modified) => match modified.duration_since(std::time::UNIX_EPOCH) {
+                Ok(since) => since.as_nanos() as i64,
+                Err(before) => -(before.duration().as_nanos() as i64),
+            },
+            Err(_) => 0,
+        }
+    }
+
+    /// Owner of an entry for its STAT packet, as `(uid, gid)`
+    pub(crate) fn ownership(&self, metadata: &std::fs::Metadata) -> (u32, u32) {
+        if !self.preserve_ownership {
+            return (0, 0);
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            (metadata.uid(), metadata.gid())
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = metadata;
+            (0, 0)
+        }
+    }
+}
+
+/// A file listed to BuildKit, as it was when its STAT packet was sent
+#[derive(Debug, Clone)]
+pub(crate) struct ListedFile {
+    pub(crate) path: PathBuf,
+    pub(crate) rel_path: String,
+    pub(crate) size: u64,
+    digest: Option<[u8; 32]>,
+    /// Content of a file served from memory rather than from disk
+    pub(crate) content: Option<Bytes>,
+}
+
+impl ListedFile {
+    /// Record a listed file, hashing it for snapshots
+    pub(crate) async fn list(
+        path: PathBuf,
+        rel_path: String,
+        size: u64,
+        options: &FileSyncOptions,
+    ) -> Result<Self> {
+        let digest = if options.snapshot {
+            Some(hash_file(&path).await?)
+        } else {
+            None
+        };
+        Ok(Self {
+            path,
+            rel_path,
+            size,
+            digest,
+            content: None,
+        })
+    }
+
+    /// Record a file served from memory
+    pub(crate) fn in_memory(rel_path: String, content: Bytes) -> Self {
+        Self {
+            path: PathBuf::from(&rel_path),
+            rel_path,
+            size: content.len() as u64,
+            digest: None,
+            content: Some(content),
+        }
+    }
+
+    /// Open the file to send its data
+    pub(crate) async fn open(&self) -> Result<ContextFileReader> {
+        let reader = ContextFileReader::open(&self.path, self.size).await?;
+        Ok(match self.digest {
+            Some(digest) => reader.verify_digest(digest),
+            None => reader,
+        })
+    }
+}
+
+/// What a DiffCopy walk does with an entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Visit {
+    /// List the entry
+    Send,
+    /// Walk an ignored directory that may hold re-included entries, listing
+    /// it only once one of them is sent
+    Hold,
+    /// Leave the entry (and everything below it) out
+    Skip,
+}
+
+/// Decides which entries a DiffCopy of a local directory sends
+///
+/// Entries have to be visited in walk order, parents first.
+#[derive(Debug, Default)]
+pub(crate) struct ContextFilter {
+    /// Pattern sets an entry has to match each of, e.g. BuildKit's
+    /// followpaths and the configured include patterns
+    includes: Vec<IgnorePatterns>,
+    ignore: IgnorePatterns,
+    /// Per-pattern results of the directories walked so far, for each
+    /// include set and then the ignore patterns
+    dir_matches: HashMap<String, Vec<Vec<bool>>>,
+    /// Held directories not listed yet, outermost first
+    held: Vec<Stat>,
+}
+
+impl ContextFilter {
+    /// Filter by include pattern sets (empty ones include everything) and
+    /// ignore patterns
+    pub(crate) fn new(includes: Vec<IgnorePatterns>, ignore: IgnorePatterns) -> Self {
+        Self {
+            includes: includes.into_iter().filter(|set| !set.is_empty()).collect(),
+            ignore,
+            ..Self::default()
+        }
+    }
+
+    /// Decide about the entry at `rel_path`
+    pub(crate) fn visit(&mut self, rel_path: &str, is_dir: bool) -> Visit {
+        // Held directories whose walk ended without sending anything stay out
+        while self
+            .held
+            .last()
+            .is_some_and(|dir| !is_below(rel_path, &dir.path))
+        {
+            self.held.pop();
+        }
+        if self.includes.is_empty() && self.ignore.is_empty() {
+            return Visit::Send;
+        }
+
+        let parent = rel_path
+            .rsplit_once('/')
+            .and_then(|(dir, _)| self.dir_matches.get(dir));
+        let parent_matches = |i: usize| parent.map_or(&[][..], |matches| matches[i].as_slice());
+        let mut matches = Vec::with_capacity(self.includes.len() + 1);
+        let mut included = true;
+        let mut may_include = true;
+        for (i, include) in self.includes.iter().enumerate() {
+            let (matched, include_matches) = include.check(rel_path, parent_matches(i));
+            included &= matched;
+            may_include &= matched || (is_dir && include.may_match_under(rel_path));
+            matches.push(include_matches);
+        }
+        let (ignored, ignore_matches) = self
+            .ignore
+            .check(rel_path, parent_matches(self.includes.len()));
+        matches.push(ignore_matches);
+
+        // Directories that are left out themselves are still walked when
+        // something below them may be sent
+        let walk = is_dir && may_include && (!ignored || self.ignore.may_keep_under(rel_path));
+        let visit = if included && !ignored {
+            Visit::Send
+        } else if walk {
+            Visit::Hold
+        } else {
+            Visit::Skip
+        };
+        if is_dir && visit != Visit::Skip {
+            self.dir_matches.insert(rel_path.to_string(), matches);
+        }
+        visit
+    }
+
+    /// Hold back the STAT of a directory visited as [`Visit::Hold`]
+    pub(crate) fn hold(&mut self, stat: Stat) {
+        self.held.push(stat);
+    }
+
+    /// Held directories that have to be listed before the next entry
+    pub(crate) fn take_held(&mut self) -> Vec<Stat> {
+        std::mem::take(&mut self.held)
+    }
+}
+
+/// Whether `path` lies below the directory `dir`
+fn is_below(path: &str, dir: &str) -> bool {
+    path.strip_prefix(dir)
+        .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// `followpaths` plus the targets of symlinks among them, as fsutil adds,
+/// so a Dockerfile linking elsewhere in the directory is sent with its
+/// target
+pub(crate) fn follow_links(root: &Path, followpaths: &[String]) -> Vec<String> {
+    let mut paths = followpaths.to_vec();
+    for path in followpaths {
+        let mut current = path.clone();
+        // Give up on chains as long as the kernel does
+        for _ in 0..40 {
+            let Ok(target) = std::fs::read_link(root.join(&current)) else {
+                break;
+            };
+            let target = target.to_string_lossy();
+            // Absolute targets are taken relative to the root
+            let joined = match current.rsplit_once('/') {
+                _ if target.starts_with('/') => target.into_owned(),
+                Some((dir, _)) => format!("/{}/{}", dir, target),
+                None => format!("/{}", target),
+            };
+            current = clean(&joined);
+            if current == "." || paths.contains(&current) {
+                break;
+            }
+            paths.push(current.clone());
+        }
+    }
+    paths
+}
+
+/// A local context file opened for sending
+///
+/// Files are only ever opened read-only, so a sync cannot modify the context
+/// (and a binary being executed never fails with `ETXTBSY`). The size
+/// announced in the file's STAT packet is checked while reading: a file that
+/// disappears, shrinks or grows mid-sync fails with its path instead of
+/// sending data that does not match what BuildKit was told.
+#[derive(Debug)]
+pub struct ContextFileReader {
+    path: PathBuf,
+    file: fs::File,
+    expected: u64,
+    read: u64,
+    snapshot: Option<([u8; 32], Sha256)>,
+}
+
+impl ContextFileReader {
+    /// Open `path`, whose STAT packet announced `expected` bytes
+    pub async fn open(path: impl Into<PathBuf>, expected: u64) -> Result<Self> {
+        let path = path.into();
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .open(&path)
+            .await
+            .map_err(|source| Error::ContextFileRead {
+                path: path.clone(),
+                source,
+            })?;
+        Ok(Self {
+            path,
+            file,
+            expected,
+            read: 0,
+            snapshot: None,
+        })
+    }
+
+    /// Also check that the content hashes to `digest` (SHA-256)
+    ///
+    /// Changes are then reported as [`Error::ContextChangedDuringBuild`].
+    pub fn verify_digest(mut self, digest: [u8; 32]) -> Self {
+        self.snapshot = Some((digest, Sha256::new()));
+        self
+    }
+
+    /// Read the next chunk into `buf`; returns 0 once the whole file was read
+    pub async fn read_chunk(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self
+            .file
+            .read(buf)
+            .await
+            .map_err(|source| Error::ContextFileRead {
+                path: self.path.clone(),
+                source,
+            })?;
+        if n == 0 && self.read < self.expected {
+            return Err(self.changed(format!(
+                "truncated to {} of {} bytes",
+                self.read, self.expected
+            )));
+        }
+        self.read += n as u64;
+        if self.read > self.expected {
+            return Err(self.changed(format!("grew beyond {} bytes", self.expected)));
+        }
+        if let Some((digest, hasher)) = &mut self.snapshot {
+            if n > 0 {
+                hasher.update(&buf[..n]);
+            } else if <[u8; 32]>::from(hasher.finalize_reset()) != *digest {
+                return Err(self.changed("content differs from the snapshot".to_string()));
+            }
+        }
+        Ok(n)
+    }
+
+    /// Path of the file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn changed(&self, reason: String) -> Error {
+        let path = self.path.clone();
+        if self.snapshot.is_some() {
+            Error::ContextChangedDuringBuild { path, reason }
+        } else {
+            Error::ContextFileChanged { path, reason }
+        }
+    }
+}
+
+/// SHA-256 of a context file's content
+pub(crate) async fn hash_file(path: &Path) -> Result<[u8; 32]> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let read_error = |source| Error::ContextFileRead {
+            path: path.clone(),
+            source,
+        };
+        let mut file = std::fs::File::open(&path).map_err(read_error)?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let n = file.read(&mut buffer).map_err(read_error)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+        }
+        Ok(hasher.finalize().into())
+    })
+    .await
+    .map_err(|e| Error::other(format!("hashing failed: {}", e)))?
+}
+
 /// File sync server implementation
 ///
 /// Implements the BuildKit file synchronization protocol for streaming
-/// local build context files to BuildKit.
+/// local build context files to BuildKit. Besides the main context, named
+/// directories can be served for frontends that ask for them by name (e.g.,
+/// named contexts bound into `RUN --mount=type=bind,from=<name>`).
+/// The main context can also come from a [`ContextProvider`] rather than
+/// the root directory.
 #[derive(Debug, Clone)]
 pub struct FileSyncServer {
     root_path: PathBuf,
+    provider: Option<Arc<dyn ContextProvider>>,
+    dirs: HashMap<String, PathBuf>,
+    dockerfile: Option<Bytes>,
+    duplicates: DuplicateFiles,
+    options: FileSyncOptions,
+    include: IgnorePatterns,
+    exclude: IgnorePatterns,
+    stats: Arc<Mutex<SyncStats>>,
+    changed: Arc<Mutex<BTreeSet<String>>>,
 }
 
 impl FileSyncServer {
@@ -39,6 +445,15 @@ impl FileSyncServer {
     pub fn new(root_path: impl Into<PathBuf>) -> Self {
         Self {
             root_path: root_path.into(),
+            provider: None,
+            dirs: HashMap::new(),
+            dockerfile: None,
+            duplicates: DuplicateFiles::default(),
+            options: FileSyncOptions::default(),
+            include: IgnorePatterns::default(),
+            exclude: IgnorePatterns::default(),
+            stats: Arc::new(Mutex::new(SyncStats::default())),
+            changed: Arc::new(Mutex::new(BTreeSet::new())),
         }
     }
 
@@ -47,6 +462,120 @@ impl FileSyncServer {
         self.root_path.clone()
     }
 
+    /// Replace the root path, keeping named directories
+    pub fn set_root_path(&mut self, root_path: impl Into<PathBuf>) {
+        self.root_path = root_path.into();
+    }
+
+    /// Serve the main context (and the Dockerfile) from `provider` instead
+    /// of the root directory
+    pub fn set_provider(&mut self, provider: Arc<dyn ContextProvider>) {
+        self.provider = Some(provider);
+    }
+
+    /// Provider serving the main context, if any
+    pub fn provider(&self) -> Option<&Arc<dyn ContextProvider>> {
+        self.provider.as_ref()
+    }
+
+    /// Check whether a request's `dir-name` is for a registered directory
+    pub fn has_dir(&self, dir_name: Option<&str>) -> bool {
+        dir_name.is_some_and(|name| self.dirs.contains_key(name))
+    }
+
+    /// Serve `path` for requests for the directory `name`
+    pub fn add_dir(&mut self, name: impl Into<String>, path: impl Into<PathBuf>) {
+        self.dirs.insert(name.into(), path.into());
+    }
+
+    /// Serve the Dockerfile from memory instead of the `dockerfile` directory
+    ///
+    /// The content is sent under whatever file name the frontend asks for.
+    pub fn set_dockerfile(&mut self, content: impl Into<Bytes>) {
+        self.dockerfile = Some(content.into());
+    }
+
+    /// Dockerfile served from memory, if any
+    pub fn dockerfile(&self) -> Option<&Bytes> {
+        self.dockerfile.as_ref()
+    }
+
+    /// Directory served for a request's `dir-name`
+    ///
+    /// Unregistered names (including "context" and "dockerfile") map to the
+    /// root path.
+    pub fn dir_for(&self, dir_name: Option<&str>) -> PathBuf {
+        dir_name
+            .and_then(|name| self.dirs.get(name))
+            .unwrap_or(&self.root_path)
+            .clone()
+    }
+
+    /// Set how files duplicating an earlier file's content are sent
+    pub fn set_duplicate_files(&mut self, duplicates: DuplicateFiles) {
+        self.duplicates = duplicates;
+    }
+
+    /// How files duplicating an earlier file's content are sent
+    pub fn duplicate_files(&self) -> DuplicateFiles {
+        self.duplicates
+    }
+
+    /// Set the options for sending directories
+    pub fn set_options(&mut self, options: FileSyncOptions) {
+        self.options = options;
+    }
+
+    /// Options for sending directories
+    pub fn options(&self) -> &FileSyncOptions {
+        &self.options
+    }
+
+    /// Filter the root directory beyond its `.dockerignore`
+    ///
+    /// Only entries matching `include` are sent, unless it is empty;
+    /// `exclude` is applied after the `.dockerignore` patterns.
+    pub fn set_context_patterns(&mut self, include: IgnorePatterns, exclude: IgnorePatterns) {
+        self.include = include;
+        self.exclude = exclude;
+    }
+
+    /// Patterns root directory entries have to match, if any
+    pub fn context_include(&self) -> &IgnorePatterns {
+        &self.include
+    }
+
+    /// Patterns excluding root directory entries on top of `.dockerignore`
+    pub fn context_exclude(&self) -> &IgnorePatterns {
+        &self.exclude
+    }
+
+    /// Totals of all transfers so far, shared by clones of this server
+    pub fn stats(&self) -> SyncStats {
+        *self.stats.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Add the totals of a finished transfer
+    pub(crate) fn record_stats(&self, stats: &SyncStats) {
+        self.stats.lock().unwrap_or_else(|e| e.into_inner()).add(stats);
+    }
+
+    /// Files of the root directory whose data BuildKit requested, sorted
+    ///
+    /// BuildKit only asks for files that are new or changed since it last
+    /// synced the directory under the session's shared key. Shared by clones
+    /// of this server.
+    pub fn changed_files(&self) -> Vec<String> {
+        let changed = self.changed.lock().unwrap_or_else(|e| e.into_inner());
+        changed.iter().cloned().collect()
+    }
+
+    /// Record a root directory file whose data was sent
+    pub(crate) fn record_changed(&self, rel_path: &str) {
+        let mut changed = self.changed.lock().unwrap_or_else(|e| e.into_inner());
+        changed.insert(rel_path.to_string());
+    }
+
     /// Check if a path is within the allowed root directory
     fn validate_path(&self, rel_path: &str) -> Result<PathBuf> {
         let full_path = self.root_path.join(rel_path);
@@ -62,16 +591,21 @@ impl FileSyncServer {
     }
 
     /// Create a stat packet from file metadata
-    async fn create_stat_packet(path: &Path, rel_path: &str) -> Result<Packet> {
+    async fn create_stat_packet(
+        path: &Path,
+        rel_path: &str,
+        options: &FileSyncOptions,
+    ) -> Result<Packet> {
         let metadata = fs::metadata(path).await?;
+        let (uid, gid) = options.ownership(&metadata);
 
         let mut stat = Stat {
             path: rel_path.to_string(),
             mode: 0,
-            uid: 0,
-            gid: 0,
+            uid,
+            gid,
             size: metadata.len() as i64,
-            mod_time: 0,
+            mod_time: options.mod_time(&metadata),
             linkname: String::new(),
             devmajor: 0,
             devminor: 0,
@@ -103,6 +637,7 @@ impl FileSyncServer {
         path: &'a Path,
         prefix: &'a str,
         tx: &'a tokio::sync::mpsc::Sender<std::result::Result<Packet, Status>>,
+        options: &'a FileSyncOptions,
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send + 'a>> {
         Box::pin(async move {
             let mut entries = fs::read_dir(path).await?;
@@ -117,14 +652,14 @@ impl FileSyncServer {
                 };
 
                 let entry_path = entry.path();
-                let stat_packet = Self::create_stat_packet(&entry_path, &rel_path).await?;
+                let stat_packet = Self::create_stat_packet(&entry_path, &rel_path, options).await?;
 
                 tx.send(Ok(stat_packet)).await
                     .map_err(|_| Error::send_failed("STAT packet", "channel closed"))?;
 
                 // Recursively handle directories
                 if entry_path.is_dir() {
-                    FileSyncServer::read_directory(&entry_path, &rel_path, tx).await?;
+                    FileSyncServer::read_directory(&entry_path, &rel_path, tx, options).await?;
                 }
             }
 
@@ -136,15 +671,16 @@ impl FileSyncServer {
     async fn send_file_data(
         &self,
         path: &Path,
+        size: u64,
         id: u32,
         tx: &tokio::sync::mpsc::Sender<std::result::Result<Packet, Status>>,
     ) -> Result<()> {
-        let mut file = fs::File::open(path).await?;
+        let mut file = ContextFileReader::open(path, size).await?;
 
         let mut buffer = vec![0u8; 1024 * 1024]; // 1MB chunks
 
         loop {
-            let n = file.read(&mut buffer).await?;
+            let n = file.read_chunk(&mut buffer).await?;
             if n == 0 {
                 break;
             }
@@ -188,11 +724,11 @@ impl FileSync for FileSyncServer {
         let (tx, rx) = tokio::sync::mpsc::channel(128);
         let server = self.clone();
 
-        tokio::spawn(async move {
+        spawn_named("buildkit.filesync.diffcopy", tracing::info_span!("filesync_diffcopy"), async move {
             tracing::debug!("Starting DiffCopy session");
 
             // First, send all file stats
-            if let Err(e) = FileSyncServer::read_directory(&server.root_path, "", &tx).await {
+            if let Err(e) = FileSyncServer::read_directory(&server.root_path, "", &tx, &server.options).await {
                 tracing::error!("Failed to read directory: {}", e);
                 let _ = tx.send(Err(Status::internal(format!("Failed to read directory: {}", e)))).await;
                 return;
@@ -214,8 +750,8 @@ impl FileSync for FileSyncServer {
                                 }
                             };
 
-                            if path.is_file() {
-                                if let Err(e) = server.send_file_data(&path, packet.id, &tx).await {
+                            if let Some(metadata) = std::fs::metadata(&path).ok().filter(|m| m.is_file()) {
+                                if let Err(e) = server.send_file_data(&path, metadata.len(), packet.id, &tx).await {
                                     tracing::error!("Failed to send file data: {}", e);
                                     let _ = tx.send(Err(Status::internal(format!("Failed to send file: {}", e)))).await;
                                     return;
@@ -0,0 +1,180 @@
+//! gRPC message framing for the session tunnel
+//!
+//! Every gRPC message on an HTTP/2 stream is prefixed with a 1-byte
+//! compression flag and a 4-byte big-endian length. HTTP/2 DATA chunks do not
+//! line up with message boundaries, so incoming bytes are accumulated in a
+//! [`FrameDecoder`] until complete messages can be taken off the front.
+//!
+//! The input comes from the daemon and is treated as untrusted: malformed
+//! headers produce errors instead of panics, and [`FrameLimits`] caps how much
+//! memory a single stream can make the decoder hold.
+
+use crate::error::{Error, Result};
+use bytes::{Buf, Bytes, BytesMut};
+
+/// Length of the gRPC message prefix
+pub const FRAME_HEADER_LEN: usize = 5;
+
+/// Default cap on a single message payload (16 MiB)
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 << 20;
+
+/// Default cap on bytes held by a decoder at once (32 MiB)
+pub const DEFAULT_MAX_BUFFERED: usize = 32 << 20;
+
+/// Memory limits for a [`FrameDecoder`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameLimits {
+    /// Largest accepted message payload, checked as soon as the header arrives
+    pub max_frame_size: usize,
+    /// Largest number of bytes buffered while waiting for complete frames
+    pub max_buffered: usize,
+}
+
+impl Default for FrameLimits {
+    fn default() -> Self {
+        Self {
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            max_buffered: DEFAULT_MAX_BUFFERED,
+        }
+    }
+}
+
+/// A single gRPC message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Whether the payload is compressed with the negotiated encoding
+    pub compressed: bool,
+    /// Message payload
+    pub payload: Bytes,
+}
+
+/// Reassembles gRPC messages from arbitrarily split chunks
+///
+/// Callers should drain complete frames with [`decode`](Self::decode) after
+/// every [`push`](Self::push); only then does the buffer stay bounded by a
+/// single partial frame plus one chunk.
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buffer: BytesMut,
+    limits: FrameLimits,
+}
+
+impl FrameDecoder {
+    /// Create an empty decoder with the default limits
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty decoder with custom limits
+    pub fn with_limits(limits: FrameLimits) -> Self {
+        Self {
+            buffer: BytesMut::new(),
+            limits,
+        }
+    }
+
+    /// Limits enforced by this decoder
+    pub fn limits(&self) -> FrameLimits {
+        self.limits
+    }
+
+    /// Append a received chunk
+    ///
+    /// Fails without buffering the chunk if it would take the decoder past
+    /// [`FrameLimits::max_buffered`].
+    pub fn push(&mut self, chunk: &[u8]) -> Result<()> {
+        let buffered = self.buffer.len().saturating_add(chunk.len());
+        if buffered > self.limits.max_buffered {
+            return Err(Error::protocol(format!(
+                "gRPC stream buffered {} bytes, limit is {}",
+                buffered, self.limits.max_buffered
+            )));
+        }
+        self.buffer.extend_from_slice(chunk);
+        Ok(())
+    }
+
+    /// Number of bytes buffered but not yet returned as frames
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Take the next complete frame, if one is buffered
+    pub fn decode(&mut self) -> Result<Option<Frame>> {
+        if self.buffer.len() < FRAME_HEADER_LEN {
+            return Ok(None);
+        }
+
+        let compressed = match self.buffer[0] {
+            0 => false,
+            1 => true,
+            flag => {
+                return Err(Error::protocol(format!(
+                    "invalid gRPC compression flag {}",
+                    flag
+                )))
+            }
+        };
+        let length = u32::from_be_bytes([
+            self.buffer[1],
+            self.buffer[2],
+            self.buffer[3],
+            self.buffer[4],
+        ]) as usize;
+
+        if length > self.limits.max_frame_size {
+            return Err(Error::protocol(format!(
+                "gRPC message of {} bytes exceeds limit of {}",
+                length, self.limits.max_frame_size
+            )));
+        }
+
+        if self.buffer.len() - FRAME_HEADER_LEN < length {
+            return Ok(None);
+        }
+
+        self.buffer.advance(FRAME_HEADER_LEN);
+        let payload = self.buffer.split_to(length).freeze();
+        Ok(Some(Frame {
+            compressed,
+            payload,
+        }))
+    }
+
+    /// Check that the stream ended on a frame boundary
+    pub fn finish(&self) -> Result<()> {
+        if self.buffer.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::protocol(format!(
+                "stream ended inside a gRPC frame ({} bytes left over)",
+                self.buffer.len()
+            )))
+        }
+    }
+}
+
+/// Prefix an uncompressed payload with the gRPC message header
+pub fn encode_frame(payload: &[u8]) -> Bytes {
+    let mut framed = BytesMut::with_capacity(FRAME_HEADER_LEN + payload.len());
+    framed.extend_from_slice(&[0]);
+    framed.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    framed.extend_from_slice(payload);
+    framed.freeze()
+}
+
+/// Encode a status message for the `grpc-message` header
+///
+/// gRPC percent-encodes the UTF-8 message, so error text with newlines or
+/// non-ASCII characters still makes a valid header value.
+pub fn grpc_message(message: &str) -> http::HeaderValue {
+    let mut encoded = String::with_capacity(message.len());
+    for byte in message.bytes() {
+        if (0x20..=0x7e).contains(&byte) && byte != b'%' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    http::HeaderValue::from_str(&encoded).expect("percent-encoded message is a valid header")
+}
@@ -4,39 +4,162 @@
 //! We use the h2 crate to handle the HTTP/2 server protocol.
 
 use crate::error::{Error, Result};
+use crate::task::spawn_named;
 use bytes::Bytes;
 use filemode::{UnixMode, GoFileMode};
 use h2::server::{self, SendResponse};
 use http::{Request, Response, StatusCode};
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::task::{Context as TaskContext, Poll};
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, Notify};
 use prost::Message as ProstMessage;
 
 use crate::proto::moby::buildkit::v1::BytesMessage;
-use super::{FileSyncServer, AuthServer, SecretsServer};
+use super::{FileSyncServer, FileSendServer, AuthServer, SecretsServer, DirectoryReceiver, SendTarget};
+use super::filesend::EXPORTER_ID_HEADER;
+use super::frame::{encode_frame, grpc_message, FrameDecoder, FrameLimits};
+use super::access_log::AccessLogPolicy;
+use super::content::{BlobUpload, ContentStoreServer, STORE_ID_HEADER};
+use super::priority::TransferGate;
+use super::dedup::TransferDedup;
+use super::filesync::{follow_links, ContextFilter, FileSyncOptions, ListedFile, Visit};
+use super::ignore::IgnorePatterns;
+use super::source::{list_context, ContextProvider};
+use super::sshforward::{SshForwardServer, DEFAULT_SSH_ID, SSH_ID_HEADER};
+use super::upload::{UploadServer, UPLOAD_PATH_HEADER};
 
 /// Stream multiplexer for handling gRPC tunneled through session
 pub struct GrpcTunnel {
     file_sync: Option<FileSyncServer>,
-    auth: Option<AuthServer>,
+    file_send: Option<FileSendServer>,
+    auth: AuthServer,
     secrets: Option<SecretsServer>,
+    ssh: Option<SshForwardServer>,
+    upload: Option<UploadServer>,
+    content: Option<ContentStoreServer>,
+    active_requests: Arc<AtomicUsize>,
+    frame_limits: FrameLimits,
+    transfer_gate: Arc<TransferGate>,
+    fault: Arc<Mutex<Option<Error>>>,
+    fatal: Arc<Notify>,
+    access_log: AccessLogPolicy,
+}
+
+/// How long outstanding requests may take to finish once the connection ends
+const DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Decrements the active request count when a handler finishes
+struct ActiveRequest(Arc<AtomicUsize>);
+
+impl ActiveRequest {
+    fn start(counter: &Arc<AtomicUsize>) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Self(Arc::clone(counter))
+    }
+}
+
+impl Drop for ActiveRequest {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
 impl GrpcTunnel {
     /// Create a new gRPC tunnel
+    ///
+    /// Without `auth`, registry tokens are still fetched anonymously.
     pub fn new(
         _response_tx: mpsc::Sender<BytesMessage>,
         file_sync: Option<FileSyncServer>,
+        file_send: Option<FileSendServer>,
         auth: Option<AuthServer>,
         secrets: Option<SecretsServer>,
     ) -> Self {
         Self {
             file_sync,
-            auth,
+            file_send,
+            auth: auth.unwrap_or_default(),
             secrets,
+            ssh: None,
+            upload: None,
+            content: None,
+            active_requests: Arc::new(AtomicUsize::new(0)),
+            frame_limits: FrameLimits::default(),
+            transfer_gate: Arc::new(TransferGate::new()),
+            fault: Arc::new(Mutex::new(None)),
+            fatal: Arc::new(Notify::new()),
+            access_log: AccessLogPolicy::default(),
+        }
+    }
+
+    /// Serve blobs from (and accept blobs into) custom content stores
+    pub fn with_content_stores(mut self, content: Option<ContentStoreServer>) -> Self {
+        self.content = content;
+        self
+    }
+
+    /// Forward SSH agents to BuildKit
+    pub fn with_ssh_forward(mut self, ssh: Option<SshForwardServer>) -> Self {
+        self.ssh = ssh;
+        self
+    }
+
+    /// Serve streams BuildKit pulls through the Upload service
+    pub fn with_uploads(mut self, upload: Option<UploadServer>) -> Self {
+        self.upload = upload;
+        self
+    }
+
+    /// Count in-flight requests in `counter` (see [`super::SessionHealth`])
+    pub fn with_active_requests(mut self, counter: Arc<AtomicUsize>) -> Self {
+        self.active_requests = counter;
+        self
+    }
+
+    /// Bound the memory used to reassemble messages from BuildKit
+    pub fn with_frame_limits(mut self, limits: FrameLimits) -> Self {
+        self.frame_limits = limits;
+        self
+    }
+
+    /// Record the first protocol fault in `slot`
+    ///
+    /// Disconnects caused by the daemon closing the session are not faults.
+    pub fn with_fault_slot(mut self, slot: Arc<Mutex<Option<Error>>>) -> Self {
+        self.fault = slot;
+        self
+    }
+
+    /// Wake `signal` when the tunnel cannot serve the session at all
+    ///
+    /// The failure is recorded in the [fault slot](Self::with_fault_slot).
+    /// Without this, a build whose tunnel never comes up waits forever for
+    /// files BuildKit can no longer request.
+    pub fn with_fatal_signal(mut self, signal: Arc<Notify>) -> Self {
+        self.fatal = signal;
+        self
+    }
+
+    /// Set how credential and secret requests are logged
+    pub fn with_access_log(mut self, policy: AccessLogPolicy) -> Self {
+        self.access_log = policy;
+        self
+    }
+
+    /// Log a failed request and keep it if it is a protocol fault
+    fn report(&self, error: Error) {
+        if error.is_disconnect() {
+            tracing::debug!("gRPC request ended by session close: {}", error);
+            return;
+        }
+
+        tracing::error!("Failed to handle gRPC request: {}", error);
+        if error.is_protocol_fault() {
+            let mut fault = self.fault.lock().unwrap_or_else(|e| e.into_inner());
+            fault.get_or_insert(error);
         }
     }
 
@@ -52,24 +175,74 @@ impl GrpcTunnel {
         let stream = MessageStream::new(inbound_rx, outbound_tx);
 
         // Start HTTP/2 server
-        let mut h2_conn = server::handshake(stream).await
-            .map_err(|e| Error::Http2Handshake { source: e })?;
+        let mut h2_conn = match server::handshake(stream).await {
+            Ok(conn) => conn,
+            Err(e) => {
+                let e = Error::Http2Handshake { source: e };
+                if e.is_disconnect() {
+                    tracing::info!("BuildKit closed the session before using it");
+                    return Ok(());
+                }
+                // Nothing can be served; fail the build instead of letting it wait
+                let failure = Error::SessionHandshake(e.to_string());
+                tracing::error!("{}", failure);
+                *tunnel.fault.lock().unwrap_or_else(|e| e.into_inner()) = Some(failure);
+                tunnel.fatal.notify_one();
+                return Err(e);
+            }
+        };
 
         tracing::info!("HTTP/2 server started in session tunnel");
 
         // Accept incoming HTTP/2 streams
-        while let Some(result) = h2_conn.accept().await {
-            let (request, respond) = result.map_err(|e| Error::Http2Stream { source: e })?;
+        let mut handlers = Vec::new();
+        let result = loop {
+            let (request, respond) = match h2_conn.accept().await {
+                Some(Ok(accepted)) => accepted,
+                Some(Err(e)) => break Err(Error::Http2Stream { source: e }),
+                None => break Ok(()),
+            };
             let tunnel_ref = Arc::clone(&tunnel);
+            let active = ActiveRequest::start(&tunnel.active_requests);
+            let span = tracing::info_span!("session_request", method = %request.uri().path());
 
-            tokio::spawn(async move {
+            handlers.retain(|handler: &tokio::task::JoinHandle<()>| !handler.is_finished());
+            handlers.push(spawn_named("buildkit.session.request", span, async move {
+                let _active = active;
                 if let Err(e) = tunnel_ref.handle_request(request, respond).await {
-                    tracing::error!("Failed to handle gRPC request: {}", e);
+                    tunnel_ref.report(e);
                 }
-            });
+            }));
+        };
+
+        // Let requests that were in flight when the connection ended finish
+        let drained = tokio::time::timeout(DRAIN_TIMEOUT, async {
+            for handler in handlers {
+                let _ = handler.await;
+            }
+        })
+        .await;
+        if drained.is_err() {
+            tracing::warn!("Session requests still running {:?} after the tunnel closed", DRAIN_TIMEOUT);
         }
 
-        Ok(())
+        match result {
+            Err(e) if e.is_disconnect() => {
+                tracing::info!("BuildKit closed the session tunnel");
+                Ok(())
+            }
+            Err(e) => {
+                let mut fault = tunnel.fault.lock().unwrap_or_else(|e| e.into_inner());
+                if fault.is_none() {
+                    *fault = Some(Error::protocol(e.to_string()));
+                }
+                Err(e)
+            }
+            Ok(()) => {
+                tracing::info!("Session tunnel closed");
+                Ok(())
+            }
+        }
     }
 
     /// Handle a single gRPC request
@@ -103,40 +276,138 @@ impl GrpcTunnel {
             .map(|s| s.to_string())
             .collect();
 
+        // Extract the .dockerignore patterns the frontend read, if any
+        let exclude_patterns: Vec<String> = req.headers()
+            .get_all("exclude-patterns")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .map(|s| s.to_string())
+            .collect();
+
+        // Extract exporter ID header used by FileSend (defaults to the first exporter)
+        let exporter_id = req.headers()
+            .get(EXPORTER_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap_or(0);
+
+        // Extract content store ID used by the containerd Content service
+        let store_id = req.headers()
+            .get(STORE_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+
+        // Extract the agent ID used by SSH.ForwardAgent
+        let ssh_id = req.headers()
+            .get(SSH_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|s| !s.is_empty())
+            .unwrap_or(DEFAULT_SSH_ID)
+            .to_string();
+
+        // Extract the URL path of an Upload.Pull request
+        let upload_path = req.headers()
+            .get(UPLOAD_PATH_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+
         let body = req.into_body();
 
         // Dispatch to appropriate service
         match method.as_str() {
             "/grpc.health.v1.Health/Check" => {
                 // Read request body for unary RPC
-                let payload = Self::read_unary_request(body).await?;
+                let payload = Self::read_unary_request(body, self.frame_limits).await?;
                 let response_payload = self.handle_health_check(payload).await?;
                 self.send_success_response(respond, response_payload).await
             }
             "/moby.filesync.v1.FileSync/DiffCopy" => {
                 // DiffCopy is a bidirectional streaming RPC - pass the stream
-                self.handle_file_sync_diff_copy_stream(body, respond, dir_name, followpaths).await
+                self.handle_file_sync_diff_copy_stream(body, respond, dir_name, followpaths, exclude_patterns).await
+            }
+            "/moby.filesync.v1.FileSend/DiffCopy" => {
+                // FileSend streams exporter output from BuildKit to us
+                self.handle_file_send_diff_copy_stream(body, respond, exporter_id).await
             }
             "/moby.filesync.v1.Auth/GetTokenAuthority" => {
-                // Token-based auth not supported - return error to make BuildKit fall back
-                // BuildKit requires either a valid pubkey or error to properly fallback to Credentials
-                tracing::info!("Auth.GetTokenAuthority called - returning not implemented");
-                self.send_error_response(respond, "Token auth not implemented").await
+                // Without a key (an error status) BuildKit falls back to Credentials
+                let payload = Self::read_unary_request(body, self.frame_limits).await?;
+                match self.handle_auth_get_token_authority(payload).await {
+                    Ok(response_payload) => self.send_success_response(respond, response_payload).await,
+                    Err(Error::Grpc(status)) => {
+                        self.send_status_response(respond, status.code(), status.message()).await
+                    }
+                    Err(e) => self.send_status_response(respond, tonic::Code::Internal, &e.to_string()).await,
+                }
+            }
+            "/moby.filesync.v1.Auth/VerifyTokenAuthority" => {
+                let payload = Self::read_unary_request(body, self.frame_limits).await?;
+                match self.handle_auth_verify_token_authority(payload).await {
+                    Ok(response_payload) => self.send_success_response(respond, response_payload).await,
+                    Err(Error::Grpc(status)) => {
+                        self.send_status_response(respond, status.code(), status.message()).await
+                    }
+                    Err(e) => self.send_status_response(respond, tonic::Code::Internal, &e.to_string()).await,
+                }
             }
             "/moby.filesync.v1.Auth/Credentials" => {
-                let payload = Self::read_unary_request(body).await?;
+                let payload = Self::read_unary_request(body, self.frame_limits).await?;
                 let response_payload = self.handle_auth_credentials(payload).await?;
                 self.send_success_response(respond, response_payload).await
             }
             "/moby.filesync.v1.Auth/FetchToken" => {
-                let payload = Self::read_unary_request(body).await?;
-                let response_payload = self.handle_auth_fetch_token(payload).await?;
-                self.send_success_response(respond, response_payload).await
+                let payload = Self::read_unary_request(body, self.frame_limits).await?;
+                match self.handle_auth_fetch_token(payload).await {
+                    Ok(response_payload) => self.send_success_response(respond, response_payload).await,
+                    Err(Error::Grpc(status)) => {
+                        self.send_status_response(respond, status.code(), status.message()).await
+                    }
+                    Err(e) => self.send_status_response(respond, tonic::Code::Internal, &e.to_string()).await,
+                }
             }
             "/moby.buildkit.secrets.v1.Secrets/GetSecret" => {
-                let payload = Self::read_unary_request(body).await?;
-                let response_payload = self.handle_secrets_get_secret(payload).await?;
-                self.send_success_response(respond, response_payload).await
+                let payload = Self::read_unary_request(body, self.frame_limits).await?;
+                match self.handle_secrets_get_secret(payload).await {
+                    Ok(response_payload) => self.send_success_response(respond, response_payload).await,
+                    Err(e) => {
+                        self.send_status_response(respond, secret_status(&e), &e.to_string()).await?;
+                        Err(e)
+                    }
+                }
+            }
+            "/moby.sshforward.v1.SSH/CheckAgent" => {
+                let payload = Self::read_unary_request(body, self.frame_limits).await?;
+                match self.handle_ssh_check_agent(payload) {
+                    Ok(response_payload) => self.send_success_response(respond, response_payload).await,
+                    Err(e) => self.send_status_response(respond, tonic::Code::NotFound, &e.to_string()).await,
+                }
+            }
+            "/moby.sshforward.v1.SSH/ForwardAgent" => {
+                self.handle_ssh_forward_agent_stream(body, respond, &ssh_id).await
+            }
+            "/moby.upload.v1.Upload/Pull" => {
+                self.handle_upload_pull_stream(body, respond, &upload_path).await
+            }
+            "/containerd.services.content.v1.Content/Info"
+            | "/containerd.services.content.v1.Content/Update"
+            | "/containerd.services.content.v1.Content/Abort" => {
+                let payload = Self::read_unary_request(body, self.frame_limits).await?;
+                match self.handle_content_unary(&method, &store_id, payload).await {
+                    Ok(response_payload) => self.send_success_response(respond, response_payload).await,
+                    Err(e) => {
+                        tracing::debug!("Content request {} failed: {}", method, e);
+                        self.send_status_response(respond, content_status(&e), &e.to_string()).await
+                    }
+                }
+            }
+            "/containerd.services.content.v1.Content/Read" => {
+                let payload = Self::read_unary_request(body, self.frame_limits).await?;
+                self.handle_content_read_stream(payload, respond, &store_id).await
+            }
+            "/containerd.services.content.v1.Content/Write" => {
+                self.handle_content_write_stream(body, respond, &store_id).await
             }
             _ => {
                 tracing::warn!("Unknown gRPC method: {}", method);
@@ -146,21 +417,24 @@ impl GrpcTunnel {
     }
 
     /// Read complete request body for unary RPC
-    async fn read_unary_request(mut body: h2::RecvStream) -> Result<Bytes> {
-        let mut request_data = Vec::new();
+    async fn read_unary_request(mut body: h2::RecvStream, limits: FrameLimits) -> Result<Bytes> {
+        let mut decoder = FrameDecoder::with_limits(limits);
 
         while let Some(chunk) = body.data().await {
             let chunk = chunk.map_err(|e| Error::Http2Stream { source: e })?;
-            request_data.extend_from_slice(&chunk);
+            decoder.push(&chunk)?;
             let _ = body.flow_control().release_capacity(chunk.len());
         }
 
-        // Skip the 5-byte gRPC prefix (1 byte compression + 4 bytes length)
-        let payload = if request_data.len() > 5 {
-            Bytes::copy_from_slice(&request_data[5..])
-        } else {
-            Bytes::new()
+        // An empty body is an empty request message
+        let payload = match decoder.decode()? {
+            Some(frame) if frame.compressed => {
+                return Err(Error::protocol("compressed unary requests are not supported"));
+            }
+            Some(frame) => frame.payload,
+            None => Bytes::new(),
         };
+        decoder.finish()?;
 
         Ok(payload)
     }
@@ -182,12 +456,9 @@ impl GrpcTunnel {
             .map_err(|e| Error::Http2Stream { source: e })?;
 
         // Send response with gRPC framing (5-byte prefix)
-        let mut framed = Vec::new();
-        framed.push(0); // No compression
-        framed.extend_from_slice(&(payload.len() as u32).to_be_bytes());
-        framed.extend_from_slice(&payload);
+        let framed = encode_frame(&payload);
 
-        send_stream.send_data(Bytes::from(framed), false)
+        send_stream.send_data(framed, false)
             .map_err(|e| Error::Http2Stream { source: e })?;
 
         // Send trailers with grpc-status
@@ -204,15 +475,37 @@ impl GrpcTunnel {
 
     /// Send error gRPC response
     async fn send_error_response(
+        &self,
+        respond: SendResponse<Bytes>,
+        message: &str,
+    ) -> Result<()> {
+        self.send_status_response(respond, tonic::Code::Unimplemented, message).await
+    }
+
+    /// End a response stream with an error status in its trailers
+    ///
+    /// Best effort: the peer may already be gone.
+    fn send_error_trailers(stream: &mut h2::SendStream<Bytes>, code: tonic::Code, message: &str) {
+        let trailers = Response::builder()
+            .header("grpc-status", (code as i32).to_string())
+            .header("grpc-message", grpc_message(message))
+            .body(())
+            .unwrap();
+        let _ = stream.send_trailers(trailers.headers().clone());
+    }
+
+    /// Send a trailers-only gRPC response with the given status
+    async fn send_status_response(
         &self,
         mut respond: SendResponse<Bytes>,
+        code: tonic::Code,
         message: &str,
     ) -> Result<()> {
         let response = Response::builder()
             .status(StatusCode::OK)
             .header("content-type", "application/grpc")
-            .header("grpc-status", "12") // UNIMPLEMENTED
-            .header("grpc-message", message)
+            .header("grpc-status", (code as i32).to_string())
+            .header("grpc-message", grpc_message(message))
             .body(())
             .unwrap();
 
@@ -229,6 +522,7 @@ impl GrpcTunnel {
         mut respond: SendResponse<Bytes>,
         dir_name: Option<String>,
         followpaths: Vec<String>,
+        exclude_patterns: Vec<String>,
     ) -> Result<()> {
         use crate::proto::fsutil::types::{Packet, packet::PacketType};
         use prost::Message as ProstMessage;
@@ -262,8 +556,8 @@ impl GrpcTunnel {
 
         tracing::info!("Sent response headers for DiffCopy");
 
-        // Get the root path from FileSyncServer
-        let root_path = file_sync.get_root_path();
+        // Get the directory requested by name (the main context by default)
+        let root_path = file_sync.dir_for(dir_name.as_deref());
         tracing::info!("Starting to send STAT packets from: {} (call #{})", root_path.display(), call_id);
         eprintln!("Root path: {}, is_dir: {}", root_path.display(), root_path.is_dir());
 
@@ -275,95 +569,127 @@ impl GrpcTunnel {
         let mut id_counter = 0u32;
 
         let send_only_dockerfile = dir_name.as_deref() == Some("dockerfile");
-
-        if send_only_dockerfile {
-            // BuildKit only wants the Dockerfile - determine actual filename from followpaths
-            // When using custom dockerfile, BuildKit sends followpaths like ["Custom.Dockerfile", ...]
-            let dockerfile_name = if !followpaths.is_empty() && followpaths[0].ends_with(".Dockerfile") {
-                // Custom dockerfile name
-                followpaths[0].clone()
-            } else {
-                // Default to "Dockerfile"
-                "Dockerfile".to_string()
+        // Changed files are tracked for the main context only
+        let main_context = !send_only_dockerfile && root_path == file_sync.get_root_path();
+
+        // The frontend blocks on the Dockerfile; let it overtake a concurrent context sync
+        let _urgent = send_only_dockerfile.then(|| self.transfer_gate.urgent());
+        let gate = (!send_only_dockerfile).then_some(self.transfer_gate.as_ref());
+
+        let inline_dockerfile = file_sync.dockerfile().filter(|_| send_only_dockerfile);
+        // Named directories always come from disk
+        let provider = file_sync
+            .provider()
+            .filter(|_| !file_sync.has_dir(dir_name.as_deref()));
+        if let Some(content) = inline_dockerfile {
+            // Serve the in-memory Dockerfile under the name the frontend asked
+            // for; followpaths lists it first, then its .dockerignore
+            use crate::proto::fsutil::types::Stat;
+
+            let dockerfile_name = followpaths
+                .first()
+                .filter(|path| !path.ends_with(".dockerignore"))
+                .cloned()
+                .unwrap_or_else(|| "Dockerfile".to_string());
+            let stat_packet = Packet {
+                r#type: PacketType::PacketStat as i32,
+                stat: Some(Stat {
+                    path: dockerfile_name.clone(),
+                    mode: 0o644,
+                    size: content.len() as i64,
+                    ..Default::default()
+                }),
+                id: 0,
+                data: vec![],
             };
-
-            eprintln!("BuildKit requested 'dockerfile' - sending only {}", dockerfile_name);
-            use crate::proto::fsutil::types::{Packet, packet::PacketType, Stat};
-
-            let dockerfile_path = root_path.join(&dockerfile_name);
-            if !dockerfile_path.exists() {
-                tracing::error!("{} not found at {}", dockerfile_name, dockerfile_path.display());
+            tracing::debug!("Sending inline Dockerfile as {}", dockerfile_name);
+            Self::send_grpc_packet(&mut send_stream, &stat_packet).await?;
+            file_map.insert(0, ListedFile::in_memory(dockerfile_name, content.clone()));
+        } else if let Some(provider) = provider {
+            // The context (Dockerfile included) lives in the client process
+            let sent = Self::send_provided_stat_packets(
+                provider.as_ref(),
+                &mut send_stream,
+                &mut file_map,
+                &followpaths,
+            ).await;
+            if let Err(e) = sent {
+                tracing::error!("Error sending STAT packets: {}", e);
                 let trailers = Response::builder()
                     .header("grpc-status", "2")
-                    .header("grpc-message", format!("{} not found", dockerfile_name))
+                    .header("grpc-message", grpc_message(&e.to_string()))
                     .body(())
                     .unwrap();
                 let _ = send_stream.send_trailers(trailers.headers().clone());
-                return Err(Error::PathNotFound(dockerfile_path.clone()));
-            }
-
-            let metadata = tokio::fs::metadata(&dockerfile_path).await?;
-
-            let mut stat = Stat {
-                path: dockerfile_name.clone(),
-                mode: 0,
-                uid: 0,
-                gid: 0,
-                size: metadata.len() as i64,
-                mod_time: 0,
-                linkname: String::new(),
-                devmajor: 0,
-                devminor: 0,
-                xattrs: std::collections::HashMap::new(),
-            };
-
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::PermissionsExt;
-                let unix_mode = metadata.permissions().mode();
-                stat.mode = GoFileMode::from(UnixMode::from(unix_mode)).as_u32();
-            }
-
-            #[cfg(not(unix))]
-            {
-                stat.mode = 0o644;  // Regular file in Go FileMode format (just permissions)
+                return Err(e);
             }
-
-            let mode = stat.mode;
-            let stat_packet = Packet {
-                r#type: PacketType::PacketStat as i32,
-                stat: Some(stat),
-                id: 0,
-                data: vec![],
-            };
-
-            eprintln!("DFS: Sending STAT #0: {} (FILE, mode: 0o{:o})", dockerfile_name, mode);
-            Self::send_grpc_packet(&mut send_stream, &stat_packet).await?;
-
-            // Store in file map
-            file_map.insert(0, dockerfile_path);
         } else {
-            // BuildKit wants the full context - send tree using depth-first traversal
-            // If followpaths is specified, only send those files and their parent directories
-            // fsutil requires files in depth-first order with entries sorted alphabetically within each directory
+            // Send the tree using depth-first traversal; fsutil requires files in
+            // depth-first order with entries sorted alphabetically within each directory.
+            // With followpaths (e.g. the Dockerfile and its .dockerignore when BuildKit
+            // only wants those), only the paths listed, what is below them and their
+            // parent directories are walked and sent
+            let mut followpaths = followpaths;
+            if send_only_dockerfile && followpaths.is_empty() {
+                followpaths.push("Dockerfile".to_string());
+            }
             if followpaths.is_empty() {
                 eprintln!("BuildKit requested full context - sending entire directory tree");
             } else {
                 eprintln!("BuildKit requested filtered context - followpaths: {:?}", followpaths);
             }
 
-            if let Err(e) = Self::send_stat_packets_dfs(
-                root_path.clone(),
-                String::new(),
-                &mut send_stream,
-                &mut file_map,
-                &mut id_counter,
-                if followpaths.is_empty() { None } else { Some(&followpaths) },
-            ).await {
+            // Ignored files are never statted; without patterns from the
+            // frontend, the main context's own .dockerignore applies. Requests for
+            // a few files (such as the frontend reading .dockerignore) get them as
+            // they are
+            let whole_context = main_context && followpaths.is_empty();
+            let ignore = if exclude_patterns.is_empty() && whole_context {
+                IgnorePatterns::read(&root_path)
+            } else {
+                Ok(IgnorePatterns::new(&exclude_patterns))
+            };
+            let mut dedup = TransferDedup::new(file_sync.duplicate_files());
+            let sent = match ignore {
+                Ok(mut ignore) => {
+                    // The configured patterns narrow the main context further
+                    let include = if whole_context {
+                        ignore.append(file_sync.context_exclude());
+                        file_sync.context_include().clone()
+                    } else {
+                        IgnorePatterns::default()
+                    };
+                    let follow = IgnorePatterns::follow(follow_links(&root_path, &followpaths));
+                    let mut filter = ContextFilter::new(vec![follow, include], ignore);
+                    Self::send_stat_packets_dfs(
+                        root_path.clone(),
+                        String::new(),
+                        &mut send_stream,
+                        &mut file_map,
+                        &mut id_counter,
+                        &mut filter,
+                        &mut dedup,
+                        file_sync.options(),
+                    ).await
+                }
+                Err(e) => Err(e),
+            };
+            let stats = dedup.stats();
+            if !send_only_dockerfile {
+                file_sync.record_stats(stats);
+            }
+            if stats.duplicate_files > 0 {
+                tracing::info!(
+                    "{} of {} files in {} duplicate earlier files ({} of {} bytes)",
+                    stats.duplicate_files, stats.files, root_path.display(),
+                    stats.duplicate_bytes, stats.bytes
+                );
+            }
+            if let Err(e) = sent {
                 tracing::error!("Error sending STAT packets: {}", e);
                 let trailers = Response::builder()
                     .header("grpc-status", "2")
-                    .header("grpc-message", e.to_string())
+                    .header("grpc-message", grpc_message(&e.to_string()))
                     .body(())
                     .unwrap();
                 let _ = send_stream.send_trailers(trailers.headers().clone());
@@ -384,38 +710,40 @@ impl GrpcTunnel {
 
         // Now listen for REQ packets from BuildKit and send the requested files
         // We need to accumulate data across multiple chunks to form complete gRPC messages
-        let mut buffer = Vec::new();
+        let mut decoder = FrameDecoder::with_limits(self.frame_limits);
         let mut received_fin = false;
 
         loop {
             // Read next chunk from request stream
             match request_stream.data().await {
                 Some(Ok(chunk)) => {
-                    buffer.extend_from_slice(&chunk);
+                    if let Err(e) = decoder.push(&chunk) {
+                        tracing::error!("Rejecting DiffCopy stream from BuildKit: {}", e);
+                        Self::send_error_trailers(&mut send_stream, tonic::Code::ResourceExhausted, &e.to_string());
+                        return Err(e);
+                    }
                     let _ = request_stream.flow_control().release_capacity(chunk.len());
 
                     // Try to parse complete gRPC messages from buffer
-                    while buffer.len() >= 5 {
-                        // Read gRPC frame header (5 bytes)
-                        let compressed = buffer[0];
-                        let length = u32::from_be_bytes([buffer[1], buffer[2], buffer[3], buffer[4]]) as usize;
-
-                        if buffer.len() < 5 + length {
-                            // Not enough data for complete message yet
-                            break;
-                        }
-
-                        // Extract the complete message
-                        let message_data = buffer[5..5+length].to_vec();
-                        buffer.drain(0..5+length);
+                    loop {
+                        let frame = match decoder.decode() {
+                            Ok(Some(frame)) => frame,
+                            Ok(None) => break,
+                            Err(e) => {
+                                // The stream is out of sync; nothing after this can be trusted
+                                tracing::error!("Malformed gRPC frame from BuildKit: {}", e);
+                                Self::send_error_trailers(&mut send_stream, tonic::Code::Internal, &e.to_string());
+                                return Err(e);
+                            }
+                        };
 
-                        if compressed != 0 {
+                        if frame.compressed {
                             tracing::warn!("Received compressed message, skipping");
                             continue;
                         }
 
                         // Decode the packet
-                        let packet = match Packet::decode(Bytes::from(message_data)) {
+                        let packet = match Packet::decode(frame.payload) {
                             Ok(p) => p,
                             Err(e) => {
                                 tracing::error!("Failed to decode packet: {}", e);
@@ -432,10 +760,23 @@ impl GrpcTunnel {
                                 // BuildKit is requesting file data for a specific ID
                                 tracing::info!("Received REQ packet with id: {}", packet.id);
 
-                                if let Some(file_path) = file_map.get(&packet.id) {
-                                    tracing::info!("Sending file data for id {}: {}", packet.id, file_path.display());
-                                    if let Err(e) = Self::send_file_data_packets(file_path.clone(), packet.id, &mut send_stream).await {
+                                if let Some(file) = file_map.get(&packet.id) {
+                                    tracing::info!("Sending file data for id {}: {}", packet.id, file.path.display());
+                                    if let Err(e) = Self::send_file_data_packets(file, packet.id, &mut send_stream, gate).await {
+                                        // Fail the transfer rather than leave BuildKit with partial data
                                         tracing::error!("Failed to send file data: {}", e);
+                                        let err_packet = Packet {
+                                            r#type: PacketType::PacketErr as i32,
+                                            stat: None,
+                                            id: packet.id,
+                                            data: e.to_string().into_bytes(),
+                                        };
+                                        let _ = Self::send_grpc_packet(&mut send_stream, &err_packet).await;
+                                        Self::send_error_trailers(&mut send_stream, tonic::Code::Unknown, &e.to_string());
+                                        return Err(e);
+                                    }
+                                    if main_context {
+                                        file_sync.record_changed(&file.rel_path);
                                     }
                                 } else {
                                     tracing::warn!("File ID {} not found in map (probably a directory, ignoring)", packet.id);
@@ -494,41 +835,214 @@ impl GrpcTunnel {
         Ok(())
     }
 
+    /// Handle FileSend.DiffCopy streaming request
+    ///
+    /// BuildKit writes the exported tarball as a sequence of BytesMessage
+    /// frames and closes its side of the stream when done.
+    async fn handle_file_send_diff_copy_stream(
+        &self,
+        mut request_stream: h2::RecvStream,
+        mut respond: SendResponse<Bytes>,
+        exporter_id: u32,
+    ) -> Result<()> {
+        use crate::proto::moby::filesync::v1::BytesMessage as FileBytesMessage;
+        use tokio::io::AsyncWriteExt;
+
+        tracing::info!("FileSend.DiffCopy started for exporter {}", exporter_id);
+
+        let file_send = match &self.file_send {
+            Some(fs) => fs,
+            None => {
+                tracing::error!("FileSend not available");
+                return self.send_error_response(respond, "FileSend not available").await;
+            }
+        };
+
+        if let Some(SendTarget::Directory(dest)) = file_send.target(exporter_id) {
+            return Self::handle_file_send_directory_stream(
+                request_stream,
+                respond,
+                exporter_id,
+                dest.clone(),
+                self.frame_limits,
+            )
+            .await;
+        }
+
+        let mut writer = match file_send.open_archive(exporter_id).await {
+            Ok(w) => w,
+            Err(e) => {
+                tracing::error!("Failed to open export destination: {}", e);
+                return self.send_error_response(respond, &e.to_string()).await;
+            }
+        };
+
+        let response = Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "application/grpc")
+            .body(())
+            .unwrap();
+
+        let mut send_stream = respond.send_response(response, false)
+            .map_err(|e| Error::Http2Stream { source: e })?;
+
+        let mut decoder = FrameDecoder::with_limits(self.frame_limits);
+        let mut written = 0u64;
+        let mut write_result = Ok(());
+
+        'recv: while let Some(chunk) = request_stream.data().await {
+            let chunk = chunk.map_err(|e| Error::Http2Stream { source: e })?;
+            decoder.push(&chunk)?;
+            let _ = request_stream.flow_control().release_capacity(chunk.len());
+
+            while let Some(frame) = decoder.decode()? {
+                if frame.compressed {
+                    tracing::warn!("Received compressed message, skipping");
+                    continue;
+                }
+
+                let message = FileBytesMessage::decode(frame.payload)
+                    .map_err(|e| Error::decode("BytesMessage", e))?;
+
+                if let Err(e) = writer.write_all(&message.data).await {
+                    write_result = Err(e);
+                    break 'recv;
+                }
+                written += message.data.len() as u64;
+            }
+        }
+
+        if write_result.is_ok() {
+            write_result = writer.shutdown().await;
+        }
+
+        let trailers = match &write_result {
+            Ok(()) => {
+                tracing::info!("FileSend.DiffCopy completed for exporter {} ({} bytes)", exporter_id, written);
+                Response::builder()
+                    .header("grpc-status", "0")
+                    .body(())
+                    .unwrap()
+            }
+            Err(e) => {
+                tracing::error!("Failed to write export for exporter {}: {}", exporter_id, e);
+                Response::builder()
+                    .header("grpc-status", "13") // INTERNAL
+                    .header("grpc-message", grpc_message(&e.to_string()))
+                    .body(())
+                    .unwrap()
+            }
+        };
+
+        send_stream.send_trailers(trailers.headers().clone())
+            .map_err(|e| Error::Http2Stream { source: e })?;
+
+        write_result.map_err(Error::from)
+    }
+
+    /// Receive a `type=local` export into a directory
+    ///
+    /// BuildKit acts as the fsutil sender: we get STAT packets for the
+    /// exported tree, request regular files by ID, and write the DATA packets
+    /// that follow. Once everything has arrived we send FIN and wait for the
+    /// daemon to close the stream.
+    async fn handle_file_send_directory_stream(
+        mut request_stream: h2::RecvStream,
+        mut respond: SendResponse<Bytes>,
+        exporter_id: u32,
+        dest: std::path::PathBuf,
+        limits: FrameLimits,
+    ) -> Result<()> {
+        use crate::proto::fsutil::types::Packet;
+
+        tracing::info!("Receiving local export {} into {}", exporter_id, dest.display());
+
+        let response = Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "application/grpc")
+            .body(())
+            .unwrap();
+
+        let mut send_stream = respond.send_response(response, false)
+            .map_err(|e| Error::Http2Stream { source: e })?;
+
+        let mut receiver = DirectoryReceiver::new(&dest);
+        let mut decoder = FrameDecoder::with_limits(limits);
+
+        let result: Result<()> = async {
+            tokio::fs::create_dir_all(&dest).await?;
+
+            while let Some(chunk) = request_stream.data().await {
+                let chunk = chunk.map_err(|e| Error::Http2Stream { source: e })?;
+                decoder.push(&chunk)?;
+                let _ = request_stream.flow_control().release_capacity(chunk.len());
+
+                while let Some(frame) = decoder.decode()? {
+                    if frame.compressed {
+                        return Err(Error::protocol("compressed fsutil packets are not supported"));
+                    }
+
+                    let packet = Packet::decode(frame.payload)
+                        .map_err(|e| Error::decode("Packet", e))?;
+                    for reply in receiver.handle(packet).await? {
+                        Self::send_grpc_packet(&mut send_stream, &reply).await?;
+                    }
+                }
+            }
+
+            if !receiver.is_done() {
+                return Err(Error::session("local export stream ended before all files were received"));
+            }
+            Ok(())
+        }
+        .await;
+
+        let trailers = match &result {
+            Ok(()) => {
+                tracing::info!("Local export {} written to {}", exporter_id, dest.display());
+                Response::builder()
+                    .header("grpc-status", "0")
+                    .body(())
+                    .unwrap()
+            }
+            Err(e) => {
+                tracing::error!("Failed to receive local export {}: {}", exporter_id, e);
+                Response::builder()
+                    .header("grpc-status", "13") // INTERNAL
+                    .header("grpc-message", grpc_message(&e.to_string()))
+                    .body(())
+                    .unwrap()
+            }
+        };
+
+        send_stream.send_trailers(trailers.headers().clone())
+            .map_err(|e| Error::Http2Stream { source: e })?;
+
+        result
+    }
+
     /// Send STAT packets using depth-first traversal
     /// This is the correct way to send files to BuildKit's fsutil validator
     /// which requires files in depth-first order with entries sorted alphabetically within each directory
     ///
-    /// If followpaths is Some, only sends files in the list and their parent directories
+    /// Only entries that `filter` lets through are sent, e.g. the files in followpaths
+    /// and their parent directories, or those not ignored by .dockerignore.
+    /// Hardlinks on disk, and files that `dedup` finds to duplicate an earlier file,
+    /// are sent as hardlinks to it, and BuildKit never requests their data.
     fn send_stat_packets_dfs<'a>(
         path: std::path::PathBuf,
         prefix: String,
         stream: &'a mut h2::SendStream<Bytes>,
-        file_map: &'a mut std::collections::HashMap<u32, std::path::PathBuf>,
+        file_map: &'a mut std::collections::HashMap<u32, ListedFile>,
         id_counter: &'a mut u32,
-        followpaths: Option<&'a Vec<String>>,
+        filter: &'a mut ContextFilter,
+        dedup: &'a mut TransferDedup,
+        options: &'a FileSyncOptions,
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send + 'a>> {
         Box::pin(async move {
             use crate::proto::fsutil::types::{Packet, packet::PacketType, Stat};
 
-            tracing::debug!("send_stat_packets_dfs: {} (prefix: {}, followpaths: {:?})", path.display(), prefix, followpaths);
-
-            // Build set of paths to include if followpaths is specified
-            let include_paths = if let Some(paths) = followpaths {
-                let mut set = std::collections::HashSet::new();
-                for p in paths {
-                    set.insert(p.clone());
-                    // Add all parent directories
-                    let mut parent = p.as_str();
-                    while let Some(idx) = parent.rfind('/') {
-                        parent = &parent[..idx];
-                        set.insert(parent.to_string());
-                    }
-                }
-                tracing::debug!("Built include_paths set with {} entries: {:?}", set.len(), set);
-                Some(set)
-            } else {
-                None
-            };
+            tracing::debug!("send_stat_packets_dfs: {} (prefix: {})", path.display(), prefix);
 
             // Read all entries in this directory
             let mut entries = Vec::new();
@@ -538,45 +1052,42 @@ impl GrpcTunnel {
                 let file_name = entry.file_name();
                 let name = file_name.to_string_lossy().to_string();
                 let entry_path = entry.path();
-                let metadata = entry.metadata().await?;
+                // The file type comes with the directory listing; filtered out
+                // entries are never statted
+                let is_dir = entry.file_type().await?.is_dir();
 
-                entries.push((name, entry_path, metadata));
+                entries.push((name, entry_path, is_dir));
             }
 
             // Sort entries alphabetically by name (fsutil requirement)
             entries.sort_by(|a, b| a.0.cmp(&b.0));
 
             // Process entries in sorted order (depth-first)
-            for (name, entry_path, metadata) in entries {
+            for (name, entry_path, is_dir) in entries {
                 let rel_path = if prefix.is_empty() {
                     name.clone()
                 } else {
                     format!("{}/{}", prefix, name)
                 };
 
-                // Skip if not in include_paths (when filtering is enabled)
-                if let Some(ref paths) = include_paths {
-                    if !paths.contains(&rel_path) {
-                        tracing::debug!("Skipping {} (not in followpaths)", rel_path);
-                        eprintln!("DFS: Skipping {} (not in include_paths)", rel_path);
-                        continue;
-                    } else {
-                        eprintln!("DFS: Including {} (found in include_paths)", rel_path);
-                    }
+                // Skip entries outside followpaths or ignored by .dockerignore
+                let visit = filter.visit(&rel_path, is_dir);
+                if visit == Visit::Skip {
+                    tracing::debug!("Skipping {} (filtered out)", rel_path);
+                    continue;
                 }
-
-                let entry_id = *id_counter;
-                *id_counter += 1;
+                let metadata = tokio::fs::symlink_metadata(&entry_path).await?;
+                let (uid, gid) = options.ownership(&metadata);
 
                 // Create and send STAT packet for this entry
                 let mut stat = Stat {
                     path: rel_path.clone(),
                     mode: 0,
-                    uid: 0,
-                    gid: 0,
+                    uid,
+                    gid,
                     // For directories, size must be 0 (fsutil protocol requirement)
                     size: if metadata.is_dir() { 0 } else { metadata.len() as i64 },
-                    mod_time: 0,
+                    mod_time: options.mod_time(&metadata),
                     linkname: String::new(),
                     devmajor: 0,
                     devminor: 0,
@@ -600,6 +1111,38 @@ impl GrpcTunnel {
                     };
                 }
 
+                // Ignored directories are walked for re-included entries and only
+                // listed once one is sent
+                if visit == Visit::Hold {
+                    filter.hold(stat);
+                    Self::send_stat_packets_dfs(entry_path, rel_path, stream, file_map, id_counter, filter, dedup, options).await?;
+                    continue;
+                }
+                for held in filter.take_held() {
+                    tracing::debug!("Sending held STAT packet for: {} (id: {})", held.path, *id_counter);
+                    let held_packet = Packet {
+                        r#type: PacketType::PacketStat as i32,
+                        stat: Some(held),
+                        id: *id_counter,
+                        data: vec![],
+                    };
+                    *id_counter += 1;
+                    Self::send_grpc_packet(stream, &held_packet).await?;
+                }
+
+                // BuildKit numbers the STAT packets in the order they arrive
+                let entry_id = *id_counter;
+                *id_counter += 1;
+
+                if metadata.is_file() {
+                    if let Some(original) = dedup.check(&entry_path, &rel_path, &metadata, stat.mode).await? {
+                        // Like fsutil's sender, links carry no size of their own
+                        stat.linkname = original;
+                        stat.size = 0;
+                    }
+                }
+                let linked = !stat.linkname.is_empty();
+
                 let mode = stat.mode;
                 let size = stat.size;
                 let path_sent = stat.path.clone();
@@ -617,14 +1160,17 @@ impl GrpcTunnel {
                          mode, mode, size, (mode & 0o040000) != 0);
                 Self::send_grpc_packet(stream, &stat_packet).await?;
 
-                // Store file path in map for later data requests (only for files)
-                if metadata.is_file() {
-                    file_map.insert(entry_id, entry_path.clone());
+                // Store file path in map for later data requests (only for files with data)
+                if metadata.is_file() && !linked {
+                    let listed =
+                        ListedFile::list(entry_path.clone(), rel_path.clone(), metadata.len(), options)
+                            .await?;
+                    file_map.insert(entry_id, listed);
                 }
 
                 // Recursively process directories
                 if metadata.is_dir() {
-                    Self::send_stat_packets_dfs(entry_path, rel_path, stream, file_map, id_counter, followpaths).await?;
+                    Self::send_stat_packets_dfs(entry_path, rel_path, stream, file_map, id_counter, filter, dedup, options).await?;
                 }
             }
 
@@ -632,6 +1178,67 @@ impl GrpcTunnel {
         })
     }
 
+    /// Send STAT packets for a provided context
+    ///
+    /// Like [`send_stat_packets_dfs`](Self::send_stat_packets_dfs), but the
+    /// entries and their content come from `provider` instead of the disk.
+    async fn send_provided_stat_packets(
+        provider: &dyn ContextProvider,
+        stream: &mut h2::SendStream<Bytes>,
+        file_map: &mut std::collections::HashMap<u32, ListedFile>,
+        followpaths: &[String],
+    ) -> Result<()> {
+        use crate::proto::fsutil::types::{Packet, packet::PacketType, Stat};
+
+        let entries = list_context(provider).await?;
+        let follow = IgnorePatterns::follow(followpaths);
+        let mut filter = ContextFilter::new(vec![follow], IgnorePatterns::default());
+
+        let mut id = 0u32;
+        for entry in entries {
+            let visit = filter.visit(&entry.path, entry.is_dir());
+            if visit == Visit::Skip {
+                tracing::debug!("Skipping {} (not in followpaths)", entry.path);
+                continue;
+            }
+            let is_file = entry.is_file();
+            let stat = Stat {
+                path: entry.path.clone(),
+                mode: GoFileMode::from(UnixMode::from(entry.mode)).as_u32(),
+                size: if is_file { entry.content.len() as i64 } else { 0 },
+                linkname: entry.linkname,
+                ..Default::default()
+            };
+            if visit == Visit::Hold {
+                filter.hold(stat);
+                continue;
+            }
+            for held in filter.take_held() {
+                let held_packet = Packet {
+                    r#type: PacketType::PacketStat as i32,
+                    stat: Some(held),
+                    id,
+                    data: vec![],
+                };
+                Self::send_grpc_packet(stream, &held_packet).await?;
+                id += 1;
+            }
+            let stat_packet = Packet {
+                r#type: PacketType::PacketStat as i32,
+                stat: Some(stat),
+                id,
+                data: vec![],
+            };
+            tracing::debug!("Sending STAT packet for provided {} (id: {})", entry.path, id);
+            Self::send_grpc_packet(stream, &stat_packet).await?;
+            if is_file {
+                file_map.insert(id, ListedFile::in_memory(entry.path, entry.content));
+            }
+            id += 1;
+        }
+        Ok(())
+    }
+
     /// Recursively collect all entries (files and directories) from a path
     /// Returns a vector of (relative_path, absolute_path, metadata) tuples
     fn collect_entries_recursive<'a>(
@@ -670,23 +1277,54 @@ impl GrpcTunnel {
     }
 
     /// Send file data as DATA packets in response to a REQ
+    ///
+    /// The file is opened read-only and must still be as it was when it was
+    /// listed; see [`ContextFileReader`](super::ContextFileReader).
+    ///
+    /// Bulk transfers pass the session's `gate` and yield to urgent transfers
+    /// before every chunk.
     async fn send_file_data_packets(
-        path: std::path::PathBuf,
+        listed: &ListedFile,
         req_id: u32,
         stream: &mut h2::SendStream<Bytes>,
+        gate: Option<&TransferGate>,
     ) -> Result<()> {
         use crate::proto::fsutil::types::{Packet, packet::PacketType};
-        use tokio::io::AsyncReadExt;
 
-        tracing::info!("Sending file data for: {} (id: {})", path.display(), req_id);
+        tracing::info!("Sending file data for: {} (id: {})", listed.path.display(), req_id);
 
-        let mut file = tokio::fs::File::open(&path).await
-            ?;
+        if let Some(content) = &listed.content {
+            for chunk in content.chunks(32 * 1024) {
+                if let Some(gate) = gate {
+                    gate.wait_turn().await;
+                }
+                let data_packet = Packet {
+                    r#type: PacketType::PacketData as i32,
+                    stat: None,
+                    id: req_id,
+                    data: chunk.to_vec(),
+                };
+                Self::send_grpc_packet(stream, &data_packet).await?;
+            }
+            return Self::send_grpc_packet(stream, &Packet {
+                r#type: PacketType::PacketData as i32,
+                stat: None,
+                id: req_id,
+                data: vec![],
+            })
+            .await;
+        }
+
+        let mut file = listed.open().await?;
 
         let mut buffer = vec![0u8; 32 * 1024]; // 32KB chunks
 
         loop {
-            let n = file.read(&mut buffer).await?;
+            if let Some(gate) = gate {
+                gate.wait_turn().await;
+            }
+
+            let n = file.read_chunk(&mut buffer).await?;
             if n == 0 {
                 break;
             }
@@ -728,17 +1366,13 @@ impl GrpcTunnel {
         packet.encode(&mut payload)?;
 
         // Add gRPC framing (5-byte prefix)
-        let mut framed = Vec::new();
-        framed.push(0); // No compression
-        framed.extend_from_slice(&(payload.len() as u32).to_be_bytes());
-        framed.extend_from_slice(&payload);
+        let framed = encode_frame(&payload);
 
         let packet_type = PacketType::try_from(packet.r#type).ok();
         tracing::trace!("Sending packet: type={:?}, id={}, data_len={}, total_frame_len={}",
             packet_type, packet.id, packet.data.len(), framed.len());
 
-        stream.send_data(Bytes::from(framed), false)
-            .map_err(|e| Error::Http2Stream { source: e })?;
+        Self::send_with_capacity(stream, framed).await?;
 
         // Give the h2 stream a chance to flush
         tokio::task::yield_now().await;
@@ -746,21 +1380,61 @@ impl GrpcTunnel {
         Ok(())
     }
 
+    /// Send data once the peer's flow-control window has room for it
+    ///
+    /// `send_data` alone queues without limit inside h2, which would put
+    /// everything a bulk transfer produced ahead of any other stream.
+    async fn send_with_capacity(
+        stream: &mut h2::SendStream<Bytes>,
+        mut data: Bytes,
+    ) -> Result<()> {
+        while !data.is_empty() {
+            stream.reserve_capacity(data.len());
+            let capacity = match std::future::poll_fn(|cx| stream.poll_capacity(cx)).await {
+                Some(Ok(capacity)) => capacity,
+                Some(Err(e)) => return Err(Error::Http2Stream { source: e }),
+                None => return Err(Error::session("stream closed while sending data")),
+            };
+            if capacity == 0 {
+                continue;
+            }
+
+            let chunk = data.split_to(capacity.min(data.len()));
+            stream.send_data(chunk, false)
+                .map_err(|e| Error::Http2Stream { source: e })?;
+        }
+        Ok(())
+    }
+
     /// Handle Auth.GetTokenAuthority request
-    #[allow(dead_code)]
+    ///
+    /// Hosts without credentials get an `Unavailable` status as
+    /// [`Error::Grpc`], which makes BuildKit fall back to Credentials.
     async fn handle_auth_get_token_authority(&self, payload: Bytes) -> Result<Bytes> {
-        use crate::proto::moby::filesync::v1::{GetTokenAuthorityRequest, GetTokenAuthorityResponse};
+        use crate::proto::moby::filesync::v1::GetTokenAuthorityRequest;
+        use tonic::Request;
+        use crate::proto::moby::filesync::v1::auth_server::Auth;
 
         let request = GetTokenAuthorityRequest::decode(payload)
             .map_err(|e| Error::decode("GetTokenAuthorityRequest", e))?;
 
-        tracing::info!("Auth.GetTokenAuthority request for host: {}", request.host);
+        let response = self.auth.get_token_authority(Request::new(request)).await?.into_inner();
 
-        // Return empty response - we don't implement token-based auth
-        // BuildKit will detect empty public_key and fall back to Credentials method
-        let response = GetTokenAuthorityResponse {
-            public_key: vec![],
-        };
+        let mut buf = Vec::new();
+        response.encode(&mut buf)?;
+        Ok(Bytes::from(buf))
+    }
+
+    /// Handle Auth.VerifyTokenAuthority request
+    async fn handle_auth_verify_token_authority(&self, payload: Bytes) -> Result<Bytes> {
+        use crate::proto::moby::filesync::v1::VerifyTokenAuthorityRequest;
+        use tonic::Request;
+        use crate::proto::moby::filesync::v1::auth_server::Auth;
+
+        let request = VerifyTokenAuthorityRequest::decode(payload)
+            .map_err(|e| Error::decode("VerifyTokenAuthorityRequest", e))?;
+
+        let response = self.auth.verify_token_authority(Request::new(request)).await?.into_inner();
 
         let mut buf = Vec::new();
         response.encode(&mut buf)?;
@@ -776,37 +1450,17 @@ impl GrpcTunnel {
         let request = CredentialsRequest::decode(payload)
             .map_err(|e| Error::decode("CredentialsRequest", e))?;
 
-        tracing::info!("Auth.Credentials request for host: {}", request.host);
-
-        // Use AuthServer if configured, otherwise return empty credentials
-        let response = if let Some(auth) = &self.auth {
-            match auth.credentials(Request::new(request.clone())).await {
-                Ok(resp) => {
-                    let inner = resp.into_inner();
-                    if !inner.username.is_empty() {
-                        tracing::debug!("Returning credentials for host: {} (username: {})",
-                            request.host, inner.username);
-                    } else {
-                        tracing::debug!("No credentials found for host: {}, returning empty", request.host);
-                    }
-                    inner
-                }
-                Err(status) => {
-                    tracing::warn!("Failed to get credentials: {}, returning empty", status.message());
-                    use crate::proto::moby::filesync::v1::CredentialsResponse;
-                    CredentialsResponse {
-                        username: String::new(),
-                        secret: String::new(),
-                    }
+        // The AuthServer logs the access; hosts without credentials get empty ones
+        let response = match self.auth.credentials(Request::new(request)).await {
+            Ok(resp) => resp.into_inner(),
+            Err(status) => {
+                tracing::warn!("Failed to get credentials: {}, returning empty", status.message());
+                use crate::proto::moby::filesync::v1::CredentialsResponse;
+                CredentialsResponse {
+                    username: String::new(),
+                    secret: String::new(),
                 }
             }
-        } else {
-            tracing::debug!("No auth configured, returning empty credentials");
-            use crate::proto::moby::filesync::v1::CredentialsResponse;
-            CredentialsResponse {
-                username: String::new(),
-                secret: String::new(),
-            }
         };
 
         let mut buf = Vec::new();
@@ -815,16 +1469,20 @@ impl GrpcTunnel {
     }
 
     /// Handle Auth.FetchToken request
-    async fn handle_auth_fetch_token(&self, _payload: Bytes) -> Result<Bytes> {
-        use crate::proto::moby::filesync::v1::FetchTokenResponse;
+    ///
+    /// Token exchange failures come back as [`Error::Grpc`] carrying the
+    /// status BuildKit should see.
+    async fn handle_auth_fetch_token(&self, payload: Bytes) -> Result<Bytes> {
+        use crate::proto::moby::filesync::v1::FetchTokenRequest;
+        use tonic::Request;
+        use crate::proto::moby::filesync::v1::auth_server::Auth;
 
-        tracing::info!("Auth.FetchToken called");
+        let request = FetchTokenRequest::decode(payload)
+            .map_err(|e| Error::decode("FetchTokenRequest", e))?;
 
-        let response = FetchTokenResponse {
-            token: String::new(),
-            expires_in: 0,
-            issued_at: 0,
-        };
+        // The AuthServer logs the access and caches tokens; hosts without
+        // credentials get an anonymous token
+        let response = self.auth.fetch_token(Request::new(request)).await?.into_inner();
 
         let mut buf = Vec::new();
         response.encode(&mut buf)?;
@@ -838,8 +1496,6 @@ impl GrpcTunnel {
         let request = GetSecretRequest::decode(payload)
             .map_err(|e| Error::decode("GetSecretRequest", e))?;
 
-        tracing::info!("Secrets.GetSecret request for ID: {}", request.id);
-
         // If secrets service is not configured, return empty data
         let response = if let Some(secrets) = &self.secrets {
             // Use the SecretsServer's get_secret implementation through the Secrets trait
@@ -847,18 +1503,19 @@ impl GrpcTunnel {
             use crate::proto::moby::secrets::v1::secrets_server::Secrets;
 
             match secrets.get_secret(Request::new(request.clone())).await {
-                Ok(resp) => {
-                    let inner = resp.into_inner();
-                    tracing::debug!("Returning secret '{}' ({} bytes)", request.id, inner.data.len());
-                    inner
-                }
-                Err(status) => {
-                    tracing::warn!("Secret '{}' not found: {}", request.id, status.message());
+                // The secrets server logs the access according to the policy
+                Ok(resp) => resp.into_inner(),
+                Err(status) if status.code() == tonic::Code::NotFound => {
                     return Err(Error::SecretNotFound(status.message().to_string()));
                 }
+                // Unreadable or oversized secret files keep their status
+                Err(status) => return Err(Error::from(status)),
             }
         } else {
-            tracing::warn!("Secrets service not configured");
+            tracing::warn!(
+                "Secret '{}' requested but no secrets are configured",
+                self.access_log.redact(&request.id)
+            );
             return Err(Error::SecretsNotConfigured);
         };
 
@@ -867,6 +1524,326 @@ impl GrpcTunnel {
         Ok(Bytes::from(buf))
     }
 
+    /// Handle SSH.CheckAgent, telling BuildKit whether an agent ID is forwarded
+    fn handle_ssh_check_agent(&self, payload: Bytes) -> Result<Bytes> {
+        use crate::proto::moby::sshforward::v1::{CheckAgentRequest, CheckAgentResponse};
+
+        let request = CheckAgentRequest::decode(payload)
+            .map_err(|e| Error::decode("CheckAgentRequest", e))?;
+        let id = if request.id.is_empty() { DEFAULT_SSH_ID } else { request.id.as_str() };
+        if !self.ssh.as_ref().is_some_and(|ssh| ssh.has_agent(id)) {
+            return Err(Error::ssh_agent(format!("no SSH agent is forwarded as {:?}", id)));
+        }
+        tracing::debug!("SSH agent {} is available", id);
+
+        let mut buf = Vec::new();
+        CheckAgentResponse {}.encode(&mut buf)?;
+        Ok(Bytes::from(buf))
+    }
+
+    /// Handle SSH.ForwardAgent, piping the agent protocol to the forwarded agent
+    async fn handle_ssh_forward_agent_stream(
+        &self,
+        mut request_stream: h2::RecvStream,
+        mut respond: SendResponse<Bytes>,
+        id: &str,
+    ) -> Result<()> {
+        use crate::proto::moby::sshforward::v1::BytesMessage as SshBytesMessage;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let ssh = match self.ssh.as_ref().filter(|ssh| ssh.has_agent(id)) {
+            Some(ssh) => ssh,
+            None => {
+                let message = format!("no SSH agent is forwarded as {:?}", id);
+                return self.send_status_response(respond, tonic::Code::NotFound, &message).await;
+            }
+        };
+        let agent = match ssh.connect(id).await {
+            Ok(agent) => agent,
+            Err(e) => {
+                tracing::warn!("{}", e);
+                return self.send_status_response(respond, tonic::Code::Unavailable, &e.to_string()).await;
+            }
+        };
+        tracing::debug!("Forwarding SSH agent {}", id);
+
+        let response = Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "application/grpc")
+            .body(())
+            .unwrap();
+
+        let mut send_stream = respond.send_response(response, false)
+            .map_err(|e| Error::Http2Stream { source: e })?;
+
+        let (mut agent_reader, mut agent_writer) = tokio::io::split(agent);
+        let mut decoder = FrameDecoder::with_limits(self.frame_limits);
+        let mut buf = vec![0u8; 32 * 1024];
+        let mut client_open = true;
+
+        // Runs until the agent closes the connection; BuildKit closing its
+        // side only ends the requests to the agent
+        loop {
+            tokio::select! {
+                chunk = request_stream.data(), if client_open => match chunk {
+                    Some(chunk) => {
+                        let chunk = chunk.map_err(|e| Error::Http2Stream { source: e })?;
+                        decoder.push(&chunk)?;
+                        let _ = request_stream.flow_control().release_capacity(chunk.len());
+                        while let Some(frame) = decoder.decode()? {
+                            let message = SshBytesMessage::decode(frame.payload)
+                                .map_err(|e| Error::decode("BytesMessage", e))?;
+                            agent_writer.write_all(&message.data).await?;
+                        }
+                    }
+                    None => {
+                        client_open = false;
+                        let _ = agent_writer.shutdown().await;
+                    }
+                },
+                read = agent_reader.read(&mut buf) => {
+                    let n = read?;
+                    if n == 0 {
+                        break;
+                    }
+                    let mut payload = Vec::new();
+                    SshBytesMessage { data: buf[..n].to_vec() }.encode(&mut payload)?;
+                    Self::send_with_capacity(&mut send_stream, encode_frame(&payload)).await?;
+                }
+            }
+        }
+
+        let trailers = Response::builder()
+            .header("grpc-status", "0")
+            .body(())
+            .unwrap();
+        send_stream.send_trailers(trailers.headers().clone())
+            .map_err(|e| Error::Http2Stream { source: e })
+    }
+
+    /// Handle Upload.Pull, streaming a registered upload to BuildKit
+    async fn handle_upload_pull_stream(
+        &self,
+        // BuildKit sends nothing, but the stream stays open until the upload ends
+        _request_stream: h2::RecvStream,
+        mut respond: SendResponse<Bytes>,
+        path: &str,
+    ) -> Result<()> {
+        use crate::proto::moby::upload::v1::BytesMessage as UploadBytesMessage;
+        use tokio::io::AsyncReadExt;
+
+        let taken = match &self.upload {
+            Some(upload) => upload.take(path).await,
+            None => Err(Error::session("no uploads are configured")),
+        };
+        let mut reader = match taken {
+            Ok(reader) => reader,
+            Err(e) => {
+                tracing::warn!("Upload.Pull {} failed: {}", path, e);
+                return self.send_status_response(respond, tonic::Code::NotFound, &e.to_string()).await;
+            }
+        };
+        tracing::debug!("Streaming upload {}", path);
+
+        let response = Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "application/grpc")
+            .body(())
+            .unwrap();
+
+        let mut send_stream = respond.send_response(response, false)
+            .map_err(|e| Error::Http2Stream { source: e })?;
+
+        let mut buf = vec![0u8; 32 * 1024];
+        let mut total = 0u64;
+        let result = loop {
+            match reader.read(&mut buf).await {
+                Ok(0) => break Ok(()),
+                Ok(n) => {
+                    let mut payload = Vec::new();
+                    UploadBytesMessage { data: buf[..n].to_vec() }.encode(&mut payload)?;
+                    Self::send_with_capacity(&mut send_stream, encode_frame(&payload)).await?;
+                    total += n as u64;
+                }
+                Err(e) => break Err(e),
+            }
+        };
+
+        let trailers = match &result {
+            Ok(()) => {
+                tracing::debug!("Upload {} sent {} bytes", path, total);
+                Response::builder()
+                    .header("grpc-status", "0")
+                    .body(())
+                    .unwrap()
+            }
+            Err(e) => {
+                tracing::warn!("Reading upload {} failed: {}", path, e);
+                Response::builder()
+                    .header("grpc-status", (tonic::Code::Internal as i32).to_string())
+                    .header("grpc-message", grpc_message(&format!("reading upload failed: {}", e)))
+                    .body(())
+                    .unwrap()
+            }
+        };
+        send_stream.send_trailers(trailers.headers().clone())
+            .map_err(|e| Error::Http2Stream { source: e })?;
+        result.map_err(Error::from)
+    }
+
+    /// Handle the unary Content methods (Info, Update, Abort)
+    async fn handle_content_unary(&self, method: &str, store_id: &str, payload: Bytes) -> Result<Bytes> {
+        use crate::proto::containerd::services::content::v1::{InfoRequest, UpdateRequest};
+
+        let content = self.content.as_ref()
+            .ok_or_else(|| Error::content_store("no content stores are configured"))?;
+
+        let mut buf = Vec::new();
+        match method.rsplit('/').next() {
+            Some("Info") => {
+                let request = InfoRequest::decode(payload)
+                    .map_err(|e| Error::decode("InfoRequest", e))?;
+                tracing::debug!("Content.Info {} in store {}", request.digest, store_id);
+                content.info(store_id, request).await?.encode(&mut buf)?;
+            }
+            Some("Update") => {
+                let request = UpdateRequest::decode(payload)
+                    .map_err(|e| Error::decode("UpdateRequest", e))?;
+                content.update(store_id, request).await?.encode(&mut buf)?;
+            }
+            // Each Write stream streams into its own BlobWriter, hashing as it
+            // goes; a writer dropped uncommitted discards its data, so there is
+            // nothing to abort
+            _ => {}
+        }
+        Ok(Bytes::from(buf))
+    }
+
+    /// Handle Content.Read, streaming a blob from a content store
+    async fn handle_content_read_stream(
+        &self,
+        payload: Bytes,
+        mut respond: SendResponse<Bytes>,
+        store_id: &str,
+    ) -> Result<()> {
+        use crate::proto::containerd::services::content::v1::ReadContentRequest;
+
+        let content = match &self.content {
+            Some(content) => content,
+            None => return self.send_error_response(respond, "Content stores not configured").await,
+        };
+        let request = ReadContentRequest::decode(payload)
+            .map_err(|e| Error::decode("ReadContentRequest", e))?;
+        tracing::debug!("Content.Read {} from store {}", request.digest, store_id);
+
+        let response = Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "application/grpc")
+            .body(())
+            .unwrap();
+
+        let mut send_stream = respond.send_response(response, false)
+            .map_err(|e| Error::Http2Stream { source: e })?;
+
+        let mut position = request.offset.max(0) as u64;
+        let result = loop {
+            match content.read_chunk(store_id, &request, &mut position).await {
+                Ok(Some(chunk)) => {
+                    let mut buf = Vec::new();
+                    chunk.encode(&mut buf)?;
+                    Self::send_with_capacity(&mut send_stream, encode_frame(&buf)).await?;
+                }
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+
+        Self::send_content_trailers(&mut send_stream, &result)?;
+        result
+    }
+
+    /// Handle Content.Write, collecting a blob BuildKit exports into a store
+    async fn handle_content_write_stream(
+        &self,
+        mut request_stream: h2::RecvStream,
+        mut respond: SendResponse<Bytes>,
+        store_id: &str,
+    ) -> Result<()> {
+        use crate::proto::containerd::services::content::v1::WriteContentRequest;
+
+        let store = match self.content.as_ref().map(|content| content.store(store_id)) {
+            Some(Ok(store)) => Arc::clone(store),
+            Some(Err(e)) => {
+                return self.send_status_response(respond, content_status(&e), &e.to_string()).await;
+            }
+            None => return self.send_error_response(respond, "Content stores not configured").await,
+        };
+
+        let response = Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "application/grpc")
+            .body(())
+            .unwrap();
+
+        let mut send_stream = respond.send_response(response, false)
+            .map_err(|e| Error::Http2Stream { source: e })?;
+
+        let mut decoder = FrameDecoder::with_limits(self.frame_limits);
+        let mut upload = BlobUpload::new();
+        let mut result = Ok(());
+
+        'recv: while let Some(chunk) = request_stream.data().await {
+            let chunk = chunk.map_err(|e| Error::Http2Stream { source: e })?;
+            decoder.push(&chunk)?;
+            let _ = request_stream.flow_control().release_capacity(chunk.len());
+
+            while let Some(frame) = decoder.decode()? {
+                if frame.compressed {
+                    result = Err(Error::protocol("compressed content writes are not supported"));
+                    break 'recv;
+                }
+                let request = WriteContentRequest::decode(frame.payload)
+                    .map_err(|e| Error::decode("WriteContentRequest", e))?;
+
+                match upload.apply(store.as_ref(), request).await {
+                    Ok(reply) => {
+                        let mut buf = Vec::new();
+                        reply.encode(&mut buf)?;
+                        Self::send_with_capacity(&mut send_stream, encode_frame(&buf)).await?;
+                    }
+                    Err(e) => {
+                        result = Err(e);
+                        break 'recv;
+                    }
+                }
+            }
+        }
+
+        if let Err(e) = &result {
+            tracing::warn!("Content.Write to store {} failed: {}", store_id, e);
+        }
+        Self::send_content_trailers(&mut send_stream, &result)?;
+        result
+    }
+
+    /// Finish a Content stream with the status for `result`
+    fn send_content_trailers(stream: &mut h2::SendStream<Bytes>, result: &Result<()>) -> Result<()> {
+        let trailers = match result {
+            Ok(()) => Response::builder()
+                .header("grpc-status", "0")
+                .body(())
+                .unwrap(),
+            Err(e) => Response::builder()
+                .header("grpc-status", (content_status(e) as i32).to_string())
+                .header("grpc-message", grpc_message(&e.to_string()))
+                .body(())
+                .unwrap(),
+        };
+
+        stream.send_trailers(trailers.headers().clone())
+            .map_err(|e| Error::Http2Stream { source: e })
+    }
+
     /// Handle Health.Check request
     async fn handle_health_check(&self, _payload: Bytes) -> Result<Bytes> {
         tracing::info!("Health check called");
@@ -888,6 +1865,27 @@ impl GrpcTunnel {
     }
 }
 
+/// gRPC status for a failed content store request
+///
+/// containerd clients check for NotFound before uploading a blob.
+fn content_status(error: &Error) -> tonic::Code {
+    match error {
+        Error::ContentNotFound { .. } => tonic::Code::NotFound,
+        Error::Decode { .. } | Error::Protocol(_) => tonic::Code::InvalidArgument,
+        _ => tonic::Code::Internal,
+    }
+}
+
+/// gRPC status for a failed secret request
+fn secret_status(error: &Error) -> tonic::Code {
+    match error {
+        Error::SecretNotFound(_) | Error::SecretsNotConfigured => tonic::Code::NotFound,
+        Error::Grpc(status) => status.code(),
+        Error::Decode { .. } => tonic::Code::InvalidArgument,
+        _ => tonic::Code::Internal,
+    }
+}
+
 /// A stream that wraps BytesMessage channels to implement AsyncRead + AsyncWrite
 struct MessageStream {
     inbound_rx: Arc<tokio::sync::Mutex<mpsc::Receiver<BytesMessage>>>,
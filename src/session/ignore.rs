@@ -0,0 +1,320 @@
+//! `.dockerignore` patterns
+//!
+//! Local contexts are filtered on the client while they are walked, so
+//! ignored trees such as `node_modules` or `target` are neither statted nor
+//! sent. Patterns follow Docker's rules: paths are relative to the context
+//! root, `*` and `?` stay within one path component, `**` spans any number
+//! of them, a pattern also ignores everything below a matching directory, and
+//! `!` re-includes paths, with the last matching pattern winning.
+
+use crate::error::{Error, Result};
+use std::path::Path;
+
+/// Name of the ignore file in a context directory
+pub const DOCKERIGNORE_FILE: &str = ".dockerignore";
+
+/// Compiled `.dockerignore` patterns
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IgnorePatterns {
+    patterns: Vec<Pattern>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pattern {
+    /// Cleaned pattern, without the `!`
+    text: String,
+    exclusion: bool,
+    tokens: Vec<Token>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    /// `?`: any character but `/`
+    Any,
+    /// `*`: any run of characters without `/`
+    Star,
+    /// `**`: any run of characters
+    DoubleStar,
+    /// `**/`: any number of leading directories
+    Dirs,
+    /// `[...]`: a character class
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl IgnorePatterns {
+    /// Compile patterns as written on the lines of a `.dockerignore`
+    ///
+    /// Blank patterns are skipped; comments are not recognised here.
+    pub fn new<I, S>(patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let patterns = patterns
+            .into_iter()
+            .filter_map(|pattern| Pattern::compile(pattern.as_ref()))
+            .collect();
+        Self { patterns }
+    }
+
+    /// Compile BuildKit's `followpaths`
+    ///
+    /// These name paths rather than `.dockerignore` lines, so a leading `!`
+    /// is part of the name instead of a negation.
+    pub(crate) fn follow<I, S>(paths: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self::new(paths.into_iter().map(|path| {
+            let path = path.as_ref();
+            match path.strip_prefix('!') {
+                Some(name) => format!("\\!{}", name),
+                None => path.to_string(),
+            }
+        }))
+    }
+
+    /// Parse the content of a `.dockerignore` file
+    pub fn parse(content: &str) -> Self {
+        let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+        Self::new(content.lines().filter(|line| !line.starts_with('#')))
+    }
+
+    /// Read `.dockerignore` from a context directory; a missing file ignores
+    /// nothing
+    pub fn read(dir: &Path) -> Result<Self> {
+        let path = dir.join(DOCKERIGNORE_FILE);
+        match std::fs::read_to_string(&path) {
+            Ok(content) => Ok(Self::parse(&content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(source) => Err(Error::ContextFileRead { path, source }),
+        }
+    }
+
+    /// Whether there are no patterns
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Add `other`'s patterns after these, so they take precedence
+    pub(crate) fn append(&mut self, other: &IgnorePatterns) {
+        self.patterns.extend(other.patterns.iter().cloned());
+    }
+
+    /// Whether `path` (relative, `/`-separated) is ignored, either itself or
+    /// through one of its parent directories
+    pub fn is_ignored(&self, path: &str) -> bool {
+        let mut parent = vec![false; self.patterns.len()];
+        let mut ignored = false;
+        let mut end = 0;
+        for component in path.split('/') {
+            end += component.len();
+            (ignored, parent) = self.check(&path[..end], &parent);
+            end += 1;
+        }
+        ignored
+    }
+
+    /// Whether `path` is ignored, given the per-pattern results of its
+    /// parent directory; also returns the results for `path` itself
+    pub(crate) fn check(&self, path: &str, parent: &[bool]) -> (bool, Vec<bool>) {
+        let mut ignored = false;
+        let mut matches = Vec::with_capacity(self.patterns.len());
+        for (i, pattern) in self.patterns.iter().enumerate() {
+            let mut matched = parent.get(i).copied().unwrap_or(false);
+            // Later patterns can only flip the result one way
+            if !matched && pattern.exclusion == ignored {
+                matched = pattern.matches(path);
+            }
+            if matched {
+                ignored = !pattern.exclusion;
+            }
+            matches.push(matched);
+        }
+        (ignored, matches)
+    }
+
+    /// Whether a `!` pattern may re-include something below the ignored
+    /// directory `dir`, so it still has to be walked
+    ///
+    /// Like Docker, only exceptions spelled out under the directory's path
+    /// count.
+    pub(crate) fn may_keep_under(&self, dir: &str) -> bool {
+        let prefix = format!("{}/", dir);
+        self.patterns
+            .iter()
+            .any(|pattern| pattern.exclusion && format!("{}/", pattern.text).starts_with(&prefix))
+    }
+
+    /// Whether a pattern (other than a `!` exception) may match something
+    /// below the directory `dir`, which does not match itself
+    pub(crate) fn may_match_under(&self, dir: &str) -> bool {
+        self.patterns
+            .iter()
+            .filter(|pattern| !pattern.exclusion)
+            .any(|pattern| {
+                let mut components = pattern.text.split('/');
+                for dir_component in dir.split('/') {
+                    match components.next() {
+                        Some(component) if component.contains("**") => return true,
+                        Some(component) if component_matches(component, dir_component) => {}
+                        _ => return false,
+                    }
+                }
+                components.next().is_some()
+            })
+    }
+}
+
+impl Pattern {
+    fn compile(pattern: &str) -> Option<Self> {
+        let pattern = pattern.trim();
+        let (exclusion, pattern) = match pattern.strip_prefix('!') {
+            Some(rest) => (true, rest.trim()),
+            None => (false, pattern),
+        };
+        if pattern.is_empty() {
+            return None;
+        }
+        let text = clean(pattern);
+        Some(Self {
+            tokens: tokenize(&text),
+            text,
+            exclusion,
+        })
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        let path: Vec<char> = path.chars().collect();
+        match_tokens(&self.tokens, &path)
+    }
+}
+
+/// Lexically clean a pattern or path like Go's `filepath.Clean`, relative
+/// to the context root
+pub(crate) fn clean(pattern: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+    for component in pattern.split('/') {
+        match component {
+            "" | "." => {}
+            ".." if components.last().is_some_and(|last| *last != "..") => {
+                components.pop();
+            }
+            // The root has no parent
+            ".." if pattern.starts_with('/') => {}
+            component => components.push(component),
+        }
+    }
+    if components.is_empty() {
+        ".".to_string()
+    } else {
+        components.join("/")
+    }
+}
+
+/// Whether a single path component matches a single pattern component
+fn component_matches(pattern: &str, component: &str) -> bool {
+    let component: Vec<char> = component.chars().collect();
+    match_tokens(&tokenize(pattern), &component)
+}
+
+fn tokenize(pattern: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                while chars.peek() == Some(&'*') {
+                    chars.next();
+                }
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    Token::Dirs
+                } else {
+                    Token::DoubleStar
+                }
+            }
+            '*' => Token::Star,
+            '?' => Token::Any,
+            '\\' => Token::Char(chars.next().unwrap_or('\\')),
+            '[' => {
+                let mut class = chars.clone();
+                match parse_class(&mut class) {
+                    Some(token) => {
+                        chars = class;
+                        token
+                    }
+                    None => Token::Char('['),
+                }
+            }
+            c => Token::Char(c),
+        };
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// Parse a character class after its `[`; `None` if it is not closed
+fn parse_class(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> Option<Token> {
+    let negated = matches!(chars.peek(), Some('^' | '!'));
+    if negated {
+        chars.next();
+    }
+    let mut ranges = Vec::new();
+    loop {
+        let mut low = chars.next()?;
+        if low == ']' && !ranges.is_empty() {
+            return Some(Token::Class { negated, ranges });
+        }
+        if low == '\\' {
+            low = chars.next()?;
+        }
+        let mut high = low;
+        if chars.peek() == Some(&'-') {
+            let mut ahead = chars.clone();
+            ahead.next();
+            if let Some(end) = ahead.next().filter(|end| *end != ']') {
+                high = if end == '\\' { ahead.next()? } else { end };
+                *chars = ahead;
+            }
+        }
+        ranges.push((low, high));
+    }
+}
+
+fn match_tokens(tokens: &[Token], path: &[char]) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return path.is_empty();
+    };
+    match token {
+        Token::Star => {
+            let run = path.iter().take_while(|c| **c != '/').count();
+            (0..=run).any(|n| match_tokens(rest, &path[n..]))
+        }
+        Token::DoubleStar => (0..=path.len()).any(|n| match_tokens(rest, &path[n..])),
+        Token::Dirs => {
+            match_tokens(rest, path)
+                || path
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, c)| **c == '/')
+                    .any(|(i, _)| match_tokens(rest, &path[i + 1..]))
+        }
+        Token::Any => path
+            .split_first()
+            .is_some_and(|(c, path)| *c != '/' && match_tokens(rest, path)),
+        Token::Char(expected) => path
+            .split_first()
+            .is_some_and(|(c, path)| c == expected && match_tokens(rest, path)),
+        Token::Class { negated, ranges } => path.split_first().is_some_and(|(c, path)| {
+            let in_class = ranges.iter().any(|&(low, high)| (low..=high).contains(c));
+            *c != '/' && in_class != *negated && match_tokens(rest, path)
+        }),
+    }
+}
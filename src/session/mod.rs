@@ -1,24 +1,91 @@
 //! BuildKit session implementation for file access and streaming
 
+pub mod access;
+pub mod access_log;
+pub mod content;
+pub mod dedup;
 pub mod filesync;
+pub mod filesend;
+pub mod frame;
 pub mod auth;
 pub mod secrets;
 pub mod grpc_tunnel;
+pub mod ignore;
+pub mod priority;
+pub mod shared_key;
+pub mod source;
+pub mod sshagent;
+pub mod sshforward;
+pub mod upload;
 
 use crate::error::{Error, Result};
+use bytes::Bytes;
 use std::collections::HashMap;
 use std::path::PathBuf;
-use std::sync::Arc;
-use tokio::sync::{mpsc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex, Notify};
+use tokio::task::{AbortHandle, JoinHandle};
+use tonic::metadata::{Ascii, MetadataKey, MetadataMap, MetadataValue};
 use tonic::transport::Channel;
 use uuid::Uuid;
 
 use crate::proto::moby::buildkit::v1::{BytesMessage, control_client::ControlClient};
+use crate::task::spawn_named;
+use frame::FrameLimits;
 use grpc_tunnel::GrpcTunnel;
 
-pub use filesync::FileSyncServer;
+pub use access::ContextUser;
+pub use access_log::AccessLogPolicy;
+pub use content::{BlobInfo, BlobWriter, ContentStore, ContentStoreServer};
+pub use dedup::{DuplicateFiles, SyncStats};
+pub use filesync::{ContextFileReader, FileSyncOptions, FileSyncServer};
+pub use ignore::IgnorePatterns;
+pub use filesend::{DirectoryReceiver, FileSendServer, SendTarget};
 pub use auth::{AuthServer, RegistryAuthConfig};
 pub use secrets::SecretsServer;
+pub use shared_key::{random_shared_key, shared_key_for_path, SharedKey};
+pub use source::{list_context, ContextEntry, ContextProvider, FileEntry, TarContext};
+pub use sshagent::SshKey;
+pub use sshforward::SshForwardServer;
+pub use upload::{Upload, UploadServer};
+
+/// Header carrying the session ID
+const HEADER_UUID: &str = "X-Docker-Expose-Session-Uuid";
+/// Header carrying the session name (the shared key)
+const HEADER_NAME: &str = "X-Docker-Expose-Session-Name";
+/// Header carrying the shared key
+const HEADER_SHARED_KEY: &str = "X-Docker-Expose-Session-Sharedkey";
+/// Header listing the gRPC methods the session serves, one value each
+const HEADER_GRPC_METHOD: &str = "X-Docker-Expose-Session-Grpc-Method";
+
+// gRPC metadata keys must be lowercase
+const HEADER_UUID_KEY: &str = "x-docker-expose-session-uuid";
+const HEADER_NAME_KEY: &str = "x-docker-expose-session-name";
+const HEADER_SHARED_KEY_KEY: &str = "x-docker-expose-session-sharedkey";
+const HEADER_GRPC_METHOD_KEY: &str = "x-docker-expose-session-grpc-method";
+
+/// gRPC methods served over the session
+const SESSION_METHODS: [&str; 17] = [
+    "/grpc.health.v1.Health/Check",
+    "/moby.filesync.v1.FileSync/DiffCopy",
+    "/moby.filesync.v1.FileSync/TarStream",
+    "/moby.filesync.v1.FileSend/DiffCopy",
+    "/moby.filesync.v1.Auth/Credentials",
+    "/moby.filesync.v1.Auth/FetchToken",
+    "/moby.filesync.v1.Auth/GetTokenAuthority",
+    "/moby.filesync.v1.Auth/VerifyTokenAuthority",
+    "/moby.buildkit.secrets.v1.Secrets/GetSecret",
+    "/moby.sshforward.v1.SSH/CheckAgent",
+    "/moby.sshforward.v1.SSH/ForwardAgent",
+    "/moby.upload.v1.Upload/Pull",
+    "/containerd.services.content.v1.Content/Info",
+    "/containerd.services.content.v1.Content/Update",
+    "/containerd.services.content.v1.Content/Read",
+    "/containerd.services.content.v1.Content/Write",
+    "/containerd.services.content.v1.Content/Abort",
+];
 
 /// Session manager for BuildKit
 ///
@@ -31,20 +98,112 @@ pub struct Session {
     pub shared_key: String,
     tx: Option<mpsc::Sender<BytesMessage>>,
     services: Arc<Mutex<SessionServices>>,
+    tasks: Option<SessionTasks>,
+    active_requests: Arc<AtomicUsize>,
+    frame_limits: FrameLimits,
+    fault: Arc<std::sync::Mutex<Option<Error>>>,
+    fatal: Arc<Notify>,
+    access_log: AccessLogPolicy,
+    duplicate_files: DuplicateFiles,
+    file_sync_options: FileSyncOptions,
+    context_include: IgnorePatterns,
+    context_exclude: IgnorePatterns,
+}
+
+/// How long [`Session::finish`] waits for in-flight requests
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Background tasks driving a started session
+struct SessionTasks {
+    inbound: JoinHandle<()>,
+    outbound: JoinHandle<()>,
+    tunnel: JoinHandle<()>,
+}
+
+/// State of a session background task
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    /// The session has not been started
+    NotStarted,
+    /// The task is running
+    Running,
+    /// The task has exited (the stream closed or it failed)
+    Finished,
+}
+
+impl TaskState {
+    fn of(handle: &AbortHandle) -> Self {
+        if handle.is_finished() {
+            TaskState::Finished
+        } else {
+            TaskState::Running
+        }
+    }
+}
+
+/// Liveness report for a session's background tasks
+///
+/// A dead forwarder otherwise only shows up as a build that hangs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionHealth {
+    /// Task forwarding messages from BuildKit into the tunnel
+    pub inbound: TaskState,
+    /// Task forwarding tunnel output back to BuildKit
+    pub outbound: TaskState,
+    /// HTTP/2 server answering BuildKit's requests
+    pub tunnel: TaskState,
+    /// Requests (file sync, auth, secrets, ...) currently being handled
+    pub active_requests: usize,
+}
+
+impl SessionHealth {
+    /// Check whether every background task is running
+    pub fn is_healthy(&self) -> bool {
+        [self.inbound, self.outbound, self.tunnel]
+            .iter()
+            .all(|state| *state == TaskState::Running)
+    }
+}
+
+/// Reports the health of a started session from outside the build
+///
+/// Cheap to clone, and does not keep the session's tasks alive.
+#[derive(Debug, Clone)]
+pub struct SessionMonitor {
+    inbound: AbortHandle,
+    outbound: AbortHandle,
+    tunnel: AbortHandle,
+    active_requests: Arc<AtomicUsize>,
+}
+
+impl SessionMonitor {
+    /// Report whether the session's background tasks are still alive
+    pub fn health(&self) -> SessionHealth {
+        SessionHealth {
+            inbound: TaskState::of(&self.inbound),
+            outbound: TaskState::of(&self.outbound),
+            tunnel: TaskState::of(&self.tunnel),
+            active_requests: self.active_requests.load(Ordering::Relaxed),
+        }
+    }
 }
 
 /// Session service handlers
 struct SessionServices {
     file_sync: Option<FileSyncServer>,
+    file_send: Option<FileSendServer>,
     auth: Option<AuthServer>,
     secrets: Option<SecretsServer>,
+    ssh: Option<SshForwardServer>,
+    upload: Option<UploadServer>,
+    content: Option<ContentStoreServer>,
 }
 
 impl Session {
-    /// Create a new session
+    /// Create a new session with a random shared key
     pub fn new() -> Self {
         let id = Uuid::new_v4().to_string();
-        let shared_key = format!("session-{}", Uuid::new_v4());
+        let shared_key = random_shared_key();
 
         Self {
             id,
@@ -52,19 +211,167 @@ impl Session {
             tx: None,
             services: Arc::new(Mutex::new(SessionServices {
                 file_sync: None,
+                file_send: None,
                 auth: None,
                 secrets: None,
+                ssh: None,
+                upload: None,
+                content: None,
             })),
+            tasks: None,
+            active_requests: Arc::new(AtomicUsize::new(0)),
+            frame_limits: FrameLimits::default(),
+            fault: Arc::new(std::sync::Mutex::new(None)),
+            fatal: Arc::new(Notify::new()),
+            access_log: AccessLogPolicy::default(),
+            duplicate_files: DuplicateFiles::default(),
+            file_sync_options: FileSyncOptions::default(),
+            context_include: IgnorePatterns::default(),
+            context_exclude: IgnorePatterns::default(),
         }
     }
 
+    /// Set the shared key BuildKit uses to cache synced local sources
+    ///
+    /// See [`SharedKey`] for choosing a key that survives across builds.
+    pub fn with_shared_key(mut self, key: impl Into<String>) -> Self {
+        self.shared_key = key.into();
+        self
+    }
+
+    /// Bound the memory used to reassemble messages from BuildKit
+    pub fn with_frame_limits(mut self, limits: FrameLimits) -> Self {
+        self.frame_limits = limits;
+        self
+    }
+
+    /// Set how the tunnel logs credential and secret requests
+    ///
+    /// Services added with [`add_auth`](Self::add_auth) and
+    /// [`add_secrets`](Self::add_secrets) carry their own policy.
+    pub fn with_access_log(mut self, policy: AccessLogPolicy) -> Self {
+        self.access_log = policy;
+        self
+    }
+
+    /// Set how local context files with duplicate content are sent
+    ///
+    /// Applies to file sync services added afterwards.
+    pub fn with_duplicate_files(mut self, duplicates: DuplicateFiles) -> Self {
+        self.duplicate_files = duplicates;
+        self
+    }
+
+    /// Set the options for sending local directories
+    ///
+    /// Applies to file sync services added afterwards.
+    pub fn with_file_sync_options(mut self, options: FileSyncOptions) -> Self {
+        self.file_sync_options = options;
+        self
+    }
+
+    /// Filter the main context with include and exclude patterns
+    ///
+    /// See [`FileSyncServer::set_context_patterns`]; applies to file sync
+    /// services added afterwards.
+    pub fn with_context_patterns(
+        mut self,
+        include: IgnorePatterns,
+        exclude: IgnorePatterns,
+    ) -> Self {
+        self.context_include = include;
+        self.context_exclude = exclude;
+        self
+    }
+
+    fn new_file_sync(&self, root_path: PathBuf) -> FileSyncServer {
+        let mut file_sync = FileSyncServer::new(root_path);
+        file_sync.set_duplicate_files(self.duplicate_files);
+        file_sync.set_options(self.file_sync_options.clone());
+        file_sync.set_context_patterns(self.context_include.clone(), self.context_exclude.clone());
+        file_sync
+    }
+
     /// Add file sync service for a specific directory
     pub async fn add_file_sync(&mut self, root_path: PathBuf) {
         let mut services = self.services.lock().await;
-        services.file_sync = Some(FileSyncServer::new(root_path));
+        match &mut services.file_sync {
+            Some(file_sync) => file_sync.set_root_path(root_path),
+            None => services.file_sync = Some(self.new_file_sync(root_path)),
+        }
         tracing::debug!("Added FileSync service");
     }
 
+    /// Serve a local directory under `name` (e.g., a named build context)
+    pub async fn add_local_dir(&mut self, name: impl Into<String>, path: PathBuf) {
+        let name = name.into();
+        let mut services = self.services.lock().await;
+        // Builds without a local context never request the root, so any
+        // directory will do until one is set
+        services
+            .file_sync
+            .get_or_insert_with(|| self.new_file_sync(path.clone()))
+            .add_dir(name.clone(), path);
+        tracing::debug!("Added local directory {}", name);
+    }
+
+    /// Serve the main context from `provider` rather than a local directory
+    pub async fn add_context_provider(&mut self, provider: Arc<dyn ContextProvider>) {
+        let mut services = self.services.lock().await;
+        match &mut services.file_sync {
+            Some(file_sync) => file_sync.set_provider(provider),
+            None => {
+                // The root directory is never read while a provider is set
+                let mut file_sync = self.new_file_sync(PathBuf::new());
+                file_sync.set_provider(provider);
+                services.file_sync = Some(file_sync);
+            }
+        }
+        tracing::debug!("Added context provider");
+    }
+
+    /// Serve the Dockerfile from memory rather than from the context
+    pub async fn add_inline_dockerfile(&mut self, content: impl Into<Bytes>) {
+        let mut services = self.services.lock().await;
+        match &mut services.file_sync {
+            Some(file_sync) => file_sync.set_dockerfile(content),
+            None => {
+                // Only the Dockerfile is ever requested; the root is unused
+                let mut file_sync = self.new_file_sync(PathBuf::new());
+                file_sync.set_dockerfile(content);
+                services.file_sync = Some(file_sync);
+            }
+        }
+        tracing::debug!("Added inline Dockerfile");
+    }
+
+    /// Totals of the files sent from local directories so far
+    pub async fn sync_stats(&self) -> SyncStats {
+        let services = self.services.lock().await;
+        services
+            .file_sync
+            .as_ref()
+            .map(FileSyncServer::stats)
+            .unwrap_or_default()
+    }
+
+    /// Main context files BuildKit requested because they were new or changed
+    pub async fn changed_files(&self) -> Vec<String> {
+        let services = self.services.lock().await;
+        services
+            .file_sync
+            .as_ref()
+            .map(FileSyncServer::changed_files)
+            .unwrap_or_default()
+    }
+
+    /// Add file send service for exporters that stream results to the client
+    pub async fn add_file_send(&mut self, file_send: FileSendServer) {
+        let mut services = self.services.lock().await;
+        services.file_send = Some(file_send);
+        tracing::debug!("Added FileSend service");
+    }
+
     /// Add authentication service
     pub async fn add_auth(&mut self, auth: AuthServer) {
         let mut services = self.services.lock().await;
@@ -79,6 +386,27 @@ impl Session {
         tracing::debug!("Added Secrets service");
     }
 
+    /// Add SSH agent forwarding
+    pub async fn add_ssh_forward(&mut self, ssh: SshForwardServer) {
+        let mut services = self.services.lock().await;
+        services.ssh = Some(ssh);
+        tracing::debug!("Added SSH forwarding service");
+    }
+
+    /// Add streams BuildKit can pull through the Upload service
+    pub async fn add_uploads(&mut self, upload: UploadServer) {
+        let mut services = self.services.lock().await;
+        services.upload = Some(upload);
+        tracing::debug!("Added Upload service");
+    }
+
+    /// Add content stores BuildKit can read blobs from and export blobs to
+    pub async fn add_content_stores(&mut self, content: ContentStoreServer) {
+        let mut services = self.services.lock().await;
+        services.content = Some(content);
+        tracing::debug!("Added Content service");
+    }
+
     /// Start a session with BuildKit
     pub async fn start(&mut self, mut control: ControlClient<Channel>) -> Result<()> {
         let (tx, mut rx) = mpsc::channel::<BytesMessage>(128);
@@ -96,18 +424,7 @@ impl Session {
 
         // Create request with session metadata headers
         let mut request = tonic::Request::new(outbound);
-        let metadata = request.metadata_mut();
-
-        // Add session metadata headers
-        for (key, values) in self.metadata() {
-            if let Ok(k) = key.parse::<tonic::metadata::MetadataKey<tonic::metadata::Ascii>>() {
-                for value in values {
-                    if let Ok(v) = value.parse::<tonic::metadata::MetadataValue<tonic::metadata::Ascii>>() {
-                        metadata.append(k.clone(), v);
-                    }
-                }
-            }
-        }
+        *request.metadata_mut() = self.grpc_metadata();
 
         // Start the session
         let response = control
@@ -123,67 +440,140 @@ impl Session {
         // Get services for tunnel
         let services_guard = services.lock().await;
         let file_sync = services_guard.file_sync.clone();
-        let auth = services_guard.auth.clone();
+        let file_send = services_guard.file_send.clone();
+        // Auth is always advertised, so anonymous token exchanges are served too
+        let auth = services_guard.auth.clone().unwrap_or_else(|| {
+            let mut auth = AuthServer::new();
+            auth.set_access_log(self.access_log);
+            auth
+        });
         let secrets = services_guard.secrets.clone();
+        let ssh = services_guard.ssh.clone();
+        let upload = services_guard.upload.clone();
+        let content = services_guard.content.clone();
         drop(services_guard);
 
         // Spawn task to receive from BuildKit and forward to tunnel
-        tokio::spawn(async move {
-            while let Ok(Some(msg)) = inbound.message().await {
-                if let Err(e) = inbound_tx.send(msg).await {
-                    tracing::error!("Failed to forward inbound message: {}", e);
-                    break;
+        let inbound_task = spawn_named(
+            "buildkit.session.inbound",
+            tracing::info_span!("session_inbound", session_id = %session_id),
+            async move {
+                loop {
+                    match inbound.message().await {
+                        Ok(Some(msg)) => {
+                            if inbound_tx.send(msg).await.is_err() {
+                                tracing::debug!("Session tunnel stopped reading");
+                                break;
+                            }
+                        }
+                        Ok(None) => {
+                            tracing::info!("BuildKit closed the session");
+                            break;
+                        }
+                        // The daemon cancels the session stream once the solve is done
+                        Err(status) if status.code() == tonic::Code::Cancelled => {
+                            tracing::info!("BuildKit cancelled the session");
+                            break;
+                        }
+                        Err(status) => {
+                            tracing::warn!("Session stream failed: {}", status);
+                            break;
+                        }
+                    }
                 }
-            }
-            tracing::info!("Session {} inbound ended", session_id);
-        });
+            },
+        );
 
         // Spawn task to receive from tunnel and forward to BuildKit
         let tx_clone = tx.clone();
-        tokio::spawn(async move {
-            while let Some(msg) = outbound_rx.recv().await {
-                if let Err(e) = tx_clone.send(msg).await {
-                    tracing::error!("Failed to forward outbound message: {}", e);
-                    break;
+        let outbound_task = spawn_named(
+            "buildkit.session.outbound",
+            tracing::info_span!("session_outbound", session_id = %self.id),
+            async move {
+                while let Some(msg) = outbound_rx.recv().await {
+                    if let Err(e) = tx_clone.send(msg).await {
+                        tracing::error!("Failed to forward outbound message: {}", e);
+                        break;
+                    }
                 }
-            }
-        });
+                tracing::info!("Session outbound ended");
+            },
+        );
 
         // Start the HTTP/2 server in the tunnel
-        let tunnel = GrpcTunnel::new(tx.clone(), file_sync, auth, secrets);
-        tokio::spawn(async move {
-            if let Err(e) = tunnel.serve(inbound_rx, outbound_tx).await {
-                tracing::error!("HTTP/2 tunnel error: {}", e);
-            }
-        });
+        let tunnel = GrpcTunnel::new(tx.clone(), file_sync, file_send, Some(auth), secrets)
+            .with_content_stores(content)
+            .with_ssh_forward(ssh)
+            .with_uploads(upload)
+            .with_active_requests(Arc::clone(&self.active_requests))
+            .with_frame_limits(self.frame_limits)
+            .with_fault_slot(Arc::clone(&self.fault))
+            .with_fatal_signal(Arc::clone(&self.fatal))
+            .with_access_log(self.access_log);
+        let tunnel_task = spawn_named(
+            "buildkit.session.tunnel",
+            tracing::info_span!("session_tunnel", session_id = %self.id),
+            async move {
+                if let Err(e) = tunnel.serve(inbound_rx, outbound_tx).await {
+                    tracing::error!("HTTP/2 tunnel error: {}", e);
+                }
+            },
+        );
 
+        self.tasks = Some(SessionTasks {
+            inbound: inbound_task,
+            outbound: outbound_task,
+            tunnel: tunnel_task,
+        });
         self.tx = Some(tx);
         Ok(())
     }
 
     /// Get session metadata to attach to solve request
     pub fn metadata(&self) -> HashMap<String, Vec<String>> {
-        let mut meta = HashMap::new();
-        meta.insert("X-Docker-Expose-Session-Uuid".to_string(), vec![self.id.clone()]);
-        meta.insert("X-Docker-Expose-Session-Name".to_string(), vec![self.shared_key.clone()]);
-        meta.insert("X-Docker-Expose-Session-Sharedkey".to_string(), vec![self.shared_key.clone()]);
-
-        // Add supported gRPC methods
-        let methods = vec![
-            "/grpc.health.v1.Health/Check".to_string(),
-            "/moby.filesync.v1.FileSync/DiffCopy".to_string(),
-            "/moby.filesync.v1.FileSync/TarStream".to_string(),
-            "/moby.filesync.v1.Auth/Credentials".to_string(),
-            "/moby.filesync.v1.Auth/FetchToken".to_string(),
-            "/moby.filesync.v1.Auth/GetTokenAuthority".to_string(),
-            "/moby.filesync.v1.Auth/VerifyTokenAuthority".to_string(),
-            "/moby.buildkit.secrets.v1.Secrets/GetSecret".to_string(),
-        ];
-        meta.insert("X-Docker-Expose-Session-Grpc-Method".to_string(), methods);
-
+        let mut meta = HashMap::with_capacity(4);
+        meta.insert(HEADER_UUID.to_string(), vec![self.id.clone()]);
+        meta.insert(HEADER_NAME.to_string(), vec![self.shared_key.clone()]);
+        meta.insert(HEADER_SHARED_KEY.to_string(), vec![self.shared_key.clone()]);
+        meta.insert(
+            HEADER_GRPC_METHOD.to_string(),
+            SESSION_METHODS.iter().map(|m| m.to_string()).collect(),
+        );
         meta
     }
 
+    /// Session metadata as gRPC request headers
+    ///
+    /// Same headers as [`metadata`](Self::metadata), without re-parsing the
+    /// header names and method list for every request; only the session ID
+    /// and shared key are converted. Values that are not valid ASCII header
+    /// values are left out.
+    pub fn grpc_metadata(&self) -> MetadataMap {
+        static METHODS: OnceLock<Vec<MetadataValue<Ascii>>> = OnceLock::new();
+        let methods = METHODS.get_or_init(|| {
+            SESSION_METHODS
+                .iter()
+                .map(|method| MetadataValue::from_static(method))
+                .collect()
+        });
+
+        let mut metadata = MetadataMap::with_capacity(3 + methods.len());
+        if let Ok(id) = self.id.parse::<MetadataValue<Ascii>>() {
+            metadata.append(MetadataKey::from_static(HEADER_UUID_KEY), id);
+        }
+        if let Ok(key) = self.shared_key.parse::<MetadataValue<Ascii>>() {
+            metadata.append(MetadataKey::from_static(HEADER_NAME_KEY), key.clone());
+            metadata.append(MetadataKey::from_static(HEADER_SHARED_KEY_KEY), key);
+        }
+        for method in methods {
+            metadata.append(
+                MetadataKey::from_static(HEADER_GRPC_METHOD_KEY),
+                method.clone(),
+            );
+        }
+        metadata
+    }
+
     /// Send a message to the session stream
     pub async fn send(&self, msg: BytesMessage) -> Result<()> {
         if let Some(ref tx) = self.tx {
@@ -196,6 +586,89 @@ impl Session {
         }
     }
 
+    /// Report whether the session's background tasks are still alive
+    pub fn health(&self) -> SessionHealth {
+        match self.monitor() {
+            Some(monitor) => monitor.health(),
+            None => SessionHealth {
+                inbound: TaskState::NotStarted,
+                outbound: TaskState::NotStarted,
+                tunnel: TaskState::NotStarted,
+                active_requests: self.active_requests.load(Ordering::Relaxed),
+            },
+        }
+    }
+
+    /// Monitor for the session's health, once it is started
+    pub fn monitor(&self) -> Option<SessionMonitor> {
+        self.tasks.as_ref().map(|tasks| SessionMonitor {
+            inbound: tasks.inbound.abort_handle(),
+            outbound: tasks.outbound.abort_handle(),
+            tunnel: tasks.tunnel.abort_handle(),
+            active_requests: Arc::clone(&self.active_requests),
+        })
+    }
+
+    /// Handles that abort the session's background tasks
+    ///
+    /// Empty until the session is started.
+    pub fn abort_handles(&self) -> Vec<tokio::task::AbortHandle> {
+        match &self.tasks {
+            Some(tasks) => vec![
+                tasks.inbound.abort_handle(),
+                tasks.outbound.abort_handle(),
+                tasks.tunnel.abort_handle(),
+            ],
+            None => Vec::new(),
+        }
+    }
+
+    /// Wait until the session fails in a way the build cannot recover from
+    ///
+    /// Resolves when the tunnel could not be set up at all, e.g. because the
+    /// HTTP/2 handshake inside the session failed; otherwise never. Race it
+    /// against the solve so the build fails instead of waiting for files
+    /// BuildKit can no longer request.
+    pub async fn failed(&self) -> Error {
+        self.fatal.notified().await;
+        self.fault
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+            .unwrap_or_else(|| Error::SessionHandshake("session tunnel stopped".to_string()))
+    }
+
+    /// Wind the session down after the solve
+    ///
+    /// Waits (briefly) for in-flight requests to finish, stops the background
+    /// tasks and returns the first protocol fault the tunnel saw. The daemon
+    /// closing the session, even mid-transfer, is not reported.
+    pub async fn finish(&mut self) -> Result<()> {
+        let drained = tokio::time::timeout(DRAIN_TIMEOUT, async {
+            while self.active_requests.load(Ordering::Relaxed) > 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await;
+        if drained.is_err() {
+            tracing::warn!(
+                "{} session requests still running after {:?}",
+                self.active_requests.load(Ordering::Relaxed),
+                DRAIN_TIMEOUT
+            );
+        }
+
+        if let Some(tasks) = &self.tasks {
+            tasks.inbound.abort();
+            tasks.outbound.abort();
+            tasks.tunnel.abort();
+        }
+        self.tx = None;
+
+        let fault = self.fault.lock().unwrap_or_else(|e| e.into_inner()).take();
+        fault.map_or(Ok(()), Err)
+    }
+
     /// Get session ID for SolveRequest
     pub fn get_id(&self) -> String {
         self.id.clone()
@@ -208,6 +681,17 @@ impl Default for Session {
     }
 }
 
+/// Tear the session down if the build is dropped before [`Session::finish`]
+impl Drop for Session {
+    fn drop(&mut self) {
+        if let Some(tasks) = &self.tasks {
+            tasks.inbound.abort();
+            tasks.outbound.abort();
+            tasks.tunnel.abort();
+        }
+    }
+}
+
 /// File sync helper for sending local files to BuildKit
 pub struct FileSync {
     context_path: PathBuf,
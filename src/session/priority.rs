@@ -0,0 +1,63 @@
+//! Prioritization between concurrent file transfers
+//!
+//! BuildKit opens one FileSync stream for the Dockerfile directory and one for
+//! the build context, and may do so concurrently. HTTP/2 has no usable
+//! priority support in `h2`, so a large context sync can fill the connection
+//! window and delay the (tiny) Dockerfile stream the frontend is blocked on.
+//!
+//! [`TransferGate`] lets urgent transfers mark themselves active; bulk
+//! transfers wait for their turn between chunks, so urgent data is never
+//! queued behind more than one bulk chunk.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// Coordinates urgent and bulk transfers sharing a session
+#[derive(Debug, Default)]
+pub struct TransferGate {
+    urgent: AtomicUsize,
+    idle: Notify,
+}
+
+impl TransferGate {
+    /// Create a gate with no urgent transfers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mark an urgent transfer as active until the guard is dropped
+    pub fn urgent(self: &Arc<Self>) -> UrgentTransfer {
+        self.urgent.fetch_add(1, Ordering::SeqCst);
+        UrgentTransfer(Arc::clone(self))
+    }
+
+    /// Number of urgent transfers in progress
+    pub fn urgent_count(&self) -> usize {
+        self.urgent.load(Ordering::SeqCst)
+    }
+
+    /// Wait until no urgent transfer is in progress
+    pub async fn wait_turn(&self) {
+        loop {
+            // Register before checking so a release in between is not missed
+            let released = self.idle.notified();
+            if self.urgent_count() == 0 {
+                return;
+            }
+            released.await;
+        }
+    }
+}
+
+/// Guard for an urgent transfer; bulk transfers resume once all are dropped
+#[derive(Debug)]
+pub struct UrgentTransfer(Arc<TransferGate>);
+
+impl Drop for UrgentTransfer {
+    fn drop(&mut self) {
+        if self.0.urgent.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
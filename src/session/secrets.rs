@@ -2,13 +2,67 @@
 
 use tonic::{Request, Response, Status};
 use std::collections::HashMap;
+use std::path::PathBuf;
+use super::access_log::AccessLogPolicy;
 use crate::proto::moby::secrets::v1::{
     secrets_server::Secrets,
     GetSecretRequest, GetSecretResponse,
 };
 
 /// Maximum secret size (500KB, matching BuildKit's MaxSecretSize)
-const MAX_SECRET_SIZE: usize = 500 * 1024;
+pub(crate) const MAX_SECRET_SIZE: usize = 500 * 1024;
+
+/// Where the value of a secret comes from
+#[derive(Clone)]
+enum SecretSource {
+    /// Held in memory
+    Data(Vec<u8>),
+    /// Read from a file each time BuildKit asks for it
+    File(PathBuf),
+}
+
+impl std::fmt::Debug for SecretSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SecretSource::Data(data) => write!(f, "Data({} bytes)", data.len()),
+            SecretSource::File(path) => f.debug_tuple("File").field(path).finish(),
+        }
+    }
+}
+
+impl SecretSource {
+    /// Load the secret, enforcing MAX_SECRET_SIZE on files
+    async fn load(&self, id: &str) -> Result<Vec<u8>, Status> {
+        let path = match self {
+            SecretSource::Data(data) => return Ok(data.clone()),
+            SecretSource::File(path) => path,
+        };
+        let unreadable = |e: std::io::Error| {
+            let message = format!("secret {}: cannot read {}: {}", id, path.display(), e);
+            if e.kind() == std::io::ErrorKind::NotFound {
+                Status::not_found(message)
+            } else {
+                Status::internal(message)
+            }
+        };
+        let too_large = || {
+            Status::resource_exhausted(format!(
+                "secret {}: {} exceeds the maximum of {} bytes",
+                id, path.display(), MAX_SECRET_SIZE
+            ))
+        };
+        let size = tokio::fs::metadata(path).await.map_err(unreadable)?.len();
+        if size > MAX_SECRET_SIZE as u64 {
+            return Err(too_large());
+        }
+        let data = tokio::fs::read(path).await.map_err(unreadable)?;
+        // The file may have grown since it was measured
+        if data.len() > MAX_SECRET_SIZE {
+            return Err(too_large());
+        }
+        Ok(data)
+    }
+}
 
 /// Secrets server implementation for BuildKit session
 ///
@@ -16,7 +70,8 @@ const MAX_SECRET_SIZE: usize = 500 * 1024;
 /// `RUN --mount=type=secret,id=<secret_id>` in Dockerfiles.
 #[derive(Debug, Clone, Default)]
 pub struct SecretsServer {
-    secrets: HashMap<String, Vec<u8>>,
+    secrets: HashMap<String, SecretSource>,
+    access_log: AccessLogPolicy,
 }
 
 impl SecretsServer {
@@ -32,9 +87,15 @@ impl SecretsServer {
     pub fn new() -> Self {
         Self {
             secrets: HashMap::new(),
+            access_log: AccessLogPolicy::default(),
         }
     }
 
+    /// Set how secret requests are logged
+    pub fn set_access_log(&mut self, policy: AccessLogPolicy) {
+        self.access_log = policy;
+    }
+
     /// Add a secret with the given ID and data
     ///
     /// # Arguments
@@ -58,10 +119,28 @@ impl SecretsServer {
         if data.len() > MAX_SECRET_SIZE {
             return Err(format!("Secret size {} exceeds maximum of {}", data.len(), MAX_SECRET_SIZE));
         }
-        self.secrets.insert(id.into(), data);
+        self.secrets.insert(id.into(), SecretSource::Data(data));
         Ok(())
     }
 
+    /// Add a secret read from a file when BuildKit requests it
+    ///
+    /// The file is not touched until the build mounts the secret, and is
+    /// read again on every request. Files larger than MAX_SECRET_SIZE (500KB)
+    /// are refused at that point.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use buildkit_client::session::SecretsServer;
+    ///
+    /// let mut secrets = SecretsServer::new();
+    /// secrets.add_secret_file("netrc", "/home/user/.netrc");
+    /// ```
+    pub fn add_secret_file(&mut self, id: impl Into<String>, path: impl Into<PathBuf>) {
+        self.secrets.insert(id.into(), SecretSource::File(path.into()));
+    }
+
     /// Add a secret from a string value
     ///
     /// # Arguments
@@ -113,16 +192,26 @@ impl Secrets for SecretsServer {
         request: Request<GetSecretRequest>,
     ) -> Result<Response<GetSecretResponse>, Status> {
         let req = request.into_inner();
-        tracing::debug!("Secret requested - ID: {}, Annotations: {:?}", req.id, req.annotations);
-
-        if let Some(data) = self.secrets.get(&req.id) {
-            tracing::debug!("Found secret '{}' ({} bytes)", req.id, data.len());
-            Ok(Response::new(GetSecretResponse {
-                data: data.clone(),
-            }))
+        if let Some(source) = self.secrets.get(&req.id) {
+            let data = match source.load(self.access_log.redact(&req.id)).await {
+                Ok(data) => data,
+                Err(status) => {
+                    tracing::warn!("{}", status.message());
+                    return Err(status);
+                }
+            };
+            self.access_log.record(
+                || format!("Secret requested: {}", req.id),
+                || format!(
+                    "Returning secret '{}' ({} bytes, annotations: {:?})",
+                    req.id, data.len(), req.annotations
+                ),
+            );
+            Ok(Response::new(GetSecretResponse { data }))
         } else {
-            tracing::warn!("Secret '{}' not found", req.id);
-            Err(Status::not_found(format!("secret {} not found", req.id)))
+            let id = self.access_log.redact(&req.id);
+            tracing::warn!("Secret '{}' not found", id);
+            Err(Status::not_found(format!("secret {} not found", id)))
         }
     }
 }
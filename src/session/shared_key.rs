@@ -0,0 +1,65 @@
+//! Session shared keys
+//!
+//! BuildKit keys the cache of previously synced local sources by the
+//! session's shared key. A key that is stable for a context directory lets
+//! repeat builds send only the files that changed, while a fresh key per
+//! build makes every build upload the whole context again.
+
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use uuid::Uuid;
+
+/// How the shared key of a build's session is chosen
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum SharedKey {
+    /// Derive the key from the absolute context path, like buildx
+    ///
+    /// Builds without a local context get a random key.
+    #[default]
+    ContextPath,
+    /// Use a new random key for every build
+    Random,
+    /// Use this exact key
+    Fixed(String),
+}
+
+impl SharedKey {
+    /// Use an exact key
+    pub fn fixed(key: impl Into<String>) -> Self {
+        SharedKey::Fixed(key.into())
+    }
+
+    /// Pick the key for a session whose local context, if any, is `context`
+    ///
+    /// `context` should be canonicalized so that different spellings of the
+    /// same directory share one key.
+    pub fn resolve(&self, context: Option<&Path>) -> String {
+        match (self, context) {
+            (SharedKey::Fixed(key), _) => key.clone(),
+            (SharedKey::ContextPath, Some(context)) => shared_key_for_path(context),
+            _ => random_shared_key(),
+        }
+    }
+}
+
+/// Shared key derived from a context directory
+///
+/// # Example
+///
+/// ```
+/// use buildkit_client::session::shared_key_for_path;
+/// use std::path::Path;
+///
+/// let key = shared_key_for_path(Path::new("/src/app"));
+/// assert_eq!(key, shared_key_for_path(Path::new("/src/app")));
+/// assert_ne!(key, shared_key_for_path(Path::new("/src/other")));
+/// ```
+pub fn shared_key_for_path(context: &Path) -> String {
+    let digest = Sha256::digest(context.as_os_str().as_encoded_bytes());
+    format!("context-{:x}", digest)
+}
+
+/// A new random shared key
+pub fn random_shared_key() -> String {
+    format!("session-{}", Uuid::new_v4())
+}
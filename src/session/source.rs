@@ -0,0 +1,377 @@
+//! Build contexts served from somewhere other than a local directory
+//!
+//! The file sync server normally walks a directory on disk. A
+//! [`ContextProvider`] lets it serve a context that only exists in the
+//! client process instead, e.g. files generated from templates or the
+//! content of a tar archive ([`TarContext`]).
+
+use crate::error::{Error, Result};
+use bytes::Bytes;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::{Mutex, OnceCell};
+
+/// Unix file type mask
+const S_IFMT: u32 = 0o170000;
+
+/// Unix file type bits of a directory
+const S_IFDIR: u32 = 0o040000;
+
+/// Unix file type bits of a symbolic link
+const S_IFLNK: u32 = 0o120000;
+
+/// Unix file type bits of a regular file
+const S_IFREG: u32 = 0o100000;
+
+/// A file of an in-memory build context
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileEntry {
+    /// File content
+    pub content: Bytes,
+    /// Permission bits (e.g., `0o644`)
+    pub mode: u32,
+}
+
+impl FileEntry {
+    /// Regular file with mode `0o644`
+    pub fn new(content: impl Into<Bytes>) -> Self {
+        Self {
+            content: content.into(),
+            mode: 0o644,
+        }
+    }
+
+    /// Executable file with mode `0o755`
+    pub fn executable(content: impl Into<Bytes>) -> Self {
+        Self::new(content).with_mode(0o755)
+    }
+
+    /// Set the permission bits
+    pub fn with_mode(mut self, mode: u32) -> Self {
+        self.mode = mode & 0o7777;
+        self
+    }
+}
+
+impl From<&str> for FileEntry {
+    fn from(content: &str) -> Self {
+        FileEntry::new(content.to_string())
+    }
+}
+
+impl From<String> for FileEntry {
+    fn from(content: String) -> Self {
+        FileEntry::new(content)
+    }
+}
+
+impl From<Vec<u8>> for FileEntry {
+    fn from(content: Vec<u8>) -> Self {
+        FileEntry::new(content)
+    }
+}
+
+/// An entry of a provided context
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextEntry {
+    /// Slash-separated path relative to the context root
+    pub path: String,
+    /// Unix mode, including the file type bits
+    pub mode: u32,
+    /// File content; empty for directories and symlinks
+    pub content: Bytes,
+    /// Target of a symlink
+    pub linkname: String,
+}
+
+impl ContextEntry {
+    /// Regular file
+    pub fn file(path: impl Into<String>, mode: u32, content: impl Into<Bytes>) -> Self {
+        Self {
+            path: path.into(),
+            mode: S_IFREG | (mode & 0o7777),
+            content: content.into(),
+            linkname: String::new(),
+        }
+    }
+
+    /// Directory with mode `0o755`
+    pub fn dir(path: impl Into<String>) -> Self {
+        Self::dir_with_mode(path, 0o755)
+    }
+
+    /// Directory with the given permission bits
+    pub fn dir_with_mode(path: impl Into<String>, mode: u32) -> Self {
+        Self {
+            path: path.into(),
+            mode: S_IFDIR | (mode & 0o7777),
+            content: Bytes::new(),
+            linkname: String::new(),
+        }
+    }
+
+    /// Symbolic link pointing at `target`
+    pub fn symlink(path: impl Into<String>, target: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            mode: S_IFLNK | 0o777,
+            content: Bytes::new(),
+            linkname: target.into(),
+        }
+    }
+
+    /// Check whether the entry is a directory
+    pub fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+
+    /// Check whether the entry is a regular file
+    pub fn is_file(&self) -> bool {
+        self.mode & S_IFMT == S_IFREG
+    }
+}
+
+/// A build context the file sync server serves without a local directory
+///
+/// Implement this to generate a context on the fly. Entries may be returned
+/// in any order and parent directories may be left out; they are sorted and
+/// completed before being sent.
+#[tonic::async_trait]
+pub trait ContextProvider: Send + Sync + std::fmt::Debug {
+    /// List the files (and optionally directories) of the context
+    async fn entries(&self) -> Result<Vec<ContextEntry>>;
+}
+
+#[tonic::async_trait]
+impl ContextProvider for HashMap<PathBuf, FileEntry> {
+    async fn entries(&self) -> Result<Vec<ContextEntry>> {
+        self.iter()
+            .map(|(path, file)| {
+                Ok(ContextEntry::file(
+                    context_path(path)?,
+                    file.mode,
+                    file.content.clone(),
+                ))
+            })
+            .collect()
+    }
+}
+
+/// List a provided context in the order fsutil expects
+///
+/// Missing parent directories are added, and entries are sorted depth-first
+/// with the entries of each directory sorted by name.
+pub async fn list_context(provider: &dyn ContextProvider) -> Result<Vec<ContextEntry>> {
+    let mut listing: BTreeMap<Vec<String>, ContextEntry> = BTreeMap::new();
+    for entry in provider.entries().await? {
+        let path = context_path(Path::new(&entry.path))?;
+        let components: Vec<String> = path.split('/').map(str::to_string).collect();
+        for depth in 1..components.len() {
+            let parent = components[..depth].to_vec();
+            if let Some(existing) = listing.get(&parent) {
+                if !existing.is_dir() {
+                    return Err(Error::InvalidConfig(format!(
+                        "context entry {:?} is inside the file {:?}",
+                        path, existing.path
+                    )));
+                }
+                continue;
+            }
+            listing.insert(parent.clone(), ContextEntry::dir(parent.join("/")));
+        }
+        let entry = ContextEntry { path, ..entry };
+        match listing.get(&components) {
+            Some(existing) if !(existing.is_dir() && entry.is_dir()) => {
+                return Err(Error::InvalidConfig(format!(
+                    "duplicate context entry {:?}",
+                    entry.path
+                )));
+            }
+            _ => {}
+        }
+        listing.insert(components, entry);
+    }
+    Ok(listing.into_values().collect())
+}
+
+/// Normalize a context-relative path to slash-separated form
+///
+/// Absolute paths and paths leaving the context are rejected.
+fn context_path(path: &Path) -> Result<String> {
+    let mut components = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => components.push(name.to_string_lossy().into_owned()),
+            Component::CurDir => {}
+            _ => {
+                return Err(Error::InvalidConfig(format!(
+                    "context path {} must be relative and stay inside the context",
+                    path.display()
+                )));
+            }
+        }
+    }
+    if components.is_empty() {
+        return Err(Error::InvalidConfig("empty context path".to_string()));
+    }
+    Ok(components.join("/"))
+}
+
+/// A tar archive served as the build context
+///
+/// Many CI systems already hold the context as a tarball artifact; serving
+/// it directly saves unpacking it to disk. The archive is only read when
+/// BuildKit first asks for the context, then kept in memory for the rest of
+/// the build. Gzip-compressed archives are detected and decompressed.
+pub struct TarContext {
+    origin: TarOrigin,
+    entries: OnceCell<Vec<ContextEntry>>,
+}
+
+enum TarOrigin {
+    Path(PathBuf),
+    Reader(Mutex<Option<Box<dyn AsyncRead + Send + Unpin>>>),
+}
+
+impl TarContext {
+    /// Serve the archive at `path`
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        Self {
+            origin: TarOrigin::Path(path.into()),
+            entries: OnceCell::new(),
+        }
+    }
+
+    /// Serve the archive read from `reader` (e.g., stdin or a download)
+    ///
+    /// The reader is consumed when BuildKit first asks for the context.
+    pub fn from_reader(reader: impl AsyncRead + Send + Unpin + 'static) -> Self {
+        Self {
+            origin: TarOrigin::Reader(Mutex::new(Some(Box::new(reader)))),
+            entries: OnceCell::new(),
+        }
+    }
+
+    /// Path of the archive, unless it is read from a stream
+    pub fn path(&self) -> Option<&Path> {
+        match &self.origin {
+            TarOrigin::Path(path) => Some(path),
+            TarOrigin::Reader(_) => None,
+        }
+    }
+
+    async fn read_archive(&self) -> Result<Vec<u8>> {
+        match &self.origin {
+            TarOrigin::Path(path) => {
+                tokio::fs::read(path)
+                    .await
+                    .map_err(|source| Error::ContextFileRead {
+                        path: path.clone(),
+                        source,
+                    })
+            }
+            TarOrigin::Reader(reader) => {
+                let mut reader = reader.lock().await.take().ok_or_else(|| {
+                    Error::context_archive("the archive stream was already consumed")
+                })?;
+                let mut data = Vec::new();
+                reader.read_to_end(&mut data).await?;
+                Ok(data)
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for TarContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.origin {
+            TarOrigin::Path(path) => f.debug_tuple("TarContext").field(path).finish(),
+            TarOrigin::Reader(_) => f.write_str("TarContext(<stream>)"),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl ContextProvider for TarContext {
+    async fn entries(&self) -> Result<Vec<ContextEntry>> {
+        let entries = self
+            .entries
+            .get_or_try_init(|| async {
+                let data = self.read_archive().await?;
+                tokio::task::spawn_blocking(move || unpack_entries(&data))
+                    .await
+                    .map_err(|e| Error::other(format!("reading the archive failed: {}", e)))?
+            })
+            .await?;
+        Ok(entries.clone())
+    }
+}
+
+/// Read the entries of a (possibly gzip-compressed) tar archive
+///
+/// Hardlinks become copies of the file they point at; device nodes and
+/// FIFOs are skipped.
+fn unpack_entries(data: &[u8]) -> Result<Vec<ContextEntry>> {
+    let archive_error = |e: std::io::Error| Error::context_archive(e.to_string());
+    let reader: Box<dyn Read + '_> = if data.starts_with(&[0x1f, 0x8b]) {
+        Box::new(flate2::read::GzDecoder::new(data))
+    } else {
+        Box::new(data)
+    };
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = Vec::new();
+    let mut files: HashMap<String, Bytes> = HashMap::new();
+    for entry in archive.entries().map_err(archive_error)? {
+        let mut entry = entry.map_err(archive_error)?;
+        let raw_path = entry.path().map_err(archive_error)?.into_owned();
+        // The archive root itself (usually "./")
+        if raw_path
+            .components()
+            .all(|component| component == Component::CurDir)
+        {
+            continue;
+        }
+        let path = context_path(&raw_path)?;
+        let mode = entry.header().mode().map_err(archive_error)?;
+        let entry_type = entry.header().entry_type();
+        match entry_type {
+            tar::EntryType::Regular | tar::EntryType::Continuous => {
+                let mut content = Vec::with_capacity(entry.size() as usize);
+                entry.read_to_end(&mut content).map_err(archive_error)?;
+                let content = Bytes::from(content);
+                files.insert(path.clone(), content.clone());
+                entries.push(ContextEntry::file(path, mode, content));
+            }
+            tar::EntryType::Directory => entries.push(ContextEntry::dir_with_mode(path, mode)),
+            tar::EntryType::Symlink => {
+                let target = entry.link_name().map_err(archive_error)?.ok_or_else(|| {
+                    Error::context_archive(format!("symlink {} has no target", path))
+                })?;
+                entries.push(ContextEntry::symlink(
+                    path,
+                    target.to_string_lossy().into_owned(),
+                ));
+            }
+            tar::EntryType::Link => {
+                let target = entry.link_name().map_err(archive_error)?.ok_or_else(|| {
+                    Error::context_archive(format!("hardlink {} has no target", path))
+                })?;
+                let target = context_path(&target)?;
+                let content = files.get(&target).cloned().ok_or_else(|| {
+                    Error::context_archive(format!(
+                        "hardlink {} points at {}, which is not an earlier file",
+                        path, target
+                    ))
+                })?;
+                files.insert(path.clone(), content.clone());
+                entries.push(ContextEntry::file(path, mode, content));
+            }
+            other => {
+                tracing::warn!("Skipping {} in the context archive ({:?})", path, other);
+            }
+        }
+    }
+    Ok(entries)
+}
@@ -0,0 +1,356 @@
+//! SSH agent forwarding for BuildKit sessions
+//!
+//! BuildKit never sees private keys. When a git source with an SSH remote
+//! (or a `RUN --mount=type=ssh` step) needs to authenticate, the daemon asks
+//! the session whether an agent with the given ID exists (`CheckAgent`), then
+//! tunnels the agent protocol through `ForwardAgent`; the client pipes it to
+//! a local ssh-agent socket, or straight to the agent embedded in the client.
+//!
+//! Builds forward agents named in [`BuildConfig::ssh`](crate::BuildConfig::ssh),
+//! using the same specs as `docker build --ssh`, and keys given to
+//! [`BuildConfig::ssh_key`](crate::BuildConfig::ssh_key), which an agent
+//! embedded in the client serves from memory.
+
+use super::sshagent::{self, Identity, SshKey};
+use crate::error::{Error, Result};
+use crate::task::spawn_named;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::process::{Child, Command};
+
+/// Metadata header carrying the agent ID of a `ForwardAgent` request
+pub const SSH_ID_HEADER: &str = "buildkit.ssh.id";
+
+/// Agent ID used when none is given (and by git sources with SSH remotes)
+pub const DEFAULT_SSH_ID: &str = "default";
+
+/// How long to wait for a private agent to create its socket
+const AGENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Buffer between BuildKit and the embedded agent, per connection
+const MEMORY_AGENT_BUFFER: usize = 64 * 1024;
+
+/// Connection to a forwarded agent
+pub trait AgentStream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> AgentStream for T {}
+
+/// SSH agents served to BuildKit, keyed by ID
+#[derive(Debug, Clone, Default)]
+pub struct SshForwardServer {
+    agents: HashMap<String, Agent>,
+}
+
+#[derive(Debug, Clone)]
+enum Agent {
+    /// An agent listening on a unix socket
+    Socket {
+        socket: PathBuf,
+        /// Keeps a private agent running while the server is in use
+        _owned: Option<Arc<AgentProcess>>,
+    },
+    /// Keys served in-process by the embedded agent
+    Memory(MemoryAgent),
+}
+
+impl SshForwardServer {
+    /// Create a server without agents
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forward the agent listening on `socket` as `id`
+    pub fn add_socket(&mut self, id: impl Into<String>, socket: impl Into<PathBuf>) {
+        self.agents.insert(
+            id.into(),
+            Agent::Socket {
+                socket: socket.into(),
+                _owned: None,
+            },
+        );
+    }
+
+    /// Forward the local ssh-agent (`SSH_AUTH_SOCK`) as `id`
+    pub fn add_local_agent(&mut self, id: impl Into<String>) -> Result<()> {
+        let socket = std::env::var_os("SSH_AUTH_SOCK")
+            .filter(|socket| !socket.is_empty())
+            .ok_or_else(|| {
+                Error::ssh_agent("SSH_AUTH_SOCK is not set; start ssh-agent or use a key file")
+            })?;
+        self.add_socket(id, socket);
+        Ok(())
+    }
+
+    /// Forward a private agent holding the key at `key_path` as `id`
+    ///
+    /// The agent (`ssh-agent` and `ssh-add` from OpenSSH) only holds this
+    /// key and is stopped once the server and every clone of it are dropped.
+    /// Keys protected by a passphrase must be added to a running agent
+    /// instead.
+    pub async fn add_key_file(&mut self, id: impl Into<String>, key_path: &Path) -> Result<()> {
+        self.add_key_files(id, &[key_path]).await
+    }
+
+    /// Forward a private agent holding every key in `key_paths` as `id`
+    ///
+    /// See [`add_key_file`](Self::add_key_file).
+    pub async fn add_key_files(
+        &mut self,
+        id: impl Into<String>,
+        key_paths: &[&Path],
+    ) -> Result<()> {
+        let process = AgentProcess::start().await?;
+        for key_path in key_paths {
+            process.add_key(key_path).await?;
+        }
+        self.agents.insert(
+            id.into(),
+            Agent::Socket {
+                socket: process.socket.clone(),
+                _owned: Some(Arc::new(process)),
+            },
+        );
+        Ok(())
+    }
+
+    /// Forward an agent embedded in the client that serves `keys` as `id`
+    ///
+    /// The keys stay in memory and no OpenSSH tools are needed; see
+    /// [`SshKey`] for the keys supported. The agent is served in-process to
+    /// each forwarded connection, without a socket anyone else could reach.
+    pub fn add_keys(&mut self, id: impl Into<String>, keys: &[SshKey]) -> Result<()> {
+        let mut identities = Vec::new();
+        for key in keys {
+            identities.extend(key.identities()?);
+        }
+        self.agents.insert(
+            id.into(),
+            Agent::Memory(MemoryAgent {
+                identities: identities.into(),
+            }),
+        );
+        Ok(())
+    }
+
+    /// Forward the agent described by a `docker build --ssh` spec
+    ///
+    /// `id` forwards the local ssh-agent, `id=socket` the agent listening on
+    /// `socket`, and `id=key[,key...]` a private agent holding the given key
+    /// files.
+    pub async fn add_spec(&mut self, spec: &str) -> Result<()> {
+        let (id, paths) = parse_spec(spec)?;
+        match paths.as_slice() {
+            [] => self.add_local_agent(id),
+            [path] if is_socket(path) => {
+                self.add_socket(id, *path);
+                Ok(())
+            }
+            keys => self.add_key_files(id, keys).await,
+        }
+    }
+
+    /// Socket of the agent forwarded as `id`
+    ///
+    /// `None` for agents served from memory, which have no socket.
+    pub fn socket(&self, id: &str) -> Option<&Path> {
+        match self.agents.get(id) {
+            Some(Agent::Socket { socket, .. }) => Some(socket.as_path()),
+            Some(Agent::Memory(_)) | None => None,
+        }
+    }
+
+    /// Check whether an agent is forwarded as `id`
+    pub fn has_agent(&self, id: &str) -> bool {
+        self.agents.contains_key(id)
+    }
+
+    /// Open a connection to the agent forwarded as `id`
+    pub async fn connect(&self, id: &str) -> Result<Box<dyn AgentStream>> {
+        match self.agents.get(id) {
+            Some(Agent::Socket { socket, .. }) => Ok(Box::new(connect_agent(socket).await?)),
+            Some(Agent::Memory(agent)) => Ok(Box::new(agent.connect())),
+            None => Err(Error::ssh_agent(format!(
+                "no SSH agent is forwarded as {:?}",
+                id
+            ))),
+        }
+    }
+
+    /// Check whether no agent was added
+    pub fn is_empty(&self) -> bool {
+        self.agents.is_empty()
+    }
+}
+
+/// Split a `docker build --ssh` spec into the agent ID and its paths
+pub(crate) fn parse_spec(spec: &str) -> Result<(&str, Vec<&Path>)> {
+    let (id, paths) = match spec.split_once('=') {
+        Some((id, paths)) => (id, paths.split(',').map(Path::new).collect()),
+        None => (spec, Vec::new()),
+    };
+    if id.is_empty() || id.contains(',') || paths.iter().any(|path| path.as_os_str().is_empty()) {
+        return Err(Error::InvalidConfig(format!(
+            "invalid SSH agent {:?}, expected id[=socket|key[,key...]]",
+            spec
+        )));
+    }
+    Ok((id, paths))
+}
+
+/// Check whether `path` is a unix socket rather than a key file
+#[cfg(unix)]
+fn is_socket(path: &Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket())
+}
+
+#[cfg(not(unix))]
+fn is_socket(_path: &Path) -> bool {
+    false
+}
+
+/// Connect to the agent listening on `socket`
+#[cfg(unix)]
+async fn connect_agent(socket: &Path) -> Result<tokio::net::UnixStream> {
+    tokio::net::UnixStream::connect(socket)
+        .await
+        .map_err(|e| Error::ssh_agent(format!("failed to connect to {}: {}", socket.display(), e)))
+}
+
+#[cfg(not(unix))]
+async fn connect_agent(socket: &Path) -> Result<tokio::io::DuplexStream> {
+    Err(Error::ssh_agent(format!(
+        "cannot reach {}: only unix sockets are supported",
+        socket.display()
+    )))
+}
+
+/// An `ssh-agent` started for one build
+///
+/// The agent is killed and its socket directory removed on drop.
+#[derive(Debug)]
+struct AgentProcess {
+    dir: PathBuf,
+    socket: PathBuf,
+    child: Child,
+}
+
+impl AgentProcess {
+    async fn start() -> Result<Self> {
+        let dir = agent_dir()?;
+        let socket = dir.join("agent.sock");
+
+        let child = Command::new("ssh-agent")
+            .arg("-D")
+            .arg("-a")
+            .arg(&socket)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn();
+        let mut process = match child {
+            Ok(child) => Self { dir, socket, child },
+            Err(e) => {
+                let _ = std::fs::remove_dir_all(&dir);
+                return Err(Error::ssh_agent(format!("failed to run ssh-agent: {}", e)));
+            }
+        };
+
+        let deadline = tokio::time::Instant::now() + AGENT_TIMEOUT;
+        while !process.socket.exists() {
+            if let Ok(Some(status)) = process.child.try_wait() {
+                return Err(Error::ssh_agent(format!(
+                    "ssh-agent exited with {}",
+                    status
+                )));
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(Error::ssh_agent(format!(
+                    "ssh-agent not ready after {:?}",
+                    AGENT_TIMEOUT
+                )));
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        Ok(process)
+    }
+
+    async fn add_key(&self, key_path: &Path) -> Result<()> {
+        let output = Command::new("ssh-add")
+            .arg(key_path)
+            .env("SSH_AUTH_SOCK", &self.socket)
+            // Never prompt for a passphrase
+            .env_remove("DISPLAY")
+            .env_remove("SSH_ASKPASS")
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .map_err(|e| Error::ssh_agent(format!("failed to run ssh-add: {}", e)))?;
+        if !output.status.success() {
+            return Err(Error::ssh_agent(format!(
+                "ssh-add {} failed: {}",
+                key_path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AgentProcess {
+    fn drop(&mut self) {
+        let _ = self.child.start_kill();
+        if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+            tracing::warn!("Failed to remove {}: {}", self.dir.display(), e);
+        }
+    }
+}
+
+/// Keys served from memory, by the embedded agent
+///
+/// Every connection is an in-memory pipe to a task of its own, so the keys
+/// are never reachable through the filesystem.
+#[derive(Clone)]
+struct MemoryAgent {
+    identities: Arc<[Identity]>,
+}
+
+impl MemoryAgent {
+    /// Open a connection to the agent
+    fn connect(&self) -> tokio::io::DuplexStream {
+        let (client, agent) = tokio::io::duplex(MEMORY_AGENT_BUFFER);
+        let identities = self.identities.clone();
+        let span = tracing::debug_span!("ssh_agent_connection");
+        spawn_named("buildkit.ssh_agent.connection", span, async move {
+            if let Err(e) = sshagent::serve(agent, &identities).await {
+                tracing::warn!("SSH agent connection failed: {}", e);
+            }
+        });
+        client
+    }
+}
+
+impl std::fmt::Debug for MemoryAgent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MemoryAgent")
+            .field("keys", &self.identities.len())
+            .finish()
+    }
+}
+
+/// Create a private directory for an agent socket
+fn agent_dir() -> Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("buildkit-ssh-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(dir)
+}
@@ -0,0 +1,88 @@
+//! Upload service for streamed build contexts
+//!
+//! BuildKit's HTTP source treats the host `buildkit-session` specially:
+//! instead of downloading `http://buildkit-session/<id>`, it pulls the body
+//! through the session's `Upload` service. A context tarball generated on
+//! the fly (or read from stdin) can be streamed that way without a local
+//! directory; the frontend unpacks it like any remote tarball context.
+
+use crate::error::{Error, Result};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::io::AsyncRead;
+use tokio::sync::Mutex;
+
+/// Host BuildKit pulls through the session rather than over the network
+pub const UPLOAD_HOST: &str = "buildkit-session";
+
+/// Metadata header carrying the URL path of a `Pull` request
+pub const UPLOAD_PATH_HEADER: &str = "urlpath";
+
+/// A stream served once to BuildKit through the `Upload` service
+pub struct Upload {
+    reader: Mutex<Option<Box<dyn AsyncRead + Send + Unpin>>>,
+}
+
+impl Upload {
+    /// Serve the content read from `reader` (e.g., stdin or a tar builder)
+    pub fn new(reader: impl AsyncRead + Send + Unpin + 'static) -> Self {
+        Self {
+            reader: Mutex::new(Some(Box::new(reader))),
+        }
+    }
+
+    /// Take the stream; an upload can only be pulled once
+    pub(crate) async fn take(&self) -> Option<Box<dyn AsyncRead + Send + Unpin>> {
+        self.reader.lock().await.take()
+    }
+}
+
+impl std::fmt::Debug for Upload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Upload(<stream>)")
+    }
+}
+
+/// URL BuildKit pulls the upload registered as `id` from
+pub fn upload_url(id: &str) -> String {
+    format!("http://{}/{}", UPLOAD_HOST, id)
+}
+
+/// Streams BuildKit can pull, keyed by ID
+#[derive(Debug, Clone, Default)]
+pub struct UploadServer {
+    uploads: HashMap<String, Arc<Upload>>,
+}
+
+impl UploadServer {
+    /// Create a server without uploads
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve `upload` as `id`, returning the URL to use as the context
+    pub fn add(&mut self, id: impl Into<String>, upload: Arc<Upload>) -> String {
+        let id = id.into();
+        let url = upload_url(&id);
+        self.uploads.insert(id, upload);
+        url
+    }
+
+    /// Take the stream requested for the URL path `path`
+    pub(crate) async fn take(&self, path: &str) -> Result<Box<dyn AsyncRead + Send + Unpin>> {
+        let id = path.trim_start_matches('/');
+        let upload = self
+            .uploads
+            .get(id)
+            .ok_or_else(|| Error::session(format!("no upload {:?}", id)))?;
+        upload
+            .take()
+            .await
+            .ok_or_else(|| Error::session(format!("upload {:?} was already pulled", id)))
+    }
+
+    /// Check whether no upload was added
+    pub fn is_empty(&self) -> bool {
+        self.uploads.is_empty()
+    }
+}
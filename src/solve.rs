@@ -1,24 +1,86 @@
 //! BuildKit solve operation implementation
 
-use crate::builder::{BuildConfig, DockerfileSource};
-use crate::client::BuildKitClient;
+use crate::builder::{
+    proxy_build_args, BuildConfig, DockerfileSource, Entitlement, NamedContext, NetworkMode,
+    RegistryConfig,
+};
+use crate::cache::{
+    local_store_id, CacheExport, CacheImport, CACHE_MANIFEST_RESPONSE_KEY, LOCAL_CACHE_TAG,
+};
+use crate::layout::{context_store_id, OciLayout};
+use crate::git::{GitAuth, GitCheckout};
+use crate::client::{BuildKitClient, InFlightRef, SharedOutcome};
 use crate::error::{Error, Result};
-use crate::progress::ProgressHandler;
-use crate::session::{Session, FileSync};
+use crate::frontend::FrontendAttrs;
+use crate::debug::solve_with_debugger;
+use crate::gateway::{solve_with_gateway, Forward, REQUEST_ID_ATTR};
+use crate::http_context::{http_context_definition, validate_url, HTTP_AUTH_HEADER_SECRET};
+use crate::attest::{fetch_attestations, AttestationManifestRef};
+use crate::oci::Descriptor;
+use crate::output::Output;
+use crate::invalidation::{CacheInvalidationReport, VertexRecorder};
+use crate::progress::{
+    ProgressBufferConfig, ProgressHandler, ProgressMetrics, StatusBuffer, VertexSummary,
+};
+use crate::task::{spawn_named, AbortOnDrop};
+use crate::registry::{mirror_reference, split_image_name, split_tag, verify_manifest};
+use crate::session::{Session, FileSync, FileSendServer, SendTarget, ContentStore, SyncStats};
+use crate::session::{AuthServer, RegistryAuthConfig};
+use crate::session::sshforward::{SshForwardServer, DEFAULT_SSH_ID};
+use crate::session::UploadServer;
+use crate::session::IgnorePatterns;
 use crate::proto::moby::buildkit::v1::{
-    Exporter, SolveRequest, StatusRequest, CacheOptions, CacheOptionsEntry,
+    Exporter, SolveRequest, StatusRequest, CacheOptions,
 };
+use base64::Engine;
 use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::watch;
 use tokio_stream::StreamExt;
-use uuid::Uuid;
+
+/// How long the status stream may take to end once the solve returned
+const STATUS_DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
 
 /// Build result containing the image digest and metadata
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BuildResult {
+    /// Build reference used for the solve (as recorded in BuildKit's history)
+    pub build_ref: String,
     /// Container image digest
     pub digest: Option<String>,
     /// Export metadata
     pub metadata: HashMap<String, String>,
+    /// Descriptor of the exported image or index (`containerimage.descriptor`)
+    pub descriptor: Option<Descriptor>,
+    /// Status buffering counters (all zero when no progress handler was given)
+    pub progress_metrics: ProgressMetrics,
+    /// Files sent from local directories, with duplicates if they were looked for
+    pub sync_stats: SyncStats,
+    /// Final state of every build step (empty when no progress handler was given)
+    pub vertexes: Vec<VertexSummary>,
+    /// Main context files BuildKit requested because they were new or changed
+    pub changed_files: Vec<String>,
+}
+
+impl BuildResult {
+    /// Report which changed context files caused steps to be rebuilt
+    ///
+    /// Steps are only known when the build ran with a progress handler.
+    pub fn cache_invalidation_report(&self) -> CacheInvalidationReport {
+        CacheInvalidationReport::analyze(&self.vertexes, &self.changed_files)
+    }
+
+    /// List the attestation manifests attached to the exported image
+    ///
+    /// `store` must hold the exported index, e.g. a
+    /// [`RegistryContent`](crate::registry::RegistryContent) for a pushed
+    /// image. Single-platform images without attestations yield nothing.
+    pub async fn attestations(&self, store: &dyn ContentStore) -> Result<Vec<AttestationManifestRef>> {
+        match &self.descriptor {
+            Some(descriptor) => fetch_attestations(store, descriptor).await,
+            None => Ok(Vec::new()),
+        }
+    }
 }
 
 impl BuildKitClient {
@@ -33,171 +95,483 @@ impl BuildKitClient {
     pub async fn build(
         &mut self,
         config: BuildConfig,
+        progress_handler: Option<Box<dyn ProgressHandler>>,
+    ) -> Result<BuildResult> {
+        #[cfg(feature = "journal")]
+        if let Some(journal) = self.journal() {
+            use crate::journal::{config_hash, JournalEntry};
+
+            let mut config = config;
+            // Pin the ref so failed builds are journaled under it as well
+            if config.ref_id.is_none() {
+                config.ref_id = Some(config.resolve_ref()?);
+            }
+            let build_ref = config.ref_id.clone().unwrap_or_default();
+            let hash = config_hash(&config);
+            let started_at = std::time::SystemTime::now();
+
+            let result = self.run_build(config, progress_handler, None).await;
+            let entry = JournalEntry::new(build_ref.clone(), hash, started_at, &result);
+            // SQLite writes block, so they stay off the runtime's workers
+            let recorded = tokio::task::spawn_blocking(move || journal.record(&entry))
+                .await
+                .map_err(|e| Error::other(format!("journal task failed: {}", e)))
+                .and_then(|recorded| recorded);
+            if let Err(e) = recorded {
+                tracing::warn!("Failed to journal build {}: {}", build_ref, e);
+            }
+            return result;
+        }
+
+        self.run_build(config, progress_handler, None).await
+    }
+
+    /// Run a frontend subrequest (e.g., `frontend.lint`) instead of a build
+    ///
+    /// The session is set up as for [`build`](Self::build), but nothing is
+    /// exported; returns the metadata of the frontend's answer.
+    pub(crate) async fn frontend_subrequest(
+        &mut self,
+        config: BuildConfig,
+        request_id: &str,
+    ) -> Result<HashMap<String, String>> {
+        let result = self
+            .run_build(config, None, Some(Forward::Subrequest(request_id)))
+            .await?;
+        Ok(result.metadata)
+    }
+
+    pub(crate) async fn run_build(
+        &mut self,
+        config: BuildConfig,
+        progress_handler: Option<Box<dyn ProgressHandler>>,
+        forward: Option<Forward<'_>>,
+    ) -> Result<BuildResult> {
+        config.validate()?;
+
+        // Resolve the build reference and reserve it while the build runs
+        let build_ref = config.resolve_ref()?;
+        let in_flight = loop {
+            match self.reserve_ref(&build_ref) {
+                Err(Error::DuplicateBuildRef(_)) if config.deduplicate && forward.is_none() => {
+                    if let Some(outcome) = self.join_ref(&build_ref) {
+                        return self
+                            .join_build(
+                                &build_ref,
+                                outcome,
+                                progress_handler,
+                                config.progress_buffer,
+                            )
+                            .await;
+                    }
+                    // The running build finished in between; run it again
+                }
+                reserved => break reserved?,
+            }
+        };
+
+        let result = self
+            .solve_build(config, progress_handler, forward, build_ref, &in_flight)
+            .await;
+        in_flight.publish(&result);
+        result
+    }
+
+    /// Wait for the running build `build_ref` and share its result
+    ///
+    /// With a progress handler, the build's status stream is followed as
+    /// well; the progress fields of the result are then this request's own.
+    async fn join_build(
+        &mut self,
+        build_ref: &str,
+        mut outcome: watch::Receiver<Option<SharedOutcome>>,
+        mut progress_handler: Option<Box<dyn ProgressHandler>>,
+        buffer_config: ProgressBufferConfig,
+    ) -> Result<BuildResult> {
+        tracing::info!("Joining running build {}", build_ref);
+        let (progress, outcome) = tokio::join!(
+            async {
+                let handler = progress_handler.as_mut()?;
+                Some(self.monitor_progress(build_ref, handler, buffer_config).await)
+            },
+            async {
+                // Fails if the build went away without an outcome (cancelled)
+                outcome
+                    .wait_for(Option::is_some)
+                    .await
+                    .ok()
+                    .and_then(|outcome| outcome.clone())
+            }
+        );
+        let mut result = match outcome {
+            Some(Ok(result)) => result,
+            Some(Err(message)) => {
+                return Err(Error::JoinedBuildFailed {
+                    build_ref: build_ref.to_string(),
+                    message,
+                })
+            }
+            None => return Err(Error::BuildCancelled(build_ref.to_string())),
+        };
+        if let Some(progress) = progress {
+            (result.progress_metrics, result.vertexes) = progress?;
+        }
+        Ok(result)
+    }
+
+    async fn solve_build(
+        &mut self,
+        mut config: BuildConfig,
         mut progress_handler: Option<Box<dyn ProgressHandler>>,
+        forward: Option<Forward<'_>>,
+        build_ref: String,
+        in_flight: &InFlightRef,
     ) -> Result<BuildResult> {
-        // Generate unique build reference
-        let build_ref = format!("build-{}", Uuid::new_v4());
         tracing::info!("Starting build with ref: {}", build_ref);
 
-        // Create and start session
-        let mut session = Session::new();
+        if let Some(dockerfile) = read_dockerfile(&config).await {
+            // Labs syntax must be opted into; a missing Dockerfile is left
+            // for the frontend to report
+            if !config.frontend.is_labs() {
+                if let Ok(dockerfile) = &dockerfile {
+                    config.frontend.check_dockerfile(dockerfile)?;
+                }
+            }
 
-        // Add file sync for local builds
-        if let DockerfileSource::Local { context_path, .. } = &config.source {
-            let abs_path = std::fs::canonicalize(context_path)
-                .map_err(|e| Error::PathResolution {
+            // Fail fast on dead remote sources before the daemon gets to them
+            if config.verify_remote_sources {
+                let dockerfile = dockerfile?;
+                let sources = crate::remote_sources::find_remote_sources(&dockerfile);
+                tracing::info!("Verifying {} remote source(s)", sources.len());
+                crate::remote_sources::verify_remote_sources(&sources).await?;
+            }
+        }
+
+        // Pin the git ref to a commit so the build is reproducible
+        if config.resolve_git_ref {
+            if let DockerfileSource::GitHub { repo_url, git_ref, token, .. } = &mut config.source {
+                let sha =
+                    crate::github::resolve_ref(repo_url, git_ref.as_deref(), token.as_deref())
+                        .await?;
+                *git_ref = Some(sha);
+            }
+        }
+
+        // Pin OCI layout contexts to the manifest their tag points at
+        for (name, context) in config.named_contexts.iter_mut() {
+            if let NamedContext::OciLayout { path, tag, digest: digest @ None } = context {
+                if !path.is_dir() {
+                    return Err(Error::NotADirectory(path.clone()));
+                }
+                let layout = OciLayout::open(path.as_path())?;
+                let manifest = layout.resolve_tag(tag)?.ok_or_else(|| {
+                    Error::InvalidConfig(format!(
+                        "named context {:?}: tag {:?} not found in {}",
+                        name,
+                        tag,
+                        path.display()
+                    ))
+                })?;
+                *digest = Some(manifest.digest);
+            }
+        }
+
+        // Explicit build arguments win over the environment
+        if config.forward_proxy_env {
+            for (name, value) in proxy_build_args(std::env::vars()) {
+                config.build_args.entry(name).or_insert(value);
+            }
+        }
+
+        // Validate frontend attributes before opening a session
+        let mut frontend_attrs = FrontendAttrs::from_config(&config)?;
+        if let Some(Forward::Subrequest(request_id)) = &forward {
+            frontend_attrs.set(REQUEST_ID_ATTR, *request_id)?;
+        }
+
+        let context_dir = match &config.source {
+            DockerfileSource::Local { context_path, .. } => Some(
+                std::fs::canonicalize(context_path).map_err(|e| Error::PathResolution {
                     path: context_path.clone(),
                     source: e,
-                })?;
+                })?,
+            ),
+            _ => None,
+        };
+
+        // Create and start session
+        let mut session = Session::new()
+            .with_shared_key(config.shared_key.resolve(context_dir.as_deref()))
+            .with_duplicate_files(config.duplicate_files)
+            .with_file_sync_options(config.file_sync.clone())
+            .with_context_patterns(
+                IgnorePatterns::new(&config.context_include),
+                IgnorePatterns::new(&config.context_exclude),
+            )
+            .with_frame_limits(config.frame_limits)
+            .with_access_log(config.access_log);
+
+        // Add file sync for local builds
+        if let Some(abs_path) = context_dir {
+            if let Some(user) = config.context_user.clone() {
+                let root = abs_path.clone();
+                tokio::task::spawn_blocking(move || user.check_tree(&root))
+                    .await
+                    .map_err(|e| Error::other(format!("Permission check failed: {}", e)))??;
+            }
             session.add_file_sync(abs_path).await;
         }
-
-        // Add auth for registry authentication
-        if let Some(ref registry_auth) = config.registry_auth {
-            let mut auth = crate::session::AuthServer::new();
-            auth.add_registry(crate::session::RegistryAuthConfig {
-                host: registry_auth.host.clone(),
-                username: registry_auth.username.clone(),
-                password: registry_auth.password.clone(),
-            });
-            session.add_auth(auth).await;
+        if let DockerfileSource::Context { context, .. } = &config.source {
+            session.add_context_provider(context.provider()).await;
+        }
+        let upload_url = match &config.source {
+            DockerfileSource::Upload { upload, .. } => {
+                let mut uploads = UploadServer::new();
+                let url = uploads.add(uuid::Uuid::new_v4().to_string(), Arc::clone(upload));
+                session.add_uploads(uploads).await;
+                Some(url)
+            }
+            _ => None,
+        };
+        if let Some(content) = &config.inline_dockerfile {
+            session.add_inline_dockerfile(content.clone()).await;
         }
 
-        // Add secrets if provided
-        if !config.secrets.is_empty() {
-            let secrets = crate::session::SecretsServer::from_map(config.secrets.clone())
-                .map_err(|e| Error::secrets(format!("Failed to create secrets server: {}", e)))?;
-            session.add_secrets(secrets).await;
-            tracing::debug!("Added {} secrets to session", config.secrets.len());
+        // Serve named local contexts next to the main one
+        for (name, context) in &config.named_contexts {
+            let NamedContext::Local(path) = context else {
+                continue;
+            };
+            let abs_path = std::fs::canonicalize(path).map_err(|e| Error::PathResolution {
+                path: path.clone(),
+                source: e,
+            })?;
+            if !abs_path.is_dir() {
+                return Err(Error::NotADirectory(path.clone()));
+            }
+            session.add_local_dir(name.as_str(), abs_path).await;
         }
 
-        // Start the session by connecting to BuildKit
-        session.start(self.control().clone()).await?;
+        // Git contexts behind a proxy are fetched here and uploaded instead
+        let checkout = match &config.source {
+            DockerfileSource::GitHub {
+                repo_url,
+                git_ref,
+                token,
+                proxy: Some(proxy),
+                ..
+            } => Some(
+                GitCheckout::fetch(repo_url, git_ref.as_deref(), token.as_deref(), proxy).await?,
+            ),
+            _ => None,
+        };
+        if let Some(checkout) = &checkout {
+            session.add_file_sync(checkout.path().to_path_buf()).await;
+        }
 
-        tracing::info!("Session started: {}", session.get_id());
+        // Resolve outputs and register client-side export destinations
+        let outputs = config.resolved_outputs();
+        let file_send = file_send_for(&outputs);
+        if !file_send.is_empty() {
+            session.add_file_send(file_send).await;
+        }
 
-        // Prepare frontend attributes
-        let mut frontend_attrs = HashMap::new();
+        // Credentials for the push target and for cache registries
+        let mut auth = AuthServer::new();
+        for registry_auth in config
+            .registry_auth
+            .iter()
+            .chain(&config.cache_registry_auths)
+        {
+            auth.add_registry(RegistryAuthConfig::from(registry_auth));
+        }
 
-        // Set dockerfile filename
-        match &config.source {
-            DockerfileSource::Local { dockerfile_path, .. } => {
-                if let Some(path) = dockerfile_path {
-                    frontend_attrs.insert(
-                        "filename".to_string(),
-                        path.to_string_lossy().to_string(),
-                    );
+        // Prepare cache imports
+        let mut content_stores = config.content_stores.clone();
+        let mut cache_imports = Vec::new();
+        let mut imports = config
+            .cache_from
+            .iter()
+            .map(|spec| CacheImport::parse(spec))
+            .collect::<Result<Vec<_>>>()?;
+        imports.extend(config.cache_imports.iter().cloned());
+        let mut exports = config
+            .cache_to
+            .iter()
+            .map(|spec| CacheExport::parse(spec))
+            .collect::<Result<Vec<_>>>()?;
+        exports.extend(config.cache_exports.iter().cloned());
+        warn_uncovered_cache_registries(
+            &auth,
+            imports
+                .iter()
+                .filter_map(CacheImport::registry_ref)
+                .chain(exports.iter().filter_map(CacheExport::registry_ref)),
+        );
+        for import in imports {
+            let mut entry = import.to_entry();
+            if let Some(reference) = import.registry_ref() {
+                // Mirrors come first; BuildKit skips caches it cannot pull
+                let mirrors = config
+                    .registry_config_for(reference)
+                    .map_or(&[][..], |registry| &registry.mirrors[..]);
+                for mirror in mirrors {
+                    let mirrored = mirror_reference(reference, mirror);
+                    let mut mirror_entry = entry.clone();
+                    if is_insecure_registry(config, &mirrored) {
+                        mark_insecure(&mut mirror_entry.attrs);
+                    }
+                    mirror_entry.attrs.insert("ref".to_string(), mirrored);
+                    cache_imports.push(mirror_entry);
                 }
+                if is_insecure_registry(config, reference) {
+                    mark_insecure(&mut entry.attrs);
+                }
+            }
+            if let CacheImport::Local { src } = &import {
+                let layout = OciLayout::open(src)?;
+                let Some(manifest) = layout.resolve_tag(LOCAL_CACHE_TAG)? else {
+                    tracing::warn!("No cache found in {}, skipping import", src.display());
+                    continue;
+                };
+                entry.attrs.insert("digest".to_string(), manifest.digest);
+                content_stores.add_store(local_store_id(src), Arc::new(layout));
             }
-            DockerfileSource::GitHub { dockerfile_path, .. } => {
-                if let Some(path) = dockerfile_path {
-                    frontend_attrs.insert("filename".to_string(), path.clone());
+            if let CacheImport::OciLayout { path, tag } = &import {
+                if !path.is_dir() {
+                    return Err(Error::NotADirectory(path.clone()));
                 }
+                let layout = OciLayout::open(path.as_path())?;
+                let manifest = layout.resolve_tag(tag)?.ok_or_else(|| {
+                    Error::InvalidConfig(format!(
+                        "cache import: tag {:?} not found in {}",
+                        tag,
+                        path.display()
+                    ))
+                })?;
+                entry.attrs.insert("digest".to_string(), manifest.digest);
+                content_stores.add_store(local_store_id(path), Arc::new(layout));
             }
+            cache_imports.push(entry);
         }
 
-        // Add build args
-        for (key, value) in &config.build_args {
-            frontend_attrs.insert(format!("build-arg:{}", key), value.clone());
+        // Prepare cache exports
+        let mut cache_layouts = Vec::new();
+        let mut cache_exports = Vec::new();
+        for export in exports {
+            if let CacheExport::Local { dest } = export.backend() {
+                let layout = OciLayout::open(dest)?;
+                content_stores.add_store(local_store_id(dest), Arc::new(layout.clone()));
+                cache_layouts.push((layout, export.options().ignore_error));
+            }
+            let mut entry = export.to_entry();
+            if export
+                .registry_ref()
+                .is_some_and(|reference| is_insecure_registry(config, reference))
+            {
+                mark_insecure(&mut entry.attrs);
+            }
+            cache_exports.push(entry);
         }
 
-        // Set target stage
-        if let Some(target) = &config.target {
-            frontend_attrs.insert("target".to_string(), target.clone());
+        // OCI layout contexts are read through the session as well
+        for context in config.named_contexts.values() {
+            if let NamedContext::OciLayout { path, .. } = context {
+                let layout = OciLayout::open(path.as_path())?;
+                content_stores.add_store(context_store_id(path), Arc::new(layout));
+            }
         }
 
-        // Set platforms
-        if !config.platforms.is_empty() {
-            let platforms_str = config
-                .platforms
-                .iter()
-                .map(|p| p.to_string())
-                .collect::<Vec<_>>()
-                .join(",");
-            frontend_attrs.insert("platform".to_string(), platforms_str);
-        }
-
-        // Set no-cache
-        if config.no_cache {
-            frontend_attrs.insert("no-cache".to_string(), "true".to_string());
-        }
-
-        // Set pull
-        if config.pull {
-            frontend_attrs.insert("image-resolve-mode".to_string(), "pull".to_string());
-        }
-
-        // Prepare context source
-        let context = self.prepare_context(&config, &session).await?;
-        frontend_attrs.insert("context".to_string(), context);
-
-        // Prepare exports (push to registry)
-        let mut exports = Vec::new();
-        if !config.tags.is_empty() {
-            let mut export_attrs = HashMap::new();
-            export_attrs.insert("name".to_string(), config.tags.join(","));
-            export_attrs.insert("push".to_string(), "true".to_string());
-
-            // Check if registry needs insecure flag based on tag or registry_auth
-            let registry_host = if let Some(auth) = &config.registry_auth {
-                Some(auth.host.as_str())
-            } else {
-                // Extract registry host from the first tag (format: host/image:tag or image:tag)
-                config.tags.first().and_then(|tag| {
-                    let parts: Vec<&str> = tag.split('/').collect();
-                    if parts.len() > 1 && (parts[0].contains(':') || parts[0].contains('.') || parts[0] == "localhost") {
-                        Some(parts[0])
-                    } else {
-                        None
-                    }
-                })
-            };
+        // Serve custom blob stores, local cache directories and OCI layout contexts
+        if !content_stores.is_empty() {
+            session.add_content_stores(content_stores).await;
+        }
 
-            // Determine if registry is insecure (HTTP instead of HTTPS)
-            if let Some(host) = registry_host {
-                let is_insecure = host.starts_with("localhost")
-                    || host.starts_with("127.0.0.1")
-                    || host.starts_with("registry:") // Docker Compose service name
-                    || (!host.contains('.') && !host.starts_with("docker.io")); // Simple heuristic for local names
+        // Add auth for registry authentication; without credentials it still
+        // fetches anonymous tokens, e.g. for public Docker Hub images
+        auth.set_access_log(config.access_log);
+        session.add_auth(auth).await;
 
-                if is_insecure {
-                    export_attrs.insert("registry.insecure".to_string(), "true".to_string());
-                }
+        // SSH git remotes and `RUN --mount=type=ssh` steps authenticate
+        // through forwarded agents
+        let mut ssh = SshForwardServer::new();
+        if let DockerfileSource::Git { url, auth: Some(auth), .. } = &config.source {
+            auth.validate(url)?;
+            match auth {
+                GitAuth::SshAgent => ssh.add_local_agent(DEFAULT_SSH_ID)?,
+                GitAuth::SshKey(path) => ssh.add_key_file(DEFAULT_SSH_ID, path).await?,
+                GitAuth::Token(_) | GitAuth::Basic { .. } => {}
             }
+        }
+        for (id, keys) in &config.ssh_keys {
+            ssh.add_keys(id.clone(), keys)?;
+        }
+        for spec in &config.ssh_agents {
+            ssh.add_spec(spec).await?;
+        }
+        if !ssh.is_empty() {
+            session.add_ssh_forward(ssh).await;
+        }
 
-            exports.push(Exporter {
-                r#type: "image".to_string(),
-                attrs: export_attrs,
-            });
+        // Add secrets if provided
+        let mut secret_values = config.secrets.clone();
+        match &config.source {
+            DockerfileSource::Http { auth_header: Some(header), .. } => {
+                secret_values.insert(HTTP_AUTH_HEADER_SECRET.to_string(), header.clone());
+            }
+            // Checkouts through a proxy send the token themselves
+            DockerfileSource::GitHub { repo_url, token: Some(token), proxy: None, .. } => {
+                secret_values.insert(GitAuth::token_secret_name(repo_url), token.clone());
+            }
+            DockerfileSource::Git { url, auth: Some(auth), .. } => {
+                if let Some(header) = auth.authorization_header() {
+                    secret_values.insert(GitAuth::secret_name(url), header);
+                }
+            }
+            _ => {}
+        }
+        if !secret_values.is_empty() || !config.secret_files.is_empty() {
+            let count = secret_values.len() + config.secret_files.len();
+            let mut secrets = crate::session::SecretsServer::from_map(secret_values)
+                .map_err(|e| Error::secrets(format!("Failed to create secrets server: {}", e)))?;
+            for (id, path) in &config.secret_files {
+                secrets.add_secret_file(id.clone(), path.clone());
+            }
+            secrets.set_access_log(config.access_log);
+            session.add_secrets(secrets).await;
+            tracing::debug!("Added {} secrets to session", count);
         }
 
-        // Prepare cache imports
-        let cache_imports = config
-            .cache_from
-            .iter()
-            .map(|source| {
-                let mut attrs = HashMap::new();
-                attrs.insert("ref".to_string(), source.clone());
-                CacheOptionsEntry {
-                    r#type: "registry".to_string(),
-                    attrs,
+        // Start the session by connecting to BuildKit
+        session.start(self.control().clone()).await?;
+
+        tracing::info!("Session started: {}", session.get_id());
+        in_flight.attach(&session);
+
+        // Add the session-dependent context to the frontend attributes,
+        // or pass it as an input when it needs more than a URL
+        let mut frontend_inputs = HashMap::new();
+        match self.prepare_context(&config, &session, upload_url).await? {
+            // An explicit context attribute wins over the session's
+            Some(_) if config.frontend_attrs.contains_key("context") => {}
+            Some(context) => frontend_attrs.context(context)?,
+            None => {
+                if let DockerfileSource::Http { url, checksum, auth_header, .. } = &config.source {
+                    let definition = http_context_definition(
+                        url,
+                        checksum.as_deref(),
+                        auth_header.as_ref().map(|_| HTTP_AUTH_HEADER_SECRET),
+                    )?;
+                    frontend_inputs.insert("context".to_string(), definition);
                 }
-            })
-            .collect();
+            }
+        }
+        tracing::debug!("Frontend attributes:\n{}", frontend_attrs.redacted());
 
-        // Prepare cache exports
-        let cache_exports = config
-            .cache_to
+        // Prepare exports (image push, local directory, ...)
+        let exports: Vec<Exporter> = outputs
             .iter()
-            .map(|dest| {
-                let mut attrs = HashMap::new();
-                attrs.insert("ref".to_string(), dest.clone());
-                attrs.insert("mode".to_string(), "max".to_string());
-                CacheOptionsEntry {
-                    r#type: "registry".to_string(),
-                    attrs,
-                }
-            })
+            .map(|output| exporter_for(&config, output))
             .collect();
 
         // Debug: Log exporter configuration
@@ -207,14 +581,20 @@ impl BuildKitClient {
         }
 
         // Create solve request with session
-        let request = SolveRequest {
+        // The host network is only available to builds entitled to it
+        let mut entitlements = config.entitlements.clone();
+        if config.network == NetworkMode::Host {
+            entitlements.insert(Entitlement::NetworkHost);
+        }
+
+        let mut request = SolveRequest {
             r#ref: build_ref.clone(),
             definition: None,
             exporter_deprecated: String::new(),
             exporter_attrs_deprecated: HashMap::new(),
             session: session.get_id(),  // Use session ID
-            frontend: "dockerfile.v0".to_string(),
-            frontend_attrs,
+            frontend: config.frontend.name().to_string(),
+            frontend_attrs: frontend_attrs.into_map(),
             cache: Some(CacheOptions {
                 export_ref_deprecated: String::new(),
                 import_refs_deprecated: vec![],
@@ -222,8 +602,11 @@ impl BuildKitClient {
                 exports: cache_exports,
                 imports: cache_imports,
             }),
-            entitlements: vec![],
-            frontend_inputs: HashMap::new(),
+            entitlements: entitlements
+                .iter()
+                .map(|entitlement| entitlement.to_string())
+                .collect(),
+            frontend_inputs,
             internal: false,
             source_policy: None,
             exporters: exports,
@@ -231,42 +614,103 @@ impl BuildKitClient {
             // source_policy_session: String::new(),
         };
 
+        // Apply caller overrides last so they see the complete request
+        for mutation in &config.solve_mutations {
+            mutation.apply(&mut request);
+        }
+
         // Start the build
         tracing::info!("Sending solve request to buildkit");
 
         // Create request with session metadata headers
         let mut grpc_request = tonic::Request::new(request);
-        let metadata = grpc_request.metadata_mut();
-
-        // Add session metadata headers
-        for (key, values) in session.metadata() {
-            if let Ok(k) = key.parse::<tonic::metadata::MetadataKey<tonic::metadata::Ascii>>() {
-                // Add each value for the key (supports multi-value headers)
-                for value in values {
-                    if let Ok(v) = value.parse::<tonic::metadata::MetadataValue<tonic::metadata::Ascii>>() {
-                        metadata.append(k.clone(), v);
-                    }
+        *grpc_request.metadata_mut() = session.grpc_metadata();
+
+        // Dropping the solve on shutdown cancels it on the daemon side
+        let client = self.clone();
+        let monitor = self.clone();
+        let channel = self.channel();
+        let solved = tokio::select! {
+            solved = async {
+                let solve = async {
+                    // Only the gateway API returns a frontend's answer
+                    let exporter_response = match forward {
+                        Some(Forward::Subrequest(_)) => {
+                            solve_with_gateway(self.control(), channel, grpc_request)
+                                .await?
+                                .into_iter()
+                                .map(|(key, value)| (key, String::from_utf8_lossy(&value).into_owned()))
+                                .collect()
+                        }
+                        Some(Forward::Debug(debugger)) => {
+                            solve_with_debugger(self.control(), channel, grpc_request, debugger).await?
+                        }
+                        None => self.control().solve(grpc_request).await?.into_inner().exporter_response,
+                    };
+                    Ok::<_, Error>(exporter_response)
+                };
+
+                // Monitor build progress while the solve runs if a handler is provided
+                monitor
+                    .follow_solve(&build_ref, progress_handler.as_mut(), config.progress_buffer, solve)
+                    .await
+            } => solved,
+            _ = client.shutdown_requested() => {
+                tracing::warn!("Cancelling build {} for client shutdown", build_ref);
+                // Let the session flush what it has in flight; the grace period
+                // of the shutdown bounds how long this may take
+                if let Err(e) = session.finish().await {
+                    tracing::debug!("Session of cancelled build {} failed: {}", build_ref, e);
                 }
+                return Err(Error::BuildCancelled(build_ref.clone()));
             }
-        }
+            error = session.failed() => {
+                tracing::error!("Session of build {} failed: {}", build_ref, error);
+                let _ = session.finish().await;
+                return Err(error);
+            }
+        };
+        let (exporter_response, (progress_metrics, vertexes)) = solved?;
 
-        let response = self
-            .control()
-            .solve(grpc_request)
-            .await?;
+        // Extract digest and metadata
+        let digest = exporter_response
+            .get("containerimage.digest")
+            .cloned();
+
+        let descriptor = Descriptor::from_exporter_response(&exporter_response)
+            .unwrap_or_else(|e| {
+                tracing::warn!("Ignoring exported image descriptor: {}", e);
+                None
+            });
 
-        let solve_response = response.into_inner();
+        // Disconnects are expected here; only genuine protocol faults fail the build
+        session.finish().await?;
+        let sync_stats = session.sync_stats().await;
+        let changed_files = session.changed_files().await;
 
-        // Monitor build progress if handler is provided
-        if let Some(ref mut handler) = progress_handler {
-            self.monitor_progress(&build_ref, handler).await?;
+        // Point local cache directories at the cache just exported
+        if let Some(manifest) = exporter_response.get(CACHE_MANIFEST_RESPONSE_KEY) {
+            let manifest = base64::engine::general_purpose::STANDARD
+                .decode(manifest)
+                .unwrap_or_else(|_| manifest.clone().into_bytes());
+            for (layout, ignore_error) in &cache_layouts {
+                match layout.set_tag(&manifest, LOCAL_CACHE_TAG) {
+                    Ok(()) => {
+                        tracing::info!("Updated cache index in {}", layout.root().display())
+                    }
+                    Err(e) if *ignore_error => tracing::warn!(
+                        "Ignoring cache export failure in {}: {}",
+                        layout.root().display(),
+                        e
+                    ),
+                    Err(e) => return Err(e),
+                }
+            }
         }
 
-        // Extract digest and metadata
-        let digest = solve_response
-            .exporter_response
-            .get("containerimage.digest")
-            .cloned();
+        if config.verify_push {
+            verify_pushed(&config, &outputs, digest.as_deref()).await?;
+        }
 
         tracing::info!("Build completed successfully");
         if let Some(ref d) = digest {
@@ -274,14 +718,27 @@ impl BuildKitClient {
         }
 
         Ok(BuildResult {
+            build_ref,
             digest,
-            metadata: solve_response.exporter_response,
+            metadata: exporter_response,
+            descriptor,
+            progress_metrics,
+            sync_stats,
+            vertexes,
+            changed_files,
         })
     }
 
     /// Prepare build context based on source type
-    async fn prepare_context(&self, config: &BuildConfig, session: &Session) -> Result<String> {
-        match &config.source {
+    ///
+    /// Returns `None` for contexts passed to the frontend as an input.
+    async fn prepare_context(
+        &self,
+        config: &BuildConfig,
+        session: &Session,
+        upload_url: Option<String>,
+    ) -> Result<Option<String>> {
+        let context = match &config.source {
             DockerfileSource::Local { context_path, .. } => {
                 // Validate the context path
                 let file_sync = FileSync::new(context_path);
@@ -289,12 +746,36 @@ impl BuildKitClient {
 
                 // Use session-based input
                 // The format is: input:<name> where name references the session
-                Ok(format!("input:{}:context", session.shared_key))
+                format!("input:{}:context", session.shared_key)
             }
+            // Checked out on the client or provided, and served like a local context
+            DockerfileSource::GitHub { proxy: Some(_), .. } | DockerfileSource::Context { .. } => {
+                format!("input:{}:context", session.shared_key)
+            }
+            // Credentials reach BuildKit's git source as a session secret
+            DockerfileSource::Git { url, git_ref, .. } => match git_ref {
+                Some(git_ref) => format!("{}#{}", url, git_ref),
+                None => url.clone(),
+            },
+            // The frontend downloads bare URLs itself
+            DockerfileSource::Http {
+                url,
+                checksum: None,
+                auth_header: None,
+                ..
+            } => {
+                validate_url(url)?;
+                url.clone()
+            }
+            DockerfileSource::Http { .. } => return Ok(None),
+            // BuildKit pulls the tarball through the session's Upload service
+            DockerfileSource::Upload { .. } => upload_url
+                .ok_or_else(|| Error::session("upload context was not registered"))?,
+            // The token reaches BuildKit's git source as a session secret,
+            // keeping it out of vertex names, logs and cache keys
             DockerfileSource::GitHub {
                 repo_url,
                 git_ref,
-                token,
                 ..
             } => {
                 let mut url = repo_url.clone();
@@ -304,28 +785,72 @@ impl BuildKitClient {
                     url.push_str(".git");
                 }
 
-                // Add authentication token if provided
-                if let Some(token) = token {
-                    // Format: https://token@github.com/user/repo.git
-                    url = url.replace("https://", &format!("https://{}@", token));
-                }
-
                 // Add git reference
                 if let Some(git_ref) = git_ref {
                     url = format!("{}#{}", url, git_ref);
                 }
 
-                Ok(url)
+                url
             }
-        }
+        };
+        Ok(Some(context))
+    }
+
+    /// Run `solve` while `handler` follows the build's status stream
+    ///
+    /// Status updates reach the handler as the build runs. Once the solve has
+    /// returned, the stream gets [`STATUS_DRAIN_TIMEOUT`] to deliver its last
+    /// updates and end. An error of the solve takes precedence over one of
+    /// the progress handler.
+    pub(crate) async fn follow_solve<T>(
+        mut self,
+        build_ref: &str,
+        handler: Option<&mut Box<dyn ProgressHandler>>,
+        buffer_config: ProgressBufferConfig,
+        solve: impl std::future::Future<Output = Result<T>>,
+    ) -> Result<(T, (ProgressMetrics, Vec<VertexSummary>))> {
+        // Dropped once the solve returns
+        let (solved_tx, solved_rx) = tokio::sync::oneshot::channel::<()>();
+        let solve = async move {
+            let solved = solve.await;
+            drop(solved_tx);
+            solved
+        };
+        let progress = async {
+            let Some(handler) = handler else {
+                return Ok((ProgressMetrics::default(), Vec::new()));
+            };
+            tokio::select! {
+                progress = self.monitor_progress(build_ref, handler, buffer_config) => progress,
+                _ = async {
+                    let _ = solved_rx.await;
+                    tokio::time::sleep(STATUS_DRAIN_TIMEOUT).await;
+                } => {
+                    tracing::warn!(
+                        "Status stream of {} still open {:?} after the solve",
+                        build_ref,
+                        STATUS_DRAIN_TIMEOUT
+                    );
+                    Ok((ProgressMetrics::default(), Vec::new()))
+                }
+            }
+        };
+        let (solved, progress) = tokio::join!(solve, progress);
+        Ok((solved?, progress?))
     }
 
     /// Monitor build progress and send updates to the handler
-    async fn monitor_progress(
+    ///
+    /// Status messages are read in a background task into a bounded
+    /// [`StatusBuffer`] so a slow handler applies the configured overflow
+    /// policy instead of stalling or growing without bound. Step states are
+    /// recorded before buffering, so none are lost to the overflow policy.
+    pub(crate) async fn monitor_progress(
         &mut self,
         build_ref: &str,
         handler: &mut Box<dyn ProgressHandler>,
-    ) -> Result<()> {
+        buffer_config: ProgressBufferConfig,
+    ) -> Result<(ProgressMetrics, Vec<VertexSummary>)> {
         let status_request = StatusRequest {
             r#ref: build_ref.to_string(),
         };
@@ -336,22 +861,203 @@ impl BuildKitClient {
             .await?
             .into_inner();
 
+        let buffer = Arc::new(StatusBuffer::new(buffer_config));
+        let producer = Arc::clone(&buffer);
+        let recorder = Arc::new(std::sync::Mutex::new(VertexRecorder::default()));
+        let steps = Arc::clone(&recorder);
+        let span = tracing::info_span!("progress_stream", build_ref = %build_ref);
+        // A handler error or a dropped monitor must not leave the producer
+        // blocked on a full buffer, holding the status stream open
+        let _producer = AbortOnDrop(spawn_named("buildkit.progress", span, async move {
+            while let Some(response) = stream.next().await {
+                match response {
+                    Ok(status) => {
+                        steps
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .record(&status.vertexes);
+                        producer.push(status).await
+                    }
+                    Err(e) => {
+                        producer.fail(e.to_string());
+                        return;
+                    }
+                }
+            }
+            producer.close();
+        }));
+
         handler.on_start()?;
 
-        while let Some(response) = stream.next().await {
-            match response {
+        while let Some(item) = buffer.pop().await {
+            match item {
                 Ok(status) => {
-                    handler.on_status(status)?;
+                    handler.on_status(status.into())?;
                 }
                 Err(e) => {
                     tracing::error!("Status stream error: {}", e);
-                    handler.on_error(&e.to_string())?;
+                    handler.on_error(&e)?;
                     break;
                 }
             }
         }
 
+        let metrics = buffer.metrics();
+        if metrics.dropped > 0 || metrics.coalesced > 0 {
+            tracing::warn!(
+                "Progress handler fell behind: {} dropped, {} coalesced of {} status messages",
+                metrics.dropped, metrics.coalesced, metrics.received
+            );
+        }
+
         handler.on_complete()?;
-        Ok(())
+        let vertexes = std::mem::take(&mut *recorder.lock().unwrap_or_else(|e| e.into_inner()));
+        Ok((metrics, vertexes.into_vertexes()))
+    }
+}
+
+/// Register the client-side destinations of `outputs`
+///
+/// BuildKit identifies each export stream by the exporter's index.
+pub(crate) fn file_send_for(outputs: &[Output]) -> FileSendServer {
+    let mut file_send = FileSendServer::new();
+    for (index, output) in outputs.iter().enumerate() {
+        let target = match output {
+            Output::Local { dest } => Some(SendTarget::Directory(dest.clone())),
+            _ => output.archive_dest().cloned().map(SendTarget::Archive),
+        };
+        if let Some(target) = target {
+            file_send.add_target(index as u32, target);
+        }
+    }
+    file_send
+}
+
+/// Convert a single output into a BuildKit exporter
+fn exporter_for(config: &BuildConfig, output: &Output) -> Exporter {
+    let mut attrs = output.attrs();
+    if matches!(output, Output::Image(_) | Output::Oci(_) | Output::Docker(_)) {
+        for annotation in &config.annotations {
+            attrs.insert(annotation.attr_key(), annotation.value.clone());
+        }
+    }
+    // Attestations live in an image index, which single-platform builds only get on request
+    if config.provenance.is_some() && matches!(output, Output::Image(_) | Output::Oci(_)) {
+        attrs
+            .entry("multi-platform".to_string())
+            .or_insert_with(|| "true".to_string());
+    }
+    if let Output::Image(image) = output {
+        let insecure = image
+            .names
+            .iter()
+            .any(|name| is_insecure_registry(config, name));
+        if image.push && insecure {
+            mark_insecure(&mut attrs);
+        }
+    }
+    Exporter {
+        r#type: output.exporter_type().to_string(),
+        attrs,
+    }
+}
+
+/// Read the Dockerfile a build uses, if the client has it
+///
+/// `None` for sources only the daemon fetches (git, HTTP, uploads).
+pub(crate) async fn read_dockerfile(config: &BuildConfig) -> Option<Result<String>> {
+    match (&config.inline_dockerfile, &config.source) {
+        (Some(content), _) => Some(Ok(content.clone())),
+        (None, DockerfileSource::Local { context_path, dockerfile_path }) => {
+            let path = match dockerfile_path {
+                Some(path) if path.is_absolute() => path.clone(),
+                Some(path) => context_path.join(path),
+                None => context_path.join("Dockerfile"),
+            };
+            Some(tokio::fs::read_to_string(&path).await.map_err(Error::from))
+        }
+        (None, DockerfileSource::Context { context, dockerfile_path }) => {
+            let name = dockerfile_path.as_deref().unwrap_or("Dockerfile");
+            Some(context.read_file(name).await.and_then(|content| {
+                content
+                    .map(|content| String::from_utf8_lossy(&content).into_owned())
+                    .ok_or_else(|| Error::PathNotFound(std::path::PathBuf::from(name)))
+            }))
+        }
+        (
+            None,
+            DockerfileSource::GitHub { .. }
+            | DockerfileSource::Git { .. }
+            | DockerfileSource::Http { .. }
+            | DockerfileSource::Upload { .. },
+        ) => None,
+    }
+}
+
+/// Check that every pushed name resolves to the built manifest
+async fn verify_pushed(
+    config: &BuildConfig,
+    outputs: &[Output],
+    digest: Option<&str>,
+) -> Result<()> {
+    for output in outputs {
+        let Output::Image(image) = output else {
+            continue;
+        };
+        if !image.push {
+            continue;
+        }
+        let by_digest = image
+            .attrs
+            .get("push-by-digest")
+            .is_some_and(|value| value == "true");
+        for name in &image.names {
+            let expected = digest.ok_or_else(|| Error::PushVerification {
+                name: name.clone(),
+                reason: "the build returned no image digest".to_string(),
+            })?;
+            let reference = match name.split_once('@') {
+                Some((_, pinned)) => pinned,
+                None if by_digest => expected,
+                None => split_tag(name).1,
+            };
+            verify_manifest(config, name, reference, expected).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether the registry of `reference` is configured as insecure
+fn is_insecure_registry(config: &BuildConfig, reference: &str) -> bool {
+    config
+        .registry_config_for(reference)
+        .is_some_and(RegistryConfig::is_insecure)
+}
+
+/// Let BuildKit reach the registry over plain HTTP or unverified TLS
+fn mark_insecure(attrs: &mut HashMap<String, String>) {
+    attrs.insert("registry.insecure".to_string(), "true".to_string());
+}
+
+/// Warn about registry cache refs without credentials, if any are configured
+///
+/// Caches on other registries may well be public (e.g. on ghcr.io), so they
+/// are used anonymously rather than failing the build.
+fn warn_uncovered_cache_registries<'a>(
+    auth: &AuthServer,
+    references: impl Iterator<Item = &'a str>,
+) {
+    if auth.is_empty() {
+        return;
+    }
+    for reference in references {
+        let (host, _) = split_image_name(reference);
+        if !auth.has_credentials(&host) {
+            tracing::warn!(
+                "No registry credentials for cache {} (host {}); accessing it anonymously",
+                reference,
+                host
+            );
+        }
     }
 }
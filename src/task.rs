@@ -0,0 +1,70 @@
+//! Named background tasks
+//!
+//! Every task the client spawns goes through [`spawn_named`] so it shows up
+//! under a descriptive name in tokio-console and carries a tracing span.
+//! Task names are only recorded when built with `RUSTFLAGS="--cfg tokio_unstable"`
+//! (a tokio-console requirement); the spans are always attached.
+
+use std::future::Future;
+use tokio::task::{AbortHandle, JoinHandle, JoinSet};
+use tracing::Instrument;
+
+/// Spawn a task named `name`, instrumented with `span`
+pub(crate) fn spawn_named<F>(name: &str, span: tracing::Span, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let future = future.instrument(span);
+
+    #[cfg(tokio_unstable)]
+    {
+        match tokio::task::Builder::new().name(name).spawn(future) {
+            Ok(handle) => handle,
+            Err(e) => panic!("failed to spawn task {}: {}", name, e),
+        }
+    }
+
+    #[cfg(not(tokio_unstable))]
+    {
+        let _ = name;
+        tokio::spawn(future)
+    }
+}
+
+/// Spawn a task named `name` into `set`, instrumented with `span`
+pub(crate) fn spawn_named_in<F>(
+    set: &mut JoinSet<F::Output>,
+    name: &str,
+    span: tracing::Span,
+    future: F,
+) -> AbortHandle
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let future = future.instrument(span);
+
+    #[cfg(tokio_unstable)]
+    {
+        match set.build_task().name(name).spawn(future) {
+            Ok(handle) => handle,
+            Err(e) => panic!("failed to spawn task {}: {}", name, e),
+        }
+    }
+
+    #[cfg(not(tokio_unstable))]
+    {
+        let _ = name;
+        set.spawn(future)
+    }
+}
+
+/// Aborts a task when dropped, so it cannot outlive the code waiting on it
+pub(crate) struct AbortOnDrop<T>(pub(crate) JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
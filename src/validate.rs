@@ -0,0 +1,390 @@
+//! Pre-flight checks of build configurations
+//!
+//! [`BuildConfig::validate`] catches mistakes that would otherwise surface as
+//! gRPC errors halfway through a build: malformed tags and platforms, secrets
+//! BuildKit refuses, missing context directories and options that cannot be
+//! combined. It reports every problem at once and touches neither the network
+//! nor the daemon; builds run it before anything else.
+
+use crate::builder::{is_platform_component, BuildConfig, DockerfileSource, NamedContext};
+use crate::cache::CacheImport;
+use crate::error::{ConfigProblem, Error, Result};
+use crate::frontend::FrontendAttrs;
+use crate::output::Output;
+use crate::session::secrets::MAX_SECRET_SIZE;
+use crate::session::sshforward::parse_spec as parse_ssh_spec;
+
+impl BuildConfig {
+    /// Check the configuration before building
+    ///
+    /// Fails with [`Error::InvalidBuildConfig`] listing every problem found.
+    pub fn validate(&self) -> Result<()> {
+        let problems = self.problems();
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidBuildConfig(problems))
+        }
+    }
+
+    /// Problems [`validate`](Self::validate) would report
+    pub fn problems(&self) -> Vec<ConfigProblem> {
+        let mut problems = Vec::new();
+        self.check_source(&mut problems);
+        self.check_frontend_attrs(&mut problems);
+        self.check_outputs(&mut problems);
+        self.check_cache_imports(&mut problems);
+        self.check_registries(&mut problems);
+
+        for platform in &self.platforms {
+            let mut components = vec![platform.os.as_str(), platform.arch.as_str()];
+            components.extend(platform.variant.as_deref());
+            if !components.into_iter().all(is_platform_component) {
+                problems.push(ConfigProblem::new(
+                    "platforms",
+                    format!("invalid platform {:?}", platform.to_string()),
+                ));
+            }
+        }
+
+        let mut secrets: Vec<_> = self.secrets.iter().collect();
+        secrets.sort();
+        for (id, value) in secrets {
+            if id.is_empty() {
+                problems.push(ConfigProblem::new("secrets", "empty secret ID"));
+            } else if value.len() > MAX_SECRET_SIZE {
+                problems.push(ConfigProblem::new(
+                    "secrets",
+                    format!(
+                        "secret {:?} is {} bytes; BuildKit accepts at most {}",
+                        id,
+                        value.len(),
+                        MAX_SECRET_SIZE
+                    ),
+                ));
+            }
+        }
+        self.check_secret_files(&mut problems);
+        self.check_ssh_agents(&mut problems);
+
+        if let Err(e) = self.resolve_ref() {
+            problems.push(ConfigProblem::new("ref_id", config_message(e)));
+        }
+        if self.deduplicate && self.ref_id.is_none() && self.ref_key.is_none() {
+            problems.push(ConfigProblem::new(
+                "deduplicate",
+                "generated refs never repeat; set ref_id or ref_key",
+            ));
+        }
+
+        problems
+    }
+
+    fn check_source(&self, problems: &mut Vec<ConfigProblem>) {
+        if let DockerfileSource::Local {
+            context_path,
+            dockerfile_path,
+        } = &self.source
+        {
+            if !context_path.is_dir() {
+                problems.push(ConfigProblem::new(
+                    "source",
+                    format!("context {} is not a directory", context_path.display()),
+                ));
+            } else if self.inline_dockerfile.is_none() {
+                let dockerfile = match dockerfile_path {
+                    Some(path) if path.is_absolute() => path.clone(),
+                    Some(path) => context_path.join(path),
+                    None => context_path.join("Dockerfile"),
+                };
+                if !dockerfile.is_file() {
+                    problems.push(ConfigProblem::new(
+                        "dockerfile_path",
+                        format!("Dockerfile {} does not exist", dockerfile.display()),
+                    ));
+                }
+            }
+        }
+
+        for (name, context) in &self.named_contexts {
+            let problem = match context {
+                NamedContext::Local(path) if !path.is_dir() => {
+                    Some(format!("{} is not a directory", path.display()))
+                }
+                NamedContext::OciLayout { path, .. } if !path.join("index.json").is_file() => {
+                    Some(format!("{} is not an OCI layout", path.display()))
+                }
+                NamedContext::Image(reference) => reference_problem(reference),
+                _ => None,
+            };
+            if let Some(problem) = problem {
+                problems.push(ConfigProblem::new(
+                    "named_contexts",
+                    format!("context {:?}: {}", name, problem),
+                ));
+            }
+        }
+    }
+
+    fn check_frontend_attrs(&self, problems: &mut Vec<ConfigProblem>) {
+        // OCI layout digests are only resolved when the build starts
+        let mut config = self.clone();
+        config
+            .named_contexts
+            .retain(|_, context| !matches!(context, NamedContext::OciLayout { digest: None, .. }));
+        if let Err(e) = FrontendAttrs::from_config(&config) {
+            problems.push(ConfigProblem::new("frontend", config_message(e)));
+        }
+    }
+
+    fn check_outputs(&self, problems: &mut Vec<ConfigProblem>) {
+        for tag in &self.tags {
+            if let Some(problem) = reference_problem(tag) {
+                problems.push(ConfigProblem::new("tags", problem));
+            }
+        }
+
+        for output in &self.outputs {
+            let (names, compression) = match output {
+                Output::Image(image) => (&image.names, &image.compression),
+                Output::Oci(archive) | Output::Docker(archive) | Output::Tar(archive) => {
+                    (&archive.names, &archive.compression)
+                }
+                Output::Local { .. } => continue,
+            };
+            for name in names {
+                if let Some(problem) = reference_problem(name) {
+                    problems.push(ConfigProblem::new("outputs", problem));
+                }
+            }
+            if let Err(e) = compression.validate() {
+                problems.push(ConfigProblem::new("outputs", config_message(e)));
+            }
+            let stored = matches!(
+                output,
+                Output::Image(image) if image.unpack || image.store == Some(true)
+            );
+            if stored && names.is_empty() && self.tags.is_empty() {
+                problems.push(ConfigProblem::new(
+                    "outputs",
+                    "images kept in the worker's image store need a name or a tag",
+                ));
+            }
+            if matches!(output, Output::Docker(_)) && self.platforms.len() > 1 {
+                problems.push(ConfigProblem::new(
+                    "outputs",
+                    "the docker exporter cannot export multi-platform images; \
+                     use an OCI tarball or push to a registry",
+                ));
+            }
+        }
+    }
+
+    fn check_cache_imports(&self, problems: &mut Vec<ConfigProblem>) {
+        let parsed = self
+            .cache_from
+            .iter()
+            .filter_map(|spec| CacheImport::parse(spec).ok());
+        for import in self.cache_imports.iter().cloned().chain(parsed) {
+            if let CacheImport::OciLayout { path, .. } = import {
+                if !path.join("index.json").is_file() {
+                    problems.push(ConfigProblem::new(
+                        "cache_imports",
+                        format!("{} is not an OCI layout", path.display()),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn check_secret_files(&self, problems: &mut Vec<ConfigProblem>) {
+        let mut files: Vec<_> = self.secret_files.iter().collect();
+        files.sort();
+        for (id, path) in files {
+            if id.is_empty() {
+                problems.push(ConfigProblem::new("secrets", "empty secret ID"));
+                continue;
+            }
+            let problem = match std::fs::metadata(path) {
+                Ok(metadata) if !metadata.is_file() => {
+                    format!("secret {:?}: {} is not a file", id, path.display())
+                }
+                Ok(metadata) if metadata.len() > MAX_SECRET_SIZE as u64 => format!(
+                    "secret {:?}: {} is {} bytes; BuildKit accepts at most {}",
+                    id,
+                    path.display(),
+                    metadata.len(),
+                    MAX_SECRET_SIZE
+                ),
+                Ok(_) => continue,
+                Err(e) => format!("secret {:?}: cannot read {}: {}", id, path.display(), e),
+            };
+            problems.push(ConfigProblem::new("secrets", problem));
+        }
+    }
+
+    fn check_ssh_agents(&self, problems: &mut Vec<ConfigProblem>) {
+        for spec in &self.ssh_agents {
+            match parse_ssh_spec(spec) {
+                Ok((id, paths)) => {
+                    for path in paths.into_iter().filter(|path| !path.exists()) {
+                        problems.push(ConfigProblem::new(
+                            "ssh_agents",
+                            format!("SSH agent {:?}: {} does not exist", id, path.display()),
+                        ));
+                    }
+                }
+                Err(e) => problems.push(ConfigProblem::new("ssh_agents", config_message(e))),
+            }
+        }
+
+        let mut keys: Vec<_> = self.ssh_keys.iter().collect();
+        keys.sort_by_key(|(id, _)| *id);
+        for (id, keys) in keys {
+            for key in keys {
+                if let Err(e) = key.public_keys() {
+                    problems.push(ConfigProblem::new(
+                        "ssh_keys",
+                        format!("SSH key for {:?}: {}", id, config_message(e)),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn check_registries(&self, problems: &mut Vec<ConfigProblem>) {
+        for (host, registry) in &self.registries {
+            let mut problem = |message: String| {
+                problems.push(ConfigProblem::new(
+                    "registries",
+                    format!("registry {:?}: {}", host, message),
+                ));
+            };
+            for path in &registry.ca_certs {
+                if !path.is_file() {
+                    problem(format!("CA certificate {} does not exist", path.display()));
+                }
+            }
+            for mirror in &registry.mirrors {
+                let valid = !mirror.is_empty()
+                    && !mirror.contains('/')
+                    && reference_problem(&format!("{}/mirror", mirror)).is_none();
+                if !valid {
+                    problem(format!("invalid mirror host {:?}", mirror));
+                }
+            }
+        }
+    }
+}
+
+/// Message of a configuration error, without the error kind
+fn config_message(error: Error) -> String {
+    match error {
+        Error::InvalidConfig(message) | Error::InvalidOutput(message) => message,
+        error => error.to_string(),
+    }
+}
+
+/// What is wrong with an image reference, following the distribution
+/// reference grammar (`[host[:port]/]path[:tag][@digest]`)
+pub fn reference_problem(reference: &str) -> Option<String> {
+    let invalid = |reason: &str| {
+        Some(format!(
+            "invalid image reference {:?}: {}",
+            reference, reason
+        ))
+    };
+
+    let (name, digest) = match reference.split_once('@') {
+        Some((name, digest)) => (name, Some(digest)),
+        None => (reference, None),
+    };
+    if let Some(digest) = digest {
+        let valid = digest.split_once(':').is_some_and(|(algorithm, hex)| {
+            !algorithm.is_empty()
+                && algorithm
+                    .bytes()
+                    .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b"+._-".contains(&b))
+                && hex.len() >= 32
+                && hex.bytes().all(|b| b.is_ascii_hexdigit())
+        });
+        if !valid {
+            return invalid("malformed digest");
+        }
+    }
+
+    let (name, tag) = match name.rsplit_once(':') {
+        Some((repository, tag)) if !tag.contains('/') => (repository, Some(tag)),
+        _ => (name, None),
+    };
+    if let Some(tag) = tag {
+        let valid = tag.len() <= 128
+            && tag.bytes().enumerate().all(|(i, b)| {
+                b.is_ascii_alphanumeric() || b == b'_' || (i > 0 && matches!(b, b'.' | b'-'))
+            });
+        if tag.is_empty() || !valid {
+            return invalid("malformed tag");
+        }
+    }
+
+    if name.is_empty() {
+        return invalid("empty name");
+    }
+    if name.len() > 255 {
+        return invalid("name longer than 255 characters");
+    }
+    let mut components = name.split('/').peekable();
+    if let Some(first) = components.peek() {
+        let is_host = name.contains('/')
+            && (first.contains('.') || first.contains(':') || *first == "localhost");
+        if is_host {
+            let (host, port) = match first.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (*first, None),
+            };
+            let valid_host = !host.is_empty()
+                && host
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'-'));
+            let valid_port = match port {
+                Some(port) => !port.is_empty() && port.bytes().all(|b| b.is_ascii_digit()),
+                None => true,
+            };
+            if !valid_host || !valid_port {
+                return invalid("malformed registry host");
+            }
+            components.next();
+        }
+    }
+    for component in components {
+        if !is_path_component(component) {
+            return invalid("repository names are lowercase letters, digits and separators");
+        }
+    }
+    None
+}
+
+/// Whether `component` is lowercase alphanumerics joined by `.`, `_`, `__`
+/// or runs of `-`
+fn is_path_component(component: &str) -> bool {
+    let mut separator = String::new();
+    let mut seen_alnum = false;
+    for c in component.chars() {
+        if c.is_ascii_lowercase() || c.is_ascii_digit() {
+            if !separator.is_empty() {
+                let valid = matches!(separator.as_str(), "." | "_" | "__")
+                    || separator.chars().all(|c| c == '-');
+                if !valid || !seen_alnum {
+                    return false;
+                }
+                separator.clear();
+            }
+            seen_alnum = true;
+        } else if matches!(c, '.' | '_' | '-') {
+            separator.push(c);
+        } else {
+            return false;
+        }
+    }
+    seen_alnum && separator.is_empty()
+}
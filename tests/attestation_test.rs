@@ -0,0 +1,172 @@
+//! Tests for exporter descriptors and attestation discovery
+
+use base64::Engine;
+use buildkit_client::attest::{attestations_in_index, fetch_attestations, SLSA_PROVENANCE_V02};
+use buildkit_client::oci::{Descriptor, ImageIndex, DESCRIPTOR_RESPONSE_KEY};
+use buildkit_client::registry::split_image_name;
+use buildkit_client::{BlobInfo, ContentStore, Platform};
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[derive(Default)]
+struct MemoryStore {
+    blobs: Mutex<HashMap<String, Bytes>>,
+}
+
+impl MemoryStore {
+    fn put(&self, data: &str) -> String {
+        let digest = format!("sha256:{:x}", Sha256::digest(data.as_bytes()));
+        self.blobs
+            .lock()
+            .unwrap()
+            .insert(digest.clone(), Bytes::from(data.to_string()));
+        digest
+    }
+}
+
+#[tonic::async_trait]
+impl ContentStore for MemoryStore {
+    async fn info(&self, digest: &str) -> buildkit_client::Result<Option<BlobInfo>> {
+        Ok(self.blobs.lock().unwrap().get(digest).map(|data| BlobInfo {
+            digest: digest.to_string(),
+            size: data.len() as u64,
+            labels: HashMap::new(),
+        }))
+    }
+
+    async fn read_at(
+        &self,
+        digest: &str,
+        offset: u64,
+        len: usize,
+    ) -> buildkit_client::Result<Bytes> {
+        let data = self
+            .blobs
+            .lock()
+            .unwrap()
+            .get(digest)
+            .cloned()
+            .unwrap_or_default();
+        let start = (offset as usize).min(data.len());
+        Ok(data.slice(start..(start + len).min(data.len())))
+    }
+}
+
+fn index_json(image: &str, attestation: &str) -> String {
+    format!(
+        r#"{{
+  "schemaVersion": 2,
+  "mediaType": "application/vnd.oci.image.index.v1+json",
+  "manifests": [
+    {{
+      "mediaType": "application/vnd.oci.image.manifest.v1+json",
+      "digest": "{image}",
+      "size": 100,
+      "platform": {{ "architecture": "arm64", "os": "linux", "variant": "v8" }}
+    }},
+    {{
+      "mediaType": "application/vnd.oci.image.manifest.v1+json",
+      "digest": "{attestation}",
+      "size": 200,
+      "annotations": {{
+        "vnd.docker.reference.digest": "{image}",
+        "vnd.docker.reference.type": "attestation-manifest"
+      }},
+      "platform": {{ "architecture": "unknown", "os": "unknown" }}
+    }}
+  ]
+}}"#
+    )
+}
+
+#[test]
+fn test_descriptor_from_exporter_response() {
+    let json = r#"{"mediaType":"application/vnd.oci.image.index.v1+json","digest":"sha256:abc","size":856}"#;
+    let mut response = HashMap::new();
+    assert_eq!(Descriptor::from_exporter_response(&response).unwrap(), None);
+
+    response.insert(
+        DESCRIPTOR_RESPONSE_KEY.to_string(),
+        base64::engine::general_purpose::STANDARD.encode(json),
+    );
+    let descriptor = Descriptor::from_exporter_response(&response)
+        .unwrap()
+        .unwrap();
+    assert_eq!(descriptor.digest, "sha256:abc");
+    assert_eq!(descriptor.size, 856);
+    assert!(descriptor.is_index());
+
+    response.insert(
+        DESCRIPTOR_RESPONSE_KEY.to_string(),
+        "not base64!".to_string(),
+    );
+    assert!(Descriptor::from_exporter_response(&response).is_err());
+}
+
+#[test]
+fn test_attestations_in_index() {
+    let index = ImageIndex::from_json(index_json("sha256:img", "sha256:att").as_bytes()).unwrap();
+    assert_eq!(
+        index.manifests[0].platform,
+        Some(Platform::linux_arm64().with_variant("v8"))
+    );
+
+    let attestations = attestations_in_index(&index);
+    assert_eq!(attestations.len(), 1);
+    assert_eq!(attestations[0].descriptor.digest, "sha256:att");
+    assert_eq!(attestations[0].subject, "sha256:img");
+}
+
+#[tokio::test]
+async fn test_fetch_attestations() {
+    let store = MemoryStore::default();
+    let statement = store.put(
+        r#"{"_type":"https://in-toto.io/Statement/v0.1","predicateType":"https://slsa.dev/provenance/v0.2","subject":[{"name":"pkg:docker/app","digest":{"sha256":"img"}}],"predicate":{"builder":{"id":"ci"}}}"#,
+    );
+    let config = store.put("{}");
+    let manifest = store.put(&format!(
+        r#"{{"schemaVersion":2,"config":{{"mediaType":"application/vnd.in-toto+json","digest":"{config}","size":2}},"layers":[{{"mediaType":"application/vnd.in-toto+json","digest":"{statement}","size":1,"annotations":{{"in-toto.io/predicate-type":"https://slsa.dev/provenance/v0.2"}}}}]}}"#
+    ));
+    let index = store.put(&index_json("sha256:img", &manifest));
+    let descriptor = Descriptor {
+        media_type: "application/vnd.oci.image.index.v1+json".to_string(),
+        digest: index,
+        ..Default::default()
+    };
+
+    let refs = fetch_attestations(&store, &descriptor).await.unwrap();
+    assert_eq!(refs.len(), 1);
+    let attestations = refs[0].fetch(&store).await.unwrap();
+    assert_eq!(attestations.len(), 1);
+    assert_eq!(attestations[0].predicate_type, SLSA_PROVENANCE_V02);
+    assert_eq!(attestations[0].statement.subject[0].digest["sha256"], "img");
+    assert_eq!(attestations[0].statement.predicate["builder"]["id"], "ci");
+
+    // Plain manifests carry no attestations
+    let manifest_only = Descriptor {
+        media_type: "application/vnd.oci.image.manifest.v1+json".to_string(),
+        ..descriptor
+    };
+    assert!(fetch_attestations(&store, &manifest_only)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_split_image_name() {
+    assert_eq!(
+        split_image_name("localhost:5000/team/app:v1"),
+        ("localhost:5000".to_string(), "team/app".to_string())
+    );
+    assert_eq!(
+        split_image_name("user/app@sha256:abc"),
+        ("registry-1.docker.io".to_string(), "user/app".to_string())
+    );
+    assert_eq!(
+        split_image_name("ghcr.io/org/app"),
+        ("ghcr.io".to_string(), "org/app".to_string())
+    );
+}
@@ -1,6 +1,6 @@
 //! Unit tests for BuildConfig and related types
 
-use buildkit_client::{BuildConfig, DockerfileSource, Platform, RegistryAuth};
+use buildkit_client::{BuildConfig, DockerfileSource, ImageResolveMode, Platform, RegistryAuth};
 use std::path::PathBuf;
 
 #[test]
@@ -89,7 +89,7 @@ fn test_build_config_builder_pattern() {
     assert_eq!(config.target, Some("production".to_string()));
     assert_eq!(config.platforms.len(), 2); // default + added
     assert!(config.no_cache);
-    assert!(config.pull);
+    assert_eq!(config.image_resolve_mode, ImageResolveMode::ForcePull);
 }
 
 #[test]
@@ -98,6 +98,7 @@ fn test_registry_auth() {
         host: "docker.io".to_string(),
         username: "testuser".to_string(),
         password: "testpass".to_string(),
+        identity_token: None,
     };
 
     let config = BuildConfig::local("./app")
@@ -110,6 +111,7 @@ fn test_registry_auth() {
 }
 
 #[test]
+#[allow(deprecated)]
 fn test_cache_config() {
     let config = BuildConfig::local("./app")
         .cache_from("type=registry,ref=myapp:cache")
@@ -132,6 +134,48 @@ fn test_secrets_config() {
     assert_eq!(config.secrets.get("api_key"), Some(&"another_secret".to_string()));
 }
 
+#[test]
+fn test_ssh_config() {
+    let config = BuildConfig::local("./app")
+        .ssh("default")
+        .ssh("deploy=/home/user/.ssh/id_ed25519");
+    assert_eq!(
+        config.ssh_agents,
+        ["default", "deploy=/home/user/.ssh/id_ed25519"]
+    );
+}
+
+#[test]
+fn test_ssh_key_config() {
+    let config = BuildConfig::local("./app")
+        .ssh_key("default", "first key")
+        .ssh_key("default", "second key")
+        .ssh_key("deploy", "deploy key");
+    assert_eq!(config.ssh_keys["default"].len(), 2);
+    assert_eq!(config.ssh_keys["deploy"].len(), 1);
+
+    // Keys never show up in debug output
+    assert!(!format!("{:?}", config).contains("deploy key"));
+}
+
+#[test]
+fn test_secret_from_file_config() {
+    let config = BuildConfig::local("./app")
+        .secret("netrc", "inline")
+        .secret_from_file("netrc", "/home/user/.netrc")
+        .secret_from_file("ca", "/etc/ssl/ca.pem")
+        .secret("ca", "replaced");
+
+    // The last source given for an ID wins
+    assert_eq!(config.secret_files.len(), 1);
+    assert_eq!(
+        config.secret_files["netrc"],
+        PathBuf::from("/home/user/.netrc")
+    );
+    assert_eq!(config.secrets.len(), 1);
+    assert_eq!(config.secrets["ca"], "replaced");
+}
+
 #[test]
 fn test_multi_platform_build() {
     let config = BuildConfig::local("./app")
@@ -169,3 +213,604 @@ fn test_dockerfile_path_github() {
         _ => panic!("Expected GitHub source"),
     }
 }
+
+#[test]
+fn test_output_parse_image() {
+    use buildkit_client::Output;
+
+    let output = Output::parse("type=image,name=localhost:5000/app:v1,push=true").unwrap();
+    assert_eq!(output.exporter_type(), "image");
+
+    let attrs = output.attrs();
+    assert_eq!(attrs.get("name"), Some(&"localhost:5000/app:v1".to_string()));
+    assert_eq!(attrs.get("push"), Some(&"true".to_string()));
+}
+
+#[test]
+fn test_output_parse_registry_and_local() {
+    use buildkit_client::Output;
+
+    match Output::parse("type=registry,name=docker.io/user/app").unwrap() {
+        Output::Image(image) => {
+            assert!(image.push);
+            assert_eq!(image.names, vec!["docker.io/user/app".to_string()]);
+        }
+        _ => panic!("Expected Image output"),
+    }
+
+    match Output::parse("type=local,dest=./out").unwrap() {
+        Output::Local { dest } => assert_eq!(dest, PathBuf::from("./out")),
+        _ => panic!("Expected Local output"),
+    }
+
+    assert!(Output::parse("type=local").is_err());
+    assert!(Output::parse("dest=./out").is_err());
+    assert!(Output::parse("type=image,push=maybe").is_err());
+}
+
+#[test]
+fn test_build_config_multiple_outputs() {
+    use buildkit_client::{ImageOutput, Output};
+
+    let config = BuildConfig::local("./app")
+        .tag("myapp:v1")
+        .output(Output::Image(ImageOutput::new().push(false)))
+        .output(Output::local("./out"));
+
+    assert_eq!(config.outputs.len(), 2);
+    assert_eq!(config.outputs[0].exporter_type(), "image");
+    assert_eq!(config.outputs[1].exporter_type(), "local");
+}
+
+#[test]
+fn test_resolved_outputs_push() {
+    use buildkit_client::Output;
+
+    let pushed = BuildConfig::local(".").tag("registry.example.com/app:v1");
+    match &pushed.resolved_outputs()[..] {
+        [Output::Image(image)] => assert!(image.push),
+        other => panic!("Expected a single image output, got {:?}", other),
+    }
+
+    let local = BuildConfig::local(".").tag("app:dev").push(false);
+    match &local.resolved_outputs()[..] {
+        [Output::Image(image)] => {
+            assert!(!image.push);
+            assert_eq!(image.names, vec!["app:dev".to_string()]);
+            assert!(!Output::Image(image.clone()).attrs().contains_key("push"));
+        }
+        other => panic!("Expected a single image output, got {:?}", other),
+    }
+
+    // Tags name an existing unnamed image output instead of adding another
+    let config = BuildConfig::local(".")
+        .tag("app:v1")
+        .output(Output::local("./out"))
+        .output(Output::Image(Default::default()));
+    let outputs = config.resolved_outputs();
+    assert_eq!(outputs.len(), 2);
+    assert_eq!(outputs[1].attrs().get("name"), Some(&"app:v1".to_string()));
+
+    assert!(BuildConfig::local(".").resolved_outputs().is_empty());
+}
+
+#[test]
+fn test_output_oci() {
+    use buildkit_client::{ExportDest, Output};
+
+    let output = Output::parse("type=oci,dest=out/image.tar,name=app:v1").unwrap();
+    assert_eq!(output.exporter_type(), "oci");
+    assert_eq!(output.attrs().get("name"), Some(&"app:v1".to_string()));
+    match output.archive_dest() {
+        Some(ExportDest::File(path)) => assert_eq!(path, &PathBuf::from("out/image.tar")),
+        _ => panic!("Expected file destination"),
+    }
+
+    let output = Output::oci_writer(tokio::io::sink());
+    assert!(matches!(output.archive_dest(), Some(ExportDest::Writer(_))));
+
+    assert!(Output::parse("type=oci").is_err());
+    assert!(Output::local("./out").archive_dest().is_none());
+}
+
+#[test]
+fn test_output_compression() {
+    use buildkit_client::{Compression, ImageOutput, Output};
+
+    let output = Output::Image(
+        ImageOutput::new()
+            .name("app:v1")
+            .compression(Compression::Zstd)
+            .compression_level(19)
+            .force_compression(true),
+    );
+    let attrs = output.attrs();
+    assert_eq!(attrs.get("compression"), Some(&"zstd".to_string()));
+    assert_eq!(attrs.get("compression-level"), Some(&"19".to_string()));
+    assert_eq!(attrs.get("force-compression"), Some(&"true".to_string()));
+
+    // Nothing is set unless asked for
+    let attrs = Output::image("app:v1").attrs();
+    assert!(!attrs.contains_key("compression"));
+    assert!(!attrs.contains_key("force-compression"));
+
+    let parsed = Output::parse("type=oci,dest=out.tar,compression=estargz,compression-level=5").unwrap();
+    assert_eq!(parsed.attrs().get("compression"), Some(&"estargz".to_string()));
+
+    assert!(Output::parse("type=image,compression=lz4").is_err());
+    assert!(Output::parse("type=image,compression=gzip,compression-level=15").is_err());
+    assert!(Output::parse("type=image,compression=zstd,compression-level=15").is_ok());
+}
+
+#[test]
+fn test_output_oci_mediatypes() {
+    use buildkit_client::{ImageOutput, Output};
+
+    let output = Output::Image(
+        ImageOutput::new()
+            .name("app:v1")
+            .push(true)
+            .oci_mediatypes(true),
+    );
+    let attrs = output.attrs();
+    assert_eq!(attrs.get("oci-mediatypes"), Some(&"true".to_string()));
+
+    // Left to BuildKit unless set
+    let attrs = Output::image("app:v1").attrs();
+    assert!(!attrs.contains_key("oci-mediatypes"));
+
+    match Output::parse("type=registry,name=app:v1,oci-mediatypes=false").unwrap() {
+        Output::Image(image) => assert_eq!(image.oci_mediatypes, Some(false)),
+        other => panic!("expected image output, got {:?}", other),
+    }
+    assert!(Output::parse("type=image,oci-mediatypes=maybe").is_err());
+}
+
+#[test]
+fn test_output_unpack() {
+    use buildkit_client::{ImageOutput, Output};
+
+    let output = Output::Image(ImageOutput::new().name("app:v1").unpack(true));
+    assert_eq!(output.attrs().get("unpack"), Some(&"true".to_string()));
+    assert!(!Output::image("app:v1").attrs().contains_key("unpack"));
+
+    match Output::parse("type=image,name=app:v1,unpack=true").unwrap() {
+        Output::Image(image) => {
+            assert!(image.unpack);
+            assert!(!image.push);
+        }
+        other => panic!("expected image output, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_output_containerd() {
+    use buildkit_client::Output;
+
+    let output = Output::containerd(["app:dev", "app:latest"]);
+    let attrs = output.attrs();
+    assert_eq!(output.exporter_type(), "image");
+    assert_eq!(attrs["name"], "app:dev,app:latest");
+    assert_eq!(attrs["unpack"], "true");
+    assert_eq!(attrs["store"], "true");
+    assert!(!attrs.contains_key("push"));
+
+    // Without names, the configured tags are stored
+    let config = BuildConfig::local("./app")
+        .tag("app:v1")
+        .output(Output::containerd(Vec::<String>::new()));
+    match config.resolved_outputs().as_slice() {
+        [Output::Image(image)] => {
+            assert_eq!(image.names, ["app:v1"]);
+            assert!(image.unpack);
+        }
+        other => panic!("expected one image output, got {:?}", other),
+    }
+
+    match Output::parse("type=image,name=app:v1,store=false").unwrap() {
+        Output::Image(image) => assert_eq!(image.store, Some(false)),
+        other => panic!("expected image output, got {:?}", other),
+    }
+    assert!(!Output::image("app:v1").attrs().contains_key("store"));
+}
+
+#[test]
+fn test_output_tar() {
+    use buildkit_client::Output;
+
+    let output = Output::parse("type=tar,dest=rootfs.tar").unwrap();
+    assert_eq!(output.exporter_type(), "tar");
+    assert!(output.attrs().is_empty());
+    assert!(Output::parse("type=tar").is_err());
+}
+
+#[tokio::test]
+async fn test_output_tar_reader() {
+    use buildkit_client::Output;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let (output, mut reader) = Output::tar_reader(1024);
+    let mut writer = output.archive_dest().unwrap().open().await.unwrap();
+    writer.write_all(b"tar bytes").await.unwrap();
+    writer.shutdown().await.unwrap();
+    drop(writer);
+
+    let mut received = Vec::new();
+    reader.read_to_end(&mut received).await.unwrap();
+    assert_eq!(received, b"tar bytes");
+}
+
+#[test]
+fn test_build_ref_default_and_prefix() {
+    let generated = BuildConfig::local(".").resolve_ref().unwrap();
+    assert!(generated.starts_with("build-"));
+
+    let config = BuildConfig::local(".").ref_prefix("ci-job");
+    let first = config.resolve_ref().unwrap();
+    assert!(first.starts_with("ci-job-"));
+    assert_ne!(first, config.resolve_ref().unwrap());
+}
+
+#[test]
+fn test_build_ref_custom() {
+    let config = BuildConfig::local(".").ref_id("pipeline-42.step_3");
+    assert_eq!(config.resolve_ref().unwrap(), "pipeline-42.step_3");
+
+    for invalid in ["", "has space", "a/b"] {
+        assert!(BuildConfig::local(".").ref_id(invalid).resolve_ref().is_err());
+    }
+}
+
+#[test]
+fn test_build_ref_from_key() {
+    let config = BuildConfig::local(".").ref_key("delivery-8d1f");
+    let derived = config.resolve_ref().unwrap();
+    assert!(derived.starts_with("build-"));
+    assert_eq!(derived.len(), "build-".len() + 32);
+    // Same key, same ref
+    assert_eq!(derived, config.resolve_ref().unwrap());
+    assert_ne!(
+        derived,
+        BuildConfig::local(".")
+            .ref_key("delivery-8d20")
+            .resolve_ref()
+            .unwrap()
+    );
+
+    let config = BuildConfig::local(".")
+        .ref_key("delivery-8d1f")
+        .ref_prefix("webhook");
+    assert!(config.resolve_ref().unwrap().starts_with("webhook-"));
+
+    // An explicit ref wins
+    let config = config.ref_id("job-1");
+    assert_eq!(config.resolve_ref().unwrap(), "job-1");
+    assert!(!config.deduplicate);
+    assert!(config.deduplicate(true).deduplicate);
+}
+
+#[test]
+fn test_quick_build_cache_ref() {
+    use buildkit_client::quick::cache_ref_for;
+
+    assert_eq!(cache_ref_for("app:v1"), "app:buildcache");
+    assert_eq!(cache_ref_for("localhost:5000/app:v1"), "localhost:5000/app:buildcache");
+    assert_eq!(cache_ref_for("localhost:5000/app"), "localhost:5000/app:buildcache");
+    assert_eq!(
+        cache_ref_for("docker.io/user/app@sha256:abc"),
+        "docker.io/user/app:buildcache"
+    );
+}
+
+#[test]
+fn test_quick_build_config() {
+    use buildkit_client::{CacheExport, CacheImport, QuickBuild};
+
+    let config = QuickBuild::new("./app", "localhost:5000/app:v1")
+        .platform(Platform::linux_arm64())
+        .to_config();
+
+    assert_eq!(config.tags, vec!["localhost:5000/app:v1".to_string()]);
+    assert!(config.push);
+    assert_eq!(
+        config.cache_imports,
+        vec![CacheImport::registry("localhost:5000/app:buildcache")]
+    );
+    assert_eq!(
+        config.cache_exports,
+        vec![CacheExport::registry("localhost:5000/app:buildcache")]
+    );
+    assert!(config.cache_from.is_empty());
+    assert_eq!(config.platforms.len(), 1);
+    assert_eq!(config.platforms[0].arch, "arm64");
+
+    // Default platform is kept when none are given
+    let config = QuickBuild::new(".", "app:v1").to_config();
+    assert_eq!(config.platforms.len(), 1);
+    assert_eq!(config.platforms[0].arch, "amd64");
+}
+
+#[test]
+fn test_annotations() {
+    use buildkit_client::{Annotation, AnnotationScope};
+
+    let config = BuildConfig::local(".")
+        .annotation("org.opencontainers.image.source", "https://example.com/app")
+        .scoped_annotation(
+            AnnotationScope::Index,
+            "org.opencontainers.image.version",
+            "1.2.0",
+        );
+    let keys: Vec<String> = config.annotations.iter().map(|a| a.attr_key()).collect();
+    assert_eq!(
+        keys,
+        vec![
+            "annotation.org.opencontainers.image.source",
+            "annotation-index.org.opencontainers.image.version",
+        ]
+    );
+
+    let parsed =
+        Annotation::parse("manifest-descriptor:org.opencontainers.image.title=app").unwrap();
+    assert_eq!(parsed.scope, AnnotationScope::ManifestDescriptor);
+    assert_eq!(parsed.key, "org.opencontainers.image.title");
+    assert_eq!(parsed.value, "app");
+
+    // Values may contain '=' and ':'
+    let parsed = Annotation::parse("org.opencontainers.image.url=https://x/?a=b").unwrap();
+    assert_eq!(parsed.scope, AnnotationScope::Manifest);
+    assert_eq!(parsed.value, "https://x/?a=b");
+
+    assert!(Annotation::parse("no-value").is_err());
+    assert!(Annotation::parse("layer:key=value").is_err());
+}
+
+#[test]
+fn test_provenance() {
+    use buildkit_client::{Provenance, ProvenanceMode, QuickBuild};
+
+    assert_eq!(
+        Provenance::new(ProvenanceMode::Min).attr_value(),
+        "mode=min"
+    );
+    assert_eq!(
+        Provenance::new(ProvenanceMode::Max)
+            .inline_only(true)
+            .builder_id("https://ci.example.com/run/1")
+            .attr_value(),
+        "mode=max,inline-only=true,builder-id=https://ci.example.com/run/1"
+    );
+    // Fields containing CSV separators are quoted
+    assert_eq!(
+        Provenance::new(ProvenanceMode::Min)
+            .builder_id("a,\"b\"")
+            .attr_value(),
+        "mode=min,\"builder-id=a,\"\"b\"\"\""
+    );
+
+    assert_eq!(
+        "max".parse::<ProvenanceMode>().unwrap(),
+        ProvenanceMode::Max
+    );
+    assert!("full".parse::<ProvenanceMode>().is_err());
+
+    let config = BuildConfig::local(".").provenance(ProvenanceMode::Max);
+    assert_eq!(
+        config.provenance,
+        Some(Provenance::new(ProvenanceMode::Max))
+    );
+    assert!(BuildConfig::local(".").provenance.is_none());
+
+    // Quick builds attach inline min provenance unless disabled
+    let config = QuickBuild::new(".", "app:v1").to_config();
+    assert_eq!(
+        config.provenance,
+        Some(Provenance::new(ProvenanceMode::Min).inline_only(true))
+    );
+    let config = QuickBuild::new(".", "app:v1").provenance(None).to_config();
+    assert!(config.provenance.is_none());
+}
+
+#[test]
+fn test_solve_mutations() {
+    use buildkit_client::proto::moby::buildkit::v1::SolveRequest;
+
+    let config = BuildConfig::local(".")
+        .with_solve_mutation(|request| {
+            request
+                .frontend_attrs
+                .insert("experimental".to_string(), "1".to_string());
+        })
+        .with_solve_mutation(|request| {
+            request.frontend_attrs.remove("experimental");
+            request.entitlements.push("network.host".to_string());
+        });
+
+    // Mutations survive cloning and run in order
+    let config = config.clone();
+    let mut request = SolveRequest::default();
+    for mutation in &config.solve_mutations {
+        mutation.apply(&mut request);
+    }
+    assert!(request.frontend_attrs.is_empty());
+    assert_eq!(request.entitlements, vec!["network.host".to_string()]);
+    assert!(format!("{:?}", config).contains("SolveMutation(..)"));
+}
+
+#[test]
+fn test_cache_exports() {
+    use buildkit_client::CacheExport;
+
+    let inline = CacheExport::Inline.to_entry();
+    assert_eq!(inline.r#type, "inline");
+    assert!(inline.attrs.is_empty());
+
+    let registry = CacheExport::registry("localhost:5000/app:buildcache").to_entry();
+    assert_eq!(registry.r#type, "registry");
+    assert_eq!(registry.attrs["ref"], "localhost:5000/app:buildcache");
+    assert_eq!(registry.attrs["mode"], "max");
+
+    let config = BuildConfig::local(".")
+        .tag("localhost:5000/app:v1")
+        .cache_export(CacheExport::Inline);
+    assert_eq!(config.cache_exports, vec![CacheExport::Inline]);
+    assert!(config.cache_to.is_empty());
+}
+
+#[test]
+fn test_resolve_git_ref() {
+    use buildkit_client::github::parse_repo_url;
+
+    let config = BuildConfig::github("https://github.com/moby/buildkit")
+        .git_ref("v0.13.0")
+        .resolve_git_ref(true);
+    assert!(config.resolve_git_ref);
+    assert!(!BuildConfig::github("https://github.com/moby/buildkit").resolve_git_ref);
+
+    let repo = Some(("moby".to_string(), "buildkit".to_string()));
+    assert_eq!(parse_repo_url("https://github.com/moby/buildkit"), repo);
+    assert_eq!(parse_repo_url("https://github.com/moby/buildkit.git"), repo);
+    assert_eq!(parse_repo_url("https://github.com/moby/buildkit/"), repo);
+    assert_eq!(
+        parse_repo_url("https://ghp_token@github.com/moby/buildkit"),
+        repo
+    );
+    assert_eq!(parse_repo_url("https://github.com/moby"), None);
+    assert_eq!(
+        parse_repo_url("https://github.com/moby/buildkit/tree/master"),
+        None
+    );
+    assert_eq!(parse_repo_url("git@github.com:moby/buildkit.git"), None);
+}
+
+#[test]
+fn test_entitlements() {
+    use buildkit_client::Entitlement;
+
+    let config = BuildConfig::local(".")
+        .allow(Entitlement::SecurityInsecure)
+        .allow(Entitlement::NetworkHost)
+        .allow(Entitlement::NetworkHost);
+    let names: Vec<String> = config.entitlements.iter().map(|e| e.to_string()).collect();
+    assert_eq!(names, vec!["network.host", "security.insecure"]);
+    assert!(BuildConfig::local(".").entitlements.is_empty());
+
+    assert_eq!(
+        "network.host".parse::<Entitlement>().unwrap(),
+        Entitlement::NetworkHost
+    );
+    assert_eq!(
+        "security.insecure".parse::<Entitlement>().unwrap(),
+        Entitlement::SecurityInsecure
+    );
+    assert!("device".parse::<Entitlement>().is_err());
+}
+
+#[test]
+fn test_proxy_build_args() {
+    use buildkit_client::builder::proxy_build_args;
+
+    let env = [
+        ("HTTP_PROXY", "http://proxy.corp:3128"),
+        ("https_proxy", "http://proxy.corp:3128"),
+        ("NO_PROXY", "localhost,.corp"),
+        ("ALL_PROXY", ""),
+        ("PROXY_USER", "alice"),
+        ("PATH", "/usr/bin"),
+    ]
+    .map(|(name, value)| (name.to_string(), value.to_string()));
+    let args = proxy_build_args(env);
+    let names: Vec<&str> = args.keys().map(String::as_str).collect();
+    assert_eq!(names, ["HTTP_PROXY", "NO_PROXY", "https_proxy"]);
+    assert_eq!(args["NO_PROXY"], "localhost,.corp");
+
+    assert!(!BuildConfig::local(".").forward_proxy_env);
+    assert!(
+        BuildConfig::local(".")
+            .forward_proxy_env(true)
+            .forward_proxy_env
+    );
+}
+
+#[test]
+fn test_registry_config() {
+    use buildkit_client::RegistryConfig;
+
+    let config = BuildConfig::local(".")
+        .insecure_registry("localhost:5000")
+        .registry_config(
+            "docker.io",
+            RegistryConfig {
+                skip_tls_verify: true,
+                ..Default::default()
+            },
+        );
+    assert_eq!(
+        config.registry_config_for("localhost:5000/app:v1"),
+        Some(&RegistryConfig::insecure())
+    );
+    assert!(config
+        .registry_config_for("alpine:3.20")
+        .is_some_and(RegistryConfig::is_insecure));
+
+    // Only configured registries are insecure, whatever their names look like
+    assert_eq!(config.registry_config_for("localhost:5001/app"), None);
+    assert_eq!(config.registry_config_for("registry:5000/app"), None);
+    assert!(!RegistryConfig::default().is_insecure());
+}
+
+#[test]
+fn test_verify_push() {
+    assert!(!BuildConfig::local(".").verify_push);
+    assert!(BuildConfig::local(".").verify_push(true).verify_push);
+}
+
+#[test]
+fn test_registry_mirrors() {
+    use buildkit_client::registry::{mirror_reference, RegistryContent};
+    use buildkit_client::RegistryConfig;
+
+    assert_eq!(
+        mirror_reference("localhost:5000/team/app", "mirror:5001"),
+        "mirror:5001/team/app"
+    );
+    assert_eq!(
+        mirror_reference("app@sha256:abc", "mirror.local"),
+        "mirror.local/library/app@sha256:abc"
+    );
+
+    let registry = RegistryConfig::default()
+        .with_mirror("mirror-a.local")
+        .with_mirror("mirror-b.local")
+        .with_ca_cert("/nonexistent/ca.pem");
+    assert_eq!(registry.mirrors, ["mirror-a.local", "mirror-b.local"]);
+    assert!(!registry.is_insecure());
+    assert!(RegistryContent::configured("ghcr.io/org/app", &registry).is_err());
+}
+
+#[test]
+fn test_registry_auth_identity_token() {
+    let auth = RegistryAuth::identity_token("docker.io", "dckr_pat_secret");
+    assert_eq!(auth.identity_token.as_deref(), Some("dckr_pat_secret"));
+    assert!(auth.username.is_empty());
+
+    // Secrets never show up in debug output
+    let debug = format!(
+        "{:?} {:?}",
+        auth,
+        RegistryAuth::basic("ghcr.io", "user", "hunter2")
+    );
+    assert!(!debug.contains("dckr_pat_secret"));
+    assert!(!debug.contains("hunter2"));
+    assert!(debug.contains("ghcr.io"));
+}
+
+#[test]
+fn test_context_patterns() {
+    let config = BuildConfig::local(".")
+        .context_include(["src", "Cargo.*"])
+        .context_exclude(["**/*.tmp"])
+        .context_exclude(vec!["!src/keep.tmp".to_string()]);
+    assert_eq!(config.context_include, ["src", "Cargo.*"]);
+    assert_eq!(config.context_exclude, ["**/*.tmp", "!src/keep.tmp"]);
+}
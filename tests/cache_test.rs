@@ -0,0 +1,375 @@
+//! Unit tests for cache import/export backends
+
+use buildkit_client::cache::{local_store_id, LOCAL_CACHE_TAG};
+use buildkit_client::layout::OciLayout;
+use buildkit_client::{BlobInfo, BlobWriter, BuildConfig, CacheExport, CacheImport, ContentStore};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+
+fn digest_of(data: &[u8]) -> String {
+    format!("sha256:{:x}", Sha256::digest(data))
+}
+
+#[test]
+fn test_local_cache_entries() {
+    let export = CacheExport::local("/var/cache/buildkit").to_entry();
+    assert_eq!(export.r#type, "local");
+    assert_eq!(export.attrs["dest"], "/var/cache/buildkit");
+    assert_eq!(export.attrs["mode"], "max");
+
+    let import = CacheImport::local("/var/cache/buildkit").to_entry();
+    assert_eq!(import.r#type, "local");
+    assert_eq!(import.attrs["src"], "/var/cache/buildkit");
+    assert!(!import.attrs.contains_key("digest"));
+
+    let registry = CacheImport::registry("localhost:5000/app:buildcache").to_entry();
+    assert_eq!(registry.r#type, "registry");
+    assert_eq!(registry.attrs["ref"], "localhost:5000/app:buildcache");
+
+    assert_eq!(
+        local_store_id(Path::new("/var/cache/buildkit")),
+        "local:/var/cache/buildkit"
+    );
+
+    let config = BuildConfig::local(".")
+        .cache_import(CacheImport::local("/cache"))
+        .cache_export(CacheExport::local("/cache"));
+    assert_eq!(config.cache_imports, vec![CacheImport::local("/cache")]);
+    assert_eq!(config.cache_exports, vec![CacheExport::local("/cache")]);
+}
+
+#[test]
+fn test_oci_layout_cache_import() {
+    let import = CacheImport::oci_layout("/srv/bases", "alpine-3.20");
+    assert_eq!(import.cache_type(), "local");
+    let entry = import.to_entry();
+    assert_eq!(entry.r#type, "local");
+    assert_eq!(entry.attrs["src"], "/srv/bases");
+    assert_eq!(entry.attrs["tag"], "alpine-3.20");
+    assert!(!entry.attrs.contains_key("digest"));
+
+    // buildctl selects the manifest of a local import with `tag`
+    assert_eq!(
+        CacheImport::parse("type=local,src=/srv/bases,tag=alpine-3.20").unwrap(),
+        import
+    );
+    assert_eq!(
+        CacheImport::parse("type=local,src=/cache").unwrap(),
+        CacheImport::local("/cache")
+    );
+}
+
+#[tokio::test]
+async fn test_oci_layout_blobs() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let layout = OciLayout::open(dir.path().join("cache")).unwrap();
+    assert!(dir.path().join("cache/oci-layout").is_file());
+
+    let data = b"cache layer".to_vec();
+    let digest = digest_of(&data);
+    assert_eq!(layout.info(&digest).await.unwrap(), None);
+
+    // Chunks are staged until the writer commits
+    let mut writer = layout.writer("layer").await.unwrap();
+    writer.write(&data[..5]).await.unwrap();
+    writer.write(&data[5..]).await.unwrap();
+    assert_eq!(layout.info(&digest).await.unwrap(), None);
+    writer
+        .commit(BlobInfo {
+            digest: digest.clone(),
+            size: data.len() as u64,
+            labels: HashMap::new(),
+        })
+        .await
+        .unwrap();
+
+    let info = layout.info(&digest).await.unwrap().unwrap();
+    assert_eq!(info.size, data.len() as u64);
+    assert_eq!(
+        &layout.read_at(&digest, 6, 100).await.unwrap()[..],
+        b"layer"
+    );
+    assert!(layout.read_at(&digest, 100, 10).await.unwrap().is_empty());
+
+    // An abandoned upload leaves nothing behind
+    let mut writer = layout.writer("partial").await.unwrap();
+    writer.write(b"partial").await.unwrap();
+    drop(writer);
+    let staged = std::fs::read_dir(dir.path().join("cache/blobs"))
+        .unwrap()
+        .filter(|entry| {
+            let name = entry.as_ref().unwrap().file_name();
+            name.to_string_lossy().starts_with(".tmp-")
+        })
+        .count();
+    assert_eq!(staged, 0);
+
+    // Digests must not escape the blob directory
+    assert!(layout.info("sha256:../../etc/passwd").await.is_err());
+}
+
+#[test]
+fn test_oci_layout_tags() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let layout = OciLayout::open(dir.path()).unwrap();
+    assert_eq!(layout.resolve_tag(LOCAL_CACHE_TAG).unwrap(), None);
+
+    let first = br#"{"mediaType":"application/vnd.oci.image.manifest.v1+json","digest":"sha256:aaaa","size":10}"#;
+    let second = br#"{"mediaType":"application/vnd.oci.image.manifest.v1+json","digest":"sha256:bbbb","size":20}"#;
+    layout.set_tag(first, LOCAL_CACHE_TAG).unwrap();
+    layout.set_tag(first, "previous").unwrap();
+    layout.set_tag(second, LOCAL_CACHE_TAG).unwrap();
+
+    // Re-tagging replaces the entry and leaves other tags alone
+    let index = layout.index().unwrap();
+    assert_eq!(index.manifests.len(), 2);
+    let latest = layout.resolve_tag(LOCAL_CACHE_TAG).unwrap().unwrap();
+    assert_eq!(latest.digest, "sha256:bbbb");
+    assert_eq!(latest.size, 20);
+    let previous = layout.resolve_tag("previous").unwrap().unwrap();
+    assert_eq!(previous.digest, "sha256:aaaa");
+
+    assert!(layout.set_tag(b"[]", LOCAL_CACHE_TAG).is_err());
+}
+
+#[test]
+fn test_s3_cache_entries() {
+    use buildkit_client::cache::S3Cache;
+
+    let cache = S3Cache::new("ci-cache", "eu-west-1")
+        .prefix("app/")
+        .endpoint_url("http://minio:9000")
+        .use_path_style(true)
+        .credentials("AKIDEXAMPLE", "secret");
+
+    let export = CacheExport::S3(cache.clone()).to_entry();
+    assert_eq!(export.r#type, "s3");
+    assert_eq!(export.attrs["bucket"], "ci-cache");
+    assert_eq!(export.attrs["region"], "eu-west-1");
+    assert_eq!(export.attrs["prefix"], "app/");
+    assert_eq!(export.attrs["endpoint_url"], "http://minio:9000");
+    assert_eq!(export.attrs["use_path_style"], "true");
+    assert_eq!(export.attrs["access_key_id"], "AKIDEXAMPLE");
+    assert_eq!(export.attrs["secret_access_key"], "secret");
+    assert_eq!(export.attrs["mode"], "max");
+    assert!(!export.attrs.contains_key("session_token"));
+
+    let import = CacheImport::S3(cache.clone()).to_entry();
+    assert_eq!(import.r#type, "s3");
+    assert!(!import.attrs.contains_key("mode"));
+
+    // Secrets stay out of logs
+    let debug = format!("{:?}", cache);
+    assert!(debug.contains("AKIDEXAMPLE"));
+    assert!(!debug.contains("\"secret\""));
+
+    // Unset options are omitted rather than sent empty
+    let minimal = S3Cache::new("ci-cache", "us-east-1").attrs();
+    assert_eq!(minimal.len(), 2);
+}
+
+#[test]
+fn test_azblob_cache_entries() {
+    use buildkit_client::cache::AzBlobCache;
+
+    let cache = AzBlobCache::new("https://ciaccount.blob.core.windows.net")
+        .container("build-cache")
+        .prefix("app/")
+        .name("main")
+        .secret_access_key("account-key");
+
+    let export = CacheExport::AzBlob(cache.clone()).to_entry();
+    assert_eq!(export.r#type, "azblob");
+    assert_eq!(
+        export.attrs["account_url"],
+        "https://ciaccount.blob.core.windows.net"
+    );
+    assert_eq!(export.attrs["container"], "build-cache");
+    assert_eq!(export.attrs["prefix"], "app/");
+    assert_eq!(export.attrs["name"], "main");
+    assert_eq!(export.attrs["secret_access_key"], "account-key");
+    assert_eq!(export.attrs["mode"], "max");
+
+    let import = CacheImport::AzBlob(cache.clone()).to_entry();
+    assert_eq!(import.r#type, "azblob");
+    assert!(!import.attrs.contains_key("mode"));
+
+    assert!(!format!("{:?}", cache).contains("account-key"));
+    assert_eq!(
+        AzBlobCache::new("https://a.blob.core.windows.net")
+            .attrs()
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn test_parse_cache_specs() {
+    use buildkit_client::cache::{CacheMode, GhaCache, S3Cache};
+
+    // Bare references keep meaning a registry cache
+    assert_eq!(
+        CacheImport::parse("myapp:cache").unwrap(),
+        CacheImport::registry("myapp:cache")
+    );
+    // The reference is the ref attribute, not the whole spec
+    assert_eq!(
+        CacheImport::parse("type=registry,ref=myapp:cache").unwrap(),
+        CacheImport::registry("myapp:cache")
+    );
+    assert_eq!(
+        CacheExport::parse("type=inline").unwrap(),
+        CacheExport::Inline
+    );
+    assert_eq!(
+        CacheExport::parse("type=local,dest=/cache,mode=max").unwrap(),
+        CacheExport::local("/cache")
+    );
+    assert_eq!(
+        CacheImport::parse("type=s3,bucket=ci,region=us-east-1").unwrap(),
+        CacheImport::S3(S3Cache::new("ci", "us-east-1"))
+    );
+    assert_eq!(
+        CacheExport::parse("type=gha,scope=app,mode=max").unwrap(),
+        CacheExport::Gha(GhaCache::new().scope("app"))
+    );
+
+    // Export options wrap the typed variant
+    assert_eq!(
+        CacheExport::parse("type=registry,ref=myapp:cache,mode=min").unwrap(),
+        CacheExport::registry("myapp:cache").mode(CacheMode::Min)
+    );
+
+    // Attributes the typed variants do not model are kept verbatim
+    let export = CacheExport::parse("type=registry,ref=myapp:cache,compression=zstd").unwrap();
+    assert_eq!(export.cache_type(), "registry");
+    assert!(matches!(export, CacheExport::Raw { .. }));
+    let entry = export.to_entry();
+    assert_eq!(entry.attrs["ref"], "myapp:cache");
+    assert_eq!(entry.attrs["compression"], "zstd");
+    assert!(matches!(
+        CacheExport::parse("type=local,dest=/cache,mode=all").unwrap(),
+        CacheExport::Raw { .. }
+    ));
+
+    let import = CacheImport::parse("type=custom,endpoint=http://cache:8080").unwrap();
+    assert_eq!(import.to_entry().r#type, "custom");
+    assert_eq!(import.to_entry().attrs["endpoint"], "http://cache:8080");
+
+    assert!(CacheImport::parse("ref=myapp:cache").is_err());
+    assert!(CacheExport::parse("type=registry,ref").is_err());
+}
+
+#[test]
+fn test_gha_cache_entries() {
+    use buildkit_client::cache::GhaCache;
+
+    let cache = GhaCache {
+        scope: Some("app".to_string()),
+        url: Some("https://cache.actions.example/".to_string()),
+        url_v2: None,
+        token: Some("runtime-token".to_string()),
+    };
+    let export = CacheExport::Gha(cache.clone()).to_entry();
+    assert_eq!(export.r#type, "gha");
+    assert_eq!(export.attrs["scope"], "app");
+    assert_eq!(export.attrs["url"], "https://cache.actions.example/");
+    assert_eq!(export.attrs["token"], "runtime-token");
+    assert_eq!(export.attrs["mode"], "max");
+    assert!(!export.attrs.contains_key("url_v2"));
+
+    assert!(!format!("{:?}", cache).contains("runtime-token"));
+}
+
+#[test]
+fn test_cache_export_options() {
+    use buildkit_client::cache::{CacheExportOptions, CacheMode};
+
+    let export = CacheExport::registry("myapp:cache")
+        .mode(CacheMode::Min)
+        .ignore_error(true)
+        .oci_mediatypes(true);
+    assert_eq!(export.cache_type(), "registry");
+    assert_eq!(export.backend(), &CacheExport::registry("myapp:cache"));
+    let entry = export.to_entry();
+    assert_eq!(entry.attrs["ref"], "myapp:cache");
+    assert_eq!(entry.attrs["mode"], "min");
+    assert_eq!(entry.attrs["ignore-error"], "true");
+    assert_eq!(entry.attrs["oci-mediatypes"], "true");
+
+    // Setting options again updates them instead of nesting
+    let export = export.mode(CacheMode::Max).ignore_error(false);
+    assert_eq!(
+        export.options(),
+        CacheExportOptions {
+            mode: Some(CacheMode::Max),
+            ignore_error: false,
+            oci_mediatypes: Some(true),
+        }
+    );
+    assert_eq!(export.backend(), &CacheExport::registry("myapp:cache"));
+    assert!(!export.to_entry().attrs.contains_key("ignore-error"));
+
+    // Default options leave the export plain
+    assert_eq!(
+        CacheExport::local("/cache").with_options(CacheExportOptions::default()),
+        CacheExport::local("/cache")
+    );
+
+    let parsed = CacheExport::parse("type=local,dest=/cache,ignore-error=true").unwrap();
+    assert_eq!(parsed, CacheExport::local("/cache").ignore_error(true));
+    assert_eq!(parsed.to_entry().attrs["mode"], "max");
+
+    assert_eq!("min".parse::<CacheMode>().unwrap(), CacheMode::Min);
+    assert!("all".parse::<CacheMode>().is_err());
+}
+
+#[test]
+fn test_cache_registry_refs() {
+    use buildkit_client::cache::CacheMode;
+    use buildkit_client::session::{AuthServer, RegistryAuthConfig};
+    use buildkit_client::RegistryAuth;
+
+    assert_eq!(
+        CacheImport::registry("cache.example.com/app:cache").registry_ref(),
+        Some("cache.example.com/app:cache")
+    );
+    assert_eq!(
+        CacheExport::registry("cache.example.com/app:cache")
+            .mode(CacheMode::Min)
+            .registry_ref(),
+        Some("cache.example.com/app:cache")
+    );
+    assert_eq!(
+        CacheExport::parse("type=registry,ref=ghcr.io/app:cache,compression=zstd")
+            .unwrap()
+            .registry_ref(),
+        Some("ghcr.io/app:cache")
+    );
+    assert_eq!(CacheImport::local("/cache").registry_ref(), None);
+    assert_eq!(CacheExport::Inline.registry_ref(), None);
+
+    let mut auth = AuthServer::new();
+    assert!(auth.is_empty());
+    auth.add_registry(RegistryAuthConfig {
+        host: "ghcr.io".to_string(),
+        username: "user".to_string(),
+        password: "token".to_string(),
+        identity_token: None,
+    });
+    assert!(!auth.is_empty());
+    assert!(auth.has_credentials("ghcr.io"));
+    assert!(!auth.has_credentials("cache.example.com"));
+
+    let config = BuildConfig::local(".")
+        .cache_registry_auth(RegistryAuth {
+            host: "cache.example.com".to_string(),
+            username: "user".to_string(),
+            password: "token".to_string(),
+            identity_token: None,
+        })
+        .cache_export(CacheExport::registry("cache.example.com/app:cache"));
+    assert_eq!(config.cache_registry_auths.len(), 1);
+    assert_eq!(config.cache_registry_auths[0].host, "cache.example.com");
+}
@@ -0,0 +1,285 @@
+//! Tests for build contexts served without a local directory
+
+use buildkit_client::session::upload::upload_url;
+use buildkit_client::session::{list_context, FileSyncServer, Session, UploadServer};
+use buildkit_client::{
+    BuildConfig, ContextEntry, ContextProvider, ContextSource, DockerfileSource, Error, FileEntry,
+    FrontendAttrs, TarContext, Upload,
+};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+fn memory(files: &[(&str, &str)]) -> HashMap<PathBuf, FileEntry> {
+    files
+        .iter()
+        .map(|(path, content)| (PathBuf::from(path), FileEntry::from(*content)))
+        .collect()
+}
+
+fn paths(entries: &[ContextEntry]) -> Vec<&str> {
+    entries.iter().map(|entry| entry.path.as_str()).collect()
+}
+
+#[test]
+fn test_file_entry_modes() {
+    assert_eq!(FileEntry::new("x").mode, 0o644);
+    assert_eq!(FileEntry::executable("#!/bin/sh\n").mode, 0o755);
+    // File type bits are dropped
+    assert_eq!(FileEntry::new("x").with_mode(0o100600).mode, 0o600);
+}
+
+#[tokio::test]
+async fn test_list_memory_context_in_fsutil_order() {
+    let files = memory(&[
+        ("src/main.rs", "fn main() {}"),
+        ("Dockerfile", "FROM rust"),
+        ("src-extra/a", "a"),
+        ("./config/app.toml", "port = 80"),
+        ("src/bin/tool.rs", "fn main() {}"),
+    ]);
+    let entries = list_context(&files).await.unwrap();
+
+    // Depth-first, each directory sorted by name, parents created
+    assert_eq!(
+        paths(&entries),
+        vec![
+            "Dockerfile",
+            "config",
+            "config/app.toml",
+            "src",
+            "src/bin",
+            "src/bin/tool.rs",
+            "src/main.rs",
+            "src-extra",
+            "src-extra/a",
+        ]
+    );
+    assert!(entries[1].is_dir());
+    assert_eq!(entries[1].mode, 0o040755);
+    assert!(!entries[0].is_dir());
+    assert_eq!(entries[0].mode, 0o100644);
+    assert_eq!(&entries[0].content[..], b"FROM rust");
+}
+
+#[tokio::test]
+async fn test_list_context_rejects_escaping_paths() {
+    for path in ["../secret", "/etc/passwd", "a/../../b", ""] {
+        let files = memory(&[(path, "x")]);
+        assert!(
+            list_context(&files).await.is_err(),
+            "{:?} was accepted",
+            path
+        );
+    }
+}
+
+#[derive(Debug)]
+struct Conflicting;
+
+#[tonic::async_trait]
+impl ContextProvider for Conflicting {
+    async fn entries(&self) -> buildkit_client::Result<Vec<ContextEntry>> {
+        Ok(vec![
+            ContextEntry::file("app", 0o644, "binary"),
+            ContextEntry::file("app/config", 0o644, "conf"),
+        ])
+    }
+}
+
+#[derive(Debug)]
+struct WithDirs;
+
+#[tonic::async_trait]
+impl ContextProvider for WithDirs {
+    async fn entries(&self) -> buildkit_client::Result<Vec<ContextEntry>> {
+        Ok(vec![
+            ContextEntry::file("etc/motd", 0o644, "hi"),
+            ContextEntry::dir("etc"),
+            ContextEntry::dir("empty"),
+        ])
+    }
+}
+
+#[tokio::test]
+async fn test_list_custom_provider() {
+    assert!(list_context(&Conflicting).await.is_err());
+
+    let entries = list_context(&WithDirs).await.unwrap();
+    assert_eq!(paths(&entries), vec!["empty", "etc", "etc/motd"]);
+}
+
+#[tokio::test]
+async fn test_build_config_memory() {
+    let config = BuildConfig::memory(memory(&[("Dockerfile", "FROM alpine")]));
+    match &config.source {
+        DockerfileSource::Context {
+            context: ContextSource::Memory(files),
+            dockerfile_path,
+        } => {
+            assert_eq!(files.len(), 1);
+            assert_eq!(dockerfile_path, &None);
+        }
+        _ => panic!("Expected Context source"),
+    }
+
+    let config = BuildConfig::memory(memory(&[("build/Dockerfile.ci", "FROM alpine")]))
+        .dockerfile("build/Dockerfile.ci");
+    match &config.source {
+        DockerfileSource::Context {
+            context,
+            dockerfile_path,
+        } => {
+            assert_eq!(dockerfile_path.as_deref(), Some("build/Dockerfile.ci"));
+            let dockerfile = context.read_file("build/Dockerfile.ci").await.unwrap();
+            assert_eq!(dockerfile.as_deref(), Some(&b"FROM alpine"[..]));
+            assert_eq!(context.read_file("Dockerfile").await.unwrap(), None);
+        }
+        _ => panic!("Expected Context source"),
+    }
+
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.get("filename"), Some("build/Dockerfile.ci"));
+}
+
+#[test]
+fn test_filesync_server_provider() {
+    let mut server = FileSyncServer::new(PathBuf::new());
+    assert!(server.provider().is_none());
+
+    let files = memory(&[("Dockerfile", "FROM alpine")]);
+    server.set_provider(Arc::new(files));
+    assert!(server.provider().is_some());
+
+    // Named directories are still served from disk
+    server.add_dir("assets", std::env::temp_dir());
+    assert!(server.has_dir(Some("assets")));
+    assert!(!server.has_dir(Some("context")));
+    assert!(!server.has_dir(None));
+}
+
+fn tar_archive() -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut append = |path: &str, entry_type: tar::EntryType, mode: u32, content: &[u8]| {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_mode(mode);
+        header.set_size(content.len() as u64);
+        builder.append_data(&mut header, path, content).unwrap();
+    };
+    append("./", tar::EntryType::Directory, 0o755, b"");
+    append(
+        "./Dockerfile",
+        tar::EntryType::Regular,
+        0o644,
+        b"FROM alpine\n",
+    );
+    append("./bin/", tar::EntryType::Directory, 0o700, b"");
+    append("./bin/run", tar::EntryType::Regular, 0o755, b"#!/bin/sh\n");
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Symlink);
+    header.set_size(0);
+    builder
+        .append_link(&mut header, "./latest", "bin/run")
+        .unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Link);
+    header.set_mode(0o755);
+    header.set_size(0);
+    builder
+        .append_link(&mut header, "./bin/start", "./bin/run")
+        .unwrap();
+    builder.into_inner().unwrap()
+}
+
+#[tokio::test]
+async fn test_tar_context_entries() {
+    let archive = TarContext::from_reader(std::io::Cursor::new(tar_archive()));
+    let entries = list_context(&archive).await.unwrap();
+    assert_eq!(
+        paths(&entries),
+        vec!["Dockerfile", "bin", "bin/run", "bin/start", "latest"]
+    );
+    assert_eq!(entries[1].mode, 0o040700);
+    assert_eq!(entries[2].mode, 0o100755);
+    // Hardlinks become copies of their target
+    assert_eq!(&entries[3].content[..], b"#!/bin/sh\n");
+    assert_eq!(entries[4].linkname, "bin/run");
+    assert!(!entries[4].is_file() && !entries[4].is_dir());
+
+    // Entries are kept once the stream was read
+    assert_eq!(list_context(&archive).await.unwrap(), entries);
+}
+
+#[tokio::test]
+async fn test_tar_context_from_gzip_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("context.tar.gz");
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    std::io::Write::write_all(&mut encoder, &tar_archive()).unwrap();
+    std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+
+    let config = BuildConfig::tar(&path);
+    match &config.source {
+        DockerfileSource::Context {
+            context: context @ ContextSource::Tar(archive),
+            ..
+        } => {
+            assert_eq!(archive.path(), Some(path.as_path()));
+            let dockerfile = context.read_file("Dockerfile").await.unwrap();
+            assert_eq!(dockerfile.as_deref(), Some(&b"FROM alpine\n"[..]));
+            // Only regular files are read
+            assert_eq!(context.read_file("bin").await.unwrap(), None);
+        }
+        _ => panic!("Expected Tar source"),
+    }
+}
+
+#[tokio::test]
+async fn test_tar_context_errors() {
+    let missing = TarContext::open("/nonexistent/context.tar");
+    assert!(matches!(
+        list_context(&missing).await,
+        Err(Error::ContextFileRead { .. })
+    ));
+
+    let garbage = TarContext::from_reader(std::io::Cursor::new(vec![0xffu8; 1024]));
+    assert!(list_context(&garbage).await.is_err());
+}
+
+#[test]
+fn test_upload_server_urls() {
+    let mut uploads = UploadServer::new();
+    assert!(uploads.is_empty());
+    let url = uploads.add(
+        "ctx-1",
+        Arc::new(Upload::new(std::io::Cursor::new(b"tarball".to_vec()))),
+    );
+    assert_eq!(url, "http://buildkit-session/ctx-1");
+    assert_eq!(upload_url("ctx-1"), url);
+    assert!(!uploads.is_empty());
+
+    let session = Session::new();
+    let methods = &session.metadata()["X-Docker-Expose-Session-Grpc-Method"];
+    assert!(methods.contains(&"/moby.upload.v1.Upload/Pull".to_string()));
+
+    let upload = Upload::new(std::io::Cursor::new(b"tarball".to_vec()));
+    assert_eq!(format!("{:?}", upload), "Upload(<stream>)");
+}
+
+#[test]
+fn test_build_config_upload() {
+    let config = BuildConfig::upload(tokio::io::empty()).dockerfile("docker/Dockerfile");
+    match &config.source {
+        DockerfileSource::Upload {
+            dockerfile_path, ..
+        } => assert_eq!(dockerfile_path.as_deref(), Some("docker/Dockerfile")),
+        _ => panic!("Expected Upload source"),
+    }
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.get("filename"), Some("docker/Dockerfile"));
+
+    // The Dockerfile comes from the uploaded tarball
+    let config = BuildConfig::upload(tokio::io::empty()).inline_dockerfile("FROM alpine\n");
+    assert!(FrontendAttrs::from_config(&config).is_err());
+}
@@ -0,0 +1,43 @@
+//! Tests for the LLB graph used to copy images
+
+use buildkit_client::copy::{image_definition, IMAGE_SOURCE_SCHEME};
+use buildkit_client::proto::pb;
+use prost::Message;
+use sha2::{Digest, Sha256};
+
+#[test]
+fn test_image_definition() {
+    let definition = image_definition("registry.example.com/app:v1").unwrap();
+    assert_eq!(definition.def.len(), 2);
+
+    let source = pb::Op::decode(definition.def[0].as_slice()).unwrap();
+    match source.op {
+        Some(pb::op::Op::Source(op)) => assert_eq!(
+            op.identifier,
+            format!("{}registry.example.com/app:v1", IMAGE_SOURCE_SCHEME)
+        ),
+        other => panic!("expected a source op, got {:?}", other),
+    }
+
+    // The terminal op points at the source by the digest of its encoding
+    let digest = format!("sha256:{:x}", Sha256::digest(&definition.def[0]));
+    let terminal = pb::Op::decode(definition.def[1].as_slice()).unwrap();
+    assert!(terminal.op.is_none());
+    assert_eq!(terminal.inputs.len(), 1);
+    assert_eq!(terminal.inputs[0].digest, digest);
+    assert_eq!(terminal.inputs[0].index, 0);
+
+    assert_eq!(
+        definition.metadata[&digest].caps.get("source.image"),
+        Some(&true)
+    );
+}
+
+#[test]
+fn test_image_definition_rejects_invalid_refs() {
+    for image in ["", "docker-image://alpine", "alpine latest"] {
+        assert!(image_definition(image).is_err(), "{:?}", image);
+    }
+    // Short names are normalized by BuildKit
+    assert!(image_definition("alpine").is_ok());
+}
@@ -0,0 +1,123 @@
+//! Tests for debugging failed steps
+
+use buildkit_client::debug::{DebugState, FailedStep, ShellDebugger, SOLVE_ERROR_TYPE};
+use buildkit_client::proto::errdefs;
+use buildkit_client::proto::google::rpc::Status;
+use buildkit_client::proto::pb;
+use prost::Message;
+
+const FAILED: &str = "process \"/bin/sh -c make\" did not complete successfully: exit code: 2";
+
+fn run_step() -> pb::Op {
+    pb::Op {
+        op: Some(pb::op::Op::Exec(pb::ExecOp {
+            meta: Some(pb::Meta {
+                args: vec!["/bin/sh".to_string(), "-c".to_string(), "make".to_string()],
+                cwd: "/src".to_string(),
+                ..Default::default()
+            }),
+            mounts: vec![
+                pb::Mount {
+                    input: 0,
+                    dest: "/".to_string(),
+                    output: 0,
+                    ..Default::default()
+                },
+                pb::Mount {
+                    input: 1,
+                    dest: "/src".to_string(),
+                    output: -1,
+                    readonly: true,
+                    ..Default::default()
+                },
+                pb::Mount {
+                    input: -1,
+                    dest: "/tmp".to_string(),
+                    output: -1,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+}
+
+fn solve_error(op: pb::Op) -> tonic::Status {
+    let solve = errdefs::Solve {
+        input_i_ds: vec!["base".to_string(), "context".to_string()],
+        mount_i_ds: vec![
+            "root-after".to_string(),
+            "context".to_string(),
+            "tmp-after".to_string(),
+        ],
+        op: Some(op),
+        ..Default::default()
+    };
+    let details = Status {
+        code: tonic::Code::Unknown as i32,
+        message: FAILED.to_string(),
+        details: vec![prost_types::Any {
+            type_url: format!("type.googleapis.com/{}", SOLVE_ERROR_TYPE),
+            value: solve.encode_to_vec(),
+        }],
+    };
+    tonic::Status::with_details(tonic::Code::Unknown, FAILED, details.encode_to_vec().into())
+}
+
+#[test]
+fn test_failed_step_from_status() {
+    let step = FailedStep::from_status(&solve_error(run_step())).unwrap();
+    assert_eq!(step.message, FAILED);
+    assert_eq!(step.args(), ["/bin/sh", "-c", "make"]);
+    assert_eq!(step.input_ids, ["base", "context"]);
+    assert_eq!(step.mount_ids.len(), 3);
+    assert!(step.exec.is_some());
+
+    // Errors without a failed step
+    assert!(FailedStep::from_status(&tonic::Status::unknown(FAILED)).is_none());
+}
+
+#[test]
+fn test_failed_step_mounts() {
+    let step = FailedStep::from_status(&solve_error(run_step())).unwrap();
+
+    let failed = step.mounts(DebugState::Failed).unwrap();
+    let results: Vec<&str> = failed
+        .iter()
+        .map(|mount| mount.result_id.as_str())
+        .collect();
+    assert_eq!(results, ["root-after", "context", "tmp-after"]);
+    assert_eq!(failed[1].dest, "/src");
+    assert!(failed[1].readonly);
+
+    // Scratch mounts start out empty
+    let initial = step.mounts(DebugState::Initial).unwrap();
+    let results: Vec<&str> = initial
+        .iter()
+        .map(|mount| mount.result_id.as_str())
+        .collect();
+    assert_eq!(results, ["base", "context", ""]);
+}
+
+#[test]
+fn test_failed_step_not_a_run_step() {
+    let source = pb::Op {
+        op: Some(pb::op::Op::Source(pb::SourceOp {
+            identifier: "docker-image://docker.io/library/alpine:3.20".to_string(),
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    let step = FailedStep::from_status(&solve_error(source)).unwrap();
+    assert!(step.exec.is_none());
+    assert!(step.args().is_empty());
+    assert!(step.mounts(DebugState::Failed).is_err());
+}
+
+#[test]
+fn test_shell_debugger_defaults() {
+    let debugger = ShellDebugger::default();
+    assert_eq!(debugger.shell, ["/bin/sh"]);
+    assert_eq!(debugger.state, DebugState::Failed);
+}
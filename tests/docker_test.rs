@@ -0,0 +1,114 @@
+//! Unit tests for loading exported images into a Docker daemon
+//!
+//! A fake daemon listening on a unix socket stands in for dockerd.
+
+#![cfg(unix)]
+
+use buildkit_client::docker::DockerLoader;
+use buildkit_client::Output;
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixListener;
+
+/// Accept one request, returning the raw request bytes and replying with `response`
+async fn fake_daemon(listener: UnixListener, response: &'static [u8]) -> Vec<u8> {
+    let (mut stream, _) = listener.accept().await.unwrap();
+    let mut request = Vec::new();
+    let mut buf = [0u8; 4096];
+    while !request.ends_with(b"0\r\n\r\n") {
+        let n = stream.read(&mut buf).await.unwrap();
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    stream.write_all(response).await.unwrap();
+    stream.shutdown().await.unwrap();
+    request
+}
+
+#[tokio::test]
+async fn test_docker_load_streams_tarball() {
+    let dir = TempDir::new().unwrap();
+    let socket = dir.path().join("docker.sock");
+    let listener = UnixListener::bind(&socket).unwrap();
+    let daemon = tokio::spawn(fake_daemon(
+        listener,
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\nLoaded image: app:dev\n",
+    ));
+
+    let (output, load) = DockerLoader::with_socket(&socket).output("app:dev");
+    assert_eq!(output.exporter_type(), "docker");
+    assert_eq!(output.attrs().get("name"), Some(&"app:dev".to_string()));
+
+    let mut writer = output.archive_dest().unwrap().open().await.unwrap();
+    writer.write_all(b"fake tarball").await.unwrap();
+    writer.shutdown().await.unwrap();
+    drop(writer);
+
+    assert_eq!(load.wait().await.unwrap(), "Loaded image: app:dev");
+
+    let request = String::from_utf8(daemon.await.unwrap()).unwrap();
+    assert!(request.starts_with("POST /images/load"));
+    assert!(request.contains("fake tarball"));
+}
+
+#[tokio::test]
+async fn test_docker_load_tags_every_name() {
+    let dir = TempDir::new().unwrap();
+    let socket = dir.path().join("docker.sock");
+    let listener = UnixListener::bind(&socket).unwrap();
+    let daemon = tokio::spawn(fake_daemon(
+        listener,
+        b"HTTP/1.1 200 OK\r\n\r\nLoaded image: app:dev\nLoaded image: app:latest\n",
+    ));
+
+    let (output, load) =
+        DockerLoader::with_socket(&socket).tagged_output(["app:dev", "app:latest"]);
+    assert_eq!(output.attrs()["name"], "app:dev,app:latest");
+
+    let mut writer = output.archive_dest().unwrap().open().await.unwrap();
+    writer.write_all(b"fake tarball").await.unwrap();
+    writer.shutdown().await.unwrap();
+    drop(writer);
+
+    let loaded = load.wait().await.unwrap();
+    assert!(loaded.ends_with("Loaded image: app:latest"), "{}", loaded);
+    daemon.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_docker_load_reports_daemon_error() {
+    let dir = TempDir::new().unwrap();
+    let socket = dir.path().join("docker.sock");
+    let listener = UnixListener::bind(&socket).unwrap();
+    let daemon = tokio::spawn(fake_daemon(
+        listener,
+        b"HTTP/1.1 500 Internal Server Error\r\nTransfer-Encoding: chunked\r\n\r\n9\r\nbad input\r\n0\r\n\r\n",
+    ));
+
+    let (output, load) = DockerLoader::with_socket(&socket).output("app:dev");
+    let writer = output.archive_dest().unwrap().open().await.unwrap();
+    drop(writer);
+
+    let err = load.wait().await.unwrap_err().to_string();
+    assert!(err.contains("500"));
+    assert!(err.contains("bad input"));
+    daemon.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_docker_load_missing_socket() {
+    let dir = TempDir::new().unwrap();
+    let (output, load) = DockerLoader::with_socket(dir.path().join("missing.sock")).output("app:dev");
+    drop(output);
+
+    assert!(load.wait().await.is_err());
+}
+
+#[test]
+fn test_output_parse_docker() {
+    let output = Output::parse("type=docker,dest=image.tar,name=app:v1").unwrap();
+    assert_eq!(output.exporter_type(), "docker");
+    assert!(output.archive_dest().is_some());
+}
@@ -0,0 +1,70 @@
+//! Tests for .dockerignore pattern matching
+
+use buildkit_client::IgnorePatterns;
+
+#[test]
+fn test_dockerignore_parse() {
+    let patterns = IgnorePatterns::parse(
+        "\u{feff}# build output\n\
+         target/\n\
+         \n\
+         /node_modules\n\
+         \x20 *.log  \n\
+         ./docs/../tmp\n",
+    );
+
+    assert!(patterns.is_ignored("target"));
+    assert!(patterns.is_ignored("target/debug/app"));
+    assert!(patterns.is_ignored("node_modules/react/index.js"));
+    assert!(patterns.is_ignored("build.log"));
+    assert!(patterns.is_ignored("tmp/cache"));
+
+    // Patterns are anchored at the context root and `*` stays in one component
+    assert!(!patterns.is_ignored("src/target"));
+    assert!(!patterns.is_ignored("logs/build.log"));
+    assert!(!patterns.is_ignored("docs"));
+    assert!(!patterns.is_ignored("# build output"));
+
+    assert!(IgnorePatterns::parse("# only a comment\n\n").is_empty());
+}
+
+#[test]
+fn test_dockerignore_wildcards() {
+    let patterns = IgnorePatterns::new(["**/*.pyc", "cache/**", "file?.[a-c]", "data/[!x]*"]);
+
+    assert!(patterns.is_ignored("main.pyc"));
+    assert!(patterns.is_ignored("app/lib/mod.pyc"));
+    assert!(patterns.is_ignored("cache/a/b"));
+    assert!(!patterns.is_ignored("cache"));
+    assert!(patterns.is_ignored("file1.b"));
+    assert!(!patterns.is_ignored("file10.b"));
+    assert!(!patterns.is_ignored("file1.d"));
+    assert!(patterns.is_ignored("data/set"));
+    assert!(!patterns.is_ignored("data/xset"));
+}
+
+#[test]
+fn test_dockerignore_exceptions() {
+    let patterns = IgnorePatterns::parse("*.md\n!README.md\nnode_modules\n!node_modules/keep\n");
+
+    assert!(patterns.is_ignored("CHANGELOG.md"));
+    assert!(!patterns.is_ignored("README.md"));
+    assert!(patterns.is_ignored("node_modules/left-pad/index.js"));
+    assert!(!patterns.is_ignored("node_modules/keep"));
+    assert!(!patterns.is_ignored("node_modules/keep/index.js"));
+
+    // The last matching pattern wins
+    let reversed = IgnorePatterns::new(["!README.md", "*.md"]);
+    assert!(reversed.is_ignored("README.md"));
+}
+
+#[test]
+fn test_dockerignore_read() {
+    let dir = tempfile::TempDir::new().unwrap();
+    assert!(IgnorePatterns::read(dir.path()).unwrap().is_empty());
+
+    std::fs::write(dir.path().join(".dockerignore"), ".git\n").unwrap();
+    let patterns = IgnorePatterns::read(dir.path()).unwrap();
+    assert!(patterns.is_ignored(".git/config"));
+    assert!(!patterns.is_ignored(".github/workflows"));
+}
@@ -0,0 +1,133 @@
+//! Unit tests for gRPC frame reassembly on the session tunnel
+
+use buildkit_client::session::frame::{
+    encode_frame, grpc_message, FrameDecoder, FrameLimits, FRAME_HEADER_LEN,
+};
+
+#[test]
+fn test_frame_roundtrip() {
+    let framed = encode_frame(b"hello");
+    assert_eq!(framed.len(), FRAME_HEADER_LEN + 5);
+    assert_eq!(&framed[..FRAME_HEADER_LEN], &[0, 0, 0, 0, 5]);
+
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&framed).unwrap();
+    let frame = decoder.decode().unwrap().unwrap();
+    assert!(!frame.compressed);
+    assert_eq!(&frame.payload[..], b"hello");
+    assert!(decoder.decode().unwrap().is_none());
+    assert!(decoder.finish().is_ok());
+}
+
+#[test]
+fn test_frame_split_across_chunks() {
+    let mut stream = encode_frame(b"first").to_vec();
+    stream.extend_from_slice(&encode_frame(b""));
+    stream.extend_from_slice(&encode_frame(b"third"));
+
+    // One byte at a time splits headers as well as payloads
+    let mut decoder = FrameDecoder::new();
+    let mut payloads = Vec::new();
+    for byte in &stream {
+        decoder.push(std::slice::from_ref(byte)).unwrap();
+        while let Some(frame) = decoder.decode().unwrap() {
+            payloads.push(frame.payload.to_vec());
+        }
+    }
+
+    assert_eq!(
+        payloads,
+        vec![b"first".to_vec(), Vec::new(), b"third".to_vec()]
+    );
+    assert_eq!(decoder.buffered(), 0);
+}
+
+#[test]
+fn test_frame_truncated() {
+    let framed = encode_frame(b"truncated payload");
+
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&framed[..framed.len() - 1]).unwrap();
+    assert!(decoder.decode().unwrap().is_none());
+    assert!(decoder.finish().is_err());
+
+    // A partial header is also left over
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&framed[..3]).unwrap();
+    assert!(decoder.decode().unwrap().is_none());
+    assert!(decoder.finish().is_err());
+}
+
+#[test]
+fn test_frame_oversized_length() {
+    // A length beyond the data (but within limits) waits for more input
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&[0, 0, 0xff, 0xff, 0xff, 1, 2, 3]).unwrap();
+    assert!(decoder.decode().unwrap().is_none());
+    assert_eq!(decoder.buffered(), 8);
+    assert!(decoder.finish().is_err());
+}
+
+#[test]
+fn test_frame_compression_flag() {
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&[1, 0, 0, 0, 1, 42]).unwrap();
+    let frame = decoder.decode().unwrap().unwrap();
+    assert!(frame.compressed);
+    assert_eq!(&frame.payload[..], &[42]);
+
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&[7, 0, 0, 0, 0]).unwrap();
+    assert!(decoder.decode().is_err());
+}
+
+#[test]
+fn test_frame_size_limit() {
+    let limits = FrameLimits {
+        max_frame_size: 16,
+        max_buffered: 1024,
+    };
+
+    // Rejected as soon as the header arrives, before the payload is buffered
+    let mut decoder = FrameDecoder::with_limits(limits);
+    decoder.push(&[0, 0, 0, 0, 17]).unwrap();
+    assert!(decoder.decode().is_err());
+
+    let mut decoder = FrameDecoder::with_limits(limits);
+    decoder.push(&encode_frame(&[0; 16])).unwrap();
+    assert_eq!(decoder.decode().unwrap().unwrap().payload.len(), 16);
+
+    // A hostile length prefix cannot request a 4 GiB allocation
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&[0, 0xff, 0xff, 0xff, 0xff]).unwrap();
+    assert!(decoder.decode().is_err());
+}
+
+#[test]
+fn test_frame_buffer_limit() {
+    let limits = FrameLimits {
+        max_frame_size: 1024,
+        max_buffered: 64,
+    };
+    let mut decoder = FrameDecoder::with_limits(limits);
+
+    decoder.push(&[0, 0, 0, 4, 0]).unwrap();
+    decoder.push(&[0; 59]).unwrap();
+    assert_eq!(decoder.buffered(), 64);
+    assert!(decoder.decode().unwrap().is_none());
+
+    // The chunk that would overflow is rejected and not buffered
+    assert!(decoder.push(&[0]).is_err());
+    assert_eq!(decoder.buffered(), 64);
+}
+
+#[test]
+fn test_grpc_message_encoding() {
+    assert_eq!(grpc_message("not found"), "not found");
+
+    // Newlines, non-ASCII and '%' are percent-encoded instead of panicking
+    assert_eq!(
+        grpc_message("copy failed:\n50% of ünïcode"),
+        "copy failed:%0A50%25 of %C3%BCn%C3%AFcode"
+    );
+}
@@ -0,0 +1,476 @@
+//! Golden tests for frontend attribute construction
+//!
+//! Each file in `tests/golden/frontend_attrs` holds the attributes `buildctl`
+//! sends for the command in its header comment.
+
+use buildkit_client::frontend::syntax_directive;
+use buildkit_client::{
+    BuildConfig, Error, Frontend, FrontendAttrs, ImageResolveMode, LabsFeature, NamedContext,
+    NetworkMode, Platform, ProvenanceMode,
+};
+
+fn golden(name: &str) -> String {
+    let path = format!(
+        "{}/tests/golden/frontend_attrs/{}.txt",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", path, e))
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+#[test]
+fn test_frontend_attrs_minimal() {
+    let attrs = FrontendAttrs::from_config(&BuildConfig::local(".")).unwrap();
+    assert_eq!(attrs.to_string(), golden("minimal"));
+}
+
+#[test]
+fn test_frontend_attrs_full() {
+    let config = BuildConfig::local(".")
+        .dockerfile("docker/Dockerfile.prod")
+        .build_arg("VERSION", "1.2.0")
+        .build_arg("COMMIT", "abc123")
+        .target("release")
+        .platform(Platform::linux_arm64().with_variant("v8"))
+        .no_cache(true)
+        .pull(true)
+        .provenance(ProvenanceMode::Max);
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.to_string(), golden("full"));
+
+    // Rendering does not depend on insertion order
+    let again = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs, again);
+
+    // Logs keep build argument names but not their values
+    let redacted = attrs.redacted().to_string();
+    assert!(redacted.contains("build-arg:COMMIT=<redacted>\n"));
+    assert!(!redacted.contains("abc123"));
+    assert!(redacted.contains("target=release\n"));
+}
+
+#[test]
+fn test_frontend_attrs_github() {
+    let config = BuildConfig::github("https://github.com/user/repo")
+        .dockerfile("build/Dockerfile")
+        .platform(Platform::linux_arm64());
+    let mut attrs = FrontendAttrs::from_config(&config).unwrap();
+    attrs
+        .context("https://github.com/user/repo.git#main")
+        .unwrap();
+    assert_eq!(attrs.to_string(), golden("github"));
+}
+
+#[test]
+fn test_frontend_attrs_bind_contexts() {
+    let config = BuildConfig::local(".")
+        .bind_context("vendor", "../vendor")
+        .bind_context("assets", "./assets");
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.to_string(), golden("bind_contexts"));
+
+    // The main context and Dockerfile names cannot be rebound
+    let config = BuildConfig::local(".").bind_context("dockerfile", "./other");
+    assert!(FrontendAttrs::from_config(&config).is_err());
+    let mut attrs = FrontendAttrs::new();
+    assert!(attrs.named_context("context", "local:context").is_err());
+    assert!(attrs.named_context("", "local:x").is_err());
+    assert!(attrs.named_context("my assets", "local:x").is_err());
+}
+
+#[test]
+fn test_frontend_attrs_inline_dockerfile() {
+    // The inline Dockerfile replaces any configured path
+    let config = BuildConfig::local(".")
+        .dockerfile("docker/Custom.Dockerfile")
+        .inline_dockerfile("FROM alpine:3.20\n");
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.to_string(), golden("minimal"));
+
+    let config = BuildConfig::github("https://github.com/org/app.git").inline_dockerfile("FROM x");
+    assert!(FrontendAttrs::from_config(&config).is_err());
+}
+
+#[test]
+fn test_frontend_attrs_named_contexts() {
+    let config = BuildConfig::local(".")
+        .named_context("vendor", NamedContext::Local("../vendor".into()))
+        .named_context("assets", "./assets".parse().unwrap());
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.to_string(), golden("bind_contexts"));
+
+    assert!("".parse::<NamedContext>().is_err());
+    let config = BuildConfig::local(".").named_context("context", NamedContext::Local(".".into()));
+    assert!(FrontendAttrs::from_config(&config).is_err());
+}
+
+#[test]
+fn test_frontend_attrs_remote_named_contexts() {
+    let digest = "sha256:5b0bcabd1ed22e9fb1310cf6c2dec7cdef19f0ad69efa1f392e94a4333501270";
+    let config = BuildConfig::local(".")
+        .named_context("alpine", NamedContext::image("alpine:3.20"))
+        .named_context(
+            "lib",
+            "https://github.com/org/lib.git#v1.2".parse().unwrap(),
+        )
+        .named_context(
+            "base",
+            format!("oci-layout://./base-layout:v1@{}", digest)
+                .parse()
+                .unwrap(),
+        );
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.to_string(), golden("named_contexts_remote"));
+
+    // The layout digest is resolved when the build starts
+    let config =
+        BuildConfig::local(".").named_context("base", NamedContext::oci_layout("./layout", "v1"));
+    assert!(FrontendAttrs::from_config(&config).is_err());
+    let config = BuildConfig::local(".").named_context("base", NamedContext::image("a b"));
+    assert!(FrontendAttrs::from_config(&config).is_err());
+}
+
+#[test]
+fn test_named_context_parse() {
+    let parse = |s: &str| s.parse::<NamedContext>().unwrap();
+    assert_eq!(
+        parse("docker-image://alpine:3.20"),
+        NamedContext::image("alpine:3.20")
+    );
+    assert_eq!(
+        parse("git@github.com:org/lib.git#main"),
+        NamedContext::git("git@github.com:org/lib.git#main")
+    );
+    assert_eq!(parse("../vendor"), NamedContext::Local("../vendor".into()));
+    assert_eq!(
+        parse("oci-layout:///srv/layouts/base"),
+        NamedContext::oci_layout("/srv/layouts/base", "latest")
+    );
+    assert_eq!(
+        parse("oci-layout://./base:v2@sha256:abc"),
+        NamedContext::OciLayout {
+            path: "./base".into(),
+            tag: "v2".to_string(),
+            digest: Some("sha256:abc".to_string()),
+        }
+    );
+    assert!("https://example.com/context.tar.gz"
+        .parse::<NamedContext>()
+        .is_err());
+}
+
+#[test]
+fn test_frontend_attrs_network() {
+    let config = BuildConfig::local(".").network(NetworkMode::None);
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.to_string(), golden("network"));
+
+    let config = BuildConfig::local(".").network(NetworkMode::Host);
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.get("force-network-mode"), Some("host"));
+
+    // The default mode leaves the choice to each step
+    let config = BuildConfig::local(".").network(NetworkMode::Default);
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.to_string(), golden("minimal"));
+
+    assert_eq!("none".parse::<NetworkMode>().unwrap(), NetworkMode::None);
+    assert!("bridge".parse::<NetworkMode>().is_err());
+}
+
+#[test]
+fn test_frontend_attrs_add_hosts() {
+    let config = BuildConfig::local(".")
+        .add_host("registry.internal", "10.0.0.5")
+        .add_host("db.internal", "fd00::5");
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.to_string(), golden("add_hosts"));
+
+    let config = BuildConfig::local(".").add_host("gateway.internal", "host-gateway");
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(
+        attrs.get("add-hosts"),
+        Some("gateway.internal=host-gateway")
+    );
+
+    for (host, ip) in [
+        ("registry.internal", "not-an-ip"),
+        ("", "10.0.0.5"),
+        ("a,b", "10.0.0.5"),
+        ("a=b", "10.0.0.5"),
+    ] {
+        let config = BuildConfig::local(".").add_host(host, ip);
+        assert!(
+            FrontendAttrs::from_config(&config).is_err(),
+            "{}={} should be rejected",
+            host,
+            ip
+        );
+    }
+}
+
+#[test]
+fn test_frontend_attrs_shm_size() {
+    let config = BuildConfig::local(".").shm_size(2 * 1024 * 1024 * 1024);
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.to_string(), golden("shm_size"));
+
+    let attrs = FrontendAttrs::from_config(&BuildConfig::local(".")).unwrap();
+    assert_eq!(attrs.get("shm-size"), None);
+}
+
+#[test]
+fn test_frontend_attrs_ulimit() {
+    let config = BuildConfig::local(".")
+        .ulimit("nproc=1024")
+        .ulimit("nofile=65536:65536")
+        .ulimit("nproc=4096:8192");
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.to_string(), golden("ulimit"));
+
+    let config = BuildConfig::local(".").ulimit("memlock=-1:-1");
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.get("ulimit"), Some("memlock=-1:-1"));
+
+    for ulimit in [
+        "nofile",
+        "files=1024",
+        "nofile=many",
+        "nofile=2048:1024",
+        "nofile=-1:1024",
+        "nofile=-2",
+    ] {
+        let config = BuildConfig::local(".").ulimit(ulimit);
+        assert!(FrontendAttrs::from_config(&config).is_err(), "{}", ulimit);
+    }
+}
+
+#[test]
+fn test_frontend_attrs_pinned_frontend() {
+    let config = BuildConfig::local(".").frontend(Frontend::dockerfile_labs());
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.to_string(), golden("frontend_labs"));
+    assert_eq!(config.frontend.name(), "gateway.v0");
+
+    let config = BuildConfig::local(".").frontend(Frontend::dockerfile_stable());
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.get("source"), Some("docker/dockerfile:1"));
+    assert!(!config.frontend.is_labs());
+
+    // The builtin frontend needs no source
+    let config = BuildConfig::local(".");
+    assert_eq!(config.frontend.name(), "dockerfile.v0");
+    assert_eq!(
+        FrontendAttrs::from_config(&config).unwrap().to_string(),
+        golden("minimal")
+    );
+
+    assert_eq!(
+        "labs".parse::<Frontend>().unwrap(),
+        Frontend::dockerfile_labs()
+    );
+    assert!("docker/dockerfile:1.7-labs@sha256:abc"
+        .parse::<Frontend>()
+        .unwrap()
+        .is_labs());
+    assert!(!"docker/dockerfile:1.7"
+        .parse::<Frontend>()
+        .unwrap()
+        .is_labs());
+}
+
+#[test]
+fn test_frontend_attrs_gateway_frontend() {
+    let config = BuildConfig::local(".")
+        .frontend(Frontend::gateway("ghcr.io/acme/mockerfile-frontend:0.3"))
+        .dockerfile("mockerfile.yaml");
+    assert_eq!(config.frontend.name(), "gateway.v0");
+    assert!(!config.frontend.is_dockerfile());
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(
+        attrs.get("source"),
+        Some("ghcr.io/acme/mockerfile-frontend:0.3")
+    );
+    assert_eq!(attrs.get("filename"), Some("mockerfile.yaml"));
+
+    // Build definitions of other frontends are not Dockerfiles
+    assert!(config
+        .frontend
+        .check_dockerfile("RUN --security=insecure make\n")
+        .is_ok());
+
+    assert_eq!(
+        "gateway=earthly/buildkitd-frontend:latest"
+            .parse::<Frontend>()
+            .unwrap(),
+        Frontend::Gateway("earthly/buildkitd-frontend:latest".to_string())
+    );
+    assert!("gateway=".parse::<Frontend>().is_err());
+    assert!("docker/dockerfile:1.7"
+        .parse::<Frontend>()
+        .unwrap()
+        .is_dockerfile());
+
+    let config = BuildConfig::local(".").frontend(Frontend::gateway(""));
+    assert!(FrontendAttrs::from_config(&config).is_err());
+}
+
+#[test]
+fn test_frontend_labs_feature_check() {
+    let dockerfile = "FROM alpine\n\
+        RUN --mount=type=secret,id=token,env=TOKEN \\\n    ./fetch.sh\n\
+        RUN --security=insecure ./privileged.sh\n";
+    assert_eq!(
+        LabsFeature::find(dockerfile),
+        vec![(2, LabsFeature::SecretEnv), (4, LabsFeature::RunSecurity)]
+    );
+
+    match Frontend::Builtin.check_dockerfile(dockerfile) {
+        Err(Error::LabsFeatureRequired { line, feature }) => {
+            assert_eq!(line, 2);
+            assert_eq!(feature, "RUN --mount=type=secret,env=");
+        }
+        other => panic!("expected LabsFeatureRequired, got {:?}", other),
+    }
+    assert!(Frontend::dockerfile_stable()
+        .check_dockerfile(dockerfile)
+        .is_err());
+    assert!(Frontend::dockerfile_labs()
+        .check_dockerfile(dockerfile)
+        .is_ok());
+
+    // The Dockerfile can opt in itself
+    let with_syntax = format!("# syntax=docker/dockerfile:1-labs\n{}", dockerfile);
+    assert_eq!(
+        syntax_directive(&with_syntax),
+        Some("docker/dockerfile:1-labs")
+    );
+    assert!(Frontend::Builtin.check_dockerfile(&with_syntax).is_ok());
+
+    // Directives after other content are plain comments
+    let late = format!(
+        "FROM alpine\n# syntax=docker/dockerfile:1-labs\n{}",
+        dockerfile
+    );
+    assert_eq!(syntax_directive(&late), None);
+    assert!(Frontend::Builtin.check_dockerfile(&late).is_err());
+
+    // Secret mounts without env are stable syntax
+    let stable = "FROM alpine\nRUN --mount=type=secret,id=token cat /run/secrets/token\n";
+    assert!(Frontend::Builtin.check_dockerfile(stable).is_ok());
+}
+
+#[test]
+fn test_frontend_attrs_cgroup_parent() {
+    let config = BuildConfig::local(".").cgroup_parent("/buildkit/team-a");
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.to_string(), golden("cgroup_parent"));
+
+    let config = BuildConfig::local(".").cgroup_parent("");
+    assert!(FrontendAttrs::from_config(&config).is_err());
+}
+
+#[test]
+fn test_frontend_attrs_hostname() {
+    let config = BuildConfig::local(".").build_hostname("legacy-builder");
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.to_string(), golden("hostname"));
+
+    let config = BuildConfig::local(".").build_hostname("build01.ci.example.com");
+    assert!(FrontendAttrs::from_config(&config).is_ok());
+
+    for hostname in ["", "-builder", "builder-", "build_01", "a..b", "has space"] {
+        let config = BuildConfig::local(".").build_hostname(hostname);
+        assert!(
+            FrontendAttrs::from_config(&config).is_err(),
+            "{:?}",
+            hostname
+        );
+    }
+}
+
+#[test]
+fn test_frontend_attrs_no_cache_filter() {
+    let config = BuildConfig::local(".").no_cache_filter(["assets", "tests", "assets"]);
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.get("no-cache"), Some("assets,tests"));
+
+    // Disabling the cache everywhere wins
+    let config = config.no_cache(true);
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.get("no-cache"), Some(""));
+
+    for stage in ["", "a,b"] {
+        let config = BuildConfig::local(".").no_cache_filter([stage]);
+        assert!(FrontendAttrs::from_config(&config).is_err(), "{:?}", stage);
+    }
+}
+
+#[test]
+fn test_frontend_attrs_image_resolve_mode() {
+    let attrs = FrontendAttrs::from_config(&BuildConfig::local(".")).unwrap();
+    assert_eq!(attrs.get("image-resolve-mode"), None);
+
+    let config = BuildConfig::local(".").image_resolve_mode(ImageResolveMode::PreferLocal);
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.get("image-resolve-mode"), Some("local"));
+
+    let config = BuildConfig::local(".").pull(true);
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.get("image-resolve-mode"), Some("pull"));
+
+    assert_eq!(
+        "local".parse::<ImageResolveMode>().unwrap(),
+        ImageResolveMode::PreferLocal
+    );
+    assert_eq!(
+        "pull".parse::<ImageResolveMode>().unwrap(),
+        ImageResolveMode::ForcePull
+    );
+    assert!("always".parse::<ImageResolveMode>().is_err());
+}
+
+#[test]
+fn test_frontend_attrs_raw() {
+    let config = BuildConfig::local(".")
+        .target("release")
+        .no_cache(true)
+        .frontend_attr("attest:sbom", "")
+        .frontend_attr("target", "debug")
+        .frontend_attr("build-arg:BUILDKIT_MULTI_PLATFORM", "1");
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.get("attest:sbom"), Some(""));
+    assert_eq!(attrs.get("build-arg:BUILDKIT_MULTI_PLATFORM"), Some("1"));
+    assert_eq!(attrs.get("no-cache"), Some(""));
+    // Explicit attributes win over the typed options
+    assert_eq!(attrs.get("target"), Some("debug"));
+
+    let config = BuildConfig::local(".").frontend_attr("has space", "1");
+    assert!(FrontendAttrs::from_config(&config).is_err());
+}
+
+#[test]
+fn test_frontend_attrs_validation() {
+    let mut attrs = FrontendAttrs::new();
+    assert!(attrs.set("", "value").is_err());
+    assert!(attrs.set("has space", "value").is_err());
+    assert!(attrs.set("key=value", "value").is_err());
+    assert!(attrs.set("target", "line\nbreak").is_err());
+    assert!(attrs.build_arg("", "value").is_err());
+    assert!(attrs.is_empty());
+
+    let config = BuildConfig::local(".").build_arg("BAD NAME", "1");
+    assert!(FrontendAttrs::from_config(&config).is_err());
+
+    // The default platform is not repeated when added explicitly
+    let config = BuildConfig::local(".")
+        .platform(Platform::linux_amd64())
+        .platform(Platform::linux_arm64());
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.get("platform"), Some("linux/amd64,linux/arm64"));
+}
@@ -0,0 +1,233 @@
+//! Unit tests for git credentials and client-side git checkouts
+
+use buildkit_client::git::{git_host, GitCheckout};
+use buildkit_client::{BuildConfig, DockerfileSource, FrontendAttrs, GitAuth, GitProxy};
+use std::path::Path;
+use std::process::Command;
+
+fn git(dir: &Path, args: &[&str]) -> bool {
+    Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .env("GIT_AUTHOR_NAME", "test")
+        .env("GIT_AUTHOR_EMAIL", "test@example.com")
+        .env("GIT_COMMITTER_NAME", "test")
+        .env("GIT_COMMITTER_EMAIL", "test@example.com")
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+#[test]
+fn test_git_proxy_config() {
+    let config = BuildConfig::github("https://gitlab.internal/team/app")
+        .git_ref("main")
+        .git_proxy(GitProxy::ssh_jump("ops@bastion.example.com"));
+    match config.source {
+        DockerfileSource::GitHub { proxy, .. } => {
+            assert_eq!(
+                proxy,
+                Some(GitProxy::SshJump("ops@bastion.example.com".into()))
+            );
+        }
+        _ => panic!("expected a git source"),
+    }
+
+    // Local builds have nothing to fetch
+    let config = BuildConfig::local(".").git_proxy(GitProxy::socks("127.0.0.1:1080"));
+    assert!(matches!(config.source, DockerfileSource::Local { .. }));
+}
+
+#[tokio::test]
+async fn test_git_checkout_fetches_ref() {
+    let repo = tempfile::TempDir::new().unwrap();
+    if !git(repo.path(), &["init", "--quiet"]) {
+        eprintln!("Skipping: git is not available");
+        return;
+    }
+    std::fs::write(repo.path().join("Dockerfile"), "FROM alpine\n").unwrap();
+    assert!(git(repo.path(), &["add", "Dockerfile"]));
+    assert!(git(repo.path(), &["commit", "--quiet", "-m", "initial"]));
+    assert!(git(repo.path(), &["tag", "v1"]));
+
+    // Local remotes ignore the proxy, which keeps the test offline
+    let url = format!("file://{}", repo.path().display());
+    let checkout = GitCheckout::fetch(&url, Some("v1"), None, &GitProxy::socks("127.0.0.1:1"))
+        .await
+        .unwrap();
+    let dir = checkout.path().to_path_buf();
+    assert!(dir.join("Dockerfile").is_file());
+    assert!(!dir.join(".git").exists());
+
+    drop(checkout);
+    assert!(!dir.exists());
+
+    let missing = GitCheckout::fetch(&url, Some("v2"), None, &GitProxy::socks("127.0.0.1:1")).await;
+    assert!(matches!(missing, Err(buildkit_client::Error::Git(_))));
+}
+
+#[test]
+fn test_git_auth_header() {
+    // base64("x-access-token:glpat-123")
+    assert_eq!(
+        GitAuth::token("glpat-123")
+            .authorization_header()
+            .as_deref(),
+        Some("basic eC1hY2Nlc3MtdG9rZW46Z2xwYXQtMTIz")
+    );
+    // base64("ci-bot:app-password")
+    assert_eq!(
+        GitAuth::basic("ci-bot", "app-password")
+            .authorization_header()
+            .as_deref(),
+        Some("basic Y2ktYm90OmFwcC1wYXNzd29yZA==")
+    );
+
+    let debug = format!(
+        "{:?} {:?}",
+        GitAuth::token("glpat-123"),
+        GitAuth::basic("ci-bot", "app-password")
+    );
+    assert!(!debug.contains("glpat-123"));
+    assert!(!debug.contains("app-password"));
+    assert!(debug.contains("ci-bot"));
+}
+
+#[test]
+fn test_git_auth_secret_name() {
+    assert_eq!(
+        GitAuth::secret_name("https://gitlab.com/group/app.git"),
+        "GIT_AUTH_HEADER.gitlab.com"
+    );
+    assert_eq!(
+        GitAuth::secret_name("https://user@git.internal:8443/team/app.git#main"),
+        "GIT_AUTH_HEADER.git.internal:8443"
+    );
+    assert_eq!(
+        GitAuth::secret_name("git@bitbucket.org:team/app.git"),
+        "GIT_AUTH_HEADER"
+    );
+    assert_eq!(
+        git_host("http://bitbucket.org/team/app"),
+        Some("bitbucket.org")
+    );
+    assert_eq!(git_host("https://"), None);
+}
+
+#[test]
+fn test_generic_git_source() {
+    let config = BuildConfig::git("https://bitbucket.org/team/app.git")
+        .git_ref("release")
+        .dockerfile("docker/Dockerfile")
+        .git_auth(GitAuth::basic("x-token-auth", "secret"));
+    match &config.source {
+        DockerfileSource::Git {
+            url,
+            git_ref,
+            dockerfile_path,
+            auth,
+        } => {
+            assert_eq!(url, "https://bitbucket.org/team/app.git");
+            assert_eq!(git_ref.as_deref(), Some("release"));
+            assert_eq!(dockerfile_path.as_deref(), Some("docker/Dockerfile"));
+            assert_eq!(auth, &Some(GitAuth::basic("x-token-auth", "secret")));
+        }
+        _ => panic!("expected a git source"),
+    }
+
+    // Credentials never become frontend attributes
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.get("filename"), Some("docker/Dockerfile"));
+    assert!(!attrs.to_string().contains("secret"));
+
+    // GitHub-only options leave generic sources alone
+    let config = BuildConfig::git("https://gitlab.com/group/app.git").github_token("ghp_x");
+    assert!(matches!(
+        config.source,
+        DockerfileSource::Git { auth: None, .. }
+    ));
+}
+
+#[test]
+fn test_git_ssh_auth() {
+    let key = GitAuth::ssh_key("/run/secrets/deploy_key");
+    assert!(key.is_ssh());
+    assert!(GitAuth::ssh_agent().is_ssh());
+    assert!(!GitAuth::token("glpat-123").is_ssh());
+    // SSH credentials are forwarded as an agent, never sent as a header
+    assert_eq!(key.authorization_header(), None);
+    assert_eq!(format!("{:?}", key), "SshKey(\"/run/secrets/deploy_key\")");
+
+    assert!(key.validate("git@github.com:acme/platform.git").is_ok());
+    assert!(GitAuth::ssh_agent()
+        .validate("ssh://git@git.internal:2222/team/app.git")
+        .is_ok());
+    assert!(key
+        .validate("https://github.com/acme/platform.git")
+        .is_err());
+    assert!(GitAuth::token("glpat-123")
+        .validate("git@gitlab.com:group/app.git")
+        .is_err());
+    assert!(GitAuth::token("glpat-123")
+        .validate("https://gitlab.com/group/app.git")
+        .is_ok());
+}
+
+#[test]
+fn test_git_ssh_source() {
+    let config = BuildConfig::git("git@github.com:acme/platform.git")
+        .git_ref("main")
+        .git_auth(GitAuth::ssh_agent());
+    match &config.source {
+        DockerfileSource::Git {
+            url, git_ref, auth, ..
+        } => {
+            assert_eq!(url, "git@github.com:acme/platform.git");
+            assert_eq!(git_ref.as_deref(), Some("main"));
+            assert_eq!(auth, &Some(GitAuth::SshAgent));
+        }
+        _ => panic!("expected a git source"),
+    }
+}
+
+#[test]
+fn test_github_token_stays_out_of_debug_output() {
+    assert_eq!(
+        GitAuth::token_secret_name("https://github.com/acme/private.git"),
+        "GIT_AUTH_TOKEN.github.com"
+    );
+
+    let config = BuildConfig::github("https://github.com/acme/private.git")
+        .github_token("ghp_s3cr3t")
+        .git_ref("main");
+    let debug = format!("{:?}", config);
+    assert!(!debug.contains("ghp_s3cr3t"));
+    assert!(debug.contains("<redacted>"));
+    assert!(debug.contains("https://github.com/acme/private.git"));
+
+    let config = BuildConfig::http_context("https://example.com/app.tar.gz")
+        .http_auth_header("Bearer abc123");
+    assert!(!format!("{:?}", config.source).contains("abc123"));
+}
+
+#[test]
+fn test_keep_git_dir() {
+    let config = BuildConfig::git("https://gitlab.com/group/app.git").keep_git_dir(true);
+    assert!(config.keep_git_dir);
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(
+        attrs.get("build-arg:BUILDKIT_CONTEXT_KEEP_GIT_DIR"),
+        Some("1")
+    );
+
+    let attrs =
+        FrontendAttrs::from_config(&BuildConfig::git("https://gitlab.com/group/app.git")).unwrap();
+    assert_eq!(attrs.get("build-arg:BUILDKIT_CONTEXT_KEEP_GIT_DIR"), None);
+
+    // Client-side checkouts drop .git
+    let config = BuildConfig::github("https://github.com/acme/app")
+        .git_proxy(GitProxy::socks("127.0.0.1:1080"))
+        .keep_git_dir(true);
+    assert!(FrontendAttrs::from_config(&config).is_err());
+}
@@ -0,0 +1,334 @@
+//! Unit tests for following build step logs
+
+use buildkit_client::handle::StepFilter;
+use buildkit_client::proto::moby::buildkit::v1::{StatusResponse, Vertex, VertexLog};
+use buildkit_client::{StatusSummary, StepMatcher};
+
+fn vertex(digest: &str, name: &str) -> Vertex {
+    Vertex {
+        digest: digest.to_string(),
+        name: name.to_string(),
+        ..Default::default()
+    }
+}
+
+fn log(digest: &str, data: &[u8]) -> VertexLog {
+    VertexLog {
+        vertex: digest.to_string(),
+        msg: data.to_vec(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_step_matcher_name() {
+    let matcher = StepMatcher::name(r"RUN cargo test").unwrap();
+    assert!(matcher.matches("[test 3/3] RUN cargo test --all"));
+    assert!(!matcher.matches("[build 2/3] RUN cargo build"));
+
+    assert!(StepMatcher::name("(unclosed").is_err());
+}
+
+#[test]
+fn test_step_matcher_line() {
+    let dockerfile = "FROM rust:1\n\
+                      # build everything\n\
+                      RUN cargo build \\\n    --release\n\
+                      RUN cargo test\n";
+
+    let matcher = StepMatcher::line(3).resolve(dockerfile).unwrap();
+    assert!(matcher.matches("[2/3] RUN cargo build --release"));
+    assert!(!matcher.matches("[3/3] RUN cargo test"));
+
+    // Continuation lines belong to the instruction they continue
+    let matcher = StepMatcher::line(4).resolve(dockerfile).unwrap();
+    assert!(matcher.matches("[2/3] RUN cargo build --release"));
+
+    let matcher = StepMatcher::line(5).resolve(dockerfile).unwrap();
+    assert!(matcher.matches("[3/3] RUN cargo test"));
+
+    // Unresolved line matchers never match
+    assert!(!StepMatcher::line(5).matches("[3/3] RUN cargo test"));
+}
+
+#[test]
+fn test_step_filter_selects_matching_logs() {
+    let mut filter = StepFilter::new(StepMatcher::name("RUN cargo test").unwrap());
+
+    let status: StatusSummary = StatusResponse {
+        vertexes: vec![
+            vertex("sha256:build", "[2/3] RUN cargo build"),
+            vertex("sha256:test", "[3/3] RUN cargo test"),
+        ],
+        logs: vec![log("sha256:build", b"Compiling"), log("sha256:test", b"running 3 tests")],
+        ..Default::default()
+    }
+    .into();
+    let logs = filter.filter(&status);
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].data, b"running 3 tests");
+
+    // Vertexes are remembered across updates
+    let status: StatusSummary = StatusResponse {
+        logs: vec![log("sha256:test", b"test result: ok")],
+        ..Default::default()
+    }
+    .into();
+    assert_eq!(filter.filter(&status).len(), 1);
+}
+
+mod daemon {
+    //! A `Control` service that streams one step's log until released
+
+    use buildkit_client::proto::moby::buildkit::v1::control_server::Control;
+    use buildkit_client::proto::moby::buildkit::v1::*;
+    use std::pin::Pin;
+    use std::time::Duration;
+    use tokio::sync::{mpsc, watch};
+    use tokio_stream::wrappers::ReceiverStream;
+    use tonic::{Request, Response, Status, Streaming};
+
+    type Stream<T> = Pin<Box<dyn tokio_stream::Stream<Item = Result<T, Status>> + Send>>;
+
+    pub struct MockDaemon {
+        /// Set once the solve may return
+        pub released: watch::Receiver<bool>,
+    }
+
+    #[tonic::async_trait]
+    impl Control for MockDaemon {
+        async fn disk_usage(
+            &self,
+            _request: Request<DiskUsageRequest>,
+        ) -> Result<Response<DiskUsageResponse>, Status> {
+            Err(Status::unimplemented("disk usage"))
+        }
+
+        type PruneStream = Stream<UsageRecord>;
+
+        async fn prune(
+            &self,
+            _request: Request<PruneRequest>,
+        ) -> Result<Response<Self::PruneStream>, Status> {
+            Err(Status::unimplemented("prune"))
+        }
+
+        async fn solve(
+            &self,
+            _request: Request<SolveRequest>,
+        ) -> Result<Response<SolveResponse>, Status> {
+            let mut released = self.released.clone();
+            let _ = released.wait_for(|released| *released).await;
+            Ok(Response::new(SolveResponse::default()))
+        }
+
+        type StatusStream = Stream<StatusResponse>;
+
+        async fn status(
+            &self,
+            _request: Request<StatusRequest>,
+        ) -> Result<Response<Self::StatusStream>, Status> {
+            let released = self.released.clone();
+            let (tx, rx) = mpsc::channel(8);
+            tokio::spawn(async move {
+                // Repeated so a follower that subscribes late still sees it
+                while !*released.borrow() {
+                    let status = StatusResponse {
+                        vertexes: vec![super::vertex("sha256:test", "[2/2] RUN cargo test")],
+                        logs: vec![super::log("sha256:test", b"running 3 tests\n")],
+                        ..Default::default()
+                    };
+                    if tx.send(Ok(status)).await.is_err() {
+                        return;
+                    }
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+            });
+            Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+        }
+
+        type SessionStream = Stream<BytesMessage>;
+
+        async fn session(
+            &self,
+            request: Request<Streaming<BytesMessage>>,
+        ) -> Result<Response<Self::SessionStream>, Status> {
+            let mut inbound = request.into_inner();
+            let (tx, rx) = mpsc::channel(1);
+            tokio::spawn(async move {
+                // Keep the session open until the client ends it
+                while let Ok(Some(_)) = inbound.message().await {}
+                drop(tx);
+            });
+            Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+        }
+
+        async fn list_workers(
+            &self,
+            _request: Request<ListWorkersRequest>,
+        ) -> Result<Response<ListWorkersResponse>, Status> {
+            Err(Status::unimplemented("list workers"))
+        }
+
+        async fn info(
+            &self,
+            _request: Request<InfoRequest>,
+        ) -> Result<Response<InfoResponse>, Status> {
+            Err(Status::unimplemented("info"))
+        }
+
+        type ListenBuildHistoryStream = Stream<BuildHistoryEvent>;
+
+        async fn listen_build_history(
+            &self,
+            _request: Request<BuildHistoryRequest>,
+        ) -> Result<Response<Self::ListenBuildHistoryStream>, Status> {
+            Err(Status::unimplemented("build history"))
+        }
+
+        async fn update_build_history(
+            &self,
+            _request: Request<UpdateBuildHistoryRequest>,
+        ) -> Result<Response<UpdateBuildHistoryResponse>, Status> {
+            Err(Status::unimplemented("build history"))
+        }
+    }
+}
+
+/// Serve a [`daemon::MockDaemon`] on localhost, returning its address
+async fn serve_mock_daemon(released: tokio::sync::watch::Receiver<bool>) -> std::net::SocketAddr {
+    use buildkit_client::proto::moby::buildkit::v1::control_server::ControlServer;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let incoming = async_stream::stream! {
+        loop {
+            yield listener.accept().await.map(|(stream, _)| stream);
+        }
+    };
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(ControlServer::new(daemon::MockDaemon { released }))
+            .serve_with_incoming(Box::pin(incoming)),
+    );
+    addr
+}
+
+#[tokio::test]
+async fn test_follow_step_while_solving() {
+    use buildkit_client::{BuildConfig, BuildKitClient};
+    use std::time::Duration;
+
+    let (release, released) = tokio::sync::watch::channel(false);
+    let addr = serve_mock_daemon(released).await;
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("Dockerfile"),
+        "FROM alpine\nRUN cargo test\n",
+    )
+    .unwrap();
+    let client = BuildKitClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+    let handle = client
+        .start_build(BuildConfig::local(dir.path()), None)
+        .await
+        .unwrap();
+    let mut logs = handle
+        .follow_step(StepMatcher::name("RUN cargo test").unwrap())
+        .unwrap();
+
+    // The solve only returns once the log has been seen
+    let chunk = tokio::time::timeout(Duration::from_secs(10), logs.next())
+        .await
+        .expect("step log should arrive while the solve runs")
+        .expect("step log");
+    assert_eq!(chunk.data, b"running 3 tests\n");
+
+    release.send(true).unwrap();
+    let result = tokio::time::timeout(Duration::from_secs(10), handle.wait())
+        .await
+        .expect("build should finish once released");
+    assert!(result.is_ok(), "{:?}", result);
+}
+
+#[tokio::test]
+async fn test_session_health_while_solving() {
+    use buildkit_client::session::TaskState;
+    use buildkit_client::{BuildConfig, BuildKitClient};
+    use std::time::Duration;
+
+    let (release, released) = tokio::sync::watch::channel(false);
+    let addr = serve_mock_daemon(released).await;
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("Dockerfile"),
+        "FROM alpine\nRUN cargo test\n",
+    )
+    .unwrap();
+    let client = BuildKitClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+    let handle = client
+        .start_build(BuildConfig::local(dir.path()), None)
+        .await
+        .unwrap();
+    let build_ref = handle.build_ref().to_string();
+    let mut logs = handle
+        .follow_step(StepMatcher::name("RUN cargo test").unwrap())
+        .unwrap();
+
+    // Status only streams once the session is started and the solve sent
+    tokio::time::timeout(Duration::from_secs(10), logs.next())
+        .await
+        .expect("step log should arrive while the solve runs")
+        .expect("step log");
+    let health = handle.session_health().expect("session started");
+    assert!(health.is_healthy(), "{:?}", health);
+    assert_eq!(health.tunnel, TaskState::Running);
+    assert!(client.session_health(&build_ref).is_some());
+
+    release.send(true).unwrap();
+    let result = tokio::time::timeout(Duration::from_secs(10), handle.wait())
+        .await
+        .expect("build should finish once released");
+    assert!(result.is_ok(), "{:?}", result);
+    assert_eq!(client.session_health(&build_ref), None);
+}
+
+#[tokio::test]
+async fn test_follow_line_in_inline_dockerfile() {
+    use buildkit_client::{BuildConfig, BuildKitClient};
+    use std::time::Duration;
+
+    let (release, released) = tokio::sync::watch::channel(false);
+    let addr = serve_mock_daemon(released).await;
+
+    // Line 2 of the file on disk is another step; the inline one is built
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("Dockerfile"),
+        "FROM alpine\nRUN cargo build\n",
+    )
+    .unwrap();
+    let config = BuildConfig::local(dir.path()).inline_dockerfile("FROM alpine\nRUN cargo test\n");
+    let client = BuildKitClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+    let handle = client.start_build(config, None).await.unwrap();
+    let mut logs = handle.follow_step(StepMatcher::line(2)).unwrap();
+
+    let chunk = tokio::time::timeout(Duration::from_secs(10), logs.next())
+        .await
+        .expect("step log should arrive while the solve runs")
+        .expect("step log");
+    assert_eq!(chunk.data, b"running 3 tests\n");
+
+    release.send(true).unwrap();
+    let result = tokio::time::timeout(Duration::from_secs(10), handle.wait())
+        .await
+        .expect("build should finish once released");
+    assert!(result.is_ok(), "{:?}", result);
+}
@@ -0,0 +1,138 @@
+//! Tests for remote HTTP(S) tarball contexts
+
+use buildkit_client::http_context::{
+    http_context_definition, validate_checksum, validate_url, HTTP_AUTH_HEADER_SECRET,
+};
+use buildkit_client::proto::pb;
+use buildkit_client::{BuildConfig, DockerfileSource, FrontendAttrs};
+use prost::Message;
+use sha2::{Digest, Sha256};
+
+const CHECKSUM: &str = "sha256:9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+#[test]
+fn test_validate_url() {
+    assert!(validate_url("https://example.com/app-1.2.tar.gz").is_ok());
+    assert!(validate_url("http://10.0.0.5:8080/context.tar").is_ok());
+    for url in [
+        "",
+        "ftp://example.com/a.tar",
+        "https://",
+        "https:///a.tar",
+        "app.tar",
+    ] {
+        assert!(validate_url(url).is_err(), "{:?}", url);
+    }
+}
+
+#[test]
+fn test_validate_checksum() {
+    assert!(validate_checksum(CHECKSUM).is_ok());
+    for checksum in [
+        "",
+        "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08",
+        "sha256:9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08",
+        "sha256:abc",
+        "sha512:9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08",
+    ] {
+        assert!(validate_checksum(checksum).is_err(), "{:?}", checksum);
+    }
+}
+
+#[test]
+fn test_http_context_definition() {
+    let url = "https://example.com/app-1.2.tar.gz";
+    let definition =
+        http_context_definition(url, Some(CHECKSUM), Some(HTTP_AUTH_HEADER_SECRET)).unwrap();
+    assert_eq!(definition.def.len(), 3);
+
+    let source = pb::Op::decode(definition.def[0].as_slice()).unwrap();
+    match source.op {
+        Some(pb::op::Op::Source(op)) => {
+            assert_eq!(op.identifier, url);
+            assert_eq!(op.attrs["http.checksum"], CHECKSUM);
+            assert_eq!(op.attrs["http.authheadersecret"], HTTP_AUTH_HEADER_SECRET);
+        }
+        other => panic!("expected a source op, got {:?}", other),
+    }
+    let source_digest = format!("sha256:{:x}", Sha256::digest(&definition.def[0]));
+    assert_eq!(
+        definition.metadata[&source_digest]
+            .caps
+            .get("source.http.checksum"),
+        Some(&true)
+    );
+
+    // The download is unpacked into scratch
+    let unpack = pb::Op::decode(definition.def[1].as_slice()).unwrap();
+    assert_eq!(unpack.inputs[0].digest, source_digest);
+    match unpack.op {
+        Some(pb::op::Op::File(op)) => {
+            assert_eq!(op.actions.len(), 1);
+            assert_eq!(op.actions[0].input, -1);
+            assert_eq!(op.actions[0].secondary_input, 0);
+            match &op.actions[0].action {
+                Some(pb::file_action::Action::Copy(copy)) => {
+                    assert_eq!(copy.dest, "/");
+                    assert!(copy.attempt_unpack_docker_compatibility);
+                }
+                other => panic!("expected a copy, got {:?}", other),
+            }
+        }
+        other => panic!("expected a file op, got {:?}", other),
+    }
+
+    let terminal = pb::Op::decode(definition.def[2].as_slice()).unwrap();
+    assert!(terminal.op.is_none());
+    assert_eq!(
+        terminal.inputs[0].digest,
+        format!("sha256:{:x}", Sha256::digest(&definition.def[1]))
+    );
+}
+
+#[test]
+fn test_http_context_definition_without_options() {
+    let definition = http_context_definition("https://example.com/a.tar", None, None).unwrap();
+    let source = pb::Op::decode(definition.def[0].as_slice()).unwrap();
+    match source.op {
+        Some(pb::op::Op::Source(op)) => {
+            assert!(!op.attrs.contains_key("http.checksum"));
+            assert!(!op.attrs.contains_key("http.authheadersecret"));
+        }
+        other => panic!("expected a source op, got {:?}", other),
+    }
+
+    assert!(http_context_definition("https://example.com/a.tar", Some("md5:x"), None).is_err());
+    assert!(http_context_definition("file:///a.tar", None, None).is_err());
+}
+
+#[test]
+fn test_build_config_http_context() {
+    let config = BuildConfig::http_context("https://example.com/app.tar.gz")
+        .http_checksum(CHECKSUM)
+        .http_auth_header("Bearer secret-token")
+        .dockerfile("build/Dockerfile");
+    match &config.source {
+        DockerfileSource::Http {
+            url,
+            checksum,
+            auth_header,
+            dockerfile_path,
+        } => {
+            assert_eq!(url, "https://example.com/app.tar.gz");
+            assert_eq!(checksum.as_deref(), Some(CHECKSUM));
+            assert_eq!(auth_header.as_deref(), Some("Bearer secret-token"));
+            assert_eq!(dockerfile_path.as_deref(), Some("build/Dockerfile"));
+        }
+        _ => panic!("Expected Http source"),
+    }
+
+    let attrs = FrontendAttrs::from_config(&config).unwrap();
+    assert_eq!(attrs.get("filename"), Some("build/Dockerfile"));
+    // The header never becomes a frontend attribute
+    assert!(!attrs.to_string().contains("secret-token"));
+
+    // HTTP options are ignored for other sources
+    let config = BuildConfig::local(".").http_checksum(CHECKSUM);
+    assert!(matches!(config.source, DockerfileSource::Local { .. }));
+}
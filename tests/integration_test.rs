@@ -399,7 +399,9 @@ async fn test_push_to_local_registry() {
     let image_name = format!("push-test-{}", rand::random::<u32>());
     let tag = format!("registry:5000/{image_name}:latest");
 
-    let config = BuildConfig::local(&test_dir).tag(&tag);
+    let config = BuildConfig::local(&test_dir)
+        .tag(&tag)
+        .insecure_registry("registry:5000");
 
     let result = client.build(config, None).await;
 
@@ -439,7 +441,10 @@ async fn test_push_multiple_tags() {
     let tag1 = format!("registry:5000/{image_name}:v1.0");
     let tag2 = format!("registry:5000/{image_name}:latest");
 
-    let config = BuildConfig::local(&test_dir).tag(&tag1).tag(&tag2);
+    let config = BuildConfig::local(&test_dir)
+        .tag(&tag1)
+        .tag(&tag2)
+        .insecure_registry("registry:5000");
 
     let result = client.build(config, None).await;
 
@@ -805,3 +810,96 @@ async fn test_github_with_commit_ref() {
         result.err()
     );
 }
+
+#[tokio::test]
+async fn test_shutdown_rejects_new_builds() {
+    skip_without_buildkit!();
+
+    let test_dir = create_temp_dir("shutdown");
+    create_test_dockerfile(&test_dir, None);
+
+    let addr = get_buildkit_addr();
+    let mut client = BuildKitClient::connect(&addr).await.unwrap();
+
+    // Nothing is running, so nothing needs to be aborted
+    let aborted = client
+        .clone()
+        .shutdown(std::time::Duration::from_secs(1))
+        .await;
+    assert!(aborted.is_empty());
+    assert!(client.is_shutting_down());
+
+    let result = client.build(BuildConfig::local(&test_dir), None).await;
+    assert!(matches!(
+        result,
+        Err(buildkit_client::Error::ClientShutdown)
+    ));
+}
+
+#[tokio::test]
+async fn test_shutdown_cancels_running_build() {
+    skip_without_buildkit!();
+
+    let test_dir = create_temp_dir("shutdown-running");
+    create_test_dockerfile(&test_dir, Some("FROM alpine:latest\nRUN sleep 60\n"));
+
+    let addr = get_buildkit_addr();
+    let client = BuildKitClient::connect(&addr).await.unwrap();
+
+    let mut builder = client.clone();
+    let config = BuildConfig::local(&test_dir).no_cache(true);
+    let build = tokio::spawn(async move { builder.build(config, None).await });
+
+    // Give the solve time to reach the daemon
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    client.shutdown(std::time::Duration::from_secs(10)).await;
+
+    let result = build.await.unwrap();
+    assert!(matches!(
+        result,
+        Err(buildkit_client::Error::BuildCancelled(_))
+    ));
+    assert!(client.in_flight_refs().is_empty());
+}
+
+#[tokio::test]
+async fn test_deduplicated_build_joins_running_build() {
+    skip_without_buildkit!();
+
+    let test_dir = create_temp_dir("dedup");
+    create_test_dockerfile(&test_dir, Some("FROM alpine:latest\nRUN sleep 5\n"));
+
+    let addr = get_buildkit_addr();
+    let client = BuildKitClient::connect(&addr).await.unwrap();
+
+    let key = format!("webhook-{}", std::process::id());
+    let config = BuildConfig::local(&test_dir)
+        .no_cache(true)
+        .ref_key(key.as_str())
+        .deduplicate(true);
+
+    let mut builder = client.clone();
+    let first_config = config.clone();
+    let first = tokio::spawn(async move { builder.build(first_config, None).await });
+
+    // Give the first build time to reserve its ref
+    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    let mut duplicate = client.clone();
+    let result = duplicate
+        .build(config.clone().deduplicate(false), None)
+        .await;
+    assert!(matches!(
+        result,
+        Err(buildkit_client::Error::DuplicateBuildRef(_))
+    ));
+    let joined = duplicate.build(config, None).await;
+    let first = first.await.unwrap();
+
+    cleanup_temp_dir(&test_dir);
+
+    let first = first.expect("first build failed");
+    let joined = joined.expect("joined build failed");
+    assert_eq!(joined.build_ref, first.build_ref);
+    assert_eq!(joined.digest, first.digest);
+    assert!(client.in_flight_refs().is_empty());
+}
@@ -0,0 +1,177 @@
+//! Tests for the build journal
+#![cfg(feature = "journal")]
+
+use buildkit_client::journal::config_hash;
+use buildkit_client::{
+    BuildConfig, BuildJournal, BuildResult, Error, FailureKind, JournalEntry, JournalQuery,
+    JournalStats, SyncStats, VertexSummary,
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn vertex(name: &str, cached: bool) -> VertexSummary {
+    VertexSummary {
+        digest: format!("sha256:{}", name.len()),
+        inputs: Vec::new(),
+        name: name.to_string(),
+        cached,
+        started: None,
+        completed: None,
+        error: None,
+    }
+}
+
+fn success(build_ref: &str) -> BuildResult {
+    BuildResult {
+        build_ref: build_ref.to_string(),
+        digest: Some("sha256:abc".to_string()),
+        metadata: Default::default(),
+        descriptor: None,
+        progress_metrics: Default::default(),
+        sync_stats: SyncStats {
+            files: 3,
+            bytes: 300,
+            ..Default::default()
+        },
+        vertexes: vec![
+            vertex("[internal] load build context", false),
+            vertex("[1/3] FROM alpine", true),
+            vertex("[2/3] COPY . /app", false),
+            vertex("[3/3] RUN make", false),
+        ],
+        changed_files: Vec::new(),
+    }
+}
+
+fn at(ms: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(ms)
+}
+
+#[test]
+fn test_entry_from_success() {
+    let entry = JournalEntry::new("b1", "sha256:cfg", at(1_000), &Ok(success("b1")));
+    assert!(entry.succeeded());
+    assert_eq!(entry.digest.as_deref(), Some("sha256:abc"));
+    // Internal vertexes are not steps
+    assert_eq!(entry.steps, 3);
+    assert_eq!(entry.cached_steps, 1);
+    assert_eq!(entry.context_files, 3);
+    assert_eq!(entry.context_bytes, 300);
+}
+
+#[test]
+fn test_failure_classification() {
+    let cases = [
+        (Error::BuildCancelled("b".into()), FailureKind::Cancelled),
+        (Error::InvalidConfig("x".into()), FailureKind::Config),
+        (Error::Build("exit code 1".into()), FailureKind::Solve),
+        (
+            Error::Grpc(Box::new(tonic::Status::unavailable("down"))),
+            FailureKind::Connection,
+        ),
+        (Error::session("closed"), FailureKind::Session),
+        (Error::PathNotFound("ctx".into()), FailureKind::Context),
+        (Error::Other("?".into()), FailureKind::Other),
+    ];
+    for (error, kind) in cases {
+        assert_eq!(FailureKind::classify(&error), kind, "{}", error);
+    }
+
+    let entry = JournalEntry::new(
+        "b2",
+        "sha256:cfg",
+        at(0),
+        &Err(Error::Build("exit code 1".into())),
+    );
+    assert_eq!(entry.failure, Some(FailureKind::Solve));
+    assert_eq!(
+        entry.error.as_deref(),
+        Some("Build execution failed: exit code 1")
+    );
+}
+
+#[test]
+fn test_record_and_query() {
+    let journal = BuildJournal::open_in_memory().unwrap();
+    let mut first = JournalEntry::new("b1", "sha256:a", at(1_000), &Ok(success("b1")));
+    first.duration = Duration::from_millis(400);
+    let mut second = JournalEntry::new(
+        "b2",
+        "sha256:a",
+        at(2_000),
+        &Err(Error::Build("boom".into())),
+    );
+    second.duration = Duration::from_millis(100);
+    let mut third = JournalEntry::new("b3", "sha256:b", at(3_000), &Ok(success("b3")));
+    third.duration = Duration::from_millis(700);
+    for entry in [&first, &second, &third] {
+        journal.record(entry).unwrap();
+    }
+
+    // Newest first, and entries round-trip
+    let all = journal.query(&JournalQuery::new()).unwrap();
+    assert_eq!(all, vec![third.clone(), second.clone(), first.clone()]);
+
+    let by_config = journal
+        .query(&JournalQuery::new().config_hash("sha256:a"))
+        .unwrap();
+    assert_eq!(by_config, vec![second.clone(), first.clone()]);
+
+    let failed = journal
+        .query(&JournalQuery::new().succeeded(false))
+        .unwrap();
+    assert_eq!(failed, vec![second.clone()]);
+    let solve_failures = journal
+        .query(&JournalQuery::new().failure(FailureKind::Solve))
+        .unwrap();
+    assert_eq!(solve_failures, vec![second.clone()]);
+
+    let recent = journal
+        .query(&JournalQuery::new().since(at(2_000)).limit(1))
+        .unwrap();
+    assert_eq!(recent, vec![third.clone()]);
+
+    assert_eq!(journal.find("b1").unwrap(), Some(first));
+    assert_eq!(journal.find("missing").unwrap(), None);
+
+    let stats = JournalStats::from_entries(&all);
+    assert_eq!(stats.builds, 3);
+    assert_eq!(stats.failures, 1);
+    assert_eq!(stats.mean_duration, Duration::from_millis(400));
+    assert_eq!(stats.cache_hit_ratio, 2.0 / 6.0);
+}
+
+#[test]
+fn test_journal_persists() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("journal.db");
+    let entry = JournalEntry::new("b1", "sha256:a", at(1_000), &Ok(success("b1")));
+    BuildJournal::open(&path).unwrap().record(&entry).unwrap();
+
+    let reopened = BuildJournal::open(&path).unwrap();
+    assert_eq!(reopened.find("b1").unwrap(), Some(entry));
+}
+
+#[test]
+fn test_config_hash() {
+    let base = BuildConfig::local("./app")
+        .tag("app:v1")
+        .build_arg("A", "1");
+    assert_eq!(config_hash(&base), config_hash(&base.clone()));
+    assert!(config_hash(&base).starts_with("sha256:"));
+
+    let other_arg = BuildConfig::local("./app")
+        .tag("app:v1")
+        .build_arg("A", "2");
+    assert_ne!(config_hash(&base), config_hash(&other_arg));
+    let other_tag = BuildConfig::local("./app")
+        .tag("app:v2")
+        .build_arg("A", "1");
+    assert_ne!(config_hash(&base), config_hash(&other_tag));
+
+    // Credentials are not part of what is built
+    let repo = BuildConfig::github("https://github.com/org/app.git").git_ref("main");
+    assert_eq!(
+        config_hash(&repo.clone().github_token("ghp_one")),
+        config_hash(&repo.github_token("ghp_two"))
+    );
+}
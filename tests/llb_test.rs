@@ -0,0 +1,97 @@
+//! Tests for solving raw LLB definitions
+
+use buildkit_client::copy::image_definition;
+use buildkit_client::llb::validate_definition;
+use buildkit_client::proto::pb;
+use buildkit_client::{Entitlement, LlbSolveOptions, RegistryAuth};
+use prost::Message;
+
+#[test]
+fn test_validate_definition() {
+    assert!(validate_definition(&image_definition("alpine:3.20").unwrap()).is_ok());
+    assert!(validate_definition(&pb::Definition::default()).is_err());
+
+    // An input pointing outside the graph
+    let mut definition = image_definition("alpine:3.20").unwrap();
+    definition.def.remove(0);
+    assert!(validate_definition(&definition).is_err());
+
+    let mut definition = image_definition("alpine:3.20").unwrap();
+    definition.def.push(vec![0xff, 0xff, 0xff]);
+    assert!(validate_definition(&definition).is_err());
+}
+
+#[test]
+fn test_validate_definition_with_multiple_inputs() {
+    let source = |identifier: &str| {
+        pb::Op {
+            op: Some(pb::op::Op::Source(pb::SourceOp {
+                identifier: identifier.to_string(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .encode_to_vec()
+    };
+    let digest = |op: &[u8]| {
+        use sha2::{Digest, Sha256};
+        format!("sha256:{:x}", Sha256::digest(op))
+    };
+    let base = source("docker-image://docker.io/library/alpine:3.20");
+    let local = source("local://context");
+    let merge = pb::Op {
+        inputs: vec![
+            pb::Input {
+                digest: digest(&base),
+                index: 0,
+            },
+            pb::Input {
+                digest: digest(&local),
+                index: 0,
+            },
+        ],
+        op: Some(pb::op::Op::Merge(pb::MergeOp::default())),
+        ..Default::default()
+    }
+    .encode_to_vec();
+    let terminal = pb::Op {
+        inputs: vec![pb::Input {
+            digest: digest(&merge),
+            index: 0,
+        }],
+        ..Default::default()
+    }
+    .encode_to_vec();
+
+    let definition = pb::Definition {
+        def: vec![base, local, merge, terminal],
+        ..Default::default()
+    };
+    assert!(validate_definition(&definition).is_ok());
+}
+
+#[test]
+fn test_llb_solve_options() {
+    let options = LlbSolveOptions::new()
+        .ref_id("llb-1")
+        .local_dir("context", ".")
+        .secret("token", "s3cr3t")
+        .registry_auth(RegistryAuth {
+            host: "registry.example.com".to_string(),
+            username: "ci".to_string(),
+            password: "hunter2".to_string(),
+            identity_token: None,
+        })
+        .entitlement(Entitlement::NetworkHost);
+    assert_eq!(options.ref_id.as_deref(), Some("llb-1"));
+    assert!(options.local_dirs.contains_key("context"));
+    assert_eq!(options.secrets["token"], "s3cr3t");
+    assert!(options.entitlements.contains(&Entitlement::NetworkHost));
+
+    // Secret values and passwords never show up in logs
+    let debug = format!("{:?}", options);
+    assert!(debug.contains("token"));
+    assert!(debug.contains("registry.example.com"));
+    assert!(!debug.contains("s3cr3t"));
+    assert!(!debug.contains("hunter2"));
+}
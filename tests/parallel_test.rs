@@ -0,0 +1,340 @@
+//! Tests for client-side index assembly of per-platform builds
+
+use buildkit_client::oci::{
+    Descriptor, ImageIndex, MEDIA_TYPE_IMAGE_INDEX, MEDIA_TYPE_IMAGE_MANIFEST,
+};
+use buildkit_client::parallel::platform_manifests;
+use buildkit_client::Platform;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+fn manifest(digest: &str) -> Descriptor {
+    Descriptor {
+        media_type: MEDIA_TYPE_IMAGE_MANIFEST.to_string(),
+        digest: digest.to_string(),
+        size: 512,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_platform_manifest_gets_platform() {
+    let manifests = platform_manifests(&Platform::linux_arm64(), manifest("sha256:a"), None);
+    assert_eq!(manifests.len(), 1);
+    assert_eq!(manifests[0].digest, "sha256:a");
+    assert_eq!(manifests[0].platform, Some(Platform::linux_arm64()));
+}
+
+#[test]
+fn test_nested_index_is_flattened() {
+    let attestation = Descriptor {
+        platform: Some(Platform::new("unknown", "unknown")),
+        annotations: HashMap::from([
+            (
+                "vnd.docker.reference.type".to_string(),
+                "attestation-manifest".to_string(),
+            ),
+            (
+                "vnd.docker.reference.digest".to_string(),
+                "sha256:image".to_string(),
+            ),
+        ]),
+        ..manifest("sha256:att")
+    };
+    let nested = ImageIndex::new(vec![manifest("sha256:image"), attestation.clone()]);
+    let index = Descriptor {
+        media_type: MEDIA_TYPE_IMAGE_INDEX.to_string(),
+        ..manifest("sha256:index")
+    };
+
+    let manifests = platform_manifests(&Platform::linux_amd64(), index, Some(nested));
+    assert_eq!(manifests.len(), 2);
+    assert_eq!(manifests[0].digest, "sha256:image");
+    assert_eq!(manifests[0].platform, Some(Platform::linux_amd64()));
+    // Attestation manifests keep their own platform and annotations
+    assert_eq!(manifests[1], attestation);
+}
+
+#[test]
+fn test_index_json_round_trip() {
+    let mut manifests = platform_manifests(&Platform::linux_amd64(), manifest("sha256:a"), None);
+    manifests.extend(platform_manifests(
+        &Platform::linux_arm_v7(),
+        manifest("sha256:b"),
+        None,
+    ));
+    let index = ImageIndex::new(manifests);
+
+    let json = index.to_json().unwrap();
+    let value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(value["schemaVersion"], 2);
+    assert_eq!(value["mediaType"], MEDIA_TYPE_IMAGE_INDEX);
+    assert_eq!(
+        value["manifests"][1]["platform"],
+        serde_json::json!({"os": "linux", "architecture": "arm", "variant": "v7"})
+    );
+    assert!(value["manifests"][0].get("annotations").is_none());
+
+    assert_eq!(ImageIndex::from_json(&json).unwrap(), index);
+}
+
+mod daemon {
+    //! A `Control` service whose solves export a pushed image
+
+    use buildkit_client::proto::moby::buildkit::v1::control_server::Control;
+    use buildkit_client::proto::moby::buildkit::v1::*;
+    use std::collections::HashMap;
+    use std::pin::Pin;
+    use tokio::sync::mpsc;
+    use tokio_stream::wrappers::ReceiverStream;
+    use tonic::{Request, Response, Status, Streaming};
+
+    type Stream<T> = Pin<Box<dyn tokio_stream::Stream<Item = Result<T, Status>> + Send>>;
+
+    pub struct MockDaemon {
+        /// Exporter response returned by every solve
+        pub exporter_response: HashMap<String, String>,
+    }
+
+    #[tonic::async_trait]
+    impl Control for MockDaemon {
+        async fn disk_usage(
+            &self,
+            _request: Request<DiskUsageRequest>,
+        ) -> Result<Response<DiskUsageResponse>, Status> {
+            Err(Status::unimplemented("disk usage"))
+        }
+
+        type PruneStream = Stream<UsageRecord>;
+
+        async fn prune(
+            &self,
+            _request: Request<PruneRequest>,
+        ) -> Result<Response<Self::PruneStream>, Status> {
+            Err(Status::unimplemented("prune"))
+        }
+
+        async fn solve(
+            &self,
+            _request: Request<SolveRequest>,
+        ) -> Result<Response<SolveResponse>, Status> {
+            Ok(Response::new(SolveResponse {
+                exporter_response: self.exporter_response.clone(),
+            }))
+        }
+
+        type StatusStream = Stream<StatusResponse>;
+
+        async fn status(
+            &self,
+            _request: Request<StatusRequest>,
+        ) -> Result<Response<Self::StatusStream>, Status> {
+            Err(Status::unimplemented("status"))
+        }
+
+        type SessionStream = Stream<BytesMessage>;
+
+        async fn session(
+            &self,
+            request: Request<Streaming<BytesMessage>>,
+        ) -> Result<Response<Self::SessionStream>, Status> {
+            let mut inbound = request.into_inner();
+            let (tx, rx) = mpsc::channel(1);
+            tokio::spawn(async move {
+                // Keep the session open until the client ends it
+                while let Ok(Some(_)) = inbound.message().await {}
+                drop(tx);
+            });
+            Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+        }
+
+        async fn list_workers(
+            &self,
+            _request: Request<ListWorkersRequest>,
+        ) -> Result<Response<ListWorkersResponse>, Status> {
+            Err(Status::unimplemented("list workers"))
+        }
+
+        async fn info(
+            &self,
+            _request: Request<InfoRequest>,
+        ) -> Result<Response<InfoResponse>, Status> {
+            Err(Status::unimplemented("info"))
+        }
+
+        type ListenBuildHistoryStream = Stream<BuildHistoryEvent>;
+
+        async fn listen_build_history(
+            &self,
+            _request: Request<BuildHistoryRequest>,
+        ) -> Result<Response<Self::ListenBuildHistoryStream>, Status> {
+            Err(Status::unimplemented("build history"))
+        }
+
+        async fn update_build_history(
+            &self,
+            _request: Request<UpdateBuildHistoryRequest>,
+        ) -> Result<Response<UpdateBuildHistoryResponse>, Status> {
+            Err(Status::unimplemented("build history"))
+        }
+    }
+}
+
+/// Manifests held by the fake registry, by request path
+type Manifests = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+/// Serve manifest GETs and PUTs from `manifests`, returning the registry host
+async fn fake_registry(manifests: Manifests) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let manifests = Arc::clone(&manifests);
+            tokio::spawn(async move {
+                let mut request = Vec::new();
+                let mut buf = [0u8; 4096];
+                let head_end = loop {
+                    if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                        break end + 4;
+                    }
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                };
+                let head = String::from_utf8_lossy(&request[..head_end]).into_owned();
+                let length: usize = head
+                    .lines()
+                    .find_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        name.eq_ignore_ascii_case("content-length")
+                            .then(|| value.trim().parse().ok())?
+                    })
+                    .unwrap_or(0);
+                while request.len() < head_end + length {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let mut parts = head.split_whitespace();
+                let method = parts.next().unwrap_or_default();
+                let path = parts.next().unwrap_or_default().to_string();
+
+                let response = match method {
+                    "PUT" => {
+                        let body = request[head_end..head_end + length].to_vec();
+                        manifests.lock().unwrap().insert(path, body);
+                        "HTTP/1.1 201 Created\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .as_bytes()
+                            .to_vec()
+                    }
+                    _ => match manifests.lock().unwrap().get(&path) {
+                        Some(body) => {
+                            let mut response = format!(
+                                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                                body.len()
+                            )
+                            .into_bytes();
+                            if method != "HEAD" {
+                                response.extend_from_slice(body);
+                            }
+                            response
+                        }
+                        None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .as_bytes()
+                            .to_vec(),
+                    },
+                };
+                let _ = stream.write_all(&response).await;
+                let _ = stream.shutdown().await;
+            });
+        }
+    });
+    addr.to_string()
+}
+
+#[tokio::test]
+async fn test_build_per_platform_verifies_push() {
+    use base64::Engine;
+    use buildkit_client::oci::DESCRIPTOR_RESPONSE_KEY;
+    use buildkit_client::proto::moby::buildkit::v1::control_server::ControlServer;
+    use buildkit_client::{BuildConfig, BuildKitClient, RegistryConfig};
+    use sha2::{Digest, Sha256};
+
+    // The platform image the daemon "pushed" by digest
+    let image = br#"{"schemaVersion":2,"mediaType":"application/vnd.oci.image.manifest.v1+json"}"#;
+    let image_digest = format!("sha256:{:x}", Sha256::digest(image));
+    let manifests = Manifests::default();
+    manifests.lock().unwrap().insert(
+        format!("/v2/app/manifests/{}", image_digest),
+        image.to_vec(),
+    );
+    let host = fake_registry(Arc::clone(&manifests)).await;
+
+    let descriptor = Descriptor {
+        size: image.len() as u64,
+        ..manifest(&image_digest)
+    };
+    let exporter_response = HashMap::from([
+        ("containerimage.digest".to_string(), image_digest.clone()),
+        (
+            DESCRIPTOR_RESPONSE_KEY.to_string(),
+            base64::engine::general_purpose::STANDARD
+                .encode(serde_json::to_vec(&descriptor).unwrap()),
+        ),
+    ]);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let incoming = async_stream::stream! {
+        loop {
+            yield listener.accept().await.map(|(stream, _)| stream);
+        }
+    };
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(ControlServer::new(daemon::MockDaemon { exporter_response }))
+            .serve_with_incoming(Box::pin(incoming)),
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("Dockerfile"), "FROM alpine\n").unwrap();
+    let config = BuildConfig::local(dir.path())
+        .platform(Platform::linux_amd64())
+        .tag(format!("{}/app:v1", host))
+        .registry_config(
+            host.clone(),
+            RegistryConfig {
+                plain_http: true,
+                ..Default::default()
+            },
+        )
+        .verify_push(true);
+    let client = BuildKitClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+
+    // Both the platform image and the index are resolved in the registry
+    let result = client
+        .build_per_platform(config, HashMap::new())
+        .await
+        .unwrap();
+    let pushed = manifests
+        .lock()
+        .unwrap()
+        .get("/v2/app/manifests/v1")
+        .cloned()
+        .expect("image index pushed to the tag");
+    assert_eq!(
+        result.digest,
+        format!("sha256:{:x}", Sha256::digest(&pushed))
+    );
+    assert_eq!(result.index.manifests.len(), 1);
+    assert_eq!(result.index.manifests[0].digest, image_digest);
+    assert_eq!(
+        result.index.manifests[0].platform,
+        Some(Platform::linux_amd64())
+    );
+}
@@ -0,0 +1,144 @@
+//! Property tests for Platform parsing and formatting
+
+use buildkit_client::Platform;
+use proptest::prelude::*;
+use std::collections::HashMap;
+
+/// A valid lowercase platform component
+fn component() -> impl Strategy<Value = String> {
+    "[a-z0-9][a-z0-9._-]{0,11}"
+}
+
+fn platform() -> impl Strategy<Value = Platform> {
+    (component(), component(), proptest::option::of(component())).prop_map(|(os, arch, variant)| {
+        let platform = Platform::new(os, arch);
+        match variant {
+            Some(variant) => platform.with_variant(variant),
+            None => platform,
+        }
+    })
+}
+
+proptest! {
+    #[test]
+    fn round_trips_through_string(platform in platform()) {
+        let parsed = Platform::parse(&platform.to_string()).unwrap();
+        prop_assert_eq!(&parsed, &platform);
+        prop_assert_eq!(parsed.to_string(), platform.to_string());
+    }
+
+    #[test]
+    fn normalizes_case(platform in platform()) {
+        let upper = platform.to_string().to_ascii_uppercase();
+        let parsed = Platform::parse(&upper).unwrap();
+        prop_assert_eq!(&parsed, &platform);
+
+        // Equal platforms collide as map keys
+        let mut counts = HashMap::new();
+        *counts.entry(parsed).or_insert(0) += 1;
+        *counts.entry(platform).or_insert(0) += 1;
+        prop_assert_eq!(counts.len(), 1);
+    }
+
+    #[test]
+    fn rejects_wrong_component_count(parts in proptest::collection::vec(component(), 0..8)) {
+        prop_assume!(parts.len() != 2 && parts.len() != 3);
+        prop_assert!(Platform::parse(&parts.join("/")).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_characters(
+        platform in platform(),
+        bad in "[ \t,:;@#%*?=+]",
+        position in 0usize..64,
+    ) {
+        let mut s = platform.to_string();
+        let index = position % (s.len() + 1);
+        s.insert_str(index, &bad);
+        prop_assert!(Platform::parse(&s).is_err());
+    }
+
+    #[test]
+    fn round_trips_through_from_str(platform in platform()) {
+        let parsed: Platform = platform.to_string().parse().unwrap();
+        prop_assert_eq!(parsed, platform);
+    }
+
+    #[test]
+    fn round_trips_through_serde(platform in platform()) {
+        let json = serde_json::to_string(&platform).unwrap();
+        let parsed: Platform = serde_json::from_str(&json).unwrap();
+        prop_assert_eq!(parsed, platform);
+    }
+
+    #[test]
+    fn never_panics(s in "\\PC{0,40}") {
+        if let Ok(platform) = Platform::parse(&s) {
+            prop_assert_eq!(Platform::parse(&platform.to_string()).unwrap(), platform);
+        }
+    }
+}
+
+#[test]
+fn test_platform_rejects_empty_components() {
+    for s in [
+        "",
+        "/",
+        "linux/",
+        "/amd64",
+        "linux//v7",
+        "linux/arm/",
+        " linux/amd64",
+    ] {
+        assert!(Platform::parse(s).is_err(), "{:?} should be rejected", s);
+    }
+}
+
+#[test]
+fn test_platform_serde_uses_oci_layout() {
+    let json = serde_json::to_value(Platform::linux_arm_v7()).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({"os": "linux", "architecture": "arm", "variant": "v7"})
+    );
+    let json = serde_json::to_value(Platform::linux_amd64()).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({"os": "linux", "architecture": "amd64"})
+    );
+
+    // Deserializing normalizes like the constructors do
+    let platform: Platform =
+        serde_json::from_str(r#"{"os": "Linux", "architecture": "ARM64", "variant": ""}"#).unwrap();
+    assert_eq!(platform, Platform::linux_arm64());
+}
+
+#[test]
+fn test_platform_constructors() {
+    let cases = [
+        (Platform::linux_amd64(), "linux/amd64"),
+        (Platform::linux_arm64(), "linux/arm64"),
+        (Platform::linux_arm_v7(), "linux/arm/v7"),
+        (Platform::linux_386(), "linux/386"),
+        (Platform::linux_riscv64(), "linux/riscv64"),
+        (Platform::linux_s390x(), "linux/s390x"),
+        (Platform::linux_ppc64le(), "linux/ppc64le"),
+        (Platform::windows_amd64(), "windows/amd64"),
+    ];
+    for (platform, expected) in cases {
+        assert_eq!(platform.to_string(), expected);
+        assert_eq!(expected.parse::<Platform>().unwrap(), platform);
+    }
+}
+
+#[test]
+fn test_platform_current() {
+    let current = Platform::current();
+    assert_eq!(current.to_string().parse::<Platform>().unwrap(), current);
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    assert_eq!(current, Platform::linux_amd64());
+    #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+    assert_eq!(current, Platform::linux_arm64());
+    #[cfg(target_os = "macos")]
+    assert_eq!(current.os, "darwin");
+}
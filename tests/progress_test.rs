@@ -1,6 +1,6 @@
 //! Unit tests for progress handlers
 
-use buildkit_client::progress::{ProgressHandler, ConsoleProgressHandler, JsonProgressHandler};
+use buildkit_client::progress::{ProgressHandler, ConsoleProgressHandler, JsonProgressHandler, StatusSummary};
 use buildkit_client::proto::moby::buildkit::v1::StatusResponse;
 
 #[test]
@@ -41,12 +41,7 @@ fn test_console_progress_handler_on_error() {
 fn test_console_progress_handler_on_status() {
     let mut handler = ConsoleProgressHandler::new(false);
 
-    let status = StatusResponse {
-        vertexes: vec![],
-        statuses: vec![],
-        logs: vec![],
-        warnings: vec![],
-    };
+    let status = StatusSummary::default();
 
     let result = handler.on_status(status);
     assert!(result.is_ok());
@@ -56,12 +51,7 @@ fn test_console_progress_handler_on_status() {
 fn test_json_progress_handler_on_status() {
     let mut handler = JsonProgressHandler::new();
 
-    let status = StatusResponse {
-        vertexes: vec![],
-        statuses: vec![],
-        logs: vec![],
-        warnings: vec![],
-    };
+    let status = StatusSummary::default();
 
     let result = handler.on_status(status);
     assert!(result.is_ok());
@@ -72,12 +62,7 @@ fn test_console_progress_handler_verbose_mode() {
     let mut handler_quiet = ConsoleProgressHandler::new(false);
     let mut handler_verbose = ConsoleProgressHandler::new(true);
 
-    let status = StatusResponse {
-        vertexes: vec![],
-        statuses: vec![],
-        logs: vec![],
-        warnings: vec![],
-    };
+    let status = StatusSummary::default();
 
     assert!(handler_quiet.on_status(status.clone()).is_ok());
     assert!(handler_verbose.on_status(status).is_ok());
@@ -108,7 +93,7 @@ fn test_progress_handler_with_vertexes() {
         warnings: vec![],
     };
 
-    let result = handler.on_status(status);
+    let result = handler.on_status(status.into());
     assert!(result.is_ok());
 }
 
@@ -133,7 +118,7 @@ fn test_progress_handler_with_logs() {
         warnings: vec![],
     };
 
-    let result = handler.on_status(status);
+    let result = handler.on_status(status.into());
     assert!(result.is_ok());
 }
 
@@ -160,7 +145,7 @@ fn test_progress_handler_with_warnings() {
         warnings: vec![warning],
     };
 
-    let result = handler.on_status(status);
+    let result = handler.on_status(status.into());
     assert!(result.is_ok());
 }
 
@@ -190,7 +175,7 @@ fn test_json_progress_output_format() {
     };
 
     // JSON handler should be able to serialize the status
-    let result = handler.on_status(status);
+    let result = handler.on_status(status.into());
     assert!(result.is_ok());
 }
 
@@ -200,12 +185,7 @@ fn test_json_handler_lifecycle() {
 
     assert!(handler.on_start().is_ok());
 
-    let status = StatusResponse {
-        vertexes: vec![],
-        statuses: vec![],
-        logs: vec![],
-        warnings: vec![],
-    };
+    let status = StatusSummary::default();
     assert!(handler.on_status(status).is_ok());
 
     assert!(handler.on_complete().is_ok());
@@ -217,13 +197,265 @@ fn test_console_handler_lifecycle() {
 
     assert!(handler.on_start().is_ok());
 
-    let status = StatusResponse {
+    let status = StatusSummary::default();
+    assert!(handler.on_status(status).is_ok());
+
+    assert!(handler.on_complete().is_ok());
+}
+
+fn log_status(vertex: &str, msg: &[u8]) -> StatusResponse {
+    use buildkit_client::proto::moby::buildkit::v1::VertexLog;
+
+    StatusResponse {
         vertexes: vec![],
         statuses: vec![],
-        logs: vec![],
+        logs: vec![VertexLog {
+            vertex: vertex.to_string(),
+            timestamp: None,
+            stream: 1,
+            msg: msg.to_vec(),
+        }],
         warnings: vec![],
+    }
+}
+
+#[tokio::test]
+async fn test_status_buffer_delivers_in_order() {
+    use buildkit_client::progress::{ProgressBufferConfig, StatusBuffer};
+
+    let buffer = StatusBuffer::new(ProgressBufferConfig::default());
+    buffer.push(log_status("v1", b"a")).await;
+    buffer.push(log_status("v1", b"b")).await;
+    buffer.close();
+
+    let first = buffer.pop().await.unwrap().unwrap();
+    assert_eq!(first.logs[0].msg, b"a");
+    let second = buffer.pop().await.unwrap().unwrap();
+    assert_eq!(second.logs[0].msg, b"b");
+    assert!(buffer.pop().await.is_none());
+
+    let metrics = buffer.metrics();
+    assert_eq!(metrics.received, 2);
+    assert_eq!(metrics.delivered, 2);
+    assert_eq!(metrics.dropped, 0);
+}
+
+#[tokio::test]
+async fn test_status_buffer_drop_newest() {
+    use buildkit_client::progress::{OverflowPolicy, ProgressBufferConfig, StatusBuffer};
+
+    let buffer = StatusBuffer::new(ProgressBufferConfig::new(1, OverflowPolicy::DropNewest));
+    buffer.push(log_status("v1", b"kept")).await;
+    buffer.push(log_status("v1", b"dropped")).await;
+    buffer.close();
+
+    let status = buffer.pop().await.unwrap().unwrap();
+    assert_eq!(status.logs[0].msg, b"kept");
+    assert!(buffer.pop().await.is_none());
+    assert_eq!(buffer.metrics().dropped, 1);
+}
+
+#[tokio::test]
+async fn test_status_buffer_coalesces_logs_per_vertex() {
+    use buildkit_client::progress::{OverflowPolicy, ProgressBufferConfig, StatusBuffer};
+
+    let buffer = StatusBuffer::new(ProgressBufferConfig::new(1, OverflowPolicy::Coalesce));
+    buffer.push(log_status("v1", b"hello ")).await;
+    buffer.push(log_status("v1", b"world")).await;
+    buffer.push(log_status("v2", b"other")).await;
+    buffer.close();
+
+    let status = buffer.pop().await.unwrap().unwrap();
+    assert_eq!(status.logs.len(), 2);
+    assert_eq!(status.logs[0].msg, b"hello world");
+    assert_eq!(status.logs[1].vertex, "v2");
+    assert!(buffer.pop().await.is_none());
+
+    let metrics = buffer.metrics();
+    assert_eq!(metrics.received, 3);
+    assert_eq!(metrics.delivered, 1);
+    assert_eq!(metrics.coalesced, 2);
+}
+
+#[tokio::test]
+async fn test_status_buffer_reports_stream_error() {
+    use buildkit_client::progress::{ProgressBufferConfig, StatusBuffer};
+
+    let buffer = StatusBuffer::new(ProgressBufferConfig::default());
+    buffer.push(log_status("v1", b"a")).await;
+    buffer.fail("stream reset");
+
+    assert!(buffer.pop().await.unwrap().is_ok());
+    assert_eq!(buffer.pop().await.unwrap().unwrap_err(), "stream reset");
+    assert!(buffer.pop().await.is_none());
+}
+
+#[test]
+fn test_status_summary_from_response() {
+    use buildkit_client::proto::moby::buildkit::v1::{Vertex, VertexLog, VertexWarning};
+    use prost_types::Timestamp;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let response = StatusResponse {
+        vertexes: vec![Vertex {
+            digest: "sha256:abc123".to_string(),
+            inputs: vec!["sha256:def456".to_string()],
+            name: "[1/2] RUN make".to_string(),
+            cached: true,
+            started: Some(Timestamp { seconds: 1234567890, nanos: 0 }),
+            completed: None,
+            error: String::new(),
+            progress_group: None,
+        }],
+        statuses: vec![],
+        logs: vec![VertexLog {
+            vertex: "sha256:abc123".to_string(),
+            timestamp: None,
+            stream: 2,
+            msg: b"compiling".to_vec(),
+        }],
+        warnings: vec![VertexWarning {
+            vertex: "sha256:abc123".to_string(),
+            level: 1,
+            short: b"Deprecated".to_vec(),
+            detail: vec![b"line one".to_vec()],
+            url: String::new(),
+            info: None,
+            ranges: vec![],
+        }],
     };
-    assert!(handler.on_status(status).is_ok());
 
-    assert!(handler.on_complete().is_ok());
+    let summary = StatusSummary::from(response);
+
+    let vertex = &summary.vertexes[0];
+    assert_eq!(vertex.name, "[1/2] RUN make");
+    assert_eq!(vertex.inputs, vec!["sha256:def456".to_string()]);
+    assert!(vertex.cached);
+    assert_eq!(vertex.started, Some(UNIX_EPOCH + Duration::from_secs(1234567890)));
+    assert_eq!(vertex.completed, None);
+    assert_eq!(vertex.error, None);
+
+    assert_eq!(summary.logs[0].stream, 2);
+    assert_eq!(summary.logs[0].data, b"compiling");
+
+    assert_eq!(summary.warnings[0].short, "Deprecated");
+    assert_eq!(summary.warnings[0].detail, vec!["line one".to_string()]);
+}
+
+#[test]
+fn test_handler_with_owned_summary() {
+    use buildkit_client::progress::{LogChunk, VertexSummary};
+
+    let mut vertex = VertexSummary::default();
+    vertex.digest = "sha256:abc123".to_string();
+    vertex.name = "Owned vertex".to_string();
+    vertex.started = Some(std::time::SystemTime::now());
+
+    let mut log = LogChunk::default();
+    log.vertex = vertex.digest.clone();
+    log.stream = 1;
+    log.data = b"hello".to_vec();
+
+    let mut status = StatusSummary::default();
+    status.vertexes.push(vertex);
+    status.logs.push(log);
+
+    assert!(ConsoleProgressHandler::new(true).on_status(status.clone()).is_ok());
+    assert!(JsonProgressHandler::new().on_status(status).is_ok());
+}
+
+#[test]
+fn test_cache_invalidation_report() {
+    use buildkit_client::progress::VertexSummary;
+    use buildkit_client::proto::moby::buildkit::v1::Vertex;
+    use buildkit_client::CacheInvalidationReport;
+
+    let vertex = |digest: &str, inputs: &[&str], name: &str, cached: bool| {
+        VertexSummary::from(Vertex {
+            digest: digest.to_string(),
+            inputs: inputs.iter().map(|input| input.to_string()).collect(),
+            name: name.to_string(),
+            cached,
+            ..Default::default()
+        })
+    };
+    let vertexes = vec![
+        vertex("sha256:ctx", &[], "[internal] load build context", false),
+        vertex(
+            "sha256:base",
+            &[],
+            "[1/5] FROM docker.io/library/rust:1",
+            true,
+        ),
+        vertex(
+            "sha256:manifest",
+            &["sha256:base", "sha256:ctx"],
+            "[2/5] COPY Cargo.toml Cargo.lock ./",
+            true,
+        ),
+        vertex(
+            "sha256:src",
+            &["sha256:manifest", "sha256:ctx"],
+            "[3/5] COPY src/ ./src/",
+            false,
+        ),
+        vertex(
+            "sha256:build",
+            &["sha256:src"],
+            "[4/5] RUN cargo build --release",
+            false,
+        ),
+        vertex(
+            "sha256:conf",
+            &["sha256:build", "sha256:ctx"],
+            "[5/5] COPY config/*.toml /etc/app/",
+            false,
+        ),
+    ];
+    let changed = vec![
+        "config/app.toml".to_string(),
+        "src/main.rs".to_string(),
+        "src/util/mod.rs".to_string(),
+    ];
+
+    let report = CacheInvalidationReport::analyze(&vertexes, &changed);
+    let steps: Vec<(&str, Vec<&str>, bool)> = report
+        .steps
+        .iter()
+        .map(|step| {
+            let files = step.files.iter().map(String::as_str).collect();
+            (step.name.as_str(), files, step.inputs_rebuilt)
+        })
+        .collect();
+    assert_eq!(
+        steps,
+        vec![
+            (
+                "[3/5] COPY src/ ./src/",
+                vec!["src/main.rs", "src/util/mod.rs"],
+                false
+            ),
+            ("[4/5] RUN cargo build --release", vec![], true),
+            (
+                "[5/5] COPY config/*.toml /etc/app/",
+                vec!["config/app.toml"],
+                true
+            ),
+        ]
+    );
+    assert!(!report.steps[1].invalidated_by_files());
+    assert_eq!(
+        report.invalidating_files(),
+        vec!["config/app.toml", "src/main.rs", "src/util/mod.rs"]
+    );
+
+    // Copies from other stages never read the context
+    let vertexes = vec![vertex(
+        "sha256:bin",
+        &[],
+        "[stage-1 2/2] COPY --from=build /app/bin /bin",
+        false,
+    )];
+    let report = CacheInvalidationReport::analyze(&vertexes, &changed);
+    assert!(report.steps[0].files.is_empty());
 }
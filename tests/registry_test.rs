@@ -0,0 +1,84 @@
+//! Unit tests for registry access
+//!
+//! Requests go to a minimal HTTP server on localhost.
+
+use buildkit_client::registry::RegistryContent;
+use buildkit_client::RegistryConfig;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+const MANIFEST: &str =
+    r#"{"schemaVersion":2,"mediaType":"application/vnd.oci.image.manifest.v1+json"}"#;
+
+/// Access `app` on a registry served over plain HTTP
+fn plain_http(host: &str) -> RegistryContent {
+    let config = RegistryConfig {
+        plain_http: true,
+        ..Default::default()
+    };
+    RegistryContent::configured(&format!("{}/app:v1", host), &config).unwrap()
+}
+
+/// Serve `response` to every connection, returning the registry host
+async fn fake_registry(response: String) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut buf = [0u8; 4096];
+            let _ = stream.read(&mut buf).await;
+            let _ = stream.write_all(response.as_bytes()).await;
+            let _ = stream.shutdown().await;
+        }
+    });
+    addr.to_string()
+}
+
+#[tokio::test]
+async fn test_manifest_digest() {
+    let host = fake_registry(format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/vnd.oci.image.manifest.v1+json\r\n\
+         Docker-Content-Digest: sha256:0000\r\nContent-Length: {}\r\n\r\n{}",
+        MANIFEST.len(),
+        MANIFEST
+    ))
+    .await;
+    let store = plain_http(&host);
+
+    // Computed from the content, not taken from the header
+    let digest = store.manifest_digest("v1").await.unwrap();
+    assert_eq!(
+        digest,
+        Some(format!("sha256:{:x}", Sha256::digest(MANIFEST.as_bytes())))
+    );
+}
+
+#[tokio::test]
+async fn test_manifest_digest_missing() {
+    let host =
+        fake_registry("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string()).await;
+    let store = plain_http(&host);
+    assert_eq!(store.manifest_digest("v1").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_manifest_digest_error() {
+    let host = fake_registry(
+        "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n".to_string(),
+    )
+    .await;
+    let store = plain_http(&host);
+    assert!(store.manifest_digest("v1").await.is_err());
+}
+
+#[test]
+fn test_localhost_uses_https_by_default() {
+    // Only the registry config switches to plain HTTP, whatever the host
+    for name in ["localhost:5000/app", "localhost.example.com/app"] {
+        let store = RegistryContent::configured(name, &RegistryConfig::default()).unwrap();
+        assert!(format!("{:?}", store).contains("https://"));
+    }
+    let store = RegistryContent::configured("localhost:5000/app", &RegistryConfig::insecure());
+    assert!(format!("{:?}", store.unwrap()).contains("\"http://localhost:5000\""));
+}
@@ -0,0 +1,136 @@
+//! Unit tests for remote ADD source discovery and verification
+//!
+//! Verification runs against a minimal HTTP server on localhost.
+
+use buildkit_client::remote_sources::{find_remote_sources, verify_remote_sources, RemoteSource};
+use buildkit_client::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Serve `response` to every connection, returning the base URL
+async fn fake_server(response: &'static [u8]) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut buf = [0u8; 4096];
+            let _ = stream.read(&mut buf).await;
+            let _ = stream.write_all(response).await;
+            let _ = stream.shutdown().await;
+        }
+    });
+    format!("http://{}", addr)
+}
+
+#[test]
+fn test_find_remote_sources() {
+    let dockerfile = r#"
+# ADD https://example.com/commented.tar.gz /x
+FROM alpine
+ADD https://example.com/a.tar.gz /a.tar.gz
+add --chown=1000 --checksum=sha256:abc123 \
+    https://example.com/b.tar.gz /b.tar.gz
+ADD ./local.txt /local.txt
+ADD ["https://example.com/c.txt", "/c.txt"]
+ADD https://example.com/${VERSION}.tar.gz /v.tar.gz
+COPY https://example.com/not-add.txt /n
+"#;
+
+    let sources = find_remote_sources(dockerfile);
+    assert_eq!(sources.len(), 3);
+
+    assert_eq!(sources[0].url, "https://example.com/a.tar.gz");
+    assert_eq!(sources[0].checksum, None);
+    assert_eq!(sources[0].line, 4);
+
+    assert_eq!(sources[1].url, "https://example.com/b.tar.gz");
+    assert_eq!(sources[1].checksum.as_deref(), Some("sha256:abc123"));
+    assert_eq!(sources[1].line, 5);
+
+    assert_eq!(sources[2].url, "https://example.com/c.txt");
+}
+
+#[test]
+fn test_find_remote_sources_multiple_sources() {
+    let sources = find_remote_sources("ADD http://a.example/1 http://a.example/2 /dest/\n");
+    let urls: Vec<_> = sources.iter().map(|s| s.url.as_str()).collect();
+    assert_eq!(urls, vec!["http://a.example/1", "http://a.example/2"]);
+}
+
+#[tokio::test]
+async fn test_verify_reachable_source() {
+    let base = fake_server(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").await;
+    let sources = vec![RemoteSource {
+        url: format!("{}/file", base),
+        checksum: None,
+        line: 1,
+    }];
+    verify_remote_sources(&sources).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_verify_missing_source() {
+    let base = fake_server(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n").await;
+    let sources = vec![RemoteSource {
+        url: format!("{}/missing", base),
+        checksum: None,
+        line: 7,
+    }];
+    let err = verify_remote_sources(&sources).await.unwrap_err();
+    assert!(matches!(err, Error::RemoteSource { line: 7, .. }));
+}
+
+#[tokio::test]
+async fn test_verify_forbidden_source() {
+    // Authenticated or rate-limited URLs are left for BuildKit to fetch
+    let base = fake_server(b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n").await;
+    let sources = vec![
+        RemoteSource {
+            url: format!("{}/private", base),
+            checksum: None,
+            line: 1,
+        },
+        RemoteSource {
+            url: format!("{}/private.tar.gz", base),
+            checksum: Some(format!("sha256:{}", "0".repeat(64))),
+            line: 2,
+        },
+    ];
+    verify_remote_sources(&sources).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_verify_missing_checksum_source() {
+    let base = fake_server(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n").await;
+    let sources = vec![RemoteSource {
+        url: format!("{}/missing.tar.gz", base),
+        checksum: Some(format!("sha256:{}", "0".repeat(64))),
+        line: 3,
+    }];
+    let err = verify_remote_sources(&sources).await.unwrap_err();
+    assert!(matches!(err, Error::RemoteSource { line: 3, .. }));
+}
+
+#[tokio::test]
+async fn test_verify_checksum() {
+    let base = fake_server(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello").await;
+    let url = format!("{}/hello", base);
+
+    // sha256("hello")
+    let good = vec![RemoteSource {
+        url: url.clone(),
+        checksum: Some(
+            "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824".to_string(),
+        ),
+        line: 1,
+    }];
+    verify_remote_sources(&good).await.unwrap();
+
+    let bad = vec![RemoteSource {
+        url,
+        checksum: Some(format!("sha256:{}", "0".repeat(64))),
+        line: 2,
+    }];
+    let err = verify_remote_sources(&bad).await.unwrap_err();
+    assert!(err.to_string().contains("checksum mismatch"));
+}
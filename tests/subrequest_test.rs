@@ -0,0 +1,196 @@
+//! Tests for frontend subrequests and image resolution through the gateway API
+
+use buildkit_client::gateway::SourceLocation;
+use buildkit_client::image_config::ResolvedImage;
+use buildkit_client::lint::LintReport;
+use buildkit_client::outline::Outline;
+
+const LINT_RESULT: &str = r#"{
+  "warnings": [
+    {
+      "ruleName": "FromAsCasing",
+      "description": "The 'as' keyword should match the case of the 'from' keyword",
+      "url": "https://docs.docker.com/go/dockerfile/rule/from-as-casing/",
+      "detail": "'as' and 'FROM' keywords' casing do not match",
+      "location": {
+        "ranges": [{"start": {"line": 1}, "end": {"line": 1}}]
+      }
+    },
+    {
+      "ruleName": "UndefinedVar",
+      "description": "Variables should be defined before their use",
+      "detail": "Usage of undefined variable '$VERSION'",
+      "location": {
+        "sourceIndex": 1,
+        "ranges": [{"start": {"line": 4, "character": 2}, "end": {"line": 4, "character": 20}}]
+      }
+    }
+  ],
+  "sources": [
+    {"filename": "Dockerfile", "language": "Dockerfile", "data": "RlJPTSBhbHBpbmUgYXMgYmFzZQo="},
+    {"filename": "app.Dockerfile", "language": "Dockerfile"}
+  ]
+}"#;
+
+#[test]
+fn test_lint_report_from_json() {
+    let report = LintReport::from_json(LINT_RESULT.as_bytes()).unwrap();
+    assert!(!report.is_clean());
+    assert!(report.build_error.is_none());
+    assert_eq!(report.warnings.len(), 2);
+
+    let casing = &report.warnings[0];
+    assert_eq!(casing.rule_name, "FromAsCasing");
+    assert_eq!(casing.location.line(), Some(1));
+    assert_eq!(
+        report.source(&casing.location).unwrap().filename,
+        "Dockerfile"
+    );
+
+    let undefined = &report.warnings[1];
+    assert_eq!(undefined.url, "");
+    assert_eq!(undefined.location.ranges[0].start.character, 2);
+    assert_eq!(undefined.location.ranges[0].end.character, 20);
+    assert_eq!(
+        report.source(&undefined.location).unwrap().filename,
+        "app.Dockerfile"
+    );
+}
+
+#[test]
+fn test_lint_report_with_build_error() {
+    // Empty lists may be encoded as `null`
+    let report = LintReport::from_json(
+        br#"{"warnings": null, "sources": [], "buildError": {"message": "dockerfile parse error on line 3: unknown instruction: RUNN", "location": {"ranges": [{"start": {"line": 3}, "end": {"line": 3}}]}}}"#,
+    )
+    .unwrap();
+    assert!(!report.is_clean());
+    let error = report.build_error.unwrap();
+    assert!(error.message.contains("RUNN"));
+    assert_eq!(error.location.unwrap().line(), Some(3));
+}
+
+#[test]
+fn test_clean_lint_report() {
+    let report = LintReport::from_json(br#"{"warnings": [], "sources": []}"#).unwrap();
+    assert!(report.is_clean());
+    assert!(LintReport::from_json(b"not json").is_err());
+
+    // Locations without ranges have no line
+    assert_eq!(SourceLocation::default().line(), None);
+    assert!(report.source(&SourceLocation::default()).is_none());
+}
+
+const OUTLINE_RESULT: &str = r#"{
+  "name": "release",
+  "description": "Build the release binary",
+  "args": [
+    {"name": "RUST_VERSION", "description": "Toolchain to build with", "value": "1.80", "location": {"ranges": [{"start": {"line": 2}, "end": {"line": 2}}]}},
+    {"name": "FEATURES"}
+  ],
+  "secrets": [
+    {"name": "cargo_token", "required": true},
+    {"name": "sccache"}
+  ],
+  "ssh": [{"name": "default"}],
+  "cache": [{"ID": "/usr/local/cargo/registry"}],
+  "sources": ["RlJPTSBydXN0Cg=="]
+}"#;
+
+#[test]
+fn test_outline_from_json() {
+    let outline = Outline::from_json(OUTLINE_RESULT.as_bytes()).unwrap();
+    assert_eq!(outline.name, "release");
+    assert_eq!(outline.description, "Build the release binary");
+
+    let version = outline.arg("RUST_VERSION").unwrap();
+    assert_eq!(version.value, "1.80");
+    assert_eq!(version.description, "Toolchain to build with");
+    assert_eq!(version.location.as_ref().and_then(|l| l.line()), Some(2));
+    let features = outline.arg("FEATURES").unwrap();
+    assert_eq!(features.value, "");
+    assert!(features.location.is_none());
+    assert!(outline.arg("MISSING").is_none());
+
+    assert_eq!(
+        outline.required_secrets().collect::<Vec<_>>(),
+        vec!["cargo_token"]
+    );
+    assert_eq!(outline.secrets.len(), 2);
+    assert_eq!(outline.ssh[0].name, "default");
+    assert_eq!(outline.required_ssh().count(), 0);
+    assert_eq!(outline.cache[0].id, "/usr/local/cargo/registry");
+}
+
+#[test]
+fn test_empty_outline() {
+    let outline = Outline::from_json(br#"{"args": null}"#).unwrap();
+    assert_eq!(outline, Outline::default());
+    assert!(Outline::from_json(b"[]").is_err());
+}
+
+const IMAGE_CONFIG: &str = r#"{
+  "architecture": "arm64",
+  "os": "linux",
+  "variant": "v8",
+  "config": {
+    "User": "app",
+    "Env": ["PATH=/usr/local/bin:/usr/bin", "APP_MODE=prod=ready"],
+    "Entrypoint": ["/app"],
+    "Cmd": null,
+    "WorkingDir": "/srv",
+    "Labels": {"org.opencontainers.image.source": "https://github.com/example/app"},
+    "ExposedPorts": {"8080/tcp": {}}
+  },
+  "rootfs": {"type": "layers", "diff_ids": []}
+}"#;
+
+#[test]
+fn test_resolved_image_config() {
+    let digest = "sha256:4bcff63911fcb4448bd4fdacec207030997caf25e9bea4045fa6c8c44de311d1";
+    let image = ResolvedImage::new(
+        "docker.io/example/app:1.0".to_string(),
+        digest.to_string(),
+        IMAGE_CONFIG.as_bytes().to_vec(),
+    )
+    .unwrap();
+    assert_eq!(
+        image.pinned_reference(),
+        format!("docker.io/example/app:1.0@{}", digest)
+    );
+    assert_eq!(image.config.architecture, "arm64");
+    assert_eq!(image.config.variant.as_deref(), Some("v8"));
+
+    let config = &image.config.config;
+    assert_eq!(config.user, "app");
+    assert_eq!(config.env_var("PATH"), Some("/usr/local/bin:/usr/bin"));
+    assert_eq!(config.env_var("APP_MODE"), Some("prod=ready"));
+    assert_eq!(config.env_var("HOME"), None);
+    assert_eq!(config.entrypoint, Some(vec!["/app".to_string()]));
+    assert_eq!(config.cmd, None);
+    assert_eq!(config.working_dir, "/srv");
+    assert_eq!(
+        config.labels["org.opencontainers.image.source"],
+        "https://github.com/example/app"
+    );
+    assert!(config.exposed_ports.contains_key("8080/tcp"));
+
+    assert!(ResolvedImage::new("alpine".to_string(), digest.to_string(), b"{".to_vec()).is_err());
+}
+
+#[test]
+fn test_resolved_image_pins_digest_references() {
+    let digest = "sha256:4bcff63911fcb4448bd4fdacec207030997caf25e9bea4045fa6c8c44de311d1";
+    let image = ResolvedImage::new(
+        format!("docker.io/library/alpine@{}", digest),
+        digest.to_string(),
+        br#"{"config": {"Env": null, "Labels": null}}"#.to_vec(),
+    )
+    .unwrap();
+    assert_eq!(
+        image.pinned_reference(),
+        format!("docker.io/library/alpine@{}", digest)
+    );
+    assert!(image.config.config.env.is_empty());
+    assert!(image.config.config.labels.is_empty());
+}